@@ -3,42 +3,12 @@
 
 //! RTU server example with slave address filtering and optional response
 
-use std::{future, thread, time::Duration};
+use std::{thread, time::Duration};
 
-use tokio_modbus::{prelude::*, server::rtu::Server};
-
-struct Service {
-    slave: Slave,
-}
-
-impl Service {
-    fn handle(&self, req: SlaveRequest<'_>) -> Result<Option<Response>, ExceptionCode> {
-        let SlaveRequest { slave, request } = req;
-        if slave != self.slave.into() {
-            // Filtering: Ignore requests with mismatching slave IDs.
-            return Ok(None);
-        }
-        match request {
-            Request::ReadInputRegisters(_addr, cnt) => {
-                let mut registers = vec![0; cnt.into()];
-                registers[2] = 0x77;
-                Ok(Some(Response::ReadInputRegisters(registers)))
-            }
-            _ => Err(ExceptionCode::IllegalFunction),
-        }
-    }
-}
-
-impl tokio_modbus::server::Service for Service {
-    type Request = SlaveRequest<'static>;
-    type Response = Option<Response>;
-    type Exception = ExceptionCode;
-    type Future = future::Ready<Result<Self::Response, Self::Exception>>;
-
-    fn call(&self, req: Self::Request) -> Self::Future {
-        future::ready(self.handle(req))
-    }
-}
+use tokio_modbus::{
+    prelude::*,
+    server::{rtu::Server, DataStore, SlaveFilter},
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -50,7 +20,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let _server = thread::spawn(move || {
         let rt = tokio::runtime::Runtime::new().unwrap();
         let server = Server::new(server_serial);
-        let service = Service { slave };
+        let store = DataStore::new().with_input_registers(0..=6);
+        store.set_input_registers(2, &[0x77]).unwrap();
+        // Filtering: Ignore requests with mismatching slave IDs.
+        let service = SlaveFilter::new(store, [slave]);
         rt.block_on(async {
             if let Err(err) = server.serve_forever(service).await {
                 eprintln!("{err}");
@@ -70,7 +43,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(rsp.unwrap(), vec![0x0, 0x0, 0x77, 0x0, 0x0, 0x0, 0x0]);
 
     println!("CLIENT: Reading with illegal function... (should return IllegalFunction)");
-    let response = ctx.read_holding_registers(0x100, 1).await.unwrap();
+    let response = ctx.read_exception_status().await.unwrap();
     println!("CLIENT: The result is '{response:?}'");
     assert!(matches!(response, Err(ExceptionCode::IllegalFunction)));
 
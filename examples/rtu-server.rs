@@ -3,32 +3,12 @@
 
 //! RTU server example
 
-use std::{future, thread, time::Duration};
+use std::{thread, time::Duration};
 
-use tokio_modbus::{prelude::*, server::rtu::Server};
-
-struct Service;
-
-impl tokio_modbus::server::Service for Service {
-    type Request = SlaveRequest<'static>;
-    type Response = Response;
-    type Exception = ExceptionCode;
-    type Future = future::Ready<Result<Self::Response, Self::Exception>>;
-
-    fn call(&self, req: Self::Request) -> Self::Future {
-        match req.request {
-            Request::ReadInputRegisters(_addr, cnt) => {
-                let mut registers = vec![0; cnt.into()];
-                registers[2] = 0x77;
-                future::ready(Ok(Response::ReadInputRegisters(registers)))
-            }
-            Request::ReadHoldingRegisters(_, _) => {
-                future::ready(Err(ExceptionCode::IllegalDataAddress))
-            }
-            _ => unimplemented!(),
-        }
-    }
-}
+use tokio_modbus::{
+    prelude::*,
+    server::{rtu::Server, DataStore},
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -43,7 +23,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let _server = thread::spawn(move || {
         let rt = tokio::runtime::Runtime::new().unwrap();
         let server = Server::new(server_serial);
-        let service = Service;
+        let service = DataStore::new().with_input_registers(0..=6);
+        service.set_input_registers(2, &[0x77]).unwrap();
         rt.block_on(async {
             if let Err(err) = server.serve_forever(service).await {
                 eprintln!("{err}");
@@ -3,185 +3,52 @@
 
 //! # TCP server example
 //!
-//! This example shows how to start a server and implement basic register
-//! read/write operations.
-
-use std::{
-    collections::HashMap,
-    future,
-    net::SocketAddr,
-    sync::{Arc, Mutex},
-    time::Duration,
-};
-
-use tokio::net::TcpListener;
-
-use tokio_modbus::{
-    prelude::*,
-    server::tcp::{accept_tcp_connection, Server},
-};
-
-struct ExampleService {
-    input_registers: Arc<Mutex<HashMap<u16, u16>>>,
-    holding_registers: Arc<Mutex<HashMap<u16, u16>>>,
-}
-
-impl tokio_modbus::server::Service for ExampleService {
-    type Request = Request<'static>;
-    type Response = Response;
-    type Exception = ExceptionCode;
-    type Future = future::Ready<Result<Self::Response, Self::Exception>>;
-
-    fn call(&self, req: Self::Request) -> Self::Future {
-        let res = match req {
-            Request::ReadInputRegisters(addr, cnt) => {
-                register_read(&self.input_registers.lock().unwrap(), addr, cnt)
-                    .map(Response::ReadInputRegisters)
-            }
-            Request::ReadHoldingRegisters(addr, cnt) => {
-                register_read(&self.holding_registers.lock().unwrap(), addr, cnt)
-                    .map(Response::ReadHoldingRegisters)
-            }
-            Request::WriteMultipleRegisters(addr, values) => {
-                register_write(&mut self.holding_registers.lock().unwrap(), addr, &values)
-                    .map(|_| Response::WriteMultipleRegisters(addr, values.len() as u16))
-            }
-            Request::WriteSingleRegister(addr, value) => register_write(
-                &mut self.holding_registers.lock().unwrap(),
-                addr,
-                std::slice::from_ref(&value),
-            )
-            .map(|_| Response::WriteSingleRegister(addr, value)),
-            _ => {
-                println!("SERVER: Exception::IllegalFunction - Unimplemented function code in request: {req:?}");
-                Err(ExceptionCode::IllegalFunction)
-            }
-        };
-        future::ready(res)
-    }
-}
-
-impl ExampleService {
-    fn new() -> Self {
-        // Insert some test data as register values.
-        let mut input_registers = HashMap::new();
-        input_registers.insert(0, 1234);
-        input_registers.insert(1, 5678);
-        let mut holding_registers = HashMap::new();
-        holding_registers.insert(0, 10);
-        holding_registers.insert(1, 20);
-        holding_registers.insert(2, 30);
-        holding_registers.insert(3, 40);
-        Self {
-            input_registers: Arc::new(Mutex::new(input_registers)),
-            holding_registers: Arc::new(Mutex::new(holding_registers)),
-        }
-    }
-}
-
-/// Helper function implementing reading registers from a HashMap.
-fn register_read(
-    registers: &HashMap<u16, u16>,
-    addr: u16,
-    cnt: u16,
-) -> Result<Vec<u16>, ExceptionCode> {
-    let mut response_values = vec![0; cnt.into()];
-    for i in 0..cnt {
-        let reg_addr = addr + i;
-        if let Some(r) = registers.get(&reg_addr) {
-            response_values[i as usize] = *r;
-        } else {
-            println!("SERVER: Exception::IllegalDataAddress");
-            return Err(ExceptionCode::IllegalDataAddress);
-        }
-    }
-
-    Ok(response_values)
-}
-
-/// Write a holding register. Used by both the write single register
-/// and write multiple registers requests.
-fn register_write(
-    registers: &mut HashMap<u16, u16>,
-    addr: u16,
-    values: &[u16],
-) -> Result<(), ExceptionCode> {
-    for (i, value) in values.iter().enumerate() {
-        let reg_addr = addr + i as u16;
-        if let Some(r) = registers.get_mut(&reg_addr) {
-            *r = *value;
-        } else {
-            println!("SERVER: Exception::IllegalDataAddress");
-            return Err(ExceptionCode::IllegalDataAddress);
-        }
-    }
+//! This example shows how to spin up a server with in-memory data tables
+//! and access the registers both from the host application and a client.
+//!
+//! See the RTU over TCP server example for serving a [`DataStore`] with
+//! [`Server`](tokio_modbus::server::tcp::Server) directly.
+//!
+//! [`DataStore`]: tokio_modbus::server::DataStore
 
-    Ok(())
-}
+use tokio_modbus::{prelude::*, testing::spawn_memory_server};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let socket_addr = "127.0.0.1:5502".parse().unwrap();
-
-    tokio::select! {
-        _ = server_context(socket_addr) => unreachable!(),
-        _ = client_context(socket_addr) => println!("Exiting"),
-    }
+    let (socket_addr, server) = spawn_memory_server().await?;
+    println!("SERVER: Listening on {socket_addr}");
 
-    Ok(())
-}
-
-async fn server_context(socket_addr: SocketAddr) -> anyhow::Result<()> {
-    println!("Starting up server on {socket_addr}");
-    let listener = TcpListener::bind(socket_addr).await?;
-    let server = Server::new(listener);
-    let new_service = |_socket_addr| Ok(Some(ExampleService::new()));
-    let on_connected = |stream, socket_addr| async move {
-        accept_tcp_connection(stream, socket_addr, new_service)
-    };
-    let on_process_error = |err| {
-        eprintln!("{err}");
-    };
-    server.serve(&on_connected, on_process_error).await?;
-    Ok(())
-}
+    // Insert some test data as register values.
+    server.memory().set_input_registers(0, &[1234, 5678]);
+    server.memory().set_holding_registers(0, &[10, 20, 30, 40]);
 
-async fn client_context(socket_addr: SocketAddr) {
-    tokio::join!(
-        async {
-            // Give the server some time for starting up
-            tokio::time::sleep(Duration::from_secs(1)).await;
+    println!("CLIENT: Connecting client...");
+    let mut ctx = tcp::connect(socket_addr).await?;
 
-            println!("CLIENT: Connecting client...");
-            let mut ctx = tcp::connect(socket_addr).await.unwrap();
+    println!("CLIENT: Reading 2 input registers...");
+    let response = ctx.read_input_registers(0x00, 2).await?;
+    println!("CLIENT: The result is '{response:?}'");
+    assert_eq!(response?, vec![1234, 5678]);
 
-            println!("CLIENT: Reading 2 input registers...");
-            let response = ctx.read_input_registers(0x00, 2).await.unwrap();
-            println!("CLIENT: The result is '{response:?}'");
-            assert_eq!(response.unwrap(), vec![1234, 5678]);
+    println!("CLIENT: Writing 2 holding registers...");
+    ctx.write_multiple_registers(0x01, &[7777, 8888]).await??;
 
-            println!("CLIENT: Writing 2 holding registers...");
-            ctx.write_multiple_registers(0x01, &[7777, 8888])
-                .await
-                .unwrap()
-                .unwrap();
+    // Read back a block including the two registers we wrote.
+    println!("CLIENT: Reading 4 holding registers...");
+    let response = ctx.read_holding_registers(0x00, 4).await?;
+    println!("CLIENT: The result is '{response:?}'");
+    assert_eq!(response?, vec![10, 7777, 8888, 40]);
+    assert_eq!(server.memory().holding_registers(0x01, 2), [7777, 8888]);
 
-            // Read back a block including the two registers we wrote.
-            println!("CLIENT: Reading 4 holding registers...");
-            let response = ctx.read_holding_registers(0x00, 4).await.unwrap();
-            println!("CLIENT: The result is '{response:?}'");
-            assert_eq!(response.unwrap(), vec![10, 7777, 8888, 40]);
+    // Now we try to read beyond the end of the address space.
+    // This should return a Modbus exception response with the code
+    // IllegalDataAddress.
+    println!("CLIENT: Reading nonexistent holding register address... (should return IllegalDataAddress)");
+    let response = ctx.read_holding_registers(0xFFFF, 2).await?;
+    println!("CLIENT: The result is '{response:?}'");
+    assert!(matches!(response, Err(ExceptionCode::IllegalDataAddress)));
 
-            // Now we try to read with an invalid register address.
-            // This should return a Modbus exception response with the code
-            // IllegalDataAddress.
-            println!("CLIENT: Reading nonexistent holding register address... (should return IllegalDataAddress)");
-            let response = ctx.read_holding_registers(0x100, 1).await.unwrap();
-            println!("CLIENT: The result is '{response:?}'");
-            assert!(matches!(response, Err(ExceptionCode::IllegalDataAddress)));
+    println!("CLIENT: Done.");
 
-            println!("CLIENT: Done.")
-        },
-        tokio::time::sleep(Duration::from_secs(5))
-    );
+    Ok(())
 }
@@ -7,13 +7,11 @@
 //! TCP server example
 
 use std::{
-    collections::HashMap,
     fs::File,
-    future,
     io::{self, BufReader},
     net::SocketAddr,
     path::Path,
-    sync::{Arc, Mutex},
+    sync::Arc,
     time::Duration,
 };
 
@@ -21,7 +19,7 @@ use pkcs8::der::Decode;
 use pki_types::{CertificateDer, PrivateKeyDer, ServerName};
 use rustls_pemfile::{certs, pkcs8_private_keys};
 use tokio::net::{TcpListener, TcpStream};
-use tokio_modbus::{prelude::*, server::tcp::Server};
+use tokio_modbus::server::{tcp::Server, DataStore};
 use tokio_rustls::{TlsAcceptor, TlsConnector};
 
 fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
@@ -83,102 +81,14 @@ fn load_keys(path: &Path, password: Option<&str>) -> io::Result<PrivateKeyDer<'s
     }
 }
 
-struct ExampleService {
-    input_registers: Arc<Mutex<HashMap<u16, u16>>>,
-    holding_registers: Arc<Mutex<HashMap<u16, u16>>>,
-}
-
-impl tokio_modbus::server::Service for ExampleService {
-    type Request = Request<'static>;
-    type Response = Response;
-    type Exception = ExceptionCode;
-    type Future = future::Ready<Result<Self::Response, Self::Exception>>;
-
-    fn call(&self, req: Self::Request) -> Self::Future {
-        let res = match req {
-            Request::ReadInputRegisters(addr, cnt) => {
-                register_read(&self.input_registers.lock().unwrap(), addr, cnt)
-                    .map(Response::ReadInputRegisters)
-            }
-            Request::ReadHoldingRegisters(addr, cnt) => {
-                register_read(&self.holding_registers.lock().unwrap(), addr, cnt)
-                    .map(Response::ReadHoldingRegisters)
-            }
-            Request::WriteMultipleRegisters(addr, values) => {
-                register_write(&mut self.holding_registers.lock().unwrap(), addr, &values)
-                    .map(|_| Response::WriteMultipleRegisters(addr, values.len() as u16))
-            }
-            Request::WriteSingleRegister(addr, value) => register_write(
-                &mut self.holding_registers.lock().unwrap(),
-                addr,
-                std::slice::from_ref(&value),
-            )
-            .map(|_| Response::WriteSingleRegister(addr, value)),
-            _ => {
-                println!("SERVER: Exception::IllegalFunction - Unimplemented function code in request: {req:?}");
-                Err(ExceptionCode::IllegalFunction)
-            }
-        };
-        future::ready(res)
-    }
-}
-
-impl ExampleService {
-    fn new() -> Self {
-        // Insert some test data as register values.
-        let mut input_registers = HashMap::new();
-        input_registers.insert(0, 1234);
-        input_registers.insert(1, 5678);
-        let mut holding_registers = HashMap::new();
-        holding_registers.insert(0, 10);
-        holding_registers.insert(1, 20);
-        holding_registers.insert(2, 30);
-        holding_registers.insert(3, 40);
-        Self {
-            input_registers: Arc::new(Mutex::new(input_registers)),
-            holding_registers: Arc::new(Mutex::new(holding_registers)),
-        }
-    }
-}
-
-/// Helper function implementing reading registers from a HashMap.
-fn register_read(
-    registers: &HashMap<u16, u16>,
-    addr: u16,
-    cnt: u16,
-) -> Result<Vec<u16>, ExceptionCode> {
-    let mut response_values = vec![0; cnt.into()];
-    for i in 0..cnt {
-        let reg_addr = addr + i;
-        if let Some(r) = registers.get(&reg_addr) {
-            response_values[i as usize] = *r;
-        } else {
-            println!("SERVER: Exception::IllegalDataAddress");
-            return Err(ExceptionCode::IllegalDataAddress);
-        }
-    }
-
-    Ok(response_values)
-}
-
-/// Write a holding register. Used by both the write single register
-/// and write multiple registers requests.
-fn register_write(
-    registers: &mut HashMap<u16, u16>,
-    addr: u16,
-    values: &[u16],
-) -> Result<(), ExceptionCode> {
-    for (i, value) in values.iter().enumerate() {
-        let reg_addr = addr + i as u16;
-        if let Some(r) = registers.get_mut(&reg_addr) {
-            *r = *value;
-        } else {
-            println!("SERVER: Exception::IllegalDataAddress");
-            return Err(ExceptionCode::IllegalDataAddress);
-        }
-    }
-
-    Ok(())
+/// Registers with some test data.
+fn example_store() -> DataStore {
+    let store = DataStore::new()
+        .with_input_registers(0..=1)
+        .with_holding_registers(0..=3);
+    store.set_input_registers(0, &[1234, 5678]).unwrap();
+    store.set_holding_registers(0, &[10, 20, 30, 40]).unwrap();
+    store
 }
 
 #[tokio::main]
@@ -209,7 +119,7 @@ async fn server_context(socket_addr: SocketAddr) -> anyhow::Result<()> {
             .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
         let acceptor = TlsAcceptor::from(Arc::new(config));
 
-        let service = ExampleService::new();
+        let service = example_store();
         let stream = acceptor.accept(stream).await;
         match stream {
             Ok(stream) => Ok(Some((service, stream))),
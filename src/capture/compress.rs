@@ -0,0 +1,119 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Compression of capture files
+
+use std::io::{self, Write};
+
+use flate2::write::GzEncoder;
+
+/// The compression of capture files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Uncompressed `.pcapng` files.
+    ///
+    /// This is the default.
+    #[default]
+    None,
+
+    /// Gzip compressed `.pcapng.gz` files.
+    ///
+    /// Each frame is flushed, i.e. the files could be decompressed while
+    /// capturing. Wireshark opens compressed files directly.
+    Gzip,
+}
+
+impl Compression {
+    /// The extension of the files.
+    pub(super) const fn extension(self) -> &'static str {
+        match self {
+            Self::None => ".pcapng",
+            Self::Gzip => ".pcapng.gz",
+        }
+    }
+}
+
+/// Compresses the data that is written into a file.
+pub(super) enum CompressedWriter<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    pub(super) fn new(writer: W, compression: Compression) -> Self {
+        match compression {
+            Compression::None => Self::Plain(writer),
+            Compression::Gzip => Self::Gzip(GzEncoder::new(writer, flate2::Compression::default())),
+        }
+    }
+
+    /// Complete the compressed stream and flush the file.
+    pub(super) fn finish(self) -> io::Result<W> {
+        let mut writer = match self {
+            Self::Plain(writer) => writer,
+            Self::Gzip(gzip) => gzip.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Gzip(gzip) => gzip.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            // Performs a sync flush of the compressed stream.
+            Self::Gzip(gzip) => gzip.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read as _;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    fn gunzip(compressed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed).read_to_end(&mut decompressed)?;
+        Ok(decompressed)
+    }
+
+    #[test]
+    fn decompress_flushed_data_before_finish() {
+        let mut writer = CompressedWriter::new(Vec::new(), Compression::Gzip);
+        writer.write_all(b"modbus").unwrap();
+        writer.flush().unwrap();
+        let CompressedWriter::Gzip(gzip) = &writer else {
+            unreachable!();
+        };
+        // The trailer is missing, but the flushed data is complete.
+        let mut decompressed = Vec::new();
+        let err = GzDecoder::new(gzip.get_ref().as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(decompressed, b"modbus");
+
+        writer.write_all(b" frames").unwrap();
+        let compressed = writer.finish().unwrap();
+        assert_eq!(gunzip(&compressed).unwrap(), b"modbus frames");
+    }
+
+    #[test]
+    fn write_uncompressed() {
+        let mut writer = CompressedWriter::new(Vec::new(), Compression::None);
+        writer.write_all(b"modbus").unwrap();
+        assert_eq!(writer.finish().unwrap(), b"modbus");
+    }
+}
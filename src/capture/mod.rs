@@ -0,0 +1,527 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Capture of Modbus TCP frames into pcapng files
+//!
+//! A [`PcapngWriter`] receives the frames of a [tap](crate::tap) and
+//! writes them as TCP segments of a single connection or as UDP datagrams
+//! into a
+//! [pcapng](https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-02.html)
+//! file, which could be inspected with Wireshark. The IP and TCP/UDP headers
+//! are synthesized from the given addresses, i.e. the capture doesn't
+//! contain the handshake or the actual segmentation of the connection.
+//!
+//! Sessions on other transports, e.g. RTU, could be captured with
+//! the synthesized frames of a
+//! [`RecordingClient`](crate::testing::RecordingClient).
+//!
+//! Long running captures are split into multiple, optionally compressed
+//! files by a [`RotatingPcapngWriter`].
+//!
+//! Only frames with an MBAP header are captured. With a
+//! [`FrameTransform`](crate::transform::FrameTransform) the captured
+//! frames are the wrapped frames on the wire.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn capture() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::{fs::File, io::BufWriter, sync::Arc};
+//!
+//! use tokio_modbus::{
+//!     capture::PcapngWriter,
+//!     client::tcp,
+//!     prelude::*,
+//!     tap::{Frame, FrameTap as _},
+//! };
+//!
+//! let socket_addr = "192.168.0.222:502".parse()?;
+//! let file = BufWriter::new(File::create("modbus.pcapng")?);
+//! let capture = Arc::new(PcapngWriter::new(
+//!     file,
+//!     "192.168.0.1:50200".parse()?,
+//!     socket_addr,
+//! )?);
+//! let mut ctx = tcp::Builder::new(socket_addr)
+//!     .tap({
+//!         let capture = Arc::clone(&capture);
+//!         move |frame: &Frame| capture.tap(frame)
+//!     })
+//!     .connect()
+//!     .await?;
+//! ctx.read_holding_registers(0x1000, 4).await??;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt,
+    io::{self, Write},
+    net::{IpAddr, SocketAddr},
+    sync::{Mutex, PoisonError},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    mutex::lock,
+    tap::{Direction, Frame, FrameTap},
+};
+
+mod compress;
+use self::compress::CompressedWriter;
+pub use self::compress::Compression;
+
+mod rotate;
+pub use self::rotate::{RotatingPcapngWriter, Rotation};
+
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+/// Raw IPv4 or IPv6 packets.
+const LINKTYPE_RAW: u16 = 101;
+
+const IPPROTO_TCP: u8 = 6;
+
+const IPPROTO_UDP: u8 = 17;
+
+/// TCP flags of all segments.
+const TCP_PSH_ACK: u8 = 0x18;
+
+const TTL: u8 = 64;
+
+/// Writes tapped Modbus TCP frames into a pcapng file.
+///
+/// The local endpoint sends the frames with [`Direction::Sent`] and the
+/// peer sends the frames with [`Direction::Received`], i.e. for a client
+/// the peer is the server and vice versa. The frames of all connections
+/// of a server are captured as a single connection.
+pub struct PcapngWriter<W> {
+    local: SocketAddr,
+    peer: SocketAddr,
+    protocol: u8,
+    state: Mutex<State<W>>,
+}
+
+impl<W> fmt::Debug for PcapngWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PcapngWriter")
+            .field("local", &self.local)
+            .field("peer", &self.peer)
+            .field("udp", &(self.protocol == IPPROTO_UDP))
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct State<W> {
+    writer: W,
+    local_seq: u32,
+    peer_seq: u32,
+    ip_id: u16,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Start a capture of a TCP connection between the `local` and
+    /// the `peer` endpoint.
+    ///
+    /// Writes the header of the file. IPv4 addresses are mapped to IPv6
+    /// if only one of the endpoints has an IPv6 address.
+    pub fn new(writer: W, local: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        Self::with_protocol(writer, local, peer, IPPROTO_TCP)
+    }
+
+    /// Start a capture of UDP datagrams between the `local` and
+    /// the `peer` endpoint.
+    ///
+    /// See also: [`Self::new()`]
+    pub fn new_udp(writer: W, local: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        Self::with_protocol(writer, local, peer, IPPROTO_UDP)
+    }
+
+    fn with_protocol(
+        mut writer: W,
+        local: SocketAddr,
+        peer: SocketAddr,
+        protocol: u8,
+    ) -> io::Result<Self> {
+        let (local, peer) = match (local, peer) {
+            (SocketAddr::V4(local), SocketAddr::V6(_)) => (
+                SocketAddr::new(local.ip().to_ipv6_mapped().into(), local.port()),
+                peer,
+            ),
+            (SocketAddr::V6(_), SocketAddr::V4(peer)) => (
+                local,
+                SocketAddr::new(peer.ip().to_ipv6_mapped().into(), peer.port()),
+            ),
+            _ => (local, peer),
+        };
+        let mut header = Vec::with_capacity(48);
+        // Section header block without options and an unknown length.
+        header.extend_from_slice(&SECTION_HEADER_BLOCK.to_le_bytes());
+        header.extend_from_slice(&28u32.to_le_bytes());
+        header.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&(-1i64).to_le_bytes());
+        header.extend_from_slice(&28u32.to_le_bytes());
+        // Interface description block with microsecond timestamps.
+        header.extend_from_slice(&INTERFACE_DESCRIPTION_BLOCK.to_le_bytes());
+        header.extend_from_slice(&20u32.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&20u32.to_le_bytes());
+        writer.write_all(&header)?;
+        writer.flush()?;
+        Ok(Self {
+            local,
+            peer,
+            protocol,
+            state: Mutex::new(State {
+                writer,
+                local_seq: 1,
+                peer_seq: 1,
+                ip_id: 0,
+            }),
+        })
+    }
+
+    /// Write a frame as a TCP segment or as a UDP datagram.
+    ///
+    /// Frames without an MBAP header are ignored. The writer is flushed
+    /// after each frame, i.e. the file could be inspected while capturing.
+    pub fn write_frame(&self, frame: &Frame) -> io::Result<()> {
+        if frame.transaction_id.is_none() {
+            return Ok(());
+        }
+        let mut state = lock(&self.state);
+        let State {
+            writer,
+            local_seq,
+            peer_seq,
+            ip_id,
+        } = &mut *state;
+        let (src, dst, seq, ack) = match frame.direction {
+            Direction::Sent => (self.local, self.peer, local_seq, *peer_seq),
+            Direction::Received => (self.peer, self.local, peer_seq, *local_seq),
+        };
+        let packet = if self.protocol == IPPROTO_UDP {
+            ip_packet(src, dst, *ip_id, &udp_datagram(src, dst, &frame.bytes)?)?
+        } else {
+            let segment = tcp_segment(src, dst, *seq, ack, &frame.bytes)?;
+            #[allow(clippy::cast_possible_truncation)]
+            let len = frame.bytes.len() as u32;
+            *seq = seq.wrapping_add(len);
+            ip_packet(src, dst, *ip_id, &segment)?
+        };
+        *ip_id = ip_id.wrapping_add(1);
+
+        let timestamp = frame
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        #[allow(clippy::cast_possible_truncation)]
+        let (timestamp_high, timestamp_low) = ((timestamp >> 32) as u32, timestamp as u32);
+        #[allow(clippy::cast_possible_truncation)]
+        let packet_len = packet.len() as u32;
+        let padding = (4 - packet.len() % 4) % 4;
+        #[allow(clippy::cast_possible_truncation)]
+        let block_len = (32 + packet.len() + padding) as u32;
+        let mut block = Vec::with_capacity(block_len as usize);
+        block.extend_from_slice(&ENHANCED_PACKET_BLOCK.to_le_bytes());
+        block.extend_from_slice(&block_len.to_le_bytes());
+        // The interface ID
+        block.extend_from_slice(&0u32.to_le_bytes());
+        block.extend_from_slice(&timestamp_high.to_le_bytes());
+        block.extend_from_slice(&timestamp_low.to_le_bytes());
+        block.extend_from_slice(&packet_len.to_le_bytes());
+        block.extend_from_slice(&packet_len.to_le_bytes());
+        block.extend_from_slice(&packet);
+        block.resize(block.len() + padding, 0);
+        block.extend_from_slice(&block_len.to_le_bytes());
+        writer.write_all(&block)?;
+        writer.flush()
+    }
+
+    /// Finish the capture and return the writer.
+    pub fn into_inner(self) -> W {
+        self.state
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .writer
+    }
+}
+
+impl<W: Write + Send> FrameTap for PcapngWriter<W> {
+    fn tap(&self, frame: &Frame) {
+        if let Err(err) = self.write_frame(frame) {
+            log::warn!("Failed to capture frame: {err}");
+        }
+    }
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Frame too large")
+}
+
+/// Synthesize a TCP segment that contains the `payload`.
+fn tcp_segment(
+    src: SocketAddr,
+    dst: SocketAddr,
+    seq: u32,
+    ack: u32,
+    payload: &[u8],
+) -> io::Result<Segment> {
+    const TCP_HEADER_LEN: usize = 20;
+
+    if TCP_HEADER_LEN + payload.len() > usize::from(u16::MAX) {
+        return Err(too_large());
+    }
+    let mut bytes = Vec::with_capacity(TCP_HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&src.port().to_be_bytes());
+    bytes.extend_from_slice(&dst.port().to_be_bytes());
+    bytes.extend_from_slice(&seq.to_be_bytes());
+    bytes.extend_from_slice(&ack.to_be_bytes());
+    // Data offset in 32-bit words.
+    bytes.push(5 << 4);
+    bytes.push(TCP_PSH_ACK);
+    // Window size
+    bytes.extend_from_slice(&u16::MAX.to_be_bytes());
+    // Checksum
+    bytes.extend_from_slice(&0u16.to_be_bytes());
+    // Urgent pointer
+    bytes.extend_from_slice(&0u16.to_be_bytes());
+    bytes.extend_from_slice(payload);
+    Ok(Segment {
+        protocol: IPPROTO_TCP,
+        checksum_offset: 16,
+        bytes,
+    })
+}
+
+/// Synthesize a UDP datagram that contains the `payload`.
+fn udp_datagram(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> io::Result<Segment> {
+    const UDP_HEADER_LEN: usize = 8;
+
+    let udp_len = u16::try_from(UDP_HEADER_LEN + payload.len()).map_err(|_| too_large())?;
+    let mut bytes = Vec::with_capacity(UDP_HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&src.port().to_be_bytes());
+    bytes.extend_from_slice(&dst.port().to_be_bytes());
+    bytes.extend_from_slice(&udp_len.to_be_bytes());
+    // Checksum
+    bytes.extend_from_slice(&0u16.to_be_bytes());
+    bytes.extend_from_slice(payload);
+    Ok(Segment {
+        protocol: IPPROTO_UDP,
+        checksum_offset: 6,
+        bytes,
+    })
+}
+
+/// A TCP segment or a UDP datagram without a checksum.
+struct Segment {
+    protocol: u8,
+    checksum_offset: usize,
+    bytes: Vec<u8>,
+}
+
+/// Synthesize an IP packet that contains the `segment`.
+///
+/// The checksum of the segment is calculated with the pseudo-header.
+fn ip_packet(
+    src: SocketAddr,
+    dst: SocketAddr,
+    ip_id: u16,
+    segment: &Segment,
+) -> io::Result<Vec<u8>> {
+    let Segment {
+        protocol,
+        checksum_offset,
+        bytes: segment,
+    } = segment;
+    let protocol = *protocol;
+    let segment_len = u16::try_from(segment.len()).map_err(|_| too_large())?;
+    let mut packet;
+    let mut pseudo_header = Vec::with_capacity(40);
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            const IPV4_HEADER_LEN: usize = 20;
+            let total_len =
+                u16::try_from(IPV4_HEADER_LEN + segment.len()).map_err(|_| too_large())?;
+            packet = Vec::with_capacity(IPV4_HEADER_LEN + segment.len());
+            // Version and header length in 32-bit words.
+            packet.push(0x45);
+            packet.push(0);
+            packet.extend_from_slice(&total_len.to_be_bytes());
+            packet.extend_from_slice(&ip_id.to_be_bytes());
+            // Don't fragment
+            packet.extend_from_slice(&0x4000u16.to_be_bytes());
+            packet.push(TTL);
+            packet.push(protocol);
+            packet.extend_from_slice(&0u16.to_be_bytes());
+            packet.extend_from_slice(&src.octets());
+            packet.extend_from_slice(&dst.octets());
+            let header_checksum = checksum(&[&packet]);
+            packet[10..12].copy_from_slice(&header_checksum.to_be_bytes());
+
+            pseudo_header.extend_from_slice(&src.octets());
+            pseudo_header.extend_from_slice(&dst.octets());
+            pseudo_header.extend_from_slice(&[0, protocol]);
+            pseudo_header.extend_from_slice(&segment_len.to_be_bytes());
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            packet = Vec::with_capacity(40 + segment.len());
+            // Version, traffic class, and flow label
+            packet.extend_from_slice(&0x6000_0000u32.to_be_bytes());
+            packet.extend_from_slice(&segment_len.to_be_bytes());
+            packet.push(protocol);
+            packet.push(TTL);
+            packet.extend_from_slice(&src.octets());
+            packet.extend_from_slice(&dst.octets());
+
+            pseudo_header.extend_from_slice(&src.octets());
+            pseudo_header.extend_from_slice(&dst.octets());
+            pseudo_header.extend_from_slice(&u32::from(segment_len).to_be_bytes());
+            pseudo_header.extend_from_slice(&[0, 0, 0, protocol]);
+        }
+        _ => unreachable!("addresses of the same family"),
+    }
+    let mut segment_checksum = checksum(&[&pseudo_header, segment]);
+    if protocol == IPPROTO_UDP && segment_checksum == 0 {
+        // A zero checksum is transmitted as all ones, otherwise
+        // it would indicate that the checksum has not been calculated.
+        segment_checksum = 0xFFFF;
+    }
+    let start = packet.len();
+    packet.extend_from_slice(segment);
+    packet[start + checksum_offset..start + checksum_offset + 2]
+        .copy_from_slice(&segment_checksum.to_be_bytes());
+    Ok(packet)
+}
+
+/// The Internet checksum (RFC 1071) of consecutive chunks.
+///
+/// All chunks except the last must have an even length.
+fn checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum = chunks
+        .iter()
+        .flat_map(|chunk| chunk.chunks(2))
+        .map(|word| u32::from(u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)])))
+        .sum::<u32>();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let sum = sum as u16;
+    !sum
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::{bytes::Bytes, tap::Pdu, Request, Slave};
+
+    use super::*;
+
+    #[test]
+    fn write_frames() {
+        let local = "10.0.0.1:50200".parse().unwrap();
+        let peer = "10.0.0.2:502".parse().unwrap();
+        let capture = PcapngWriter::new(Vec::new(), local, peer).unwrap();
+        let mbap = [
+            0x00, 0x2A, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x10, 0x00, 0x00, 0x04,
+        ];
+        let frame = Frame {
+            direction: Direction::Sent,
+            time: SystemTime::UNIX_EPOCH + Duration::from_micros(0x1_0000_0002),
+            bytes: Bytes::copy_from_slice(&mbap),
+            slave: Slave(1),
+            transaction_id: Some(42),
+            pdu: Pdu::Request(Request::ReadHoldingRegisters(0x1000, 4)),
+        };
+        capture.write_frame(&frame).unwrap();
+        // Frames without an MBAP header are ignored.
+        capture
+            .write_frame(&Frame {
+                transaction_id: None,
+                ..frame.clone()
+            })
+            .unwrap();
+        capture
+            .write_frame(&Frame {
+                direction: Direction::Received,
+                ..frame
+            })
+            .unwrap();
+        let file = capture.into_inner();
+
+        assert_eq!(file[..4], SECTION_HEADER_BLOCK.to_le_bytes());
+        assert_eq!(file[8..12], BYTE_ORDER_MAGIC.to_le_bytes());
+        let idb = &file[28..48];
+        assert_eq!(idb[..4], INTERFACE_DESCRIPTION_BLOCK.to_le_bytes());
+        assert_eq!(idb[8..10], LINKTYPE_RAW.to_le_bytes());
+
+        // 20 bytes IPv4, 20 bytes TCP, 12 bytes MBAP
+        let epb_len = 32 + 52;
+        assert_eq!(file.len(), 48 + 2 * epb_len);
+        let epb = &file[48..48 + epb_len];
+        assert_eq!(epb[..4], ENHANCED_PACKET_BLOCK.to_le_bytes());
+        assert_eq!(epb[12..16], 1u32.to_le_bytes());
+        assert_eq!(epb[16..20], 2u32.to_le_bytes());
+        assert_eq!(epb[20..24], 52u32.to_le_bytes());
+        let packet = &epb[28..80];
+        assert_eq!(packet[12..16], [10, 0, 0, 1]);
+        assert_eq!(packet[16..20], [10, 0, 0, 2]);
+        assert_eq!(checksum(&[&packet[..20]]), 0);
+        assert_eq!(packet[20..22], 50200u16.to_be_bytes());
+        assert_eq!(packet[22..24], 502u16.to_be_bytes());
+        assert_eq!(packet[40..], mbap);
+        let pseudo_header = [10, 0, 0, 1, 10, 0, 0, 2, 0, IPPROTO_TCP, 0, 32];
+        assert_eq!(checksum(&[&pseudo_header, &packet[20..]]), 0);
+
+        // The response is acknowledging the request.
+        let packet = &file[48 + epb_len + 28..48 + 2 * epb_len - 4];
+        assert_eq!(packet[12..16], [10, 0, 0, 2]);
+        assert_eq!(packet[24..28], 1u32.to_be_bytes());
+        assert_eq!(packet[28..32], 13u32.to_be_bytes());
+    }
+
+    #[test]
+    fn write_udp_datagrams() {
+        let local = "10.0.0.1:50200".parse().unwrap();
+        let peer = "10.0.0.2:502".parse().unwrap();
+        let capture = PcapngWriter::new_udp(Vec::new(), local, peer).unwrap();
+        let mbap = [
+            0x00, 0x2A, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x10, 0x00, 0x00, 0x04,
+        ];
+        capture
+            .write_frame(&Frame {
+                direction: Direction::Received,
+                time: SystemTime::UNIX_EPOCH,
+                bytes: Bytes::copy_from_slice(&mbap),
+                slave: Slave(1),
+                transaction_id: Some(42),
+                pdu: Pdu::Request(Request::ReadHoldingRegisters(0x1000, 4)),
+            })
+            .unwrap();
+        let file = capture.into_inner();
+
+        // 20 bytes IPv4, 8 bytes UDP, 12 bytes MBAP
+        let epb_len = 32 + 40;
+        assert_eq!(file.len(), 48 + epb_len);
+        let packet = &file[48 + 28..48 + 28 + 40];
+        assert_eq!(packet[9], IPPROTO_UDP);
+        assert_eq!(checksum(&[&packet[..20]]), 0);
+        let datagram = &packet[20..];
+        assert_eq!(datagram[0..2], 502u16.to_be_bytes());
+        assert_eq!(datagram[2..4], 50200u16.to_be_bytes());
+        assert_eq!(datagram[4..6], 20u16.to_be_bytes());
+        assert_eq!(datagram[8..], mbap);
+        let pseudo_header = [10, 0, 0, 2, 10, 0, 0, 1, 0, IPPROTO_UDP, 0, 20];
+        assert_eq!(checksum(&[&pseudo_header, datagram]), 0);
+    }
+}
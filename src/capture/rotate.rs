@@ -0,0 +1,559 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Rotation of capture files
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    mutex::lock,
+    tap::{Frame, FrameTap},
+};
+
+use super::{CompressedWriter, Compression, PcapngWriter, IPPROTO_TCP, IPPROTO_UDP};
+
+/// When capture files are rotated and how many are retained.
+///
+/// The files are named after the path with an ascending number, e.g.
+/// `modbus-000001.pcapng` for the path `modbus`. Without any limits
+/// all frames are written into a single file.
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    path: PathBuf,
+    max_file_size: Option<u64>,
+    max_file_age: Option<Duration>,
+    max_files: Option<usize>,
+    compression: Compression,
+}
+
+impl Rotation {
+    /// Write capture files next to `path`.
+    ///
+    /// The directory must exist.
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_file_size: None,
+            max_file_age: None,
+            max_files: None,
+            compression: Compression::None,
+        }
+    }
+
+    /// Continue with a new file after a file has reached the given size.
+    ///
+    /// Files could exceed the size by a single frame. The size of
+    /// compressed files is the compressed size.
+    #[must_use]
+    pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = Some(max_file_size);
+        self
+    }
+
+    /// Continue with a new file after a file has been written for the
+    /// given time.
+    ///
+    /// Checked before writing a frame, i.e. files are not rotated while
+    /// no frames are captured.
+    #[must_use]
+    pub fn max_file_age(mut self, max_file_age: Duration) -> Self {
+        self.max_file_age = Some(max_file_age);
+        self
+    }
+
+    /// Delete the oldest files when more than `max_files` files exist,
+    /// including the current file.
+    ///
+    /// Files of previous captures with the same path are also deleted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_files` is zero.
+    #[must_use]
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        assert!(max_files > 0, "the current file must be retained");
+        self.max_files = Some(max_files);
+        self
+    }
+
+    /// Compress the files.
+    ///
+    /// Defaults to [`Compression::None`].
+    #[must_use]
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    fn file_path(&self, index: u64) -> PathBuf {
+        let mut file_name = self.path.file_name().unwrap_or_default().to_owned();
+        file_name.push(format!("-{index:06}{}", self.compression.extension()));
+        self.path.with_file_name(file_name)
+    }
+
+    fn dir(&self) -> &Path {
+        match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        }
+    }
+
+    /// The number of a file of a capture with the same path.
+    fn file_index(&self, file_name: &str) -> Option<u64> {
+        let prefix = self.path.file_name()?.to_str()?;
+        let index = file_name
+            .strip_prefix(prefix)?
+            .strip_prefix('-')?
+            .strip_suffix(Compression::None.extension())
+            .or_else(|| {
+                file_name
+                    .strip_prefix(prefix)?
+                    .strip_prefix('-')?
+                    .strip_suffix(Compression::Gzip.extension())
+            })?;
+        if index.is_empty() || !index.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        index.parse().ok()
+    }
+
+    /// The files of previous captures, oldest first.
+    fn existing_files(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(self.dir())? {
+            let entry = entry?;
+            let Some(index) = entry.file_name().to_str().and_then(|n| self.file_index(n)) else {
+                continue;
+            };
+            files.push((index, entry.path()));
+        }
+        files.sort_unstable();
+        Ok(files)
+    }
+}
+
+/// Writes tapped Modbus TCP frames into rotating pcapng files.
+///
+/// Same as [`PcapngWriter`], but bounds the disk usage of always-on
+/// captures, e.g. of the server of a gateway, according to a [`Rotation`].
+/// Each file is a complete capture that starts with its own header.
+/// Snapshots of incidents could be taken with [`Self::rotate()`].
+///
+/// ```no_run
+/// # #[cfg(feature = "tcp-server")]
+/// # async fn capture() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::{sync::Arc, time::Duration};
+///
+/// use tokio_modbus::{
+///     capture::{Compression, RotatingPcapngWriter, Rotation},
+///     server::tcp::Server,
+///     tap::{Frame, FrameTap as _},
+/// };
+///
+/// let rotation = Rotation::new("/var/log/modbus/gateway")
+///     .max_file_size(10 * 1024 * 1024)
+///     .max_file_age(Duration::from_secs(3600))
+///     .max_files(48)
+///     .compression(Compression::Gzip);
+/// let capture = Arc::new(RotatingPcapngWriter::new(
+///     rotation,
+///     "192.168.0.1:502".parse()?,
+///     "0.0.0.0:0".parse()?,
+/// )?);
+/// let listener = tokio::net::TcpListener::bind("0.0.0.0:502").await?;
+/// let server = Server::new(listener).with_tap({
+///     let capture = Arc::clone(&capture);
+///     move |frame: &Frame| capture.tap(frame)
+/// });
+/// // ...
+///
+/// // Preserve the traffic that led to an incident.
+/// let snapshot = capture.rotate()?;
+/// std::fs::rename(&snapshot, "/var/log/modbus/incident.pcapng.gz")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RotatingPcapngWriter {
+    rotation: Rotation,
+    local: SocketAddr,
+    peer: SocketAddr,
+    protocol: u8,
+    state: Mutex<State>,
+}
+
+impl fmt::Debug for RotatingPcapngWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RotatingPcapngWriter")
+            .field("rotation", &self.rotation)
+            .field("local", &self.local)
+            .field("peer", &self.peer)
+            .finish_non_exhaustive()
+    }
+}
+
+struct State {
+    current: Option<CaptureFile>,
+    next_index: u64,
+    /// All retained files including the current file, oldest first.
+    files: VecDeque<PathBuf>,
+}
+
+struct CaptureFile {
+    path: PathBuf,
+    writer: PcapngWriter<CompressedWriter<CountingWriter>>,
+    written: Arc<AtomicU64>,
+    opened: Instant,
+    frames: u64,
+}
+
+impl RotatingPcapngWriter {
+    /// Start a capture of a TCP connection between the `local` and
+    /// the `peer` endpoint.
+    ///
+    /// Creates the first file. The numbering continues after the files
+    /// of previous captures with the same path.
+    ///
+    /// See also: [`PcapngWriter::new()`]
+    pub fn new(rotation: Rotation, local: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        Self::with_protocol(rotation, local, peer, IPPROTO_TCP)
+    }
+
+    /// Start a capture of UDP datagrams between the `local` and
+    /// the `peer` endpoint.
+    ///
+    /// See also: [`Self::new()`]
+    pub fn new_udp(rotation: Rotation, local: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        Self::with_protocol(rotation, local, peer, IPPROTO_UDP)
+    }
+
+    fn with_protocol(
+        rotation: Rotation,
+        local: SocketAddr,
+        peer: SocketAddr,
+        protocol: u8,
+    ) -> io::Result<Self> {
+        let existing_files = rotation.existing_files()?;
+        let next_index = existing_files.last().map_or(1, |(index, _)| index + 1);
+        let writer = Self {
+            rotation,
+            local,
+            peer,
+            protocol,
+            state: Mutex::new(State {
+                current: None,
+                next_index,
+                files: existing_files.into_iter().map(|(_, path)| path).collect(),
+            }),
+        };
+        writer.open(&mut lock(&writer.state))?;
+        Ok(writer)
+    }
+
+    /// Write a frame into the current file.
+    ///
+    /// Rotates the files before and after writing the frame if needed.
+    ///
+    /// See also: [`PcapngWriter::write_frame()`]
+    pub fn write_frame(&self, frame: &Frame) -> io::Result<()> {
+        let mut state = lock(&self.state);
+        if let (Some(current), Some(max_file_age)) = (&state.current, self.rotation.max_file_age) {
+            if current.frames > 0 && current.opened.elapsed() >= max_file_age {
+                self.rotate_locked(&mut state)?;
+            }
+        }
+        let current = self.current(&mut state)?;
+        current.writer.write_frame(frame)?;
+        current.frames += 1;
+        if let Some(max_file_size) = self.rotation.max_file_size {
+            if current.written.load(Ordering::Relaxed) >= max_file_size {
+                self.rotate_locked(&mut state)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Complete the current file and continue with a new file.
+    ///
+    /// Returns the path of the completed file. The completed file is
+    /// subject to the retention of [`Rotation::max_files()`] unless it
+    /// is moved to another path.
+    pub fn rotate(&self) -> io::Result<PathBuf> {
+        self.rotate_locked(&mut lock(&self.state))
+    }
+
+    /// The path of the file that is currently written.
+    #[must_use]
+    pub fn current_path(&self) -> Option<PathBuf> {
+        lock(&self.state)
+            .current
+            .as_ref()
+            .map(|current| current.path.clone())
+    }
+
+    /// Complete the current file.
+    ///
+    /// The current file is also completed when dropped, but errors
+    /// are only logged.
+    pub fn finish(self) -> io::Result<()> {
+        let current = lock(&self.state).current.take();
+        current.map_or(Ok(()), CaptureFile::finish)
+    }
+
+    fn current<'a>(&self, state: &'a mut State) -> io::Result<&'a mut CaptureFile> {
+        if state.current.is_none() {
+            // Opening the previous file has failed.
+            self.open(state)?;
+        }
+        Ok(state.current.as_mut().expect("opened"))
+    }
+
+    fn rotate_locked(&self, state: &mut State) -> io::Result<PathBuf> {
+        let current = self.current(state)?;
+        let path = current.path.clone();
+        state.current.take().expect("opened").finish()?;
+        self.open(state)?;
+        Ok(path)
+    }
+
+    fn open(&self, state: &mut State) -> io::Result<()> {
+        let path = self.rotation.file_path(state.next_index);
+        state.next_index += 1;
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        let written = Arc::new(AtomicU64::new(0));
+        let file = CountingWriter {
+            writer: BufWriter::new(file),
+            written: Arc::clone(&written),
+        };
+        let file = CompressedWriter::new(file, self.rotation.compression);
+        let writer = PcapngWriter::with_protocol(file, self.local, self.peer, self.protocol)?;
+        state.current = Some(CaptureFile {
+            path: path.clone(),
+            writer,
+            written,
+            opened: Instant::now(),
+            frames: 0,
+        });
+        state.files.push_back(path);
+        if let Some(max_files) = self.rotation.max_files {
+            while state.files.len() > max_files {
+                let Some(path) = state.files.pop_front() else {
+                    break;
+                };
+                log::debug!("Deleting capture file {}", path.display());
+                match fs::remove_file(&path) {
+                    Ok(()) => {}
+                    // The file might have been moved.
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RotatingPcapngWriter {
+    fn drop(&mut self) {
+        if let Some(current) = lock(&self.state).current.take() {
+            if let Err(err) = current.finish() {
+                log::warn!("Failed to complete capture file: {err}");
+            }
+        }
+    }
+}
+
+impl FrameTap for RotatingPcapngWriter {
+    fn tap(&self, frame: &Frame) {
+        if let Err(err) = self.write_frame(frame) {
+            log::warn!("Failed to capture frame: {err}");
+        }
+    }
+}
+
+impl CaptureFile {
+    fn finish(self) -> io::Result<()> {
+        self.writer.into_inner().finish().map(drop)
+    }
+}
+
+/// Counts the bytes that are written into a file.
+struct CountingWriter {
+    writer: BufWriter<File>,
+    written: Arc<AtomicU64>,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.writer.write(buf)?;
+        self.written.fetch_add(len as u64, Ordering::Relaxed);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read as _, time::SystemTime};
+
+    use crate::{bytes::Bytes, tap::Direction, tap::Pdu, Request, Slave};
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tokio-modbus-{name}-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn frame(transaction_id: u16) -> Frame {
+        let [hi, lo] = transaction_id.to_be_bytes();
+        Frame {
+            direction: Direction::Sent,
+            time: SystemTime::UNIX_EPOCH,
+            bytes: Bytes::copy_from_slice(&[hi, lo, 0, 0, 0, 6, 1, 3, 0, 0, 0, 1]),
+            slave: Slave(1),
+            transaction_id: Some(transaction_id),
+            pdu: Pdu::Request(Request::ReadHoldingRegisters(0, 1)),
+        }
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn rotate_files_by_size_and_retain_the_latest() {
+        let dir = temp_dir("rotate");
+        let local = "10.0.0.1:50200".parse().unwrap();
+        let peer = "10.0.0.2:502".parse().unwrap();
+        // The header and 2 frames of 84 bytes.
+        let rotation = Rotation::new(dir.join("modbus"))
+            .max_file_size(48 + 2 * 84)
+            .max_files(2);
+        let capture = RotatingPcapngWriter::new(rotation.clone(), local, peer).unwrap();
+        for transaction_id in 0..5 {
+            capture.write_frame(&frame(transaction_id)).unwrap();
+        }
+        assert_eq!(
+            file_names(&dir),
+            ["modbus-000002.pcapng", "modbus-000003.pcapng"]
+        );
+        assert_eq!(
+            fs::metadata(dir.join("modbus-000002.pcapng"))
+                .unwrap()
+                .len(),
+            48 + 2 * 84
+        );
+
+        // Rotate on demand.
+        let snapshot = capture.rotate().unwrap();
+        assert_eq!(snapshot, dir.join("modbus-000003.pcapng"));
+        assert_eq!(fs::metadata(&snapshot).unwrap().len(), 48 + 84);
+        assert_eq!(
+            capture.current_path(),
+            Some(dir.join("modbus-000004.pcapng"))
+        );
+        capture.finish().unwrap();
+
+        // The numbering continues after a restart.
+        let capture = RotatingPcapngWriter::new(rotation, local, peer).unwrap();
+        assert_eq!(
+            capture.current_path(),
+            Some(dir.join("modbus-000005.pcapng"))
+        );
+        drop(capture);
+        assert_eq!(
+            file_names(&dir),
+            ["modbus-000004.pcapng", "modbus-000005.pcapng"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotate_files_by_age() {
+        let dir = temp_dir("age");
+        let rotation = Rotation::new(dir.join("modbus")).max_file_age(Duration::ZERO);
+        let capture = RotatingPcapngWriter::new(
+            rotation,
+            "10.0.0.1:50200".parse().unwrap(),
+            "10.0.0.2:502".parse().unwrap(),
+        )
+        .unwrap();
+        // Empty files are not rotated.
+        capture.write_frame(&frame(0)).unwrap();
+        capture.write_frame(&frame(1)).unwrap();
+        capture.finish().unwrap();
+        assert_eq!(
+            file_names(&dir),
+            ["modbus-000001.pcapng", "modbus-000002.pcapng"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn compress_files() {
+        let dir = temp_dir("gzip");
+        let local = "10.0.0.1:50200".parse().unwrap();
+        let peer = "10.0.0.2:502".parse().unwrap();
+        let rotation = Rotation::new(dir.join("modbus")).compression(Compression::Gzip);
+        let capture = RotatingPcapngWriter::new(rotation, local, peer).unwrap();
+        let uncompressed = PcapngWriter::new(Vec::new(), local, peer).unwrap();
+        for transaction_id in 0..100 {
+            capture.write_frame(&frame(transaction_id)).unwrap();
+            uncompressed.write_frame(&frame(transaction_id)).unwrap();
+        }
+        let path = capture.current_path().unwrap();
+        assert_eq!(path, dir.join("modbus-000001.pcapng.gz"));
+        capture.finish().unwrap();
+
+        let uncompressed = uncompressed.into_inner();
+        let compressed = fs::read(path).unwrap();
+        assert!(compressed.len() < uncompressed.len() / 2);
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, uncompressed);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_file_names() {
+        let rotation = Rotation::new("captures/modbus");
+        assert_eq!(rotation.dir(), Path::new("captures"));
+        assert_eq!(rotation.file_index("modbus-000042.pcapng"), Some(42));
+        assert_eq!(
+            rotation.file_index("modbus-1234567.pcapng.gz"),
+            Some(1_234_567)
+        );
+        assert_eq!(rotation.file_index("modbus-.pcapng"), None);
+        assert_eq!(rotation.file_index("modbus-+1.pcapng"), None);
+        assert_eq!(rotation.file_index("modbus-1.pcap"), None);
+        assert_eq!(rotation.file_index("other-000001.pcapng"), None);
+        assert_eq!(Rotation::new("modbus").dir(), Path::new("."));
+    }
+}
@@ -0,0 +1,276 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Two-step writes that need to be armed before execution
+
+use std::{io, time::Duration};
+
+use crate::{
+    frame::{Address, Word},
+    Result,
+};
+
+use super::{label::LogPrefix, Writer};
+
+/// Writes that need to be armed before they are executed.
+///
+/// Many safety-adjacent devices only accept commands in two steps,
+/// also known as _select before operate_: An arm register is written
+/// first and the command is then written into an execute register
+/// within a device specific time window. The device disarms itself
+/// after the time window has elapsed.
+///
+/// An instance describes the profile of a device and could be used
+/// for any number of writes.
+#[derive(Debug, Clone)]
+pub struct ArmedWrite {
+    arm_addr: Address,
+    execute_addr: Address,
+    timeout: Duration,
+    arm_value: Word,
+    disarm_value: Word,
+}
+
+impl ArmedWrite {
+    /// Arm by writing the register at `arm_addr` and execute by writing
+    /// the register at `execute_addr` within `timeout`.
+    ///
+    /// The arm register is armed with 1 and disarmed with 0 by default.
+    #[must_use]
+    pub const fn new(arm_addr: Address, execute_addr: Address, timeout: Duration) -> Self {
+        Self {
+            arm_addr,
+            execute_addr,
+            timeout,
+            arm_value: 1,
+            disarm_value: 0,
+        }
+    }
+
+    /// The value that is written into the arm register for arming.
+    #[must_use]
+    pub const fn arm_value(mut self, arm_value: Word) -> Self {
+        self.arm_value = arm_value;
+        self
+    }
+
+    /// The value that is written into the arm register for disarming.
+    #[must_use]
+    pub const fn disarm_value(mut self, disarm_value: Word) -> Self {
+        self.disarm_value = disarm_value;
+        self
+    }
+
+    /// The address of the arm register.
+    #[must_use]
+    pub const fn arm_addr(&self) -> Address {
+        self.arm_addr
+    }
+
+    /// The address of the execute register.
+    #[must_use]
+    pub const fn execute_addr(&self) -> Address {
+        self.execute_addr
+    }
+
+    /// The time within which the execute register needs to be written
+    /// after arming.
+    #[must_use]
+    pub const fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Arm and write `value` into the execute register (0x06 + 0x06).
+    ///
+    /// If executing fails or doesn't complete within the timeout, then
+    /// the arm register is disarmed explicitly before returning the error
+    /// or exception, i.e. the device doesn't remain armed until the time
+    /// window elapses. The same applies if arming fails unexpectedly, i.e.
+    /// not with an exception, because the device might have been armed
+    /// nevertheless.
+    ///
+    /// Failing to disarm is logged as an error.
+    pub async fn write<W>(&self, writer: &mut W, value: Word) -> Result<()>
+    where
+        W: Writer + ?Sized,
+    {
+        let Self {
+            execute_addr,
+            timeout,
+            ..
+        } = *self;
+        match writer
+            .write_single_register(self.arm_addr, self.arm_value)
+            .await
+        {
+            Ok(Ok(())) => (),
+            Ok(Err(exception)) => return Ok(Err(exception)),
+            Err(err) => {
+                self.disarm(writer).await;
+                return Err(err);
+            }
+        }
+        let result =
+            tokio::time::timeout(timeout, writer.write_single_register(execute_addr, value))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("execute register {execute_addr} not written within {timeout:?}"),
+                    )
+                    .into())
+                });
+        if !matches!(result, Ok(Ok(()))) {
+            self.disarm(writer).await;
+        }
+        result
+    }
+
+    async fn disarm<W>(&self, writer: &mut W)
+    where
+        W: Writer + ?Sized,
+    {
+        let label = writer.label().map(ToOwned::to_owned);
+        let prefix = LogPrefix(label.as_deref());
+        let arm_addr = self.arm_addr;
+        match writer
+            .write_single_register(arm_addr, self.disarm_value)
+            .await
+        {
+            Ok(Ok(())) => (),
+            Ok(Err(exception)) => {
+                log::error!("{prefix}Failed to disarm register {arm_addr}: {exception}");
+            }
+            Err(err) => {
+                log::error!("{prefix}Failed to disarm register {arm_addr}: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future,
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+
+    use crate::{
+        client::{Client, Context, SlaveContext},
+        Error, ExceptionCode, Request, Response, Slave,
+    };
+
+    use super::*;
+
+    const ARM_ADDR: Address = 10;
+    const EXECUTE_ADDR: Address = 11;
+
+    #[derive(Debug, Clone, Copy, Default)]
+    enum Failure {
+        #[default]
+        None,
+        Exception,
+        Transport,
+        Stuck,
+    }
+
+    #[derive(Debug, Default)]
+    struct DeviceMock {
+        arm_failure: Failure,
+        execute_failure: Failure,
+        writes: Arc<Mutex<Vec<(Address, Word)>>>,
+    }
+
+    #[async_trait]
+    impl Client for DeviceMock {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            let Request::WriteSingleRegister(addr, word) = request else {
+                return Ok(Err(ExceptionCode::IllegalFunction));
+            };
+            let failure = match (addr, word) {
+                (ARM_ADDR, 1) => self.arm_failure,
+                (EXECUTE_ADDR, _) => self.execute_failure,
+                _ => Failure::None,
+            };
+            match failure {
+                Failure::None => (),
+                Failure::Exception => return Ok(Err(ExceptionCode::IllegalDataValue)),
+                Failure::Transport => {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe").into());
+                }
+                Failure::Stuck => future::pending().await,
+            }
+            self.writes.lock().unwrap().push((addr, word));
+            Ok(Ok(Response::WriteSingleRegister(addr, word)))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for DeviceMock {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    async fn write(
+        arm_failure: Failure,
+        execute_failure: Failure,
+    ) -> (Result<()>, Vec<(Address, Word)>) {
+        let client = DeviceMock {
+            arm_failure,
+            execute_failure,
+            ..Default::default()
+        };
+        let writes = Arc::clone(&client.writes);
+        let client: Box<dyn Client> = Box::new(client);
+        let mut context = Context::from(client);
+        let armed_write = ArmedWrite::new(ARM_ADDR, EXECUTE_ADDR, Duration::from_millis(10));
+        let result = armed_write.write(&mut context, 42).await;
+        let writes = writes.lock().unwrap().clone();
+        (result, writes)
+    }
+
+    #[tokio::test]
+    async fn arm_and_execute() {
+        let (result, writes) = write(Failure::None, Failure::None).await;
+        assert_eq!(result.unwrap(), Ok(()));
+        assert_eq!(writes, [(ARM_ADDR, 1), (EXECUTE_ADDR, 42)]);
+    }
+
+    #[tokio::test]
+    async fn skip_execute_if_arming_is_rejected() {
+        let (result, writes) = write(Failure::Exception, Failure::None).await;
+        assert_eq!(result.unwrap(), Err(ExceptionCode::IllegalDataValue));
+        assert!(writes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn disarm_if_arming_fails() {
+        let (result, writes) = write(Failure::Transport, Failure::None).await;
+        assert!(matches!(result, Err(Error::Transport(_))));
+        assert_eq!(writes, [(ARM_ADDR, 0)]);
+    }
+
+    #[tokio::test]
+    async fn disarm_if_execute_fails() {
+        let (result, writes) = write(Failure::None, Failure::Exception).await;
+        assert_eq!(result.unwrap(), Err(ExceptionCode::IllegalDataValue));
+        assert_eq!(writes, [(ARM_ADDR, 1), (ARM_ADDR, 0)]);
+
+        let (result, writes) = write(Failure::None, Failure::Transport).await;
+        assert!(matches!(result, Err(Error::Transport(_))));
+        assert_eq!(writes, [(ARM_ADDR, 1), (ARM_ADDR, 0)]);
+    }
+
+    #[tokio::test]
+    async fn disarm_if_execute_times_out() {
+        let (result, writes) = write(Failure::None, Failure::Stuck).await;
+        assert!(
+            matches!(result, Err(Error::Transport(err)) if err.kind() == io::ErrorKind::TimedOut)
+        );
+        assert_eq!(writes, [(ARM_ADDR, 1), (ARM_ADDR, 0)]);
+    }
+}
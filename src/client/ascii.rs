@@ -0,0 +1,56 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! ASCII client connections
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::*;
+
+/// Connect to no particular Modbus slave device for sending
+/// broadcast messages.
+pub fn attach<T>(transport: T) -> Context
+where
+    T: AsyncRead + AsyncWrite + Debug + Unpin + Send + 'static,
+{
+    attach_slave(transport, Slave::broadcast())
+}
+
+/// Connect to any kind of Modbus slave device.
+pub fn attach_slave<T>(transport: T, slave: Slave) -> Context
+where
+    T: AsyncRead + AsyncWrite + Debug + Unpin + Send + 'static,
+{
+    let client = crate::service::rtu::Client::with_codec(
+        transport,
+        crate::codec::ascii::ClientCodec::default(),
+        slave,
+    );
+    Context::new_serial(Box::new(client), slave)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn read_holding_registers() {
+        const REQUEST: &[u8] = b":1103006B00037E\r\n";
+
+        let (transport, mut device) = tokio::io::duplex(1024);
+        let mut context = attach_slave(transport, Slave(0x11));
+        let respond = async {
+            let mut buf = [0; REQUEST.len()];
+            device.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, REQUEST);
+            device
+                .write_all(b":110306022B0000006455\r\n")
+                .await
+                .unwrap();
+        };
+        let (res, ()) = tokio::join!(context.read_holding_registers(0x006B, 3), respond);
+        assert_eq!(res.unwrap(), Ok(vec![0x022B, 0x0000, 0x0064]));
+    }
+}
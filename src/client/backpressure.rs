@@ -0,0 +1,183 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Detect sustained failures of requests
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::mutex::lock;
+
+use super::Context;
+
+/// Minimum number of requests within the window before engaging.
+const DEFAULT_MIN_REQUESTS: usize = 10;
+
+#[derive(Debug, Default)]
+struct Window {
+    /// The time and failure of each request, oldest first.
+    requests: VecDeque<(Instant, bool)>,
+    failures: usize,
+    engaged: bool,
+}
+
+/// Signals sustained failures of requests to pause polling.
+///
+/// Engages if more than the given percentage of all requests within a
+/// sliding time window failed, e.g. due to a degraded RS-485 segment or
+/// an unreachable gateway. Exception responses are not considered as
+/// failures. All attempts are counted, including retries of a
+/// [`RetryPolicy`](super::RetryPolicy).
+///
+/// While engaged, monitored contexts don't repeat failed requests.
+/// Schedulers could check [`Self::is_engaged()`] or wait for
+/// [`Self::released()`] before polling again to avoid retry storms that
+/// further saturate the communication. The backpressure is released
+/// when the failures have dropped below the threshold or expired.
+///
+/// The window is shared by all clones, e.g. for monitoring all contexts
+/// of a bus segment together.
+#[derive(Debug, Clone)]
+pub struct Backpressure {
+    window: Duration,
+    max_failure_percent: u8,
+    min_requests: usize,
+    shared: Arc<Mutex<Window>>,
+}
+
+impl Backpressure {
+    /// Engage if more than `max_failure_percent` of all requests
+    /// failed within the `window`.
+    ///
+    /// At least 10 requests are required within the window
+    /// unless configured otherwise.
+    #[must_use]
+    pub fn new(window: Duration, max_failure_percent: u8) -> Self {
+        Self {
+            window,
+            max_failure_percent: max_failure_percent.min(100),
+            min_requests: DEFAULT_MIN_REQUESTS,
+            shared: Arc::default(),
+        }
+    }
+
+    /// The minimum number of requests within the window before engaging.
+    #[must_use]
+    pub const fn min_requests(mut self, min_requests: usize) -> Self {
+        self.min_requests = min_requests;
+        self
+    }
+
+    /// Monitor all requests of a client context.
+    #[must_use]
+    pub fn monitor(&self, mut context: Context) -> Context {
+        context.options.backpressure = Some(self.clone());
+        context
+    }
+
+    /// Checks if too many requests failed recently.
+    #[must_use]
+    pub fn is_engaged(&self) -> bool {
+        self.update(Instant::now(), None)
+    }
+
+    /// Wait until the backpressure has been released.
+    ///
+    /// Returns immediately if not engaged.
+    pub async fn released(&self) {
+        loop {
+            let now = Instant::now();
+            let expiry = {
+                let mut window = lock(&self.shared);
+                if !self.update_window(&mut window, now, None) {
+                    return;
+                }
+                window
+                    .requests
+                    .front()
+                    .map_or(now, |(time, _)| *time + self.window)
+            };
+            tokio::time::sleep_until(expiry.into()).await;
+        }
+    }
+
+    pub(super) fn record(&self, failed: bool) {
+        self.update(Instant::now(), Some(failed));
+    }
+
+    fn update(&self, now: Instant, failed: Option<bool>) -> bool {
+        self.update_window(&mut lock(&self.shared), now, failed)
+    }
+
+    /// Expire old requests and record a new request.
+    ///
+    /// Returns `true` if engaged.
+    fn update_window(&self, window: &mut Window, now: Instant, failed: Option<bool>) -> bool {
+        while let Some((time, failed)) = window.requests.front().copied() {
+            if now.saturating_duration_since(time) < self.window {
+                break;
+            }
+            window.requests.pop_front();
+            window.failures -= usize::from(failed);
+        }
+        if let Some(failed) = failed {
+            window.requests.push_back((now, failed));
+            window.failures += usize::from(failed);
+        }
+        let requests = window.requests.len();
+        let failures = window.failures;
+        let engaged = requests >= self.min_requests.max(1)
+            && failures * 100 > requests * usize::from(self.max_failure_percent);
+        if engaged != window.engaged {
+            window.engaged = engaged;
+            if engaged {
+                log::warn!(
+                    "Engaging backpressure: {failures} of {requests} request(s) failed within {window:?}",
+                    window = self.window
+                );
+            } else {
+                log::info!("Releasing backpressure");
+            }
+        }
+        engaged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engage_and_release() {
+        let backpressure = Backpressure::new(Duration::from_secs(10), 50).min_requests(4);
+        let start = Instant::now();
+        let mut window = Window::default();
+        // Too few requests.
+        for failed in [false, true, true] {
+            assert!(!backpressure.update_window(&mut window, start, Some(failed)));
+        }
+        // Exactly 50%.
+        assert!(!backpressure.update_window(&mut window, start, Some(false)));
+        assert!(backpressure.update_window(
+            &mut window,
+            start + Duration::from_secs(5),
+            Some(true)
+        ));
+        // The first 4 requests have expired.
+        assert!(!backpressure.update_window(&mut window, start + Duration::from_secs(10), None));
+        assert_eq!(window.requests.len(), 1);
+        assert_eq!(window.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn released_when_failures_expire() {
+        let backpressure = Backpressure::new(Duration::from_millis(20), 0).min_requests(1);
+        backpressure.record(true);
+        assert!(backpressure.is_engaged());
+        backpressure.released().await;
+        assert!(!backpressure.is_engaged());
+    }
+}
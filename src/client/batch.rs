@@ -0,0 +1,460 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Outcome of multiple requests and planning of reads
+
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::{Address, Error, ExceptionCode, Quantity, Request, Response, Result};
+
+/// The failure of a single item in a batch.
+#[derive(Debug)]
+pub enum BatchFailure {
+    /// The server responded with an exception.
+    Exception(ExceptionCode),
+
+    /// The request failed due to a protocol or transport error.
+    Error(Error),
+}
+
+/// The outcome of a batch of requests.
+///
+/// Captures the result of each item individually instead of failing
+/// the whole batch on the first error. Items are ordered like the
+/// requests, i.e. the indices are preserved.
+#[derive(Debug)]
+pub struct BatchOutcome<T> {
+    results: Vec<Result<T>>,
+}
+
+impl<T> BatchOutcome<T> {
+    /// The number of items.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Check if the batch contains no items.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Check if all items succeeded.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.results
+            .iter()
+            .all(|result| matches!(result, Ok(Ok(_))))
+    }
+
+    /// The result of a single item.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&Result<T>> {
+        self.results.get(index)
+    }
+
+    /// The results of all items.
+    pub fn results(&self) -> &[Result<T>] {
+        &self.results
+    }
+
+    /// The indices of all items that failed.
+    #[must_use]
+    pub fn failed_indices(&self) -> Vec<usize> {
+        self.results
+            .iter()
+            .enumerate()
+            .filter_map(|(index, result)| (!matches!(result, Ok(Ok(_)))).then_some(index))
+            .collect()
+    }
+
+    /// The successful items with their indices.
+    pub fn successes(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.results
+            .iter()
+            .enumerate()
+            .filter_map(|(index, result)| match result {
+                Ok(Ok(item)) => Some((index, item)),
+                _ => None,
+            })
+    }
+
+    /// Replace the result of a single item, e.g. after retrying it.
+    ///
+    /// Returns the previous result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn replace(&mut self, index: usize, result: Result<T>) -> Result<T> {
+        std::mem::replace(&mut self.results[index], result)
+    }
+
+    /// Split the items into successes and failures, preserving the indices.
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn partition(self) -> (Vec<(usize, T)>, Vec<(usize, BatchFailure)>) {
+        let mut successes = Vec::new();
+        let mut failures = Vec::new();
+        for (index, result) in self.results.into_iter().enumerate() {
+            match result {
+                Ok(Ok(item)) => successes.push((index, item)),
+                Ok(Err(exception)) => failures.push((index, BatchFailure::Exception(exception))),
+                Err(err) => failures.push((index, BatchFailure::Error(err))),
+            }
+        }
+        (successes, failures)
+    }
+
+    /// The results of all items.
+    #[must_use]
+    pub fn into_results(self) -> Vec<Result<T>> {
+        self.results
+    }
+
+    /// Collect all items or fail on the first error or exception.
+    pub fn into_all(self) -> Result<Vec<T>> {
+        let mut items = Vec::with_capacity(self.results.len());
+        for result in self.results {
+            match result? {
+                Ok(item) => items.push(item),
+                Err(exception) => return Ok(Err(exception)),
+            }
+        }
+        Ok(Ok(items))
+    }
+}
+
+impl<T> FromIterator<Result<T>> for BatchOutcome<T> {
+    fn from_iter<I: IntoIterator<Item = Result<T>>>(iter: I) -> Self {
+        Self {
+            results: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<T> From<Vec<Result<T>>> for BatchOutcome<T> {
+    fn from(results: Vec<Result<T>>) -> Self {
+        Self { results }
+    }
+}
+
+/// Reads scattered values with as few requests as possible.
+///
+/// The targets are given as address and quantity. Overlapping and
+/// adjacent targets are merged into a single request, as well as
+/// targets that are separated by at most [`Self::with_max_gap()`]
+/// addresses. Requests are split at the maximum quantity that fits
+/// into a PDU.
+///
+/// Use [`Context::read_planned()`](super::Context::read_planned) for
+/// executing the plan.
+///
+/// ```
+/// use tokio_modbus::client::ReadPlan;
+///
+/// let plan = ReadPlan::holding_registers([(0x100, 2), (0x102, 2), (0x108, 1), (0x200, 4)])
+///     .with_max_gap(4);
+/// assert_eq!(plan.requests().collect::<Vec<_>>(), [(0x100, 9), (0x200, 4)]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReadPlan<T> {
+    targets: Vec<(Address, Quantity)>,
+    max_gap: Quantity,
+    max_quantity: Quantity,
+    spans: Vec<Range<u32>>,
+    request: fn(Address, Quantity) -> Request<'static>,
+    values: fn(&Response) -> Option<&[T]>,
+}
+
+impl ReadPlan<u16> {
+    /// Plan reading holding registers (0x03).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a target exceeds the address space.
+    #[must_use]
+    pub fn holding_registers(targets: impl IntoIterator<Item = (Address, Quantity)>) -> Self {
+        Self::new(
+            targets,
+            MAX_READ_REGISTERS,
+            Request::ReadHoldingRegisters,
+            |response| match response {
+                Response::ReadHoldingRegisters(words) => Some(words),
+                _ => None,
+            },
+        )
+    }
+
+    /// Plan reading input registers (0x04).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a target exceeds the address space.
+    #[must_use]
+    pub fn input_registers(targets: impl IntoIterator<Item = (Address, Quantity)>) -> Self {
+        Self::new(
+            targets,
+            MAX_READ_REGISTERS,
+            Request::ReadInputRegisters,
+            |response| match response {
+                Response::ReadInputRegisters(words) => Some(words),
+                _ => None,
+            },
+        )
+    }
+}
+
+impl ReadPlan<bool> {
+    /// Plan reading coils (0x01).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a target exceeds the address space.
+    #[must_use]
+    pub fn coils(targets: impl IntoIterator<Item = (Address, Quantity)>) -> Self {
+        Self::new(
+            targets,
+            super::MAX_READ_BITS,
+            Request::ReadCoils,
+            |response| match response {
+                Response::ReadCoils(coils) => Some(coils),
+                _ => None,
+            },
+        )
+    }
+
+    /// Plan reading discrete inputs (0x02).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a target exceeds the address space.
+    #[must_use]
+    pub fn discrete_inputs(targets: impl IntoIterator<Item = (Address, Quantity)>) -> Self {
+        Self::new(
+            targets,
+            super::MAX_READ_BITS,
+            Request::ReadDiscreteInputs,
+            |response| match response {
+                Response::ReadDiscreteInputs(inputs) => Some(inputs),
+                _ => None,
+            },
+        )
+    }
+}
+
+impl<T> ReadPlan<T> {
+    fn new(
+        targets: impl IntoIterator<Item = (Address, Quantity)>,
+        max_quantity: Quantity,
+        request: fn(Address, Quantity) -> Request<'static>,
+        values: fn(&Response) -> Option<&[T]>,
+    ) -> Self {
+        let mut targets: Vec<_> = targets.into_iter().collect();
+        for &(addr, cnt) in &targets {
+            assert!(
+                u32::from(addr) + u32::from(cnt) <= u32::from(Address::MAX) + 1,
+                "reading {cnt} values from address {addr} exceeds the address space"
+            );
+        }
+        targets.sort_unstable();
+        targets.dedup();
+        let mut plan = Self {
+            targets,
+            max_gap: 0,
+            max_quantity,
+            spans: Vec::new(),
+            request,
+            values,
+        };
+        plan.merge();
+        plan
+    }
+
+    /// Merge targets that are separated by at most `max_gap` addresses.
+    ///
+    /// The values in between are read and discarded. Only allow gaps
+    /// if the device permits reading them, otherwise the whole request
+    /// is rejected with an exception.
+    #[must_use]
+    pub fn with_max_gap(mut self, max_gap: Quantity) -> Self {
+        self.max_gap = max_gap;
+        self.merge();
+        self
+    }
+
+    /// Limit the quantity of a single request, e.g. for devices with
+    /// small buffers.
+    ///
+    /// The limit can't exceed the maximum quantity that fits into a PDU.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_quantity` is zero.
+    #[must_use]
+    pub fn with_max_quantity(mut self, max_quantity: Quantity) -> Self {
+        assert!(max_quantity > 0, "requests must not be empty");
+        self.max_quantity = self.max_quantity.min(max_quantity);
+        self
+    }
+
+    /// The deduplicated targets in ascending order.
+    #[must_use]
+    pub fn targets(&self) -> &[(Address, Quantity)] {
+        &self.targets
+    }
+
+    /// The address and quantity of all requests.
+    pub fn requests(&self) -> impl Iterator<Item = (Address, Quantity)> + '_ {
+        self.spans.iter().flat_map(|span| self.chunks(span))
+    }
+
+    fn merge(&mut self) {
+        self.spans.clear();
+        let max_gap = u32::from(self.max_gap);
+        for &(addr, cnt) in self.targets.iter().filter(|(_, cnt)| *cnt > 0) {
+            let target = u32::from(addr)..u32::from(addr) + u32::from(cnt);
+            match self.spans.last_mut() {
+                Some(span) if target.start <= span.end + max_gap => {
+                    span.end = span.end.max(target.end);
+                }
+                _ => self.spans.push(target),
+            }
+        }
+    }
+
+    /// Split a span into requests with the maximum quantity.
+    pub(super) fn chunks(&self, span: &Range<u32>) -> impl Iterator<Item = (Address, Quantity)> {
+        let max_quantity = u32::from(self.max_quantity);
+        let end = span.end;
+        span.clone()
+            .step_by(max_quantity as usize)
+            .map(move |start| {
+                let cnt = (end - start).min(max_quantity);
+                // Spans are within the address space and chunks within the
+                // maximum quantity.
+                #[allow(clippy::cast_possible_truncation)]
+                (start as Address, cnt as Quantity)
+            })
+    }
+
+    pub(super) fn spans(&self) -> &[Range<u32>] {
+        &self.spans
+    }
+
+    pub(super) fn request(&self, addr: Address, cnt: Quantity) -> Request<'static> {
+        (self.request)(addr, cnt)
+    }
+
+    pub(super) fn values<'r>(&self, response: &'r Response) -> Option<&'r [T]> {
+        (self.values)(response)
+    }
+
+    /// Pick the values of the targets from the values of the spans.
+    pub(super) fn pick(
+        &self,
+        spans: &[(Range<u32>, Vec<T>)],
+    ) -> BTreeMap<(Address, Quantity), Vec<T>>
+    where
+        T: Clone,
+    {
+        self.targets
+            .iter()
+            .map(|&(addr, cnt)| {
+                let start = u32::from(addr);
+                let values = if cnt == 0 {
+                    Vec::new()
+                } else {
+                    let index = spans.partition_point(|(span, _)| span.start <= start) - 1;
+                    let (span, values) = &spans[index];
+                    let offset = (start - span.start) as usize;
+                    values[offset..offset + usize::from(cnt)].to_vec()
+                };
+                ((addr, cnt), values)
+            })
+            .collect()
+    }
+}
+
+/// The maximum number of registers that can be read with a single request.
+const MAX_READ_REGISTERS: Quantity = 125;
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    fn outcome() -> BatchOutcome<u16> {
+        vec![
+            Ok(Ok(1)),
+            Ok(Err(ExceptionCode::IllegalDataAddress)),
+            Err(Error::Transport(io::ErrorKind::TimedOut.into())),
+            Ok(Ok(4)),
+        ]
+        .into()
+    }
+
+    #[test]
+    fn partition_preserves_indices() {
+        let outcome = outcome();
+        assert!(!outcome.is_success());
+        assert_eq!(outcome.failed_indices(), [1, 2]);
+        assert_eq!(outcome.successes().collect::<Vec<_>>(), [(0, &1), (3, &4)]);
+
+        let (successes, failures) = outcome.partition();
+        assert_eq!(successes, [(0, 1), (3, 4)]);
+        assert!(matches!(
+            failures[..],
+            [
+                (
+                    1,
+                    BatchFailure::Exception(ExceptionCode::IllegalDataAddress)
+                ),
+                (2, BatchFailure::Error(Error::Transport(_)))
+            ]
+        ));
+    }
+
+    #[test]
+    fn replace_failed_items() {
+        let mut outcome = outcome();
+        for index in outcome.failed_indices() {
+            let _previous = outcome.replace(index, Ok(Ok(0)));
+        }
+        assert!(outcome.is_success());
+        assert_eq!(outcome.into_all().unwrap().unwrap(), [1, 0, 0, 4]);
+    }
+
+    #[test]
+    fn into_all_fails_on_first_failure() {
+        let result = outcome().into_all();
+        assert_eq!(result.unwrap(), Err(ExceptionCode::IllegalDataAddress));
+    }
+
+    #[test]
+    fn plan_reads() {
+        let plan = ReadPlan::holding_registers([(10, 2), (0, 4), (2, 4), (0, 4), (8, 0), (20, 1)]);
+        assert_eq!(plan.targets(), [(0, 4), (2, 4), (8, 0), (10, 2), (20, 1)]);
+        assert_eq!(
+            plan.requests().collect::<Vec<_>>(),
+            [(0, 6), (10, 2), (20, 1)]
+        );
+
+        let plan = plan.with_max_gap(4);
+        assert_eq!(plan.requests().collect::<Vec<_>>(), [(0, 12), (20, 1)]);
+
+        let plan = plan.with_max_gap(8).with_max_quantity(8);
+        assert_eq!(
+            plan.requests().collect::<Vec<_>>(),
+            [(0, 8), (8, 8), (16, 5)]
+        );
+
+        let plan = ReadPlan::coils([(0, 2000), (2000, 1), (u16::MAX, 1)]);
+        assert_eq!(
+            plan.requests().collect::<Vec<_>>(),
+            [(0, 2000), (2000, 1), (u16::MAX, 1)]
+        );
+    }
+}
@@ -0,0 +1,334 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Structured logging of client calls
+
+use std::{
+    fmt::{self, Write as _},
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{frame::*, mutex::lock, Result, Slave};
+
+struct Shared {
+    enabled: AtomicBool,
+    writer: Mutex<Box<dyn io::Write + Send>>,
+}
+
+/// Writes each call of a [`Context`](super::Context) and its result as a
+/// single line of JSON, e.g. for audit trails.
+///
+/// Each line is an object with the following fields:
+///
+/// - `timestamp`: The time when the request has been sent (RFC 3339, UTC)
+/// - `direction`: `"read"` or `"write"`, `null` for other functions
+/// - `unit`: The slave or unit ID, `null` if not set
+/// - `fc`: The function code
+/// - `addr`: The first address, `null` for other functions
+/// - `qty`: The number of coils or registers, `null` for other functions
+/// - `values`: The values that have been read or written
+/// - `exception`: The exception code of an exception response
+/// - `error`: The message of a failed call
+///
+/// The last three fields are only present if applicable. Calls that are
+/// repeated by a [`RetryPolicy`](super::RetryPolicy) are logged once with
+/// their final result.
+///
+/// Logging is enabled initially and could be toggled at runtime by
+/// [`Self::set_enabled()`]. All clones share the same writer and state.
+///
+/// # Example
+///
+/// ```no_run
+/// # #[cfg(feature = "tcp")]
+/// # async fn log() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::{fs::File, io::BufWriter};
+///
+/// use tokio_modbus::client::{tcp, CallLog};
+///
+/// let call_log = CallLog::new(BufWriter::new(File::create("calls.jsonl")?));
+/// let mut ctx = tcp::connect("192.168.0.222:502".parse()?).await?;
+/// ctx.set_call_log(Some(call_log.clone()));
+/// // ...
+/// call_log.set_enabled(false);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// A read of two holding registers is logged as:
+///
+/// ```json
+/// {"timestamp":"2024-05-01T12:00:00.000Z","direction":"read","unit":1,"fc":3,"addr":4096,"qty":2,"values":[1,2]}
+/// ```
+#[derive(Clone)]
+pub struct CallLog(Arc<Shared>);
+
+impl fmt::Debug for CallLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallLog")
+            .field("enabled", &self.is_enabled())
+            .finish_non_exhaustive()
+    }
+}
+
+impl CallLog {
+    /// Write the lines to `writer`.
+    ///
+    /// The writer should be buffered if it is slow, because lines are
+    /// written while processing calls.
+    #[must_use]
+    pub fn new(writer: impl io::Write + Send + 'static) -> Self {
+        Self(Arc::new(Shared {
+            enabled: AtomicBool::new(true),
+            writer: Mutex::new(Box::new(writer)),
+        }))
+    }
+
+    /// Enable or disable logging.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Check if logging is enabled.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.0.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Write the line of a call.
+    ///
+    /// Failures are logged and don't affect the call.
+    pub(crate) fn record(
+        &self,
+        time: SystemTime,
+        slave: Option<Slave>,
+        request: &Request<'_>,
+        result: &Result<DecodedResponse>,
+    ) {
+        let mut line = String::new();
+        format_line(&mut line, time, slave, request, result)
+            .unwrap_or_else(|_| unreachable!("formatting into a string never fails"));
+        line.push('\n');
+        let mut writer = lock(&self.0.writer);
+        if let Err(err) = writer
+            .write_all(line.as_bytes())
+            .and_then(|()| writer.flush())
+        {
+            log::warn!("Failed to write call log: {err}");
+        }
+    }
+}
+
+fn format_line(
+    line: &mut String,
+    time: SystemTime,
+    slave: Option<Slave>,
+    request: &Request<'_>,
+    result: &Result<DecodedResponse>,
+) -> fmt::Result {
+    use Request::*;
+
+    let (direction, addr, qty) = match request {
+        ReadCoils(addr, cnt)
+        | ReadDiscreteInputs(addr, cnt)
+        | ReadInputRegisters(addr, cnt)
+        | ReadHoldingRegisters(addr, cnt)
+        | ReadWriteMultipleRegisters(addr, cnt, _, _) => (Some("read"), Some(*addr), Some(*cnt)),
+        WriteSingleCoil(addr, _) | WriteSingleRegister(addr, _) | MaskWriteRegister(addr, _, _) => {
+            (Some("write"), Some(*addr), Some(1))
+        }
+        WriteMultipleCoils(addr, coils) => {
+            (Some("write"), Some(*addr), u16::try_from(coils.len()).ok())
+        }
+        WriteMultipleRegisters(addr, words) => {
+            (Some("write"), Some(*addr), u16::try_from(words.len()).ok())
+        }
+        _ => (None, None, None),
+    };
+    line.push_str("{\"timestamp\":\"");
+    format_timestamp(line, time)?;
+    line.push_str("\",\"direction\":");
+    match direction {
+        Some(direction) => write!(line, "\"{direction}\"")?,
+        None => line.push_str("null"),
+    }
+    line.push_str(",\"unit\":");
+    format_optional(line, slave.map(|slave| slave.0))?;
+    write!(line, ",\"fc\":{}", request.function_code().value())?;
+    line.push_str(",\"addr\":");
+    format_optional(line, addr)?;
+    line.push_str(",\"qty\":");
+    format_optional(line, qty)?;
+    match result {
+        Ok(Ok(DecodedResponse::Registers(_, registers))) => format_values(line, registers.iter())?,
+        Ok(Ok(DecodedResponse::Response(response))) => match (request, response) {
+            (_, Response::ReadCoils(coils) | Response::ReadDiscreteInputs(coils)) => {
+                format_values(line, coils)?;
+            }
+            (
+                _,
+                Response::ReadInputRegisters(words)
+                | Response::ReadHoldingRegisters(words)
+                | Response::ReadWriteMultipleRegisters(words),
+            ) => format_values(line, words)?,
+            (WriteSingleCoil(_, coil), _) => format_values(line, [*coil])?,
+            (WriteMultipleCoils(_, coils), _) => format_values(line, coils.iter())?,
+            (WriteSingleRegister(_, word), _) => format_values(line, [*word])?,
+            (WriteMultipleRegisters(_, words), _) => format_values(line, words.iter())?,
+            _ => {}
+        },
+        Ok(Err(exception)) => write!(line, ",\"exception\":{}", u8::from(*exception))?,
+        Err(err) => {
+            line.push_str(",\"error\":");
+            format_string(line, &err.to_string())?;
+        }
+    }
+    line.push('}');
+    Ok(())
+}
+
+fn format_optional(line: &mut String, value: Option<impl fmt::Display>) -> fmt::Result {
+    if let Some(value) = value {
+        write!(line, "{value}")
+    } else {
+        line.push_str("null");
+        Ok(())
+    }
+}
+
+fn format_values(
+    line: &mut String,
+    values: impl IntoIterator<Item = impl fmt::Display>,
+) -> fmt::Result {
+    line.push_str(",\"values\":[");
+    for (i, value) in values.into_iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        write!(line, "{value}")?;
+    }
+    line.push(']');
+    Ok(())
+}
+
+fn format_string(line: &mut String, value: &str) -> fmt::Result {
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c if c.is_control() => write!(line, "\\u{:04x}", u32::from(c))?,
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+    Ok(())
+}
+
+/// Format the time as RFC 3339 in UTC with milliseconds.
+fn format_timestamp(line: &mut String, time: SystemTime) -> fmt::Result {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01, see
+    // <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    write!(
+        line,
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, time::Duration};
+
+    use crate::{data::Registers, Error, ExceptionCode};
+
+    use super::*;
+
+    fn line(request: &Request<'_>, result: &Result<DecodedResponse>) -> String {
+        let time = UNIX_EPOCH + Duration::from_millis(1_714_564_800_123);
+        let mut line = String::new();
+        format_line(&mut line, time, Some(Slave(1)), request, result).unwrap();
+        line
+    }
+
+    #[test]
+    fn format_calls() {
+        assert_eq!(
+            line(
+                &Request::ReadHoldingRegisters(0x1000, 2),
+                &Ok(Ok(Response::ReadHoldingRegisters(vec![1, 2]).into()))
+            ),
+            r#"{"timestamp":"2024-05-01T12:00:00.123Z","direction":"read","unit":1,"fc":3,"addr":4096,"qty":2,"values":[1,2]}"#
+        );
+        assert_eq!(
+            line(
+                &Request::ReadInputRegisters(0x1000, 2),
+                &Ok(Ok(DecodedResponse::Registers(
+                    FunctionCode::ReadInputRegisters,
+                    Registers::from([1, 2].as_slice())
+                )))
+            ),
+            r#"{"timestamp":"2024-05-01T12:00:00.123Z","direction":"read","unit":1,"fc":4,"addr":4096,"qty":2,"values":[1,2]}"#
+        );
+        assert_eq!(
+            line(
+                &Request::WriteMultipleCoils(3, Cow::Borrowed(&[true, false])),
+                &Ok(Ok(Response::WriteMultipleCoils(3, 2).into()))
+            ),
+            r#"{"timestamp":"2024-05-01T12:00:00.123Z","direction":"write","unit":1,"fc":15,"addr":3,"qty":2,"values":[true,false]}"#
+        );
+        assert_eq!(
+            line(
+                &Request::ReadCoils(0, 1),
+                &Ok(Err(ExceptionCode::IllegalDataAddress))
+            ),
+            r#"{"timestamp":"2024-05-01T12:00:00.123Z","direction":"read","unit":1,"fc":1,"addr":0,"qty":1,"exception":2}"#
+        );
+        assert_eq!(
+            line(
+                &Request::ReportServerId,
+                &Err(Error::Transport(io::Error::other("broken \"pipe\"")))
+            ),
+            r#"{"timestamp":"2024-05-01T12:00:00.123Z","direction":null,"unit":1,"fc":17,"addr":null,"qty":null,"error":"broken \"pipe\""}"#
+        );
+    }
+
+    #[test]
+    fn format_timestamps() {
+        let mut line = String::new();
+        format_timestamp(&mut line, UNIX_EPOCH).unwrap();
+        assert_eq!(line, "1970-01-01T00:00:00.000Z");
+        line.clear();
+        // Leap day
+        format_timestamp(&mut line, UNIX_EPOCH + Duration::from_secs(951_868_799)).unwrap();
+        assert_eq!(line, "2000-02-29T23:59:59.000Z");
+    }
+}
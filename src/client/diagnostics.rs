@@ -0,0 +1,189 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Counters of serial line devices
+
+use crate::DiagnosticsSubFunction;
+
+/// A counter of a serial line device that is returned by a
+/// diagnostics sub-function (0x08).
+///
+/// All counters are reset when the device is restarted and by
+/// [`DiagnosticsSubFunction::ClearCountersAndDiagnosticRegister`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticsCounter {
+    /// The number of messages that the device detected on the bus.
+    BusMessage,
+
+    /// The number of CRC errors.
+    BusCommunicationError,
+
+    /// The number of exception responses of the device.
+    BusExceptionError,
+
+    /// The number of messages that have been addressed to the device,
+    /// including broadcasts.
+    ServerMessage,
+
+    /// The number of messages that the device didn't respond to,
+    /// e.g. broadcasts.
+    ServerNoResponse,
+
+    /// The number of negative acknowledge exception responses.
+    ServerNak,
+
+    /// The number of server device busy exception responses.
+    ServerBusy,
+
+    /// The number of messages that couldn't be handled due to a
+    /// character overrun.
+    BusCharacterOverrun,
+}
+
+impl DiagnosticsCounter {
+    /// All counters in the order of their sub-functions.
+    pub const ALL: [Self; 8] = [
+        Self::BusMessage,
+        Self::BusCommunicationError,
+        Self::BusExceptionError,
+        Self::ServerMessage,
+        Self::ServerNoResponse,
+        Self::ServerNak,
+        Self::ServerBusy,
+        Self::BusCharacterOverrun,
+    ];
+
+    /// The sub-function that returns this counter.
+    #[must_use]
+    pub const fn sub_function(self) -> DiagnosticsSubFunction {
+        match self {
+            Self::BusMessage => DiagnosticsSubFunction::ReturnBusMessageCount,
+            Self::BusCommunicationError => DiagnosticsSubFunction::ReturnBusCommunicationErrorCount,
+            Self::BusExceptionError => DiagnosticsSubFunction::ReturnBusExceptionErrorCount,
+            Self::ServerMessage => DiagnosticsSubFunction::ReturnServerMessageCount,
+            Self::ServerNoResponse => DiagnosticsSubFunction::ReturnServerNoResponseCount,
+            Self::ServerNak => DiagnosticsSubFunction::ReturnServerNakCount,
+            Self::ServerBusy => DiagnosticsSubFunction::ReturnServerBusyCount,
+            Self::BusCharacterOverrun => DiagnosticsSubFunction::ReturnBusCharacterOverrunCount,
+        }
+    }
+
+    /// The counter that is returned by a sub-function, if any.
+    #[must_use]
+    pub const fn from_sub_function(sub_function: DiagnosticsSubFunction) -> Option<Self> {
+        // Custom sub-functions might also denote these sub-functions.
+        let counter = match DiagnosticsSubFunction::new(sub_function.value()) {
+            DiagnosticsSubFunction::ReturnBusMessageCount => Self::BusMessage,
+            DiagnosticsSubFunction::ReturnBusCommunicationErrorCount => Self::BusCommunicationError,
+            DiagnosticsSubFunction::ReturnBusExceptionErrorCount => Self::BusExceptionError,
+            DiagnosticsSubFunction::ReturnServerMessageCount => Self::ServerMessage,
+            DiagnosticsSubFunction::ReturnServerNoResponseCount => Self::ServerNoResponse,
+            DiagnosticsSubFunction::ReturnServerNakCount => Self::ServerNak,
+            DiagnosticsSubFunction::ReturnServerBusyCount => Self::ServerBusy,
+            DiagnosticsSubFunction::ReturnBusCharacterOverrunCount => Self::BusCharacterOverrun,
+            _ => return None,
+        };
+        Some(counter)
+    }
+}
+
+/// A summary of all counters of a serial line device.
+///
+/// See also: [`Context::read_bus_counters()`](super::Context::read_bus_counters)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BusCounters {
+    /// See [`DiagnosticsCounter::BusMessage`].
+    pub bus_messages: u16,
+
+    /// See [`DiagnosticsCounter::BusCommunicationError`].
+    pub bus_communication_errors: u16,
+
+    /// See [`DiagnosticsCounter::BusExceptionError`].
+    pub bus_exception_errors: u16,
+
+    /// See [`DiagnosticsCounter::ServerMessage`].
+    pub server_messages: u16,
+
+    /// See [`DiagnosticsCounter::ServerNoResponse`].
+    pub server_no_responses: u16,
+
+    /// See [`DiagnosticsCounter::ServerNak`].
+    pub server_naks: u16,
+
+    /// See [`DiagnosticsCounter::ServerBusy`].
+    pub server_busy: u16,
+
+    /// See [`DiagnosticsCounter::BusCharacterOverrun`].
+    pub bus_character_overruns: u16,
+}
+
+impl BusCounters {
+    /// The value of a single counter.
+    #[must_use]
+    pub const fn get(&self, counter: DiagnosticsCounter) -> u16 {
+        match counter {
+            DiagnosticsCounter::BusMessage => self.bus_messages,
+            DiagnosticsCounter::BusCommunicationError => self.bus_communication_errors,
+            DiagnosticsCounter::BusExceptionError => self.bus_exception_errors,
+            DiagnosticsCounter::ServerMessage => self.server_messages,
+            DiagnosticsCounter::ServerNoResponse => self.server_no_responses,
+            DiagnosticsCounter::ServerNak => self.server_naks,
+            DiagnosticsCounter::ServerBusy => self.server_busy,
+            DiagnosticsCounter::BusCharacterOverrun => self.bus_character_overruns,
+        }
+    }
+
+    /// Mutable access to a single counter.
+    pub fn get_mut(&mut self, counter: DiagnosticsCounter) -> &mut u16 {
+        match counter {
+            DiagnosticsCounter::BusMessage => &mut self.bus_messages,
+            DiagnosticsCounter::BusCommunicationError => &mut self.bus_communication_errors,
+            DiagnosticsCounter::BusExceptionError => &mut self.bus_exception_errors,
+            DiagnosticsCounter::ServerMessage => &mut self.server_messages,
+            DiagnosticsCounter::ServerNoResponse => &mut self.server_no_responses,
+            DiagnosticsCounter::ServerNak => &mut self.server_naks,
+            DiagnosticsCounter::ServerBusy => &mut self.server_busy,
+            DiagnosticsCounter::BusCharacterOverrun => &mut self.bus_character_overruns,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_sub_functions() {
+        for counter in DiagnosticsCounter::ALL {
+            let sub_function = counter.sub_function();
+            assert_eq!(
+                DiagnosticsCounter::from_sub_function(sub_function),
+                Some(counter)
+            );
+            assert!(!sub_function.echoes_data());
+            assert_eq!(
+                DiagnosticsCounter::from_sub_function(DiagnosticsSubFunction::Custom(
+                    sub_function.value()
+                )),
+                Some(counter)
+            );
+        }
+        assert_eq!(
+            DiagnosticsCounter::from_sub_function(DiagnosticsSubFunction::ReturnDiagnosticRegister),
+            None
+        );
+    }
+
+    #[test]
+    fn access_counters() {
+        let mut counters = BusCounters::default();
+        for (value, counter) in (1..).zip(DiagnosticsCounter::ALL) {
+            *counters.get_mut(counter) = value;
+        }
+        for (value, counter) in (1..).zip(DiagnosticsCounter::ALL) {
+            assert_eq!(counters.get(counter), value);
+        }
+        assert_eq!(counters.bus_communication_errors, 2);
+        assert_eq!(counters.server_busy, 7);
+    }
+}
@@ -0,0 +1,277 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Validate requests without sending them
+
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    frame::{Quantity, Request, Response},
+    mutex::lock,
+    slave::{Slave, SlaveContext},
+    ExceptionCode, Result,
+};
+
+use super::{Client, Context};
+
+type Rule = Box<dyn FnMut(Slave, &Request<'_>) -> Option<ResponseResult> + Send>;
+
+type ResponseResult = std::result::Result<Response, ExceptionCode>;
+
+#[derive(Default)]
+struct Shared {
+    requests: Vec<(Slave, Request<'static>)>,
+    responses: VecDeque<ResponseResult>,
+    rules: Vec<Rule>,
+}
+
+/// A client that records requests instead of sending them.
+///
+/// Intended for validating generated request sequences, e.g. of recipes
+/// or configuration downloads, before executing them on live equipment.
+/// No transport is involved.
+///
+/// Each request is answered by the first applicable source:
+///
+/// 1. Canned responses in the order they have been pushed.
+/// 2. Rules in the order they have been added.
+/// 3. Default responses: Reads return zeros, writes are echoed,
+///    and all other requests fail with [`ExceptionCode::IllegalFunction`].
+///
+/// The recorded requests, canned responses, and rules are shared by all
+/// clones.
+///
+/// # Example
+///
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use tokio_modbus::{client::DryRunClient, prelude::*};
+///
+/// let client = DryRunClient::new();
+/// let mut ctx = client.attach_slave(Slave(1));
+/// ctx.write_single_register(0x1000, 42).await??;
+/// assert_eq!(
+///     client.requests(),
+///     [(Slave(1), Request::WriteSingleRegister(0x1000, 42))]
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DryRunClient {
+    slave: Slave,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl fmt::Debug for DryRunClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DryRunClient")
+            .field("slave", &self.slave)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for DryRunClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DryRunClient {
+    /// Create a new client without any canned responses and rules.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            slave: Slave::tcp_device(),
+            shared: Arc::default(),
+        }
+    }
+
+    /// Attach a new client context for the given slave.
+    #[must_use]
+    pub fn attach_slave(&self, slave: Slave) -> Context {
+        let mut client = self.clone();
+        client.slave = slave;
+        Context::with_slave(Box::new(client), slave)
+    }
+
+    /// Answer the next unanswered request with a canned response.
+    pub fn push_response(&self, response: std::result::Result<Response, ExceptionCode>) {
+        lock(&self.shared).responses.push_back(response);
+    }
+
+    /// Answer requests by a rule.
+    ///
+    /// The rule returns `None` for requests that it doesn't apply to.
+    pub fn add_rule(
+        &self,
+        rule: impl FnMut(Slave, &Request<'_>) -> Option<std::result::Result<Response, ExceptionCode>>
+            + Send
+            + 'static,
+    ) {
+        lock(&self.shared).rules.push(Box::new(rule));
+    }
+
+    /// All requests that have been recorded so far.
+    #[must_use]
+    pub fn requests(&self) -> Vec<(Slave, Request<'static>)> {
+        lock(&self.shared).requests.clone()
+    }
+
+    /// Take all requests that have been recorded so far.
+    #[must_use]
+    pub fn take_requests(&self) -> Vec<(Slave, Request<'static>)> {
+        std::mem::take(&mut lock(&self.shared).requests)
+    }
+}
+
+#[async_trait]
+impl Client for DryRunClient {
+    async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+        let slave = self.slave;
+        log::info!("Dry run for slave {slave}: {request:?}");
+        let mut shared = lock(&self.shared);
+        let response = if let Some(response) = shared.responses.pop_front() {
+            response
+        } else if let Some(response) = shared
+            .rules
+            .iter_mut()
+            .find_map(|rule| rule(slave, &request))
+        {
+            response
+        } else {
+            default_response(&request)
+        };
+        shared.requests.push((slave, request.into_owned()));
+        Ok(response)
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SlaveContext for DryRunClient {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = slave;
+    }
+}
+
+fn quantity(len: usize) -> Quantity {
+    Quantity::try_from(len).unwrap_or(Quantity::MAX)
+}
+
+/// Reads return zeros and writes are echoed.
+fn default_response(request: &Request<'_>) -> ResponseResult {
+    use Request::*;
+
+    let response = match request {
+        ReadCoils(_, cnt) => Response::ReadCoils(vec![false; (*cnt).into()]),
+        ReadDiscreteInputs(_, cnt) => Response::ReadDiscreteInputs(vec![false; (*cnt).into()]),
+        ReadInputRegisters(_, cnt) => Response::ReadInputRegisters(vec![0; (*cnt).into()]),
+        ReadHoldingRegisters(_, cnt) => Response::ReadHoldingRegisters(vec![0; (*cnt).into()]),
+        ReadWriteMultipleRegisters(_, cnt, _, _) => {
+            Response::ReadWriteMultipleRegisters(vec![0; (*cnt).into()])
+        }
+        ReadFifoQueue(_) => Response::ReadFifoQueue(vec![]),
+        ReadExceptionStatus => Response::ReadExceptionStatus(0),
+        WriteSingleCoil(addr, coil) => Response::WriteSingleCoil(*addr, *coil),
+        WriteMultipleCoils(addr, coils) => {
+            Response::WriteMultipleCoils(*addr, quantity(coils.len()))
+        }
+        WriteSingleRegister(addr, word) => Response::WriteSingleRegister(*addr, *word),
+        WriteMultipleRegisters(addr, words) => {
+            Response::WriteMultipleRegisters(*addr, quantity(words.len()))
+        }
+        MaskWriteRegister(addr, and_mask, or_mask) => {
+            Response::MaskWriteRegister(*addr, *and_mask, *or_mask)
+        }
+        Diagnostics(sub_function, data) if sub_function.echoes_data() => {
+            Response::Diagnostics(*sub_function, data.to_vec())
+        }
+        Diagnostics(sub_function, _) => Response::Diagnostics(*sub_function, vec![0]),
+        ReportServerId | ReadDeviceIdentification(_, _) | Custom(_, _) => {
+            return Err(ExceptionCode::IllegalFunction);
+        }
+    };
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{Reader as _, Writer as _};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn record_requests_with_default_responses() {
+        let client = DryRunClient::new();
+        let mut context = client.attach_slave(Slave(1));
+        assert_eq!(
+            context.read_holding_registers(0, 3).await.unwrap(),
+            Ok(vec![0; 3])
+        );
+        context
+            .write_multiple_coils(10, &[true, false])
+            .await
+            .unwrap()
+            .unwrap();
+        context.set_slave(Slave(2));
+        context
+            .write_single_register(20, 42)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            context.call(Request::ReportServerId).await.unwrap(),
+            Err(ExceptionCode::IllegalFunction)
+        );
+        assert_eq!(
+            client.take_requests(),
+            [
+                (Slave(1), Request::ReadHoldingRegisters(0, 3)),
+                (
+                    Slave(1),
+                    Request::WriteMultipleCoils(10, vec![true, false].into())
+                ),
+                (Slave(2), Request::WriteSingleRegister(20, 42)),
+                (Slave(2), Request::ReportServerId),
+            ]
+        );
+        assert!(client.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn answer_with_canned_responses_before_rules() {
+        let client = DryRunClient::new();
+        client.add_rule(|slave, request| match request {
+            Request::ReadHoldingRegisters(100, 1) if slave == Slave(1) => {
+                Some(Ok(Response::ReadHoldingRegisters(vec![7])))
+            }
+            _ => None,
+        });
+        client.push_response(Err(ExceptionCode::ServerDeviceBusy));
+        let mut context = client.attach_slave(Slave(1));
+
+        assert_eq!(
+            context.read_holding_registers(100, 1).await.unwrap(),
+            Err(ExceptionCode::ServerDeviceBusy)
+        );
+        assert_eq!(
+            context.read_holding_registers(100, 1).await.unwrap(),
+            Ok(vec![7])
+        );
+        assert_eq!(
+            context.read_holding_registers(101, 1).await.unwrap(),
+            Ok(vec![0])
+        );
+        assert_eq!(client.requests().len(), 3);
+    }
+}
@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Periodic writes of a heartbeat register
+
+use std::time::Duration;
+
+use crate::{frame::Address, Result};
+
+use super::Writer;
+
+/// Writes an incrementing value into a heartbeat register.
+///
+/// Many devices monitor a dedicated holding register and switch into
+/// a safe state if its value stops changing, i.e. if the master is
+/// considered as disconnected. The server side counterpart is
+/// `server::HeartbeatMonitor`.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    addr: Address,
+    interval: Duration,
+}
+
+impl Heartbeat {
+    /// Write the heartbeat register at `addr` every `interval`.
+    #[must_use]
+    pub const fn new(addr: Address, interval: Duration) -> Self {
+        Self { addr, interval }
+    }
+
+    /// The address of the heartbeat register.
+    #[must_use]
+    pub const fn addr(&self) -> Address {
+        self.addr
+    }
+
+    /// The interval between writes.
+    #[must_use]
+    pub const fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Write the heartbeat register (0x06) periodically until a write fails.
+    ///
+    /// The value starts at 0 and wraps around after reaching its maximum.
+    /// Returns the error or exception of the failed write. Usually spawned
+    /// as a separate task with its own client context.
+    pub async fn run<W>(&self, writer: &mut W) -> Result<()>
+    where
+        W: Writer + ?Sized,
+    {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut value: u16 = 0;
+        loop {
+            interval.tick().await;
+            if let Err(exception) = writer.write_single_register(self.addr, value).await? {
+                log::warn!(
+                    "Failed to write heartbeat register {addr}: {exception}",
+                    addr = self.addr
+                );
+                return Ok(Err(exception));
+            }
+            value = value.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+
+    use crate::{
+        client::{Client, Context, SlaveContext},
+        ExceptionCode, Request, Response, Slave,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct HeartbeatMock {
+        values: Arc<Mutex<Vec<u16>>>,
+    }
+
+    #[async_trait]
+    impl Client for HeartbeatMock {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            let Request::WriteSingleRegister(addr, value) = request else {
+                return Ok(Err(ExceptionCode::IllegalFunction));
+            };
+            let mut values = self.values.lock().unwrap();
+            if values.len() == 3 {
+                return Ok(Err(ExceptionCode::ServerDeviceBusy));
+            }
+            values.push(value);
+            Ok(Ok(Response::WriteSingleRegister(addr, value)))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for HeartbeatMock {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn write_incrementing_values_until_failure() {
+        let client = HeartbeatMock::default();
+        let values = Arc::clone(&client.values);
+        let client: Box<dyn Client> = Box::new(client);
+        let mut context = Context::from(client);
+        let heartbeat = Heartbeat::new(100, Duration::from_millis(1));
+        let result = heartbeat.run(&mut context).await.unwrap();
+        assert_eq!(result, Err(ExceptionCode::ServerDeviceBusy));
+        assert_eq!(*values.lock().unwrap(), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,53 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Labels for identifying client connections
+
+use std::{error, fmt, io};
+
+/// Prefixes log messages with an optional label.
+pub(super) struct LogPrefix<'a>(pub(super) Option<&'a str>);
+
+impl fmt::Display for LogPrefix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(label) = self.0 {
+            write!(f, "{label}: ")?;
+        }
+        Ok(())
+    }
+}
+
+/// A transport error of a labeled connection.
+#[derive(Debug)]
+struct LabeledError {
+    label: String,
+    source: io::Error,
+}
+
+impl fmt::Display for LabeledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { label, source } = self;
+        write!(f, "{label}: {source}")
+    }
+}
+
+impl error::Error for LabeledError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Add the label to the message of a transport error.
+///
+/// The kind of the error is preserved and the original
+/// error is available as the source.
+pub(super) fn labeled_error(label: &str, source: io::Error) -> io::Error {
+    let kind = source.kind();
+    io::Error::new(
+        kind,
+        LabeledError {
+            label: label.to_owned(),
+            source,
+        },
+    )
+}
@@ -0,0 +1,245 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Limit the number of outstanding requests across client contexts
+
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use super::Context;
+
+/// A limit for the number of outstanding requests that is shared
+/// by multiple client contexts.
+///
+/// Useful for applications that talk to many Modbus buses or devices
+/// simultaneously and need to bound the total number of concurrent
+/// transactions, e.g. process-wide.
+///
+/// Requests that exceed the limit wait until a previous request has
+/// finished. Waiting requests are served in FIFO order, i.e. no
+/// context is starved by others.
+///
+/// Only requests on the wire count as outstanding, i.e. a context that
+/// waits before repeating a failed request or reconnects doesn't block
+/// the others.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimit {
+    /// Create a new limit that allows up to `max_outstanding_requests`
+    /// concurrent requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_outstanding_requests` is 0 or exceeds
+    /// [`Semaphore::MAX_PERMITS`].
+    #[must_use]
+    pub fn new(max_outstanding_requests: usize) -> Self {
+        assert!(max_outstanding_requests > 0);
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_outstanding_requests)),
+        }
+    }
+
+    /// The number of requests that could currently be sent without waiting.
+    #[must_use]
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Subject all requests of a client context to this limit.
+    ///
+    /// Limits are composed, i.e. a context that is subject to multiple
+    /// limits waits until all of them allow another request. Applying
+    /// the same limit again has no effect.
+    #[must_use]
+    pub fn limit(&self, mut context: Context) -> Context {
+        let limits = &mut context.options.concurrency_limits;
+        if !limits
+            .iter()
+            .any(|limit| Arc::ptr_eq(&limit.semaphore, &self.semaphore))
+        {
+            limits.push(self.clone());
+            // Contexts that share multiple limits must acquire them
+            // in the same order to avoid deadlocks.
+            limits.sort_by_key(|limit| Arc::as_ptr(&limit.semaphore));
+        }
+        context
+    }
+
+    /// Acquire a permit from each limit, in order.
+    pub(super) async fn acquire_all(limits: &[Self]) -> Vec<OwnedSemaphorePermit> {
+        let mut permits = Vec::with_capacity(limits.len());
+        for limit in limits {
+            permits.push(limit.acquire().await);
+        }
+        permits
+    }
+
+    async fn acquire(&self) -> OwnedSemaphorePermit {
+        Arc::clone(&self.semaphore)
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+
+    use crate::{
+        client::{Backoff, Client, RetryPolicy, SlaveContext},
+        Error, Request, Response, Result, Slave,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Outstanding {
+        current: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    #[derive(Debug)]
+    struct SlowClient(Arc<Outstanding>);
+
+    #[async_trait]
+    impl Client for SlowClient {
+        async fn call(&mut self, _: Request<'_>) -> Result<Response> {
+            let current = self.0.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.0.max.fetch_max(current, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.0.current.fetch_sub(1, Ordering::SeqCst);
+            Ok(Ok(Response::ReadHoldingRegisters(vec![0])))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for SlowClient {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    /// Fails the first request with a transient error.
+    #[derive(Debug, Default)]
+    struct FlakyClient {
+        calls: usize,
+    }
+
+    #[async_trait]
+    impl Client for FlakyClient {
+        async fn call(&mut self, _: Request<'_>) -> Result<Response> {
+            self.calls += 1;
+            if self.calls == 1 {
+                return Err(Error::Transport(io::ErrorKind::TimedOut.into()));
+            }
+            Ok(Ok(Response::ReadHoldingRegisters(vec![0])))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for FlakyClient {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn limit_outstanding_requests_across_contexts() {
+        let outstanding = Arc::new(Outstanding::default());
+        let limit = ConcurrencyLimit::new(2);
+        let tasks: Vec<_> = (0..5)
+            .map(|_| {
+                let client: Box<dyn Client> = Box::new(SlowClient(Arc::clone(&outstanding)));
+                let mut context = limit.limit(client.into());
+                tokio::spawn(async move {
+                    context
+                        .call(Request::ReadHoldingRegisters(0, 1))
+                        .await
+                        .unwrap()
+                        .unwrap();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(outstanding.max.load(Ordering::SeqCst), 2);
+        assert_eq!(limit.available(), 2);
+    }
+
+    #[tokio::test]
+    async fn compose_limits() {
+        let outstanding = Arc::new(Outstanding::default());
+        let process_limit = ConcurrencyLimit::new(3);
+        let bus_limit = ConcurrencyLimit::new(1);
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let client: Box<dyn Client> = Box::new(SlowClient(Arc::clone(&outstanding)));
+                let context = bus_limit.limit(process_limit.limit(client.into()));
+                // No effect, otherwise the context would wait forever.
+                let mut context = bus_limit.limit(context);
+                tokio::spawn(async move {
+                    context
+                        .call(Request::ReadHoldingRegisters(0, 1))
+                        .await
+                        .unwrap()
+                        .unwrap();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(outstanding.max.load(Ordering::SeqCst), 1);
+        assert_eq!(process_limit.available(), 3);
+        assert_eq!(bus_limit.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn release_permits_while_waiting_for_retry() {
+        let limit = ConcurrencyLimit::new(1);
+        let client: Box<dyn Client> = Box::<FlakyClient>::default();
+        let mut retrying = limit.limit(client.into());
+        retrying.set_retry_policy(Some(RetryPolicy::new(Backoff::new(
+            Duration::from_millis(300),
+            Duration::from_millis(300),
+            2,
+        ))));
+        let retrying = tokio::spawn(async move {
+            retrying
+                .call(Request::ReadHoldingRegisters(0, 1))
+                .await
+                .unwrap()
+                .unwrap();
+        });
+        // Wait until the first attempt has failed.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let client: Box<dyn Client> = Box::new(SlowClient(Arc::default()));
+        let mut other = limit.limit(client.into());
+        tokio::time::timeout(
+            Duration::from_millis(200),
+            other.call(Request::ReadHoldingRegisters(0, 1)),
+        )
+        .await
+        .expect("not blocked by the retry backoff")
+        .unwrap()
+        .unwrap();
+        retrying.await.unwrap();
+        assert_eq!(limit.available(), 1);
+    }
+}
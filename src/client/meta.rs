@@ -0,0 +1,26 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Timing information of requests
+
+use std::time::{Duration, SystemTime};
+
+/// Timing information about a request and its response.
+///
+/// Returned by [`Context::call_with_meta()`](super::Context::call_with_meta).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// Round-trip time from sending the request until receiving the response.
+    ///
+    /// Measured with a monotonic clock.
+    pub rtt: Duration,
+
+    /// Wall-clock time when the request has been sent.
+    pub tx_time: SystemTime,
+
+    /// Wall-clock time when the response has been received.
+    pub rx_time: SystemTime,
+
+    /// Number of times the request has been repeated.
+    pub retries: usize,
+}
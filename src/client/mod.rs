@@ -3,11 +3,27 @@
 
 //! Modbus clients
 
-use std::{borrow::Cow, fmt::Debug, io};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    io,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
 use async_trait::async_trait;
 
-use crate::{frame::*, slave::*, Result};
+use crate::{
+    data::Registers,
+    frame::*,
+    metrics::{self, Metrics},
+    slave::*,
+    ConnectionStats, Error, ProtocolError, Result,
+};
+
+#[cfg(feature = "rtu")]
+pub mod ascii;
 
 #[cfg(feature = "rtu")]
 pub mod rtu;
@@ -15,15 +31,100 @@ pub mod rtu;
 #[cfg(feature = "tcp")]
 pub mod tcp;
 
+#[cfg(feature = "tcp")]
+pub mod udp;
+
+#[cfg(feature = "tls")]
+pub mod tls;
+
+#[cfg(feature = "ws")]
+pub mod ws;
+
 #[cfg(feature = "sync")]
 pub mod sync;
 
+pub mod typed;
+
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+pub mod poll;
+
+pub mod scan;
+
+pub mod tags;
+
+mod armed;
+pub use self::armed::ArmedWrite;
+
+mod backpressure;
+pub use self::backpressure::Backpressure;
+
+mod batch;
+pub use self::batch::{BatchFailure, BatchOutcome, ReadPlan};
+
+mod call_log;
+pub use self::call_log::CallLog;
+
+mod diagnostics;
+pub use self::diagnostics::{BusCounters, DiagnosticsCounter};
+
+mod dry_run;
+pub use self::dry_run::DryRunClient;
+
+mod heartbeat;
+pub use self::heartbeat::Heartbeat;
+
+mod label;
+use self::label::{labeled_error, LogPrefix};
+
+mod limit;
+pub use self::limit::ConcurrencyLimit;
+
+mod meta;
+pub use self::meta::ResponseMeta;
+
+mod recovery;
+use self::recovery::Reconnect;
+pub use self::recovery::{Backoff, ErrorRecovery};
+
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+mod transport;
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+pub use self::transport::{
+    attach_transport, attach_transport_slave, RequestAdu, ResponseAdu, Transport,
+};
+
+mod verify;
+use self::verify::is_requested_bit_count;
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+pub(crate) use self::verify::verify_decoded_response;
+use self::verify::verify_decoded_response_data;
+pub use self::verify::{verify_response, verify_response_data, VerifiableHeader};
+
+mod retry;
+pub use self::retry::RetryPolicy;
+
+mod watchdog;
+pub use self::watchdog::{Watchdog, WatchdogEvent};
+
 /// Transport independent asynchronous client trait
 #[async_trait]
 pub trait Client: SlaveContext + Send + Debug {
     /// Invokes a _Modbus_ function.
     async fn call(&mut self, request: Request<'_>) -> Result<Response>;
 
+    /// Invokes a _Modbus_ function like [`Self::call()`], but the built-in
+    /// clients return the registers that have been read as a view into
+    /// the received frame.
+    ///
+    /// Not part of the public API, the result could only be used within
+    /// this crate, see [`Context::read_holding_registers_view()`].
+    #[doc(hidden)]
+    async fn call_with_register_views(&mut self, request: Request<'_>) -> Result<DecodedResponse> {
+        self.call(request)
+            .await
+            .map(|result| result.map(Into::into))
+    }
+
     /// Disconnects the client.
     ///
     /// Permanently disconnects the client by shutting down the
@@ -34,6 +135,48 @@ pub trait Client: SlaveContext + Send + Debug {
     /// actual behavior might depend on the underlying transport
     /// protocol (RTU/TCP) that is used by the client.
     async fn disconnect(&mut self) -> io::Result<()>;
+
+    /// Resynchronizes the communication, e.g. after a protocol error or
+    /// a timeout, by discarding any stale data that is still in transit.
+    ///
+    /// Returns `false` if the client is not able to resynchronize
+    /// without reconnecting.
+    async fn resynchronize(&mut self) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// The number of stale responses that have been discarded.
+    ///
+    /// Responses to previous requests might arrive late, e.g. after the
+    /// caller stopped waiting due to a timeout. These responses are
+    /// discarded instead of mistaking them for the response to a
+    /// subsequent request.
+    ///
+    /// Only counted by clients that are able to detect stale responses.
+    fn stale_responses(&self) -> u64 {
+        0
+    }
+
+    /// The traffic of the current connection.
+    ///
+    /// Only accounted by clients that are connected through a transport.
+    /// The accounting of a connection is also logged when disconnecting.
+    fn connection_stats(&self) -> Option<ConnectionStats> {
+        None
+    }
+
+    /// The number of frames of the current connection that have been
+    /// discarded due to an invalid checksum.
+    ///
+    /// Only counted by RTU clients.
+    fn checksum_errors(&self) -> u64 {
+        0
+    }
+
+    /// The label that identifies the connection, e.g. in log messages.
+    fn label(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// Asynchronous _Modbus_ reader
@@ -62,6 +205,54 @@ pub trait Reader: Client {
         write_addr: Address,
         write_data: &[Word],
     ) -> Result<Vec<Word>>;
+
+    /// Read the contents of a FIFO queue of holding registers (0x18)
+    ///
+    /// Returns the queued values, starting with the oldest value.
+    async fn read_fifo_queue(&mut self, addr: Address) -> Result<Vec<Word>> {
+        fifo_queue(self.call(Request::ReadFifoQueue(addr)).await?)
+    }
+
+    /// Read the eight exception status outputs (0x07, Serial Line only)
+    ///
+    /// Returns the outputs as a single byte, one bit per output.
+    async fn read_exception_status(&mut self) -> Result<u8> {
+        exception_status(self.call(Request::ReadExceptionStatus).await?)
+    }
+
+    /// Read the identification of the device (0x2B / 0x0E)
+    ///
+    /// Reads all objects up to the category denoted by the stream access
+    /// code. Objects that don't fit into a single response are read by
+    /// subsequent requests until the device indicates that no more objects
+    /// follow.
+    async fn read_device_identification(
+        &mut self,
+        read_device_id_code: ReadDeviceIdCode,
+    ) -> Result<BTreeMap<DeviceIdObjectId, Vec<u8>>> {
+        let mut objects = BTreeMap::new();
+        let mut object_id = DeviceIdObjectId::VendorName;
+        loop {
+            let request = Request::ReadDeviceIdentification(read_device_id_code, object_id);
+            let response = self.call(request).await?;
+            match collect_device_identification(&mut objects, object_id, response)? {
+                Ok(Some(next_object_id)) => object_id = next_object_id,
+                Ok(None) => return Ok(Ok(objects)),
+                Err(exception) => return Ok(Err(exception)),
+            }
+        }
+    }
+
+    /// Read a single object of the device identification (0x2B / 0x0E)
+    ///
+    /// Uses individual access, i.e. [`ReadDeviceIdCode::Specific`].
+    async fn read_device_identification_object(
+        &mut self,
+        object_id: DeviceIdObjectId,
+    ) -> Result<Vec<u8>> {
+        let request = Request::ReadDeviceIdentification(ReadDeviceIdCode::Specific, object_id);
+        device_identification_object(object_id, self.call(request).await?)
+    }
 }
 
 /// Asynchronous Modbus writer
@@ -86,308 +277,1484 @@ pub trait Writer: Client {
         and_mask: Word,
         or_mask: Word,
     ) -> Result<()>;
+
+    /// Toggle a single coil (0x01 + 0x05)
+    ///
+    /// Reads the current state of the coil and writes the inverted state.
+    /// Returns the new state. The coil could still be modified by other
+    /// clients between both requests, i.e. the operation is not atomic.
+    async fn toggle_coil(&mut self, addr: Address) -> Result<Coil> {
+        let coil = match self.call(Request::ReadCoils(addr, 1)).await? {
+            Ok(Response::ReadCoils(coils)) => match coils.first() {
+                Some(&coil) => coil,
+                None => {
+                    return Err(mismatching_response(
+                        "expected 1 coil".to_owned(),
+                        Response::ReadCoils(coils),
+                    ));
+                }
+            },
+            Ok(response) => return Err(unexpected_response(FunctionCode::ReadCoils, response)),
+            Err(exception) => return Ok(Err(exception)),
+        };
+        let coil = !coil;
+        self.write_single_coil(addr, coil)
+            .await
+            .map(|result| result.map(|()| coil))
+    }
+
+    /// Switch a single coil on and off again after `duration` (0x05)
+    ///
+    /// Intended for momentary-command coils. If switching the coil on fails
+    /// unexpectedly, i.e. not with an exception, then the coil might have
+    /// been switched on nevertheless. In this case switching the coil off
+    /// is attempted anyway before returning the error.
+    ///
+    /// Failing to switch the coil off is logged as an error, because the
+    /// coil remains switched on.
+    async fn pulse_coil(&mut self, addr: Address, duration: Duration) -> Result<()> {
+        let label = self.label().map(ToOwned::to_owned);
+        let prefix = LogPrefix(label.as_deref());
+        match self.write_single_coil(addr, true).await {
+            Ok(Ok(())) => (),
+            Ok(Err(exception)) => return Ok(Err(exception)),
+            Err(err) => {
+                log::warn!("{prefix}Failed to switch coil {addr} on: {err}");
+                if let Err(err) = self.write_single_coil(addr, false).await {
+                    log::error!("{prefix}Failed to switch coil {addr} off: {err}");
+                }
+                return Err(err);
+            }
+        }
+        tokio::time::sleep(duration).await;
+        let result = self.write_single_coil(addr, false).await;
+        match &result {
+            Ok(Ok(())) => (),
+            Ok(Err(exception)) => {
+                log::error!("{prefix}Failed to switch coil {addr} off: {exception}");
+            }
+            Err(err) => {
+                log::error!("{prefix}Failed to switch coil {addr} off: {err}");
+            }
+        }
+        result
+    }
 }
 
 /// Asynchronous Modbus client context
 #[derive(Debug)]
 pub struct Context {
     client: Box<dyn Client>,
+    slave: Option<Slave>,
+    error_recovery: ErrorRecovery,
+    reconnect: Option<Box<dyn Reconnect>>,
+    emulate_masked_write: BTreeSet<Option<Slave>>,
+    label: Option<String>,
+    /// The earliest time for sending the next request after a broadcast.
+    turnaround_until: Option<Instant>,
+    options: CallOptions,
 }
 
-impl From<Box<dyn Client>> for Context {
-    fn from(client: Box<dyn Client>) -> Self {
-        Self { client }
-    }
+/// The options that apply to each call of a [`Context`].
+///
+/// A call is limited by the concurrency limits, supervised by the
+/// watchdog and timed out after each attempt. Failed attempts are
+/// repeated after reconnecting or according to the retry policy,
+/// unless the watchdog tore down the connection.
+#[derive(Debug, Default)]
+struct CallOptions {
+    auto_reconnect: Option<Backoff>,
+    retry_policy: Option<RetryPolicy>,
+    timeout: Option<Duration>,
+    watchdog: Option<Watchdog>,
+    backpressure: Option<Backpressure>,
+    concurrency_limits: Vec<ConcurrencyLimit>,
+    turnaround_delay: Option<Duration>,
+    metrics: Option<Arc<dyn Metrics>>,
+    call_log: Option<CallLog>,
+    strict_validation: bool,
 }
 
-impl From<Context> for Box<dyn Client> {
-    fn from(val: Context) -> Self {
-        val.client
-    }
-}
+/// The turnaround delay after broadcast requests on serial lines.
+///
+/// The specification recommends a delay between 100 ms and 200 ms.
+#[cfg(feature = "rtu")]
+const DEFAULT_TURNAROUND_DELAY: Duration = Duration::from_millis(100);
 
-#[async_trait]
-impl Client for Context {
-    async fn call(&mut self, request: Request<'_>) -> Result<Response> {
-        self.client.call(request).await
+impl Context {
+    fn new(client: Box<dyn Client>) -> Self {
+        Self {
+            client,
+            slave: None,
+            error_recovery: ErrorRecovery::None,
+            reconnect: None,
+            emulate_masked_write: BTreeSet::new(),
+            label: None,
+            turnaround_until: None,
+            options: CallOptions::default(),
+        }
     }
 
-    async fn disconnect(&mut self) -> io::Result<()> {
-        self.client.disconnect().await
+    /// A context for a client that initially addresses `slave`.
+    fn with_slave(client: Box<dyn Client>, slave: Slave) -> Self {
+        let mut context = Self::new(client);
+        context.slave = Some(slave);
+        context
     }
-}
 
-impl SlaveContext for Context {
-    fn set_slave(&mut self, slave: Slave) {
-        self.client.set_slave(slave);
+    /// A context for a slave device on a serial line.
+    #[cfg(feature = "rtu")]
+    fn new_serial(client: Box<dyn Client>, slave: Slave) -> Self {
+        let mut context = Self::with_slave(client, slave);
+        context.options.turnaround_delay = Some(DEFAULT_TURNAROUND_DELAY);
+        context
     }
-}
 
-#[async_trait]
-impl Reader for Context {
-    async fn read_coils<'a>(&'a mut self, addr: Address, cnt: Quantity) -> Result<Vec<Coil>> {
-        self.client
-            .call(Request::ReadCoils(addr, cnt))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::ReadCoils(mut coils) => {
-                        debug_assert!(coils.len() >= cnt.into());
-                        coils.truncate(cnt.into());
-                        coils
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Returns the current error recovery mode.
+    #[must_use]
+    pub const fn error_recovery(&self) -> ErrorRecovery {
+        self.error_recovery
     }
 
-    async fn read_discrete_inputs<'a>(
-        &'a mut self,
-        addr: Address,
-        cnt: Quantity,
-    ) -> Result<Vec<Coil>> {
-        self.client
-            .call(Request::ReadDiscreteInputs(addr, cnt))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::ReadDiscreteInputs(mut coils) => {
-                        debug_assert!(coils.len() >= cnt.into());
-                        coils.truncate(cnt.into());
-                        coils
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Sets the error recovery mode for all subsequent operations.
+    ///
+    /// Recovery requires a context that is able to reconnect, e.g.
+    /// a TCP context that has been established by [`tcp::Builder`].
+    /// RTU and ASCII contexts recover from protocol errors and timeouts
+    /// by [resynchronizing](Client::resynchronize()) instead. Other
+    /// contexts are not affected.
+    pub fn set_error_recovery(&mut self, error_recovery: ErrorRecovery) {
+        self.error_recovery = error_recovery;
     }
 
-    async fn read_input_registers<'a>(
-        &'a mut self,
-        addr: Address,
-        cnt: Quantity,
-    ) -> Result<Vec<Word>> {
-        self.client
-            .call(Request::ReadInputRegisters(addr, cnt))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::ReadInputRegisters(words) => {
-                        debug_assert_eq!(words.len(), cnt.into());
-                        words
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Enables or disables transparent reconnecting for all subsequent operations.
+    ///
+    /// If the connection has been lost, e.g. closed or reset by the peer,
+    /// the context tries to re-establish it with the given `backoff`
+    /// and then repeats the failed request once. The error is only
+    /// returned if reconnecting or the repeated request failed.
+    ///
+    /// Requires a context that is able to reconnect, e.g. a TCP context
+    /// that has been established by [`tcp::Builder`]. Other contexts
+    /// are not affected.
+    ///
+    /// Only enable this option if repeating requests is safe, i.e.
+    /// if the failed request might have already been executed by the
+    /// device before the connection has been lost.
+    pub fn set_auto_reconnect(&mut self, backoff: Option<Backoff>) {
+        self.options.auto_reconnect = backoff;
     }
 
-    async fn read_holding_registers<'a>(
-        &'a mut self,
-        addr: Address,
-        cnt: Quantity,
-    ) -> Result<Vec<Word>> {
-        self.client
-            .call(Request::ReadHoldingRegisters(addr, cnt))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::ReadHoldingRegisters(words) => {
-                        debug_assert_eq!(words.len(), cnt.into());
-                        words
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Returns the current timeout of requests.
+    #[must_use]
+    pub const fn timeout(&self) -> Option<Duration> {
+        self.options.timeout
     }
 
-    async fn read_write_multiple_registers<'a>(
-        &'a mut self,
-        read_addr: Address,
-        read_count: Quantity,
-        write_addr: Address,
-        write_data: &[Word],
-    ) -> Result<Vec<Word>> {
-        self.client
-            .call(Request::ReadWriteMultipleRegisters(
-                read_addr,
-                read_count,
-                write_addr,
-                Cow::Borrowed(write_data),
-            ))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::ReadWriteMultipleRegisters(words) => {
-                        debug_assert_eq!(words.len(), read_count.into());
-                        words
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Sets a timeout for all subsequent requests.
+    ///
+    /// Requests that are not answered in time fail with [`Error::Timeout`].
+    /// Late responses are discarded by the built-in clients.
+    ///
+    /// The timeout is disabled by passing `None` (default).
+    pub fn set_timeout(&mut self, duration: impl Into<Option<Duration>>) {
+        self.options.timeout = duration.into();
     }
-}
 
-#[async_trait]
-impl Writer for Context {
-    async fn write_single_coil<'a>(&'a mut self, addr: Address, coil: Coil) -> Result<()> {
-        self.client
-            .call(Request::WriteSingleCoil(addr, coil))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::WriteSingleCoil(rsp_addr, rsp_coil) => {
-                        debug_assert_eq!(addr, rsp_addr);
-                        debug_assert_eq!(coil, rsp_coil);
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Sets the policy for repeating failed requests.
+    ///
+    /// Retries are disabled by passing `None` (default). The number of
+    /// retries is reported by [`Self::call_with_meta()`].
+    pub fn set_retry_policy(&mut self, retry_policy: Option<RetryPolicy>) {
+        self.options.retry_policy = retry_policy;
     }
 
-    async fn write_multiple_coils<'a>(&'a mut self, addr: Address, coils: &[Coil]) -> Result<()> {
-        let cnt = coils.len();
-        self.client
-            .call(Request::WriteMultipleCoils(addr, Cow::Borrowed(coils)))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::WriteMultipleCoils(rsp_addr, rsp_cnt) => {
-                        debug_assert_eq!(addr, rsp_addr);
-                        debug_assert_eq!(cnt, rsp_cnt.into());
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Sets the delay after broadcast requests before sending the next
+    /// request.
+    ///
+    /// Broadcast requests are not answered. All devices need some time
+    /// for processing the request before they are able to receive the
+    /// next request. Defaults to 100 ms for RTU and ASCII contexts.
+    ///
+    /// The delay is disabled by passing `None`.
+    pub fn set_turnaround_delay(&mut self, delay: impl Into<Option<Duration>>) {
+        self.options.turnaround_delay = delay.into();
     }
 
-    async fn write_single_register<'a>(&'a mut self, addr: Address, word: Word) -> Result<()> {
-        self.client
-            .call(Request::WriteSingleRegister(addr, word))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::WriteSingleRegister(rsp_addr, rsp_word) => {
-                        debug_assert_eq!(addr, rsp_addr);
-                        debug_assert_eq!(word, rsp_word);
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Sets a label that identifies the connection, e.g. `"boiler-plc-1"`.
+    ///
+    /// The label is included in log messages and in the messages of
+    /// transport errors to correlate failures with devices.
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = Some(label.into());
     }
 
-    async fn write_multiple_registers<'a>(
-        &'a mut self,
-        addr: Address,
-        data: &[Word],
-    ) -> Result<()> {
-        let cnt = data.len();
-        self.client
-            .call(Request::WriteMultipleRegisters(addr, Cow::Borrowed(data)))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::WriteMultipleRegisters(rsp_addr, rsp_cnt) => {
-                        debug_assert_eq!(addr, rsp_addr);
-                        debug_assert_eq!(cnt, rsp_cnt.into());
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Reports the activity of all subsequent requests to `metrics`.
+    ///
+    /// Metrics are disabled by passing `None` (default).
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn Metrics>>) {
+        self.options.metrics = metrics;
     }
 
-    async fn masked_write_register<'a>(
-        &'a mut self,
-        addr: Address,
-        and_mask: Word,
-        or_mask: Word,
-    ) -> Result<()> {
-        self.client
-            .call(Request::MaskWriteRegister(addr, and_mask, or_mask))
-            .await
-            .map(|result| {
-                result.map_err(Into::into).map(|response| match response {
-                    Response::MaskWriteRegister(rsp_addr, rsp_and_mask, rsp_or_mask) => {
-                        debug_assert_eq!(addr, rsp_addr);
-                        debug_assert_eq!(and_mask, rsp_and_mask);
-                        debug_assert_eq!(or_mask, rsp_or_mask);
-                    }
-                    _ => unreachable!("call() should reject mismatching responses"),
-                })
-            })
+    /// Writes all subsequent calls and their results to `call_log`.
+    ///
+    /// The call log is disabled by passing `None` (default).
+    pub fn set_call_log(&mut self, call_log: Option<CallLog>) {
+        self.options.call_log = call_log;
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::{Error, Result};
 
-    use super::*;
-    use std::{io, sync::Mutex};
+    /// Enables or disables the strict validation of responses for all
+    /// subsequent operations.
+    ///
+    /// Disabled by default. If enabled, responses to all calls including
+    /// [`Client::call()`] are checked by [`verify_response_data()`], e.g.
+    /// the echo of write requests and the number of values that have been
+    /// read. Mismatching responses fail with
+    /// [`ProtocolError::ResponseMismatch`] instead of being returned as is.
+    pub fn set_strict_validation(&mut self, strict_validation: bool) {
+        self.options.strict_validation = strict_validation;
+    }
 
-    #[derive(Default, Debug)]
-    pub(crate) struct ClientMock {
-        slave: Option<Slave>,
-        last_request: Mutex<Option<Request<'static>>>,
-        next_response: Option<Result<Response>>,
+    /// Invokes a _Modbus_ function and measures the timing.
+    ///
+    /// Same as [`Client::call()`], but returns timing information
+    /// about the request and response along with the result, e.g.
+    /// for determining the age of measurements.
+    pub async fn call_with_meta(
+        &mut self,
+        request: Request<'_>,
+    ) -> (Result<Response>, ResponseMeta) {
+        let (result, meta) = self.call_decoded(request, false).await;
+        let result = result.map(|result| result.map(DecodedResponse::into_response));
+        (result, meta)
     }
 
-    #[allow(dead_code)]
-    impl ClientMock {
-        pub(crate) fn slave(&self) -> Option<Slave> {
-            self.slave
+    /// Invokes a _Modbus_ function and measures the timing, optionally
+    /// with views of the registers that have been read.
+    async fn call_decoded(
+        &mut self,
+        request: Request<'_>,
+        register_views: bool,
+    ) -> (Result<DecodedResponse>, ResponseMeta) {
+        #[cfg(feature = "tracing")]
+        let span = crate::spans::client_call(
+            request.function_code(),
+            self.slave.map(|slave| slave.0),
+            self.label.as_deref(),
+        );
+        let logged = self
+            .options
+            .call_log
+            .as_ref()
+            .filter(|call_log| call_log.is_enabled())
+            .map(|_| request.clone().into_owned());
+        let validated = self.options.strict_validation.then(|| request.clone());
+        let call = self.call_with_retries(request, register_views);
+        #[cfg(feature = "tracing")]
+        let call = tracing::Instrument::instrument(call, span.clone());
+        let (result, meta) = call.await;
+        let result = match (validated, result) {
+            (Some(request), Ok(Ok(response))) => {
+                match verify_decoded_response_data(&request, &response) {
+                    Ok(()) => Ok(Ok(response)),
+                    Err(message) => Err(ProtocolError::ResponseMismatch {
+                        message,
+                        result: Ok(response.into_response()),
+                    }
+                    .into()),
+                }
+            }
+            (_, result) => result,
+        };
+        if let (Some(call_log), Some(request)) = (&self.options.call_log, logged) {
+            call_log.record(meta.tx_time, self.slave, &request, &result);
         }
+        #[cfg(feature = "tracing")]
+        crate::spans::record_call_outcome(&span, &result, meta.rtt, meta.retries);
+        (result, meta)
+    }
 
-        pub(crate) fn last_request(&self) -> &Mutex<Option<Request<'static>>> {
-            &self.last_request
+    /// Invokes a _Modbus_ function, repeating it according to the
+    /// retry policy and after reconnecting.
+    async fn call_with_retries(
+        &mut self,
+        request: Request<'_>,
+        register_views: bool,
+    ) -> (Result<DecodedResponse>, ResponseMeta) {
+        if let Some(turnaround_until) = self.turnaround_until.take() {
+            tokio::time::sleep_until(turnaround_until.into()).await;
         }
-
-        pub(crate) fn set_next_response(&mut self, next_response: Result<Response>) {
-            self.next_response = Some(next_response);
+        let broadcast = self.slave.is_some_and(Slave::is_broadcast);
+        let auto_reconnect = self
+            .options
+            .auto_reconnect
+            .filter(|_| self.reconnect.is_some());
+        let retry_policy = self.options.retry_policy.clone();
+        let repeatable = (auto_reconnect.is_some() || retry_policy.is_some())
+            .then(|| request.clone().into_owned());
+        let tx_time = SystemTime::now();
+        let tx_instant = Instant::now();
+        let (mut result, mut stalled) = self.call_watched(request, register_views).await;
+        let mut retries = 0;
+        if let Some(request) = repeatable {
+            // The first attempt has already been made.
+            let mut retry_delays = retry_policy
+                .as_ref()
+                .map(|retry_policy| retry_policy.backoff().delays().skip(1));
+            let mut reconnected = false;
+            while stalled.is_none() {
+                let Err(err) = &result else {
+                    break;
+                };
+                if let Some(backoff) =
+                    auto_reconnect.filter(|_| !reconnected && self.is_connection_lost(err))
+                {
+                    if !self.reconnect_with_backoff(backoff).await {
+                        break;
+                    }
+                    reconnected = true;
+                } else if let Some(delay) = retry_policy
+                    .as_ref()
+                    .filter(|retry_policy| retry_policy.should_retry(err))
+                    .filter(|_| {
+                        !self
+                            .options
+                            .backpressure
+                            .as_ref()
+                            .is_some_and(Backpressure::is_engaged)
+                    })
+                    .and(retry_delays.as_mut())
+                    .and_then(Iterator::next)
+                {
+                    log::debug!(
+                        "{prefix}Repeating request after error: {err}",
+                        prefix = LogPrefix(self.label.as_deref())
+                    );
+                    tokio::time::sleep(delay).await;
+                } else {
+                    break;
+                }
+                (result, stalled) = self.call_watched(request.clone(), register_views).await;
+                retries += 1;
+            }
+        }
+        let rtt = tx_instant.elapsed();
+        let rx_time = SystemTime::now();
+        if broadcast {
+            self.turnaround_until = self
+                .options
+                .turnaround_delay
+                .map(|delay| Instant::now() + delay);
         }
+        if let Some(watchdog) = stalled {
+            self.tear_down(&watchdog).await;
+        } else if let Err(err) = &result {
+            self.recover(err).await;
+        }
+        let result = match (result, &self.label) {
+            (Err(Error::Transport(err)), Some(label)) => {
+                Err(Error::Transport(labeled_error(label, err)))
+            }
+            (result, _) => result,
+        };
+        let meta = ResponseMeta {
+            rtt,
+            tx_time,
+            rx_time,
+            retries,
+        };
+        (result, meta)
     }
 
-    #[async_trait]
-    impl Client for ClientMock {
-        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
-            *self.last_request.lock().unwrap() = Some(request.into_owned());
-            match self.next_response.take().unwrap() {
-                Ok(response) => Ok(response),
-                Err(Error::Transport(err)) => {
-                    Err(io::Error::new(err.kind(), format!("{err}")).into())
-                }
-                Err(err) => Err(err),
+    /// Invokes a _Modbus_ function under the supervision of the watchdog
+    /// and records the outcome for the backpressure.
+    ///
+    /// The concurrency limits are only held during the attempt, i.e.
+    /// not while waiting before a retry or reconnecting.
+    ///
+    /// Returns the watchdog if the call stalled.
+    async fn call_watched(
+        &mut self,
+        request: Request<'_>,
+        register_views: bool,
+    ) -> (Result<DecodedResponse>, Option<Watchdog>) {
+        let _permits = ConcurrencyLimit::acquire_all(&self.options.concurrency_limits).await;
+        let function = request.function_code();
+        let counters = self.options.metrics.as_ref().map(|_| self.counters());
+        let timeout = self.options.timeout;
+        let client = &mut self.client;
+        let call = async move {
+            if register_views {
+                client.call_with_register_views(request).await
+            } else {
+                let result = client.call(request).await?;
+                Ok(result.map(Into::into))
             }
+        };
+        let call = async move {
+            let Some(timeout) = timeout else {
+                return call.await;
+            };
+            tokio::time::timeout(timeout, call)
+                .await
+                .unwrap_or(Err(Error::Timeout(timeout)))
+        };
+        let (result, stalled) = match self.options.watchdog.clone() {
+            Some(watchdog) => match tokio::time::timeout(watchdog.timeout(), call).await {
+                Ok(result) => (result, None),
+                Err(_) => (Err(watchdog.stalled().into()), Some(watchdog)),
+            },
+            None => (call.await, None),
+        };
+        if let Some(backpressure) = &self.options.backpressure {
+            backpressure.record(result.is_err());
         }
-
-        async fn disconnect(&mut self) -> io::Result<()> {
-            Ok(())
+        if let (Some(metrics), Some(counters)) = (&self.options.metrics, counters) {
+            metrics::report_result(&**metrics, function, &result);
+            self.counters().report_since(counters, &**metrics);
         }
+        (result, stalled)
     }
 
-    impl SlaveContext for ClientMock {
-        fn set_slave(&mut self, slave: Slave) {
-            self.slave = Some(slave);
-        }
+    fn counters(&self) -> metrics::Counters {
+        metrics::Counters::new(
+            self.client.connection_stats(),
+            self.client.checksum_errors(),
+        )
     }
 
-    #[test]
-    fn read_some_coils() {
-        // The protocol will always return entire bytes with, i.e.
-        // a multiple of 8 coils.
-        let response_coils = [true, false, false, true, false, true, false, true];
-        for num_coils in 1..8 {
-            let mut client = Box::<ClientMock>::default();
-            client.set_next_response(Ok(Ok(Response::ReadCoils(response_coils.to_vec()))));
-            let mut context = Context { client };
-            context.set_slave(Slave(1));
-            let coils = futures::executor::block_on(context.read_coils(1, num_coils))
-                .unwrap()
-                .unwrap();
-            assert_eq!(&response_coils[0..num_coils as usize], &coils[..]);
+    /// Invokes multiple _Modbus_ functions one after another.
+    ///
+    /// Failures of individual requests don't abort the batch,
+    /// i.e. all requests are sent.
+    pub async fn call_batch(&mut self, requests: &[Request<'_>]) -> BatchOutcome<Response> {
+        let mut results = Vec::with_capacity(requests.len());
+        for request in requests {
+            results.push(self.call(request.clone()).await);
         }
+        results.into()
     }
 
-    #[test]
-    fn read_some_discrete_inputs() {
+    /// Repeats only the failed requests of a batch.
+    ///
+    /// The `requests` must be the same that produced the `outcome`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of requests and items differ.
+    pub async fn retry_failed(
+        &mut self,
+        requests: &[Request<'_>],
+        outcome: &mut BatchOutcome<Response>,
+    ) {
+        assert_eq!(requests.len(), outcome.len());
+        for index in outcome.failed_indices() {
+            let result = self.call(requests[index].clone()).await;
+            drop(outcome.replace(index, result));
+        }
+    }
+
+    /// Read scattered values with as few requests as possible.
+    ///
+    /// Returns the values keyed by the targets of the plan. Fails on the
+    /// first request that fails.
+    pub async fn read_planned<T: Clone>(
+        &mut self,
+        plan: &ReadPlan<T>,
+    ) -> Result<BTreeMap<(Address, Quantity), Vec<T>>> {
+        let mut spans = Vec::with_capacity(plan.spans().len());
+        for span in plan.spans() {
+            let mut values = Vec::with_capacity(span.len());
+            for (addr, cnt) in plan.chunks(span) {
+                let request = plan.request(addr, cnt);
+                let function = request.function_code();
+                let response = match self.call(request).await? {
+                    Ok(response) => response,
+                    Err(exception) => return Ok(Err(exception)),
+                };
+                let Some(chunk) = plan.values(&response) else {
+                    return Err(unexpected_response(function, response));
+                };
+                // Bits are padded to entire bytes.
+                let Some(chunk) = chunk.get(..cnt.into()) else {
+                    let message = format!("expected {cnt} values instead of {}", chunk.len());
+                    return Err(mismatching_response(message, response));
+                };
+                values.extend_from_slice(chunk);
+            }
+            spans.push((span.clone(), values));
+        }
+        Ok(Ok(plan.pick(&spans)))
+    }
+
+    /// Read the same holding registers (0x03) from multiple slaves,
+    /// e.g. when polling an array of identical meters on a bus.
+    ///
+    /// The slaves are read one after another. Failures of individual
+    /// slaves don't abort the reads. Afterwards the context addresses
+    /// the previous slave again, i.e. the slave of the connection
+    /// unless another one has been set. Contexts of custom clients
+    /// that have been converted with [`Context::from()`] only know
+    /// their slave after it has been set.
+    ///
+    /// Use [`tcp::Pipeline::read_same_from_slaves()`] for reading
+    /// concurrently from the units behind a TCP gateway.
+    pub async fn read_same_from_slaves(
+        &mut self,
+        slaves: &[Slave],
+        addr: Address,
+        cnt: Quantity,
+    ) -> BTreeMap<Slave, Result<Vec<Word>>> {
+        let previous = self.slave;
+        let mut results = BTreeMap::new();
+        for &slave in slaves {
+            if results.contains_key(&slave) {
+                continue;
+            }
+            self.set_slave(slave);
+            let result = self.read_holding_registers(addr, cnt).await;
+            results.insert(slave, result);
+        }
+        if let Some(previous) = previous {
+            self.set_slave(previous);
+        }
+        results
+    }
+
+    /// Set or clear individual bits of a holding register, emulating
+    /// function 0x16 if the device doesn't support it.
+    ///
+    /// Devices that reject [`Writer::masked_write_register()`] with
+    /// [`ExceptionCode::IllegalFunction`] are remembered by this context.
+    /// For these devices the register is read (0x03), modified, and
+    /// written (0x06) instead. Unlike 0x16 the emulation is not atomic,
+    /// i.e. concurrent modifications of the register by other clients
+    /// could get lost.
+    pub async fn masked_write_register_emulated(
+        &mut self,
+        addr: Address,
+        and_mask: Word,
+        or_mask: Word,
+    ) -> Result<()> {
+        if !self.emulate_masked_write.contains(&self.slave) {
+            match self.masked_write_register(addr, and_mask, or_mask).await? {
+                Err(ExceptionCode::IllegalFunction) => {
+                    log::debug!(
+                        "{prefix}Emulating masked write for {slave:?}",
+                        prefix = LogPrefix(self.label()),
+                        slave = self.slave
+                    );
+                    self.emulate_masked_write.insert(self.slave);
+                }
+                result => return Ok(result),
+            }
+        }
+        let word = match self.read_holding_registers(addr, 1).await? {
+            Ok(words) => {
+                debug_assert_eq!(words.len(), 1);
+                words.first().copied().unwrap_or_default()
+            }
+            Err(exception) => return Ok(Err(exception)),
+        };
+        let word = (word & and_mask) | (or_mask & !and_mask);
+        self.write_single_register(addr, word).await
+    }
+
+    /// Execute a diagnostics sub-function (0x08, Serial Line only).
+    ///
+    /// Returns the data words of the response. The echo of the request
+    /// data is verified for sub-functions that echo their data, see
+    /// [`DiagnosticsSubFunction::echoes_data()`].
+    pub async fn diagnostics(
+        &mut self,
+        sub_function: DiagnosticsSubFunction,
+        data: &[Word],
+    ) -> Result<Vec<Word>> {
+        let request = Request::Diagnostics(sub_function, Cow::Borrowed(data));
+        match self.call(request).await? {
+            Ok(Response::Diagnostics(rsp_sub_function, words))
+                if rsp_sub_function == sub_function
+                    && (!sub_function.echoes_data() || words == data) =>
+            {
+                Ok(Ok(words))
+            }
+            Ok(response @ Response::Diagnostics(_, _)) => Err(mismatching_response(
+                format!("expected echo of diagnostics sub-function {sub_function}"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(FunctionCode::Diagnostics, response)),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    /// Loopback test with the Return Query Data sub-function of
+    /// diagnostics (0x08, Serial Line only).
+    ///
+    /// Succeeds if the device returned the data unmodified.
+    pub async fn return_query_data(&mut self, data: &[Word]) -> Result<()> {
+        let result = self
+            .diagnostics(DiagnosticsSubFunction::ReturnQueryData, data)
+            .await?;
+        Ok(result.map(drop))
+    }
+
+    /// Read a counter with a diagnostics sub-function (0x08, Serial Line only).
+    pub async fn read_diagnostics_counter(&mut self, counter: DiagnosticsCounter) -> Result<u16> {
+        let sub_function = counter.sub_function();
+        match self.diagnostics(sub_function, &[0]).await? {
+            Ok(words) => match words[..] {
+                [value] => Ok(Ok(value)),
+                _ => Err(mismatching_response(
+                    format!("expected a single counter value of diagnostics sub-function {sub_function}"),
+                    Response::Diagnostics(sub_function, words),
+                )),
+            },
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    /// Read all counters of a serial line device with the diagnostics
+    /// sub-functions 0x0B to 0x12 (0x08, Serial Line only).
+    ///
+    /// The counters are read one after another, i.e. they are not
+    /// a consistent snapshot. Stops at the first exception.
+    pub async fn read_bus_counters(&mut self) -> Result<BusCounters> {
+        let mut counters = BusCounters::default();
+        for counter in DiagnosticsCounter::ALL {
+            match self.read_diagnostics_counter(counter).await? {
+                Ok(value) => *counters.get_mut(counter) = value,
+                Err(exception) => return Ok(Err(exception)),
+            }
+        }
+        Ok(Ok(counters))
+    }
+
+    /// Read a fixed number of holding registers (0x03) into an array.
+    ///
+    /// Same as [`Reader::read_holding_registers()`] with `N` registers,
+    /// e.g. for decoding fixed-size structures without checking the
+    /// number of registers at runtime.
+    pub async fn read_holding_registers_array<const N: usize>(
+        &mut self,
+        addr: Address,
+    ) -> Result<[Word; N]> {
+        let cnt = array_quantity::<N>()?;
+        let result = self.read_holding_registers(addr, cnt).await?;
+        Ok(result.map(into_array))
+    }
+
+    /// Read a fixed number of input registers (0x04) into an array.
+    ///
+    /// Same as [`Reader::read_input_registers()`] with `N` registers.
+    pub async fn read_input_registers_array<const N: usize>(
+        &mut self,
+        addr: Address,
+    ) -> Result<[Word; N]> {
+        let cnt = array_quantity::<N>()?;
+        let result = self.read_input_registers(addr, cnt).await?;
+        Ok(result.map(into_array))
+    }
+
+    /// Read holding registers (0x03) without copying them into a [`Vec`].
+    ///
+    /// Same as [`Reader::read_holding_registers()`], but the registers
+    /// are accessed directly in the received frame, e.g. for polling
+    /// large blocks at a high rate. The registers are copied from the
+    /// regular response of clients that don't support views.
+    pub async fn read_holding_registers_view(
+        &mut self,
+        addr: Address,
+        cnt: Quantity,
+    ) -> Result<Registers> {
+        self.read_registers_view(FunctionCode::ReadHoldingRegisters, addr, cnt)
+            .await
+    }
+
+    /// Read input registers (0x04) without copying them into a [`Vec`].
+    ///
+    /// See also [`Self::read_holding_registers_view()`].
+    pub async fn read_input_registers_view(
+        &mut self,
+        addr: Address,
+        cnt: Quantity,
+    ) -> Result<Registers> {
+        self.read_registers_view(FunctionCode::ReadInputRegisters, addr, cnt)
+            .await
+    }
+
+    async fn read_registers_view(
+        &mut self,
+        function_code: FunctionCode,
+        addr: Address,
+        cnt: Quantity,
+    ) -> Result<Registers> {
+        let request = if function_code == FunctionCode::ReadInputRegisters {
+            Request::ReadInputRegisters(addr, cnt)
+        } else {
+            Request::ReadHoldingRegisters(addr, cnt)
+        };
+        let (result, _meta) = self.call_decoded(request, true).await;
+        let response = match result? {
+            Ok(response) if response.function_code() == function_code => response,
+            Ok(response) => {
+                return Err(unexpected_response(function_code, response.into_response()))
+            }
+            Err(exception) => return Ok(Err(exception)),
+        };
+        match response {
+            DecodedResponse::Registers(_, registers) if registers.len() == cnt.into() => {
+                Ok(Ok(registers))
+            }
+            // Clients that don't support views.
+            DecodedResponse::Response(
+                Response::ReadHoldingRegisters(words) | Response::ReadInputRegisters(words),
+            ) if words.len() == cnt.into() => Ok(Ok(Registers::from(words.as_slice()))),
+            response => Err(mismatching_response(
+                format!("expected {cnt} registers"),
+                response.into_response(),
+            )),
+        }
+    }
+
+    /// Read an arbitrary number of coils (0x01) with multiple requests.
+    ///
+    /// The range is split into consecutive chunks of at most `max_chunk`
+    /// coils, limited by the maximum of 2000 coils per request. The chunks
+    /// don't need to be aligned to bytes, i.e. the coils are returned in
+    /// the same order and number as if they had been read all at once.
+    ///
+    /// Stops at the first exception or error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_chunk` is zero.
+    pub async fn read_coils_chunked(
+        &mut self,
+        addr: Address,
+        cnt: usize,
+        max_chunk: Quantity,
+    ) -> Result<Vec<Coil>> {
+        self.read_bits_chunked(FunctionCode::ReadCoils, addr, cnt, max_chunk)
+            .await
+    }
+
+    /// Read an arbitrary number of discrete inputs (0x02) with multiple
+    /// requests.
+    ///
+    /// See also [`Self::read_coils_chunked()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_chunk` is zero.
+    pub async fn read_discrete_inputs_chunked(
+        &mut self,
+        addr: Address,
+        cnt: usize,
+        max_chunk: Quantity,
+    ) -> Result<Vec<Coil>> {
+        self.read_bits_chunked(FunctionCode::ReadDiscreteInputs, addr, cnt, max_chunk)
+            .await
+    }
+
+    async fn read_bits_chunked(
+        &mut self,
+        function_code: FunctionCode,
+        addr: Address,
+        cnt: usize,
+        max_chunk: Quantity,
+    ) -> Result<Vec<Coil>> {
+        assert!(max_chunk > 0, "chunks must not be empty");
+        let max_chunk = max_chunk.min(MAX_READ_BITS);
+        if usize::from(addr) + cnt > usize::from(Address::MAX) + 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("reading {cnt} bits from address {addr} exceeds the address space"),
+            )
+            .into());
+        }
+        let mut bits = Vec::with_capacity(cnt);
+        let mut chunk_addr = addr;
+        while bits.len() < cnt {
+            let chunk_cnt = Quantity::try_from(cnt - bits.len())
+                .map_or(max_chunk, |remaining| remaining.min(max_chunk));
+            let result = if function_code == FunctionCode::ReadCoils {
+                self.read_coils(chunk_addr, chunk_cnt).await?
+            } else {
+                self.read_discrete_inputs(chunk_addr, chunk_cnt).await?
+            };
+            match result {
+                Ok(chunk) => {
+                    debug_assert_eq!(chunk.len(), chunk_cnt.into());
+                    bits.extend(chunk);
+                    // Only wraps around after the last chunk.
+                    chunk_addr = chunk_addr.wrapping_add(chunk_cnt);
+                }
+                Err(exception) => return Ok(Err(exception)),
+            }
+        }
+        Ok(Ok(bits))
+    }
+
+    async fn recover(&mut self, err: &Error) {
+        let recover = match err {
+            Error::Transport(_) => self.error_recovery.link(),
+            Error::Protocol(_) | Error::Timeout(_) => self.error_recovery.protocol(),
+            // Nothing has been sent.
+            Error::NoResponse(_) => false,
+        };
+        if !recover {
+            return;
+        }
+        let prefix = LogPrefix(self.label.as_deref());
+        if self.reconnect.is_none() {
+            if matches!(err, Error::Transport(_)) {
+                log::debug!("{prefix}Unable to reconnect after error: {err}");
+                return;
+            }
+            match self.client.resynchronize().await {
+                Ok(true) => log::debug!("{prefix}Resynchronized after error: {err}"),
+                Ok(false) => log::debug!("{prefix}Unable to resynchronize after error: {err}"),
+                Err(err) => log::debug!("{prefix}Failed to resynchronize: {err}"),
+            }
+            return;
+        }
+        log::debug!("{prefix}Reconnecting after error: {err}");
+        if let Err(err) = self.client.disconnect().await {
+            log::debug!("{prefix}Failed to disconnect: {err}");
+        }
+        self.reconnect().await;
+    }
+
+    async fn tear_down(&mut self, watchdog: &Watchdog) {
+        let prefix = LogPrefix(self.label.as_deref());
+        log::warn!(
+            "{prefix}Tearing down stuck connection without progress within {timeout:?}",
+            timeout = watchdog.timeout()
+        );
+        if let Err(err) = self.client.disconnect().await {
+            log::debug!("{prefix}Failed to disconnect: {err}");
+        }
+        let reconnected = watchdog.reconnects() && self.reconnect().await;
+        watchdog.trip(WatchdogEvent {
+            label: self.label.clone(),
+            stalled_for: watchdog.timeout(),
+            reconnected,
+        });
+    }
+
+    /// Checks if a transport error requires to re-establish the connection.
+    fn is_connection_lost(&self, err: &Error) -> bool {
+        let (Some(reconnect), Error::Transport(err)) = (&self.reconnect, err) else {
+            return false;
+        };
+        reconnect.is_connection_lost(err)
+    }
+
+    /// Replace a lost connection, trying multiple times.
+    ///
+    /// Returns `true` if the connection has been re-established.
+    async fn reconnect_with_backoff(&mut self, backoff: Backoff) -> bool {
+        let prefix = LogPrefix(self.label.as_deref());
+        log::info!("{prefix}Connection lost, reconnecting");
+        if let Err(err) = self.client.disconnect().await {
+            log::debug!("{prefix}Failed to disconnect: {err}");
+        }
+        for delay in backoff.delays() {
+            tokio::time::sleep(delay).await;
+            if self.reconnect().await {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Replace the client with a new connection.
+    ///
+    /// Returns `true` if the connection has been re-established.
+    async fn reconnect(&mut self) -> bool {
+        let Some(reconnect) = &self.reconnect else {
+            return false;
+        };
+        match reconnect.reconnect().await {
+            Ok(mut client) => {
+                if let Some(slave) = self.slave {
+                    client.set_slave(slave);
+                }
+                self.client = client;
+                true
+            }
+            Err(err) => {
+                let prefix = LogPrefix(self.label.as_deref());
+                log::warn!("{prefix}Failed to reconnect: {err}");
+                false
+            }
+        }
+    }
+}
+
+impl From<Box<dyn Client>> for Context {
+    fn from(client: Box<dyn Client>) -> Self {
+        Self::new(client)
+    }
+}
+
+impl From<Context> for Box<dyn Client> {
+    fn from(val: Context) -> Self {
+        val.client
+    }
+}
+
+#[async_trait]
+impl Client for Context {
+    async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+        let (result, _meta) = self.call_with_meta(request).await;
+        result
+    }
+
+    async fn call_with_register_views(&mut self, request: Request<'_>) -> Result<DecodedResponse> {
+        let (result, _meta) = self.call_decoded(request, true).await;
+        result
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        self.client.disconnect().await
+    }
+
+    async fn resynchronize(&mut self) -> io::Result<bool> {
+        self.client.resynchronize().await
+    }
+
+    fn stale_responses(&self) -> u64 {
+        self.client.stale_responses()
+    }
+
+    fn connection_stats(&self) -> Option<ConnectionStats> {
+        self.client.connection_stats()
+    }
+
+    fn checksum_errors(&self) -> u64 {
+        self.client.checksum_errors()
+    }
+
+    fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+}
+
+impl SlaveContext for Context {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = Some(slave);
+        self.client.set_slave(slave);
+    }
+}
+
+/// The maximum number of coils or discrete inputs that can be read
+/// with a single request.
+const MAX_READ_BITS: Quantity = 2000;
+
+/// The number of values of an array.
+fn array_quantity<const N: usize>() -> io::Result<Quantity> {
+    Quantity::try_from(N)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("too many values: {N}")))
+}
+
+/// Convert the values of a response with a verified length.
+fn into_array<const N: usize>(words: Vec<Word>) -> [Word; N] {
+    let len = words.len();
+    words
+        .try_into()
+        .unwrap_or_else(|_| unreachable!("expected {N} values instead of {len}"))
+}
+
+/// A response of another function than requested.
+///
+/// The built-in clients already reject these responses,
+/// but custom [`Client`] implementations might not.
+fn unexpected_response(request: FunctionCode, response: Response) -> Error {
+    ProtocolError::FunctionCodeMismatch {
+        request,
+        result: Ok(response),
+    }
+    .into()
+}
+
+/// A response of the requested function that doesn't match the request.
+fn mismatching_response(message: String, response: Response) -> Error {
+    ProtocolError::ResponseMismatch {
+        message,
+        result: Ok(response),
+    }
+    .into()
+}
+
+fn fifo_queue(response: std::result::Result<Response, ExceptionCode>) -> Result<Vec<Word>> {
+    match response {
+        Ok(Response::ReadFifoQueue(words)) => Ok(Ok(words)),
+        Ok(response) => Err(unexpected_response(FunctionCode::ReadFifoQueue, response)),
+        Err(exception) => Ok(Err(exception)),
+    }
+}
+
+fn exception_status(response: std::result::Result<Response, ExceptionCode>) -> Result<u8> {
+    match response {
+        Ok(Response::ReadExceptionStatus(status)) => Ok(Ok(status)),
+        Ok(response) => Err(unexpected_response(
+            FunctionCode::ReadExceptionStatus,
+            response,
+        )),
+        Err(exception) => Ok(Err(exception)),
+    }
+}
+
+/// Collects the objects of a device identification response that has
+/// been requested starting with `object_id`.
+///
+/// Returns the object to continue with if more objects follow.
+fn collect_device_identification(
+    objects: &mut BTreeMap<DeviceIdObjectId, Vec<u8>>,
+    object_id: DeviceIdObjectId,
+    response: std::result::Result<Response, ExceptionCode>,
+) -> Result<Option<DeviceIdObjectId>> {
+    let response = match response {
+        Ok(Response::ReadDeviceIdentification(response)) => response,
+        Ok(response) => {
+            return Err(unexpected_response(
+                FunctionCode::EncapsulatedInterfaceTransport,
+                response,
+            ));
+        }
+        Err(exception) => return Ok(Err(exception)),
+    };
+    // Prevent an endless loop if the device doesn't make progress.
+    if response.more_follows && response.next_object_id <= object_id {
+        return Err(mismatching_response(
+            format!("expected next object after {object_id}"),
+            Response::ReadDeviceIdentification(response),
+        ));
+    }
+    let ReadDeviceIdentificationResponse {
+        more_follows,
+        next_object_id,
+        objects: received_objects,
+        ..
+    } = response;
+    objects.extend(received_objects);
+    Ok(Ok(more_follows.then_some(next_object_id)))
+}
+
+fn device_identification_object(
+    object_id: DeviceIdObjectId,
+    response: std::result::Result<Response, ExceptionCode>,
+) -> Result<Vec<u8>> {
+    let mut response = match response {
+        Ok(Response::ReadDeviceIdentification(response)) => response,
+        Ok(response) => {
+            return Err(unexpected_response(
+                FunctionCode::EncapsulatedInterfaceTransport,
+                response,
+            ));
+        }
+        Err(exception) => return Ok(Err(exception)),
+    };
+    let Some(index) = response
+        .objects
+        .iter()
+        .position(|(id, _)| id.value() == object_id.value())
+    else {
+        return Err(mismatching_response(
+            format!("expected object {object_id}"),
+            Response::ReadDeviceIdentification(response),
+        ));
+    };
+    let (_, value) = response.objects.swap_remove(index);
+    Ok(Ok(value))
+}
+
+#[async_trait]
+impl Reader for Context {
+    async fn read_coils<'a>(&'a mut self, addr: Address, cnt: Quantity) -> Result<Vec<Coil>> {
+        match self.call(Request::ReadCoils(addr, cnt)).await? {
+            Ok(Response::ReadCoils(mut coils)) if is_requested_bit_count(coils.len(), cnt) => {
+                coils.truncate(cnt.into());
+                Ok(Ok(coils))
+            }
+            Ok(response @ Response::ReadCoils(_)) => Err(mismatching_response(
+                format!("expected {cnt} coils"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(FunctionCode::ReadCoils, response)),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    async fn read_discrete_inputs<'a>(
+        &'a mut self,
+        addr: Address,
+        cnt: Quantity,
+    ) -> Result<Vec<Coil>> {
+        match self.call(Request::ReadDiscreteInputs(addr, cnt)).await? {
+            Ok(Response::ReadDiscreteInputs(mut coils))
+                if is_requested_bit_count(coils.len(), cnt) =>
+            {
+                coils.truncate(cnt.into());
+                Ok(Ok(coils))
+            }
+            Ok(response @ Response::ReadDiscreteInputs(_)) => Err(mismatching_response(
+                format!("expected {cnt} discrete inputs"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(
+                FunctionCode::ReadDiscreteInputs,
+                response,
+            )),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    async fn read_input_registers<'a>(
+        &'a mut self,
+        addr: Address,
+        cnt: Quantity,
+    ) -> Result<Vec<Word>> {
+        match self.call(Request::ReadInputRegisters(addr, cnt)).await? {
+            Ok(Response::ReadInputRegisters(words)) if words.len() == cnt.into() => Ok(Ok(words)),
+            Ok(response @ Response::ReadInputRegisters(_)) => Err(mismatching_response(
+                format!("expected {cnt} registers"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(
+                FunctionCode::ReadInputRegisters,
+                response,
+            )),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    async fn read_holding_registers<'a>(
+        &'a mut self,
+        addr: Address,
+        cnt: Quantity,
+    ) -> Result<Vec<Word>> {
+        match self.call(Request::ReadHoldingRegisters(addr, cnt)).await? {
+            Ok(Response::ReadHoldingRegisters(words)) if words.len() == cnt.into() => Ok(Ok(words)),
+            Ok(response @ Response::ReadHoldingRegisters(_)) => Err(mismatching_response(
+                format!("expected {cnt} registers"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(
+                FunctionCode::ReadHoldingRegisters,
+                response,
+            )),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    async fn read_write_multiple_registers<'a>(
+        &'a mut self,
+        read_addr: Address,
+        read_count: Quantity,
+        write_addr: Address,
+        write_data: &[Word],
+    ) -> Result<Vec<Word>> {
+        let request = Request::ReadWriteMultipleRegisters(
+            read_addr,
+            read_count,
+            write_addr,
+            Cow::Borrowed(write_data),
+        );
+        match self.call(request).await? {
+            Ok(Response::ReadWriteMultipleRegisters(words)) if words.len() == read_count.into() => {
+                Ok(Ok(words))
+            }
+            Ok(response @ Response::ReadWriteMultipleRegisters(_)) => Err(mismatching_response(
+                format!("expected {read_count} registers"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(
+                FunctionCode::ReadWriteMultipleRegisters,
+                response,
+            )),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+}
+
+#[async_trait]
+impl Writer for Context {
+    async fn write_single_coil<'a>(&'a mut self, addr: Address, coil: Coil) -> Result<()> {
+        match self.call(Request::WriteSingleCoil(addr, coil)).await? {
+            Ok(Response::WriteSingleCoil(rsp_addr, rsp_coil))
+                if (rsp_addr, rsp_coil) == (addr, coil) =>
+            {
+                Ok(Ok(()))
+            }
+            Ok(response @ Response::WriteSingleCoil(_, _)) => Err(mismatching_response(
+                format!("expected echo of coil {addr} = {coil}"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(FunctionCode::WriteSingleCoil, response)),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    async fn write_multiple_coils<'a>(&'a mut self, addr: Address, coils: &[Coil]) -> Result<()> {
+        let cnt = coils.len();
+        match self
+            .call(Request::WriteMultipleCoils(addr, Cow::Borrowed(coils)))
+            .await?
+        {
+            Ok(Response::WriteMultipleCoils(rsp_addr, rsp_cnt))
+                if (rsp_addr, usize::from(rsp_cnt)) == (addr, cnt) =>
+            {
+                Ok(Ok(()))
+            }
+            Ok(response @ Response::WriteMultipleCoils(_, _)) => Err(mismatching_response(
+                format!("expected echo of {cnt} coils at {addr}"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(
+                FunctionCode::WriteMultipleCoils,
+                response,
+            )),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    async fn write_single_register<'a>(&'a mut self, addr: Address, word: Word) -> Result<()> {
+        match self.call(Request::WriteSingleRegister(addr, word)).await? {
+            Ok(Response::WriteSingleRegister(rsp_addr, rsp_word))
+                if (rsp_addr, rsp_word) == (addr, word) =>
+            {
+                Ok(Ok(()))
+            }
+            Ok(response @ Response::WriteSingleRegister(_, _)) => Err(mismatching_response(
+                format!("expected echo of register {addr} = {word}"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(
+                FunctionCode::WriteSingleRegister,
+                response,
+            )),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    async fn write_multiple_registers<'a>(
+        &'a mut self,
+        addr: Address,
+        data: &[Word],
+    ) -> Result<()> {
+        let cnt = data.len();
+        match self
+            .call(Request::WriteMultipleRegisters(addr, Cow::Borrowed(data)))
+            .await?
+        {
+            Ok(Response::WriteMultipleRegisters(rsp_addr, rsp_cnt))
+                if (rsp_addr, usize::from(rsp_cnt)) == (addr, cnt) =>
+            {
+                Ok(Ok(()))
+            }
+            Ok(response @ Response::WriteMultipleRegisters(_, _)) => Err(mismatching_response(
+                format!("expected echo of {cnt} registers at {addr}"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(
+                FunctionCode::WriteMultipleRegisters,
+                response,
+            )),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+
+    async fn masked_write_register<'a>(
+        &'a mut self,
+        addr: Address,
+        and_mask: Word,
+        or_mask: Word,
+    ) -> Result<()> {
+        match self
+            .call(Request::MaskWriteRegister(addr, and_mask, or_mask))
+            .await?
+        {
+            Ok(Response::MaskWriteRegister(rsp_addr, rsp_and_mask, rsp_or_mask))
+                if (rsp_addr, rsp_and_mask, rsp_or_mask) == (addr, and_mask, or_mask) =>
+            {
+                Ok(Ok(()))
+            }
+            Ok(response @ Response::MaskWriteRegister(_, _, _)) => Err(mismatching_response(
+                format!("expected echo of masks for register {addr}"),
+                response,
+            )),
+            Ok(response) => Err(unexpected_response(
+                FunctionCode::MaskWriteRegister,
+                response,
+            )),
+            Err(exception) => Ok(Err(exception)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{bytes::Bytes, Error, Result};
+
+    use super::*;
+    use std::{io, sync::Mutex};
+
+    #[derive(Default, Debug)]
+    pub(crate) struct ClientMock {
+        slave: Option<Slave>,
+        last_request: Mutex<Option<Request<'static>>>,
+        next_response: Option<Result<Response>>,
+        next_registers: Option<(FunctionCode, Registers)>,
+    }
+
+    #[allow(dead_code)]
+    impl ClientMock {
+        pub(crate) fn slave(&self) -> Option<Slave> {
+            self.slave
+        }
+
+        pub(crate) fn last_request(&self) -> &Mutex<Option<Request<'static>>> {
+            &self.last_request
+        }
+
+        pub(crate) fn set_next_response(&mut self, next_response: Result<Response>) {
+            self.next_response = Some(next_response);
+        }
+
+        /// Responds with a view of the registers instead of the next
+        /// response, like clients that support views.
+        pub(crate) fn set_next_registers(&mut self, function: FunctionCode, registers: Registers) {
+            self.next_registers = Some((function, registers));
+        }
+    }
+
+    #[async_trait]
+    impl Client for ClientMock {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            *self.last_request.lock().unwrap() = Some(request.into_owned());
+            match self.next_response.take().unwrap() {
+                Ok(response) => Ok(response),
+                Err(Error::Transport(err)) => {
+                    Err(io::Error::new(err.kind(), format!("{err}")).into())
+                }
+                Err(err) => Err(err),
+            }
+        }
+
+        async fn call_with_register_views(
+            &mut self,
+            request: Request<'_>,
+        ) -> Result<DecodedResponse> {
+            let Some((function, registers)) = self.next_registers.take() else {
+                let result = self.call(request).await?;
+                return Ok(result.map(Into::into));
+            };
+            *self.last_request.lock().unwrap() = Some(request.into_owned());
+            Ok(Ok(DecodedResponse::Registers(function, registers)))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for ClientMock {
+        fn set_slave(&mut self, slave: Slave) {
+            self.slave = Some(slave);
+        }
+    }
+
+    /// A reader that only implements the required methods.
+    #[derive(Debug, Default)]
+    struct MinimalReader(ClientMock);
+
+    #[async_trait]
+    impl Client for MinimalReader {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            self.0.call(request).await
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for MinimalReader {
+        fn set_slave(&mut self, slave: Slave) {
+            self.0.set_slave(slave);
+        }
+    }
+
+    #[async_trait]
+    impl Reader for MinimalReader {
+        async fn read_coils(&mut self, _: Address, _: Quantity) -> Result<Vec<Coil>> {
+            unimplemented!()
+        }
+
+        async fn read_discrete_inputs(&mut self, _: Address, _: Quantity) -> Result<Vec<Coil>> {
+            unimplemented!()
+        }
+
+        async fn read_holding_registers(&mut self, _: Address, _: Quantity) -> Result<Vec<Word>> {
+            unimplemented!()
+        }
+
+        async fn read_input_registers(&mut self, _: Address, _: Quantity) -> Result<Vec<Word>> {
+            unimplemented!()
+        }
+
+        async fn read_write_multiple_registers(
+            &mut self,
+            _: Address,
+            _: Quantity,
+            _: Address,
+            _: &[Word],
+        ) -> Result<Vec<Word>> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn read_with_default_implementations() {
+        let mut reader = MinimalReader::default();
+        reader
+            .0
+            .set_next_response(Ok(Ok(Response::ReadFifoQueue(vec![1, 2]))));
+        let words = futures::executor::block_on(reader.read_fifo_queue(7)).unwrap();
+        assert_eq!(words, Ok(vec![1, 2]));
+        assert_eq!(
+            *reader.0.last_request().lock().unwrap(),
+            Some(Request::ReadFifoQueue(7))
+        );
+
+        reader
+            .0
+            .set_next_response(Ok(Err(ExceptionCode::IllegalFunction)));
+        let status = futures::executor::block_on(reader.read_exception_status()).unwrap();
+        assert_eq!(status, Err(ExceptionCode::IllegalFunction));
+    }
+
+    #[test]
+    fn read_some_coils() {
+        // The protocol will always return entire bytes with, i.e.
+        // a multiple of 8 coils.
+        let response_coils = [true, false, false, true, false, true, false, true];
+        for num_coils in 1..8 {
+            let mut client = Box::<ClientMock>::default();
+            client.set_next_response(Ok(Ok(Response::ReadCoils(response_coils.to_vec()))));
+            let mut context = Context::new(client);
+            context.set_slave(Slave(1));
+            let coils = futures::executor::block_on(context.read_coils(1, num_coils))
+                .unwrap()
+                .unwrap();
+            assert_eq!(&response_coils[0..num_coils as usize], &coils[..]);
+        }
+    }
+
+    #[test]
+    fn read_some_discrete_inputs() {
         // The protocol will always return entire bytes with, i.e.
         // a multiple of 8 coils.
         let response_inputs = [true, false, false, true, false, true, false, true];
@@ -396,7 +1763,7 @@ mod tests {
             client.set_next_response(Ok(Ok(Response::ReadDiscreteInputs(
                 response_inputs.to_vec(),
             ))));
-            let mut context = Context { client };
+            let mut context = Context::new(client);
             context.set_slave(Slave(1));
             let inputs = futures::executor::block_on(context.read_discrete_inputs(1, num_inputs))
                 .unwrap()
@@ -404,4 +1771,897 @@ mod tests {
             assert_eq!(&response_inputs[0..num_inputs as usize], &inputs[..]);
         }
     }
+
+    #[test]
+    fn reject_mismatching_number_of_coils() {
+        for num_coils in [1, 8, 9] {
+            let mut client = Box::<ClientMock>::default();
+            client.set_next_response(Ok(Ok(Response::ReadCoils(vec![true; 16]))));
+            let mut context = Context::new(client);
+            let result = futures::executor::block_on(context.read_coils(0, num_coils));
+            if num_coils == 9 {
+                assert_eq!(result.unwrap(), Ok(vec![true; 9]));
+            } else {
+                assert!(matches!(
+                    result,
+                    Err(Error::Protocol(ProtocolError::ResponseMismatch { .. }))
+                ));
+            }
+        }
+    }
+
+    fn call_with_response<T>(
+        response: Response,
+        call: impl FnOnce(&mut Context) -> futures::future::BoxFuture<'_, Result<T>>,
+    ) -> Result<T> {
+        let mut client = Box::<ClientMock>::default();
+        client.set_next_response(Ok(Ok(response)));
+        let mut context = Context::new(client);
+        futures::executor::block_on(call(&mut context))
+    }
+
+    #[test]
+    fn reject_mismatching_responses_of_custom_clients() {
+        let err = call_with_response(Response::ReadCoils(vec![true]), |context| {
+            Box::pin(context.read_holding_registers(0, 1))
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::FunctionCodeMismatch {
+                request: FunctionCode::ReadHoldingRegisters,
+                ..
+            })
+        ));
+
+        let err = call_with_response(Response::ReadHoldingRegisters(vec![1]), |context| {
+            Box::pin(context.read_holding_registers(0, 2))
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch { .. })
+        ));
+
+        let err = call_with_response(Response::ReadCoils(vec![]), |context| {
+            Box::pin(context.toggle_coil(0))
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch { .. })
+        ));
+
+        let err = call_with_response(Response::WriteSingleRegister(1, 0x1234), |context| {
+            Box::pin(context.write_single_register(0, 0x1234))
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch { .. })
+        ));
+
+        call_with_response(Response::WriteSingleRegister(0, 0x1234), |context| {
+            Box::pin(context.write_single_register(0, 0x1234))
+        })
+        .unwrap()
+        .unwrap();
+    }
+
+    #[test]
+    fn verify_diagnostics_echo() {
+        use DiagnosticsSubFunction::*;
+
+        call_with_response(
+            Response::Diagnostics(ReturnQueryData, vec![0xA537]),
+            |context| Box::pin(context.return_query_data(&[0xA537])),
+        )
+        .unwrap()
+        .unwrap();
+
+        let err = call_with_response(Response::Diagnostics(ReturnQueryData, vec![0]), |context| {
+            Box::pin(context.return_query_data(&[0xA537]))
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch { .. })
+        ));
+
+        let count = call_with_response(
+            Response::Diagnostics(ReturnBusMessageCount, vec![42]),
+            |context| Box::pin(context.diagnostics(ReturnBusMessageCount, &[0])),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(count, [42]);
+
+        let err = call_with_response(
+            Response::Diagnostics(ReturnServerBusyCount, vec![42]),
+            |context| Box::pin(context.diagnostics(ReturnBusMessageCount, &[0])),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch { .. })
+        ));
+    }
+
+    /// Answers each counter with 10 times its sub-function code.
+    fn counters_client(exception_for: Option<DiagnosticsCounter>) -> DryRunClient {
+        let client = DryRunClient::new();
+        client.add_rule(move |_, request| {
+            let Request::Diagnostics(sub_function, _) = request else {
+                return None;
+            };
+            if exception_for.map(DiagnosticsCounter::sub_function) == Some(*sub_function) {
+                return Some(Err(ExceptionCode::IllegalFunction));
+            }
+            let value = sub_function.value() * 10;
+            Some(Ok(Response::Diagnostics(*sub_function, vec![value])))
+        });
+        client
+    }
+
+    #[tokio::test]
+    async fn read_bus_counters() {
+        let client = counters_client(None);
+        let mut context = client.attach_slave(Slave(1));
+        let count = context
+            .read_diagnostics_counter(DiagnosticsCounter::BusCommunicationError)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(count, 120);
+        let counters = context.read_bus_counters().await.unwrap().unwrap();
+        assert_eq!(
+            counters,
+            BusCounters {
+                bus_messages: 110,
+                bus_communication_errors: 120,
+                bus_exception_errors: 130,
+                server_messages: 140,
+                server_no_responses: 150,
+                server_naks: 160,
+                server_busy: 170,
+                bus_character_overruns: 180,
+            }
+        );
+        assert_eq!(client.requests().len(), 9);
+
+        let client = counters_client(Some(DiagnosticsCounter::ServerNak));
+        let mut context = client.attach_slave(Slave(1));
+        let exception = context.read_bus_counters().await.unwrap().unwrap_err();
+        assert_eq!(exception, ExceptionCode::IllegalFunction);
+        // Stopped at the first exception.
+        assert_eq!(client.requests().len(), 6);
+
+        let err = call_with_response(
+            Response::Diagnostics(DiagnosticsSubFunction::ReturnBusMessageCount, vec![1, 2]),
+            |context| Box::pin(context.read_diagnostics_counter(DiagnosticsCounter::BusMessage)),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch { .. })
+        ));
+    }
+
+    /// Answers each request with a single object and the next object id.
+    fn device_identification_client(objects: &'static [(u8, &'static str)]) -> DryRunClient {
+        let client = DryRunClient::new();
+        client.add_rule(move |_, request| {
+            let Request::ReadDeviceIdentification(read_device_id_code, object_id) = *request else {
+                return None;
+            };
+            let index = objects
+                .iter()
+                .position(|(id, _)| *id == object_id.value())
+                .unwrap_or_default();
+            let more_follows =
+                read_device_id_code != ReadDeviceIdCode::Specific && index + 1 < objects.len();
+            let next_object_id = if more_follows {
+                objects[index + 1].0
+            } else {
+                0
+            };
+            let (id, value) = objects[index];
+            Some(Ok(Response::ReadDeviceIdentification(
+                ReadDeviceIdentificationResponse {
+                    read_device_id_code,
+                    conformity_level: 0x83,
+                    more_follows,
+                    next_object_id: DeviceIdObjectId::new(next_object_id),
+                    objects: vec![(DeviceIdObjectId::new(id), value.as_bytes().to_vec())],
+                },
+            )))
+        });
+        client
+    }
+
+    #[tokio::test]
+    async fn read_device_identification_with_continuation() {
+        let client =
+            device_identification_client(&[(0x00, "vendor"), (0x01, "product"), (0x80, "ext")]);
+        let mut context = client.attach_slave(Slave(1));
+        let objects = context
+            .read_device_identification(ReadDeviceIdCode::Extended)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            objects,
+            BTreeMap::from([
+                (DeviceIdObjectId::VendorName, b"vendor".to_vec()),
+                (DeviceIdObjectId::ProductCode, b"product".to_vec()),
+                (DeviceIdObjectId::Custom(0x80), b"ext".to_vec()),
+            ])
+        );
+        assert_eq!(client.requests().len(), 3);
+
+        let value = context
+            .read_device_identification_object(DeviceIdObjectId::ProductCode)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(value, b"product");
+    }
+
+    #[tokio::test]
+    async fn read_device_identification_without_progress() {
+        // The next object id refers to the same object again.
+        let client = device_identification_client(&[(0x00, "vendor"), (0x00, "vendor")]);
+        let mut context = client.attach_slave(Slave(1));
+        let err = context
+            .read_device_identification(ReadDeviceIdCode::Basic)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch { .. })
+        ));
+    }
+
+    #[derive(Debug, Default)]
+    struct CoilsMock {
+        coils: std::sync::Arc<Mutex<Vec<(Address, Coil)>>>,
+        fail_writes: usize,
+    }
+
+    #[async_trait]
+    impl Client for CoilsMock {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            let mut coils = self.coils.lock().unwrap();
+            match request {
+                Request::ReadCoils(addr, 1) => {
+                    let coil = coils
+                        .iter()
+                        .rev()
+                        .find(|(a, _)| *a == addr)
+                        .map(|(_, c)| *c);
+                    let mut response = vec![false; 8];
+                    response[0] = coil.unwrap_or_default();
+                    Ok(Ok(Response::ReadCoils(response)))
+                }
+                Request::WriteSingleCoil(addr, coil) => {
+                    if self.fail_writes > 0 {
+                        self.fail_writes -= 1;
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "timeout").into());
+                    }
+                    coils.push((addr, coil));
+                    Ok(Ok(Response::WriteSingleCoil(addr, coil)))
+                }
+                _ => Ok(Err(ExceptionCode::IllegalFunction)),
+            }
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for CoilsMock {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn toggle_coil() {
+        let client = CoilsMock::default();
+        let coils = std::sync::Arc::clone(&client.coils);
+        let mut context = Context::new(Box::new(client));
+        assert!(context.toggle_coil(3).await.unwrap().unwrap());
+        assert!(!context.toggle_coil(3).await.unwrap().unwrap());
+        assert_eq!(*coils.lock().unwrap(), [(3, true), (3, false)]);
+    }
+
+    #[tokio::test]
+    async fn pulse_coil() {
+        let client = CoilsMock::default();
+        let coils = std::sync::Arc::clone(&client.coils);
+        let mut context = Context::new(Box::new(client));
+        context
+            .pulse_coil(5, Duration::from_millis(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(*coils.lock().unwrap(), [(5, true), (5, false)]);
+    }
+
+    #[tokio::test]
+    async fn pulse_coil_switches_off_after_failure() {
+        let client = CoilsMock {
+            fail_writes: 1,
+            ..Default::default()
+        };
+        let coils = std::sync::Arc::clone(&client.coils);
+        let mut context = Context::new(Box::new(client));
+        let err = context
+            .pulse_coil(5, Duration::from_millis(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Transport(err) if err.kind() == io::ErrorKind::TimedOut));
+        assert_eq!(*coils.lock().unwrap(), [(5, false)]);
+    }
+
+    /// The value of a simulated coil or discrete input.
+    fn simulated_bit(addr: usize) -> bool {
+        addr.count_ones() % 3 == 0
+    }
+
+    fn simulated_client() -> DryRunClient {
+        let client = DryRunClient::new();
+        client.add_rule(|_, request| match request {
+            Request::ReadCoils(addr, cnt) | Request::ReadDiscreteInputs(addr, cnt) => {
+                assert!(*cnt <= MAX_READ_BITS);
+                let addr = usize::from(*addr);
+                let mut bits: Vec<_> = (addr..addr + usize::from(*cnt))
+                    .map(simulated_bit)
+                    .collect();
+                // Bits are transmitted as whole bytes.
+                bits.resize(bits.len().div_ceil(8) * 8, false);
+                Some(Ok(match request {
+                    Request::ReadCoils(_, _) => Response::ReadCoils(bits),
+                    _ => Response::ReadDiscreteInputs(bits),
+                }))
+            }
+            _ => None,
+        });
+        client
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn read_bits_chunked(
+            addr in 0..=Address::MAX,
+            cnt in 0usize..=5000,
+            max_chunk in 1..=2100 as Quantity,
+        ) {
+            let cnt = cnt.min(usize::from(Address::MAX) + 1 - usize::from(addr));
+            let client = simulated_client();
+            let mut context = client.attach_slave(Slave(1));
+            let coils = futures::executor::block_on(
+                context.read_coils_chunked(addr, cnt, max_chunk),
+            );
+            let inputs = futures::executor::block_on(
+                context.read_discrete_inputs_chunked(addr, cnt, max_chunk),
+            );
+            let oracle: Vec<_> = (usize::from(addr)..usize::from(addr) + cnt)
+                .map(simulated_bit)
+                .collect();
+            proptest::prop_assert_eq!(coils.unwrap().unwrap(), oracle.clone());
+            proptest::prop_assert_eq!(inputs.unwrap().unwrap(), oracle);
+
+            // The chunks are consecutive and don't exceed the limits.
+            let mut next_addr = usize::from(addr);
+            for (_, request) in client.requests() {
+                let (Request::ReadCoils(chunk_addr, chunk_cnt)
+                | Request::ReadDiscreteInputs(chunk_addr, chunk_cnt)) = request
+                else {
+                    unreachable!();
+                };
+                proptest::prop_assert_eq!(usize::from(chunk_addr), next_addr % 0x1_0000);
+                proptest::prop_assert!(chunk_cnt <= max_chunk.min(MAX_READ_BITS));
+                next_addr += usize::from(chunk_cnt);
+                if next_addr == usize::from(addr) + cnt {
+                    next_addr = usize::from(addr);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn read_bits_chunked_beyond_address_space() {
+        let client = simulated_client();
+        let mut context = client.attach_slave(Slave(1));
+        assert_eq!(
+            context
+                .read_coils_chunked(Address::MAX, 1, 10)
+                .await
+                .unwrap()
+                .unwrap(),
+            [simulated_bit(Address::MAX.into())]
+        );
+        assert!(matches!(
+            context.read_coils_chunked(Address::MAX, 2, 10).await,
+            Err(Error::Transport(err)) if err.kind() == io::ErrorKind::InvalidInput
+        ));
+    }
+
+    #[tokio::test]
+    async fn read_bits_chunked_stops_at_exception() {
+        let client = simulated_client();
+        client.push_response(Ok(Response::ReadCoils(vec![true; 8])));
+        client.push_response(Err(ExceptionCode::IllegalDataAddress));
+        let mut context = client.attach_slave(Slave(1));
+        assert_eq!(
+            context.read_coils_chunked(0, 30, 8).await.unwrap(),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(client.requests().len(), 2);
+    }
+
+    #[test]
+    fn read_registers_into_arrays() {
+        let mut client = Box::<ClientMock>::default();
+        client.set_next_response(Ok(Ok(Response::ReadInputRegisters(vec![1, 2, 3]))));
+        let mut context = Context::new(client);
+        let words: [Word; 3] =
+            futures::executor::block_on(context.read_input_registers_array(0x100))
+                .unwrap()
+                .unwrap();
+        assert_eq!(words, [1, 2, 3]);
+
+        let mut client = Box::<ClientMock>::default();
+        client.set_next_response(Ok(Ok(Response::ReadHoldingRegisters(vec![1, 2]))));
+        let mut context = Context::new(client);
+        let err = futures::executor::block_on(context.read_holding_registers_array::<3>(0x100))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn read_registers_into_views() {
+        let mut client = Box::<ClientMock>::default();
+        let registers = Registers::from_bytes(Bytes::from_static(&[0x00, 0x01, 0x00, 0x02]));
+        client.set_next_registers(FunctionCode::ReadHoldingRegisters, registers.unwrap());
+        let mut context = Context::new(client);
+        let registers = futures::executor::block_on(context.read_holding_registers_view(0x100, 2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(registers.to_vec(), [1, 2]);
+
+        // Clients that don't support views.
+        let mut client = Box::<ClientMock>::default();
+        client.set_next_response(Ok(Ok(Response::ReadInputRegisters(vec![3]))));
+        let mut context = Context::new(client);
+        let registers = futures::executor::block_on(context.read_input_registers_view(0x100, 1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(registers.to_vec(), [3]);
+
+        let mut client = Box::<ClientMock>::default();
+        let registers = Registers::from_bytes(Bytes::from_static(&[0x00, 0x01]));
+        client.set_next_registers(FunctionCode::ReadHoldingRegisters, registers.unwrap());
+        let mut context = Context::new(client);
+        let err =
+            futures::executor::block_on(context.read_holding_registers_view(0x100, 2)).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch { .. })
+        ));
+
+        let mut client = Box::<ClientMock>::default();
+        let registers = Registers::from_bytes(Bytes::from_static(&[0x00, 0x01]));
+        client.set_next_registers(FunctionCode::ReadHoldingRegisters, registers.unwrap());
+        let mut context = Context::new(client);
+        let err =
+            futures::executor::block_on(context.read_input_registers_view(0x100, 1)).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::FunctionCodeMismatch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn validate_responses_strictly() {
+        let mut client = Box::<ClientMock>::default();
+        client.set_next_response(Ok(Ok(Response::WriteSingleRegister(1, 0x1234))));
+        let mut context = Context::new(client);
+        let request = Request::WriteSingleRegister(0, 0x1234);
+        assert!(context.call(request.clone()).await.unwrap().is_ok());
+
+        let mut client = Box::<ClientMock>::default();
+        client.set_next_response(Ok(Ok(Response::WriteSingleRegister(1, 0x1234))));
+        let mut context = Context::new(client);
+        context.set_strict_validation(true);
+        let err = context.call(request).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::ResponseMismatch {
+                result: Ok(Response::WriteSingleRegister(1, 0x1234)),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn call_with_meta() {
+        let mut client = Box::<ClientMock>::default();
+        client.set_next_response(Ok(Ok(Response::ReadHoldingRegisters(vec![1]))));
+        let mut context = Context::new(client);
+        let before = SystemTime::now();
+        let (result, meta) = context
+            .call_with_meta(Request::ReadHoldingRegisters(0, 1))
+            .await;
+        assert_eq!(result.unwrap(), Ok(Response::ReadHoldingRegisters(vec![1])));
+        assert!(before <= meta.tx_time);
+        assert!(meta.tx_time <= meta.rx_time);
+        assert_eq!(meta.retries, 0);
+    }
+
+    #[cfg(feature = "tcp")]
+    #[tokio::test]
+    async fn timeout_unanswered_requests() {
+        let (transport, _server) = tokio::io::duplex(64);
+        let mut context = tcp::attach(transport);
+        assert_eq!(context.timeout(), None);
+        let timeout = Duration::from_millis(10);
+        context.set_timeout(timeout);
+        assert!(matches!(
+            context.read_holding_registers(0, 1).await,
+            Err(Error::Timeout(duration)) if duration == timeout
+        ));
+    }
+
+    #[derive(Debug, Default)]
+    struct RegisterMock {
+        word: Word,
+        masked_write_supported: bool,
+        requests: std::sync::Arc<Mutex<Vec<u8>>>,
+    }
+
+    #[async_trait]
+    impl Client for RegisterMock {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push(request.function_code().value());
+            match request {
+                Request::MaskWriteRegister(0, and_mask, or_mask) if self.masked_write_supported => {
+                    self.word = (self.word & and_mask) | (or_mask & !and_mask);
+                    Ok(Ok(Response::MaskWriteRegister(0, and_mask, or_mask)))
+                }
+                Request::ReadHoldingRegisters(0, 1) => {
+                    Ok(Ok(Response::ReadHoldingRegisters(vec![self.word])))
+                }
+                Request::WriteSingleRegister(0, word) => {
+                    self.word = word;
+                    Ok(Ok(Response::WriteSingleRegister(0, word)))
+                }
+                _ => Ok(Err(ExceptionCode::IllegalFunction)),
+            }
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for RegisterMock {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn emulate_masked_write_register() {
+        for masked_write_supported in [true, false] {
+            let client = RegisterMock {
+                word: 0x0012,
+                masked_write_supported,
+                ..Default::default()
+            };
+            let requests = std::sync::Arc::clone(&client.requests);
+            let mut context = Context::new(Box::new(client));
+            context
+                .masked_write_register_emulated(0, 0x00F2, 0x0025)
+                .await
+                .unwrap()
+                .unwrap();
+            context
+                .masked_write_register_emulated(0, 0xFFFF, 0x0000)
+                .await
+                .unwrap()
+                .unwrap();
+            let words = context.read_holding_registers(0, 1).await.unwrap().unwrap();
+            // Example from the specification
+            assert_eq!(words, [0x0017]);
+            let expected_requests: &[u8] = if masked_write_supported {
+                &[0x16, 0x16, 0x03]
+            } else {
+                // Masked write is only attempted once.
+                &[0x16, 0x03, 0x06, 0x03, 0x06, 0x03]
+            };
+            assert_eq!(*requests.lock().unwrap(), expected_requests);
+        }
+    }
+
+    #[tokio::test]
+    async fn label_transport_errors() {
+        let client = CoilsMock {
+            fail_writes: 1,
+            ..Default::default()
+        };
+        let mut context = Context::new(Box::new(client));
+        context.set_label("boiler-plc-1");
+        assert_eq!(context.label(), Some("boiler-plc-1"));
+        let err = context.write_single_coil(1, true).await.unwrap_err();
+        let Error::Transport(err) = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(err.to_string(), "boiler-plc-1: timeout");
+        let source = std::error::Error::source(err.get_ref().unwrap()).unwrap();
+        assert_eq!(source.to_string(), "timeout");
+    }
+
+    #[tokio::test]
+    async fn retry_failed_requests_of_batch() {
+        let client = CoilsMock {
+            fail_writes: 1,
+            ..Default::default()
+        };
+        let coils = std::sync::Arc::clone(&client.coils);
+        let mut context = Context::new(Box::new(client));
+        let requests = [
+            Request::WriteSingleCoil(1, true),
+            Request::WriteSingleCoil(2, true),
+        ];
+        let mut outcome = context.call_batch(&requests).await;
+        assert_eq!(outcome.failed_indices(), [0]);
+        assert_eq!(*coils.lock().unwrap(), [(2, true)]);
+
+        context.retry_failed(&requests, &mut outcome).await;
+        assert!(outcome.is_success());
+        assert_eq!(*coils.lock().unwrap(), [(2, true), (1, true)]);
+    }
+
+    /// Answers reads with the slave id, only slave 1 is busy.
+    #[derive(Debug, Default)]
+    struct MetersMock {
+        slave: SlaveId,
+    }
+
+    #[async_trait]
+    impl Client for MetersMock {
+        async fn call(&mut self, _request: Request<'_>) -> Result<Response> {
+            if self.slave == 1 {
+                return Ok(Err(ExceptionCode::ServerDeviceBusy));
+            }
+            Ok(Ok(Response::ReadHoldingRegisters(vec![self.slave.into()])))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for MetersMock {
+        fn set_slave(&mut self, slave: Slave) {
+            self.slave = slave.into();
+        }
+    }
+
+    #[tokio::test]
+    async fn read_same_registers_from_slaves() {
+        let mut context = Context::new(Box::<MetersMock>::default());
+        context.set_slave(Slave(7));
+        let results = context
+            .read_same_from_slaves(&[Slave(3), Slave(1), Slave(3), Slave(2)], 0, 1)
+            .await;
+        assert_eq!(
+            results
+                .into_iter()
+                .map(|(slave, result)| (slave, result.unwrap()))
+                .collect::<Vec<_>>(),
+            [
+                (Slave(1), Err(ExceptionCode::ServerDeviceBusy)),
+                (Slave(2), Ok(vec![2])),
+                (Slave(3), Ok(vec![3])),
+            ]
+        );
+        // The previous slave is addressed again.
+        let words = context.read_holding_registers(0, 1).await.unwrap().unwrap();
+        assert_eq!(words, [7]);
+    }
+
+    /// Answers reads with the addresses, fails beyond address 100.
+    #[derive(Debug, Default)]
+    struct AddressesMock {
+        requests: std::sync::Arc<Mutex<Vec<Request<'static>>>>,
+    }
+
+    #[async_trait]
+    impl Client for AddressesMock {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push(request.clone().into_owned());
+            let (Request::ReadHoldingRegisters(addr, cnt) | Request::ReadCoils(addr, cnt)) =
+                request
+            else {
+                return Ok(Err(ExceptionCode::IllegalFunction));
+            };
+            if addr + cnt > 100 {
+                return Ok(Err(ExceptionCode::IllegalDataAddress));
+            }
+            let response = if request.function_code() == FunctionCode::ReadCoils {
+                // Padded to entire bytes.
+                Response::ReadCoils(
+                    (addr..addr + cnt.next_multiple_of(8))
+                        .map(|addr| addr % 2 == 0)
+                        .collect(),
+                )
+            } else {
+                Response::ReadHoldingRegisters((addr..addr + cnt).collect())
+            };
+            Ok(Ok(response))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for AddressesMock {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn read_planned_values() {
+        let client = AddressesMock::default();
+        let requests = std::sync::Arc::clone(&client.requests);
+        let mut context = Context::new(Box::new(client));
+
+        let plan =
+            ReadPlan::holding_registers([(20, 2), (10, 2), (14, 1), (11, 2)]).with_max_gap(2);
+        let values = context.read_planned(&plan).await.unwrap().unwrap();
+        assert_eq!(
+            values.into_iter().collect::<Vec<_>>(),
+            [
+                ((10, 2), vec![10, 11]),
+                ((11, 2), vec![11, 12]),
+                ((14, 1), vec![14]),
+                ((20, 2), vec![20, 21]),
+            ]
+        );
+        assert_eq!(
+            requests.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            [
+                Request::ReadHoldingRegisters(10, 5),
+                Request::ReadHoldingRegisters(20, 2),
+            ]
+        );
+
+        let plan = ReadPlan::coils([(3, 3), (7, 2)]).with_max_quantity(4);
+        let values = context.read_planned(&plan).await.unwrap().unwrap();
+        assert_eq!(values[&(3, 3)], [false, true, false]);
+        assert_eq!(values[&(7, 2)], [false, true]);
+        assert_eq!(
+            requests.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            [Request::ReadCoils(3, 3), Request::ReadCoils(7, 2)]
+        );
+
+        let plan = ReadPlan::holding_registers([(0, 1), (99, 2)]);
+        let result = context.read_planned(&plan).await.unwrap();
+        assert_eq!(result, Err(ExceptionCode::IllegalDataAddress));
+    }
+
+    /// The outcome of an attempt, regardless of the connection.
+    #[derive(Debug)]
+    enum Attempt {
+        Respond,
+        LoseConnection,
+        TimeOut,
+        Stall,
+    }
+
+    #[derive(Debug, Default)]
+    struct Script {
+        attempts: Mutex<std::collections::VecDeque<Attempt>>,
+        connections: std::sync::atomic::AtomicUsize,
+    }
+
+    impl Script {
+        fn expect(&self, attempts: impl IntoIterator<Item = Attempt>) {
+            self.attempts.lock().unwrap().extend(attempts);
+        }
+
+        fn connections(&self) -> usize {
+            self.connections.load(std::sync::atomic::Ordering::Relaxed)
+        }
+    }
+
+    #[derive(Debug)]
+    struct ScriptedConnection(Arc<Script>);
+
+    #[async_trait]
+    impl Client for ScriptedConnection {
+        async fn call(&mut self, _request: Request<'_>) -> Result<Response> {
+            let attempt = self.0.attempts.lock().unwrap().pop_front().unwrap();
+            match attempt {
+                Attempt::Respond => Ok(Ok(Response::ReadHoldingRegisters(vec![1]))),
+                Attempt::LoseConnection => {
+                    Err(io::Error::from(io::ErrorKind::ConnectionReset).into())
+                }
+                Attempt::TimeOut => Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+                Attempt::Stall => std::future::pending().await,
+            }
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for ScriptedConnection {
+        fn set_slave(&mut self, _slave: Slave) {}
+    }
+
+    #[async_trait]
+    impl Reconnect for Arc<Script> {
+        async fn reconnect(&self) -> io::Result<Box<dyn Client>> {
+            self.connections
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok(Box::new(ScriptedConnection(Arc::clone(self))))
+        }
+    }
+
+    #[tokio::test]
+    async fn combine_call_options() {
+        let script = Arc::<Script>::default();
+        let mut context = Context::new(Box::new(ScriptedConnection(Arc::clone(&script))));
+        context.reconnect = Some(Box::new(Arc::clone(&script)));
+        let backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(1), 2);
+        context.set_auto_reconnect(Some(backoff));
+        context.set_retry_policy(Some(RetryPolicy::new(backoff)));
+        let watchdog = Watchdog::new(Duration::from_millis(50));
+        let mut context = watchdog.watch(context);
+        let request = Request::ReadHoldingRegisters(0, 1);
+
+        // Reconnecting doesn't count against the retry policy.
+        script.expect([Attempt::LoseConnection, Attempt::TimeOut, Attempt::Respond]);
+        let (result, meta) = context.call_with_meta(request.clone()).await;
+        assert_eq!(result.unwrap(), Ok(Response::ReadHoldingRegisters(vec![1])));
+        assert_eq!(meta.retries, 2);
+        assert_eq!(script.connections(), 1);
+
+        // The connection is only re-established once per call.
+        script.expect([Attempt::LoseConnection, Attempt::LoseConnection]);
+        let (result, meta) = context.call_with_meta(request.clone()).await;
+        assert!(
+            matches!(result, Err(Error::Transport(err)) if err.kind() == io::ErrorKind::ConnectionReset)
+        );
+        assert_eq!(meta.retries, 1);
+        assert_eq!(script.connections(), 2);
+
+        // Stalled requests are not repeated, but the watchdog reconnects.
+        script.expect([Attempt::TimeOut, Attempt::Stall]);
+        let (result, meta) = context.call_with_meta(request.clone()).await;
+        assert!(
+            matches!(result, Err(Error::Transport(err)) if err.kind() == io::ErrorKind::TimedOut)
+        );
+        assert_eq!(meta.retries, 1);
+        assert_eq!(watchdog.trips(), 1);
+        assert_eq!(script.connections(), 3);
+
+        script.expect([Attempt::Respond]);
+        let (result, meta) = context.call_with_meta(request).await;
+        assert_eq!(result.unwrap(), Ok(Response::ReadHoldingRegisters(vec![1])));
+        assert_eq!(meta.retries, 0);
+        assert!(script.attempts.lock().unwrap().is_empty());
+    }
 }
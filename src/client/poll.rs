@@ -0,0 +1,388 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Periodic polling of values
+//!
+//! A [`Poller`] reads groups of values at individual intervals and
+//! delivers the results as a stream of [`PollUpdate`]s.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "tcp")]
+//! # async fn poll() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::{pin::pin, time::Duration};
+//!
+//! use futures::StreamExt as _;
+//! use tokio_modbus::client::{
+//!     poll::{PollGroup, Poller},
+//!     tcp,
+//! };
+//!
+//! let ctx = tcp::connect("192.168.0.222:502".parse()?).await?;
+//! let poller = Poller::new(ctx)
+//!     .with_group(PollGroup::holding_registers("setpoints", 0x0100, 4, Duration::from_secs(10)))
+//!     .with_group(
+//!         PollGroup::input_registers("temperatures", 0x0200, 8, Duration::from_secs(1))
+//!             .with_deadband(5),
+//!     );
+//! let mut updates = pin!(poller.into_stream());
+//! while let Some(update) = updates.next().await {
+//!     match update.result {
+//!         Ok(Ok(values)) => println!("{}: {values:?}", update.group),
+//!         Ok(Err(exception)) => eprintln!("{}: {exception}", update.group),
+//!         Err(err) => eprintln!("{}: {err}", update.group),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::time::{sleep_until, Instant};
+
+use crate::{frame::*, Result};
+
+use super::{Context, Reader as _};
+
+/// The values of a poll group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollValues {
+    /// Coils or discrete inputs.
+    Bits(Vec<bool>),
+
+    /// Holding or input registers.
+    Registers(Vec<u16>),
+}
+
+impl PollValues {
+    /// Check if any value changed by more than `deadband`.
+    ///
+    /// Bits have changed if they differ.
+    fn exceeds_deadband(&self, previous: &Self, deadband: u16) -> bool {
+        match (self, previous) {
+            (Self::Bits(bits), Self::Bits(previous)) => bits != previous,
+            (Self::Registers(words), Self::Registers(previous)) => {
+                words.len() != previous.len()
+                    || words
+                        .iter()
+                        .zip(previous)
+                        .any(|(word, previous)| word.abs_diff(*previous) > deadband)
+            }
+            _ => true,
+        }
+    }
+}
+
+/// The result of polling a group.
+#[derive(Debug)]
+pub struct PollUpdate {
+    /// The name of the group.
+    pub group: String,
+
+    /// The values or the error.
+    pub result: Result<PollValues>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Table {
+    Coils,
+    DiscreteInputs,
+    HoldingRegisters,
+    InputRegisters,
+}
+
+/// Values that are read periodically with a single request.
+#[derive(Debug, Clone)]
+pub struct PollGroup {
+    name: String,
+    table: Table,
+    addr: Address,
+    cnt: Quantity,
+    interval: Duration,
+    deadband: Option<u16>,
+}
+
+impl PollGroup {
+    fn new(
+        name: impl Into<String>,
+        table: Table,
+        addr: Address,
+        cnt: Quantity,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            table,
+            addr,
+            cnt,
+            interval,
+            deadband: None,
+        }
+    }
+
+    /// Poll coils (0x01).
+    #[must_use]
+    pub fn coils(
+        name: impl Into<String>,
+        addr: Address,
+        cnt: Quantity,
+        interval: Duration,
+    ) -> Self {
+        Self::new(name, Table::Coils, addr, cnt, interval)
+    }
+
+    /// Poll discrete inputs (0x02).
+    #[must_use]
+    pub fn discrete_inputs(
+        name: impl Into<String>,
+        addr: Address,
+        cnt: Quantity,
+        interval: Duration,
+    ) -> Self {
+        Self::new(name, Table::DiscreteInputs, addr, cnt, interval)
+    }
+
+    /// Poll holding registers (0x03).
+    #[must_use]
+    pub fn holding_registers(
+        name: impl Into<String>,
+        addr: Address,
+        cnt: Quantity,
+        interval: Duration,
+    ) -> Self {
+        Self::new(name, Table::HoldingRegisters, addr, cnt, interval)
+    }
+
+    /// Poll input registers (0x04).
+    #[must_use]
+    pub fn input_registers(
+        name: impl Into<String>,
+        addr: Address,
+        cnt: Quantity,
+        interval: Duration,
+    ) -> Self {
+        Self::new(name, Table::InputRegisters, addr, cnt, interval)
+    }
+
+    /// Only report values that changed.
+    ///
+    /// An update is only delivered if any register changed by more than
+    /// `deadband` or any bit changed since the last update. Errors are
+    /// always reported.
+    #[must_use]
+    pub const fn with_deadband(mut self, deadband: u16) -> Self {
+        self.deadband = Some(deadband);
+        self
+    }
+
+    /// The name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn read(&self, context: &mut Context) -> Result<PollValues> {
+        let Self { addr, cnt, .. } = *self;
+        let result = match self.table {
+            Table::Coils => context.read_coils(addr, cnt).await?.map(PollValues::Bits),
+            Table::DiscreteInputs => context
+                .read_discrete_inputs(addr, cnt)
+                .await?
+                .map(PollValues::Bits),
+            Table::HoldingRegisters => context
+                .read_holding_registers(addr, cnt)
+                .await?
+                .map(PollValues::Registers),
+            Table::InputRegisters => context
+                .read_input_registers(addr, cnt)
+                .await?
+                .map(PollValues::Registers),
+        };
+        Ok(result)
+    }
+}
+
+#[derive(Debug)]
+struct Schedule {
+    group: PollGroup,
+    due: Instant,
+    reported: Option<PollValues>,
+}
+
+/// Polls groups of values at individual intervals.
+///
+/// All groups are read one after another with the same context. Each
+/// group is polled immediately and then after each interval. Polls that
+/// are late, e.g. because the device responds slowly, are not repeated
+/// for catching up.
+#[derive(Debug)]
+pub struct Poller {
+    context: Context,
+    schedules: Vec<Schedule>,
+}
+
+impl Poller {
+    /// Poll with the given context.
+    #[must_use]
+    pub fn new(context: Context) -> Self {
+        Self {
+            context,
+            schedules: Vec::new(),
+        }
+    }
+
+    /// Add a group.
+    #[must_use]
+    pub fn with_group(mut self, group: PollGroup) -> Self {
+        self.schedules.push(Schedule {
+            group,
+            due: Instant::now(),
+            reported: None,
+        });
+        self
+    }
+
+    /// Poll the next group that is due.
+    ///
+    /// Waits until the group is due. Returns `None` if the update has
+    /// been suppressed by the deadband or if there are no groups.
+    async fn poll_next_due(&mut self) -> Option<PollUpdate> {
+        let schedule = self
+            .schedules
+            .iter_mut()
+            .min_by_key(|schedule| schedule.due)?;
+        sleep_until(schedule.due).await;
+        let result = schedule.group.read(&mut self.context).await;
+        schedule.due = (schedule.due + schedule.group.interval).max(Instant::now());
+        if let (Ok(Ok(values)), Some(deadband)) = (&result, schedule.group.deadband) {
+            if schedule
+                .reported
+                .as_ref()
+                .is_some_and(|reported| !values.exceeds_deadband(reported, deadband))
+            {
+                return None;
+            }
+            schedule.reported = Some(values.clone());
+        }
+        Some(PollUpdate {
+            group: schedule.group.name.clone(),
+            result,
+        })
+    }
+
+    /// Poll forever and deliver the updates as a stream.
+    ///
+    /// The stream ends immediately if there are no groups.
+    pub fn into_stream(self) -> impl Stream<Item = PollUpdate> + Send {
+        futures_util::stream::unfold(self, |mut poller| async move {
+            if poller.schedules.is_empty() {
+                return None;
+            }
+            loop {
+                if let Some(update) = poller.poll_next_due().await {
+                    return Some((update, poller));
+                }
+            }
+        })
+    }
+
+    /// Stop polling and return the context.
+    #[must_use]
+    pub fn into_context(self) -> Context {
+        self.context
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        pin::pin,
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+    use futures_util::StreamExt as _;
+
+    use crate::{
+        client::Client,
+        slave::{Slave, SlaveContext},
+    };
+
+    use super::*;
+
+    /// Answers reads with a counter that is incremented by each read
+    /// of input registers.
+    #[derive(Debug, Default)]
+    struct CounterMock {
+        counter: Arc<Mutex<u16>>,
+    }
+
+    #[async_trait]
+    impl Client for CounterMock {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            let mut counter = self.counter.lock().unwrap();
+            match request {
+                Request::ReadInputRegisters(_, 1) => {
+                    *counter += 1;
+                    Ok(Ok(Response::ReadInputRegisters(vec![*counter])))
+                }
+                Request::ReadCoils(_, 1) => Ok(Ok(Response::ReadCoils(vec![*counter > 2]))),
+                _ => Ok(Err(ExceptionCode::IllegalDataAddress)),
+            }
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for CounterMock {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn poll_groups() {
+        let client: Box<dyn Client> = Box::<CounterMock>::default();
+        let interval = Duration::from_millis(10);
+        let poller = Poller::new(client.into())
+            .with_group(PollGroup::input_registers("counter", 0, 1, interval))
+            .with_group(PollGroup::coils("flag", 0, 1, interval).with_deadband(0))
+            .with_group(PollGroup::holding_registers(
+                "missing",
+                0,
+                1,
+                Duration::from_secs(60),
+            ));
+        let updates = pin!(poller.into_stream());
+        let updates: Vec<_> = updates.take(8).collect().await;
+
+        let values = |group| {
+            updates
+                .iter()
+                .filter(|update| update.group == group)
+                .map(|update| update.result.as_ref().unwrap().clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values("counter")[..3],
+            [1, 2, 3].map(|counter| Ok(PollValues::Registers(vec![counter])))
+        );
+        // The flag is only reported when it changes.
+        assert_eq!(
+            values("flag"),
+            [false, true].map(|flag| Ok(PollValues::Bits(vec![flag])))
+        );
+        assert_eq!(values("missing"), [Err(ExceptionCode::IllegalDataAddress)]);
+    }
+
+    #[tokio::test]
+    async fn stream_without_groups_ends() {
+        let client: Box<dyn Client> = Box::<CounterMock>::default();
+        let updates = pin!(Poller::new(client.into()).into_stream());
+        assert!(updates.collect::<Vec<_>>().await.is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Automatic recovery from communication errors
+
+use std::{fmt, io, time::Duration};
+
+use async_trait::async_trait;
+
+use super::Client;
+
+/// Recovery from communication errors of a client context.
+///
+/// Modeled after the error recovery modes of _libmodbus_.
+///
+/// The failed request is never repeated, i.e. the error is still
+/// returned to the caller. Only subsequent requests benefit from
+/// the recovery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorRecovery {
+    /// No recovery (default).
+    #[default]
+    None,
+
+    /// Reconnect after transport errors.
+    Link,
+
+    /// Resynchronize the communication after protocol errors and
+    /// timeouts, discarding any stale data that is still in transit.
+    ///
+    /// Contexts that are able to reconnect do so, serial line contexts
+    /// wait until the line is quiet.
+    Protocol,
+
+    /// Both [`Self::Link`] and [`Self::Protocol`].
+    Both,
+}
+
+impl ErrorRecovery {
+    /// Recover from transport errors.
+    #[must_use]
+    pub const fn link(self) -> bool {
+        matches!(self, Self::Link | Self::Both)
+    }
+
+    /// Recover from protocol errors.
+    #[must_use]
+    pub const fn protocol(self) -> bool {
+        matches!(self, Self::Protocol | Self::Both)
+    }
+}
+
+/// Delays between attempts to re-establish a lost connection.
+///
+/// The first attempt is made immediately. The delay before each
+/// subsequent attempt is doubled, starting with the initial delay
+/// and limited by the maximum delay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    max_attempts: usize,
+}
+
+impl Backoff {
+    /// Up to 5 attempts, waiting between 100 ms and 5 s.
+    pub const DEFAULT: Self = Self::new(Duration::from_millis(100), Duration::from_secs(5), 5);
+
+    /// Create a backoff with up to `max_attempts` attempts.
+    #[must_use]
+    pub const fn new(initial_delay: Duration, max_delay: Duration, max_attempts: usize) -> Self {
+        Self {
+            initial_delay,
+            max_delay,
+            max_attempts,
+        }
+    }
+
+    /// The maximum number of attempts.
+    #[must_use]
+    pub const fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// The delays before each attempt.
+    pub(crate) fn delays(self) -> impl Iterator<Item = Duration> {
+        let mut delay = Duration::ZERO;
+        (0..self.max_attempts).map(move |attempt| {
+            if attempt > 0 {
+                delay = (delay * 2).clamp(self.initial_delay, self.max_delay);
+            }
+            delay
+        })
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Checks if the connection has been lost, e.g. closed or reset by the peer.
+pub(crate) fn is_connection_lost(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// Establishes a new connection to replace a broken one.
+#[async_trait]
+pub(crate) trait Reconnect: fmt::Debug + Send + Sync {
+    async fn reconnect(&self) -> io::Result<Box<dyn Client>>;
+
+    /// Checks if the connection needs to be re-established after an error.
+    fn is_connection_lost(&self, err: &io::Error) -> bool {
+        is_connection_lost(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_delays() {
+        let backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(500), 6);
+        assert_eq!(
+            backoff.delays().collect::<Vec<_>>(),
+            [0, 100, 200, 400, 500, 500].map(Duration::from_millis)
+        );
+        assert_eq!(
+            Backoff::new(Duration::ZERO, Duration::ZERO, 0)
+                .delays()
+                .count(),
+            0
+        );
+    }
+}
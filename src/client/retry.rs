@@ -0,0 +1,212 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Automatic repetition of failed requests
+
+use std::{fmt, io, sync::Arc};
+
+use crate::Error;
+
+use super::Backoff;
+
+type RetryIf = dyn Fn(&Error) -> bool + Send + Sync;
+
+/// Repeats requests that failed with a transient error.
+///
+/// The `backoff` determines the maximum number of attempts and the
+/// delays between them. Only errors are repeated, exception responses
+/// are always returned immediately.
+///
+/// By default only transient errors are repeated, see
+/// [`Self::is_transient()`]. The classification of errors could be
+/// customized with [`Self::retry_if()`].
+///
+/// Only enable retries if repeating requests is safe, i.e. if a
+/// failed request might have already been executed by the device.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    backoff: Backoff,
+    retry_if: Arc<RetryIf>,
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("backoff", &self.backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetryPolicy {
+    /// Repeat requests that failed with a transient error.
+    #[must_use]
+    pub fn new(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            retry_if: Arc::new(Self::is_transient),
+        }
+    }
+
+    /// Repeat requests that failed with an error for which
+    /// `retry_if` returns `true`.
+    #[must_use]
+    pub fn retry_if(mut self, retry_if: impl Fn(&Error) -> bool + Send + Sync + 'static) -> Self {
+        self.retry_if = Arc::new(retry_if);
+        self
+    }
+
+    /// The maximum number of attempts and the delays between them.
+    #[must_use]
+    pub const fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    /// Checks if the error could disappear when repeating the request.
+    ///
+    /// Transient errors are timeouts, corrupted data like checksum
+    /// errors, and responses that don't match the request.
+    #[must_use]
+    pub fn is_transient(err: &Error) -> bool {
+        match err {
+            Error::Transport(err) => {
+                matches!(
+                    err.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::InvalidData
+                )
+            }
+            Error::Protocol(_) | Error::Timeout(_) => true,
+            Error::NoResponse(_) => false,
+        }
+    }
+
+    pub(super) fn should_retry(&self, err: &Error) -> bool {
+        (self.retry_if)(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use crate::{
+        client::{Backpressure, Client, Context},
+        slave::SlaveContext,
+        ExceptionCode, ProtocolError, Request, Response, Result, Slave,
+    };
+
+    use super::*;
+
+    /// Fails with the given errors before answering requests.
+    #[derive(Debug)]
+    struct Flaky {
+        errors: Vec<io::ErrorKind>,
+    }
+
+    #[async_trait]
+    impl Client for Flaky {
+        async fn call(&mut self, _request: Request<'_>) -> Result<Response> {
+            if let Some(kind) = self.errors.pop() {
+                return Err(io::Error::from(kind).into());
+            }
+            Ok(Err(ExceptionCode::ServerDeviceBusy))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for Flaky {
+        fn set_slave(&mut self, _slave: Slave) {}
+    }
+
+    fn backoff(max_attempts: usize) -> Backoff {
+        Backoff::new(
+            Duration::from_millis(1),
+            Duration::from_millis(1),
+            max_attempts,
+        )
+    }
+
+    #[tokio::test]
+    async fn repeat_transient_errors() {
+        let mut context = Context::new(Box::new(Flaky {
+            errors: vec![io::ErrorKind::InvalidData, io::ErrorKind::TimedOut],
+        }));
+        context.set_retry_policy(Some(RetryPolicy::new(backoff(3))));
+        let (result, meta) = context
+            .call_with_meta(Request::ReadHoldingRegisters(0, 1))
+            .await;
+        // Exceptions are not repeated.
+        assert_eq!(result.unwrap(), Err(ExceptionCode::ServerDeviceBusy));
+        assert_eq!(meta.retries, 2);
+    }
+
+    #[tokio::test]
+    async fn give_up_after_max_attempts() {
+        let mut context = Context::new(Box::new(Flaky {
+            errors: vec![io::ErrorKind::TimedOut; 3],
+        }));
+        context.set_retry_policy(Some(RetryPolicy::new(backoff(2))));
+        let (result, meta) = context
+            .call_with_meta(Request::ReadHoldingRegisters(0, 1))
+            .await;
+        assert!(
+            matches!(result, Err(Error::Transport(err)) if err.kind() == io::ErrorKind::TimedOut)
+        );
+        assert_eq!(meta.retries, 1);
+
+        // Permanent errors are not repeated.
+        let mut context = Context::new(Box::new(Flaky {
+            errors: vec![io::ErrorKind::ConnectionRefused, io::ErrorKind::TimedOut],
+        }));
+        context.set_retry_policy(Some(RetryPolicy::new(backoff(5))));
+        let (result, meta) = context
+            .call_with_meta(Request::ReadHoldingRegisters(0, 1))
+            .await;
+        assert!(
+            matches!(result, Err(Error::Transport(err)) if err.kind() == io::ErrorKind::ConnectionRefused)
+        );
+        assert_eq!(meta.retries, 1);
+    }
+
+    #[tokio::test]
+    async fn no_retries_while_backpressure_is_engaged() {
+        let backpressure = Backpressure::new(Duration::from_secs(60), 0).min_requests(1);
+        let mut context = backpressure.monitor(Context::new(Box::new(Flaky {
+            errors: vec![io::ErrorKind::TimedOut; 3],
+        })));
+        context.set_retry_policy(Some(RetryPolicy::new(backoff(5))));
+        let (result, meta) = context
+            .call_with_meta(Request::ReadHoldingRegisters(0, 1))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(meta.retries, 0);
+        assert!(backpressure.is_engaged());
+    }
+
+    #[test]
+    fn classify_transient_errors() {
+        assert!(RetryPolicy::is_transient(&Error::Timeout(
+            Duration::from_secs(1)
+        )));
+        assert!(RetryPolicy::is_transient(
+            &io::Error::from(io::ErrorKind::InvalidData).into()
+        ));
+        assert!(RetryPolicy::is_transient(
+            &ProtocolError::ResponseMismatch {
+                message: String::new(),
+                result: Ok(crate::Response::ReadCoils(vec![])),
+            }
+            .into()
+        ));
+        assert!(!RetryPolicy::is_transient(
+            &io::Error::from(io::ErrorKind::ConnectionRefused).into()
+        ));
+
+        let policy = RetryPolicy::new(Backoff::DEFAULT).retry_if(|_| false);
+        assert!(!policy.should_retry(&Error::Timeout(Duration::from_secs(1))));
+    }
+}
@@ -3,8 +3,16 @@
 
 //! RTU client connections
 
+use std::{fmt, sync::Arc};
+
 use tokio::io::{AsyncRead, AsyncWrite};
 
+use crate::{
+    codec::rtu::ClientCodec,
+    tap::{FrameTap, Tap},
+    timing::{FrameTiming, TimedIo},
+};
+
 use super::*;
 
 /// Connect to no particular Modbus slave device for sending
@@ -22,7 +30,235 @@ where
     T: AsyncRead + AsyncWrite + Debug + Unpin + Send + 'static,
 {
     let client = crate::service::rtu::Client::new(transport, slave);
-    Context {
-        client: Box::new(client),
+    Context::new_serial(Box::new(client), slave)
+}
+
+/// Connect to any kind of Modbus slave device and enforce the timing
+/// of the serial line.
+///
+/// Response frames that contain an inter-character gap are discarded
+/// and requests are only sent after the line has been silent for the
+/// inter-frame delay, see [`FrameTiming`].
+pub fn attach_slave_with_timing<T>(transport: T, slave: Slave, timing: FrameTiming) -> Context
+where
+    T: AsyncRead + AsyncWrite + Debug + Unpin + Send + 'static,
+{
+    let gaps = Arc::default();
+    let client = crate::service::rtu::Client::with_codec(
+        TimedIo::new(transport, timing, Arc::clone(&gaps)),
+        ClientCodec::with_frame_gaps(gaps),
+        slave,
+    );
+    Context::new_serial(Box::new(client), slave)
+}
+
+/// Connect to any kind of Modbus slave device and pass a copy of all
+/// sent and received frames to `tap`.
+pub fn attach_slave_with_tap<T>(transport: T, slave: Slave, tap: impl FrameTap + 'static) -> Context
+where
+    T: AsyncRead + AsyncWrite + Debug + Unpin + Send + 'static,
+{
+    let codec = ClientCodec {
+        tap: Some(Tap::new(tap)),
+        ..Default::default()
+    };
+    let client = crate::service::rtu::Client::with_codec(transport, codec, slave);
+    Context::new_serial(Box::new(client), slave)
+}
+
+/// Connect to a Modbus slave device on a serial port that might disappear.
+///
+/// USB serial adapters are removed from the system when unplugged or
+/// reset and might re-appear with a different device path. Errors that
+/// indicate a vanished device trigger a reopen of the port with `open`,
+/// retried according to the `backoff`, before the request is repeated.
+///
+/// The `open` function is free to locate the adapter anew, e.g. by its
+/// USB vendor and product id if `tokio_serial::available_ports()` is
+/// supported on the platform.
+///
+/// Fails if the port could not be opened initially.
+pub fn attach_reopening<T, F>(open: F, slave: Slave, backoff: Backoff) -> io::Result<Context>
+where
+    T: AsyncRead + AsyncWrite + Debug + Unpin + Send + 'static,
+    F: Fn() -> io::Result<T> + Send + Sync + 'static,
+{
+    let mut context = attach_slave(open()?, slave);
+    context.reconnect = Some(Box::new(Reopen { open, slave }));
+    context.set_auto_reconnect(Some(backoff));
+    Ok(context)
+}
+
+struct Reopen<F> {
+    open: F,
+    slave: Slave,
+}
+
+impl<F> Debug for Reopen<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reopen")
+            .field("slave", &self.slave)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<T, F> Reconnect for Reopen<F>
+where
+    T: AsyncRead + AsyncWrite + Debug + Unpin + Send + 'static,
+    F: Fn() -> io::Result<T> + Send + Sync,
+{
+    async fn reconnect(&self) -> io::Result<Box<dyn Client>> {
+        let transport = (self.open)()?;
+        log::info!("Reopened serial port: {transport:?}");
+        Ok(Box::new(crate::service::rtu::Client::new(
+            transport, self.slave,
+        )))
+    }
+
+    fn is_connection_lost(&self, err: &io::Error) -> bool {
+        // Serial ports report a vanished device inconsistently across
+        // platforms, e.g. as generic I/O errors or as not found. Only
+        // errors that are caused by the communication on the line are
+        // excluded.
+        !matches!(
+            err.kind(),
+            io::ErrorKind::TimedOut | io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _, DuplexStream};
+
+    use crate::mutex::lock;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn reopen_vanished_port_and_repeat_request() {
+        let (vanishing, server) = tokio::io::duplex(256);
+        drop(server);
+        let (reopened, mut server) = tokio::io::duplex(256);
+        let ports = Mutex::new(vec![reopened, vanishing]);
+        let open = move || -> io::Result<DuplexStream> {
+            lock(&ports)
+                .pop()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        };
+        let mut context = attach_reopening(
+            open,
+            Slave(1),
+            Backoff::new(Duration::from_millis(1), Duration::from_millis(1), 2),
+        )
+        .unwrap();
+
+        let responder = tokio::spawn(async move {
+            let mut request = [0; 8];
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A]);
+            server
+                .write_all(&[0x01, 0x03, 0x02, 0x12, 0x34, 0xB5, 0x33])
+                .await
+                .unwrap();
+            server
+        });
+        let (result, meta) = context
+            .call_with_meta(Request::ReadHoldingRegisters(0, 1))
+            .await;
+        assert_eq!(
+            result.unwrap(),
+            Ok(Response::ReadHoldingRegisters(vec![0x1234]))
+        );
+        assert_eq!(meta.retries, 1);
+        drop(responder.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn delay_requests_after_broadcasts() {
+        let (transport, mut server) = tokio::io::duplex(256);
+        let mut context = attach(transport);
+        context.set_turnaround_delay(Duration::from_millis(50));
+
+        let responder = tokio::spawn(async move {
+            let mut request = [0; 8];
+            server.read_exact(&mut request).await.unwrap();
+            let broadcast_received = tokio::time::Instant::now();
+            server.read_exact(&mut request).await.unwrap();
+            assert!(broadcast_received.elapsed() >= Duration::from_millis(50));
+            server
+                .write_all(&[0x01, 0x03, 0x02, 0x12, 0x34, 0xB5, 0x33])
+                .await
+                .unwrap();
+            server
+        });
+        // The broadcast request is not answered.
+        context.write_single_register(0, 0).await.unwrap().unwrap();
+        context.set_slave(Slave(1));
+        let response = context.read_holding_registers(0, 1).await.unwrap();
+        assert_eq!(response, Ok(vec![0x1234]));
+        drop(responder.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn discard_responses_with_inter_char_gaps() {
+        let (transport, mut server) = tokio::io::duplex(256);
+        let timing = FrameTiming::new(Duration::from_millis(20), Duration::from_millis(100));
+        let mut context = attach_slave_with_timing(transport, Slave(1), timing);
+
+        let responder = tokio::spawn(async move {
+            let mut request = [0; 8];
+            server.read_exact(&mut request).await.unwrap();
+            let interrupted = [0x01, 0x03, 0x02, 0x56, 0x78, 0x87, 0xC6];
+            server.write_all(&interrupted[..3]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            server.write_all(&interrupted[3..]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            server
+                .write_all(&[0x01, 0x03, 0x02, 0x12, 0x34, 0xB5, 0x33])
+                .await
+                .unwrap();
+            server
+        });
+        let response = context.read_holding_registers(0, 1).await.unwrap();
+        assert_eq!(response, Ok(vec![0x1234]));
+        drop(responder.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn resynchronize_after_protocol_error() {
+        let (transport, mut server) = tokio::io::duplex(256);
+        let mut context = attach_slave(transport, Slave(1));
+        context.set_error_recovery(ErrorRecovery::Protocol);
+
+        let responder = tokio::spawn(async move {
+            let mut request = [0; 8];
+            server.read_exact(&mut request).await.unwrap();
+            // Response from another slave
+            server
+                .write_all(&[0x02, 0x03, 0x02, 0x00, 0x01, 0x3D, 0x84])
+                .await
+                .unwrap();
+            // Stale data that is still in transit
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            server
+                .write_all(&[0x01, 0x03, 0x02, 0x00, 0x01, 0x79, 0x84])
+                .await
+                .unwrap();
+            server.read_exact(&mut request).await.unwrap();
+            server
+                .write_all(&[0x01, 0x03, 0x02, 0x00, 0x02, 0x39, 0x85])
+                .await
+                .unwrap();
+            server
+        });
+        let err = context.read_holding_registers(0, 1).await.unwrap_err();
+        assert!(matches!(err, Error::Protocol(_)));
+        let response = context.read_holding_registers(0, 1).await.unwrap();
+        assert_eq!(response, Ok(vec![2]));
+        drop(responder.await.unwrap());
     }
 }
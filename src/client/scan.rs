@@ -0,0 +1,298 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Discovery of units on a bus or behind a gateway and of the
+//! readable addresses of a device
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "tcp")]
+//! # async fn scan() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::time::Duration;
+//!
+//! use tokio_modbus::{
+//!     client::{
+//!         scan::{scan_registers, scan_units, Probe},
+//!         tags::RegisterType,
+//!         tcp,
+//!     },
+//!     prelude::*,
+//! };
+//!
+//! // A gateway to an RS-485 bus.
+//! let mut ctx = tcp::connect("192.168.0.222:502".parse()?).await?;
+//! let timeout = Duration::from_millis(100);
+//! for unit in scan_units(&mut ctx, 1..=247, &Probe::ReportServerId, timeout).await {
+//!     println!("{} answered after {:?}", unit.slave, unit.latency);
+//!     ctx.set_slave(unit.slave);
+//!     let ranges = scan_registers(&mut ctx, RegisterType::HoldingRegister, 0..=0xFFFF).await??;
+//!     println!("Holding registers: {ranges:?}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{ops::RangeInclusive, time::Duration};
+
+use crate::{
+    slave::{SlaveContext as _, SlaveId},
+    Address, ExceptionCode, Quantity, Request, Result, Slave,
+};
+
+use super::{tags::RegisterType, Client as _, Context};
+
+/// The maximum number of bits that could be read by a single request.
+const MAX_READ_BITS: Quantity = 2000;
+
+/// The maximum number of registers that could be read by a single request.
+const MAX_READ_REGISTERS: Quantity = 125;
+
+/// The request that is sent to each unit by [`scan_units()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Probe {
+    /// Report Server ID (0x11).
+    ReportServerId,
+
+    /// A custom request, e.g. reading a holding register that is
+    /// known to exist on the expected devices.
+    Request(Request<'static>),
+}
+
+impl Probe {
+    fn request(&self) -> Request<'static> {
+        match self {
+            Self::ReportServerId => Request::ReportServerId,
+            Self::Request(request) => request.clone(),
+        }
+    }
+}
+
+/// A unit that answered the probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unit {
+    /// The slave or unit ID.
+    pub slave: Slave,
+
+    /// The round-trip time of the probe request.
+    pub latency: Duration,
+
+    /// The exception if the unit rejected the probe request.
+    ///
+    /// The unit is present, but might not support the request.
+    pub exception: Option<ExceptionCode>,
+}
+
+/// Send the probe request to each unit in `range`, one after another,
+/// and collect the units that answered within `timeout`.
+///
+/// Units that answer with an exception are considered present. The
+/// broadcast address 0 is skipped, because broadcasts are never
+/// answered. Afterwards the context addresses the previous slave again.
+///
+/// Failures of individual units are logged and don't abort the scan.
+/// Responses that arrive after the timeout might be mistaken for the
+/// response to the next probe on some transports, i.e. the timeout
+/// should be chosen generously.
+pub async fn scan_units(
+    ctx: &mut Context,
+    range: RangeInclusive<SlaveId>,
+    probe: &Probe,
+    timeout: Duration,
+) -> Vec<Unit> {
+    let previous = ctx.slave;
+    let mut units = Vec::new();
+    for slave in range.map(Slave).filter(|slave| !slave.is_broadcast()) {
+        ctx.set_slave(slave);
+        let call = ctx.call_with_meta(probe.request());
+        let Ok((result, meta)) = tokio::time::timeout(timeout, call).await else {
+            log::debug!("Unit {slave} did not answer within {timeout:?}");
+            continue;
+        };
+        let exception = match result {
+            Ok(Ok(_)) => None,
+            Ok(Err(exception)) => Some(exception),
+            Err(err) => {
+                log::debug!("Unit {slave} did not answer: {err}");
+                continue;
+            }
+        };
+        units.push(Unit {
+            slave,
+            latency: meta.rtt,
+            exception,
+        });
+    }
+    if let Some(previous) = previous {
+        ctx.set_slave(previous);
+    }
+    units
+}
+
+/// Find the contiguous ranges of readable addresses of a table within
+/// `addresses`.
+///
+/// The addresses are read in blocks of the maximum size of a request.
+/// Blocks that are rejected with [`ExceptionCode::IllegalDataAddress`]
+/// are split in halves until all readable addresses have been found,
+/// i.e. large readable regions are discovered with few requests.
+///
+/// Other exceptions abort the scan, e.g. [`ExceptionCode::IllegalFunction`]
+/// if the device doesn't support the table at all.
+pub async fn scan_registers(
+    ctx: &mut Context,
+    register_type: RegisterType,
+    addresses: RangeInclusive<Address>,
+) -> Result<Vec<RangeInclusive<Address>>> {
+    let max_cnt = match register_type {
+        RegisterType::Coil | RegisterType::DiscreteInput => MAX_READ_BITS,
+        RegisterType::HoldingRegister | RegisterType::InputRegister => MAX_READ_REGISTERS,
+    };
+    let mut ranges: Vec<RangeInclusive<Address>> = Vec::new();
+    // Pending blocks in reverse order, i.e. the next block is on top.
+    let mut blocks = Vec::new();
+    let (first, last) = addresses.into_inner();
+    let mut start = first;
+    while start <= last {
+        let end = start.saturating_add(max_cnt - 1).min(last);
+        blocks.push((start, end));
+        let Some(next) = end.checked_add(1) else {
+            break;
+        };
+        start = next;
+    }
+    blocks.reverse();
+    while let Some((start, end)) = blocks.pop() {
+        let cnt = end - start + 1;
+        let request = match register_type {
+            RegisterType::Coil => Request::ReadCoils(start, cnt),
+            RegisterType::DiscreteInput => Request::ReadDiscreteInputs(start, cnt),
+            RegisterType::HoldingRegister => Request::ReadHoldingRegisters(start, cnt),
+            RegisterType::InputRegister => Request::ReadInputRegisters(start, cnt),
+        };
+        match ctx.call(request).await? {
+            Ok(_) => match ranges.last_mut() {
+                Some(range) if range.end().checked_add(1) == Some(start) => {
+                    *range = *range.start()..=end;
+                }
+                _ => ranges.push(start..=end),
+            },
+            Err(ExceptionCode::IllegalDataAddress) => {
+                if cnt > 1 {
+                    let mid = start + cnt / 2;
+                    blocks.push((mid, end));
+                    blocks.push((start, mid - 1));
+                }
+            }
+            Err(exception) => return Ok(Err(exception)),
+        }
+    }
+    Ok(Ok(ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use async_trait::async_trait;
+
+    use crate::{client::Client, slave::SlaveContext, Response, Result};
+
+    use super::*;
+
+    /// Answers for slave 2, rejects requests for slave 3, never answers
+    /// for slave 4, and fails for all other slaves.
+    #[derive(Debug)]
+    struct Bus {
+        slave: Slave,
+    }
+
+    #[async_trait]
+    impl Client for Bus {
+        async fn call(&mut self, _request: Request<'_>) -> Result<Response> {
+            match self.slave.0 {
+                2 => Ok(Ok(Response::ReportServerId(2, true, vec![]))),
+                3 => Ok(Err(ExceptionCode::IllegalFunction)),
+                4 => std::future::pending().await,
+                _ => Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+            }
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for Bus {
+        fn set_slave(&mut self, slave: Slave) {
+            self.slave = slave;
+        }
+    }
+
+    #[tokio::test]
+    async fn scan_responding_units() {
+        let mut ctx = Context::new(Box::new(Bus { slave: Slave(0) }));
+        ctx.set_slave(Slave(7));
+        let units = scan_units(
+            &mut ctx,
+            0..=5,
+            &Probe::ReportServerId,
+            Duration::from_millis(10),
+        )
+        .await;
+        let found: Vec<_> = units
+            .iter()
+            .map(|unit| (unit.slave, unit.exception))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Slave(2), None),
+                (Slave(3), Some(ExceptionCode::IllegalFunction))
+            ]
+        );
+        assert_eq!(ctx.slave, Some(Slave(7)));
+    }
+
+    /// Provides the holding registers 10..=19 and 300..=499.
+    #[derive(Debug)]
+    struct Device;
+
+    #[async_trait]
+    impl Client for Device {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            let Request::ReadHoldingRegisters(addr, cnt) = request else {
+                return Ok(Err(ExceptionCode::IllegalFunction));
+            };
+            let (first, last) = (u32::from(addr), u32::from(addr) + u32::from(cnt) - 1);
+            if (10..=19).contains(&first) && last <= 19
+                || (300..=499).contains(&first) && last <= 499
+            {
+                Ok(Ok(Response::ReadHoldingRegisters(vec![0; cnt.into()])))
+            } else {
+                Ok(Err(ExceptionCode::IllegalDataAddress))
+            }
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for Device {
+        fn set_slave(&mut self, _slave: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn scan_readable_registers() {
+        let mut ctx = Context::new(Box::new(Device));
+        let ranges = scan_registers(&mut ctx, RegisterType::HoldingRegister, 0..=999)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ranges, [10..=19, 300..=499]);
+
+        let result = scan_registers(&mut ctx, RegisterType::InputRegister, 0..=999).await;
+        assert_eq!(result.unwrap(), Err(ExceptionCode::IllegalFunction));
+    }
+}
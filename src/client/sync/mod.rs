@@ -9,18 +9,27 @@ pub mod rtu;
 #[cfg(feature = "tcp-sync")]
 pub mod tcp;
 
-use std::{future::Future, io, time::Duration};
+mod runtime;
+pub use self::runtime::Executor;
+use self::runtime::Runtime;
+
+use std::{collections::BTreeMap, future::Future, io, sync::Arc, time::Duration};
 
 use futures_util::future::Either;
 
-use crate::{frame::*, Result, Slave};
+use crate::{frame::*, ConnectionStats, Metrics, Result, Slave};
 
 use super::{
-    Client as AsyncClient, Context as AsyncContext, Reader as _, SlaveContext, Writer as _,
+    Backoff, BusCounters, Client as AsyncClient, Context as AsyncContext, DiagnosticsCounter,
+    ErrorRecovery, Reader as _, RetryPolicy, SlaveContext, Writer as _,
 };
 
+/// Run the task to completion or cancel it after the timeout.
+///
+/// Cancelling drops the task. The client is responsible for recovering
+/// from the cancelled call before the next call.
 fn block_on_with_timeout<T, E>(
-    runtime: &tokio::runtime::Runtime,
+    runtime: &Runtime,
     timeout: Option<Duration>,
     task: impl Future<Output = std::result::Result<T, E>>,
 ) -> std::result::Result<T, E>
@@ -61,6 +70,35 @@ pub trait Reader: Client {
         write_addr: Address,
         write_data: &[Word],
     ) -> Result<Vec<Word>>;
+    fn read_fifo_queue(&mut self, addr: Address) -> Result<Vec<Word>> {
+        super::fifo_queue(self.call(Request::ReadFifoQueue(addr))?)
+    }
+    fn read_exception_status(&mut self) -> Result<u8> {
+        super::exception_status(self.call(Request::ReadExceptionStatus)?)
+    }
+    fn read_device_identification(
+        &mut self,
+        read_device_id_code: ReadDeviceIdCode,
+    ) -> Result<BTreeMap<DeviceIdObjectId, Vec<u8>>> {
+        let mut objects = BTreeMap::new();
+        let mut object_id = DeviceIdObjectId::VendorName;
+        loop {
+            let request = Request::ReadDeviceIdentification(read_device_id_code, object_id);
+            let response = self.call(request)?;
+            match super::collect_device_identification(&mut objects, object_id, response)? {
+                Ok(Some(next_object_id)) => object_id = next_object_id,
+                Ok(None) => return Ok(Ok(objects)),
+                Err(exception) => return Ok(Err(exception)),
+            }
+        }
+    }
+    fn read_device_identification_object(
+        &mut self,
+        object_id: DeviceIdObjectId,
+    ) -> Result<Vec<u8>> {
+        let request = Request::ReadDeviceIdentification(ReadDeviceIdCode::Specific, object_id);
+        super::device_identification_object(object_id, self.call(request)?)
+    }
 }
 
 /// A transport independent synchronous writer trait.
@@ -78,7 +116,7 @@ pub trait Writer: Client {
 /// A synchronous Modbus client context.
 #[derive(Debug)]
 pub struct Context {
-    runtime: tokio::runtime::Runtime,
+    runtime: Runtime,
     async_ctx: AsyncContext,
     timeout: Option<Duration>,
 }
@@ -91,6 +129,11 @@ impl Context {
 
     /// Sets a timeout duration for all subsequent operations.
     ///
+    /// Operations that time out are cancelled and fail with
+    /// [`io::ErrorKind::TimedOut`]. The connection is resynchronized
+    /// before the next operation, i.e. late responses to cancelled
+    /// requests are discarded.
+    ///
     /// The timeout is disabled by passing `None`.
     pub fn set_timeout(&mut self, duration: impl Into<Option<Duration>>) {
         self.timeout = duration.into();
@@ -100,6 +143,129 @@ impl Context {
     pub fn reset_timeout(&mut self) {
         self.timeout = None;
     }
+
+    /// Returns the current error recovery mode.
+    pub const fn error_recovery(&self) -> ErrorRecovery {
+        self.async_ctx.error_recovery()
+    }
+
+    /// Sets the error recovery mode for all subsequent operations.
+    ///
+    /// See also [`AsyncContext::set_error_recovery()`].
+    pub fn set_error_recovery(&mut self, error_recovery: ErrorRecovery) {
+        self.async_ctx.set_error_recovery(error_recovery);
+    }
+
+    /// Enables or disables transparent reconnecting for all subsequent operations.
+    ///
+    /// See also [`AsyncContext::set_auto_reconnect()`].
+    pub fn set_auto_reconnect(&mut self, backoff: Option<Backoff>) {
+        self.async_ctx.set_auto_reconnect(backoff);
+    }
+
+    /// Sets the policy for repeating failed requests.
+    ///
+    /// See also [`AsyncContext::set_retry_policy()`].
+    pub fn set_retry_policy(&mut self, retry_policy: Option<RetryPolicy>) {
+        self.async_ctx.set_retry_policy(retry_policy);
+    }
+
+    /// Sets the delay after broadcast requests before sending the next request.
+    ///
+    /// See also [`AsyncContext::set_turnaround_delay()`].
+    pub fn set_turnaround_delay(&mut self, delay: impl Into<Option<Duration>>) {
+        self.async_ctx.set_turnaround_delay(delay);
+    }
+
+    /// Sets a label that identifies the connection.
+    ///
+    /// See also [`AsyncContext::set_label()`].
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.async_ctx.set_label(label);
+    }
+
+    /// Reports the activity of all subsequent requests to `metrics`.
+    ///
+    /// See also [`AsyncContext::set_metrics()`].
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn Metrics>>) {
+        self.async_ctx.set_metrics(metrics);
+    }
+
+    /// Enables or disables the strict validation of responses.
+    ///
+    /// See also [`AsyncContext::set_strict_validation()`].
+    pub fn set_strict_validation(&mut self, strict_validation: bool) {
+        self.async_ctx.set_strict_validation(strict_validation);
+    }
+
+    /// The label that identifies the connection.
+    pub fn label(&self) -> Option<&str> {
+        self.async_ctx.label()
+    }
+
+    /// The number of stale responses that have been discarded,
+    /// e.g. late responses to requests that have timed out.
+    ///
+    /// See also [`AsyncClient::stale_responses()`].
+    pub fn stale_responses(&self) -> u64 {
+        self.async_ctx.stale_responses()
+    }
+
+    /// The traffic of the current connection.
+    ///
+    /// See also [`AsyncClient::connection_stats()`].
+    pub fn connection_stats(&self) -> Option<ConnectionStats> {
+        self.async_ctx.connection_stats()
+    }
+
+    /// Execute a diagnostics sub-function (0x08, Serial Line only).
+    ///
+    /// See also [`AsyncContext::diagnostics()`].
+    pub fn diagnostics(
+        &mut self,
+        sub_function: DiagnosticsSubFunction,
+        data: &[Word],
+    ) -> Result<Vec<Word>> {
+        block_on_with_timeout(
+            &self.runtime,
+            self.timeout,
+            self.async_ctx.diagnostics(sub_function, data),
+        )
+    }
+
+    /// Loopback test with the Return Query Data sub-function of
+    /// diagnostics (0x08, Serial Line only).
+    ///
+    /// See also [`AsyncContext::return_query_data()`].
+    pub fn return_query_data(&mut self, data: &[Word]) -> Result<()> {
+        block_on_with_timeout(
+            &self.runtime,
+            self.timeout,
+            self.async_ctx.return_query_data(data),
+        )
+    }
+
+    /// Read a counter with a diagnostics sub-function (0x08, Serial Line only).
+    ///
+    /// See also [`AsyncContext::read_diagnostics_counter()`].
+    pub fn read_diagnostics_counter(&mut self, counter: DiagnosticsCounter) -> Result<u16> {
+        block_on_with_timeout(
+            &self.runtime,
+            self.timeout,
+            self.async_ctx.read_diagnostics_counter(counter),
+        )
+    }
+
+    /// Read all counters of a serial line device (0x08, Serial Line only).
+    ///
+    /// See also [`AsyncContext::read_bus_counters()`].
+    pub fn read_bus_counters(&mut self) -> Result<BusCounters> {
+        block_on_with_timeout(
+            &self.runtime,
+            self.timeout,
+            self.async_ctx.read_bus_counters(),
+        )
+    }
 }
 
 impl Client for Context {
@@ -161,6 +327,45 @@ impl Reader for Context {
                 .read_write_multiple_registers(read_addr, read_count, write_addr, write_data),
         )
     }
+
+    fn read_fifo_queue(&mut self, addr: Address) -> Result<Vec<Word>> {
+        block_on_with_timeout(
+            &self.runtime,
+            self.timeout,
+            self.async_ctx.read_fifo_queue(addr),
+        )
+    }
+
+    fn read_exception_status(&mut self) -> Result<u8> {
+        block_on_with_timeout(
+            &self.runtime,
+            self.timeout,
+            self.async_ctx.read_exception_status(),
+        )
+    }
+
+    fn read_device_identification(
+        &mut self,
+        read_device_id_code: ReadDeviceIdCode,
+    ) -> Result<BTreeMap<DeviceIdObjectId, Vec<u8>>> {
+        block_on_with_timeout(
+            &self.runtime,
+            self.timeout,
+            self.async_ctx
+                .read_device_identification(read_device_id_code),
+        )
+    }
+
+    fn read_device_identification_object(
+        &mut self,
+        object_id: DeviceIdObjectId,
+    ) -> Result<Vec<u8>> {
+        block_on_with_timeout(
+            &self.runtime,
+            self.timeout,
+            self.async_ctx.read_device_identification_object(object_id),
+        )
+    }
 }
 
 impl Writer for Context {
@@ -3,11 +3,11 @@
 
 use std::{io, time::Duration};
 
-use super::{block_on_with_timeout, Context};
+use super::{block_on_with_timeout, Context, Executor};
 
-use tokio_serial::{SerialPortBuilder, SerialStream};
+use tokio_serial::SerialPortBuilder;
 
-use crate::Slave;
+use crate::{client::Backoff, serial, Slave};
 
 /// Connect to no particular _Modbus_ slave device for sending
 /// broadcast messages.
@@ -35,12 +35,46 @@ pub fn connect_slave_with_timeout(
     slave: Slave,
     timeout: Option<Duration>,
 ) -> io::Result<Context> {
-    let runtime = tokio::runtime::Builder::new_current_thread()
-        .enable_io()
-        .enable_time()
-        .build()?;
+    connect_slave_with_executor(builder, slave, timeout, &Executor::Dedicated)
+}
+
+/// Connect to any kind of _Modbus_ slave device and retry while
+/// the serial port is busy.
+///
+/// See also [`serial::open_retrying_busy()`].
+pub fn connect_slave_retrying_busy(
+    builder: &SerialPortBuilder,
+    slave: Slave,
+    backoff: Backoff,
+) -> io::Result<Context> {
+    let runtime = Executor::Dedicated.runtime()?;
+    let serial = block_on_with_timeout(&runtime, None, async {
+        serial::open_retrying_busy(builder, backoff)
+            .await
+            .map_err(io::Error::from)
+    })?;
+    let async_ctx = crate::client::rtu::attach_slave(serial, slave);
+    let sync_ctx = Context {
+        runtime,
+        async_ctx,
+        timeout: None,
+    };
+    Ok(sync_ctx)
+}
+
+/// Connect to any kind of _Modbus_ slave device with a timeout
+/// and execute all requests on the selected runtime.
+pub fn connect_slave_with_executor(
+    builder: &SerialPortBuilder,
+    slave: Slave,
+    timeout: Option<Duration>,
+    executor: &Executor,
+) -> io::Result<Context> {
+    let runtime = executor.runtime()?;
     // SerialStream::open requires a runtime at least on cfg(unix).
-    let serial = block_on_with_timeout(&runtime, timeout, async { SerialStream::open(builder) })?;
+    let serial = block_on_with_timeout(&runtime, timeout, async {
+        serial::open(builder).map_err(io::Error::from)
+    })?;
     let async_ctx = crate::client::rtu::attach_slave(serial, slave);
     let sync_ctx = Context {
         runtime,
@@ -0,0 +1,86 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Runtimes for executing synchronous requests
+
+use std::{
+    future::{self, Future},
+    io,
+    sync::Mutex,
+    thread,
+};
+
+use crate::mutex::lock;
+
+use tokio::runtime::Handle;
+
+/// Selects the runtime that executes the requests of a synchronous context.
+#[derive(Debug, Clone, Default)]
+pub enum Executor {
+    /// Create a dedicated runtime for each context.
+    ///
+    /// This is the default.
+    #[default]
+    Dedicated,
+
+    /// Use a runtime that is provided by the caller.
+    ///
+    /// The runtime must drive its I/O and timers on its own, i.e. it must be
+    /// a multi-threaded runtime or a current-thread runtime that is
+    /// blocked on by another thread.
+    Shared(Handle),
+
+    /// Use a lazily created global runtime that is shared by all contexts.
+    ///
+    /// The runtime is driven by a background thread.
+    Global,
+}
+
+impl Executor {
+    pub(super) fn runtime(&self) -> io::Result<Runtime> {
+        let runtime = match self {
+            Self::Dedicated => Runtime::Dedicated(new_runtime()?),
+            Self::Shared(handle) => Runtime::Shared(handle.clone()),
+            Self::Global => Runtime::Shared(global_handle()?),
+        };
+        Ok(runtime)
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum Runtime {
+    Dedicated(tokio::runtime::Runtime),
+    Shared(Handle),
+}
+
+impl Runtime {
+    pub(super) fn block_on<F: Future>(&self, future: F) -> F::Output {
+        match self {
+            Self::Dedicated(runtime) => runtime.block_on(future),
+            Self::Shared(handle) => handle.block_on(future),
+        }
+    }
+}
+
+fn new_runtime() -> io::Result<tokio::runtime::Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()
+}
+
+fn global_handle() -> io::Result<Handle> {
+    static GLOBAL_HANDLE: Mutex<Option<Handle>> = Mutex::new(None);
+
+    let mut global_handle = lock(&GLOBAL_HANDLE);
+    if let Some(handle) = &*global_handle {
+        return Ok(handle.clone());
+    }
+    let runtime = new_runtime()?;
+    let handle = runtime.handle().clone();
+    thread::Builder::new()
+        .name("tokio-modbus-sync".to_owned())
+        .spawn(move || runtime.block_on(future::pending::<()>()))?;
+    *global_handle = Some(handle.clone());
+    Ok(handle)
+}
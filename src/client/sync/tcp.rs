@@ -7,7 +7,7 @@ use std::{io, net::SocketAddr, time::Duration};
 
 use crate::{client::tcp::connect_slave as async_connect_slave, Slave};
 
-use super::{block_on_with_timeout, Context};
+use super::{block_on_with_timeout, Context, Executor};
 
 /// Establish a direct connection to a _Modbus_ TCP coupler.
 pub fn connect(socket_addr: SocketAddr) -> io::Result<Context> {
@@ -37,10 +37,18 @@ pub fn connect_slave_with_timeout(
     slave: Slave,
     timeout: Option<Duration>,
 ) -> io::Result<Context> {
-    let runtime = tokio::runtime::Builder::new_current_thread()
-        .enable_io()
-        .enable_time()
-        .build()?;
+    connect_slave_with_executor(socket_addr, slave, timeout, &Executor::Dedicated)
+}
+
+/// Connect to any kind of _Modbus_ slave device with a timeout
+/// and execute all requests on the selected runtime.
+pub fn connect_slave_with_executor(
+    socket_addr: SocketAddr,
+    slave: Slave,
+    timeout: Option<Duration>,
+    executor: &Executor,
+) -> io::Result<Context> {
+    let runtime = executor.runtime()?;
     let async_ctx =
         block_on_with_timeout(&runtime, timeout, async_connect_slave(socket_addr, slave))?;
     let sync_ctx = Context {
@@ -50,3 +58,29 @@ pub fn connect_slave_with_timeout(
     };
     Ok(sync_ctx)
 }
+
+#[cfg(all(test, feature = "tcp-server"))]
+mod tests {
+    use crate::{client::sync::Reader as _, testing::spawn_memory_server};
+
+    use super::*;
+
+    #[test]
+    fn share_runtimes() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (socket_addr, server) = runtime.block_on(spawn_memory_server()).unwrap();
+        server.memory().set_holding_registers(0, &[1, 2]);
+
+        let timeout = Some(Duration::from_secs(5));
+        let executors = [
+            Executor::Global,
+            Executor::Global,
+            Executor::Shared(runtime.handle().clone()),
+        ];
+        for executor in &executors {
+            let mut ctx =
+                connect_slave_with_executor(socket_addr, Slave(1), timeout, executor).unwrap();
+            assert_eq!(ctx.read_holding_registers(0, 2).unwrap().unwrap(), [1, 2]);
+        }
+    }
+}
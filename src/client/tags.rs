@@ -0,0 +1,504 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Named points with scaling into engineering units
+//!
+//! A [`Tag`] describes where and how a value is stored on the device.
+//! The raw value is converted into an engineering value by
+//! `raw * scale + offset`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "tcp")]
+//! # async fn read() -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio_modbus::{
+//!     client::{
+//!         tags::{DataType, Tag, TagClient, Tags},
+//!         tcp,
+//!     },
+//!     data::WordOrder,
+//! };
+//!
+//! let tags = Tags::new()
+//!     .with_tag(
+//!         "motor_speed",
+//!         Tag::input_register(0x0010, DataType::U16)
+//!             .with_scale(0.1, 0.0)
+//!             .with_unit("rpm"),
+//!     )
+//!     .with_tag(
+//!         "setpoint",
+//!         Tag::holding_register(0x0100, DataType::F32).with_word_order(WordOrder::Cdab),
+//!     )
+//!     .with_tag("pump", Tag::coil(0x0001));
+//!
+//! let ctx = tcp::connect("192.168.0.222:502".parse()?).await?;
+//! let mut client = TagClient::new(ctx, tags);
+//! let speed = client.read_tag("motor_speed").await??;
+//! client.write_tag("setpoint", 42.5).await??;
+//! client.write_tag("pump", 1.0).await??;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, io};
+
+use crate::{
+    data::{Value as _, WordOrder},
+    frame::{Address, Quantity, Word},
+    Result,
+};
+
+use super::{Context, Reader as _, Writer as _};
+
+/// The table that contains a tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterType {
+    /// A coil, i.e. a writable bit.
+    Coil,
+
+    /// A discrete input, i.e. a read-only bit.
+    DiscreteInput,
+
+    /// Holding registers, i.e. writable registers.
+    HoldingRegister,
+
+    /// Input registers, i.e. read-only registers.
+    InputRegister,
+}
+
+impl RegisterType {
+    const fn is_writable(self) -> bool {
+        matches!(self, Self::Coil | Self::HoldingRegister)
+    }
+}
+
+/// The data type of the raw value of a tag in registers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DataType {
+    /// Unsigned 16-bit integer in a single register.
+    #[default]
+    U16,
+
+    /// Signed 16-bit integer in a single register.
+    I16,
+
+    /// Unsigned 32-bit integer in 2 registers.
+    U32,
+
+    /// Signed 32-bit integer in 2 registers.
+    I32,
+
+    /// Unsigned 64-bit integer in 4 registers.
+    U64,
+
+    /// Signed 64-bit integer in 4 registers.
+    I64,
+
+    /// 32-bit floating point number in 2 registers.
+    F32,
+
+    /// 64-bit floating point number in 4 registers.
+    F64,
+}
+
+impl DataType {
+    const fn quantity(self) -> Quantity {
+        match self {
+            Self::U16 | Self::I16 => 1,
+            Self::U32 | Self::I32 | Self::F32 => 2,
+            Self::U64 | Self::I64 | Self::F64 => 4,
+        }
+    }
+
+    // Engineering values are approximations by design.
+    #[allow(clippy::cast_precision_loss)]
+    fn unpack(self, words: &[Word], order: WordOrder) -> Option<f64> {
+        let raw = match self {
+            Self::U16 => u16::unpack(words, order)?.into(),
+            Self::I16 => i16::unpack(words, order)?.into(),
+            Self::U32 => u32::unpack(words, order)?.into(),
+            Self::I32 => i32::unpack(words, order)?.into(),
+            Self::U64 => u64::unpack(words, order)? as f64,
+            Self::I64 => i64::unpack(words, order)? as f64,
+            Self::F32 => f32::unpack(words, order)?.into(),
+            Self::F64 => f64::unpack(words, order)?,
+        };
+        Some(raw)
+    }
+
+    /// Pack a raw value that is rounded to integers if needed.
+    ///
+    /// Returns `None` if the value is out of range.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn pack(self, raw: f64, order: WordOrder) -> Option<Vec<Word>> {
+        macro_rules! pack_int {
+            ($ty:ty) => {{
+                let raw = raw.round();
+                // The bounds are rounded to the nearest representable
+                // floating point number, i.e. might be exceeded by 1.
+                #[allow(clippy::cast_lossless, clippy::cast_precision_loss)]
+                let in_range = raw >= <$ty>::MIN as f64 && raw <= <$ty>::MAX as f64;
+                in_range.then(|| (raw as $ty).pack(order))
+            }};
+        }
+        match self {
+            Self::U16 => pack_int!(u16),
+            Self::I16 => pack_int!(i16),
+            Self::U32 => pack_int!(u32),
+            Self::I32 => pack_int!(i32),
+            Self::U64 => pack_int!(u64),
+            Self::I64 => pack_int!(i64),
+            Self::F32 => {
+                (!raw.is_finite() || (raw as f32).is_finite()).then(|| (raw as f32).pack(order))
+            }
+            Self::F64 => Some(raw.pack(order)),
+        }
+    }
+}
+
+/// A named point on a device.
+///
+/// The fields could be taken from a configuration, e.g. a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    /// The table.
+    pub register_type: RegisterType,
+
+    /// The address of the bit or the first register.
+    pub addr: Address,
+
+    /// The data type of registers.
+    ///
+    /// Ignored for coils and discrete inputs.
+    pub data_type: DataType,
+
+    /// The order of the registers if the data type spans multiple
+    /// registers.
+    pub word_order: WordOrder,
+
+    /// The factor of the raw value.
+    pub scale: f64,
+
+    /// The offset that is added to the scaled raw value.
+    pub offset: f64,
+
+    /// The engineering unit, e.g. `"rpm"`.
+    pub unit: Option<String>,
+}
+
+impl Tag {
+    fn new(register_type: RegisterType, addr: Address, data_type: DataType) -> Self {
+        Self {
+            register_type,
+            addr,
+            data_type,
+            word_order: WordOrder::default(),
+            scale: 1.0,
+            offset: 0.0,
+            unit: None,
+        }
+    }
+
+    /// A coil.
+    #[must_use]
+    pub fn coil(addr: Address) -> Self {
+        Self::new(RegisterType::Coil, addr, DataType::default())
+    }
+
+    /// A discrete input.
+    #[must_use]
+    pub fn discrete_input(addr: Address) -> Self {
+        Self::new(RegisterType::DiscreteInput, addr, DataType::default())
+    }
+
+    /// A value in holding registers.
+    #[must_use]
+    pub fn holding_register(addr: Address, data_type: DataType) -> Self {
+        Self::new(RegisterType::HoldingRegister, addr, data_type)
+    }
+
+    /// A value in input registers.
+    #[must_use]
+    pub fn input_register(addr: Address, data_type: DataType) -> Self {
+        Self::new(RegisterType::InputRegister, addr, data_type)
+    }
+
+    /// Set the order of the registers.
+    #[must_use]
+    pub const fn with_word_order(mut self, word_order: WordOrder) -> Self {
+        self.word_order = word_order;
+        self
+    }
+
+    /// Set the scale factor and the offset.
+    #[must_use]
+    pub const fn with_scale(mut self, scale: f64, offset: f64) -> Self {
+        self.scale = scale;
+        self.offset = offset;
+        self
+    }
+
+    /// Set the engineering unit.
+    #[must_use]
+    pub fn with_unit(mut self, unit: impl Into<String>) -> Self {
+        self.unit = Some(unit.into());
+        self
+    }
+
+    /// Convert a raw value into an engineering value.
+    #[must_use]
+    pub fn to_engineering(&self, raw: f64) -> f64 {
+        raw * self.scale + self.offset
+    }
+
+    /// Convert an engineering value into a raw value.
+    #[must_use]
+    pub fn to_raw(&self, value: f64) -> f64 {
+        (value - self.offset) / self.scale
+    }
+}
+
+/// A set of named tags.
+#[derive(Debug, Clone, Default)]
+pub struct Tags {
+    tags: HashMap<String, Tag>,
+}
+
+impl Tags {
+    /// No tags.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tag.
+    ///
+    /// Replaces a previous tag with the same name.
+    #[must_use]
+    pub fn with_tag(mut self, name: impl Into<String>, tag: Tag) -> Self {
+        self.tags.insert(name.into(), tag);
+        self
+    }
+
+    /// Look up a tag by name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.tags.get(name)
+    }
+
+    /// All tags with their names.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tag)> {
+        self.tags.iter().map(|(name, tag)| (name.as_str(), tag))
+    }
+}
+
+impl<N: Into<String>> FromIterator<(N, Tag)> for Tags {
+    fn from_iter<I: IntoIterator<Item = (N, Tag)>>(iter: I) -> Self {
+        Self {
+            tags: iter
+                .into_iter()
+                .map(|(name, tag)| (name.into(), tag))
+                .collect(),
+        }
+    }
+}
+
+/// Reads and writes tags in engineering units.
+///
+/// Bits are represented as `0.0` and `1.0` and are not scaled. Writing
+/// a non-zero value sets a coil.
+#[derive(Debug)]
+pub struct TagClient {
+    context: Context,
+    tags: Tags,
+}
+
+impl TagClient {
+    /// Access the tags with the given context.
+    #[must_use]
+    pub const fn new(context: Context, tags: Tags) -> Self {
+        Self { context, tags }
+    }
+
+    /// The tags.
+    #[must_use]
+    pub const fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    /// The context, e.g. for reading values that are not tagged.
+    pub fn context_mut(&mut self) -> &mut Context {
+        &mut self.context
+    }
+
+    /// Return the context.
+    #[must_use]
+    pub fn into_context(self) -> Context {
+        self.context
+    }
+
+    fn tag(&self, name: &str) -> io::Result<&Tag> {
+        self.tags.get(name).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("unknown tag {name:?}"))
+        })
+    }
+
+    /// Read the engineering value of a tag.
+    ///
+    /// Unknown tags are rejected with [`io::ErrorKind::InvalidInput`].
+    pub async fn read_tag(&mut self, name: &str) -> Result<f64> {
+        let tag = self.tag(name)?.clone();
+        let cnt = tag.data_type.quantity();
+        let result = match tag.register_type {
+            RegisterType::Coil => {
+                let bits = self.context.read_coils(tag.addr, 1).await?;
+                return Ok(bits.map(|bits| f64::from(u8::from(bits[0]))));
+            }
+            RegisterType::DiscreteInput => {
+                let bits = self.context.read_discrete_inputs(tag.addr, 1).await?;
+                return Ok(bits.map(|bits| f64::from(u8::from(bits[0]))));
+            }
+            RegisterType::HoldingRegister => {
+                self.context.read_holding_registers(tag.addr, cnt).await?
+            }
+            RegisterType::InputRegister => self.context.read_input_registers(tag.addr, cnt).await?,
+        };
+        Ok(result.map(|words| {
+            let raw = tag
+                .data_type
+                .unpack(&words, tag.word_order)
+                .unwrap_or_else(|| unreachable!("verified number of registers"));
+            tag.to_engineering(raw)
+        }))
+    }
+
+    /// Write the engineering value of a tag.
+    ///
+    /// Unknown or read-only tags and values that are out of the range of
+    /// the data type are rejected with [`io::ErrorKind::InvalidInput`].
+    pub async fn write_tag(&mut self, name: &str, value: f64) -> Result<()> {
+        let tag = self.tag(name)?;
+        if !tag.register_type.is_writable() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("tag {name:?} is read-only"),
+            )
+            .into());
+        }
+        if tag.register_type == RegisterType::Coil {
+            let addr = tag.addr;
+            return self.context.write_single_coil(addr, value != 0.0).await;
+        }
+        let Some(words) = tag.data_type.pack(tag.to_raw(value), tag.word_order) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("value {value} of tag {name:?} is out of range"),
+            )
+            .into());
+        };
+        let addr = tag.addr;
+        self.context.write_multiple_registers(addr, &words).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use crate::{
+        client::Client,
+        slave::{Slave, SlaveContext},
+        ExceptionCode, Request, Response,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct DeviceMock {
+        coil: bool,
+        registers: [Word; 4],
+    }
+
+    #[async_trait]
+    impl Client for DeviceMock {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            let response = match request {
+                Request::ReadCoils(0, 1) => Response::ReadCoils(vec![self.coil]),
+                Request::WriteSingleCoil(0, coil) => {
+                    self.coil = coil;
+                    Response::WriteSingleCoil(0, coil)
+                }
+                Request::ReadHoldingRegisters(addr, cnt) => Response::ReadHoldingRegisters(
+                    self.registers[addr.into()..(addr + cnt).into()].to_vec(),
+                ),
+                Request::ReadInputRegisters(0, 1) => Response::ReadInputRegisters(vec![1234]),
+                Request::WriteMultipleRegisters(addr, words) => {
+                    let start = usize::from(addr);
+                    self.registers[start..start + words.len()].copy_from_slice(&words);
+                    Response::WriteMultipleRegisters(addr, u16::try_from(words.len()).unwrap())
+                }
+                _ => return Ok(Err(ExceptionCode::IllegalFunction)),
+            };
+            Ok(Ok(response))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for DeviceMock {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn read_and_write_tags() {
+        let tags: Tags = [
+            (
+                "speed",
+                Tag::input_register(0, DataType::U16)
+                    .with_scale(0.1, -20.0)
+                    .with_unit("rpm"),
+            ),
+            (
+                "setpoint",
+                Tag::holding_register(0, DataType::I32)
+                    .with_word_order(WordOrder::Cdab)
+                    .with_scale(0.5, 0.0),
+            ),
+            ("level", Tag::holding_register(2, DataType::F32)),
+            ("pump", Tag::coil(0)),
+        ]
+        .into_iter()
+        .collect();
+        let client: Box<dyn Client> = Box::<DeviceMock>::default();
+        let mut client = TagClient::new(client.into(), tags);
+
+        let speed = client.read_tag("speed").await.unwrap().unwrap();
+        assert!((speed - 103.4).abs() < 1e-9);
+
+        client.write_tag("setpoint", -3.0).await.unwrap().unwrap();
+        assert_eq!(client.read_tag("setpoint").await.unwrap(), Ok(-3.0));
+        let words = client
+            .context_mut()
+            .read_holding_registers(0, 2)
+            .await
+            .unwrap();
+        assert_eq!(words, Ok(vec![0xFFFA, 0xFFFF]));
+
+        client.write_tag("level", 1.5).await.unwrap().unwrap();
+        assert_eq!(client.read_tag("level").await.unwrap(), Ok(1.5));
+
+        client.write_tag("pump", 1.0).await.unwrap().unwrap();
+        assert_eq!(client.read_tag("pump").await.unwrap(), Ok(1.0));
+
+        for (name, value) in [("speed", 0.0), ("setpoint", 1e10), ("unknown", 0.0)] {
+            let Err(crate::Error::Transport(err)) = client.write_tag(name, value).await else {
+                panic!("{name} has been written");
+            };
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
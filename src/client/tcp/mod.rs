@@ -0,0 +1,667 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! TCP client connections
+
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+
+use crate::{
+    tap::{FrameTap, Tap},
+    transform::FrameTransform,
+};
+
+use super::*;
+
+mod pipeline;
+pub use self::pipeline::{
+    attach_pipelined, connect_pipelined, OrderedRequests, Pipeline, PipelineConnection,
+    PipelineStats,
+};
+
+mod probe;
+pub use self::probe::{probe, Health, Probe};
+
+mod proxy;
+use self::proxy::Destination;
+pub use self::proxy::Proxy;
+
+/// Establish a direct connection to a Modbus TCP coupler.
+pub async fn connect(socket_addr: SocketAddr) -> io::Result<Context> {
+    connect_slave(socket_addr, Slave::tcp_device()).await
+}
+
+/// Connect to a physical, broadcast, or custom Modbus device,
+/// probably through a Modbus TCP gateway that is forwarding
+/// messages to/from the corresponding slave device.
+pub async fn connect_slave(socket_addr: SocketAddr, slave: Slave) -> io::Result<Context> {
+    Builder::new(socket_addr).slave(slave).connect().await
+}
+
+/// Resolves host names into socket addresses.
+///
+/// The default resolver uses [`tokio::net::lookup_host()`].
+#[async_trait]
+pub trait Resolve: fmt::Debug + Send + Sync {
+    /// Resolve the socket addresses for `host` and `port`.
+    ///
+    /// Connecting is attempted in the order of the returned addresses.
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Addr(SocketAddr),
+    Host(String, u16),
+    Endpoints(Endpoints),
+}
+
+/// Alternative endpoints of a device, e.g. the addresses of
+/// multiple network interfaces.
+///
+/// Each endpoint is addressed by a separate slave id. Connecting
+/// is attempted in the given order. The endpoint that connected
+/// successfully is remembered and tried first when reconnecting.
+///
+/// The active endpoint is shared by all clones.
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    endpoints: Arc<[(SocketAddr, Slave)]>,
+    active: Arc<AtomicUsize>,
+}
+
+impl Endpoints {
+    /// Create a list of endpoints.
+    #[must_use]
+    pub fn new(endpoints: impl IntoIterator<Item = (SocketAddr, Slave)>) -> Self {
+        Self {
+            endpoints: endpoints.into_iter().collect(),
+            active: Arc::new(AtomicUsize::new(NO_ACTIVE_ENDPOINT)),
+        }
+    }
+
+    /// The endpoint of the most recent successful connection.
+    ///
+    /// Returns `None` until connected.
+    #[must_use]
+    pub fn active(&self) -> Option<(SocketAddr, Slave)> {
+        self.endpoints
+            .get(self.active.load(Ordering::Relaxed))
+            .copied()
+    }
+
+    /// Indices of all endpoints, starting with the active endpoint.
+    fn connect_order(&self) -> impl Iterator<Item = usize> {
+        let active = self.active.load(Ordering::Relaxed);
+        let first = (active < self.endpoints.len()).then_some(active);
+        first
+            .into_iter()
+            .chain((0..self.endpoints.len()).filter(move |&index| index != active))
+    }
+}
+
+const NO_ACTIVE_ENDPOINT: usize = usize::MAX;
+
+/// Configurable connection setup for a Modbus TCP client.
+///
+/// [`connect()`] and [`connect_slave()`] use the default settings.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    target: Target,
+    slave: Slave,
+    nodelay: bool,
+    resolver: Option<Arc<dyn Resolve>>,
+    proxy: Option<Proxy>,
+    label: Option<String>,
+    ignore_trailing_bytes: bool,
+    tap: Option<Tap>,
+    auto_reconnect: Option<Backoff>,
+    buffer_capacity: Option<usize>,
+    max_stale_transaction_age: u16,
+}
+
+impl Builder {
+    /// Prepare a connection to `socket_addr` with the default settings.
+    ///
+    /// Requests are addressed to [`Slave::tcp_device()`] unless
+    /// configured otherwise.
+    #[must_use]
+    pub const fn new(socket_addr: SocketAddr) -> Self {
+        Self::with_target(Target::Addr(socket_addr))
+    }
+
+    /// Prepare a connection to the first reachable of multiple endpoints
+    /// with the default settings.
+    ///
+    /// The slave is selected by the endpoint, i.e. [`Self::slave()`] has no effect.
+    #[must_use]
+    pub fn with_endpoints(endpoints: Endpoints) -> Self {
+        Self::with_target(Target::Endpoints(endpoints))
+    }
+
+    /// Prepare a connection to a named host with the default settings.
+    ///
+    /// The host name is resolved when connecting, see also [`Self::resolver()`].
+    #[must_use]
+    pub fn with_host(host: impl Into<String>, port: u16) -> Self {
+        Self::with_target(Target::Host(host.into(), port))
+    }
+
+    const fn with_target(target: Target) -> Self {
+        Self {
+            target,
+            slave: Slave::tcp_device(),
+            nodelay: true,
+            resolver: None,
+            proxy: None,
+            label: None,
+            ignore_trailing_bytes: false,
+            tap: None,
+            auto_reconnect: None,
+            buffer_capacity: None,
+            max_stale_transaction_age: crate::service::tcp::DEFAULT_MAX_STALE_TRANSACTION_AGE,
+        }
+    }
+
+    /// Select the slave device that is addressed by all requests.
+    #[must_use]
+    pub const fn slave(mut self, slave: Slave) -> Self {
+        self.slave = slave;
+        self
+    }
+
+    /// Enable or disable the `TCP_NODELAY` option of the socket.
+    ///
+    /// Enabled by default. Modbus requests are short and each request
+    /// waits for its response. Combining Nagle's algorithm with delayed
+    /// ACKs on the server side would add a significant latency to every
+    /// round trip.
+    #[must_use]
+    pub const fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Use a custom resolver for host names.
+    ///
+    /// Not used when connecting through a [`Proxy`], which resolves
+    /// host names on its own.
+    #[must_use]
+    pub fn resolver(mut self, resolver: impl Resolve + 'static) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Tunnel the connection through a proxy server.
+    #[must_use]
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Label the connection, see [`Context::set_label()`].
+    #[must_use]
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Accept responses with undecoded trailing bytes.
+    ///
+    /// Disabled by default, i.e. these responses are rejected as invalid.
+    /// Some devices pad their responses. If enabled, the trailing bytes
+    /// are ignored and logged as a warning.
+    #[must_use]
+    pub const fn ignore_trailing_bytes(mut self, ignore_trailing_bytes: bool) -> Self {
+        self.ignore_trailing_bytes = ignore_trailing_bytes;
+        self
+    }
+
+    /// Pass a copy of all sent and received frames to `tap`.
+    #[must_use]
+    pub fn tap(mut self, tap: impl FrameTap + 'static) -> Self {
+        self.tap = Some(Tap::new(tap));
+        self
+    }
+
+    /// Reconnect transparently if the connection has been lost,
+    /// see [`Context::set_auto_reconnect()`].
+    ///
+    /// Disabled by default.
+    #[must_use]
+    pub const fn auto_reconnect(mut self, backoff: Backoff) -> Self {
+        self.auto_reconnect = Some(backoff);
+        self
+    }
+
+    /// Allocate the read and write buffers of the connection with
+    /// `capacity` bytes.
+    ///
+    /// Defaults to 8 KiB. The buffers are reused for all calls and only
+    /// grow if a frame doesn't fit, e.g. smaller buffers save memory with
+    /// many connections while larger buffers avoid reallocations when
+    /// responses accumulate.
+    #[must_use]
+    pub const fn buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer_capacity = Some(capacity);
+        self
+    }
+
+    /// Discard late responses to up to `max_age` previous requests.
+    ///
+    /// Defaults to 32768, i.e. half of the transaction ID space. Responses
+    /// to previous requests might arrive late, e.g. after the caller
+    /// stopped waiting due to a timeout. These stale responses are
+    /// discarded, see [`Client::stale_responses()`]. Responses with any
+    /// other mismatching transaction ID fail with a protocol error.
+    /// Discarding is disabled by passing 0, i.e. all responses with
+    /// a mismatching transaction ID fail.
+    #[must_use]
+    pub const fn max_stale_transaction_age(mut self, max_age: u16) -> Self {
+        self.max_stale_transaction_age = max_age;
+        self
+    }
+
+    /// Establish the connection.
+    ///
+    /// The context is able to reconnect on its own, see also
+    /// [`Context::set_error_recovery()`].
+    pub async fn connect(self) -> io::Result<Context> {
+        let (transport, slave) = self.connect_stream().await?;
+        let mut context = Context::with_slave(Box::new(self.client(transport, slave)), slave);
+        if let Some(label) = &self.label {
+            context.set_label(label.clone());
+        }
+        context.set_auto_reconnect(self.auto_reconnect);
+        context.reconnect = Some(Box::new(self));
+        Ok(context)
+    }
+
+    fn client(&self, transport: TcpStream, slave: Slave) -> crate::service::tcp::Client<TcpStream> {
+        let mut codec = crate::codec::tcp::ClientCodec::new();
+        codec.ignore_trailing_bytes = self.ignore_trailing_bytes;
+        codec.tap.clone_from(&self.tap);
+        let mut client = crate::service::tcp::Client::with_buffer_capacity(
+            transport,
+            slave,
+            codec,
+            self.buffer_capacity,
+        );
+        client.set_max_stale_transaction_age(self.max_stale_transaction_age);
+        client
+    }
+
+    async fn connect_stream(&self) -> io::Result<(TcpStream, Slave)> {
+        let (transport, slave) = match &self.target {
+            Target::Addr(socket_addr) => (
+                self.connect_dest(Destination::Addr(*socket_addr)).await?,
+                self.slave,
+            ),
+            Target::Host(host, port) => (
+                self.connect_dest(Destination::Host(host, *port)).await?,
+                self.slave,
+            ),
+            Target::Endpoints(endpoints) => self.connect_endpoints(endpoints).await?,
+        };
+        transport.set_nodelay(self.nodelay)?;
+        Ok((transport, slave))
+    }
+
+    async fn connect_dest(&self, dest: Destination<'_>) -> io::Result<TcpStream> {
+        if let Some(proxy) = &self.proxy {
+            let mut transport = TcpStream::connect(proxy.addr()).await?;
+            proxy.handshake(&mut transport, dest).await?;
+            return Ok(transport);
+        }
+        match dest {
+            Destination::Addr(socket_addr) => TcpStream::connect(socket_addr).await,
+            Destination::Host(host, port) => {
+                let socket_addrs = self.resolve(host, port).await?;
+                connect_any(&socket_addrs).await
+            }
+        }
+    }
+
+    async fn connect_endpoints(&self, endpoints: &Endpoints) -> io::Result<(TcpStream, Slave)> {
+        let mut last_err = None;
+        for index in endpoints.connect_order() {
+            let (socket_addr, slave) = endpoints.endpoints[index];
+            match self.connect_dest(Destination::Addr(socket_addr)).await {
+                Ok(transport) => {
+                    endpoints.active.store(index, Ordering::Relaxed);
+                    return Ok((transport, slave));
+                }
+                Err(err) => {
+                    log::debug!("Failed to connect to {socket_addr}: {err}");
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no endpoints")))
+    }
+
+    async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Some(resolver) = &self.resolver {
+            return resolver.resolve(host, port).await;
+        }
+        Ok(tokio::net::lookup_host((host, port)).await?.collect())
+    }
+}
+
+#[async_trait]
+impl Reconnect for Builder {
+    async fn reconnect(&self) -> io::Result<Box<dyn Client>> {
+        let (transport, slave) = self.connect_stream().await?;
+        Ok(Box::new(self.client(transport, slave)))
+    }
+}
+
+/// Connect to the first reachable address.
+async fn connect_any(socket_addrs: &[SocketAddr]) -> io::Result<TcpStream> {
+    let mut last_err = None;
+    for socket_addr in socket_addrs {
+        match TcpStream::connect(socket_addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => {
+                log::debug!("Failed to connect to {socket_addr}: {err}");
+                last_err = Some(err);
+            }
+        }
+    }
+    Err(last_err
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no socket address resolved")))
+}
+
+/// Attach a new client context to a direct transport connection.
+///
+/// The connection could either be an ordinary [`TcpStream`] or a TLS connection.
+/// Local connections like named pipes on Windows are also supported.
+pub fn attach<T>(transport: T) -> Context
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + fmt::Debug + 'static,
+{
+    attach_slave(transport, Slave::tcp_device())
+}
+
+/// Attach a new client context to a transport connection.
+///
+/// The connection could either be an ordinary [`TcpStream`] or a TLS connection.
+pub fn attach_slave<T>(transport: T, slave: Slave) -> Context
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + fmt::Debug + 'static,
+{
+    let client = crate::service::tcp::Client::new(transport, slave);
+    Context::with_slave(Box::new(client), slave)
+}
+
+/// Attach a new client context to a transport connection that
+/// carries wrapped frames.
+///
+/// All ADUs are passed through the `transform` before sending
+/// and after receiving.
+pub fn attach_slave_with_transform<T>(
+    transport: T,
+    slave: Slave,
+    transform: impl FrameTransform + 'static,
+) -> Context
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + fmt::Debug + 'static,
+{
+    let mut codec = crate::codec::tcp::ClientCodec::new();
+    codec.transform = Some(Box::new(transform));
+    let client = crate::service::tcp::Client::with_codec(transport, slave, codec);
+    Context::with_slave(Box::new(client), slave)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn nodelay_is_enabled_by_default() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+
+        let builder = Builder::new(socket_addr);
+        let (stream, _) = tokio::join!(builder.connect_stream(), listener.accept());
+        assert!(stream.unwrap().0.nodelay().unwrap());
+
+        let builder = Builder::new(socket_addr).nodelay(false);
+        let (stream, _) = tokio::join!(builder.connect_stream(), listener.accept());
+        assert!(!stream.unwrap().0.nodelay().unwrap());
+    }
+
+    #[tokio::test]
+    async fn reconnect_after_transport_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+
+        let (context, accepted) =
+            tokio::join!(Builder::new(socket_addr).connect(), listener.accept());
+        let mut context = context.unwrap();
+        context.set_error_recovery(ErrorRecovery::Link);
+        // Close the connection on the server side.
+        drop(accepted.unwrap());
+
+        let (result, accepted) = tokio::join!(
+            context.call(Request::ReadHoldingRegisters(0, 1)),
+            listener.accept()
+        );
+        assert!(matches!(result, Err(Error::Transport(_))));
+        accepted.unwrap();
+    }
+
+    #[tokio::test]
+    async fn auto_reconnect_and_repeat_request() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+
+        let builder = Builder::new(socket_addr).auto_reconnect(Backoff::new(
+            Duration::from_millis(1),
+            Duration::from_millis(10),
+            3,
+        ));
+        let (context, accepted) = tokio::join!(builder.connect(), listener.accept());
+        let mut context = context.unwrap();
+        // Close the connection on the server side.
+        drop(accepted.unwrap());
+
+        let server = async {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 12];
+            stream.read_exact(&mut request).await.unwrap();
+            // Transaction id, protocol id, length, and unit id.
+            let mut response = request[..7].to_vec();
+            response[5] = 5;
+            response.extend_from_slice(&[0x03, 0x02, 0x12, 0x34]);
+            stream.write_all(&response).await.unwrap();
+            stream
+        };
+        let ((result, meta), _stream) = tokio::join!(
+            context.call_with_meta(Request::ReadHoldingRegisters(0, 1)),
+            server
+        );
+        assert_eq!(
+            result.unwrap(),
+            Ok(Response::ReadHoldingRegisters(vec![0x1234]))
+        );
+        assert_eq!(meta.retries, 1);
+    }
+
+    #[tokio::test]
+    async fn configure_max_stale_transaction_age() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        for max_age in [1, 0] {
+            let builder = Builder::new(socket_addr).max_stale_transaction_age(max_age);
+            let (context, accepted) = tokio::join!(builder.connect(), listener.accept());
+            let mut context = context.unwrap();
+            let (mut stream, _) = accepted.unwrap();
+
+            let server = async {
+                let mut request = [0; 12];
+                stream.read_exact(&mut request).await.unwrap();
+                assert_eq!(request[..2], [0x00, 0x00]);
+                // Late response to the previous transaction.
+                stream
+                    .write_all(&[0xFF, 0xFF, 0, 0, 0, 5, 0xFF, 0x03, 0x02, 0x00, 0x01])
+                    .await
+                    .unwrap();
+                stream
+                    .write_all(&[0x00, 0x00, 0, 0, 0, 5, 0xFF, 0x03, 0x02, 0x00, 0x02])
+                    .await
+                    .unwrap();
+            };
+            let (result, ()) = tokio::join!(context.read_holding_registers(0, 1), server);
+            if max_age > 0 {
+                assert_eq!(result.unwrap(), Ok(vec![2]));
+                assert_eq!(context.stale_responses(), 1);
+            } else {
+                assert!(matches!(result, Err(Error::Protocol(_))));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn address_initial_slave_after_reading_from_slaves() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        let (client, mut server) = tokio::io::duplex(1024);
+        let mut context = attach_slave(client, Slave(7));
+        let server = async move {
+            let mut unit_ids = vec![];
+            let mut request = [0; 12];
+            while server.read_exact(&mut request).await.is_ok() {
+                unit_ids.push(request[6]);
+                // Answer with the unit id as value.
+                let mut response = request[..7].to_vec();
+                response[5] = 5;
+                response.extend_from_slice(&[0x03, 0x02, 0x00, request[6]]);
+                server.write_all(&response).await.unwrap();
+            }
+            unit_ids
+        };
+        let client = async move {
+            let results = context
+                .read_same_from_slaves(&[Slave(1), Slave(2)], 0, 1)
+                .await;
+            assert_eq!(results.len(), 2);
+            let words = context.read_holding_registers(0, 1).await.unwrap().unwrap();
+            assert_eq!(words, [7]);
+        };
+        let ((), unit_ids) = tokio::join!(client, server);
+        assert_eq!(unit_ids, [1, 2, 7]);
+    }
+
+    #[tokio::test]
+    async fn read_registers_view_with_regular_request() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        let (client, mut server) = tokio::io::duplex(1024);
+        let mut context = attach_slave(client, Slave(1));
+        context.set_strict_validation(true);
+        let server = async move {
+            let mut request = [0; 12];
+            for transaction_id in 0..3 {
+                server.read_exact(&mut request).await.unwrap();
+                assert_eq!(request[..2], [0x00, transaction_id]);
+                assert_eq!(request[6..], [0x01, 0x03, 0x00, 0x10, 0x00, 0x02]);
+                let mut response = request[..6].to_vec();
+                response[5] = 7;
+                response.extend_from_slice(&[0x01, 0x03, 0x04, 0x12, 0x34, 0x56, 0x78]);
+                server.write_all(&response).await.unwrap();
+            }
+        };
+        let client = async move {
+            let registers_view = context
+                .read_holding_registers_view(0x10, 2)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(registers_view.to_vec(), [0x1234, 0x5678]);
+            let registers = context
+                .read_holding_registers_view(0x10, 2)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(registers, registers_view);
+            // Subsequent responses are decoded regularly.
+            let response = context
+                .call(Request::ReadHoldingRegisters(0x10, 2))
+                .await
+                .unwrap();
+            assert_eq!(
+                response,
+                Ok(Response::ReadHoldingRegisters(vec![0x1234, 0x5678]))
+            );
+        };
+        tokio::join!(client, server);
+    }
+
+    #[derive(Debug)]
+    struct StaticResolver(Vec<SocketAddr>);
+
+    #[async_trait]
+    impl Resolve for StaticResolver {
+        async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            assert_eq!(host, "plc.example");
+            assert_eq!(port, 502);
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_host_with_custom_resolver() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        // Drop a listener to get an address that refuses connections.
+        let unreachable_addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let builder = Builder::with_host("plc.example", 502)
+            .resolver(StaticResolver(vec![unreachable_addr, socket_addr]));
+        let (stream, accepted) = tokio::join!(builder.connect_stream(), listener.accept());
+        assert_eq!(stream.unwrap().0.local_addr().unwrap(), accepted.unwrap().1);
+
+        let builder = Builder::with_host("plc.example", 502).resolver(StaticResolver(vec![]));
+        let err = builder.connect_stream().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn connect_first_reachable_endpoint() {
+        let listener1 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener2 = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket_addr1 = listener1.local_addr().unwrap();
+        let socket_addr2 = listener2.local_addr().unwrap();
+        drop(listener1);
+
+        let endpoints = Endpoints::new([(socket_addr1, Slave(1)), (socket_addr2, Slave(2))]);
+        assert_eq!(endpoints.active(), None);
+        let builder = Builder::with_endpoints(endpoints.clone());
+        let (stream, _) = tokio::join!(builder.connect_stream(), listener2.accept());
+        assert_eq!(stream.unwrap().1, Slave(2));
+        assert_eq!(endpoints.active(), Some((socket_addr2, Slave(2))));
+        assert_eq!(endpoints.connect_order().collect::<Vec<_>>(), [1, 0]);
+    }
+}
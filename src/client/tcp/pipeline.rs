@@ -0,0 +1,1199 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Multiple requests in flight on a single connection
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    fmt,
+    future::{poll_fn, Future},
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context as TaskContext, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures_core::Stream as _;
+use futures_util::Sink as _;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    sync::{mpsc, oneshot},
+    time::{sleep_until, Sleep},
+};
+use tokio_util::codec::Framed;
+
+use crate::{
+    codec::tcp::ClientCodec,
+    frame::{
+        tcp::{Header, RequestAdu, ResponseAdu, TransactionId, UnitId},
+        Address, DecodedResponse, FunctionCode, Quantity, Request, Response, Word,
+    },
+    mutex::lock,
+    service::{
+        disconnect, implicit_response,
+        tcp::{verify_response, TransactionIdGenerator},
+    },
+    slave::{Slave, SlaveContext},
+    Result,
+};
+
+use super::super::{Client, Context, Reader as _};
+
+/// Number of the most recent responses for determining the latency.
+const LATENCY_SAMPLES: usize = 1000;
+
+type Reply = oneshot::Sender<Result<DecodedResponse>>;
+
+#[derive(Debug)]
+struct Command {
+    unit_id: UnitId,
+    request: Request<'static>,
+    register_views: bool,
+    reply: Reply,
+    _queued: Queued,
+}
+
+/// The state of the requests of a pipelined connection.
+///
+/// See also [`Pipeline::stats()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    /// The number of requests that wait for being sent.
+    pub queued: usize,
+
+    /// The number of requests that have been sent and wait for
+    /// their response.
+    pub in_flight: usize,
+
+    /// The median latency of the recent requests.
+    pub latency_p50: Option<Duration>,
+
+    /// The 95th percentile of the latency of the recent requests.
+    pub latency_p95: Option<Duration>,
+
+    /// The most recent error.
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Monitor {
+    queued: usize,
+    in_flight: usize,
+    max_in_flight: usize,
+    /// Wakes the connection after the limit has been changed.
+    waker: Option<Waker>,
+    /// The latencies of the most recent responses, oldest first.
+    latencies: VecDeque<Duration>,
+    last_error: Option<String>,
+}
+
+type SharedMonitor = Arc<Mutex<Monitor>>;
+
+/// Counts a request as queued until the command is dropped.
+#[derive(Debug)]
+struct Queued(SharedMonitor);
+
+impl Queued {
+    fn new(monitor: &SharedMonitor) -> Self {
+        lock(monitor).queued += 1;
+        Self(Arc::clone(monitor))
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        lock(&self.0).queued -= 1;
+    }
+}
+
+impl Monitor {
+    fn new_shared() -> SharedMonitor {
+        Arc::new(Mutex::new(Self {
+            max_in_flight: Pipeline::DEFAULT_MAX_IN_FLIGHT,
+            ..Self::default()
+        }))
+    }
+
+    fn set_max_in_flight(&mut self, max_in_flight: usize) {
+        assert!(max_in_flight > 0, "at least one request must be in flight");
+        // Transaction ids must be unique among all requests in flight.
+        self.max_in_flight = max_in_flight.min(usize::from(TransactionId::MAX) + 1);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn record(&mut self, result: &Result<DecodedResponse>, latency: Duration) {
+        match result {
+            Ok(_) => {
+                if self.latencies.len() == LATENCY_SAMPLES {
+                    self.latencies.pop_front();
+                }
+                self.latencies.push_back(latency);
+            }
+            Err(err) => self.last_error = Some(err.to_string()),
+        }
+    }
+
+    fn stats(&self) -> PipelineStats {
+        let mut latencies = self.latencies.iter().copied().collect::<Vec<_>>();
+        latencies.sort_unstable();
+        PipelineStats {
+            queued: self.queued,
+            in_flight: self.in_flight,
+            latency_p50: percentile(&latencies, 50),
+            latency_p95: percentile(&latencies, 95),
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+/// The nearest-rank percentile of sorted values.
+fn percentile(sorted: &[Duration], percent: usize) -> Option<Duration> {
+    let rank = (sorted.len() * percent).div_ceil(100);
+    sorted.get(rank.max(1) - 1).copied()
+}
+
+#[derive(Debug)]
+struct InFlight {
+    hdr: Header,
+    function_code: FunctionCode,
+    reply: Reply,
+    deadline: Option<tokio::time::Instant>,
+}
+
+fn closed() -> crate::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "pipelined connection closed").into()
+}
+
+fn timed_out() -> crate::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        "no response within the request timeout",
+    )
+    .into()
+}
+
+fn aborted() -> crate::Error {
+    io::Error::other("previous request of the sequence has not succeeded").into()
+}
+
+/// A shared handle for sending requests over a pipelined connection.
+///
+/// Requests of all clones are sent immediately without waiting for
+/// the responses to previous requests, up to the limit of requests in
+/// flight. The responses are matched by their transaction identifier
+/// and might arrive in any order.
+///
+/// Requests are only processed while the [`PipelineConnection`] is
+/// running. Calls fail with [`io::ErrorKind::BrokenPipe`] after the
+/// connection has been closed and with [`io::ErrorKind::TimedOut`]
+/// if the server doesn't answer within the
+/// [request timeout](PipelineConnection::request_timeout).
+///
+/// # Example
+///
+/// ```no_run
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use tokio_modbus::prelude::*;
+///
+/// let socket_addr = "192.168.0.222:502".parse()?;
+/// let (pipeline, connection) = tcp::connect_pipelined(socket_addr).await?;
+/// tokio::spawn(connection.run());
+///
+/// let mut ctx1 = pipeline.attach_slave(Slave(1));
+/// let mut ctx2 = pipeline.attach_slave(Slave(2));
+/// let (data1, data2) = tokio::join!(
+///     ctx1.read_holding_registers(0x1000, 7),
+///     ctx2.read_input_registers(0x2000, 3),
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    commands: Option<mpsc::Sender<Command>>,
+    unit_id: UnitId,
+    monitor: SharedMonitor,
+}
+
+impl Pipeline {
+    /// The default limit of requests in flight.
+    pub const DEFAULT_MAX_IN_FLIGHT: usize = 16;
+
+    /// Limit of requests in flight for gateways to serial lines.
+    ///
+    /// RTU and ASCII devices behind a gateway only process one request
+    /// at a time. Most gateways don't queue requests and answer excess
+    /// requests with an exception or not at all.
+    pub const MAX_IN_FLIGHT_SERIAL_GATEWAY: usize = 1;
+
+    /// Limit of requests in flight for compact PLCs and embedded devices.
+    ///
+    /// The Modbus TCP stacks of small devices often only buffer a few
+    /// requests per connection and silently drop excess requests.
+    /// Consult the manual of the device for the actual number, e.g. the
+    /// maximum number of concurrent transactions or messages per
+    /// connection.
+    pub const MAX_IN_FLIGHT_COMPACT_DEVICE: usize = 4;
+
+    /// The current limit of requests in flight on the connection.
+    ///
+    /// See also [`PipelineConnection::max_in_flight()`].
+    #[must_use]
+    pub fn max_in_flight(&self) -> usize {
+        lock(&self.monitor).max_in_flight
+    }
+
+    /// Change the limit of requests in flight on the connection.
+    ///
+    /// Affects all clones and all attached contexts. Requests beyond
+    /// the limit are queued until a previous request has been answered.
+    /// Lowering the limit doesn't affect requests that have already been
+    /// sent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is zero.
+    pub fn set_max_in_flight(&self, max_in_flight: usize) {
+        lock(&self.monitor).set_max_in_flight(max_in_flight);
+    }
+
+    /// Attach a new client context that addresses [`Slave::tcp_device()`].
+    #[must_use]
+    pub fn attach(&self) -> Context {
+        self.attach_slave(Slave::tcp_device())
+    }
+
+    /// Attach a new client context for the given slave.
+    ///
+    /// Multiple contexts could be used concurrently.
+    #[must_use]
+    pub fn attach_slave(&self, slave: Slave) -> Context {
+        let mut pipeline = self.clone();
+        pipeline.unit_id = slave.into();
+        Context::with_slave(Box::new(pipeline), slave)
+    }
+
+    /// Submit a sequence of requests for the given slave that must be
+    /// executed in order.
+    ///
+    /// See [`OrderedRequests`].
+    #[must_use]
+    pub fn ordered(&self, slave: Slave) -> OrderedRequests {
+        let mut pipeline = self.clone();
+        pipeline.unit_id = slave.into();
+        OrderedRequests {
+            pipeline,
+            previous: None,
+        }
+    }
+
+    /// The current state of the requests of all clones.
+    ///
+    /// The latency is measured from submitting a request until its
+    /// response has been received, including the time in the queue.
+    /// Only the last 1000 responses are considered. Exception responses
+    /// count as responses, failed requests don't.
+    ///
+    /// Could be used for admission control, e.g. by rejecting requests
+    /// while the queue is too long.
+    #[must_use]
+    pub fn stats(&self) -> PipelineStats {
+        lock(&self.monitor).stats()
+    }
+
+    /// Read the same holding registers (0x03) from multiple slaves
+    /// concurrently, e.g. when polling an array of identical meters
+    /// behind a gateway.
+    ///
+    /// All requests are sent without waiting for the responses, up to
+    /// the limit of requests in flight. Failures of individual slaves
+    /// don't abort the reads.
+    pub async fn read_same_from_slaves(
+        &self,
+        slaves: &[Slave],
+        addr: Address,
+        cnt: Quantity,
+    ) -> BTreeMap<Slave, Result<Vec<Word>>> {
+        let slaves = slaves.iter().copied().collect::<BTreeSet<_>>();
+        let mut reads = slaves
+            .into_iter()
+            .map(|slave| {
+                let mut context = self.attach_slave(slave);
+                let read = Box::pin(async move { context.read_holding_registers(addr, cnt).await });
+                (slave, read, None)
+            })
+            .collect::<Vec<_>>();
+        poll_fn(|cx| {
+            let mut pending = false;
+            for (_, read, result) in &mut reads {
+                if result.is_some() {
+                    continue;
+                }
+                match read.as_mut().poll(cx) {
+                    Poll::Ready(read) => *result = Some(read),
+                    Poll::Pending => pending = true,
+                }
+            }
+            if pending {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
+        reads
+            .into_iter()
+            .filter_map(|(slave, _, result)| Some((slave, result?)))
+            .collect()
+    }
+}
+
+/// A sequence of requests that are executed in submission order.
+///
+/// Requests of a [`Pipeline`] might be processed concurrently and in
+/// any order by the device. Control sequences like unlocking, setting,
+/// and committing registers require that each request has been
+/// executed before the next one is sent.
+///
+/// The order is determined when submitting requests, not when awaiting
+/// their responses. Each request is sent after the response to the
+/// previously submitted request has been received. If a request fails
+/// or is answered with an exception, all subsequently submitted requests
+/// fail without being sent. Create a new sequence with
+/// [`Pipeline::ordered()`] to continue after a failure.
+///
+/// The futures returned by [`Self::submit()`] must all be awaited, e.g.
+/// joined or spawned. Dropping a future aborts the remaining sequence.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn f(pipeline: tokio_modbus::client::tcp::Pipeline) {
+/// use tokio_modbus::prelude::*;
+///
+/// let mut sequence = pipeline.ordered(Slave(1));
+/// let unlock = sequence.submit(Request::WriteSingleRegister(0x100, 0xA5A5));
+/// let set = sequence.submit(Request::WriteSingleRegister(0x200, 42));
+/// let commit = sequence.submit(Request::WriteSingleRegister(0x100, 0x0001));
+/// let (unlocked, set, committed) = tokio::join!(unlock, set, commit);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct OrderedRequests {
+    pipeline: Pipeline,
+    /// Receives if the previously submitted request has succeeded.
+    previous: Option<oneshot::Receiver<bool>>,
+}
+
+impl OrderedRequests {
+    /// Submit the next request of the sequence.
+    ///
+    /// Resolves to the response after the request has been sent and
+    /// answered.
+    pub fn submit(
+        &mut self,
+        request: Request<'_>,
+    ) -> impl Future<Output = Result<Response>> + Send + 'static {
+        let mut pipeline = self.pipeline.clone();
+        let request = request.into_owned();
+        let previous = self.previous.take();
+        let (succeeded, next) = oneshot::channel();
+        self.previous = Some(next);
+        async move {
+            if let Some(previous) = previous {
+                if previous.await != Ok(true) {
+                    return Err(aborted());
+                }
+            }
+            let result = pipeline.call(request).await;
+            // The next request might have been dropped already.
+            succeeded.send(matches!(result, Ok(Ok(_)))).ok();
+            result
+        }
+    }
+}
+
+impl Pipeline {
+    async fn send_command(
+        &self,
+        request: Request<'_>,
+        register_views: bool,
+    ) -> Result<DecodedResponse> {
+        let Some(commands) = &self.commands else {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "disconnected").into());
+        };
+        let started = Instant::now();
+        let (reply, response) = oneshot::channel();
+        let command = Command {
+            unit_id: self.unit_id,
+            request: request.into_owned(),
+            register_views,
+            reply,
+            _queued: Queued::new(&self.monitor),
+        };
+        let result = match commands.send(command).await {
+            Ok(()) => response.await.unwrap_or_else(|_| Err(closed())),
+            Err(_) => Err(closed()),
+        };
+        lock(&self.monitor).record(&result, started.elapsed());
+        result
+    }
+}
+
+#[async_trait]
+impl Client for Pipeline {
+    async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+        let result = self.send_command(request, false).await?;
+        Ok(result.map(DecodedResponse::into_response))
+    }
+
+    async fn call_with_register_views(&mut self, request: Request<'_>) -> Result<DecodedResponse> {
+        self.send_command(request, true).await
+    }
+
+    /// Detaches this handle from the connection.
+    ///
+    /// The connection is closed after all handles have been dropped
+    /// or disconnected and all outstanding requests have been answered.
+    async fn disconnect(&mut self) -> io::Result<()> {
+        self.commands = None;
+        Ok(())
+    }
+}
+
+impl SlaveContext for Pipeline {
+    fn set_slave(&mut self, slave: Slave) {
+        self.unit_id = slave.into();
+    }
+}
+
+/// Drives a pipelined connection, see [`Pipeline`].
+///
+/// Must be spawned or polled concurrently to the requests.
+pub struct PipelineConnection<T> {
+    framed: Framed<T, ClientCodec>,
+    commands: mpsc::Receiver<Command>,
+    monitor: SharedMonitor,
+    request_timeout: Option<Duration>,
+}
+
+impl<T> fmt::Debug for PipelineConnection<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PipelineConnection")
+            .field("max_in_flight", &lock(&self.monitor).max_in_flight)
+            .field("request_timeout", &self.request_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> PipelineConnection<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Limit the number of requests in flight.
+    ///
+    /// Defaults to [`Pipeline::DEFAULT_MAX_IN_FLIGHT`]. Many devices and
+    /// gateways only process a few requests concurrently and silently
+    /// drop excess requests, see the presets of [`Pipeline`]. The limit
+    /// could also be changed later with [`Pipeline::set_max_in_flight()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is zero.
+    #[must_use]
+    pub fn max_in_flight(self, max_in_flight: usize) -> Self {
+        lock(&self.monitor).set_max_in_flight(max_in_flight);
+        self
+    }
+
+    /// Fail requests that have not been answered within `timeout`.
+    ///
+    /// Disabled by default. Servers might silently drop requests,
+    /// which would otherwise occupy a slot of
+    /// [`max_in_flight()`](Self::max_in_flight) forever. The timeout
+    /// starts when the request is sent and late responses are discarded.
+    #[must_use]
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Allocate the read and write buffers with `capacity` bytes.
+    ///
+    /// Defaults to 8 KiB. The buffers are reused for all requests and
+    /// only grow if the frames in flight don't fit.
+    #[must_use]
+    pub fn buffer_capacity(self, capacity: usize) -> Self {
+        let Self {
+            framed,
+            commands,
+            monitor,
+            request_timeout,
+        } = self;
+        // Nothing has been sent or received yet.
+        let parts = framed.into_parts();
+        debug_assert!(parts.read_buf.is_empty() && parts.write_buf.is_empty());
+        Self {
+            framed: Framed::with_capacity(parts.io, parts.codec, capacity),
+            commands,
+            monitor,
+            request_timeout,
+        }
+    }
+
+    /// Send requests and dispatch responses until the connection
+    /// is closed.
+    ///
+    /// Returns when all [`Pipeline`] handles have been dropped and
+    /// all outstanding requests have been answered. Outstanding
+    /// requests fail if the connection is lost.
+    pub async fn run(self) -> io::Result<()> {
+        let Self {
+            mut framed,
+            mut commands,
+            monitor,
+            request_timeout,
+        } = self;
+        let mut dispatcher = Dispatcher {
+            transaction_ids: TransactionIdGenerator::new(),
+            in_flight: HashMap::new(),
+            closed: false,
+            monitor,
+            request_timeout,
+            timer: None,
+        };
+        let result = poll_fn(|cx| dispatcher.poll(cx, &mut framed, &mut commands)).await;
+        if let Err(err) = &result {
+            for (_, in_flight) in dispatcher.in_flight.drain() {
+                let err = io::Error::new(err.kind(), err.to_string());
+                drop(in_flight.reply.send(Err(err.into())));
+            }
+            dispatcher.update_in_flight();
+        }
+        let disconnected = disconnect(framed).await;
+        result.and(disconnected)
+    }
+}
+
+struct Dispatcher {
+    transaction_ids: TransactionIdGenerator,
+    in_flight: HashMap<TransactionId, InFlight>,
+    closed: bool,
+    monitor: SharedMonitor,
+    request_timeout: Option<Duration>,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl Dispatcher {
+    fn update_in_flight(&self) {
+        lock(&self.monitor).in_flight = self.in_flight.len();
+    }
+
+    /// The current limit, which might be changed concurrently.
+    fn max_in_flight(&self, cx: &TaskContext<'_>) -> usize {
+        let mut monitor = lock(&self.monitor);
+        if !monitor
+            .waker
+            .as_ref()
+            .is_some_and(|waker| waker.will_wake(cx.waker()))
+        {
+            monitor.waker = Some(cx.waker().clone());
+        }
+        monitor.max_in_flight
+    }
+
+    /// Stop waiting for responses to requests whose callers have
+    /// given up, e.g. after a timeout of their own.
+    fn discard_abandoned(&mut self, cx: &mut TaskContext<'_>) {
+        let in_flight = self.in_flight.len();
+        self.in_flight.retain(|transaction_id, in_flight| {
+            let abandoned = in_flight.reply.poll_closed(cx).is_ready();
+            if abandoned {
+                log::debug!("Discarding abandoned request {transaction_id}");
+            }
+            !abandoned
+        });
+        if self.in_flight.len() != in_flight {
+            self.update_in_flight();
+        }
+    }
+
+    /// Fail requests that have not been answered in time.
+    ///
+    /// Returns `true` if any request has timed out.
+    fn poll_timeouts(&mut self, cx: &mut TaskContext<'_>) -> bool {
+        let now = tokio::time::Instant::now();
+        let expired: Vec<_> = self
+            .in_flight
+            .iter()
+            .filter(|(_, in_flight)| in_flight.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(transaction_id, _)| *transaction_id)
+            .collect();
+        for transaction_id in &expired {
+            if let Some(in_flight) = self.in_flight.remove(transaction_id) {
+                log::debug!("Request {transaction_id} timed out");
+                drop(in_flight.reply.send(Err(timed_out())));
+            }
+        }
+        if !expired.is_empty() {
+            self.update_in_flight();
+        }
+        let Some(deadline) = self
+            .in_flight
+            .values()
+            .filter_map(|in_flight| in_flight.deadline)
+            .min()
+        else {
+            self.timer = None;
+            return !expired.is_empty();
+        };
+        let timer = self
+            .timer
+            .get_or_insert_with(|| Box::pin(sleep_until(deadline)));
+        if timer.deadline() != deadline {
+            timer.as_mut().reset(deadline);
+        }
+        // Expired requests are failed when polled again.
+        timer.as_mut().poll(cx).is_ready() || !expired.is_empty()
+    }
+
+    /// The next transaction identifier that is not in flight.
+    ///
+    /// The limit of requests in flight doesn't exceed the number of
+    /// transaction identifiers, i.e. there is always one available.
+    fn next_transaction_id(&mut self) -> TransactionId {
+        loop {
+            let transaction_id = self.transaction_ids.next();
+            if !self.in_flight.contains_key(&transaction_id) {
+                return transaction_id;
+            }
+        }
+    }
+
+    fn poll<T>(
+        &mut self,
+        cx: &mut TaskContext<'_>,
+        framed: &mut Framed<T, ClientCodec>,
+        commands: &mut mpsc::Receiver<Command>,
+    ) -> Poll<io::Result<()>>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        loop {
+            let mut progress = false;
+            self.discard_abandoned(cx);
+            let max_in_flight = self.max_in_flight(cx);
+            while !self.closed && self.in_flight.len() < max_in_flight {
+                if Pin::new(&mut *framed).poll_ready(cx)?.is_pending() {
+                    break;
+                }
+                match commands.poll_recv(cx) {
+                    Poll::Ready(Some(command)) => {
+                        self.send(framed, command)?;
+                        progress = true;
+                    }
+                    Poll::Ready(None) => self.closed = true,
+                    Poll::Pending => break,
+                }
+            }
+            // Flushing makes progress in the background, i.e. it
+            // doesn't need to be completed before receiving.
+            let _ready = Pin::new(&mut *framed).poll_flush(cx)?;
+            match Pin::new(&mut *framed).poll_next(cx) {
+                Poll::Ready(Some(res_adu)) => {
+                    self.dispatch(res_adu?);
+                    progress = true;
+                }
+                Poll::Ready(None) => {
+                    if self.in_flight.is_empty() {
+                        return Poll::Ready(Ok(()));
+                    }
+                    return Poll::Ready(Err(io::Error::from(io::ErrorKind::BrokenPipe)));
+                }
+                Poll::Pending => (),
+            }
+            if self.poll_timeouts(cx) {
+                progress = true;
+            }
+            if self.closed && self.in_flight.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    fn send<T>(&mut self, framed: &mut Framed<T, ClientCodec>, command: Command) -> io::Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let Command {
+            unit_id,
+            request,
+            register_views,
+            reply,
+            _queued,
+        } = command;
+        if reply.is_closed() {
+            // The caller has given up while the request was queued.
+            return Ok(());
+        }
+        let implicit_response = match implicit_response(&request, request.expects_response()) {
+            Ok(implicit_response) => implicit_response,
+            Err(err) => {
+                drop(reply.send(Err(err)));
+                return Ok(());
+            }
+        };
+        let hdr = Header {
+            transaction_id: self.next_transaction_id(),
+            unit_id,
+        };
+        let function_code = request.function_code();
+        framed.codec_mut().register_views = register_views;
+        Pin::new(framed).start_send(RequestAdu {
+            hdr,
+            pdu: request.into(),
+        })?;
+        if let Some(response) = implicit_response {
+            drop(reply.send(Ok(Ok(response.into()))));
+            return Ok(());
+        }
+        self.in_flight.insert(
+            hdr.transaction_id,
+            InFlight {
+                hdr,
+                function_code,
+                reply,
+                deadline: self
+                    .request_timeout
+                    .map(|timeout| tokio::time::Instant::now() + timeout),
+            },
+        );
+        self.update_in_flight();
+        Ok(())
+    }
+
+    fn dispatch(&mut self, res_adu: ResponseAdu<DecodedResponse>) {
+        let Some(in_flight) = self.in_flight.remove(&res_adu.hdr.transaction_id) else {
+            log::debug!(
+                "Discarding response {res_hdr:?} without a request in flight",
+                res_hdr = res_adu.hdr
+            );
+            return;
+        };
+        self.update_in_flight();
+        let result = verify_response(in_flight.hdr, in_flight.function_code, res_adu);
+        // The caller might have given up waiting for the response.
+        drop(in_flight.reply.send(result));
+    }
+}
+
+/// Attach a pipelined client to a transport connection.
+///
+/// The returned connection must be spawned or polled concurrently
+/// to the requests, see [`Pipeline`].
+pub fn attach_pipelined<T>(transport: T) -> (Pipeline, PipelineConnection<T>)
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let (commands_tx, commands_rx) = mpsc::channel(Pipeline::DEFAULT_MAX_IN_FLIGHT);
+    let monitor = Monitor::new_shared();
+    let pipeline = Pipeline {
+        commands: Some(commands_tx),
+        unit_id: Slave::tcp_device().into(),
+        monitor: Arc::clone(&monitor),
+    };
+    let connection = PipelineConnection {
+        framed: Framed::new(transport, ClientCodec::new()),
+        commands: commands_rx,
+        monitor,
+        request_timeout: None,
+    };
+    (pipeline, connection)
+}
+
+/// Establish a pipelined connection to a Modbus TCP server.
+///
+/// See also [`attach_pipelined()`].
+pub async fn connect_pipelined(
+    socket_addr: SocketAddr,
+) -> io::Result<(Pipeline, PipelineConnection<TcpStream>)> {
+    let transport = TcpStream::connect(socket_addr).await?;
+    transport.set_nodelay(true)?;
+    Ok(attach_pipelined(transport))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    use crate::client::Writer as _;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn match_out_of_order_responses() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (pipeline, connection) = attach_pipelined(client);
+        let connection = tokio::spawn(connection.run());
+
+        let mut ctx1 = pipeline.attach_slave(Slave(1));
+        let mut ctx2 = pipeline.attach_slave(Slave(2));
+        let server = async move {
+            let mut requests = [0; 24];
+            server.read_exact(&mut requests).await.unwrap();
+            // Read holding registers of slave 1 and 2.
+            assert_eq!(
+                requests,
+                [
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x00, 0x10, 0x00, 0x01, //
+                    0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x02, 0x03, 0x00, 0x20, 0x00, 0x01,
+                ]
+            );
+            // Answer in reverse order and include an unexpected response.
+            server
+                .write_all(&[
+                    0x00, 0x07, 0x00, 0x00, 0x00, 0x05, 0x02, 0x03, 0x02, 0x00, 0x00, //
+                    0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x02, 0x03, 0x02, 0x00, 0x02, //
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x00, 0x01,
+                ])
+                .await
+                .unwrap();
+            server
+        };
+        let (response1, response2, server) = tokio::join!(
+            ctx1.read_holding_registers(0x10, 1),
+            async {
+                // Send the second request after the first one.
+                tokio::task::yield_now().await;
+                ctx2.read_holding_registers(0x20, 1).await
+            },
+            server
+        );
+        assert_eq!(response1.unwrap(), Ok(vec![1]));
+        assert_eq!(response2.unwrap(), Ok(vec![2]));
+
+        // The connection is closed by the server.
+        drop(server);
+        connection.await.unwrap().unwrap();
+        let err = ctx1.read_holding_registers(0x10, 1).await.unwrap_err();
+        assert!(
+            matches!(err, crate::Error::Transport(err) if err.kind() == io::ErrorKind::BrokenPipe)
+        );
+    }
+
+    #[tokio::test]
+    async fn execute_ordered_requests_in_submission_order() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (pipeline, connection) = attach_pipelined(client);
+        let connection = tokio::spawn(connection.run());
+
+        let mut sequence = pipeline.ordered(Slave(1));
+        drop(pipeline);
+        let requests: Vec<_> = (1..=4)
+            .map(|addr| sequence.submit(Request::WriteSingleRegister(addr, 0)))
+            .collect();
+        drop(sequence);
+        let server = async move {
+            let mut addrs = vec![];
+            let mut request = [0; 12];
+            while server.read_exact(&mut request).await.is_ok() {
+                let addr = u16::from_be_bytes([request[8], request[9]]);
+                addrs.push(addr);
+                if addr == 3 {
+                    // Exception response: IllegalDataAddress
+                    server
+                        .write_all(&[request[0], request[1], 0, 0, 0, 3, 1, 0x86, 0x02])
+                        .await
+                        .unwrap();
+                } else {
+                    // Echo the request as response
+                    server.write_all(&request).await.unwrap();
+                }
+            }
+            addrs
+        };
+        // Await the responses in reverse order.
+        let responses = async {
+            let mut responses = vec![];
+            for request in requests.into_iter().rev() {
+                responses.push(tokio::spawn(request));
+            }
+            let mut results = vec![];
+            for response in responses.into_iter().rev() {
+                results.push(response.await.unwrap());
+            }
+            results
+        };
+        let (results, addrs) = tokio::join!(responses, server);
+        // The last request is not sent after the exception response.
+        assert_eq!(addrs, [1, 2, 3]);
+        assert_eq!(
+            results[1].as_ref().unwrap(),
+            &Ok(Response::WriteSingleRegister(2, 0))
+        );
+        assert_eq!(
+            results[2].as_ref().unwrap(),
+            &Err(crate::ExceptionCode::IllegalDataAddress)
+        );
+        assert!(results[3].is_err());
+        connection.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_same_registers_from_slaves_concurrently() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (pipeline, connection) = attach_pipelined(client);
+        let connection = tokio::spawn(connection.run());
+
+        let server = async move {
+            // All requests are sent before any response is received.
+            let mut requests = [0; 36];
+            server.read_exact(&mut requests).await.unwrap();
+            assert_eq!(
+                requests,
+                [
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x00, 0x10, 0x00, 0x01, //
+                    0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x02, 0x03, 0x00, 0x10, 0x00, 0x01, //
+                    0x00, 0x02, 0x00, 0x00, 0x00, 0x06, 0x03, 0x03, 0x00, 0x10, 0x00, 0x01,
+                ]
+            );
+            server
+                .write_all(&[
+                    0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x03, 0x83, 0x02, //
+                    0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x02, 0x03, 0x02, 0x00, 0x02, //
+                    0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x01, 0x03, 0x02, 0x00, 0x01,
+                ])
+                .await
+                .unwrap();
+            server
+        };
+        let (results, server) = tokio::join!(
+            pipeline.read_same_from_slaves(&[Slave(3), Slave(1), Slave(2), Slave(1)], 0x10, 1),
+            server
+        );
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[&Slave(1)].as_ref().unwrap(), &Ok(vec![1]));
+        assert_eq!(results[&Slave(2)].as_ref().unwrap(), &Ok(vec![2]));
+        assert_eq!(
+            results[&Slave(3)].as_ref().unwrap(),
+            &Err(crate::ExceptionCode::IllegalDataAddress)
+        );
+
+        drop(server);
+        connection.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let latencies = (1..=20).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&latencies, 50), Some(Duration::from_millis(10)));
+        assert_eq!(percentile(&latencies, 95), Some(Duration::from_millis(19)));
+        assert_eq!(
+            percentile(&latencies[..1], 95),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(percentile(&[], 50), None);
+    }
+
+    #[tokio::test]
+    async fn inspect_requests() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (pipeline, connection) = attach_pipelined(client);
+        assert_eq!(pipeline.stats(), PipelineStats::default());
+
+        let mut ctx = pipeline.attach();
+        let request = ctx.read_holding_registers(0x10, 1);
+        // Queued until the connection is running.
+        let (response, ()) = tokio::join!(request, async {
+            tokio::task::yield_now().await;
+            assert_eq!(pipeline.stats().queued, 1);
+            let connection = tokio::spawn(connection.run());
+            let mut request = [0; 12];
+            server.read_exact(&mut request).await.unwrap();
+            let stats = pipeline.stats();
+            assert_eq!((stats.queued, stats.in_flight), (0, 1));
+            server
+                .write_all(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xFF, 0x83, 0x02])
+                .await
+                .unwrap();
+            drop(server);
+            drop(connection);
+        });
+        assert_eq!(
+            response.unwrap(),
+            Err(crate::ExceptionCode::IllegalDataAddress)
+        );
+        let stats = pipeline.stats();
+        assert_eq!((stats.queued, stats.in_flight), (0, 0));
+        assert!(stats.latency_p50.is_some());
+        assert_eq!(stats.latency_p50, stats.latency_p95);
+        assert_eq!(stats.last_error, None);
+
+        ctx.read_holding_registers(0x10, 1).await.unwrap_err();
+        assert!(pipeline.stats().last_error.is_some());
+    }
+
+    #[tokio::test]
+    async fn queue_requests_beyond_max_in_flight() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (pipeline, connection) = attach_pipelined(client);
+        let connection = connection.max_in_flight(Pipeline::MAX_IN_FLIGHT_SERIAL_GATEWAY);
+        assert_eq!(pipeline.max_in_flight(), 1);
+        let connection = tokio::spawn(connection.run());
+
+        let requests: Vec<_> = (1..=3)
+            .map(|addr| {
+                let mut ctx = pipeline.attach();
+                tokio::spawn(async move { ctx.write_single_register(addr, 0).await })
+            })
+            .collect();
+        let mut request = [0; 12];
+        server.read_exact(&mut request).await.unwrap();
+        let first = request;
+        let pending = Duration::from_millis(50);
+        assert!(
+            tokio::time::timeout(pending, server.read_exact(&mut request))
+                .await
+                .is_err()
+        );
+
+        // Raising the limit sends the next queued request.
+        pipeline.set_max_in_flight(2);
+        assert_eq!(pipeline.max_in_flight(), 2);
+        server.read_exact(&mut request).await.unwrap();
+        let second = request;
+        assert!(
+            tokio::time::timeout(pending, server.read_exact(&mut request))
+                .await
+                .is_err()
+        );
+
+        // Answering a request sends the last queued request.
+        server.write_all(&first).await.unwrap();
+        server.read_exact(&mut request).await.unwrap();
+        server.write_all(&second).await.unwrap();
+        server.write_all(&request).await.unwrap();
+        for request in requests {
+            assert!(matches!(request.await.unwrap(), Ok(Ok(()))));
+        }
+
+        drop(pipeline);
+        connection.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn fail_requests_that_are_never_answered() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (pipeline, connection) = attach_pipelined(client);
+        let connection = connection
+            .max_in_flight(1)
+            .request_timeout(Duration::from_millis(50));
+        let connection = tokio::spawn(connection.run());
+
+        let mut ctx = pipeline.attach();
+        let mut request = [0; 12];
+        let (response, ()) = tokio::join!(ctx.read_holding_registers(0x10, 1), async {
+            // The request is never answered.
+            server.read_exact(&mut request).await.unwrap();
+        });
+        let err = response.unwrap_err();
+        assert!(
+            matches!(err, crate::Error::Transport(err) if err.kind() == io::ErrorKind::TimedOut)
+        );
+        assert_eq!(pipeline.stats().in_flight, 0);
+
+        // The slot is available for the next request.
+        let (response, ()) = tokio::join!(ctx.write_single_register(0x10, 0), async {
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[1], 0x01);
+            server.write_all(&request).await.unwrap();
+        });
+        assert_eq!(response.unwrap(), Ok(()));
+
+        drop((ctx, pipeline));
+        connection.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn discard_requests_that_have_been_abandoned() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        let (pipeline, connection) = attach_pipelined(client);
+        let connection = tokio::spawn(connection.max_in_flight(1).run());
+
+        let mut ctx = pipeline.attach();
+        let mut request = [0; 12];
+        let (response, ()) = tokio::join!(
+            tokio::time::timeout(
+                Duration::from_millis(50),
+                ctx.read_holding_registers(0x10, 1)
+            ),
+            async {
+                server.read_exact(&mut request).await.unwrap();
+            }
+        );
+        assert!(response.is_err());
+
+        // The slot of the abandoned request is available.
+        let (response, ()) = tokio::join!(ctx.write_single_register(0x10, 0), async {
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[1], 0x01);
+            server.write_all(&request).await.unwrap();
+        });
+        assert_eq!(response.unwrap(), Ok(()));
+        assert_eq!(pipeline.stats().in_flight, 0);
+
+        drop((ctx, pipeline));
+        connection.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn skip_transaction_ids_in_flight() {
+        let (reply, _response) = oneshot::channel();
+        let hdr = Header {
+            transaction_id: 1,
+            unit_id: 1,
+        };
+        let mut dispatcher = Dispatcher {
+            transaction_ids: TransactionIdGenerator::new(),
+            in_flight: HashMap::from([(
+                hdr.transaction_id,
+                InFlight {
+                    hdr,
+                    function_code: FunctionCode::ReadHoldingRegisters,
+                    reply,
+                    deadline: None,
+                },
+            )]),
+            closed: false,
+            monitor: Monitor::new_shared(),
+            request_timeout: None,
+            timer: None,
+        };
+        assert_eq!(dispatcher.next_transaction_id(), 0);
+        assert_eq!(dispatcher.next_transaction_id(), 2);
+    }
+
+    #[tokio::test]
+    async fn fail_requests_in_flight_if_connection_is_lost() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (pipeline, connection) = attach_pipelined(client);
+        let connection = tokio::spawn(connection.run());
+        let mut ctx = pipeline.attach();
+        let (response, ()) = tokio::join!(ctx.read_coils(0, 1), async {
+            tokio::task::yield_now().await;
+            drop(server);
+        });
+        assert!(response.is_err());
+        assert!(connection.await.unwrap().is_err());
+    }
+}
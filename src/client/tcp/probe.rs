@@ -0,0 +1,146 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Quick health probes of Modbus TCP devices
+
+use std::{
+    io,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    client::{Client as _, Context},
+    Error, ExceptionCode, Request, Slave,
+};
+
+/// Health of a device as determined by [`probe()`].
+#[derive(Debug)]
+pub enum Health {
+    /// The device answered the request.
+    Healthy,
+
+    /// The device answered the request with an exception.
+    ///
+    /// The device is reachable and responsive, but might not
+    /// support the probe request.
+    Exception(ExceptionCode),
+
+    /// The connection could not be established in time.
+    Unreachable(io::Error),
+
+    /// The device did not answer the request in time or
+    /// the response was invalid.
+    Unresponsive(Error),
+}
+
+/// The result of [`probe()`].
+#[derive(Debug)]
+pub struct Probe {
+    /// The health of the device.
+    pub health: Health,
+
+    /// Time until the connection has been established.
+    ///
+    /// `None` if the device is unreachable.
+    pub connect_time: Option<Duration>,
+
+    /// Round-trip time of the probe request.
+    ///
+    /// `None` if no response has been received.
+    pub rtt: Option<Duration>,
+}
+
+impl Probe {
+    /// Check if the device answered the request, either
+    /// with a response or with an exception.
+    #[must_use]
+    pub const fn is_responsive(&self) -> bool {
+        matches!(self.health, Health::Healthy | Health::Exception(_))
+    }
+}
+
+/// Connect to a device, read a single holding register, and disconnect.
+///
+/// The whole probe including connecting is bounded by `timeout`.
+/// Failures are reported as part of the result.
+pub async fn probe(socket_addr: SocketAddr, slave: Slave, timeout: Duration) -> Probe {
+    let started = Instant::now();
+    let connected = tokio::time::timeout(timeout, super::connect_slave(socket_addr, slave))
+        .await
+        .unwrap_or_else(|_| Err(timed_out()));
+    let mut context = match connected {
+        Ok(context) => context,
+        Err(err) => {
+            log::debug!("Failed to connect to {socket_addr}: {err}");
+            return Probe {
+                health: Health::Unreachable(err),
+                connect_time: None,
+                rtt: None,
+            };
+        }
+    };
+    let connect_time = started.elapsed();
+    let probe = request(&mut context, timeout.saturating_sub(connect_time)).await;
+    if let Err(err) = context.disconnect().await {
+        log::debug!("Failed to disconnect from {socket_addr}: {err}");
+    }
+    Probe {
+        connect_time: Some(connect_time),
+        ..probe
+    }
+}
+
+async fn request(context: &mut Context, timeout: Duration) -> Probe {
+    let request = Request::ReadHoldingRegisters(0, 1);
+    let (health, rtt) = match tokio::time::timeout(timeout, context.call_with_meta(request)).await {
+        Ok((Ok(Ok(_)), meta)) => (Health::Healthy, Some(meta.rtt)),
+        Ok((Ok(Err(exception)), meta)) => (Health::Exception(exception), Some(meta.rtt)),
+        Ok((Err(err), _)) => (Health::Unresponsive(err), None),
+        Err(_) => (Health::Unresponsive(timed_out().into()), None),
+    };
+    Probe {
+        health,
+        connect_time: None,
+        rtt,
+    }
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "probe timed out")
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn probe_unreachable_and_unresponsive_devices() {
+        let timeout = Duration::from_millis(50);
+
+        // Drop a listener to get an address that refuses connections.
+        let unreachable_addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let result = probe(unreachable_addr, Slave(1), timeout).await;
+        assert!(matches!(result.health, Health::Unreachable(_)));
+        assert!(!result.is_responsive());
+        assert_eq!(result.connect_time, None);
+
+        // Accept connections without ever answering requests.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let (result, _accepted) =
+            tokio::join!(probe(socket_addr, Slave(1), timeout), listener.accept());
+        assert!(result.connect_time.is_some());
+        assert_eq!(result.rtt, None);
+        let Health::Unresponsive(Error::Transport(err)) = result.health else {
+            panic!("unexpected health: {:?}", result.health);
+        };
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
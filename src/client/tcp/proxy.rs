@@ -0,0 +1,392 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Tunneling TCP connections through a proxy server
+
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+
+/// A proxy server for tunneling TCP connections.
+///
+/// The password of SOCKS5 credentials is redacted when formatted
+/// with [`Debug`](fmt::Debug).
+#[derive(Clone, PartialEq, Eq)]
+pub enum Proxy {
+    /// SOCKS5 proxy ([RFC 1928](https://www.rfc-editor.org/rfc/rfc1928)).
+    Socks5 {
+        /// Address of the proxy server.
+        addr: SocketAddr,
+
+        /// Optional user name and password
+        /// ([RFC 1929](https://www.rfc-editor.org/rfc/rfc1929)).
+        credentials: Option<(String, String)>,
+    },
+
+    /// HTTP proxy that supports the `CONNECT` method.
+    HttpConnect {
+        /// Address of the proxy server.
+        addr: SocketAddr,
+    },
+}
+
+impl fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Socks5 { addr, credentials } => f
+                .debug_struct("Socks5")
+                .field("addr", addr)
+                .field(
+                    "credentials",
+                    &credentials.as_ref().map(|(user, _)| (user, "<redacted>")),
+                )
+                .finish(),
+            Self::HttpConnect { addr } => {
+                f.debug_struct("HttpConnect").field("addr", addr).finish()
+            }
+        }
+    }
+}
+
+impl Proxy {
+    /// Address of the proxy server.
+    #[must_use]
+    pub const fn addr(&self) -> SocketAddr {
+        match self {
+            Self::Socks5 { addr, .. } | Self::HttpConnect { addr } => *addr,
+        }
+    }
+
+    /// Request a tunnel to `dest` over a connection to the proxy server.
+    pub(super) async fn handshake<T>(&self, stream: &mut T, dest: Destination<'_>) -> io::Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        match self {
+            Self::Socks5 { credentials, .. } => {
+                socks5_handshake(stream, credentials.as_ref(), dest).await
+            }
+            Self::HttpConnect { .. } => http_connect_handshake(stream, dest).await,
+        }
+    }
+}
+
+/// The destination of a tunnel.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Destination<'a> {
+    Addr(SocketAddr),
+    Host(&'a str, u16),
+}
+
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_AUTH_NONE: u8 = 0x00;
+const SOCKS5_AUTH_USER_PASSWORD: u8 = 0x02;
+const SOCKS5_AUTH_NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+const SOCKS5_USER_PASSWORD_VERSION: u8 = 0x01;
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+const SOCKS5_REPLY_SUCCEEDED: u8 = 0x00;
+
+fn proxy_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionRefused, message.into())
+}
+
+fn socks5_field_len(field: &str, what: &str) -> io::Result<u8> {
+    u8::try_from(field.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{what} is too long")))
+}
+
+async fn socks5_handshake<T>(
+    stream: &mut T,
+    credentials: Option<&(String, String)>,
+    dest: Destination<'_>,
+) -> io::Result<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let method = if credentials.is_some() {
+        SOCKS5_AUTH_USER_PASSWORD
+    } else {
+        SOCKS5_AUTH_NONE
+    };
+    stream.write_all(&[SOCKS5_VERSION, 1, method]).await?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    match reply {
+        [SOCKS5_VERSION, SOCKS5_AUTH_NO_ACCEPTABLE_METHODS] => {
+            return Err(proxy_error("SOCKS5 proxy rejected authentication method"));
+        }
+        [SOCKS5_VERSION, selected] if selected == method => (),
+        _ => {
+            return Err(proxy_error(format!(
+                "Invalid SOCKS5 method selection: {reply:02X?}"
+            )));
+        }
+    }
+
+    if let Some((user, password)) = credentials {
+        let mut msg = vec![SOCKS5_USER_PASSWORD_VERSION];
+        msg.push(socks5_field_len(user, "SOCKS5 user name")?);
+        msg.extend_from_slice(user.as_bytes());
+        msg.push(socks5_field_len(password, "SOCKS5 password")?);
+        msg.extend_from_slice(password.as_bytes());
+        stream.write_all(&msg).await?;
+        stream.read_exact(&mut reply).await?;
+        match reply {
+            [SOCKS5_USER_PASSWORD_VERSION, SOCKS5_REPLY_SUCCEEDED] => (),
+            [SOCKS5_USER_PASSWORD_VERSION, _] => {
+                return Err(proxy_error("SOCKS5 authentication failed"));
+            }
+            _ => {
+                return Err(proxy_error(format!(
+                    "Invalid SOCKS5 authentication reply: {reply:02X?}"
+                )));
+            }
+        }
+    }
+
+    let mut msg = vec![SOCKS5_VERSION, SOCKS5_CMD_CONNECT, 0x00];
+    let port = match dest {
+        Destination::Addr(addr) => {
+            match addr.ip() {
+                IpAddr::V4(ip) => {
+                    msg.push(SOCKS5_ATYP_IPV4);
+                    msg.extend_from_slice(&ip.octets());
+                }
+                IpAddr::V6(ip) => {
+                    msg.push(SOCKS5_ATYP_IPV6);
+                    msg.extend_from_slice(&ip.octets());
+                }
+            }
+            addr.port()
+        }
+        Destination::Host(host, port) => {
+            msg.push(SOCKS5_ATYP_DOMAIN);
+            msg.push(socks5_field_len(host, "Host name")?);
+            msg.extend_from_slice(host.as_bytes());
+            port
+        }
+    };
+    msg.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&msg).await?;
+
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    let [SOCKS5_VERSION, status, _, atyp] = reply else {
+        return Err(proxy_error(format!("Invalid SOCKS5 reply: {reply:02X?}")));
+    };
+    if status != SOCKS5_REPLY_SUCCEEDED {
+        return Err(proxy_error(format!(
+            "SOCKS5 proxy failed to connect: status = {status}"
+        )));
+    }
+    // Skip the bound address and port.
+    let addr_len = match atyp {
+        SOCKS5_ATYP_IPV4 => 4,
+        SOCKS5_ATYP_IPV6 => 16,
+        SOCKS5_ATYP_DOMAIN => usize::from(stream.read_u8().await?),
+        _ => {
+            return Err(proxy_error(format!("Invalid SOCKS5 address type: {atyp}")));
+        }
+    };
+    let mut bound_addr = vec![0; addr_len + 2];
+    stream.read_exact(&mut bound_addr).await?;
+    Ok(())
+}
+
+/// Maximum length of the response header of an HTTP proxy.
+const HTTP_MAX_RESPONSE_LEN: usize = 8192;
+
+async fn http_connect_handshake<T>(stream: &mut T, dest: Destination<'_>) -> io::Result<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let authority = match dest {
+        Destination::Addr(addr) => addr.to_string(),
+        Destination::Host(host, port) => format!("{host}:{port}"),
+    };
+    let request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte to avoid consuming any data beyond the header.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= HTTP_MAX_RESPONSE_LEN {
+            return Err(proxy_error("HTTP proxy response is too long"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let status_line = response
+        .split(|&b| b == b'\r')
+        .next()
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+    let mut parts = status_line.split_whitespace();
+    let version = parts.next().unwrap_or_default();
+    let status = parts.next().unwrap_or_default();
+    if !version.starts_with("HTTP/1.") || !status.starts_with('2') || status.len() != 3 {
+        return Err(proxy_error(format!(
+            "HTTP proxy failed to connect: {status_line}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::duplex;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn socks5_connect_host() {
+        let (mut client, mut server) = duplex(1024);
+        let proxy = Proxy::Socks5 {
+            addr: "127.0.0.1:1080".parse().unwrap(),
+            credentials: Some(("user".to_owned(), "pw".to_owned())),
+        };
+        let server = async move {
+            let mut buf = [0; 3];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [0x05, 0x01, 0x02]);
+            server.write_all(&[0x05, 0x02]).await.unwrap();
+            let mut buf = [0; 9];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"\x01\x04user\x02pw");
+            server.write_all(&[0x01, 0x00]).await.unwrap();
+            let mut buf = [0; 15];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"\x05\x01\x00\x03\x08plc.host\x01\xF6");
+            server
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 10, 0, 0, 1, 0x12, 0x34])
+                .await
+                .unwrap();
+            server
+        };
+        let (res, mut server) = tokio::join!(
+            proxy.handshake(&mut client, Destination::Host("plc.host", 502)),
+            server
+        );
+        res.unwrap();
+
+        // All of the reply has been consumed.
+        server.write_all(&[0xAB]).await.unwrap();
+        assert_eq!(client.read_u8().await.unwrap(), 0xAB);
+    }
+
+    #[tokio::test]
+    async fn socks5_connect_refused() {
+        let (mut client, mut server) = duplex(1024);
+        let proxy = Proxy::Socks5 {
+            addr: "127.0.0.1:1080".parse().unwrap(),
+            credentials: None,
+        };
+        let server = async move {
+            let mut buf = [0; 3];
+            server.read_exact(&mut buf).await.unwrap();
+            server.write_all(&[0x05, 0x00]).await.unwrap();
+            let mut buf = [0; 10];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [0x05, 0x01, 0x00, 0x01, 192, 168, 0, 1, 0x01, 0xF6]);
+            // Connection refused
+            server
+                .write_all(&[0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+            server
+        };
+        let dest = Destination::Addr("192.168.0.1:502".parse().unwrap());
+        let (res, _server) = tokio::join!(proxy.handshake(&mut client, dest), server);
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[tokio::test]
+    async fn http_connect() {
+        let (mut client, mut server) = duplex(1024);
+        let proxy = Proxy::HttpConnect {
+            addr: "127.0.0.1:3128".parse().unwrap(),
+        };
+        let server = async move {
+            let request = b"CONNECT plc.host:502 HTTP/1.1\r\nHost: plc.host:502\r\n\r\n";
+            let mut buf = vec![0; request.len()];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, request);
+            server
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n\xAB")
+                .await
+                .unwrap();
+            server
+        };
+        let (res, _server) = tokio::join!(
+            proxy.handshake(&mut client, Destination::Host("plc.host", 502)),
+            server
+        );
+        res.unwrap();
+        assert_eq!(client.read_u8().await.unwrap(), 0xAB);
+    }
+
+    #[tokio::test]
+    async fn http_connect_forbidden() {
+        let (mut client, mut server) = duplex(1024);
+        let proxy = Proxy::HttpConnect {
+            addr: "127.0.0.1:3128".parse().unwrap(),
+        };
+        let server = async move {
+            server
+                .write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            server
+        };
+        let dest = Destination::Addr("192.168.0.1:502".parse().unwrap());
+        let (res, _server) = tokio::join!(proxy.handshake(&mut client, dest), server);
+        let err = res.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert!(err.to_string().contains("403"));
+    }
+
+    #[tokio::test]
+    async fn socks5_invalid_authentication_reply() {
+        let (mut client, mut server) = duplex(1024);
+        let proxy = Proxy::Socks5 {
+            addr: "127.0.0.1:1080".parse().unwrap(),
+            credentials: Some(("user".to_owned(), "pw".to_owned())),
+        };
+        let server = async move {
+            let mut buf = [0; 3];
+            server.read_exact(&mut buf).await.unwrap();
+            server.write_all(&[0x05, 0x02]).await.unwrap();
+            let mut buf = [0; 9];
+            server.read_exact(&mut buf).await.unwrap();
+            // Replies with the SOCKS version instead of the sub-negotiation version.
+            server.write_all(&[0x05, 0x00]).await.unwrap();
+            server
+        };
+        let (res, _server) = tokio::join!(
+            proxy.handshake(&mut client, Destination::Host("plc.host", 502)),
+            server
+        );
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn redact_password() {
+        let proxy = Proxy::Socks5 {
+            addr: "127.0.0.1:1080".parse().unwrap(),
+            credentials: Some(("user".to_owned(), "secret".to_owned())),
+        };
+        let debug = format!("{proxy:?}");
+        assert!(debug.contains("user"));
+        assert!(!debug.contains("secret"));
+        let debug = format!(
+            "{:?}",
+            super::super::Builder::new(proxy.addr()).proxy(proxy)
+        );
+        assert!(!debug.contains("secret"));
+    }
+}
@@ -0,0 +1,87 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Modbus/TCP Security client connections
+//!
+//! Modbus/TCP Security wraps Modbus TCP in TLS with mutual authentication,
+//! i.e. the client authenticates with a certificate that might carry its
+//! role. The default port is [`DEFAULT_PORT`].
+//!
+//! The [`ClientConfig`] is built with [`rustls`](tokio_rustls::rustls),
+//! including the root certificates, the client certificate, and the
+//! cryptographic provider.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn connect(
+//! #     config: tokio_modbus::client::tls::ClientConfig,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio_modbus::{
+//!     client::tls::{self, ServerName, TlsConfig},
+//!     prelude::*,
+//! };
+//!
+//! let config = TlsConfig::new(config, ServerName::try_from("plc.example.com")?);
+//! let mut ctx = tls::connect("192.168.0.222:802".parse()?, config).await?;
+//! let words = ctx.read_holding_registers(0x1000, 4).await??;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{io, net::SocketAddr, sync::Arc};
+
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+pub use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig};
+
+use crate::Slave;
+
+use super::{tcp, Context};
+
+/// The registered port of Modbus/TCP Security.
+pub const DEFAULT_PORT: u16 = 802;
+
+/// The TLS configuration of a connection.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    config: Arc<ClientConfig>,
+    server_name: ServerName<'static>,
+}
+
+impl TlsConfig {
+    /// Connect with the given configuration to the server with the
+    /// given name, which is verified against the server certificate.
+    #[must_use]
+    pub fn new(config: impl Into<Arc<ClientConfig>>, server_name: ServerName<'static>) -> Self {
+        Self {
+            config: config.into(),
+            server_name,
+        }
+    }
+}
+
+/// Establish a secure connection to a Modbus TCP coupler.
+pub async fn connect(socket_addr: SocketAddr, config: TlsConfig) -> io::Result<Context> {
+    connect_slave(socket_addr, Slave::tcp_device(), config).await
+}
+
+/// Establish a secure connection to a physical, broadcast, or custom
+/// Modbus device, probably through a Modbus TCP gateway.
+pub async fn connect_slave(
+    socket_addr: SocketAddr,
+    slave: Slave,
+    config: TlsConfig,
+) -> io::Result<Context> {
+    let TlsConfig {
+        config,
+        server_name,
+    } = config;
+    let stream = TcpStream::connect(socket_addr).await?;
+    stream.set_nodelay(true)?;
+    let transport = TlsConnector::from(config)
+        .connect(server_name, stream)
+        .await?;
+    Ok(tcp::attach_slave(transport, slave))
+}
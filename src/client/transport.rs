@@ -0,0 +1,203 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Custom transports for clients
+
+use std::io;
+
+use futures_core::Stream;
+use futures_util::Sink;
+
+use crate::{ExceptionResponse, Request, Response, Slave};
+
+use super::Context;
+
+/// A request with the header of its frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestAdu {
+    /// Identifies the transaction, i.e. the response must echo it.
+    pub transaction_id: u16,
+
+    /// The addressed slave or unit.
+    pub unit_id: u8,
+
+    /// The request.
+    pub request: Request<'static>,
+}
+
+/// A response or an exception with the header of its frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseAdu {
+    /// The transaction identifier of the request.
+    pub transaction_id: u16,
+
+    /// The slave or unit that has answered.
+    pub unit_id: u8,
+
+    /// The response or the exception.
+    pub response: Result<Response, ExceptionResponse>,
+}
+
+/// A physical layer that carries the frames of a client, e.g. QUIC
+/// streams, WebSocket connections, or in-process channels.
+///
+/// Implemented for all sinks of requests that are also streams of
+/// responses. The transport is responsible for encoding and decoding
+/// the frames. Requests are sent one after another and the responses
+/// are matched and verified like those of the built-in transports, see
+/// [`attach_transport()`].
+pub trait Transport:
+    Sink<RequestAdu, Error = io::Error> + Stream<Item = io::Result<ResponseAdu>> + Send + Unpin
+{
+}
+
+impl<T> Transport for T where
+    T: Sink<RequestAdu, Error = io::Error> + Stream<Item = io::Result<ResponseAdu>> + Send + Unpin
+{
+}
+
+/// Attach a new client context to a custom transport.
+///
+/// Requests are addressed to [`Slave::tcp_device()`] unless
+/// configured otherwise.
+pub fn attach_transport<T>(transport: T) -> Context
+where
+    T: Transport + 'static,
+{
+    attach_transport_slave(transport, Slave::tcp_device())
+}
+
+/// Attach a new client context to a custom transport for the given slave.
+///
+/// Each request is sent with a new transaction identifier. Responses
+/// with another transaction identifier are discarded as stale, i.e.
+/// transports without transaction identifiers must echo them.
+pub fn attach_transport_slave<T>(transport: T, slave: Slave) -> Context
+where
+    T: Transport + 'static,
+{
+    let client = crate::service::transport::Client::new(transport, slave);
+    Context::with_slave(Box::new(client), slave)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context as TaskContext, Poll},
+    };
+
+    use tokio::sync::mpsc;
+
+    use crate::{
+        client::{Client as _, Reader as _},
+        slave::SlaveContext as _,
+        Error, ExceptionCode, ProtocolError,
+    };
+
+    use super::*;
+
+    /// Passes requests and responses through in-process channels.
+    struct Channel {
+        requests: mpsc::UnboundedSender<RequestAdu>,
+        responses: mpsc::UnboundedReceiver<io::Result<ResponseAdu>>,
+    }
+
+    impl Sink<RequestAdu> for Channel {
+        type Error = io::Error;
+
+        fn poll_ready(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, adu: RequestAdu) -> io::Result<()> {
+            self.requests
+                .send(adu)
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl Stream for Channel {
+        type Item = io::Result<ResponseAdu>;
+
+        fn poll_next(
+            mut self: Pin<&mut Self>,
+            cx: &mut TaskContext<'_>,
+        ) -> Poll<Option<io::Result<ResponseAdu>>> {
+            self.responses.poll_recv(cx)
+        }
+    }
+
+    /// Answers each request with the given responses.
+    fn serve(respond: impl Fn(RequestAdu) -> Vec<ResponseAdu> + Send + 'static) -> Channel {
+        let (requests, mut request_rx) = mpsc::unbounded_channel();
+        let (response_tx, responses) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(adu) = request_rx.recv().await {
+                for response in respond(adu) {
+                    drop(response_tx.send(Ok(response)));
+                }
+            }
+        });
+        Channel {
+            requests,
+            responses,
+        }
+    }
+
+    #[tokio::test]
+    async fn call_through_custom_transport() {
+        let transport = serve(|adu| {
+            let RequestAdu {
+                transaction_id,
+                unit_id,
+                request,
+            } = adu;
+            let response = match request {
+                Request::ReadHoldingRegisters(_, cnt) => {
+                    Ok(Response::ReadHoldingRegisters(vec![0x1234; cnt.into()]))
+                }
+                _ => Err(ExceptionResponse {
+                    function: request.function_code(),
+                    exception: ExceptionCode::IllegalFunction,
+                }),
+            };
+            let stale = ResponseAdu {
+                transaction_id: transaction_id.wrapping_sub(1),
+                unit_id,
+                response: response.clone(),
+            };
+            let current = ResponseAdu {
+                transaction_id,
+                unit_id: if unit_id == 9 { 8 } else { unit_id },
+                response,
+            };
+            vec![stale, current]
+        });
+        let mut ctx = attach_transport_slave(transport, Slave(1));
+
+        assert_eq!(
+            ctx.read_holding_registers(0, 2).await.unwrap(),
+            Ok(vec![0x1234; 2])
+        );
+        assert_eq!(
+            ctx.read_input_registers(0, 2).await.unwrap(),
+            Err(ExceptionCode::IllegalFunction)
+        );
+        assert_eq!(ctx.stale_responses(), 2);
+
+        ctx.set_slave(Slave(9));
+        assert!(matches!(
+            ctx.read_holding_registers(0, 2).await,
+            Err(Error::Protocol(ProtocolError::HeaderMismatch { .. }))
+        ));
+    }
+}
@@ -0,0 +1,299 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Typed access to holding registers
+//!
+//! See the [`data`](crate::data) module for the conversion of values.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "tcp")]
+//! # async fn read() -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio_modbus::{
+//!     client::typed::{Encoding, TypedAccess as _, WordOrder},
+//!     prelude::*,
+//! };
+//!
+//! let mut ctx = tcp::connect("192.168.0.222:502".parse()?).await?;
+//! let voltage = ctx.read_f32(0x0100, WordOrder::Cdab).await??;
+//! let serial_number = ctx.read_string(0x0200, 8, Encoding::Ascii).await??;
+//! ctx.write_u32(0x0300, 86_400, WordOrder::Abcd).await??;
+//! # Ok(())
+//! # }
+//! ```
+
+use async_trait::async_trait;
+
+use crate::{
+    data::{pack_string, unpack_string, Value},
+    frame::{Address, Quantity, Word},
+    Response, Result,
+};
+
+pub use crate::data::{Encoding, WordOrder};
+
+use super::{mismatching_response, Reader, Writer};
+
+/// Read and write typed values from and to holding registers.
+///
+/// Implemented for all clients that implement both [`Reader`] and
+/// [`Writer`], e.g. [`Context`](super::Context).
+#[async_trait]
+pub trait TypedAccess: Reader + Writer {
+    /// Read an unsigned 32-bit integer from 2 holding registers.
+    async fn read_u32(&mut self, addr: Address, order: WordOrder) -> Result<u32> {
+        read_value(self, addr, order).await
+    }
+
+    /// Read a signed 32-bit integer from 2 holding registers.
+    async fn read_i32(&mut self, addr: Address, order: WordOrder) -> Result<i32> {
+        read_value(self, addr, order).await
+    }
+
+    /// Read an unsigned 64-bit integer from 4 holding registers.
+    async fn read_u64(&mut self, addr: Address, order: WordOrder) -> Result<u64> {
+        read_value(self, addr, order).await
+    }
+
+    /// Read a signed 64-bit integer from 4 holding registers.
+    async fn read_i64(&mut self, addr: Address, order: WordOrder) -> Result<i64> {
+        read_value(self, addr, order).await
+    }
+
+    /// Read a 32-bit floating point number from 2 holding registers.
+    async fn read_f32(&mut self, addr: Address, order: WordOrder) -> Result<f32> {
+        read_value(self, addr, order).await
+    }
+
+    /// Read a 64-bit floating point number from 4 holding registers.
+    async fn read_f64(&mut self, addr: Address, order: WordOrder) -> Result<f64> {
+        read_value(self, addr, order).await
+    }
+
+    /// Read a string from `cnt` holding registers.
+    ///
+    /// The string ends before the first NUL character. Strings that
+    /// can't be decoded are rejected with [`std::io::ErrorKind::InvalidData`].
+    async fn read_string(
+        &mut self,
+        addr: Address,
+        cnt: Quantity,
+        encoding: Encoding,
+    ) -> Result<String> {
+        let words = match read_words(self, addr, cnt).await? {
+            Ok(words) => words,
+            Err(exception) => return Ok(Err(exception)),
+        };
+        Ok(Ok(unpack_string(&words, encoding)?))
+    }
+
+    /// Write an unsigned 32-bit integer into 2 holding registers.
+    async fn write_u32(&mut self, addr: Address, value: u32, order: WordOrder) -> Result<()> {
+        self.write_multiple_registers(addr, &value.pack(order))
+            .await
+    }
+
+    /// Write a signed 32-bit integer into 2 holding registers.
+    async fn write_i32(&mut self, addr: Address, value: i32, order: WordOrder) -> Result<()> {
+        self.write_multiple_registers(addr, &value.pack(order))
+            .await
+    }
+
+    /// Write an unsigned 64-bit integer into 4 holding registers.
+    async fn write_u64(&mut self, addr: Address, value: u64, order: WordOrder) -> Result<()> {
+        self.write_multiple_registers(addr, &value.pack(order))
+            .await
+    }
+
+    /// Write a signed 64-bit integer into 4 holding registers.
+    async fn write_i64(&mut self, addr: Address, value: i64, order: WordOrder) -> Result<()> {
+        self.write_multiple_registers(addr, &value.pack(order))
+            .await
+    }
+
+    /// Write a 32-bit floating point number into 2 holding registers.
+    async fn write_f32(&mut self, addr: Address, value: f32, order: WordOrder) -> Result<()> {
+        self.write_multiple_registers(addr, &value.pack(order))
+            .await
+    }
+
+    /// Write a 64-bit floating point number into 4 holding registers.
+    async fn write_f64(&mut self, addr: Address, value: f64, order: WordOrder) -> Result<()> {
+        self.write_multiple_registers(addr, &value.pack(order))
+            .await
+    }
+
+    /// Write a string into `cnt` holding registers.
+    ///
+    /// The remaining registers are padded with NUL characters. Strings
+    /// that can't be encoded or don't fit into the registers are rejected
+    /// with [`std::io::ErrorKind::InvalidInput`].
+    async fn write_string(
+        &mut self,
+        addr: Address,
+        cnt: Quantity,
+        value: &str,
+        encoding: Encoding,
+    ) -> Result<()> {
+        let words = pack_string(value, cnt, encoding)?;
+        self.write_multiple_registers(addr, &words).await
+    }
+}
+
+impl<T> TypedAccess for T where T: Reader + Writer + ?Sized {}
+
+async fn read_words<T>(client: &mut T, addr: Address, cnt: Quantity) -> Result<Vec<Word>>
+where
+    T: Reader + ?Sized,
+{
+    let words = match client.read_holding_registers(addr, cnt).await? {
+        Ok(words) => words,
+        Err(exception) => return Ok(Err(exception)),
+    };
+    // Custom readers might not verify the number of registers.
+    if words.len() != usize::from(cnt) {
+        return Err(mismatching_response(
+            format!("expected {cnt} registers instead of {}", words.len()),
+            Response::ReadHoldingRegisters(words),
+        ));
+    }
+    Ok(Ok(words))
+}
+
+async fn read_value<T, V>(client: &mut T, addr: Address, order: WordOrder) -> Result<V>
+where
+    T: Reader + ?Sized,
+    V: Value,
+{
+    let result = read_words(client, addr, V::QUANTITY).await?;
+    Ok(result.map(|words| {
+        V::unpack(&words, order).unwrap_or_else(|| unreachable!("verified number of registers"))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        client::{Client, Context},
+        slave::{Slave, SlaveContext},
+        ExceptionCode, Request,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Registers(Vec<Word>);
+
+    #[async_trait]
+    impl Client for Registers {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            match request {
+                Request::ReadHoldingRegisters(addr, cnt) => {
+                    let range = usize::from(addr)..usize::from(addr) + usize::from(cnt);
+                    Ok(self
+                        .0
+                        .get(range)
+                        .map(|words| Response::ReadHoldingRegisters(words.to_vec()))
+                        .ok_or(ExceptionCode::IllegalDataAddress))
+                }
+                Request::WriteMultipleRegisters(addr, words) => {
+                    let start = usize::from(addr);
+                    if self.0.len() < start + words.len() {
+                        self.0.resize(start + words.len(), 0);
+                    }
+                    self.0[start..start + words.len()].copy_from_slice(&words);
+                    Ok(Ok(Response::WriteMultipleRegisters(
+                        addr,
+                        u16::try_from(words.len()).unwrap(),
+                    )))
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for Registers {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn word_orders() {
+        let client: Box<dyn Client> = Box::<Registers>::default();
+        let mut ctx = Context::from(client);
+        for (order, expected) in [
+            (WordOrder::Abcd, [0xAABB, 0xCCDD]),
+            (WordOrder::Dcba, [0xDDCC, 0xBBAA]),
+            (WordOrder::Badc, [0xBBAA, 0xDDCC]),
+            (WordOrder::Cdab, [0xCCDD, 0xAABB]),
+        ] {
+            ctx.write_u32(0, 0xAABB_CCDD, order).await.unwrap().unwrap();
+            assert_eq!(
+                ctx.read_holding_registers(0, 2).await.unwrap(),
+                Ok(expected.to_vec())
+            );
+            assert_eq!(ctx.read_u32(0, order).await.unwrap(), Ok(0xAABB_CCDD));
+
+            ctx.write_f64(0, -1.5e300, order).await.unwrap().unwrap();
+            assert_eq!(ctx.read_f64(0, order).await.unwrap(), Ok(-1.5e300));
+        }
+        ctx.write_u64(0, 0x1122_3344_5566_7788, WordOrder::Cdab)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            ctx.read_holding_registers(0, 4).await.unwrap(),
+            Ok(vec![0x7788, 0x5566, 0x3344, 0x1122])
+        );
+        ctx.write_i32(0, -2, WordOrder::Abcd)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ctx.read_i32(0, WordOrder::Abcd).await.unwrap(), Ok(-2));
+        assert_eq!(
+            ctx.read_i64(8, WordOrder::Abcd).await.unwrap(),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+    }
+
+    #[tokio::test]
+    async fn strings() {
+        let client: Box<dyn Client> = Box::<Registers>::default();
+        let mut ctx = Context::from(client);
+        ctx.write_string(0, 3, "abc", Encoding::Ascii)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            ctx.read_holding_registers(0, 3).await.unwrap(),
+            Ok(vec![0x6162, 0x6300, 0x0000])
+        );
+        assert_eq!(
+            ctx.read_string(0, 3, Encoding::Ascii).await.unwrap(),
+            Ok("abc".to_owned())
+        );
+
+        ctx.write_string(0, 2, "Grüß", Encoding::Latin1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            ctx.read_string(0, 2, Encoding::Latin1).await.unwrap(),
+            Ok("Grüß".to_owned())
+        );
+        assert!(ctx.read_string(0, 2, Encoding::Ascii).await.is_err());
+        assert!(ctx.read_string(0, 2, Encoding::Utf8).await.is_err());
+
+        assert!(ctx
+            .write_string(0, 1, "abc", Encoding::Ascii)
+            .await
+            .is_err());
+        assert!(ctx.write_string(0, 2, "äb", Encoding::Ascii).await.is_err());
+    }
+}
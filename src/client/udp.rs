@@ -0,0 +1,80 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! UDP client connections
+//!
+//! Modbus TCP frames that are sent as UDP datagrams, as used by some
+//! test benches and simulators.
+//!
+//! Datagrams might get lost without notice. Therefore the contexts
+//! are created with a [timeout](DEFAULT_TIMEOUT) for requests that
+//! could be changed, but should not be disabled.
+
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::net::UdpSocket;
+
+use super::*;
+
+/// The initial timeout of requests, see [`Context::set_timeout()`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Connect to a Modbus TCP device over UDP.
+pub async fn connect(socket_addr: SocketAddr) -> io::Result<Context> {
+    connect_slave(socket_addr, Slave::tcp_device()).await
+}
+
+/// Connect to a physical, broadcast, or custom Modbus device over UDP,
+/// probably through a gateway that is forwarding messages to/from the
+/// corresponding slave device.
+///
+/// The local socket is bound to an ephemeral port.
+pub async fn connect_slave(socket_addr: SocketAddr, slave: Slave) -> io::Result<Context> {
+    let local_addr = if socket_addr.is_ipv4() {
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+    } else {
+        SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+    };
+    let socket = UdpSocket::bind(local_addr).await?;
+    socket.connect(socket_addr).await?;
+    Ok(attach_slave(socket, slave))
+}
+
+/// Attach a new client context to a connected UDP socket.
+pub fn attach(socket: UdpSocket) -> Context {
+    attach_slave(socket, Slave::tcp_device())
+}
+
+/// Attach a new client context to a connected UDP socket for
+/// addressing the given slave.
+///
+/// Only datagrams from the connected peer are received.
+///
+/// Requests time out after [`DEFAULT_TIMEOUT`].
+pub fn attach_slave(socket: UdpSocket, slave: Slave) -> Context {
+    let client = crate::service::udp::Client::new(socket, slave);
+    let mut context = Context::with_slave(Box::new(client), slave);
+    context.set_timeout(DEFAULT_TIMEOUT);
+    context
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn time_out_lost_datagrams() {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let mut context = connect(server.local_addr().unwrap()).await.unwrap();
+        assert_eq!(context.timeout(), Some(DEFAULT_TIMEOUT));
+
+        // Lost datagrams are never answered.
+        context.set_timeout(Duration::from_millis(50));
+        let err = context.read_holding_registers(0, 1).await.unwrap_err();
+        assert!(matches!(err, Error::Timeout(_)));
+    }
+}
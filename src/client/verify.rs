@@ -0,0 +1,247 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Verification of responses
+
+use std::fmt;
+
+use crate::{
+    frame::{DecodedResponse, ExceptionResponse, FunctionCode, Quantity, Request, Response},
+    slave::SlaveId,
+    ProtocolError, Result,
+};
+
+/// The header of a frame that identifies the addressed device and
+/// the transaction.
+///
+/// Implemented by the headers of the built-in transports. Custom
+/// transports, e.g. proxies or other framings, could implement this
+/// trait for their own headers and use [`verify_response()`] for
+/// checking responses with the same semantics as the built-in clients.
+pub trait VerifiableHeader: fmt::Debug + PartialEq {
+    /// Checks if the header of a response belongs to this request header.
+    ///
+    /// The default implementation requires both headers to be equal.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message with the details if the headers don't match.
+    fn verify_response_header(&self, rsp_hdr: &Self) -> std::result::Result<(), String> {
+        if self != rsp_hdr {
+            return Err(format!(
+                "expected/request = {self:?}, actual/response = {rsp_hdr:?}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The header of frames that only contain the slave id.
+impl VerifiableHeader for SlaveId {}
+
+#[cfg(feature = "rtu")]
+impl VerifiableHeader for crate::frame::rtu::Header {}
+
+#[cfg(feature = "tcp")]
+impl VerifiableHeader for crate::frame::tcp::Header {}
+
+/// Checks that a response matches the request.
+///
+/// Both the headers and the function codes of request and response
+/// must match. Exception responses are returned as [`ExceptionCode`](crate::ExceptionCode)s.
+///
+/// # Errors
+///
+/// Fails with [`ProtocolError::HeaderMismatch`] or
+/// [`ProtocolError::FunctionCodeMismatch`] if the response doesn't
+/// belong to the request.
+pub fn verify_response<H: VerifiableHeader>(
+    req_hdr: &H,
+    req_function_code: FunctionCode,
+    rsp_hdr: &H,
+    result: std::result::Result<Response, ExceptionResponse>,
+) -> Result<Response> {
+    // Match headers of request and response.
+    if let Err(message) = req_hdr.verify_response_header(rsp_hdr) {
+        return Err(ProtocolError::HeaderMismatch { message, result }.into());
+    }
+
+    // Match function codes of request and response.
+    let rsp_function_code = match &result {
+        Ok(response) => response.function_code(),
+        Err(ExceptionResponse { function, .. }) => *function,
+    };
+    if req_function_code != rsp_function_code {
+        return Err(ProtocolError::FunctionCodeMismatch {
+            request: req_function_code,
+            result,
+        }
+        .into());
+    }
+
+    Ok(result.map_err(
+        |ExceptionResponse {
+             function: _,
+             exception,
+         }| exception,
+    ))
+}
+
+/// Same as [`verify_response()`] for responses that have been decoded
+/// by the built-in clients.
+///
+/// Views of registers are only copied into regular responses for
+/// reporting a mismatch.
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+pub(crate) fn verify_decoded_response<H: VerifiableHeader>(
+    req_hdr: &H,
+    req_function_code: FunctionCode,
+    rsp_hdr: &H,
+    result: std::result::Result<DecodedResponse, ExceptionResponse>,
+) -> Result<DecodedResponse> {
+    match result {
+        Ok(DecodedResponse::Registers(function_code, registers))
+            if function_code == req_function_code
+                && req_hdr.verify_response_header(rsp_hdr).is_ok() =>
+        {
+            Ok(Ok(DecodedResponse::Registers(function_code, registers)))
+        }
+        result => verify_response(
+            req_hdr,
+            req_function_code,
+            rsp_hdr,
+            result.map(DecodedResponse::into_response),
+        )
+        .map(|result| result.map(Into::into)),
+    }
+}
+
+/// Checks that the data of a response matches the request.
+///
+/// Write responses must echo the address and the value or quantity of
+/// the request, and read responses must contain the requested number
+/// of values. Coils and discrete inputs might be padded to whole bytes.
+/// Responses of other functions are not checked.
+///
+/// # Errors
+///
+/// Returns a message with the details if the response doesn't match.
+pub fn verify_response_data(
+    request: &Request<'_>,
+    response: &Response,
+) -> std::result::Result<(), String> {
+    use Request::*;
+
+    let matches = match (request, response) {
+        (ReadCoils(_, cnt), Response::ReadCoils(coils))
+        | (ReadDiscreteInputs(_, cnt), Response::ReadDiscreteInputs(coils)) => {
+            is_requested_bit_count(coils.len(), *cnt)
+        }
+        (ReadInputRegisters(_, cnt), Response::ReadInputRegisters(words))
+        | (ReadHoldingRegisters(_, cnt), Response::ReadHoldingRegisters(words))
+        | (ReadWriteMultipleRegisters(_, cnt, _, _), Response::ReadWriteMultipleRegisters(words)) => {
+            words.len() == usize::from(*cnt)
+        }
+        (WriteSingleCoil(addr, coil), Response::WriteSingleCoil(rsp_addr, rsp_coil)) => {
+            (addr, coil) == (rsp_addr, rsp_coil)
+        }
+        (WriteMultipleCoils(addr, coils), Response::WriteMultipleCoils(rsp_addr, rsp_cnt)) => {
+            (*addr, coils.len()) == (*rsp_addr, usize::from(*rsp_cnt))
+        }
+        (WriteSingleRegister(addr, word), Response::WriteSingleRegister(rsp_addr, rsp_word)) => {
+            (addr, word) == (rsp_addr, rsp_word)
+        }
+        (
+            WriteMultipleRegisters(addr, words),
+            Response::WriteMultipleRegisters(rsp_addr, rsp_cnt),
+        ) => (*addr, words.len()) == (*rsp_addr, usize::from(*rsp_cnt)),
+        (
+            MaskWriteRegister(addr, and_mask, or_mask),
+            Response::MaskWriteRegister(rsp_addr, rsp_and_mask, rsp_or_mask),
+        ) => (addr, and_mask, or_mask) == (rsp_addr, rsp_and_mask, rsp_or_mask),
+        _ => true,
+    };
+    if !matches {
+        return Err(format!(
+            "expected/request = {request:?}, actual/response = {response:?}"
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the data of a possibly undecoded response matches the
+/// request, see [`verify_response_data()`].
+pub(crate) fn verify_decoded_response_data(
+    request: &Request<'_>,
+    response: &DecodedResponse,
+) -> std::result::Result<(), String> {
+    match (request, response) {
+        (
+            Request::ReadInputRegisters(_, cnt) | Request::ReadHoldingRegisters(_, cnt),
+            DecodedResponse::Registers(_, registers),
+        ) if registers.len() != usize::from(*cnt) => Err(format!(
+            "expected/request = {request:?}, actual/response = {registers:?}"
+        )),
+        (_, DecodedResponse::Registers(..)) => Ok(()),
+        (_, DecodedResponse::Response(response)) => verify_response_data(request, response),
+    }
+}
+
+/// Checks if `len` coils or discrete inputs match the requested quantity.
+///
+/// The bits might be padded to whole bytes, but the byte count of the
+/// response must match the request.
+pub(crate) fn is_requested_bit_count(len: usize, cnt: Quantity) -> bool {
+    let cnt = usize::from(cnt);
+    (cnt..=cnt.next_multiple_of(8)).contains(&len)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Error, ExceptionCode};
+
+    use super::*;
+
+    #[test]
+    fn verify_headers_and_function_codes() {
+        let response = || Ok(Response::ReadCoils(vec![true]));
+        assert_eq!(
+            verify_response(&1, FunctionCode::ReadCoils, &1, response()).unwrap(),
+            Ok(Response::ReadCoils(vec![true]))
+        );
+        assert_eq!(
+            verify_response(
+                &1,
+                FunctionCode::ReadCoils,
+                &1,
+                Err(ExceptionResponse {
+                    function: FunctionCode::ReadCoils,
+                    exception: ExceptionCode::IllegalDataAddress,
+                })
+            )
+            .unwrap(),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert!(matches!(
+            verify_response(&1, FunctionCode::ReadCoils, &2, response()),
+            Err(Error::Protocol(ProtocolError::HeaderMismatch { .. }))
+        ));
+        assert!(matches!(
+            verify_response(&1, FunctionCode::ReadDiscreteInputs, &1, response()),
+            Err(Error::Protocol(ProtocolError::FunctionCodeMismatch { .. }))
+        ));
+    }
+
+    #[test]
+    fn verify_data() {
+        let request = Request::ReadCoils(0, 3);
+        assert!(verify_response_data(&request, &Response::ReadCoils(vec![true; 8])).is_ok());
+        assert!(verify_response_data(&request, &Response::ReadCoils(vec![true; 16])).is_err());
+        let request = Request::WriteMultipleRegisters(0x10, vec![1, 2].into());
+        assert!(verify_response_data(&request, &Response::WriteMultipleRegisters(0x10, 2)).is_ok());
+        assert!(
+            verify_response_data(&request, &Response::WriteMultipleRegisters(0x11, 2)).is_err()
+        );
+        assert!(verify_response_data(&request, &Response::ReadCoils(vec![])).is_ok());
+    }
+}
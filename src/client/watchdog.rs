@@ -0,0 +1,240 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Detect and tear down stuck connections
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::broadcast;
+
+use super::Context;
+
+/// Capacity of the event channel, i.e. the number of events that
+/// are buffered for slow subscribers.
+const EVENT_CAPACITY: usize = 16;
+
+/// A stuck connection has been detected by a [`Watchdog`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchdogEvent {
+    /// The label of the connection, see [`Context::set_label()`].
+    pub label: Option<String>,
+
+    /// The time without progress after which the watchdog has tripped.
+    pub stalled_for: Duration,
+
+    /// The connection has been re-established successfully.
+    pub reconnected: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    trips: AtomicU64,
+    events: broadcast::Sender<WatchdogEvent>,
+}
+
+/// Watchdog for connections that stopped making progress.
+///
+/// Half-open TCP connections, e.g. to flaky gateways, might never
+/// deliver a response and hang until the operating system gives up.
+/// The watchdog trips if no response has been received for an
+/// outstanding request within the timeout. The request fails with
+/// a [`io::ErrorKind::TimedOut`] transport error and the connection
+/// is torn down. Optionally, the connection is re-established if the
+/// context is able to reconnect, e.g. a TCP context that has been
+/// established by [`tcp::Builder`](super::tcp::Builder).
+///
+/// The statistics and events are shared by all clones.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    timeout: Duration,
+    reconnect: bool,
+    shared: Arc<Shared>,
+}
+
+impl Watchdog {
+    /// Create a new watchdog that trips after `timeout` without progress.
+    ///
+    /// Stuck connections are re-established by default.
+    #[must_use]
+    pub fn new(timeout: Duration) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            timeout,
+            reconnect: true,
+            shared: Arc::new(Shared {
+                trips: AtomicU64::new(0),
+                events,
+            }),
+        }
+    }
+
+    /// Re-establish stuck connections or only tear them down.
+    #[must_use]
+    pub const fn reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// The time without progress after which the watchdog trips.
+    #[must_use]
+    pub const fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The number of times the watchdog has tripped.
+    #[must_use]
+    pub fn trips(&self) -> u64 {
+        self.shared.trips.load(Ordering::Relaxed)
+    }
+
+    /// Receive all subsequent events.
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<WatchdogEvent> {
+        self.shared.events.subscribe()
+    }
+
+    /// Watch all requests of a client context.
+    #[must_use]
+    pub fn watch(&self, mut context: Context) -> Context {
+        context.options.watchdog = Some(self.clone());
+        context
+    }
+
+    pub(super) const fn reconnects(&self) -> bool {
+        self.reconnect
+    }
+
+    pub(super) fn stalled(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no progress within {timeout:?}", timeout = self.timeout),
+        )
+    }
+
+    pub(super) fn trip(&self, event: WatchdogEvent) {
+        self.shared.trips.fetch_add(1, Ordering::Relaxed);
+        // Sending only fails if there are no subscribers.
+        drop(self.shared.events.send(event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future,
+        sync::atomic::{AtomicBool, AtomicUsize},
+    };
+
+    use async_trait::async_trait;
+
+    use crate::{
+        client::{recovery::Reconnect, Client, Reader as _, SlaveContext},
+        Error, Request, Response, Result, Slave,
+    };
+
+    use super::*;
+
+    /// Never answers requests if stuck.
+    #[derive(Debug)]
+    struct Connection {
+        stuck: bool,
+        disconnected: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Client for Connection {
+        async fn call(&mut self, _request: Request<'_>) -> Result<Response> {
+            if self.stuck {
+                future::pending::<()>().await;
+            }
+            Ok(Ok(Response::ReadHoldingRegisters(vec![0])))
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            self.disconnected.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for Connection {
+        fn set_slave(&mut self, _slave: Slave) {}
+    }
+
+    #[derive(Debug, Default)]
+    struct Reconnector {
+        count: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Reconnect for Arc<Reconnector> {
+        async fn reconnect(&self) -> io::Result<Box<dyn Client>> {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            Ok(Box::new(Connection {
+                stuck: false,
+                disconnected: Arc::default(),
+            }))
+        }
+    }
+
+    fn stuck_context(reconnector: &Arc<Reconnector>) -> (Context, Arc<AtomicBool>) {
+        let disconnected = Arc::<AtomicBool>::default();
+        let client = Box::new(Connection {
+            stuck: true,
+            disconnected: Arc::clone(&disconnected),
+        });
+        let mut context = Context::from(client as Box<dyn Client>);
+        context.reconnect = Some(Box::new(Arc::clone(reconnector)));
+        context.set_label("stuck");
+        (context, disconnected)
+    }
+
+    #[tokio::test]
+    async fn reconnect_stuck_connections() {
+        let watchdog = Watchdog::new(Duration::from_millis(10));
+        let mut events = watchdog.subscribe();
+        let reconnector = Arc::<Reconnector>::default();
+        let (context, disconnected) = stuck_context(&reconnector);
+        let mut context = watchdog.watch(context);
+
+        let Err(Error::Transport(err)) = context.read_holding_registers(0, 1).await else {
+            panic!("request should have failed");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(disconnected.load(Ordering::Relaxed));
+        assert_eq!(reconnector.count.load(Ordering::Relaxed), 1);
+        assert_eq!(watchdog.trips(), 1);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            WatchdogEvent {
+                label: Some("stuck".to_owned()),
+                stalled_for: Duration::from_millis(10),
+                reconnected: true,
+            }
+        );
+
+        // The re-established connection is used for subsequent requests.
+        context.read_holding_registers(0, 1).await.unwrap().unwrap();
+        assert_eq!(watchdog.trips(), 1);
+    }
+
+    #[tokio::test]
+    async fn tear_down_stuck_connections() {
+        let watchdog = Watchdog::new(Duration::from_millis(10)).reconnect(false);
+        let mut events = watchdog.subscribe();
+        let reconnector = Arc::<Reconnector>::default();
+        let (context, disconnected) = stuck_context(&reconnector);
+        let mut context = watchdog.watch(context);
+
+        assert!(context.read_holding_registers(0, 1).await.is_err());
+        assert!(disconnected.load(Ordering::Relaxed));
+        assert_eq!(reconnector.count.load(Ordering::Relaxed), 0);
+        assert!(!events.try_recv().unwrap().reconnected);
+    }
+}
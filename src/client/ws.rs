@@ -0,0 +1,45 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Modbus TCP client connections over WebSocket
+//!
+//! See [`crate::ws`] for connecting through reverse proxies or other
+//! custom connections.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn connect() -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio_modbus::{client::ws, prelude::*};
+//!
+//! let mut ctx = ws::connect("192.168.0.222:8080".parse()?, "/modbus").await?;
+//! let words = ctx.read_holding_registers(0x1000, 4).await??;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{io, net::SocketAddr};
+
+use tokio::net::TcpStream;
+
+use crate::Slave;
+
+use super::{tcp, Context};
+
+/// Establish a WebSocket connection to a Modbus TCP coupler.
+pub async fn connect(socket_addr: SocketAddr, path: &str) -> io::Result<Context> {
+    connect_slave(socket_addr, Slave::tcp_device(), path).await
+}
+
+/// Establish a WebSocket connection to a physical, broadcast, or custom
+/// Modbus device, probably through a Modbus TCP gateway.
+pub async fn connect_slave(
+    socket_addr: SocketAddr,
+    slave: Slave,
+    path: &str,
+) -> io::Result<Context> {
+    let stream = TcpStream::connect(socket_addr).await?;
+    stream.set_nodelay(true)?;
+    let transport = crate::ws::connect(stream, &socket_addr.to_string(), path).await?;
+    Ok(tcp::attach_slave(transport, slave))
+}
@@ -0,0 +1,398 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::io::{Error, ErrorKind, Result};
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    bytes::{BufMut as _, Bytes, BytesMut},
+    frame::rtu::*,
+    slave::SlaveId,
+};
+
+use super::{
+    decode_response_pdu, encode_request_pdu, request_pdu_size, ChecksumErrors, DecodedResponse,
+    RegisterViews, RequestPdu, ResponseDecoding, MAX_PDU_SIZE,
+};
+
+const START: u8 = b':';
+
+const START_LEN: usize = 1;
+
+const END: &[u8] = b"\r\n";
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+// [Modbus over Serial Line Specification and Implementation Guide V1.02](http://modbus.org/docs/Modbus_over_serial_line_V1_02.pdf), page 17
+// "The maximum size of a Modbus ASCII frame is 513 characters."
+const MAX_FRAME_LEN: usize = START_LEN + 2 * (1 + MAX_PDU_SIZE + 1) + END.len();
+
+#[derive(Debug, Default)]
+pub(crate) struct FrameDecoder {
+    dropped_bytes: usize,
+}
+
+impl FrameDecoder {
+    /// Decode the next valid frame.
+    ///
+    /// Frames are delimited by their start and end characters. Bytes
+    /// outside of frames and invalid frames are discarded.
+    pub(crate) fn decode(&mut self, buf: &mut BytesMut) -> Option<(SlaveId, Bytes)> {
+        loop {
+            // Skip all bytes before the start of the next frame
+            let start = buf
+                .iter()
+                .position(|&byte| byte == START)
+                .unwrap_or(buf.len());
+            self.drop_bytes(buf, start);
+
+            let Some(end) = buf
+                .iter()
+                .skip(START_LEN)
+                .position(|&byte| byte == START || byte == b'\n')
+                .map(|pos| START_LEN + pos)
+            else {
+                if buf.len() >= MAX_FRAME_LEN {
+                    log::warn!("Discarding frame without end after {} bytes", buf.len());
+                    self.drop_bytes(buf, buf.len());
+                }
+                // Incomplete frame
+                return None;
+            };
+            if buf[end] == START {
+                log::warn!("Discarding incomplete frame: {:X?}", &buf[..end]);
+                self.drop_bytes(buf, end);
+                continue;
+            }
+
+            let frame = buf.split_to(end + 1);
+            match decode_frame(&frame) {
+                Ok((slave_id, pdu_data)) => {
+                    if self.dropped_bytes > 0 {
+                        log::warn!(
+                            "Successfully decoded frame after dropping {} byte(s)",
+                            self.dropped_bytes
+                        );
+                        self.dropped_bytes = 0;
+                    }
+                    return Some((slave_id, pdu_data));
+                }
+                Err(err) => {
+                    log::warn!("Discarding invalid frame: {err}");
+                    self.dropped_bytes += frame.len();
+                }
+            }
+        }
+    }
+
+    fn drop_bytes(&mut self, buf: &mut BytesMut, count: usize) {
+        if count == 0 {
+            return;
+        }
+        let dropped = buf.split_to(count);
+        log::debug!("Dropped {count} byte(s): {dropped:X?}");
+        self.dropped_bytes += count;
+    }
+}
+
+/// Decode a complete frame including start and end characters.
+fn decode_frame(frame: &[u8]) -> Result<(SlaveId, Bytes)> {
+    let Some(hex) = frame
+        .strip_prefix(&[START])
+        .and_then(|frame| frame.strip_suffix(END))
+    else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Invalid frame delimiters",
+        ));
+    };
+    // Slave id, function code, and LRC
+    if hex.len() < 6 || hex.len() % 2 != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid frame length: {}", frame.len()),
+        ));
+    }
+    let mut adu_buf = BytesMut::with_capacity(hex.len() / 2);
+    for digits in hex.chunks_exact(2) {
+        adu_buf.put_u8(decode_hex_byte(digits[0], digits[1])?);
+    }
+    let lrc = adu_buf.split_off(adu_buf.len() - 1)[0];
+    check_lrc(&adu_buf, lrc)?;
+    let slave_id = adu_buf.split_to(1)[0];
+    Ok((slave_id, adu_buf.freeze()))
+}
+
+fn decode_hex_byte(high: u8, low: u8) -> Result<u8> {
+    let digit = |digit: u8| {
+        char::from(digit).to_digit(16).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid hex digit: 0x{digit:0>2X}"),
+            )
+        })
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    Ok((digit(high)? << 4 | digit(low)?) as u8)
+}
+
+fn calc_lrc(data: &[u8]) -> u8 {
+    data.iter()
+        .fold(0_u8, |lrc, byte| lrc.wrapping_add(*byte))
+        .wrapping_neg()
+}
+
+fn check_lrc(adu_data: &[u8], expected_lrc: u8) -> Result<()> {
+    let actual_lrc = calc_lrc(adu_data);
+    if expected_lrc != actual_lrc {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid LRC: expected = 0x{expected_lrc:0>2X}, actual = 0x{actual_lrc:0>2X}"),
+        ));
+    }
+    Ok(())
+}
+
+fn encode_frame(adu_data: &[u8], buf: &mut BytesMut) {
+    let lrc = calc_lrc(adu_data);
+    buf.reserve(START_LEN + 2 * (adu_data.len() + 1) + END.len());
+    buf.put_u8(START);
+    for byte in adu_data.iter().chain(std::iter::once(&lrc)) {
+        buf.put_u8(HEX_DIGITS[usize::from(byte >> 4)]);
+        buf.put_u8(HEX_DIGITS[usize::from(byte & 0x0F)]);
+    }
+    buf.put_slice(END);
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ClientCodec {
+    pub(crate) decoder: FrameDecoder,
+    /// How to decode the response to the last request.
+    pub(crate) response_decoding: ResponseDecoding,
+    /// Return the registers of read responses as views.
+    pub(crate) register_views: bool,
+    /// Reused for encoding ADUs before they are converted to hex.
+    pub(crate) scratch: BytesMut,
+}
+
+// Frames with an invalid LRC are discarded like any other invalid frame.
+impl ChecksumErrors for ClientCodec {}
+
+impl RegisterViews for ClientCodec {
+    fn set_register_views(&mut self, register_views: bool) {
+        self.register_views = register_views;
+    }
+}
+
+#[cfg(feature = "rtu-server")]
+#[derive(Debug, Default)]
+pub(crate) struct ServerCodec {
+    pub(crate) decoder: FrameDecoder,
+    /// Reused for encoding ADUs before they are converted to hex.
+    pub(crate) scratch: BytesMut,
+}
+
+#[cfg(feature = "rtu-server")]
+impl ChecksumErrors for ServerCodec {}
+
+impl Decoder for ClientCodec {
+    type Item = ResponseAdu<DecodedResponse>;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<ResponseAdu<DecodedResponse>>> {
+        let Some((slave_id, pdu_data)) = self.decoder.decode(buf) else {
+            return Ok(None);
+        };
+
+        let hdr = Header { slave_id };
+
+        // Decoding of the PDU is unlikely to fail due
+        // to transmission errors, because the frame's bytes
+        // have already been verified with the LRC.
+        decode_response_pdu(pdu_data, false, self.response_decoding)
+            .map(|pdu| Some(ResponseAdu { hdr, pdu }))
+            .map_err(|err| {
+                // Unrecoverable error
+                log::error!("Failed to decode response PDU: {err}");
+                err
+            })
+    }
+}
+
+#[cfg(feature = "rtu-server")]
+impl Decoder for ServerCodec {
+    type Item = RequestAdu<'static>;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<RequestAdu<'static>>> {
+        let Some((slave_id, pdu_data)) = self.decoder.decode(buf) else {
+            return Ok(None);
+        };
+
+        let hdr = Header { slave_id };
+
+        // Decoding of the PDU is unlikely to fail due
+        // to transmission errors, because the frame's bytes
+        // have already been verified with the LRC.
+        super::RequestPdu::try_from(pdu_data)
+            .map(|pdu| Some(RequestAdu { hdr, pdu }))
+            .map_err(|err| {
+                // Unrecoverable error
+                log::error!("Failed to decode request PDU: {err}");
+                err
+            })
+    }
+}
+
+impl<'a> Encoder<RequestAdu<'a>> for ClientCodec {
+    type Error = Error;
+
+    fn encode(&mut self, adu: RequestAdu<'a>, buf: &mut BytesMut) -> Result<()> {
+        let RequestAdu {
+            hdr,
+            pdu: RequestPdu(request),
+        } = adu;
+        self.response_decoding = ResponseDecoding::of_request(&request, self.register_views);
+        let adu_buf = &mut self.scratch;
+        adu_buf.clear();
+        adu_buf.reserve(request_pdu_size(&request)? + 1);
+        adu_buf.put_u8(hdr.slave_id);
+        encode_request_pdu(adu_buf, &request);
+        encode_frame(adu_buf, buf);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rtu-server")]
+impl Encoder<ResponseAdu> for ServerCodec {
+    type Error = Error;
+
+    fn encode(&mut self, adu: ResponseAdu, buf: &mut BytesMut) -> Result<()> {
+        let ResponseAdu {
+            hdr,
+            pdu: super::ResponsePdu(pdu_res),
+        } = adu;
+        let adu_buf = &mut self.scratch;
+        adu_buf.clear();
+        adu_buf.reserve(super::response_result_pdu_size(&pdu_res)? + 1);
+        adu_buf.put_u8(hdr.slave_id);
+        super::encode_response_result_pdu(adu_buf, &pdu_res);
+        encode_frame(adu_buf, buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ExceptionCode, ExceptionResponse, FunctionCode, Request, Response};
+
+    use super::*;
+
+    #[test]
+    fn test_calc_lrc() {
+        let msg = [0x11, 0x03, 0x00, 0x6B, 0x00, 0x03];
+        assert_eq!(calc_lrc(&msg), 0x7E);
+
+        assert_eq!(calc_lrc(&[]), 0x00);
+        assert_eq!(calc_lrc(&[0x01, 0xFF]), 0x00);
+    }
+
+    #[test]
+    fn encode_request() {
+        let mut codec = ClientCodec::default();
+        let mut buf = BytesMut::new();
+        codec
+            .encode(
+                RequestAdu {
+                    hdr: Header { slave_id: 0x11 },
+                    pdu: Request::ReadHoldingRegisters(0x006B, 3).into(),
+                },
+                &mut buf,
+            )
+            .unwrap();
+        assert_eq!(&buf[..], b":1103006B00037E\r\n");
+    }
+
+    #[test]
+    fn decode_partly_received_response() {
+        let mut codec = ClientCodec::default();
+        let mut buf = BytesMut::from(&b":110306022B"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.extend_from_slice(b"00000064");
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.extend_from_slice(b"55\r\n:11");
+        let ResponseAdu { hdr, pdu } = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(hdr.slave_id, 0x11);
+        assert_eq!(
+            pdu.0,
+            Ok(Response::ReadHoldingRegisters(vec![0x022B, 0x0000, 0x0064]).into())
+        );
+        assert_eq!(&buf[..], b":11");
+    }
+
+    #[test]
+    fn decode_lowercase_exception_response() {
+        let mut codec = ClientCodec::default();
+        let mut buf = BytesMut::from(&b":018302\r\n:0183027a\r\n"[..]);
+        let ResponseAdu { hdr, pdu } = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(hdr.slave_id, 0x01);
+        assert_eq!(
+            pdu.0,
+            Err(ExceptionResponse {
+                function: FunctionCode::ReadHoldingRegisters,
+                exception: ExceptionCode::IllegalDataAddress,
+            })
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn discard_invalid_frames() {
+        let mut codec = ClientCodec::default();
+        let mut buf = BytesMut::from(
+            &b"\x00\xFF:1103006B00037F\r\n:11030\r\n:1103:110600010001E7\r\n:1106000100017F"[..],
+        );
+        let ResponseAdu { hdr, pdu } = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(hdr.slave_id, 0x11);
+        assert_eq!(
+            pdu.0,
+            Ok(Response::WriteSingleRegister(0x0001, 0x0001).into())
+        );
+        assert_eq!(codec.decoder.dropped_bytes, 0);
+        // The incomplete trailing frame remains in the buffer.
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert_eq!(&buf[..], b":1106000100017F");
+    }
+
+    #[test]
+    fn discard_frames_without_end() {
+        let mut codec = ClientCodec::default();
+        let mut buf = BytesMut::from(&b":"[..]);
+        buf.resize(MAX_FRAME_LEN, b'0');
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert!(buf.is_empty());
+        assert_eq!(codec.decoder.dropped_bytes, MAX_FRAME_LEN);
+    }
+
+    #[test]
+    #[cfg(feature = "rtu-server")]
+    fn decode_request_and_encode_response() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::from(&b":1103006B00037E\r\n"[..]);
+        let RequestAdu { hdr, pdu } = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(hdr.slave_id, 0x11);
+        assert_eq!(pdu.0, Request::ReadHoldingRegisters(0x006B, 3));
+
+        codec
+            .encode(
+                ResponseAdu {
+                    hdr,
+                    pdu: Response::ReadHoldingRegisters(vec![0x022B, 0x0000, 0x0064]).into(),
+                },
+                &mut buf,
+            )
+            .unwrap();
+        assert_eq!(&buf[..], b":110306022B0000006455\r\n");
+    }
+}
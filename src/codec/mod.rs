@@ -1,279 +1,71 @@
 // SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::{
-    convert::TryFrom,
-    io::{self, BufRead as _, Cursor, Error, ErrorKind},
-};
-
-use byteorder::{BigEndian, ReadBytesExt as _};
+use std::{convert::TryFrom, io::Error};
 
 use crate::{
-    bytes::{Buf as _, Bytes},
-    frame::{Coil, RequestPdu, ResponsePdu},
-    ExceptionCode, ExceptionResponse, FunctionCode, Request, Response,
+    bytes::Bytes,
+    frame::{DecodedResponse, RequestPdu, ResponsePdu},
+    ExceptionResponse, Request, Response,
 };
 
+#[cfg(feature = "rtu")]
+pub(crate) mod ascii;
+
+mod pdu;
+pub(crate) use self::pdu::*;
+
 #[cfg(feature = "rtu")]
 pub(crate) mod rtu;
 
 #[cfg(feature = "tcp")]
 pub(crate) mod tcp;
 
-/// Maximum request/response PDU size.
-///
-/// As defined by the spec for both RTU and TCP.
-const MAX_PDU_SIZE: usize = 253;
-
-#[cfg(any(test, feature = "rtu", feature = "tcp"))]
-#[allow(clippy::cast_possible_truncation)]
-fn u16_len(len: usize) -> u16 {
-    // This type conversion should always be safe, because either
-    // the caller is responsible to pass a valid usize or the
-    // possible values are limited by the protocol.
-    debug_assert!(len <= u16::MAX.into());
-    len as u16
-}
-
-#[cfg(any(test, feature = "rtu", feature = "tcp"))]
-#[allow(clippy::cast_possible_truncation)]
-fn u8_len(len: usize) -> u8 {
-    // This type conversion should always be safe, because either
-    // the caller is responsible to pass a valid usize or the
-    // possible values are limited by the protocol.
-    debug_assert!(len <= u8::MAX.into());
-    len as u8
-}
+#[cfg(test)]
+mod wire_vectors;
 
-#[cfg(any(test, feature = "rtu", feature = "tcp"))]
-fn encode_request_pdu(buf: &mut crate::bytes::BytesMut, request: &Request<'_>) {
-    use crate::{bytes::BufMut as _, frame::Request::*};
-    buf.put_u8(request.function_code().value());
-    match request {
-        ReadCoils(address, quantity)
-        | ReadDiscreteInputs(address, quantity)
-        | ReadInputRegisters(address, quantity)
-        | ReadHoldingRegisters(address, quantity) => {
-            buf.put_u16(*address);
-            buf.put_u16(*quantity);
-        }
-        WriteSingleCoil(address, state) => {
-            buf.put_u16(*address);
-            buf.put_u16(bool_to_coil(*state));
-        }
-        WriteMultipleCoils(address, coils) => {
-            buf.put_u16(*address);
-            buf.put_u16(u16_len(coils.len()));
-            buf.put_u8(u8_len(packed_coils_size(coils)));
-            encode_packed_coils(buf, coils);
-        }
-        WriteSingleRegister(address, word) => {
-            buf.put_u16(*address);
-            buf.put_u16(*word);
-        }
-        WriteMultipleRegisters(address, words) => {
-            buf.put_u16(*address);
-            let len = words.len();
-            buf.put_u16(u16_len(len));
-            buf.put_u8(u8_len(len * 2));
-            for w in words.as_ref() {
-                buf.put_u16(*w);
-            }
-        }
-        ReportServerId => {}
-        MaskWriteRegister(address, and_mask, or_mask) => {
-            buf.put_u16(*address);
-            buf.put_u16(*and_mask);
-            buf.put_u16(*or_mask);
-        }
-        ReadWriteMultipleRegisters(read_address, quantity, write_address, words) => {
-            buf.put_u16(*read_address);
-            buf.put_u16(*quantity);
-            buf.put_u16(*write_address);
-            let len = words.len();
-            buf.put_u16(u16_len(len));
-            buf.put_u8(u8_len(len * 2));
-            for w in words.as_ref() {
-                buf.put_u16(*w);
-            }
-        }
-        Custom(_, custom_data) => {
-            buf.put_slice(custom_data.as_ref());
-        }
+/// Codecs of serial line frames that are protected by a checksum.
+#[cfg(feature = "rtu")]
+pub(crate) trait ChecksumErrors {
+    /// The number of frames that have been discarded due to an
+    /// invalid checksum.
+    fn checksum_errors(&self) -> u64 {
+        0
     }
 }
 
-#[cfg(any(test, feature = "server"))]
-fn encode_response_pdu(buf: &mut crate::bytes::BytesMut, response: &Response) {
-    use crate::{bytes::BufMut as _, frame::Response::*};
-    buf.put_u8(response.function_code().value());
-    match response {
-        ReadCoils(coils) | ReadDiscreteInputs(coils) => {
-            buf.put_u8(u8_len(packed_coils_size(coils)));
-            encode_packed_coils(buf, coils);
-        }
-        ReadInputRegisters(registers)
-        | ReadHoldingRegisters(registers)
-        | ReadWriteMultipleRegisters(registers) => {
-            buf.put_u8(u8_len(registers.len() * 2));
-            for r in registers {
-                buf.put_u16(*r);
-            }
-        }
-        WriteSingleCoil(address, state) => {
-            buf.put_u16(*address);
-            buf.put_u16(bool_to_coil(*state));
-        }
-        WriteMultipleCoils(address, quantity) | WriteMultipleRegisters(address, quantity) => {
-            buf.put_u16(*address);
-            buf.put_u16(*quantity);
-        }
-        ReportServerId(server_id, run_indication, additional_data) => {
-            buf.put_u8(2 + u8_len(additional_data.len()));
-            buf.put_u8(*server_id);
-            buf.put_u8(if *run_indication { 0xFF } else { 0x00 });
-            buf.put_slice(additional_data);
-        }
-        WriteSingleRegister(address, word) => {
-            buf.put_u16(*address);
-            buf.put_u16(*word);
-        }
-        MaskWriteRegister(address, and_mask, or_mask) => {
-            buf.put_u16(*address);
-            buf.put_u16(*and_mask);
-            buf.put_u16(*or_mask);
-        }
-        Custom(_, custom_data) => {
-            buf.put_slice(custom_data);
-        }
-    }
+/// Client codecs of serial line frames that decode the responses
+/// depending on the request.
+#[cfg(feature = "rtu")]
+pub(crate) trait RegisterViews {
+    /// Return the registers of responses to subsequent read requests
+    /// as views into the received frames.
+    fn set_register_views(&mut self, register_views: bool);
 }
 
-#[cfg(any(test, feature = "server"))]
-fn encode_exception_response_pdu(buf: &mut crate::bytes::BytesMut, response: ExceptionResponse) {
-    use crate::bytes::BufMut as _;
-    debug_assert!(response.function.value() < 0x80);
-    buf.put_u8(response.function.value() + 0x80);
-    buf.put_u8(response.exception.into());
+/// Encode the PDU of a request without any header.
+#[cfg(feature = "tcp-server")]
+pub(crate) fn encode_request(request: &Request<'_>) -> Result<Bytes, Error> {
+    let mut buf = crate::bytes::BytesMut::with_capacity(request_pdu_size(request)?);
+    encode_request_pdu(&mut buf, request);
+    Ok(buf.freeze())
 }
 
-#[cfg(feature = "server")]
-fn encode_response_result_pdu(
-    buf: &mut crate::bytes::BytesMut,
+/// Encode the PDU of a response or an exception response without any header.
+#[cfg(feature = "tcp-server")]
+pub(crate) fn encode_response_result(
     res: &Result<Response, ExceptionResponse>,
-) {
-    match res {
-        Ok(response) => encode_response_pdu(buf, response),
-        Err(response) => encode_exception_response_pdu(buf, *response),
-    }
-}
-
-fn read_u16_be(reader: &mut impl io::Read) -> io::Result<u16> {
-    reader.read_u16::<BigEndian>()
-}
-
-// Only needed for requests with a dynamic payload size.
-fn check_request_pdu_size(pdu_size: usize) -> io::Result<()> {
-    if pdu_size > MAX_PDU_SIZE {
-        return Err(io::Error::new(
-            ErrorKind::InvalidData,
-            "request PDU size exceeded",
-        ));
-    }
-    Ok(())
-}
-
-#[allow(clippy::too_many_lines)] // TODO
-fn decode_request_pdu_bytes(bytes: &Bytes) -> io::Result<Request<'static>> {
-    use crate::frame::Request::*;
-    let pdu_size = bytes.len();
-    let rdr = &mut Cursor::new(&bytes);
-    let fn_code = rdr.read_u8()?;
-    let req = match fn_code {
-        0x01 => ReadCoils(read_u16_be(rdr)?, read_u16_be(rdr)?),
-        0x02 => ReadDiscreteInputs(read_u16_be(rdr)?, read_u16_be(rdr)?),
-        0x05 => WriteSingleCoil(read_u16_be(rdr)?, coil_to_bool(read_u16_be(rdr)?)?),
-        0x0F => {
-            check_request_pdu_size(pdu_size)?;
-            let address = read_u16_be(rdr)?;
-            let quantity = read_u16_be(rdr)?;
-            let byte_count = usize::from(rdr.read_u8()?);
-            if bytes.len() < 6 + byte_count {
-                return Err(io::Error::new(ErrorKind::InvalidData, "too short"));
-            }
-            rdr.consume(byte_count);
-            let packed_coils = &bytes[6..6 + byte_count];
-            WriteMultipleCoils(address, decode_packed_coils(packed_coils, quantity).into())
-        }
-        0x04 => ReadInputRegisters(read_u16_be(rdr)?, read_u16_be(rdr)?),
-        0x03 => ReadHoldingRegisters(read_u16_be(rdr)?, read_u16_be(rdr)?),
-        0x06 => WriteSingleRegister(read_u16_be(rdr)?, read_u16_be(rdr)?),
-        0x10 => {
-            check_request_pdu_size(pdu_size)?;
-            let address = read_u16_be(rdr)?;
-            let quantity = read_u16_be(rdr)?;
-            let byte_count = rdr.read_u8()?;
-            if u16::from(byte_count) != quantity * 2 {
-                return Err(io::Error::new(ErrorKind::InvalidData, "invalid quantity"));
-            }
-            let mut data = Vec::with_capacity(quantity.into());
-            for _ in 0..quantity {
-                data.push(read_u16_be(rdr)?);
-            }
-            WriteMultipleRegisters(address, data.into())
-        }
-        0x11 => ReportServerId,
-        0x16 => {
-            let address = read_u16_be(rdr)?;
-            let and_mask = read_u16_be(rdr)?;
-            let or_mask = read_u16_be(rdr)?;
-            MaskWriteRegister(address, and_mask, or_mask)
-        }
-        0x17 => {
-            check_request_pdu_size(pdu_size)?;
-            let read_address = read_u16_be(rdr)?;
-            let read_quantity = read_u16_be(rdr)?;
-            let write_address = read_u16_be(rdr)?;
-            let write_quantity = read_u16_be(rdr)?;
-            let write_count = rdr.read_u8()?;
-            if u16::from(write_count) != write_quantity * 2 {
-                return Err(io::Error::new(
-                    ErrorKind::InvalidData,
-                    "invalid write quantity",
-                ));
-            }
-            let mut data = Vec::with_capacity(write_quantity.into());
-            for _ in 0..write_quantity {
-                data.push(read_u16_be(rdr)?);
-            }
-            ReadWriteMultipleRegisters(read_address, read_quantity, write_address, data.into())
-        }
-        fn_code if fn_code < 0x80 => {
-            // Consume all remaining bytes as custom data.
-            return Ok(Custom(fn_code, bytes[1..].to_vec().into()));
-        }
-        fn_code => {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("invalid function code: 0x{fn_code:02X}"),
-            ));
-        }
-    };
-    // Verify that all data has been consumed and decoded.
-    if rdr.has_remaining() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "undecoded request data",
-        ));
-    }
-    Ok(req)
+) -> Result<Bytes, Error> {
+    let mut buf = crate::bytes::BytesMut::with_capacity(response_result_pdu_size(res)?);
+    encode_response_result_pdu(&mut buf, res);
+    Ok(buf.freeze())
 }
 
 impl TryFrom<Bytes> for Request<'static> {
     type Error = Error;
 
     fn try_from(pdu_bytes: Bytes) -> Result<Self, Self::Error> {
-        decode_request_pdu_bytes(&pdu_bytes)
+        decode_request_pdu(&pdu_bytes)
     }
 }
 
@@ -286,153 +78,11 @@ impl TryFrom<Bytes> for RequestPdu<'static> {
     }
 }
 
-// Only needed for responses with a dynamic payload size.
-fn check_response_pdu_size(pdu_size: usize) -> io::Result<()> {
-    if pdu_size > MAX_PDU_SIZE {
-        return Err(io::Error::new(
-            ErrorKind::InvalidInput,
-            "response PDU size exceeded",
-        ));
-    }
-    Ok(())
-}
-
-#[allow(clippy::too_many_lines)] // TODO
-fn decode_response_pdu_bytes(bytes: Bytes) -> io::Result<Response> {
-    use crate::frame::Response::*;
-    let pdu_size = bytes.len();
-    let rdr = &mut Cursor::new(&bytes);
-    let fn_code = rdr.read_u8()?;
-    let response = match fn_code {
-        0x01 => {
-            check_response_pdu_size(pdu_size)?;
-            let byte_count = rdr.read_u8()?;
-            if bytes.len() < 2 + usize::from(byte_count) {
-                return Err(io::Error::new(ErrorKind::InvalidData, "too short"));
-            }
-            let packed_coils = &bytes[2..2 + usize::from(byte_count)];
-            rdr.consume(byte_count.into());
-            // Here we have not information about the exact requested quantity so we just
-            // unpack the whole byte.
-            let quantity = u16::from(byte_count) * 8;
-            ReadCoils(decode_packed_coils(packed_coils, quantity))
-        }
-        0x02 => {
-            check_response_pdu_size(pdu_size)?;
-            let byte_count = rdr.read_u8()?;
-            if bytes.len() < 2 + usize::from(byte_count) {
-                return Err(io::Error::new(ErrorKind::InvalidData, "too short"));
-            }
-            let packed_coils = &bytes[2..2 + usize::from(byte_count)];
-            rdr.consume(byte_count.into());
-            // Here we have no information about the exact requested quantity so we just
-            // unpack the whole byte.
-            let quantity = u16::from(byte_count) * 8;
-            ReadDiscreteInputs(decode_packed_coils(packed_coils, quantity))
-        }
-        0x05 => WriteSingleCoil(read_u16_be(rdr)?, coil_to_bool(read_u16_be(rdr)?)?),
-        0x0F => WriteMultipleCoils(read_u16_be(rdr)?, read_u16_be(rdr)?),
-        0x04 => {
-            check_response_pdu_size(pdu_size)?;
-            let byte_count = rdr.read_u8()?;
-            if byte_count % 2 != 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "invalid quantity",
-                ));
-            }
-            let quantity = byte_count / 2;
-            let mut data = Vec::with_capacity(quantity.into());
-            for _ in 0..quantity {
-                data.push(read_u16_be(rdr)?);
-            }
-            ReadInputRegisters(data)
-        }
-        0x03 => {
-            check_response_pdu_size(pdu_size)?;
-            let byte_count = rdr.read_u8()?;
-            if byte_count % 2 != 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "invalid quantity",
-                ));
-            }
-            let quantity = byte_count / 2;
-            let mut data = Vec::with_capacity(quantity.into());
-            for _ in 0..quantity {
-                data.push(read_u16_be(rdr)?);
-            }
-            ReadHoldingRegisters(data)
-        }
-        0x06 => WriteSingleRegister(read_u16_be(rdr)?, read_u16_be(rdr)?),
-        0x10 => WriteMultipleRegisters(read_u16_be(rdr)?, read_u16_be(rdr)?),
-        0x11 => {
-            check_response_pdu_size(pdu_size)?;
-            let byte_count = rdr.read_u8()?;
-            if byte_count < 2 {
-                return Err(io::Error::new(io::ErrorKind::InvalidData, "too short"));
-            }
-            let data_len = (byte_count - 2).into();
-            let server_id = rdr.read_u8()?;
-            let run_indication_status = match rdr.read_u8()? {
-                0x00 => false,
-                0xFF => true,
-                status => {
-                    return Err(Error::new(
-                        ErrorKind::InvalidData,
-                        format!("invalid run indication status: 0x{status:02X}"),
-                    ));
-                }
-            };
-            let mut data = Vec::with_capacity(data_len);
-            for _ in 0..data_len {
-                data.push(rdr.read_u8()?);
-            }
-            ReportServerId(server_id, run_indication_status, data)
-        }
-        0x16 => {
-            let address = read_u16_be(rdr)?;
-            let and_mask = read_u16_be(rdr)?;
-            let or_mask = read_u16_be(rdr)?;
-            MaskWriteRegister(address, and_mask, or_mask)
-        }
-        0x17 => {
-            check_response_pdu_size(pdu_size)?;
-            let byte_count = rdr.read_u8()?;
-            if byte_count % 2 != 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "invalid quantity",
-                ));
-            }
-            let quantity = byte_count / 2;
-            let mut data = Vec::with_capacity(quantity.into());
-            for _ in 0..quantity {
-                data.push(read_u16_be(rdr)?);
-            }
-            ReadWriteMultipleRegisters(data)
-        }
-        _ => {
-            // Consume all remaining bytes as custom data.
-            let mut bytes = bytes;
-            return Ok(Custom(fn_code, bytes.split_off(1)));
-        }
-    };
-    // Verify that all data has been consumed and decoded.
-    if rdr.has_remaining() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "undecoded response data",
-        ));
-    }
-    Ok(response)
-}
-
 impl TryFrom<Bytes> for Response {
     type Error = Error;
 
     fn try_from(pdu_bytes: Bytes) -> Result<Self, Self::Error> {
-        decode_response_pdu_bytes(pdu_bytes)
+        decode_response_pdu_bytes(pdu_bytes, false, None)
     }
 }
 
@@ -440,20 +90,7 @@ impl TryFrom<Bytes> for ExceptionResponse {
     type Error = Error;
 
     fn try_from(bytes: Bytes) -> Result<Self, Self::Error> {
-        let mut rdr = Cursor::new(&bytes);
-        let fn_err_code = rdr.read_u8()?;
-        if fn_err_code < 0x80 {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "Invalid exception function code",
-            ));
-        }
-        let function = fn_err_code - 0x80;
-        let exception = ExceptionCode::new(rdr.read_u8()?);
-        Ok(ExceptionResponse {
-            function: FunctionCode::new(function),
-            exception,
-        })
+        decode_exception_response_pdu(&bytes)
     }
 }
 
@@ -461,115 +98,8 @@ impl TryFrom<Bytes> for ResponsePdu {
     type Error = Error;
 
     fn try_from(bytes: Bytes) -> Result<Self, Self::Error> {
-        let fn_code = Cursor::new(&bytes).read_u8()?;
-        let pdu = if fn_code < 0x80 {
-            Response::try_from(bytes)?.into()
-        } else {
-            ExceptionResponse::try_from(bytes)?.into()
-        };
-        Ok(pdu)
-    }
-}
-
-#[cfg(any(test, feature = "rtu", feature = "tcp"))]
-fn bool_to_coil(state: bool) -> u16 {
-    if state {
-        0xFF00
-    } else {
-        0x0000
-    }
-}
-
-fn coil_to_bool(coil: u16) -> io::Result<bool> {
-    match coil {
-        0xFF00 => Ok(true),
-        0x0000 => Ok(false),
-        _ => Err(Error::new(ErrorKind::InvalidData, "Invalid coil value: {}")),
-    }
-}
-
-#[cfg(any(test, feature = "rtu", feature = "tcp"))]
-fn packed_coils_size(coils: &[Coil]) -> usize {
-    (coils.len() + 7) / 8
-}
-
-#[cfg(any(test, feature = "rtu", feature = "tcp"))]
-fn encode_packed_coils(buf: &mut crate::bytes::BytesMut, coils: &[Coil]) -> usize {
-    let packed_coils_size = packed_coils_size(coils);
-    let offset = buf.len();
-    buf.resize(offset + packed_coils_size, 0);
-    let buf = &mut buf[offset..];
-    for (i, b) in coils.iter().enumerate() {
-        let v = u8::from(*b); // 0 or 1
-        buf[i / 8] |= v << (i % 8);
-    }
-    packed_coils_size
-}
-
-fn decode_packed_coils(bytes: &[u8], count: u16) -> Vec<Coil> {
-    let mut res = Vec::with_capacity(count.into());
-    for i in 0usize..count.into() {
-        res.push((bytes[i / 8] >> (i % 8)) & 0b1 > 0);
-    }
-    res
-}
-
-#[cfg(any(feature = "rtu", feature = "tcp"))]
-fn request_pdu_size(request: &Request<'_>) -> io::Result<usize> {
-    use crate::frame::Request::*;
-    let size = match request {
-        ReadCoils(_, _)
-        | ReadDiscreteInputs(_, _)
-        | ReadInputRegisters(_, _)
-        | ReadHoldingRegisters(_, _)
-        | WriteSingleRegister(_, _)
-        | WriteSingleCoil(_, _) => 5,
-        WriteMultipleCoils(_, coils) => 6 + packed_coils_size(coils),
-        WriteMultipleRegisters(_, data) => 6 + data.len() * 2,
-        ReportServerId => 1,
-        MaskWriteRegister(_, _, _) => 7,
-        ReadWriteMultipleRegisters(_, _, _, data) => 10 + data.len() * 2,
-        Custom(_, data) => 1 + data.len(),
-    };
-    if size > MAX_PDU_SIZE {
-        return Err(io::Error::new(
-            ErrorKind::InvalidInput,
-            "request PDU size exceeded",
-        ));
-    }
-    Ok(size)
-}
-
-#[cfg(feature = "server")]
-fn response_pdu_size(response: &Response) -> io::Result<usize> {
-    use crate::frame::Response::*;
-    let size = match response {
-        ReadCoils(coils) | ReadDiscreteInputs(coils) => 2 + packed_coils_size(coils),
-        WriteSingleCoil(_, _)
-        | WriteMultipleCoils(_, _)
-        | WriteMultipleRegisters(_, _)
-        | WriteSingleRegister(_, _) => 5,
-        ReadInputRegisters(data)
-        | ReadHoldingRegisters(data)
-        | ReadWriteMultipleRegisters(data) => 2 + data.len() * 2,
-        ReportServerId(_, _, ref data) => 3 + data.len(),
-        MaskWriteRegister(_, _, _) => 7,
-        Custom(_, ref data) => 1 + data.len(),
-    };
-    if size > MAX_PDU_SIZE {
-        return Err(io::Error::new(
-            ErrorKind::InvalidInput,
-            "response PDU size exceeded",
-        ));
-    }
-    Ok(size)
-}
-
-#[cfg(feature = "server")]
-fn response_result_pdu_size(res: &Result<Response, ExceptionResponse>) -> io::Result<usize> {
-    match res {
-        Ok(response) => response_pdu_size(response),
-        Err(_) => Ok(2),
+        let ResponsePdu(result) = decode_response_pdu(bytes, false, ResponseDecoding::Regular)?;
+        Ok(ResponsePdu(result.map(DecodedResponse::into_response)))
     }
 }
 
@@ -578,10 +108,33 @@ mod tests {
 
     use std::borrow::Cow;
 
-    use crate::bytes::BytesMut;
+    use crate::{
+        bytes::BytesMut, frame::Coil, DeviceIdObjectId, DiagnosticsSubFunction, ExceptionCode,
+        FunctionCode, ReadDeviceIdCode, ReadDeviceIdentificationResponse,
+    };
 
     use super::*;
 
+    /// Checks that encoding appends the same bytes independent of the
+    /// capacity and the previous contents of the buffer.
+    #[cfg(any(feature = "rtu", feature = "tcp"))]
+    pub(crate) fn assert_encoding_into_dirty_buffers(mut encode: impl FnMut(&mut BytesMut)) {
+        const DIRT: u8 = 0xA5;
+        let mut expected = BytesMut::new();
+        encode(&mut expected);
+        for capacity in 0..=expected.len() * 2 {
+            for prefix_len in [0, 1, capacity / 2, capacity] {
+                let mut buf = BytesMut::with_capacity(capacity);
+                // Dirty the spare capacity as well.
+                buf.resize(capacity.max(prefix_len), DIRT);
+                buf.truncate(prefix_len);
+                encode(&mut buf);
+                assert!(buf[..prefix_len].iter().all(|&byte| byte == DIRT));
+                assert_eq!(buf[prefix_len..], expected[..]);
+            }
+        }
+    }
+
     fn encode_request_pdu_to_bytes(request: &Request<'_>) -> Bytes {
         let mut buf = BytesMut::new();
         encode_request_pdu(&mut buf, request);
@@ -885,6 +438,27 @@ mod tests {
             assert_eq!(bytes[13], 0x12);
         }
 
+        #[test]
+        fn read_fifo_queue() {
+            let bytes = encode_request_pdu_to_bytes(&Request::ReadFifoQueue(0x04DE));
+            assert_eq!(&bytes[..], &[0x18, 0x04, 0xDE]);
+        }
+
+        #[test]
+        fn read_exception_status() {
+            let bytes = encode_request_pdu_to_bytes(&Request::ReadExceptionStatus);
+            assert_eq!(&bytes[..], &[0x07]);
+        }
+
+        #[test]
+        fn read_device_identification() {
+            let bytes = encode_request_pdu_to_bytes(&Request::ReadDeviceIdentification(
+                ReadDeviceIdCode::Basic,
+                DeviceIdObjectId::VendorName,
+            ));
+            assert_eq!(&bytes[..], &[0x2B, 0x0E, 0x01, 0x00]);
+        }
+
         #[test]
         fn custom() {
             let bytes = encode_request_pdu_to_bytes(&Request::Custom(
@@ -944,6 +518,17 @@ mod tests {
                 0b_0000_1101,
             ]))
             .is_err());
+            // More coils than packed bytes
+            assert!(Request::try_from(Bytes::from(vec![
+                0x0F,
+                0x33,
+                0x11,
+                0x00,
+                0x09,
+                0x01,
+                0b_0000_1101,
+            ]))
+            .is_err());
 
             let bytes = Bytes::from(vec![0x0F, 0x33, 0x11, 0x00, 0x04, 0x01, 0b_0000_1101]);
             let req = Request::try_from(bytes).unwrap();
@@ -980,6 +565,10 @@ mod tests {
                 0x10, 0x00, 0x06, 0x00, 0x02, 0x05, 0xAB, 0xCD, 0xEF, 0x12,
             ]))
             .is_err());
+            // The doubled quantity exceeds 16 bits.
+            assert!(
+                Request::try_from(Bytes::from(vec![0x10, 0x00, 0x06, 0x80, 0x00, 0x00])).is_err()
+            );
 
             let bytes = Bytes::from(vec![
                 0x10, 0x00, 0x06, 0x00, 0x02, 0x04, 0xAB, 0xCD, 0xEF, 0x12,
@@ -1022,6 +611,42 @@ mod tests {
             );
         }
 
+        #[test]
+        fn read_fifo_queue() {
+            let bytes = Bytes::from(vec![0x18, 0x04, 0xDE]);
+            let req = Request::try_from(bytes).unwrap();
+            assert_eq!(req, Request::ReadFifoQueue(0x04DE));
+        }
+
+        #[test]
+        fn read_exception_status() {
+            let bytes = Bytes::from(vec![0x07]);
+            let req = Request::try_from(bytes).unwrap();
+            assert_eq!(req, Request::ReadExceptionStatus);
+        }
+
+        #[test]
+        fn read_device_identification() {
+            let bytes = Bytes::from(vec![0x2B, 0x0E, 0x04, 0x81]);
+            let req = Request::try_from(bytes).unwrap();
+            assert_eq!(
+                req,
+                Request::ReadDeviceIdentification(
+                    ReadDeviceIdCode::Specific,
+                    DeviceIdObjectId::Custom(0x81)
+                )
+            );
+
+            // Invalid read device id code
+            let bytes = Bytes::from(vec![0x2B, 0x0E, 0x05, 0x00]);
+            assert!(Request::try_from(bytes).is_err());
+
+            // Other MEI types are custom requests
+            let bytes = Bytes::from(vec![0x2B, 0x0D, 0x01]);
+            let req = Request::try_from(bytes).unwrap();
+            assert_eq!(req, Request::Custom(0x2B, Cow::Borrowed(&[0x0D, 0x01])));
+        }
+
         #[test]
         fn custom() {
             let bytes = Bytes::from(vec![0x55, 0xCC, 0x88, 0xAA, 0xFF]);
@@ -1126,11 +751,10 @@ mod tests {
 
         #[test]
         fn report_server_id() {
-            let bytes = encode_response_pdu_to_bytes(&Response::ReportServerId(
-                0x42,
-                true,
-                vec![0x10, 0x20],
-            ));
+            let response = Response::ReportServerId(0x42, true, vec![0x10, 0x20]);
+            let bytes = encode_response_pdu_to_bytes(&response);
+            #[cfg(feature = "server")]
+            assert_eq!(response_pdu_size(&response).unwrap(), bytes.len());
             assert_eq!(bytes[0], 0x11);
             assert_eq!(bytes[1], 0x04);
             assert_eq!(bytes[2], 0x42);
@@ -1162,6 +786,45 @@ mod tests {
             assert_eq!(bytes[3], 0x34);
         }
 
+        #[test]
+        fn read_fifo_queue() {
+            let bytes =
+                encode_response_pdu_to_bytes(&Response::ReadFifoQueue(vec![0x01B8, 0x1284]));
+            assert_eq!(
+                &bytes[..],
+                &[0x18, 0x00, 0x06, 0x00, 0x02, 0x01, 0xB8, 0x12, 0x84]
+            );
+        }
+
+        #[test]
+        fn read_exception_status() {
+            let bytes = encode_response_pdu_to_bytes(&Response::ReadExceptionStatus(0x6D));
+            assert_eq!(&bytes[..], &[0x07, 0x6D]);
+        }
+
+        #[test]
+        fn read_device_identification() {
+            let bytes = encode_response_pdu_to_bytes(&Response::ReadDeviceIdentification(
+                ReadDeviceIdentificationResponse {
+                    read_device_id_code: ReadDeviceIdCode::Basic,
+                    conformity_level: 0x81,
+                    more_follows: true,
+                    next_object_id: DeviceIdObjectId::MajorMinorRevision,
+                    objects: vec![
+                        (DeviceIdObjectId::VendorName, b"AB".to_vec()),
+                        (DeviceIdObjectId::ProductCode, b"C".to_vec()),
+                    ],
+                },
+            ));
+            assert_eq!(
+                &bytes[..],
+                &[
+                    0x2B, 0x0E, 0x01, 0x81, 0xFF, 0x02, 0x02, 0x00, 0x02, b'A', b'B', 0x01, 0x01,
+                    b'C'
+                ]
+            );
+        }
+
         #[test]
         fn custom() {
             let bytes = encode_response_pdu_to_bytes(&Response::Custom(
@@ -1190,6 +853,77 @@ mod tests {
             );
         }
 
+        #[test]
+        fn read_requested_quantity_of_coils() {
+            let decode = |bytes: &'static [u8]| {
+                decode_response_pdu(Bytes::from_static(bytes), false, ResponseDecoding::Bits(4))
+                    .unwrap()
+                    .0
+                    .map(DecodedResponse::into_response)
+            };
+            assert_eq!(
+                decode(&[1, 1, 0b_0000_1001]),
+                Ok(Response::ReadCoils(vec![true, false, false, true]))
+            );
+            assert_eq!(
+                decode(&[2, 1, 0b_0000_0110]),
+                Ok(Response::ReadDiscreteInputs(vec![false, true, true, false]))
+            );
+            // Mismatching byte count
+            assert_eq!(
+                decode(&[1, 2, 0b_0000_1001, 0b_0000_0001]),
+                Ok(Response::ReadCoils(
+                    [
+                        [true, false, false, true, false, false, false, false],
+                        [true, false, false, false, false, false, false, false]
+                    ]
+                    .concat()
+                ))
+            );
+        }
+
+        #[test]
+        fn read_registers_into_views() {
+            let decode = |bytes: &'static [u8], ignore_trailing_bytes| {
+                decode_response_pdu(
+                    Bytes::from_static(bytes),
+                    ignore_trailing_bytes,
+                    ResponseDecoding::Registers,
+                )
+                .map(|pdu| pdu.0)
+            };
+            let registers = |bytes| crate::data::Registers::from_bytes(Bytes::from_static(bytes));
+            assert_eq!(
+                decode(&[0x03, 0x04, 0x12, 0x34, 0x56, 0x78], false).unwrap(),
+                Ok(DecodedResponse::Registers(
+                    FunctionCode::ReadHoldingRegisters,
+                    registers(&[0x12, 0x34, 0x56, 0x78]).unwrap()
+                ))
+            );
+            assert_eq!(
+                decode(&[0x04, 0x02, 0x00, 0x01, 0xFF], true).unwrap(),
+                Ok(DecodedResponse::Registers(
+                    FunctionCode::ReadInputRegisters,
+                    registers(&[0x00, 0x01]).unwrap()
+                ))
+            );
+            assert!(decode(&[0x04, 0x02, 0x00, 0x01, 0xFF], false).is_err());
+            assert!(decode(&[0x03, 0x03, 0x00, 0x01, 0x02], false).is_err());
+            assert!(decode(&[0x03, 0x04, 0x00, 0x01], false).is_err());
+            // Other responses are decoded regularly.
+            assert_eq!(
+                decode(&[0x83, 0x02], false).unwrap(),
+                Err(ExceptionResponse {
+                    function: FunctionCode::ReadHoldingRegisters,
+                    exception: ExceptionCode::IllegalDataAddress,
+                })
+            );
+            assert_eq!(
+                decode(&[0x06, 0x00, 0x01, 0x00, 0x02], false).unwrap(),
+                Ok(Response::WriteSingleRegister(1, 2).into())
+            );
+        }
+
         #[test]
         fn read_coils_max_quantity() {
             let quantity = 2000;
@@ -1298,6 +1032,73 @@ mod tests {
             assert_eq!(response, Response::ReadWriteMultipleRegisters(vec![0x1234]));
         }
 
+        #[test]
+        fn read_fifo_queue() {
+            let bytes = Bytes::from(vec![0x18, 0x00, 0x06, 0x00, 0x02, 0x01, 0xB8, 0x12, 0x84]);
+            let response = Response::try_from(bytes).unwrap();
+            assert_eq!(response, Response::ReadFifoQueue(vec![0x01B8, 0x1284]));
+
+            // Byte count doesn't match the FIFO count
+            let bytes = Bytes::from(vec![0x18, 0x00, 0x04, 0x00, 0x02, 0x01, 0xB8, 0x12, 0x84]);
+            assert!(Response::try_from(bytes).is_err());
+        }
+
+        #[test]
+        fn read_exception_status() {
+            let bytes = Bytes::from(vec![0x07, 0x6D]);
+            let response = Response::try_from(bytes).unwrap();
+            assert_eq!(response, Response::ReadExceptionStatus(0x6D));
+
+            // Missing status byte
+            let bytes = Bytes::from(vec![0x07]);
+            assert!(Response::try_from(bytes).is_err());
+        }
+
+        #[test]
+        fn read_device_identification() {
+            let bytes = Bytes::from(vec![
+                0x2B, 0x0E, 0x01, 0x81, 0x00, 0x00, 0x02, 0x00, 0x02, b'A', b'B', 0x01, 0x01, b'C',
+            ]);
+            let response = Response::try_from(bytes).unwrap();
+            assert_eq!(
+                response,
+                Response::ReadDeviceIdentification(ReadDeviceIdentificationResponse {
+                    read_device_id_code: ReadDeviceIdCode::Basic,
+                    conformity_level: 0x81,
+                    more_follows: false,
+                    next_object_id: DeviceIdObjectId::VendorName,
+                    objects: vec![
+                        (DeviceIdObjectId::VendorName, b"AB".to_vec()),
+                        (DeviceIdObjectId::ProductCode, b"C".to_vec()),
+                    ],
+                })
+            );
+
+            // Truncated object value
+            let bytes = Bytes::from(vec![
+                0x2B, 0x0E, 0x01, 0x81, 0x00, 0x00, 0x01, 0x00, 0x02, b'A',
+            ]);
+            assert!(Response::try_from(bytes).is_err());
+
+            // Invalid more follows
+            let bytes = Bytes::from(vec![0x2B, 0x0E, 0x01, 0x81, 0x01, 0x00, 0x00]);
+            assert!(Response::try_from(bytes).is_err());
+        }
+
+        #[test]
+        fn diagnostics() {
+            let bytes = Bytes::from(vec![0x08, 0x00, 0x0B, 0x01, 0x08]);
+            let response = Response::try_from(bytes).unwrap();
+            assert_eq!(
+                response,
+                Response::Diagnostics(DiagnosticsSubFunction::ReturnBusMessageCount, vec![0x0108])
+            );
+
+            // Incomplete data word
+            let bytes = Bytes::from(vec![0x08, 0x00, 0x0B, 0x01]);
+            assert!(Response::try_from(bytes).is_err());
+        }
+
         #[test]
         fn custom() {
             let bytes = Bytes::from(vec![0x55, 0xCC, 0x88, 0xAA, 0xFF]);
@@ -0,0 +1,856 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Encoding and decoding of PDUs
+//!
+//! Shared by the codecs of all transports. Doesn't perform any I/O.
+
+use std::io::{Error, ErrorKind, Result};
+
+use crate::{
+    bytes::Bytes,
+    data::Registers,
+    frame::{Coil, DecodedResponse, Quantity, ResponsePdu, Word},
+    DeviceIdObjectId, DiagnosticsSubFunction, ExceptionCode, ExceptionResponse, FunctionCode,
+    ReadDeviceIdCode, ReadDeviceIdentificationResponse, Request, Response,
+};
+
+/// Maximum request/response PDU size.
+///
+/// As defined by the spec for both RTU and TCP.
+pub(crate) const MAX_PDU_SIZE: usize = 253;
+
+/// The maximum number of values in a FIFO queue that can be read at once.
+pub(crate) const MAX_FIFO_COUNT: usize = 31;
+
+/// MEI type of Read Device Identification requests and responses.
+pub(crate) const MEI_READ_DEVICE_ID: u8 = 0x0E;
+
+/// Reads big-endian values from the bytes of a PDU.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    const fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    const fn has_remaining(&self) -> bool {
+        !self.bytes.is_empty()
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        }
+        let (bytes, remaining) = self.bytes.split_at(len);
+        self.bytes = remaining;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        let bytes = self.read_bytes(1)?;
+        Ok(bytes[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_words(&mut self, count: impl Into<usize>) -> Result<Vec<Word>> {
+        let count = count.into();
+        let mut words = Vec::with_capacity(count);
+        for _ in 0..count {
+            words.push(self.read_u16()?);
+        }
+        Ok(words)
+    }
+}
+
+#[cfg(any(test, feature = "rtu", feature = "tcp"))]
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn u16_len(len: usize) -> u16 {
+    // This type conversion should always be safe, because either
+    // the caller is responsible to pass a valid usize or the
+    // possible values are limited by the protocol.
+    debug_assert!(len <= u16::MAX.into());
+    len as u16
+}
+
+#[cfg(any(test, feature = "rtu", feature = "tcp"))]
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn u8_len(len: usize) -> u8 {
+    // This type conversion should always be safe, because either
+    // the caller is responsible to pass a valid usize or the
+    // possible values are limited by the protocol.
+    debug_assert!(len <= u8::MAX.into());
+    len as u8
+}
+
+#[cfg(any(test, feature = "rtu", feature = "tcp"))]
+pub(crate) fn encode_request_pdu(buf: &mut crate::bytes::BytesMut, request: &Request<'_>) {
+    use crate::{bytes::BufMut as _, frame::Request::*};
+    buf.put_u8(request.function_code().value());
+    match request {
+        ReadCoils(address, quantity)
+        | ReadDiscreteInputs(address, quantity)
+        | ReadInputRegisters(address, quantity)
+        | ReadHoldingRegisters(address, quantity) => {
+            buf.put_u16(*address);
+            buf.put_u16(*quantity);
+        }
+        WriteSingleCoil(address, state) => {
+            buf.put_u16(*address);
+            buf.put_u16(bool_to_coil(*state));
+        }
+        WriteMultipleCoils(address, coils) => {
+            buf.put_u16(*address);
+            buf.put_u16(u16_len(coils.len()));
+            buf.put_u8(u8_len(packed_coils_size(coils)));
+            encode_packed_coils(buf, coils);
+        }
+        WriteSingleRegister(address, word) => {
+            buf.put_u16(*address);
+            buf.put_u16(*word);
+        }
+        WriteMultipleRegisters(address, words) => {
+            buf.put_u16(*address);
+            let len = words.len();
+            buf.put_u16(u16_len(len));
+            buf.put_u8(u8_len(len * 2));
+            for w in words.as_ref() {
+                buf.put_u16(*w);
+            }
+        }
+        MaskWriteRegister(address, and_mask, or_mask) => {
+            buf.put_u16(*address);
+            buf.put_u16(*and_mask);
+            buf.put_u16(*or_mask);
+        }
+        ReadWriteMultipleRegisters(read_address, quantity, write_address, words) => {
+            buf.put_u16(*read_address);
+            buf.put_u16(*quantity);
+            buf.put_u16(*write_address);
+            let len = words.len();
+            buf.put_u16(u16_len(len));
+            buf.put_u8(u8_len(len * 2));
+            for w in words.as_ref() {
+                buf.put_u16(*w);
+            }
+        }
+        ReadFifoQueue(address) => {
+            buf.put_u16(*address);
+        }
+        ReportServerId | ReadExceptionStatus => {}
+        ReadDeviceIdentification(code, object_id) => {
+            buf.put_u8(MEI_READ_DEVICE_ID);
+            buf.put_u8(code.value());
+            buf.put_u8(object_id.value());
+        }
+        Diagnostics(sub_function, words) => {
+            buf.put_u16(sub_function.value());
+            for w in words.as_ref() {
+                buf.put_u16(*w);
+            }
+        }
+        Custom(_, custom_data) => {
+            buf.put_slice(custom_data.as_ref());
+        }
+    }
+}
+
+#[cfg(any(test, feature = "server"))]
+pub(crate) fn encode_response_pdu(buf: &mut crate::bytes::BytesMut, response: &Response) {
+    use crate::{bytes::BufMut as _, frame::Response::*};
+    buf.put_u8(response.function_code().value());
+    match response {
+        ReadCoils(coils) | ReadDiscreteInputs(coils) => {
+            buf.put_u8(u8_len(packed_coils_size(coils)));
+            encode_packed_coils(buf, coils);
+        }
+        ReadInputRegisters(registers)
+        | ReadHoldingRegisters(registers)
+        | ReadWriteMultipleRegisters(registers) => {
+            buf.put_u8(u8_len(registers.len() * 2));
+            for r in registers {
+                buf.put_u16(*r);
+            }
+        }
+        WriteSingleCoil(address, state) => {
+            buf.put_u16(*address);
+            buf.put_u16(bool_to_coil(*state));
+        }
+        WriteMultipleCoils(address, quantity) | WriteMultipleRegisters(address, quantity) => {
+            buf.put_u16(*address);
+            buf.put_u16(*quantity);
+        }
+        ReportServerId(server_id, run_indication, additional_data) => {
+            buf.put_u8(2 + u8_len(additional_data.len()));
+            buf.put_u8(*server_id);
+            buf.put_u8(if *run_indication { 0xFF } else { 0x00 });
+            buf.put_slice(additional_data);
+        }
+        WriteSingleRegister(address, word) => {
+            buf.put_u16(*address);
+            buf.put_u16(*word);
+        }
+        MaskWriteRegister(address, and_mask, or_mask) => {
+            buf.put_u16(*address);
+            buf.put_u16(*and_mask);
+            buf.put_u16(*or_mask);
+        }
+        ReadFifoQueue(words) => {
+            let len = words.len();
+            buf.put_u16(u16_len(2 + len * 2));
+            buf.put_u16(u16_len(len));
+            for w in words {
+                buf.put_u16(*w);
+            }
+        }
+        ReadExceptionStatus(status) => {
+            buf.put_u8(*status);
+        }
+        ReadDeviceIdentification(response) => {
+            let ReadDeviceIdentificationResponse {
+                read_device_id_code,
+                conformity_level,
+                more_follows,
+                next_object_id,
+                objects,
+            } = response;
+            buf.put_u8(MEI_READ_DEVICE_ID);
+            buf.put_u8(read_device_id_code.value());
+            buf.put_u8(*conformity_level);
+            buf.put_u8(if *more_follows { 0xFF } else { 0x00 });
+            buf.put_u8(next_object_id.value());
+            buf.put_u8(u8_len(objects.len()));
+            for (id, value) in objects {
+                buf.put_u8(id.value());
+                buf.put_u8(u8_len(value.len()));
+                buf.put_slice(value);
+            }
+        }
+        Diagnostics(sub_function, words) => {
+            buf.put_u16(sub_function.value());
+            for w in words {
+                buf.put_u16(*w);
+            }
+        }
+        Custom(_, custom_data) => {
+            buf.put_slice(custom_data);
+        }
+    }
+}
+
+#[cfg(any(test, feature = "server"))]
+pub(crate) fn encode_exception_response_pdu(
+    buf: &mut crate::bytes::BytesMut,
+    response: ExceptionResponse,
+) {
+    use crate::bytes::BufMut as _;
+    debug_assert!(response.function.value() < 0x80);
+    buf.put_u8(response.function.value() + 0x80);
+    buf.put_u8(response.exception.into());
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn encode_response_result_pdu(
+    buf: &mut crate::bytes::BytesMut,
+    res: &std::result::Result<Response, ExceptionResponse>,
+) {
+    match res {
+        Ok(response) => encode_response_pdu(buf, response),
+        Err(response) => encode_exception_response_pdu(buf, *response),
+    }
+}
+
+// Only needed for requests with a dynamic payload size.
+fn check_request_pdu_size(pdu_size: usize) -> Result<()> {
+    if pdu_size > MAX_PDU_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "request PDU size exceeded",
+        ));
+    }
+    Ok(())
+}
+
+/// Decode a request PDU.
+#[allow(clippy::too_many_lines)] // TODO
+pub(crate) fn decode_request_pdu(bytes: &[u8]) -> Result<Request<'static>> {
+    use crate::frame::Request::*;
+    let pdu_size = bytes.len();
+    let rdr = &mut Reader::new(bytes);
+    let fn_code = rdr.read_u8()?;
+    let req = match fn_code {
+        0x01 => ReadCoils(rdr.read_u16()?, rdr.read_u16()?),
+        0x02 => ReadDiscreteInputs(rdr.read_u16()?, rdr.read_u16()?),
+        0x05 => WriteSingleCoil(rdr.read_u16()?, coil_to_bool(rdr.read_u16()?)?),
+        0x0F => {
+            check_request_pdu_size(pdu_size)?;
+            let address = rdr.read_u16()?;
+            let quantity = rdr.read_u16()?;
+            let byte_count = usize::from(rdr.read_u8()?);
+            if byte_count != usize::from(quantity).div_ceil(8) {
+                return Err(Error::new(ErrorKind::InvalidData, "invalid quantity"));
+            }
+            let packed_coils = rdr
+                .read_bytes(byte_count)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "too short"))?;
+            WriteMultipleCoils(address, decode_packed_coils(packed_coils, quantity).into())
+        }
+        0x04 => ReadInputRegisters(rdr.read_u16()?, rdr.read_u16()?),
+        0x03 => ReadHoldingRegisters(rdr.read_u16()?, rdr.read_u16()?),
+        0x06 => WriteSingleRegister(rdr.read_u16()?, rdr.read_u16()?),
+        0x10 => {
+            check_request_pdu_size(pdu_size)?;
+            let address = rdr.read_u16()?;
+            let quantity = rdr.read_u16()?;
+            let byte_count = rdr.read_u8()?;
+            if usize::from(byte_count) != usize::from(quantity) * 2 {
+                return Err(Error::new(ErrorKind::InvalidData, "invalid quantity"));
+            }
+            WriteMultipleRegisters(address, rdr.read_words(quantity)?.into())
+        }
+        0x11 => ReportServerId,
+        0x16 => {
+            let address = rdr.read_u16()?;
+            let and_mask = rdr.read_u16()?;
+            let or_mask = rdr.read_u16()?;
+            MaskWriteRegister(address, and_mask, or_mask)
+        }
+        0x17 => {
+            check_request_pdu_size(pdu_size)?;
+            let read_address = rdr.read_u16()?;
+            let read_quantity = rdr.read_u16()?;
+            let write_address = rdr.read_u16()?;
+            let write_quantity = rdr.read_u16()?;
+            let write_count = rdr.read_u8()?;
+            if usize::from(write_count) != usize::from(write_quantity) * 2 {
+                return Err(Error::new(ErrorKind::InvalidData, "invalid write quantity"));
+            }
+            let data = rdr.read_words(write_quantity)?;
+            ReadWriteMultipleRegisters(read_address, read_quantity, write_address, data.into())
+        }
+        0x18 => ReadFifoQueue(rdr.read_u16()?),
+        0x07 => ReadExceptionStatus,
+        0x2B if bytes.get(1) == Some(&MEI_READ_DEVICE_ID) => {
+            rdr.read_u8()?;
+            let code = rdr.read_u8()?;
+            let Some(code) = ReadDeviceIdCode::new(code) else {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid read device id code: 0x{code:02X}"),
+                ));
+            };
+            ReadDeviceIdentification(code, DeviceIdObjectId::new(rdr.read_u8()?))
+        }
+        0x08 => {
+            check_request_pdu_size(pdu_size)?;
+            let (sub_function, data) = decode_diagnostics(rdr)?;
+            Diagnostics(sub_function, data.into())
+        }
+        fn_code if fn_code < 0x80 => {
+            // Consume all remaining bytes as custom data.
+            return Ok(Custom(fn_code, bytes[1..].to_vec().into()));
+        }
+        fn_code => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid function code: 0x{fn_code:02X}"),
+            ));
+        }
+    };
+    // Verify that all data has been consumed and decoded.
+    if rdr.has_remaining() {
+        return Err(Error::new(ErrorKind::InvalidData, "undecoded request data"));
+    }
+    Ok(req)
+}
+
+/// Decode the sub-function and the data words of a diagnostics PDU.
+fn decode_diagnostics(rdr: &mut Reader<'_>) -> Result<(DiagnosticsSubFunction, Vec<Word>)> {
+    let sub_function = DiagnosticsSubFunction::new(rdr.read_u16()?);
+    if rdr.remaining() % 2 != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "invalid diagnostics data",
+        ));
+    }
+    let data = rdr.read_words(rdr.remaining() / 2)?;
+    Ok((sub_function, data))
+}
+
+/// Decode the objects of a device identification response PDU.
+fn decode_device_id_objects(rdr: &mut Reader<'_>) -> Result<Vec<(DeviceIdObjectId, Vec<u8>)>> {
+    let count = rdr.read_u8()?;
+    let mut objects = Vec::with_capacity(count.into());
+    for _ in 0..count {
+        let id = DeviceIdObjectId::new(rdr.read_u8()?);
+        let len = usize::from(rdr.read_u8()?);
+        let value = rdr
+            .read_bytes(len)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "too short"))?;
+        objects.push((id, value.to_vec()));
+    }
+    Ok(objects)
+}
+
+// Only needed for responses with a dynamic payload size.
+fn check_response_pdu_size(pdu_size: usize) -> Result<()> {
+    if pdu_size > MAX_PDU_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "response PDU size exceeded",
+        ));
+    }
+    Ok(())
+}
+
+/// Decode the packed coils or discrete inputs of a read response.
+fn decode_packed_coils_response(
+    rdr: &mut Reader<'_>,
+    requested_count: Option<Quantity>,
+) -> Result<Vec<Coil>> {
+    let byte_count = rdr.read_u8()?;
+    let packed_coils = rdr
+        .read_bytes(byte_count.into())
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "too short"))?;
+    let quantity = match requested_count {
+        Some(count) if usize::from(byte_count) == usize::from(count).div_ceil(8) => count,
+        // Without the requested quantity or if the byte count doesn't
+        // match it we just unpack the whole bytes. The client rejects
+        // a mismatching number of coils.
+        _ => u16::from(byte_count) * 8,
+    };
+    Ok(decode_packed_coils(packed_coils, quantity))
+}
+
+/// Decode the words of a read response.
+fn decode_words_response(rdr: &mut Reader<'_>) -> Result<Vec<Word>> {
+    let byte_count = rdr.read_u8()?;
+    if byte_count % 2 != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "invalid quantity"));
+    }
+    rdr.read_words(byte_count / 2)
+}
+
+/// Decode a regular response PDU.
+///
+/// Trailing bytes are either ignored and logged or rejected as invalid data.
+#[allow(clippy::too_many_lines)] // TODO
+///
+/// The requested number of coils or discrete inputs is unpacked if known.
+pub(crate) fn decode_response_pdu_bytes(
+    bytes: Bytes,
+    ignore_trailing_bytes: bool,
+    bit_count: Option<Quantity>,
+) -> Result<Response> {
+    use crate::frame::Response::*;
+    let pdu_size = bytes.len();
+    let rdr = &mut Reader::new(&bytes);
+    let fn_code = rdr.read_u8()?;
+    let response = match fn_code {
+        0x01 => {
+            check_response_pdu_size(pdu_size)?;
+            ReadCoils(decode_packed_coils_response(rdr, bit_count)?)
+        }
+        0x02 => {
+            check_response_pdu_size(pdu_size)?;
+            ReadDiscreteInputs(decode_packed_coils_response(rdr, bit_count)?)
+        }
+        0x05 => WriteSingleCoil(rdr.read_u16()?, coil_to_bool(rdr.read_u16()?)?),
+        0x0F => WriteMultipleCoils(rdr.read_u16()?, rdr.read_u16()?),
+        0x04 => {
+            check_response_pdu_size(pdu_size)?;
+            ReadInputRegisters(decode_words_response(rdr)?)
+        }
+        0x03 => {
+            check_response_pdu_size(pdu_size)?;
+            ReadHoldingRegisters(decode_words_response(rdr)?)
+        }
+        0x06 => WriteSingleRegister(rdr.read_u16()?, rdr.read_u16()?),
+        0x10 => WriteMultipleRegisters(rdr.read_u16()?, rdr.read_u16()?),
+        0x11 => {
+            check_response_pdu_size(pdu_size)?;
+            let byte_count = rdr.read_u8()?;
+            if byte_count < 2 {
+                return Err(Error::new(ErrorKind::InvalidData, "too short"));
+            }
+            let data_len = (byte_count - 2).into();
+            let server_id = rdr.read_u8()?;
+            let run_indication_status = match rdr.read_u8()? {
+                0x00 => false,
+                0xFF => true,
+                status => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("invalid run indication status: 0x{status:02X}"),
+                    ));
+                }
+            };
+            let data = rdr.read_bytes(data_len)?.to_vec();
+            ReportServerId(server_id, run_indication_status, data)
+        }
+        0x16 => {
+            let address = rdr.read_u16()?;
+            let and_mask = rdr.read_u16()?;
+            let or_mask = rdr.read_u16()?;
+            MaskWriteRegister(address, and_mask, or_mask)
+        }
+        0x17 => {
+            check_response_pdu_size(pdu_size)?;
+            ReadWriteMultipleRegisters(decode_words_response(rdr)?)
+        }
+        0x18 => {
+            check_response_pdu_size(pdu_size)?;
+            let byte_count = rdr.read_u16()?;
+            let fifo_count = rdr.read_u16()?;
+            if usize::from(fifo_count) > MAX_FIFO_COUNT
+                || usize::from(byte_count) != 2 + usize::from(fifo_count) * 2
+            {
+                return Err(Error::new(ErrorKind::InvalidData, "invalid FIFO count"));
+            }
+            ReadFifoQueue(rdr.read_words(fifo_count)?)
+        }
+        0x07 => ReadExceptionStatus(rdr.read_u8()?),
+        0x2B if bytes.get(1) == Some(&MEI_READ_DEVICE_ID) => {
+            check_response_pdu_size(pdu_size)?;
+            rdr.read_u8()?;
+            let code = rdr.read_u8()?;
+            let Some(read_device_id_code) = ReadDeviceIdCode::new(code) else {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid read device id code: 0x{code:02X}"),
+                ));
+            };
+            let conformity_level = rdr.read_u8()?;
+            let more_follows = match rdr.read_u8()? {
+                0x00 => false,
+                0xFF => true,
+                more_follows => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("invalid more follows: 0x{more_follows:02X}"),
+                    ));
+                }
+            };
+            let next_object_id = DeviceIdObjectId::new(rdr.read_u8()?);
+            let objects = decode_device_id_objects(rdr)?;
+            ReadDeviceIdentification(ReadDeviceIdentificationResponse {
+                read_device_id_code,
+                conformity_level,
+                more_follows,
+                next_object_id,
+                objects,
+            })
+        }
+        0x08 => {
+            check_response_pdu_size(pdu_size)?;
+            let (sub_function, data) = decode_diagnostics(rdr)?;
+            Diagnostics(sub_function, data)
+        }
+        _ => {
+            // Consume all remaining bytes as custom data.
+            let mut bytes = bytes;
+            return Ok(Custom(fn_code, bytes.split_off(1)));
+        }
+    };
+    // Verify that all data has been consumed and decoded.
+    if rdr.has_remaining() {
+        let trailing_bytes = &bytes[bytes.len() - rdr.remaining()..];
+        if !ignore_trailing_bytes {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "undecoded response data",
+            ));
+        }
+        log::warn!(
+            "Ignoring {count} undecoded trailing byte(s) of response {response:?}: {trailing_bytes:02X?}",
+            count = trailing_bytes.len()
+        );
+    }
+    Ok(response)
+}
+
+/// Decode an exception response PDU.
+pub(crate) fn decode_exception_response_pdu(bytes: &[u8]) -> Result<ExceptionResponse> {
+    let mut rdr = Reader::new(bytes);
+    let fn_err_code = rdr.read_u8()?;
+    if fn_err_code < 0x80 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Invalid exception function code",
+        ));
+    }
+    let function = fn_err_code - 0x80;
+    let exception = ExceptionCode::new(rdr.read_u8()?);
+    Ok(ExceptionResponse {
+        function: FunctionCode::new(function),
+        exception,
+    })
+}
+
+/// How to decode the response to a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(not(any(feature = "rtu", feature = "tcp")), allow(dead_code))]
+pub(crate) enum ResponseDecoding {
+    /// Decode the response by its function code.
+    #[default]
+    Regular,
+
+    /// Unpack the requested number of coils or discrete inputs.
+    Bits(Quantity),
+
+    /// Don't decode the data of the response to a custom request.
+    Raw,
+
+    /// Access the registers that have been read in the received frame.
+    Registers,
+}
+
+impl ResponseDecoding {
+    #[cfg(any(feature = "rtu", feature = "tcp"))]
+    pub(crate) const fn of_request(request: &Request<'_>, register_views: bool) -> Self {
+        match request {
+            Request::ReadCoils(_, cnt) | Request::ReadDiscreteInputs(_, cnt) => Self::Bits(*cnt),
+            Request::ReadInputRegisters(..) | Request::ReadHoldingRegisters(..)
+                if register_views =>
+            {
+                Self::Registers
+            }
+            Request::Custom(..) => Self::Raw,
+            _ => Self::Regular,
+        }
+    }
+}
+
+/// Decode a response PDU.
+///
+/// Trailing bytes after a regular response are either
+/// ignored and logged or rejected as invalid data.
+pub(crate) fn decode_response_pdu(
+    bytes: Bytes,
+    ignore_trailing_bytes: bool,
+    decoding: ResponseDecoding,
+) -> Result<ResponsePdu<DecodedResponse>> {
+    let bit_count = match decoding {
+        ResponseDecoding::Regular => None,
+        ResponseDecoding::Bits(count) => Some(count),
+        ResponseDecoding::Raw => return decode_custom_response_pdu(bytes).map(Into::into),
+        ResponseDecoding::Registers => {
+            return decode_registers_view_response_pdu(bytes, ignore_trailing_bytes)
+        }
+    };
+    let fn_code = Reader::new(&bytes).read_u8()?;
+    let pdu = if fn_code < 0x80 {
+        ResponsePdu(Ok(decode_response_pdu_bytes(
+            bytes,
+            ignore_trailing_bytes,
+            bit_count,
+        )?
+        .into()))
+    } else {
+        decode_exception_response_pdu(&bytes)?.into()
+    };
+    Ok(pdu)
+}
+
+/// Decode the response PDU of reading registers (0x03/0x04) to a view
+/// that shares the memory of `bytes`.
+///
+/// Responses of other functions are decoded regularly.
+fn decode_registers_view_response_pdu(
+    bytes: Bytes,
+    ignore_trailing_bytes: bool,
+) -> Result<ResponsePdu<DecodedResponse>> {
+    let rdr = &mut Reader::new(&bytes);
+    let fn_code = rdr.read_u8()?;
+    if !matches!(fn_code, 0x03 | 0x04) {
+        return decode_response_pdu(bytes, ignore_trailing_bytes, ResponseDecoding::Regular);
+    }
+    check_response_pdu_size(bytes.len())?;
+    let end = 2 + usize::from(rdr.read_u8()?);
+    if bytes.len() < end {
+        return Err(Error::from(ErrorKind::UnexpectedEof));
+    }
+    if bytes.len() > end {
+        let trailing_bytes = &bytes[end..];
+        if !ignore_trailing_bytes {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "undecoded response data",
+            ));
+        }
+        log::warn!(
+            "Ignoring {count} undecoded trailing byte(s) of registers response: {trailing_bytes:02X?}",
+            count = trailing_bytes.len()
+        );
+    }
+    let registers = Registers::from_bytes(bytes.slice(2..end))
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid quantity"))?;
+    let response = DecodedResponse::Registers(FunctionCode::new(fn_code), registers);
+    Ok(ResponsePdu(Ok(response)))
+}
+
+/// Decode the response PDU to a [`Request::Custom`] without decoding
+/// its data.
+///
+/// Regular responses are returned as [`Response::Custom`] that shares
+/// the memory of `bytes`, even for the function codes of the protocol.
+/// The function of exception responses is also reported as
+/// [`FunctionCode::Custom`] to match the request.
+fn decode_custom_response_pdu(mut bytes: Bytes) -> Result<ResponsePdu> {
+    let fn_code = Reader::new(&bytes).read_u8()?;
+    let pdu = if fn_code < 0x80 {
+        Response::Custom(fn_code, bytes.split_off(1)).into()
+    } else {
+        let ExceptionResponse { exception, .. } = decode_exception_response_pdu(&bytes)?;
+        ExceptionResponse {
+            function: FunctionCode::Custom(fn_code - 0x80),
+            exception,
+        }
+        .into()
+    };
+    Ok(pdu)
+}
+
+#[cfg(any(test, feature = "rtu", feature = "tcp"))]
+pub(crate) fn bool_to_coil(state: bool) -> u16 {
+    if state {
+        0xFF00
+    } else {
+        0x0000
+    }
+}
+
+pub(crate) fn coil_to_bool(coil: u16) -> Result<bool> {
+    match coil {
+        0xFF00 => Ok(true),
+        0x0000 => Ok(false),
+        _ => Err(Error::new(ErrorKind::InvalidData, "Invalid coil value: {}")),
+    }
+}
+
+#[cfg(any(test, feature = "rtu", feature = "tcp"))]
+pub(crate) fn packed_coils_size(coils: &[Coil]) -> usize {
+    coils.len().div_ceil(8)
+}
+
+#[cfg(any(test, feature = "rtu", feature = "tcp"))]
+pub(crate) fn encode_packed_coils(buf: &mut crate::bytes::BytesMut, coils: &[Coil]) -> usize {
+    let packed_coils_size = packed_coils_size(coils);
+    let offset = buf.len();
+    buf.resize(offset + packed_coils_size, 0);
+    let buf = &mut buf[offset..];
+    for (i, b) in coils.iter().enumerate() {
+        let v = u8::from(*b); // 0 or 1
+        buf[i / 8] |= v << (i % 8);
+    }
+    packed_coils_size
+}
+
+pub(crate) fn decode_packed_coils(bytes: &[u8], count: u16) -> Vec<Coil> {
+    let mut res = Vec::with_capacity(count.into());
+    for i in 0usize..count.into() {
+        res.push((bytes[i / 8] >> (i % 8)) & 0b1 > 0);
+    }
+    res
+}
+
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+pub(crate) fn request_pdu_size(request: &Request<'_>) -> Result<usize> {
+    use crate::frame::Request::*;
+    let size = match request {
+        ReadCoils(_, _)
+        | ReadDiscreteInputs(_, _)
+        | ReadInputRegisters(_, _)
+        | ReadHoldingRegisters(_, _)
+        | WriteSingleRegister(_, _)
+        | WriteSingleCoil(_, _) => 5,
+        WriteMultipleCoils(_, coils) => 6 + packed_coils_size(coils),
+        WriteMultipleRegisters(_, data) => 6 + data.len() * 2,
+        MaskWriteRegister(_, _, _) => 7,
+        ReadWriteMultipleRegisters(_, _, _, data) => 10 + data.len() * 2,
+        ReadFifoQueue(_) => 3,
+        ReportServerId | ReadExceptionStatus => 1,
+        ReadDeviceIdentification(_, _) => 4,
+        Diagnostics(_, data) => 3 + data.len() * 2,
+        Custom(_, data) => 1 + data.len(),
+    };
+    if size > MAX_PDU_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "request PDU size exceeded",
+        ));
+    }
+    Ok(size)
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn response_pdu_size(response: &Response) -> Result<usize> {
+    use crate::frame::Response::*;
+    let size = match response {
+        ReadCoils(coils) | ReadDiscreteInputs(coils) => 2 + packed_coils_size(coils),
+        WriteSingleCoil(_, _)
+        | WriteMultipleCoils(_, _)
+        | WriteMultipleRegisters(_, _)
+        | WriteSingleRegister(_, _) => 5,
+        ReadInputRegisters(data)
+        | ReadHoldingRegisters(data)
+        | ReadWriteMultipleRegisters(data) => 2 + data.len() * 2,
+        ReportServerId(_, _, ref data) => 4 + data.len(),
+        ReadFifoQueue(ref data) => 5 + data.len() * 2,
+        ReadExceptionStatus(_) => 2,
+        ReadDeviceIdentification(ref response) => {
+            7 + response
+                .objects
+                .iter()
+                .map(|(_, value)| 2 + value.len())
+                .sum::<usize>()
+        }
+        Diagnostics(_, ref data) => 3 + data.len() * 2,
+        MaskWriteRegister(_, _, _) => 7,
+        Custom(_, ref data) => 1 + data.len(),
+    };
+    if size > MAX_PDU_SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "response PDU size exceeded",
+        ));
+    }
+    Ok(size)
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn response_result_pdu_size(
+    res: &std::result::Result<Response, ExceptionResponse>,
+) -> Result<usize> {
+    match res {
+        Ok(response) => response_pdu_size(response),
+        Err(_) => Ok(2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_big_endian_values() {
+        let mut rdr = Reader::new(&[0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(rdr.read_u8().unwrap(), 0x01);
+        assert_eq!(rdr.read_u16().unwrap(), 0x0203);
+        assert_eq!(rdr.remaining(), 1);
+        assert_eq!(rdr.read_u16().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(rdr.read_bytes(1).unwrap(), [0x04]);
+        assert!(!rdr.has_remaining());
+        assert!(rdr.read_words(0_usize).unwrap().is_empty());
+    }
+}
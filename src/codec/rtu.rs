@@ -1,7 +1,10 @@
 // SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::io::{Cursor, Error, ErrorKind, Result};
+use std::{
+    io::{Cursor, Error, ErrorKind, Result},
+    sync::{Arc, Mutex},
+};
 
 use byteorder::{BigEndian, ReadBytesExt as _};
 use smallvec::SmallVec;
@@ -10,10 +13,15 @@ use tokio_util::codec::{Decoder, Encoder};
 use crate::{
     bytes::{Buf, BufMut, Bytes, BytesMut},
     frame::rtu::*,
+    mutex::lock,
     slave::SlaveId,
+    tap::{self, Tap},
 };
 
-use super::{encode_request_pdu, request_pdu_size, RequestPdu};
+use super::{
+    decode_response_pdu, encode_request_pdu, request_pdu_size, ChecksumErrors, DecodedResponse,
+    RegisterViews, RequestPdu, ResponseDecoding, MEI_READ_DEVICE_ID,
+};
 
 // [Modbus over Serial Line Specification and Implementation Guide V1.02](http://modbus.org/docs/Modbus_over_serial_line_V1_02.pdf), page 13
 // "The maximum size of a Modbus RTU frame is 256 bytes."
@@ -21,20 +29,87 @@ const MAX_FRAME_LEN: usize = 256;
 
 type DroppedBytes = SmallVec<[u8; MAX_FRAME_LEN]>;
 
+/// Silent intervals on the line that delimit frames.
+///
+/// Detected by [`TimedIo`](crate::timing::TimedIo) when reading and
+/// consumed by the [`FrameDecoder`].
+#[derive(Debug, Default)]
+pub(crate) struct FrameGaps {
+    /// The number of bytes that have been read since the start of
+    /// the current frame, until the start has been decoded.
+    pending_start: Option<usize>,
+    /// The current frame contains an inter-character gap.
+    broken: bool,
+}
+
+impl FrameGaps {
+    pub(crate) fn start_frame(&mut self, read: usize) {
+        self.pending_start = Some(read);
+        self.broken = false;
+    }
+
+    pub(crate) fn continue_frame(&mut self, read: usize) {
+        if let Some(pending_start) = &mut self.pending_start {
+            *pending_start += read;
+        }
+    }
+
+    pub(crate) fn inter_char_gap(&mut self) {
+        self.broken = true;
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct FrameDecoder {
     dropped_bytes: SmallVec<[u8; MAX_FRAME_LEN]>,
+    gaps: Option<Arc<Mutex<FrameGaps>>>,
+    checksum_errors: u64,
 }
 
 impl Default for FrameDecoder {
     fn default() -> Self {
         Self {
             dropped_bytes: DroppedBytes::new(),
+            gaps: None,
+            checksum_errors: 0,
         }
     }
 }
 
 impl FrameDecoder {
+    fn with_gaps(gaps: Arc<Mutex<FrameGaps>>) -> Self {
+        Self {
+            gaps: Some(gaps),
+            ..Default::default()
+        }
+    }
+
+    /// Discard the bytes of incomplete and interrupted frames.
+    fn discard_invalid_frames(&self, buf: &mut BytesMut) {
+        let Some(gaps) = &self.gaps else {
+            return;
+        };
+        let mut gaps = lock(gaps);
+        if let Some(pending_start) = gaps.pending_start.take() {
+            let incomplete = buf.len().saturating_sub(pending_start);
+            if incomplete > 0 {
+                log::warn!(
+                    "Dropped {incomplete} byte(s) of an incomplete frame: {:X?}",
+                    &buf[..incomplete]
+                );
+                buf.advance(incomplete);
+            }
+        }
+        if gaps.broken && !buf.is_empty() {
+            log::warn!(
+                "Dropped {} byte(s) of a frame with an inter-character gap: {:X?}",
+                buf.len(),
+                &buf[..]
+            );
+            buf.clear();
+        }
+    }
+
     pub(crate) fn decode(
         &mut self,
         buf: &mut BytesMut,
@@ -58,6 +133,7 @@ impl FrameDecoder {
             .and_then(|crc| check_crc(&adu_buf, crc));
 
         if let Err(err) = crc_result {
+            self.checksum_errors += 1;
             // CRC is invalid - restore the input buffer
             let rem_buf = buf.split();
             debug_assert!(buf.is_empty());
@@ -83,12 +159,13 @@ impl FrameDecoder {
     }
 
     pub(crate) fn recover_on_error(&mut self, buf: &mut BytesMut) {
-        // If decoding failed the buffer cannot be empty
-        debug_assert!(!buf.is_empty());
         // Skip and record the first byte of the buffer
         {
-            let first = buf.first().unwrap();
-            log::debug!("Dropped first byte: {:X?}", first);
+            let Some(first) = buf.first() else {
+                // Nothing to skip.
+                return;
+            };
+            log::debug!("Dropped first byte: {first:X?}");
             if self.dropped_bytes.len() >= MAX_FRAME_LEN {
                 log::error!(
                     "Giving up to decode frame after dropping {} byte(s): {:X?}",
@@ -117,19 +194,72 @@ pub(crate) struct ResponseDecoder {
 #[derive(Debug, Default)]
 pub(crate) struct ClientCodec {
     pub(crate) decoder: ResponseDecoder,
+    pub(crate) tap: Option<Tap>,
+    /// How to decode the response to the last request.
+    pub(crate) response_decoding: ResponseDecoding,
+    /// Return the registers of read responses as views.
+    pub(crate) register_views: bool,
+}
+
+impl ClientCodec {
+    /// Discard frames that violate the timing of the serial line.
+    pub(crate) fn with_frame_gaps(gaps: Arc<Mutex<FrameGaps>>) -> Self {
+        Self {
+            decoder: ResponseDecoder {
+                frame_decoder: FrameDecoder::with_gaps(gaps),
+            },
+            tap: None,
+            response_decoding: ResponseDecoding::Regular,
+            register_views: false,
+        }
+    }
+}
+
+impl RegisterViews for ClientCodec {
+    fn set_register_views(&mut self, register_views: bool) {
+        self.register_views = register_views;
+    }
+}
+
+impl ChecksumErrors for ClientCodec {
+    fn checksum_errors(&self) -> u64 {
+        self.decoder.frame_decoder.checksum_errors
+    }
 }
 
 #[cfg(any(feature = "rtu-over-tcp-server", feature = "rtu-server"))]
 #[derive(Debug, Default)]
 pub(crate) struct ServerCodec {
     pub(crate) decoder: RequestDecoder,
+    pub(crate) tap: Option<Tap>,
+}
+
+#[cfg(any(feature = "rtu-over-tcp-server", feature = "rtu-server"))]
+impl ChecksumErrors for ServerCodec {
+    fn checksum_errors(&self) -> u64 {
+        self.decoder.frame_decoder.checksum_errors
+    }
+}
+
+#[cfg(feature = "rtu-server")]
+impl ServerCodec {
+    /// Discard frames that violate the timing of the serial line.
+    pub(crate) fn with_frame_gaps(gaps: Arc<Mutex<FrameGaps>>) -> Self {
+        Self {
+            decoder: RequestDecoder {
+                frame_decoder: FrameDecoder::with_gaps(gaps),
+            },
+            tap: None,
+        }
+    }
 }
 
 #[cfg(any(feature = "rtu-over-tcp-server", feature = "rtu-server"))]
 fn get_request_pdu_len(adu_buf: &BytesMut) -> Result<Option<usize>> {
     if let Some(fn_code) = adu_buf.get(1) {
         let len = match fn_code {
-            0x01..=0x06 => 5,
+            // Diagnostics requests are assumed to contain a single data word.
+            0x01..=0x06 | 0x08 => 5,
             0x07 | 0x0B | 0x0C | 0x11 => 1,
             0x0F | 0x10 => {
                 return Ok(adu_buf
@@ -138,6 +268,7 @@ fn get_request_pdu_len(adu_buf: &BytesMut) -> Result<Option<usize>> {
             }
             0x16 => 7,
             0x18 => 3,
+            0x2B if adu_buf.get(2) == Some(&MEI_READ_DEVICE_ID) => 4,
             0x17 => {
                 return Ok(adu_buf
                     .get(10)
@@ -165,7 +296,8 @@ fn get_response_pdu_len(adu_buf: &BytesMut) -> Result<Option<usize>> {
                     .get(2)
                     .map(|&byte_count| 2 + usize::from(byte_count)));
             }
-            0x05 | 0x06 | 0x0B | 0x0F | 0x10 => 5,
+            // Diagnostics responses are assumed to contain a single data word.
+            0x05 | 0x06 | 0x08 | 0x0B | 0x0F | 0x10 => 5,
             0x07 => 2,
             0x16 => 7,
             0x18 => {
@@ -176,6 +308,9 @@ fn get_response_pdu_len(adu_buf: &BytesMut) -> Result<Option<usize>> {
                     return Ok(None);
                 }
             }
+            0x2B if adu_buf.get(2) == Some(&MEI_READ_DEVICE_ID) => {
+                return Ok(get_device_id_response_pdu_len(adu_buf));
+            }
             0x81..=0xAB => 2,
             _ => {
                 return Err(Error::new(
@@ -190,6 +325,22 @@ fn get_response_pdu_len(adu_buf: &BytesMut) -> Result<Option<usize>> {
     }
 }
 
+/// The length of a Read Device Identification response PDU is only
+/// known after all object headers have been received.
+fn get_device_id_response_pdu_len(adu_buf: &BytesMut) -> Option<usize> {
+    // Slave id, function code, MEI type, read device id code,
+    // conformity level, more follows, and next object id
+    let mut offset = 7;
+    let count = *adu_buf.get(offset)?;
+    offset += 1;
+    for _ in 0..count {
+        let len = *adu_buf.get(offset + 1)?;
+        offset += 2 + usize::from(len);
+    }
+    // Without the slave id
+    Some(offset - 1)
+}
+
 fn calc_crc(data: &[u8]) -> u16 {
     let mut crc = 0xFFFF;
     for x in data {
@@ -251,6 +402,7 @@ where
 {
     const MAX_RETRIES: usize = 20;
 
+    frame_decoder.discard_invalid_frames(buf);
     for _i in 0..MAX_RETRIES {
         let result = get_pdu_len(buf).and_then(|pdu_len| {
             let Some(pdu_len) = pdu_len else {
@@ -276,26 +428,30 @@ where
 }
 
 impl Decoder for ClientCodec {
-    type Item = ResponseAdu;
+    type Item = ResponseAdu<DecodedResponse>;
     type Error = Error;
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<ResponseAdu>> {
-        let Some((slave_id, pdu_data)) = self.decoder.decode(buf)? else {
-            return Ok(None);
-        };
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<ResponseAdu<DecodedResponse>>> {
+        let decoder = &mut self.decoder;
+        let response_decoding = self.response_decoding;
+        tap::decode(self.tap.as_ref(), buf, |buf| {
+            let Some((slave_id, pdu_data)) = decoder.decode(buf)? else {
+                return Ok(None);
+            };
+
+            let hdr = Header { slave_id };
 
-        let hdr = Header { slave_id };
-
-        // Decoding of the PDU is unlikely to fail due
-        // to transmission errors, because the frame's bytes
-        // have already been verified with the CRC.
-        super::ResponsePdu::try_from(pdu_data)
-            .map(|pdu| Some(ResponseAdu { hdr, pdu }))
-            .map_err(|err| {
-                // Unrecoverable error
-                log::error!("Failed to decode response PDU: {}", err);
-                err
-            })
+            // Decoding of the PDU is unlikely to fail due
+            // to transmission errors, because the frame's bytes
+            // have already been verified with the CRC.
+            decode_response_pdu(pdu_data, false, response_decoding)
+                .map(|pdu| Some(ResponseAdu { hdr, pdu }))
+                .map_err(|err| {
+                    // Unrecoverable error
+                    log::error!("Failed to decode response PDU: {err}");
+                    err
+                })
+        })
     }
 }
 
@@ -305,22 +461,25 @@ impl Decoder for ServerCodec {
     type Error = Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<RequestAdu<'static>>> {
-        let Some((slave_id, pdu_data)) = self.decoder.decode(buf)? else {
-            return Ok(None);
-        };
+        let decoder = &mut self.decoder;
+        tap::decode(self.tap.as_ref(), buf, |buf| {
+            let Some((slave_id, pdu_data)) = decoder.decode(buf)? else {
+                return Ok(None);
+            };
 
-        let hdr = Header { slave_id };
-
-        // Decoding of the PDU is unlikely to fail due
-        // to transmission errors, because the frame's bytes
-        // have already been verified with the CRC.
-        super::RequestPdu::try_from(pdu_data)
-            .map(|pdu| Some(RequestAdu { hdr, pdu }))
-            .map_err(|err| {
-                // Unrecoverable error
-                log::error!("Failed to decode request PDU: {}", err);
-                err
-            })
+            let hdr = Header { slave_id };
+
+            // Decoding of the PDU is unlikely to fail due
+            // to transmission errors, because the frame's bytes
+            // have already been verified with the CRC.
+            super::RequestPdu::try_from(pdu_data)
+                .map(|pdu| Some(RequestAdu { hdr, pdu }))
+                .map_err(|err| {
+                    // Unrecoverable error
+                    log::error!("Failed to decode request PDU: {err}");
+                    err
+                })
+        })
     }
 }
 
@@ -328,18 +487,21 @@ impl<'a> Encoder<RequestAdu<'a>> for ClientCodec {
     type Error = Error;
 
     fn encode(&mut self, adu: RequestAdu<'a>, buf: &mut BytesMut) -> Result<()> {
-        let RequestAdu {
-            hdr,
-            pdu: RequestPdu(request),
-        } = adu;
-        let buf_offset = buf.len();
-        let request_pdu_size = request_pdu_size(&request)?;
-        buf.reserve(request_pdu_size + 3);
-        buf.put_u8(hdr.slave_id);
-        encode_request_pdu(buf, &request);
-        let crc = calc_crc(&buf[buf_offset..]);
-        buf.put_u16(crc);
-        Ok(())
+        self.response_decoding = ResponseDecoding::of_request(&adu.pdu.0, self.register_views);
+        tap::encode(self.tap.as_ref(), adu, buf, |adu, buf| {
+            let RequestAdu {
+                hdr,
+                pdu: RequestPdu(request),
+            } = adu;
+            let buf_offset = buf.len();
+            let request_pdu_size = request_pdu_size(&request)?;
+            buf.reserve(request_pdu_size + 3);
+            buf.put_u8(hdr.slave_id);
+            encode_request_pdu(buf, &request);
+            let crc = calc_crc(&buf[buf_offset..]);
+            buf.put_u16(crc);
+            Ok(())
+        })
     }
 }
 
@@ -348,18 +510,20 @@ impl Encoder<ResponseAdu> for ServerCodec {
     type Error = Error;
 
     fn encode(&mut self, adu: ResponseAdu, buf: &mut BytesMut) -> Result<()> {
-        let ResponseAdu {
-            hdr,
-            pdu: super::ResponsePdu(pdu_res),
-        } = adu;
-        let buf_offset = buf.len();
-        let response_result_pdu_size = super::response_result_pdu_size(&pdu_res)?;
-        buf.reserve(response_result_pdu_size + 3);
-        buf.put_u8(hdr.slave_id);
-        super::encode_response_result_pdu(buf, &pdu_res);
-        let crc = calc_crc(&buf[buf_offset..]);
-        buf.put_u16(crc);
-        Ok(())
+        tap::encode(self.tap.as_ref(), adu, buf, |adu, buf| {
+            let ResponseAdu {
+                hdr,
+                pdu: super::ResponsePdu(pdu_res),
+            } = adu;
+            let buf_offset = buf.len();
+            let response_result_pdu_size = super::response_result_pdu_size(&pdu_res)?;
+            buf.reserve(response_result_pdu_size + 3);
+            buf.put_u8(hdr.slave_id);
+            super::encode_response_result_pdu(buf, &pdu_res);
+            let crc = calc_crc(&buf[buf_offset..]);
+            buf.put_u16(crc);
+            Ok(())
+        })
     }
 }
 
@@ -406,7 +570,8 @@ mod tests {
         buf[1] = 0x07;
         assert_eq!(get_request_pdu_len(&buf).unwrap(), Some(1));
 
-        // TODO: 0x08
+        buf[1] = 0x08;
+        assert_eq!(get_request_pdu_len(&buf).unwrap(), Some(5));
 
         buf[1] = 0x0B;
         assert_eq!(get_request_pdu_len(&buf).unwrap(), Some(1));
@@ -439,7 +604,9 @@ mod tests {
         buf[1] = 0x18;
         assert_eq!(get_request_pdu_len(&buf).unwrap(), Some(3));
 
-        // TODO: 0x2B
+        buf[1] = 0x2B;
+        buf[2] = 0x0E;
+        assert_eq!(get_request_pdu_len(&buf).unwrap(), Some(4));
     }
 
     #[test]
@@ -473,7 +640,8 @@ mod tests {
         buf[1] = 0x07;
         assert_eq!(get_response_pdu_len(&buf).unwrap(), Some(2));
 
-        // TODO: 0x08
+        buf[1] = 0x08;
+        assert_eq!(get_response_pdu_len(&buf).unwrap(), Some(5));
 
         buf[1] = 0x0B;
         assert_eq!(get_response_pdu_len(&buf).unwrap(), Some(5));
@@ -504,7 +672,13 @@ mod tests {
         buf[3] = 0x00; // byte count Lo
         assert_eq!(get_response_pdu_len(&buf).unwrap(), Some(259));
 
-        // TODO: 0x2B
+        let mut device_id_buf = BytesMut::new();
+        device_id_buf.extend_from_slice(&[0x66, 0x2B, 0x0E, 0x01, 0x81, 0x00, 0x00]);
+        assert_eq!(get_response_pdu_len(&device_id_buf).unwrap(), None);
+        device_id_buf.extend_from_slice(&[0x02, 0x00, 0x02, b'A', b'B', 0x01]);
+        assert_eq!(get_response_pdu_len(&device_id_buf).unwrap(), None);
+        device_id_buf.extend_from_slice(&[0x01]);
+        assert_eq!(get_response_pdu_len(&device_id_buf).unwrap(), Some(14));
 
         for i in 0x81..0xAB {
             buf[1] = i;
@@ -662,7 +836,9 @@ mod tests {
             let ResponseAdu { hdr, pdu } = codec.decode(&mut buf).unwrap().unwrap();
             assert_eq!(buf.len(), 1);
             assert_eq!(hdr.slave_id, 0x01);
-            if let Ok(Response::ReadHoldingRegisters(data)) = pdu.into() {
+            if let Ok(Response::ReadHoldingRegisters(data)) =
+                pdu.0.map(DecodedResponse::into_response)
+            {
                 assert_eq!(data.len(), 2);
                 assert_eq!(data, vec![0x8902, 0x42C7]);
             } else {
@@ -693,7 +869,9 @@ mod tests {
             let ResponseAdu { hdr, pdu } = codec.decode(&mut buf).unwrap().unwrap();
             assert_eq!(buf.len(), 1);
             assert_eq!(hdr.slave_id, 0x01);
-            if let Ok(Response::ReadHoldingRegisters(data)) = pdu.into() {
+            if let Ok(Response::ReadHoldingRegisters(data)) =
+                pdu.0.map(DecodedResponse::into_response)
+            {
                 assert_eq!(data.len(), 2);
                 assert_eq!(data, vec![0x8902, 0x42C7]);
             } else {
@@ -741,19 +919,54 @@ mod tests {
         }
 
         #[test]
-        fn encode_with_limited_buf_capacity() {
+        fn discard_frames_that_violate_the_timing() {
+            let gaps = Arc::<Mutex<FrameGaps>>::default();
+            let mut codec = ClientCodec::with_frame_gaps(Arc::clone(&gaps));
+            let response = [0x01, 0x03, 0x02, 0x12, 0x34, 0xB5, 0x33];
+
+            // The incomplete frame is followed by a silent interval.
+            let mut buf = BytesMut::from(&[0x01, 0x03, 0x02][..]);
+            gaps.lock().unwrap().start_frame(3);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+            buf.extend_from_slice(&response);
+            gaps.lock().unwrap().start_frame(response.len());
+            let adu = codec.decode(&mut buf).unwrap().unwrap();
+            assert_eq!(
+                adu.pdu,
+                ResponsePdu(Ok(Response::ReadHoldingRegisters(vec![0x1234]).into()))
+            );
+            assert!(buf.is_empty());
+
+            // The frame contains an inter-character gap.
+            buf.extend_from_slice(&response[..3]);
+            gaps.lock().unwrap().start_frame(3);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+            buf.extend_from_slice(&response[3..]);
+            gaps.lock().unwrap().inter_char_gap();
+            gaps.lock().unwrap().continue_frame(4);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+            assert!(buf.is_empty());
+        }
+
+        #[test]
+        fn encode_into_dirty_buffers() {
             let mut codec = ClientCodec::default();
-            let req = Request::ReadHoldingRegisters(0x082b, 2);
-            let pdu = req.into();
-            let slave_id = 0x01;
-            let hdr = Header { slave_id };
-            let adu = RequestAdu { hdr, pdu };
-            let mut buf = BytesMut::with_capacity(40);
-            #[allow(unsafe_code)]
-            unsafe {
-                buf.set_len(33);
+            let hdr = Header { slave_id: 0x01 };
+            let requests = [
+                Request::ReadHoldingRegisters(0x082b, 2),
+                Request::WriteMultipleCoils(0x082b, vec![true; 13].into()),
+                Request::WriteMultipleRegisters(0x082b, vec![0xABCD; 123].into()),
+                Request::Custom(0x55, vec![0xCC, 0x88].into()),
+            ];
+            for request in requests {
+                crate::codec::tests::assert_encoding_into_dirty_buffers(|buf| {
+                    let adu = RequestAdu {
+                        hdr,
+                        pdu: request.clone().into(),
+                    };
+                    codec.encode(adu, buf).unwrap();
+                });
             }
-            assert!(codec.encode(adu, &mut buf).is_ok());
         }
     }
 }
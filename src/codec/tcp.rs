@@ -1,21 +1,31 @@
 // SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::io::{Error, ErrorKind, Result};
+use std::{
+    collections::VecDeque,
+    io::{Error, ErrorKind, Result},
+};
 
-use byteorder::{BigEndian, ByteOrder};
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::{
-    bytes::{BufMut, Bytes, BytesMut},
+    bytes::{Buf as _, BufMut, Bytes, BytesMut},
     frame::tcp::*,
+    tap::{self, Tap},
+    transform::FrameTransform,
 };
 
 use super::*;
 
-const HEADER_LEN: usize = 7;
+/// Maximum size of an ADU, i.e. a header and the largest PDU.
+pub(crate) const MAX_ADU_LEN: usize = HEADER_LEN + MAX_PDU_SIZE;
 
-const PROTOCOL_ID: u16 = 0x0000; // TCP
+/// Maximum number of pending requests whose responses are not decoded
+/// regularly.
+///
+/// The oldest transactions are forgotten first if their responses
+/// never arrive.
+const MAX_PENDING_REQUESTS: usize = 256;
 
 #[derive(Debug, Default)]
 pub(crate) struct AduDecoder;
@@ -23,12 +33,27 @@ pub(crate) struct AduDecoder;
 #[derive(Debug)]
 pub(crate) struct ClientCodec {
     pub(crate) decoder: AduDecoder,
+    pub(crate) transform: Option<Box<dyn FrameTransform>>,
+    pub(crate) ignore_trailing_bytes: bool,
+    pub(crate) tap: Option<Tap>,
+    /// Return the registers of read responses as views.
+    pub(crate) register_views: bool,
+    /// Pending requests whose responses are not decoded regularly.
+    pub(crate) pending_requests: VecDeque<(TransactionId, ResponseDecoding)>,
+    /// Reused for encoding ADUs before they are transformed.
+    pub(crate) scratch: BytesMut,
 }
 
 impl ClientCodec {
-    pub(crate) const fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             decoder: AduDecoder,
+            transform: None,
+            ignore_trailing_bytes: false,
+            tap: None,
+            register_views: false,
+            pending_requests: VecDeque::new(),
+            scratch: BytesMut::new(),
         }
     }
 }
@@ -37,57 +62,68 @@ impl ClientCodec {
 #[derive(Debug, Default)]
 pub(crate) struct ServerCodec {
     pub(crate) decoder: AduDecoder,
+    pub(crate) transform: Option<Box<dyn FrameTransform>>,
+    pub(crate) tap: Option<Tap>,
+    /// Reused for encoding ADUs before they are transformed.
+    pub(crate) scratch: BytesMut,
+}
+
+/// Decode an item from the unwrapped ADU if a transform is used.
+fn decode_transformed<T>(
+    transform: Option<&mut Box<dyn FrameTransform>>,
+    buf: &mut BytesMut,
+    decode: impl FnOnce(&mut BytesMut) -> Result<Option<T>>,
+) -> Result<Option<T>> {
+    let Some(transform) = transform else {
+        return decode(buf);
+    };
+    let Some(mut adu) = transform.decode(buf)? else {
+        return Ok(None);
+    };
+    match decode(&mut adu)? {
+        Some(item) if adu.is_empty() => Ok(Some(item)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Invalid ADU in transformed frame",
+        )),
+    }
+}
+
+/// Encode an item and wrap the ADU if a transform is used.
+///
+/// The unwrapped ADU is encoded into `scratch`, which retains its
+/// capacity for the next item.
+fn encode_transformed(
+    transform: Option<&mut Box<dyn FrameTransform>>,
+    scratch: &mut BytesMut,
+    buf: &mut BytesMut,
+    encode: impl FnOnce(&mut BytesMut) -> Result<()>,
+) -> Result<()> {
+    let Some(transform) = transform else {
+        return encode(buf);
+    };
+    scratch.clear();
+    encode(scratch)?;
+    // Don't leave a partially wrapped frame behind.
+    let len = buf.len();
+    transform
+        .encode(scratch, buf)
+        .inspect_err(|_| buf.truncate(len))
 }
 
 impl Decoder for AduDecoder {
     type Item = (Header, Bytes);
     type Error = Error;
 
-    #[allow(clippy::assertions_on_constants)]
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<(Header, Bytes)>> {
-        if buf.len() < HEADER_LEN {
+        let Some((header, pdu_len)) = Header::decode(buf)? else {
             return Ok(None);
-        }
-
-        debug_assert!(HEADER_LEN >= 6);
-        let len = usize::from(BigEndian::read_u16(&buf[4..6]));
-        let pdu_len = if len > 0 {
-            // len = bytes of PDU + one byte (unit ID)
-            len - 1
-        } else {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("Invalid data length: {len}"),
-            ));
         };
         if buf.len() < HEADER_LEN + pdu_len {
             return Ok(None);
         }
 
-        let header_data = buf.split_to(HEADER_LEN);
-
-        debug_assert!(HEADER_LEN >= 4);
-        let protocol_id = BigEndian::read_u16(&header_data[2..4]);
-        if protocol_id != PROTOCOL_ID {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Invalid protocol identifier: expected = {PROTOCOL_ID}, actual = {protocol_id}"
-                ),
-            ));
-        }
-
-        debug_assert!(HEADER_LEN >= 2);
-        let transaction_id = BigEndian::read_u16(&header_data[0..2]);
-
-        debug_assert!(HEADER_LEN > 6);
-        let unit_id = header_data[6];
-
-        let header = Header {
-            transaction_id,
-            unit_id,
-        };
-
+        buf.advance(HEADER_LEN);
         let pdu_data = buf.split_to(pdu_len).freeze();
 
         Ok(Some((header, pdu_data)))
@@ -95,16 +131,29 @@ impl Decoder for AduDecoder {
 }
 
 impl Decoder for ClientCodec {
-    type Item = ResponseAdu;
+    type Item = ResponseAdu<DecodedResponse>;
     type Error = Error;
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<ResponseAdu>> {
-        if let Some((hdr, pdu_data)) = self.decoder.decode(buf)? {
-            let pdu = ResponsePdu::try_from(pdu_data)?;
-            Ok(Some(ResponseAdu { hdr, pdu }))
-        } else {
-            Ok(None)
-        }
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<ResponseAdu<DecodedResponse>>> {
+        let decoder = &mut self.decoder;
+        let ignore_trailing_bytes = self.ignore_trailing_bytes;
+        let transform = self.transform.as_mut();
+        let pending_requests = &mut self.pending_requests;
+        tap::decode(self.tap.as_ref(), buf, |buf| {
+            decode_transformed(transform, buf, |buf| {
+                if let Some((hdr, pdu_data)) = decoder.decode(buf)? {
+                    let decoding = pending_requests
+                        .iter()
+                        .position(|&(id, _)| id == hdr.transaction_id)
+                        .and_then(|index| pending_requests.remove(index))
+                        .map_or(ResponseDecoding::Regular, |(_, decoding)| decoding);
+                    let pdu = decode_response_pdu(pdu_data, ignore_trailing_bytes, decoding)?;
+                    Ok(Some(ResponseAdu { hdr, pdu }))
+                } else {
+                    Ok(None)
+                }
+            })
+        })
     }
 }
 
@@ -114,12 +163,18 @@ impl Decoder for ServerCodec {
     type Error = Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<RequestAdu<'static>>> {
-        if let Some((hdr, pdu_data)) = self.decoder.decode(buf)? {
-            let pdu = RequestPdu::try_from(pdu_data)?;
-            Ok(Some(RequestAdu { hdr, pdu }))
-        } else {
-            Ok(None)
-        }
+        let decoder = &mut self.decoder;
+        let transform = self.transform.as_mut();
+        tap::decode(self.tap.as_ref(), buf, |buf| {
+            decode_transformed(transform, buf, |buf| {
+                if let Some((hdr, pdu_data)) = decoder.decode(buf)? {
+                    let pdu = RequestPdu::try_from(pdu_data)?;
+                    Ok(Some(RequestAdu { hdr, pdu }))
+                } else {
+                    Ok(None)
+                }
+            })
+        })
     }
 }
 
@@ -127,17 +182,29 @@ impl<'a> Encoder<RequestAdu<'a>> for ClientCodec {
     type Error = Error;
 
     fn encode(&mut self, adu: RequestAdu<'a>, buf: &mut BytesMut) -> Result<()> {
-        let RequestAdu {
-            hdr,
-            pdu: RequestPdu(request),
-        } = adu;
-        let request_pdu_size = request_pdu_size(&request)?;
-        buf.reserve(request_pdu_size + 7);
-        buf.put_u16(hdr.transaction_id);
-        buf.put_u16(PROTOCOL_ID);
-        buf.put_u16(u16_len(request_pdu_size + 1));
-        buf.put_u8(hdr.unit_id);
-        encode_request_pdu(buf, &request);
+        let transform = self.transform.as_mut();
+        let scratch = &mut self.scratch;
+        let decoding = ResponseDecoding::of_request(&adu.pdu.0, self.register_views);
+        let transaction_id = adu.hdr.transaction_id;
+        tap::encode(self.tap.as_ref(), adu, buf, |adu, buf| {
+            let RequestAdu {
+                hdr,
+                pdu: RequestPdu(request),
+            } = adu;
+            encode_transformed(transform, scratch, buf, |buf| {
+                let request_pdu_size = request_pdu_size(&request)?;
+                buf.reserve(HEADER_LEN + request_pdu_size);
+                buf.put_slice(&hdr.encode(request_pdu_size)?);
+                encode_request_pdu(buf, &request);
+                Ok(())
+            })
+        })?;
+        if decoding != ResponseDecoding::Regular {
+            if self.pending_requests.len() == MAX_PENDING_REQUESTS {
+                self.pending_requests.pop_front();
+            }
+            self.pending_requests.push_back((transaction_id, decoding));
+        }
         Ok(())
     }
 }
@@ -147,23 +214,28 @@ impl Encoder<ResponseAdu> for ServerCodec {
     type Error = Error;
 
     fn encode(&mut self, adu: ResponseAdu, buf: &mut BytesMut) -> Result<()> {
-        let ResponseAdu {
-            hdr,
-            pdu: ResponsePdu(pdu_result),
-        } = adu;
-        let response_result_pdu_size = super::response_result_pdu_size(&pdu_result)?;
-        buf.reserve(response_result_pdu_size + 7);
-        buf.put_u16(hdr.transaction_id);
-        buf.put_u16(PROTOCOL_ID);
-        buf.put_u16(u16_len(response_result_pdu_size + 1));
-        buf.put_u8(hdr.unit_id);
-        super::encode_response_result_pdu(buf, &pdu_result);
-        Ok(())
+        let transform = self.transform.as_mut();
+        let scratch = &mut self.scratch;
+        tap::encode(self.tap.as_ref(), adu, buf, |adu, buf| {
+            let ResponseAdu {
+                hdr,
+                pdu: ResponsePdu(pdu_result),
+            } = adu;
+            encode_transformed(transform, scratch, buf, |buf| {
+                let response_result_pdu_size = super::response_result_pdu_size(&pdu_result)?;
+                buf.reserve(HEADER_LEN + response_result_pdu_size);
+                buf.put_slice(&hdr.encode(response_result_pdu_size)?);
+                super::encode_response_result_pdu(buf, &pdu_result);
+                Ok(())
+            })
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{borrow::Cow, io::ErrorKind};
+
     use super::*;
 
     mod client {
@@ -237,6 +309,86 @@ mod tests {
             }
         }
 
+        #[test]
+        fn decode_response_with_trailing_bytes() {
+            let frame = [
+                TRANSACTION_ID_HI,
+                TRANSACTION_ID_LO,
+                PROTOCOL_ID_HI,
+                PROTOCOL_ID_LO,
+                0x00, // length high HI
+                0x07, // length low LO
+                UNIT_ID,
+                0x03, // function code
+                0x02, // byte count
+                0x12,
+                0x34,
+                0x00, // padding
+                0x00, // padding
+            ];
+
+            let mut codec = ClientCodec::new();
+            let err = codec.decode(&mut BytesMut::from(&frame[..])).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+            codec.ignore_trailing_bytes = true;
+            let ResponseAdu { pdu, .. } = codec
+                .decode(&mut BytesMut::from(&frame[..]))
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                pdu.0,
+                Ok(Response::ReadHoldingRegisters(vec![0x1234]).into())
+            );
+        }
+
+        #[test]
+        fn decode_responses_to_custom_requests_raw() {
+            let mut codec = ClientCodec::new();
+            let hdr = Header {
+                transaction_id: TRANSACTION_ID,
+                unit_id: UNIT_ID,
+            };
+            let request = Request::Custom(0x03, Cow::Borrowed(&[0x00, 0x00, 0x00, 0x01]));
+            let adu = RequestAdu {
+                hdr,
+                pdu: request.into(),
+            };
+            codec.encode(adu, &mut BytesMut::new()).unwrap();
+
+            let frame = [
+                TRANSACTION_ID_HI,
+                TRANSACTION_ID_LO,
+                PROTOCOL_ID_HI,
+                PROTOCOL_ID_LO,
+                0x00, // length high HI
+                0x05, // length low LO
+                UNIT_ID,
+                0x03, // function code
+                0x02, // byte count
+                0x12,
+                0x34,
+            ];
+            let ResponseAdu { pdu, .. } = codec
+                .decode(&mut BytesMut::from(&frame[..]))
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                pdu.0,
+                Ok(Response::Custom(0x03, Bytes::from_static(&[0x02, 0x12, 0x34])).into())
+            );
+
+            // Only the response to the custom request is decoded raw.
+            let ResponseAdu { pdu, .. } = codec
+                .decode(&mut BytesMut::from(&frame[..]))
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                pdu.0,
+                Ok(Response::ReadHoldingRegisters(vec![0x1234]).into())
+            );
+        }
+
         #[test]
         fn decode_with_invalid_protocol_id() {
             let mut codec = ClientCodec::new();
@@ -285,20 +437,344 @@ mod tests {
         }
 
         #[test]
-        fn encode_with_limited_buf_capacity() {
+        fn encode_into_dirty_buffers() {
             let mut codec = ClientCodec::new();
-            let pdu = Request::ReadInputRegisters(0x23, 5).into();
             let hdr = Header {
                 transaction_id: TRANSACTION_ID,
                 unit_id: UNIT_ID,
             };
-            let adu = RequestAdu { hdr, pdu };
-            let mut buf = BytesMut::with_capacity(40);
-            #[allow(unsafe_code)]
-            unsafe {
-                buf.set_len(29);
+            let requests = [
+                Request::ReadInputRegisters(0x23, 5),
+                Request::WriteMultipleCoils(0x23, vec![true; 13].into()),
+                Request::WriteMultipleRegisters(0x23, vec![0xABCD; 123].into()),
+                Request::Custom(0x55, vec![0xCC, 0x88].into()),
+            ];
+            for request in requests {
+                crate::codec::tests::assert_encoding_into_dirty_buffers(|buf| {
+                    let adu = RequestAdu {
+                        hdr,
+                        pdu: request.clone().into(),
+                    };
+                    codec.encode(adu, buf).unwrap();
+                });
+            }
+        }
+    }
+
+    #[cfg(feature = "tcp-server")]
+    mod transform {
+        use std::io;
+
+        use crate::Request;
+
+        use super::*;
+
+        /// Prefixes frames with their length and obfuscates the contents.
+        #[derive(Debug)]
+        struct XorTransform;
+
+        const XOR_KEY: u8 = 0x5A;
+
+        impl FrameTransform for XorTransform {
+            fn encode(&mut self, adu: &[u8], buf: &mut BytesMut) -> io::Result<()> {
+                buf.put_u8(u8::try_from(adu.len()).unwrap());
+                buf.extend(adu.iter().map(|b| b ^ XOR_KEY));
+                Ok(())
+            }
+
+            fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+                let Some(&len) = buf.first() else {
+                    return Ok(None);
+                };
+                if buf.len() < 1 + usize::from(len) {
+                    return Ok(None);
+                }
+                buf.advance(1);
+                let mut adu = buf.split_to(len.into());
+                adu.iter_mut().for_each(|b| *b ^= XOR_KEY);
+                Ok(Some(adu))
+            }
+        }
+
+        #[test]
+        fn transform_request() {
+            let mut client_codec = ClientCodec::new();
+            client_codec.transform = Some(Box::new(XorTransform));
+            let mut server_codec = ServerCodec {
+                transform: Some(Box::new(XorTransform)),
+                ..Default::default()
+            };
+            let hdr = Header {
+                transaction_id: 0x1001,
+                unit_id: 0x01,
+            };
+            let req = Request::ReadHoldingRegisters(0x0102, 3);
+            let mut buf = BytesMut::new();
+            client_codec
+                .encode(
+                    RequestAdu {
+                        hdr,
+                        pdu: req.clone().into(),
+                    },
+                    &mut buf,
+                )
+                .unwrap();
+            assert_eq!(buf.len(), 1 + HEADER_LEN + 5);
+            assert_eq!(buf[0], 12);
+            assert_eq!(buf[1], 0x10 ^ XOR_KEY);
+
+            // Incomplete frame
+            let mut partial = BytesMut::from(&buf[..5]);
+            assert!(server_codec.decode(&mut partial).unwrap().is_none());
+            assert_eq!(partial.len(), 5);
+
+            let adu = server_codec.decode(&mut buf).unwrap().unwrap();
+            assert_eq!(adu.hdr, hdr);
+            assert_eq!(adu.pdu.0, req);
+            assert!(buf.is_empty());
+        }
+
+        #[test]
+        fn reuse_scratch_buffer_for_transformed_frames() {
+            let mut codec = ClientCodec::new();
+            codec.transform = Some(Box::new(XorTransform));
+            let adu = |transaction_id| RequestAdu {
+                hdr: Header {
+                    transaction_id,
+                    unit_id: 0x01,
+                },
+                pdu: Request::ReadHoldingRegisters(0x0102, 3).into(),
+            };
+            let mut buf = BytesMut::new();
+            codec.encode(adu(1), &mut buf).unwrap();
+            let scratch = codec.scratch.as_ptr();
+            let frame = buf.split();
+            codec.encode(adu(2), &mut buf).unwrap();
+            assert_eq!(codec.scratch.as_ptr(), scratch);
+            assert_eq!(buf.len(), frame.len());
+            assert_eq!(buf[2], 0x02 ^ XOR_KEY);
+        }
+
+        #[test]
+        fn reject_trailing_bytes_in_transformed_frame() {
+            let mut codec = ClientCodec::new();
+            codec.transform = Some(Box::new(XorTransform));
+            let adu = [0x10, 0x01, 0x00, 0x00, 0x00, 0x03, 0x01, 0x81, 0x02, 0xFF];
+            let mut buf = BytesMut::new();
+            XorTransform.encode(&adu, &mut buf).unwrap();
+            let err = codec.decode(&mut buf).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+
+        /// Fails after partially wrapping the frame.
+        #[derive(Debug)]
+        struct FailingTransform;
+
+        impl FrameTransform for FailingTransform {
+            fn encode(&mut self, adu: &[u8], buf: &mut BytesMut) -> io::Result<()> {
+                buf.put_slice(&adu[..2]);
+                Err(io::Error::other("failed"))
+            }
+
+            fn decode(&mut self, _buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+                Err(io::Error::other("failed"))
+            }
+        }
+
+        #[test]
+        fn discard_partially_transformed_frame() {
+            let mut codec = ClientCodec::new();
+            codec.transform = Some(Box::new(FailingTransform));
+            let adu = RequestAdu {
+                hdr: Header {
+                    transaction_id: 0x1001,
+                    unit_id: 0x01,
+                },
+                pdu: Request::ReadHoldingRegisters(0x0102, 3).into(),
+            };
+            let mut buf = BytesMut::from(&[0xFF][..]);
+            assert!(codec.encode(adu, &mut buf).is_err());
+            assert_eq!(buf, [0xFF][..]);
+        }
+    }
+
+    #[cfg(feature = "tcp-server")]
+    mod round_trip {
+        use proptest::{collection::vec, prelude::*};
+
+        use crate::{
+            bytes::Bytes, DeviceIdObjectId, ExceptionCode, ExceptionResponse, FunctionCode,
+            ReadDeviceIdCode, Request, Response,
+        };
+
+        use super::*;
+
+        fn header() -> impl Strategy<Value = Header> {
+            (any::<TransactionId>(), any::<UnitId>()).prop_map(|(transaction_id, unit_id)| Header {
+                transaction_id,
+                unit_id,
+            })
+        }
+
+        /// A buffer with arbitrary capacity and previous contents.
+        fn buffer() -> impl Strategy<Value = BytesMut> {
+            (0..=MAX_ADU_LEN * 2, vec(any::<u8>(), 0..8)).prop_map(|(capacity, prefix)| {
+                let mut buf = BytesMut::with_capacity(capacity);
+                buf.extend_from_slice(&prefix);
+                buf
+            })
+        }
+
+        fn custom_function() -> impl Strategy<Value = u8> {
+            prop_oneof![0x41..=0x48u8, 0x64..=0x6Eu8]
+        }
+
+        fn request() -> impl Strategy<Value = Request<'static>> {
+            let addr = any::<u16>;
+            prop_oneof![
+                (addr(), addr()).prop_map(|(a, n)| Request::ReadCoils(a, n)),
+                (addr(), addr()).prop_map(|(a, n)| Request::ReadDiscreteInputs(a, n)),
+                (addr(), any::<bool>()).prop_map(|(a, c)| Request::WriteSingleCoil(a, c)),
+                (addr(), vec(any::<bool>(), 1..=1968))
+                    .prop_map(|(a, c)| Request::WriteMultipleCoils(a, c.into())),
+                (addr(), addr()).prop_map(|(a, n)| Request::ReadInputRegisters(a, n)),
+                (addr(), addr()).prop_map(|(a, n)| Request::ReadHoldingRegisters(a, n)),
+                (addr(), addr()).prop_map(|(a, w)| Request::WriteSingleRegister(a, w)),
+                (addr(), vec(addr(), 1..=123))
+                    .prop_map(|(a, w)| Request::WriteMultipleRegisters(a, w.into())),
+                (addr(), addr(), addr())
+                    .prop_map(|(a, and, or)| Request::MaskWriteRegister(a, and, or)),
+                (addr(), addr(), addr(), vec(addr(), 1..=121)).prop_map(|(r, n, w, words)| {
+                    Request::ReadWriteMultipleRegisters(r, n, w, words.into())
+                }),
+                addr().prop_map(Request::ReadFifoQueue),
+                Just(Request::ReportServerId),
+                Just(Request::ReadExceptionStatus),
+                (1..=4u8, any::<u8>()).prop_map(|(code, object_id)| {
+                    Request::ReadDeviceIdentification(
+                        ReadDeviceIdCode::new(code).unwrap(),
+                        DeviceIdObjectId::new(object_id),
+                    )
+                }),
+                (custom_function(), vec(any::<u8>(), 0..=MAX_PDU_SIZE - 1))
+                    .prop_map(|(f, data)| Request::Custom(f, data.into())),
+            ]
+        }
+
+        fn response() -> impl Strategy<Value = Response> {
+            let addr = any::<u16>;
+            // Coils are packed into whole bytes without the requested quantity.
+            let coils = || (1..=250usize).prop_flat_map(|len| vec(any::<bool>(), len * 8));
+            let words = || vec(addr(), 0..=125);
+            prop_oneof![
+                coils().prop_map(Response::ReadCoils),
+                coils().prop_map(Response::ReadDiscreteInputs),
+                (addr(), any::<bool>()).prop_map(|(a, c)| Response::WriteSingleCoil(a, c)),
+                (addr(), addr()).prop_map(|(a, n)| Response::WriteMultipleCoils(a, n)),
+                words().prop_map(Response::ReadInputRegisters),
+                words().prop_map(Response::ReadHoldingRegisters),
+                (addr(), addr()).prop_map(|(a, w)| Response::WriteSingleRegister(a, w)),
+                (addr(), addr()).prop_map(|(a, n)| Response::WriteMultipleRegisters(a, n)),
+                (any::<u8>(), any::<bool>(), vec(any::<u8>(), 0..=240))
+                    .prop_map(|(id, run, data)| Response::ReportServerId(id, run, data)),
+                (addr(), addr(), addr())
+                    .prop_map(|(a, and, or)| Response::MaskWriteRegister(a, and, or)),
+                words().prop_map(Response::ReadWriteMultipleRegisters),
+                vec(addr(), 0..=31).prop_map(Response::ReadFifoQueue),
+                any::<u8>().prop_map(Response::ReadExceptionStatus),
+                (custom_function(), vec(any::<u8>(), 0..=MAX_PDU_SIZE - 1))
+                    .prop_map(|(f, data)| Response::Custom(f, Bytes::from(data))),
+            ]
+        }
+
+        fn exception_response() -> impl Strategy<Value = ExceptionResponse> {
+            (1..=0x7Fu8, 1..=0x0Bu8).prop_map(|(function, exception)| ExceptionResponse {
+                function: FunctionCode::new(function),
+                exception: ExceptionCode::new(exception),
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn encode_and_decode_requests(
+                hdr in header(),
+                request in request(),
+                mut buf in buffer(),
+            ) {
+                let prefix = buf.clone();
+                let adu = RequestAdu {
+                    hdr,
+                    pdu: request.clone().into(),
+                };
+                ClientCodec::new().encode(adu, &mut buf).unwrap();
+                prop_assert_eq!(&buf[..prefix.len()], &prefix[..]);
+
+                let mut frame = buf.split_off(prefix.len());
+                let adu = ServerCodec::default().decode(&mut frame).unwrap().unwrap();
+                prop_assert!(frame.is_empty());
+                prop_assert_eq!(adu.hdr, hdr);
+                prop_assert_eq!(adu.pdu.0, request);
+            }
+
+            #[test]
+            fn encode_and_decode_responses(
+                hdr in header(),
+                response in prop_oneof![
+                    response().prop_map(Ok),
+                    exception_response().prop_map(Err),
+                ],
+                mut buf in buffer(),
+            ) {
+                let prefix = buf.clone();
+                let adu = ResponseAdu {
+                    hdr,
+                    pdu: ResponsePdu(response.clone()),
+                };
+                ServerCodec::default().encode(adu, &mut buf).unwrap();
+                prop_assert_eq!(&buf[..prefix.len()], &prefix[..]);
+
+                let mut frame = buf.split_off(prefix.len());
+                let adu = ClientCodec::new().decode(&mut frame).unwrap().unwrap();
+                prop_assert!(frame.is_empty());
+                prop_assert_eq!(adu.hdr, hdr);
+                prop_assert_eq!(adu.pdu.0, response.map(Into::into));
+            }
+
+            #[test]
+            fn decode_arbitrary_frames(
+                hdr in header(),
+                pdu in vec(any::<u8>(), 1..=MAX_PDU_SIZE + 1),
+                trailer in vec(any::<u8>(), 0..8),
+            ) {
+                let mut frame = BytesMut::new();
+                frame.put_slice(&hdr.encode(pdu.len()).unwrap());
+                frame.put_slice(&pdu);
+                frame.put_slice(&trailer);
+                for len in [frame.len() - trailer.len(), frame.len()] {
+                    // Must never panic.
+                    let mut buf = BytesMut::from(&frame[..len]);
+                    if let Ok(Some(adu)) = ClientCodec::new().decode(&mut buf) {
+                        prop_assert_eq!(adu.hdr, hdr);
+                    }
+                    let mut buf = BytesMut::from(&frame[..len]);
+                    if let Ok(Some(adu)) = ServerCodec::default().decode(&mut buf) {
+                        prop_assert_eq!(adu.hdr, hdr);
+                    }
+                }
+            }
+
+            #[test]
+            fn decode_arbitrary_bytes(bytes in vec(any::<u8>(), 0..=MAX_ADU_LEN + 8)) {
+                // Must never panic.
+                let mut buf = BytesMut::from(&bytes[..]);
+                if let Ok(None) = ClientCodec::new().decode(&mut buf) {
+                    prop_assert_eq!(&buf[..], &bytes[..]);
+                }
+                let mut buf = BytesMut::from(&bytes[..]);
+                if let Ok(None) = ServerCodec::default().decode(&mut buf) {
+                    prop_assert_eq!(&buf[..], &bytes[..]);
+                }
             }
-            assert!(codec.encode(adu, &mut buf).is_ok());
         }
     }
 }
@@ -0,0 +1,247 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Wire compatibility test vectors
+//!
+//! Asserts that the encoding of all supported PDUs is stable across
+//! releases by comparing it byte for byte with the fixtures file.
+//! The fixtures are shared by all transports, because RTU and TCP
+//! only differ in the framing around the PDU.
+//!
+//! Regenerate the fixtures after an intended wire change with:
+//!
+//! ```sh
+//! UPDATE_WIRE_VECTORS=1 cargo test --all-features wire_vectors
+//! ```
+//!
+//! and increment the version in the file name.
+
+use std::{borrow::Cow, fmt::Write as _};
+
+use crate::{
+    bytes::{Bytes, BytesMut},
+    DeviceIdObjectId, DiagnosticsSubFunction, ExceptionCode, FunctionCode, ReadDeviceIdCode,
+    ReadDeviceIdentificationResponse,
+};
+
+use super::*;
+
+const FIXTURES_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/wire-vectors-v1.txt");
+
+const FIXTURES: &str = include_str!("../../fixtures/wire-vectors-v1.txt");
+
+const HEADER: &str = "\
+# tokio-modbus wire test vectors, version 1
+#
+# Generated by `codec::wire_vectors`. Do not edit manually!
+#
+# Each line contains the kind of PDU, a unique name, and
+# the encoded bytes of the PDU in hexadecimal notation.
+";
+
+fn requests() -> Vec<(&'static str, Request<'static>)> {
+    use Request::*;
+    vec![
+        ("read_coils", ReadCoils(0x0012, 19)),
+        ("read_discrete_inputs", ReadDiscreteInputs(0x00C4, 22)),
+        ("write_single_coil_on", WriteSingleCoil(0x00AC, true)),
+        ("write_single_coil_off", WriteSingleCoil(0x00AC, false)),
+        (
+            "write_multiple_coils",
+            WriteMultipleCoils(
+                0x0013,
+                Cow::Owned(vec![
+                    true, false, true, true, false, false, true, true, true, false,
+                ]),
+            ),
+        ),
+        ("read_input_registers", ReadInputRegisters(0x0008, 1)),
+        ("read_holding_registers", ReadHoldingRegisters(0x006B, 3)),
+        ("write_single_register", WriteSingleRegister(0x0001, 0x0003)),
+        (
+            "write_multiple_registers",
+            WriteMultipleRegisters(0x0001, Cow::Owned(vec![0x000A, 0x0102])),
+        ),
+        ("report_server_id", ReportServerId),
+        (
+            "mask_write_register",
+            MaskWriteRegister(0x0004, 0x00F2, 0x0025),
+        ),
+        (
+            "read_write_multiple_registers",
+            ReadWriteMultipleRegisters(0x0003, 6, 0x000E, Cow::Owned(vec![0x00FF, 0x00FF, 0x00FF])),
+        ),
+        ("read_exception_status", ReadExceptionStatus),
+        (
+            "read_device_identification",
+            ReadDeviceIdentification(ReadDeviceIdCode::Basic, DeviceIdObjectId::VendorName),
+        ),
+        (
+            "diagnostics",
+            Diagnostics(
+                DiagnosticsSubFunction::ReturnQueryData,
+                Cow::Owned(vec![0xA537]),
+            ),
+        ),
+        (
+            "custom",
+            Custom(0x55, Cow::Owned(vec![0xCC, 0x88, 0xAA, 0xFF])),
+        ),
+    ]
+}
+
+fn responses() -> Vec<(&'static str, Response)> {
+    use Response::*;
+    vec![
+        (
+            "read_coils",
+            ReadCoils(vec![
+                true, false, true, true, false, false, true, true, true, true, false, true, false,
+                true, true, false, true, false, true, false, false, false, false, false,
+            ]),
+        ),
+        (
+            "read_discrete_inputs",
+            ReadDiscreteInputs(vec![
+                false, false, true, true, false, true, false, true, true, true, false, true, true,
+                false, true, true, true, false, true, false, true, true, false, false,
+            ]),
+        ),
+        ("write_single_coil", WriteSingleCoil(0x00AC, true)),
+        ("write_multiple_coils", WriteMultipleCoils(0x0013, 10)),
+        ("read_input_registers", ReadInputRegisters(vec![0x000A])),
+        (
+            "read_holding_registers",
+            ReadHoldingRegisters(vec![0x022B, 0x0000, 0x0064]),
+        ),
+        ("write_single_register", WriteSingleRegister(0x0001, 0x0003)),
+        (
+            "write_multiple_registers",
+            WriteMultipleRegisters(0x0001, 2),
+        ),
+        (
+            "report_server_id",
+            ReportServerId(0x42, true, vec![0x10, 0x20]),
+        ),
+        (
+            "mask_write_register",
+            MaskWriteRegister(0x0004, 0x00F2, 0x0025),
+        ),
+        (
+            "read_write_multiple_registers",
+            ReadWriteMultipleRegisters(vec![0x00FE, 0x0ACD, 0x0001, 0x0003, 0x000D, 0x00FF]),
+        ),
+        ("read_exception_status", ReadExceptionStatus(0x6D)),
+        (
+            "read_device_identification",
+            ReadDeviceIdentification(ReadDeviceIdentificationResponse {
+                read_device_id_code: ReadDeviceIdCode::Basic,
+                conformity_level: 0x81,
+                more_follows: false,
+                next_object_id: DeviceIdObjectId::VendorName,
+                objects: vec![
+                    (
+                        DeviceIdObjectId::VendorName,
+                        b"Company identification".to_vec(),
+                    ),
+                    (DeviceIdObjectId::ProductCode, b"Product code XX".to_vec()),
+                    (DeviceIdObjectId::MajorMinorRevision, b"V2.11".to_vec()),
+                ],
+            }),
+        ),
+        (
+            "diagnostics",
+            Diagnostics(DiagnosticsSubFunction::ReturnBusMessageCount, vec![0x0108]),
+        ),
+        (
+            "custom",
+            Custom(0x55, Bytes::from_static(&[0xCC, 0x88, 0xAA, 0xFF])),
+        ),
+    ]
+}
+
+fn exception_responses() -> Vec<(&'static str, ExceptionResponse)> {
+    [
+        ExceptionCode::IllegalFunction,
+        ExceptionCode::IllegalDataAddress,
+        ExceptionCode::IllegalDataValue,
+        ExceptionCode::ServerDeviceFailure,
+        ExceptionCode::Acknowledge,
+        ExceptionCode::ServerDeviceBusy,
+        ExceptionCode::MemoryParityError,
+        ExceptionCode::GatewayPathUnavailable,
+        ExceptionCode::GatewayTargetDevice,
+    ]
+    .into_iter()
+    .zip([
+        "illegal_function",
+        "illegal_data_address",
+        "illegal_data_value",
+        "server_device_failure",
+        "acknowledge",
+        "server_device_busy",
+        "memory_parity_error",
+        "gateway_path_unavailable",
+        "gateway_target_device",
+    ])
+    .map(|(exception, name)| {
+        let response = ExceptionResponse {
+            function: FunctionCode::ReadHoldingRegisters,
+            exception,
+        };
+        (name, response)
+    })
+    .collect()
+}
+
+fn write_vector(fixtures: &mut String, kind: &str, name: &str, pdu: &[u8]) {
+    write!(fixtures, "{kind} {name}").unwrap();
+    for byte in pdu {
+        write!(fixtures, " {byte:02X}").unwrap();
+    }
+    fixtures.push('\n');
+}
+
+fn generate() -> String {
+    let mut fixtures = HEADER.to_owned();
+    for (name, request) in requests() {
+        let mut pdu = BytesMut::new();
+        encode_request_pdu(&mut pdu, &request);
+        assert_eq!(Request::try_from(pdu.clone().freeze()).unwrap(), request);
+        write_vector(&mut fixtures, "request", name, &pdu);
+    }
+    for (name, response) in responses() {
+        let mut pdu = BytesMut::new();
+        encode_response_pdu(&mut pdu, &response);
+        assert_eq!(Response::try_from(pdu.clone().freeze()).unwrap(), response);
+        write_vector(&mut fixtures, "response", name, &pdu);
+    }
+    for (name, response) in exception_responses() {
+        let mut pdu = BytesMut::new();
+        encode_exception_response_pdu(&mut pdu, response);
+        assert_eq!(
+            ExceptionResponse::try_from(pdu.clone().freeze()).unwrap(),
+            response
+        );
+        write_vector(&mut fixtures, "exception", name, &pdu);
+    }
+    fixtures
+}
+
+#[test]
+fn wire_vectors_are_stable() {
+    let generated = generate();
+    if std::env::var_os("UPDATE_WIRE_VECTORS").is_some() {
+        std::fs::write(FIXTURES_PATH, &generated).unwrap();
+        return;
+    }
+    for (line, (fixture, generated)) in FIXTURES.lines().zip(generated.lines()).enumerate() {
+        assert_eq!(
+            fixture,
+            generated,
+            "wire encoding changed in line {line_number} of {FIXTURES_PATH}",
+            line_number = line + 1
+        );
+    }
+    assert_eq!(FIXTURES.lines().count(), generated.lines().count());
+}
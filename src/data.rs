@@ -0,0 +1,428 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Packing and unpacking of values in registers
+//!
+//! The functions are independent of any transport and could be used
+//! both for the values of clients and within `Service` implementations
+//! of servers.
+//!
+//! Values that don't fit into a single register are spread over multiple
+//! consecutive registers. The order of the bytes and registers is not
+//! specified by the _Modbus_ protocol and must be taken from the
+//! documentation of the device, see [`WordOrder`].
+//!
+//! # Example
+//!
+//! ```
+//! use tokio_modbus::data::{unpack_bcd, Value as _, WordOrder};
+//!
+//! let words = 1.5f32.pack(WordOrder::Cdab);
+//! assert_eq!(words, [0x0000, 0x3FC0]);
+//! assert_eq!(f32::unpack(&words, WordOrder::Cdab), Some(1.5));
+//!
+//! assert_eq!(unpack_bcd(&[0x1234], WordOrder::Abcd), Some(1234));
+//! ```
+
+use std::io;
+
+use crate::{bytes::Bytes, Quantity};
+
+/// The order of the bytes of a value that spans multiple registers.
+///
+/// The variants are named after the order in which the bytes of a 32-bit
+/// value `0xAABBCCDD` are transmitted. Values with 16 or 64 bits are
+/// arranged accordingly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WordOrder {
+    /// Big-endian, the most significant register first, e.g.
+    /// `[0xAABB, 0xCCDD]`.
+    #[default]
+    Abcd,
+
+    /// Little-endian, the least significant register first with
+    /// swapped bytes, e.g. `[0xDDCC, 0xBBAA]`.
+    Dcba,
+
+    /// The most significant register first with swapped bytes, e.g.
+    /// `[0xBBAA, 0xDDCC]`.
+    Badc,
+
+    /// The least significant register first, e.g. `[0xCCDD, 0xAABB]`.
+    Cdab,
+}
+
+impl WordOrder {
+    /// Convert between big-endian registers and this order.
+    ///
+    /// The conversion is its own inverse.
+    fn arrange(self, words: &mut [u16]) {
+        if matches!(self, Self::Dcba | Self::Badc) {
+            for word in words.iter_mut() {
+                *word = word.swap_bytes();
+            }
+        }
+        if matches!(self, Self::Dcba | Self::Cdab) {
+            words.reverse();
+        }
+    }
+}
+
+/// A numeric value that is stored in one or more registers.
+pub trait Value: Sized {
+    /// The number of registers.
+    const QUANTITY: Quantity;
+
+    /// Unpack the value from exactly [`Self::QUANTITY`] registers.
+    ///
+    /// Returns `None` if the number of registers doesn't match.
+    fn unpack(words: &[u16], order: WordOrder) -> Option<Self>;
+
+    /// Pack the value into [`Self::QUANTITY`] registers.
+    fn pack(self, order: WordOrder) -> Vec<u16>;
+}
+
+macro_rules! impl_value {
+    ($($ty:ty),+) => {
+        $(
+            impl Value for $ty {
+                #[allow(clippy::cast_possible_truncation)]
+                const QUANTITY: Quantity = (std::mem::size_of::<$ty>() / 2) as Quantity;
+
+                fn unpack(words: &[u16], order: WordOrder) -> Option<Self> {
+                    if words.len() != usize::from(Self::QUANTITY) {
+                        return None;
+                    }
+                    let mut words = words.to_vec();
+                    order.arrange(&mut words);
+                    let mut bytes = [0; std::mem::size_of::<$ty>()];
+                    for (chunk, word) in bytes.chunks_exact_mut(2).zip(words) {
+                        chunk.copy_from_slice(&word.to_be_bytes());
+                    }
+                    Some(Self::from_be_bytes(bytes))
+                }
+
+                fn pack(self, order: WordOrder) -> Vec<u16> {
+                    let mut words = pack_bytes(&self.to_be_bytes());
+                    order.arrange(&mut words);
+                    words
+                }
+            }
+        )+
+    };
+}
+
+impl_value!(u16, i16, u32, i32, u64, i64, f32, f64);
+
+/// Unpack the bits of registers, starting with the least significant
+/// bit of the first register.
+#[must_use]
+pub fn unpack_bits(words: &[u16]) -> Vec<bool> {
+    words
+        .iter()
+        .flat_map(|word| (0..16).map(move |bit| word & (1 << bit) != 0))
+        .collect()
+}
+
+/// Pack bits into registers, starting with the least significant bit
+/// of the first register.
+///
+/// The last register is padded with zeros.
+#[must_use]
+pub fn pack_bits(bits: &[bool]) -> Vec<u16> {
+    bits.chunks(16)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |word, (bit, &set)| word | (u16::from(set) << bit))
+        })
+        .collect()
+}
+
+/// Unpack an unsigned integer from binary-coded decimal digits with
+/// 4 digits per register.
+///
+/// Returns `None` if the registers contain a nibble that is not a
+/// decimal digit or if the value exceeds [`u64::MAX`].
+#[must_use]
+pub fn unpack_bcd(words: &[u16], order: WordOrder) -> Option<u64> {
+    let mut words = words.to_vec();
+    order.arrange(&mut words);
+    words
+        .iter()
+        .flat_map(|word| (0..4).rev().map(move |nibble| (word >> (nibble * 4)) & 0xF))
+        .try_fold(0u64, |value, digit| {
+            (digit <= 9)
+                .then_some(value)?
+                .checked_mul(10)?
+                .checked_add(digit.into())
+        })
+}
+
+/// Pack an unsigned integer into `cnt` registers as binary-coded
+/// decimal digits with 4 digits per register.
+///
+/// Returns `None` if the value doesn't fit into the registers.
+#[must_use]
+pub fn pack_bcd(mut value: u64, cnt: Quantity, order: WordOrder) -> Option<Vec<u16>> {
+    let mut words = vec![0; cnt.into()];
+    for word in words.iter_mut().rev() {
+        for nibble in 0..4 {
+            // The remainder is a single decimal digit.
+            #[allow(clippy::cast_possible_truncation)]
+            let digit = (value % 10) as u16;
+            *word |= digit << (nibble * 4);
+            value /= 10;
+        }
+    }
+    if value > 0 {
+        return None;
+    }
+    order.arrange(&mut words);
+    Some(words)
+}
+
+/// The character encoding of strings.
+///
+/// Strings are stored with two characters per register, the first
+/// character in the high byte. Strings that are shorter than the
+/// registers are padded with NUL characters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// 7-bit ASCII.
+    #[default]
+    Ascii,
+
+    /// ISO 8859-1, i.e. each byte is a Unicode code point.
+    Latin1,
+
+    /// UTF-8.
+    Utf8,
+}
+
+/// Unpack a string from registers.
+///
+/// The string ends before the first NUL character. Strings that can't
+/// be decoded are rejected with [`io::ErrorKind::InvalidData`].
+pub fn unpack_string(words: &[u16], encoding: Encoding) -> io::Result<String> {
+    let mut bytes: Vec<_> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+    if let Some(end) = bytes.iter().position(|&byte| byte == 0) {
+        bytes.truncate(end);
+    }
+    let invalid_data = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+    match encoding {
+        Encoding::Ascii => {
+            if !bytes.is_ascii() {
+                return Err(invalid_data("invalid ASCII string".to_owned()));
+            }
+            // ASCII is valid UTF-8
+            String::from_utf8(bytes).map_err(|err| invalid_data(err.to_string()))
+        }
+        Encoding::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+        Encoding::Utf8 => String::from_utf8(bytes).map_err(|err| invalid_data(err.to_string())),
+    }
+}
+
+/// Pack a string into `cnt` registers.
+///
+/// The remaining registers are padded with NUL characters. Strings that
+/// can't be encoded or don't fit into the registers are rejected with
+/// [`io::ErrorKind::InvalidInput`].
+pub fn pack_string(value: &str, cnt: Quantity, encoding: Encoding) -> io::Result<Vec<u16>> {
+    let invalid_input = |message| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let mut bytes = match encoding {
+        Encoding::Ascii => value
+            .is_ascii()
+            .then(|| value.as_bytes().to_vec())
+            .ok_or_else(|| invalid_input(format!("{value:?} is not ASCII")))?,
+        Encoding::Latin1 => value
+            .chars()
+            .map(u8::try_from)
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid_input(format!("{value:?} is not ISO 8859-1")))?,
+        Encoding::Utf8 => value.as_bytes().to_vec(),
+    };
+    let len = usize::from(cnt) * 2;
+    if bytes.len() > len {
+        return Err(invalid_input(format!("{value:?} exceeds {cnt} registers")));
+    }
+    bytes.resize(len, 0);
+    Ok(pack_bytes(&bytes))
+}
+
+fn pack_bytes(bytes: &[u8]) -> Vec<u16> {
+    debug_assert_eq!(bytes.len() % 2, 0);
+    bytes
+        .chunks_exact(2)
+        .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+        .collect()
+}
+
+/// Registers that are read directly from the received frame.
+///
+/// Holds the big-endian bytes of the registers and decodes each word
+/// on access, i.e. reading registers into this view doesn't allocate
+/// a [`Vec`], see [`Context::read_holding_registers_view()`].
+///
+/// [`Context::read_holding_registers_view()`]: crate::client::Context::read_holding_registers_view
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registers(Bytes);
+
+impl Registers {
+    /// Wrap the big-endian bytes of registers.
+    ///
+    /// Returns `None` if the number of bytes is odd.
+    #[must_use]
+    pub fn from_bytes(bytes: Bytes) -> Option<Self> {
+        (bytes.len() % 2 == 0).then_some(Self(bytes))
+    }
+
+    /// The number of registers.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len() / 2
+    }
+
+    /// Check if there are no registers.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The register at `index`, if any.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<u16> {
+        let offset = index.checked_mul(2)?;
+        let bytes = self.0.get(offset..offset.checked_add(2)?)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Iterate over all registers.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = u16> + '_ {
+        self.0
+            .chunks_exact(2)
+            .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+    }
+
+    /// Copy all registers into `words`, e.g. into a buffer that is
+    /// reused for polling.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `words` differs from [`Self::len()`].
+    pub fn copy_to_slice(&self, words: &mut [u16]) {
+        assert_eq!(words.len(), self.len(), "number of registers");
+        for (word, value) in words.iter_mut().zip(self.iter()) {
+            *word = value;
+        }
+    }
+
+    /// Copy all registers into a new vector.
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<u16> {
+        self.iter().collect()
+    }
+
+    /// The big-endian bytes of the registers.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Unwrap the big-endian bytes of the registers.
+    #[must_use]
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl From<&[u16]> for Registers {
+    fn from(words: &[u16]) -> Self {
+        Self(words.iter().flat_map(|word| word.to_be_bytes()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_orders() {
+        for (order, expected) in [
+            (WordOrder::Abcd, [0xAABB, 0xCCDD]),
+            (WordOrder::Dcba, [0xDDCC, 0xBBAA]),
+            (WordOrder::Badc, [0xBBAA, 0xDDCC]),
+            (WordOrder::Cdab, [0xCCDD, 0xAABB]),
+        ] {
+            assert_eq!(0xAABB_CCDD_u32.pack(order), expected);
+            assert_eq!(u32::unpack(&expected, order), Some(0xAABB_CCDD));
+            assert_eq!(f64::unpack(&(-1.5e300).pack(order), order), Some(-1.5e300));
+        }
+        assert_eq!(
+            0x1122_3344_5566_7788_u64.pack(WordOrder::Cdab),
+            [0x7788, 0x5566, 0x3344, 0x1122]
+        );
+        assert_eq!((-2i16).pack(WordOrder::Badc), [0xFEFF]);
+        assert_eq!(i32::unpack(&[0xFFFF], WordOrder::Abcd), None);
+    }
+
+    #[test]
+    fn bits() {
+        let bits = unpack_bits(&[0x8001, 0x0002]);
+        assert_eq!(bits.len(), 32);
+        assert!(bits[0] && bits[15] && bits[17]);
+        assert_eq!(bits.iter().filter(|&&bit| bit).count(), 3);
+        assert_eq!(pack_bits(&bits), [0x8001, 0x0002]);
+        assert_eq!(pack_bits(&[false, true, true]), [0x0006]);
+    }
+
+    #[test]
+    fn bcd() {
+        assert_eq!(
+            unpack_bcd(&[0x0012, 0x3456], WordOrder::Abcd),
+            Some(123_456)
+        );
+        assert_eq!(
+            unpack_bcd(&[0x3456, 0x0012], WordOrder::Cdab),
+            Some(123_456)
+        );
+        assert_eq!(unpack_bcd(&[0x00A0], WordOrder::Abcd), None);
+        assert_eq!(unpack_bcd(&[0x9999; 5], WordOrder::Abcd), None);
+        assert_eq!(
+            pack_bcd(123_456, 2, WordOrder::Abcd),
+            Some(vec![0x0012, 0x3456])
+        );
+        assert_eq!(pack_bcd(12_345, 1, WordOrder::Abcd), None);
+    }
+
+    #[test]
+    fn registers() {
+        let registers =
+            Registers::from_bytes(Bytes::from_static(&[0x12, 0x34, 0xAB, 0xCD])).unwrap();
+        assert_eq!(registers.len(), 2);
+        assert_eq!(registers.get(1), Some(0xABCD));
+        assert_eq!(registers.get(2), None);
+        let mut words = [0; 2];
+        registers.copy_to_slice(&mut words);
+        assert_eq!(words, [0x1234, 0xABCD]);
+        assert_eq!(Registers::from(&words[..]), registers);
+        assert!(Registers::from_bytes(Bytes::from_static(&[0x12])).is_none());
+    }
+
+    #[test]
+    fn strings() {
+        let words = pack_string("abc", 3, Encoding::Ascii).unwrap();
+        assert_eq!(words, [0x6162, 0x6300, 0x0000]);
+        assert_eq!(unpack_string(&words, Encoding::Ascii).unwrap(), "abc");
+
+        let words = pack_string("Grüß", 2, Encoding::Latin1).unwrap();
+        assert_eq!(unpack_string(&words, Encoding::Latin1).unwrap(), "Grüß");
+        assert!(unpack_string(&words, Encoding::Ascii).is_err());
+        assert!(unpack_string(&words, Encoding::Utf8).is_err());
+
+        assert!(pack_string("abc", 1, Encoding::Ascii).is_err());
+        assert!(pack_string("äb", 2, Encoding::Ascii).is_err());
+        assert!(pack_string("€", 2, Encoding::Latin1).is_err());
+    }
+}
@@ -17,6 +17,15 @@ pub enum Error {
     Protocol(#[from] ProtocolError),
     #[error(transparent)]
     Transport(#[from] std::io::Error),
+    /// No response has been received within the given duration.
+    #[error("no response within {0:?}")]
+    Timeout(std::time::Duration),
+
+    /// The request has not been sent, because its response is needed
+    /// but the server will not answer it, e.g. a read request that is
+    /// broadcast on a serial line.
+    #[error("no response expected for request (function = {0})")]
+    NoResponse(FunctionCode),
 }
 
 /// _Modbus_ protocol error.
@@ -41,4 +50,16 @@ pub enum ProtocolError {
         request: FunctionCode,
         result: Result<Response, ExceptionResponse>,
     },
+
+    /// The received response doesn't match the request, e.g. the number
+    /// of values or the echoed address of a write request differ.
+    ///
+    /// The error message contains details about the mismatch.
+    ///
+    /// The result received from the server is included for further analysis and handling.
+    #[error("mismatching response: {message} {result:?}")]
+    ResponseMismatch {
+        message: String,
+        result: Result<Response, ExceptionResponse>,
+    },
 }
@@ -0,0 +1,141 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Cargo features that have been enabled at compile time
+//!
+//! # Example
+//!
+//! ```
+//! use tokio_modbus::features;
+//!
+//! if features::TCP {
+//!     println!("TCP clients are available");
+//! }
+//! assert!(features::enabled().all(features::is_enabled));
+//! ```
+
+/// Asynchronous RTU and ASCII client, feature `"rtu"`.
+pub const RTU: bool = cfg!(feature = "rtu");
+
+/// Asynchronous TCP and UDP client, feature `"tcp"`.
+pub const TCP: bool = cfg!(feature = "tcp");
+
+/// Synchronous RTU client, feature `"rtu-sync"`.
+pub const RTU_SYNC: bool = cfg!(feature = "rtu-sync");
+
+/// Synchronous TCP client, feature `"tcp-sync"`.
+pub const TCP_SYNC: bool = cfg!(feature = "tcp-sync");
+
+/// RTU and ASCII server, feature `"rtu-server"`.
+pub const RTU_SERVER: bool = cfg!(feature = "rtu-server");
+
+/// TCP and UDP server, feature `"tcp-server"`.
+pub const TCP_SERVER: bool = cfg!(feature = "tcp-server");
+
+/// RTU over TCP server, feature `"rtu-over-tcp-server"`.
+pub const RTU_OVER_TCP_SERVER: bool = cfg!(feature = "rtu-over-tcp-server");
+
+/// Raw Modbus TCP frames, feature `"raw-frames"`.
+pub const RAW_FRAMES: bool = cfg!(feature = "raw-frames");
+
+/// Conversion between server services and _tower_ services, feature `"tower"`.
+pub const TOWER: bool = cfg!(feature = "tower");
+
+/// Modbus/TCP Security client and server, feature `"tls"`.
+pub const TLS: bool = cfg!(feature = "tls");
+
+/// Spans of client calls and server requests, feature `"tracing"`.
+pub const TRACING: bool = cfg!(feature = "tracing");
+
+/// Capture of Modbus TCP frames into pcapng files, feature `"capture"`.
+pub const CAPTURE: bool = cfg!(feature = "capture");
+
+/// Modbus TCP frames over WebSocket connections, feature `"ws"`.
+pub const WS: bool = cfg!(feature = "ws");
+
+// Features that imply other features.
+const _: () = assert!(!RTU_SYNC || RTU);
+const _: () = assert!(!TCP_SYNC || TCP);
+const _: () = assert!(!RTU_SERVER || RTU);
+const _: () = assert!(!TCP_SERVER || TCP);
+const _: () = assert!(!RTU_OVER_TCP_SERVER || (RTU && TCP_SERVER));
+const _: () = assert!(!RAW_FRAMES || TCP);
+const _: () = assert!(!TLS || TCP);
+const _: () = assert!(!CAPTURE || TCP);
+const _: () = assert!(!WS || TCP);
+
+/// All public features and whether they are enabled.
+const FEATURES: [(&str, bool); 13] = [
+    ("rtu", RTU),
+    ("tcp", TCP),
+    ("rtu-sync", RTU_SYNC),
+    ("tcp-sync", TCP_SYNC),
+    ("rtu-server", RTU_SERVER),
+    ("tcp-server", TCP_SERVER),
+    ("rtu-over-tcp-server", RTU_OVER_TCP_SERVER),
+    ("raw-frames", RAW_FRAMES),
+    ("tower", TOWER),
+    ("tls", TLS),
+    ("tracing", TRACING),
+    ("capture", CAPTURE),
+    ("ws", WS),
+];
+
+/// The names of all enabled public features.
+pub fn enabled() -> impl Iterator<Item = &'static str> {
+    FEATURES
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+}
+
+/// Check if a public feature is enabled.
+///
+/// Returns `false` for unknown and internal features.
+#[must_use]
+pub fn is_enabled(name: &str) -> bool {
+    FEATURES
+        .into_iter()
+        .any(|(feature, enabled)| feature == name && enabled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The public features as declared in the manifest.
+    fn declared_features() -> Vec<&'static str> {
+        include_str!("../Cargo.toml")
+            .lines()
+            .skip_while(|line| *line != "[features]")
+            .skip(1)
+            // Internal features are declared after the comment.
+            .take_while(|line| !line.starts_with('#') && !line.starts_with('['))
+            .filter_map(|line| line.split_once(" = ").map(|(name, _)| name))
+            .filter(|name| *name != "default")
+            .collect()
+    }
+
+    #[test]
+    fn all_features_are_declared_and_documented() {
+        let declared = declared_features();
+        let known: Vec<_> = FEATURES.iter().map(|(name, _)| *name).collect();
+        assert_eq!(declared, known);
+
+        let readme = include_str!("../README.md");
+        for name in known {
+            assert!(
+                readme.contains(&format!("- `\"{name}\"`")),
+                "feature {name} is not documented"
+            );
+        }
+    }
+
+    #[test]
+    fn enabled_features() {
+        for (name, enabled) in FEATURES {
+            assert_eq!(is_enabled(name), enabled);
+            assert_eq!(super::enabled().any(|feature| feature == name), enabled);
+        }
+        assert!(!is_enabled("sync"));
+    }
+}
@@ -0,0 +1,200 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::fmt::{self, Display};
+
+/// Access type of a Read Device Identification request.
+///
+/// The stream access codes also denote the categories of objects.
+/// Each category includes all objects of the preceding categories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ReadDeviceIdCode {
+    /// 01 (0x01) Stream access to the basic objects
+    Basic,
+
+    /// 02 (0x02) Stream access to the regular objects
+    Regular,
+
+    /// 03 (0x03) Stream access to the extended objects
+    Extended,
+
+    /// 04 (0x04) Individual access to a single object
+    Specific,
+}
+
+impl ReadDeviceIdCode {
+    /// Create a new [`ReadDeviceIdCode`] with `value`.
+    ///
+    /// Returns `None` for undefined values.
+    #[must_use]
+    pub const fn new(value: u8) -> Option<Self> {
+        let code = match value {
+            0x01 => Self::Basic,
+            0x02 => Self::Regular,
+            0x03 => Self::Extended,
+            0x04 => Self::Specific,
+            _ => return None,
+        };
+        Some(code)
+    }
+
+    /// Gets the [`u8`] value of the current [`ReadDeviceIdCode`].
+    #[must_use]
+    pub const fn value(self) -> u8 {
+        match self {
+            Self::Basic => 0x01,
+            Self::Regular => 0x02,
+            Self::Extended => 0x03,
+            Self::Specific => 0x04,
+        }
+    }
+}
+
+impl Display for ReadDeviceIdCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value().fmt(f)
+    }
+}
+
+/// Identifier of a device identification object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DeviceIdObjectId {
+    /// 00 (0x00) Vendor Name (basic)
+    VendorName,
+
+    /// 01 (0x01) Product Code (basic)
+    ProductCode,
+
+    /// 02 (0x02) Major Minor Revision (basic)
+    MajorMinorRevision,
+
+    /// 03 (0x03) Vendor Url (regular)
+    VendorUrl,
+
+    /// 04 (0x04) Product Name (regular)
+    ProductName,
+
+    /// 05 (0x05) Model Name (regular)
+    ModelName,
+
+    /// 06 (0x06) User Application Name (regular)
+    UserApplicationName,
+
+    /// Reserved (regular) or device specific (extended) object.
+    Custom(u8),
+}
+
+impl DeviceIdObjectId {
+    /// Create a new [`DeviceIdObjectId`] with `value`.
+    #[must_use]
+    pub const fn new(value: u8) -> Self {
+        match value {
+            0x00 => Self::VendorName,
+            0x01 => Self::ProductCode,
+            0x02 => Self::MajorMinorRevision,
+            0x03 => Self::VendorUrl,
+            0x04 => Self::ProductName,
+            0x05 => Self::ModelName,
+            0x06 => Self::UserApplicationName,
+            id => Self::Custom(id),
+        }
+    }
+
+    /// Gets the [`u8`] value of the current [`DeviceIdObjectId`].
+    #[must_use]
+    pub const fn value(self) -> u8 {
+        match self {
+            Self::VendorName => 0x00,
+            Self::ProductCode => 0x01,
+            Self::MajorMinorRevision => 0x02,
+            Self::VendorUrl => 0x03,
+            Self::ProductName => 0x04,
+            Self::ModelName => 0x05,
+            Self::UserApplicationName => 0x06,
+            Self::Custom(id) => id,
+        }
+    }
+
+    /// The category of the object.
+    ///
+    /// Returns the stream access code for reading the object.
+    #[must_use]
+    pub const fn category(self) -> ReadDeviceIdCode {
+        match self.value() {
+            0x00..=0x02 => ReadDeviceIdCode::Basic,
+            0x03..=0x7F => ReadDeviceIdCode::Regular,
+            0x80..=0xFF => ReadDeviceIdCode::Extended,
+        }
+    }
+}
+
+impl Display for DeviceIdObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value().fmt(f)
+    }
+}
+
+/// The data of a Read Device Identification response.
+///
+/// The objects of a stream access might be split into multiple responses.
+/// If `more_follows` is set, then the remaining objects are requested by
+/// a subsequent request that starts at `next_object_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadDeviceIdentificationResponse {
+    /// The access type of the request
+    pub read_device_id_code: ReadDeviceIdCode,
+
+    /// The conformity level of the device, i.e. the supported categories
+    /// and access types
+    pub conformity_level: u8,
+
+    /// The objects don't fit into a single response
+    pub more_follows: bool,
+
+    /// The first object of the subsequent response if `more_follows` is set
+    pub next_object_id: DeviceIdObjectId,
+
+    /// The objects with their values, usually ASCII strings
+    pub objects: Vec<(DeviceIdObjectId, Vec<u8>)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_device_id_code_values() {
+        for value in 0x01..=0x04 {
+            assert_eq!(ReadDeviceIdCode::new(value).unwrap().value(), value);
+        }
+        assert_eq!(ReadDeviceIdCode::new(0x00), None);
+        assert_eq!(ReadDeviceIdCode::new(0x05), None);
+    }
+
+    #[test]
+    fn object_categories() {
+        assert_eq!(
+            DeviceIdObjectId::MajorMinorRevision.category(),
+            ReadDeviceIdCode::Basic
+        );
+        assert_eq!(
+            DeviceIdObjectId::VendorUrl.category(),
+            ReadDeviceIdCode::Regular
+        );
+        assert_eq!(
+            DeviceIdObjectId::new(0x7F).category(),
+            ReadDeviceIdCode::Regular
+        );
+        assert_eq!(
+            DeviceIdObjectId::new(0x80).category(),
+            ReadDeviceIdCode::Extended
+        );
+    }
+
+    #[test]
+    fn object_ids_are_ordered_by_value() {
+        for value in 0x00..0xFF {
+            assert!(DeviceIdObjectId::new(value) < DeviceIdObjectId::new(value + 1));
+        }
+    }
+}
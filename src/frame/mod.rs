@@ -7,13 +7,16 @@ pub(crate) mod rtu;
 #[cfg(feature = "tcp")]
 pub(crate) mod tcp;
 
+mod device_id;
+pub use self::device_id::{DeviceIdObjectId, ReadDeviceIdCode, ReadDeviceIdentificationResponse};
+
 use std::{
     borrow::Cow,
     error,
     fmt::{self, Display},
 };
 
-use crate::bytes::Bytes;
+use crate::{bytes::Bytes, data::Registers};
 
 /// A Modbus function code.
 ///
@@ -135,6 +138,22 @@ impl FunctionCode {
             Self::Custom(code) => code,
         }
     }
+
+    /// Checks if the function is only defined for serial lines.
+    ///
+    /// These functions are not meaningful for Modbus TCP.
+    #[must_use]
+    pub const fn is_serial_line_only(self) -> bool {
+        // Custom function codes might also denote these functions.
+        matches!(
+            Self::new(self.value()),
+            Self::ReadExceptionStatus
+                | Self::Diagnostics
+                | Self::GetCommEventCounter
+                | Self::GetCommEventLog
+                | Self::ReportServerId
+        )
+    }
 }
 
 impl Display for FunctionCode {
@@ -143,6 +162,132 @@ impl Display for FunctionCode {
     }
 }
 
+/// Sub-function of a [`FunctionCode::Diagnostics`] request (Serial Line only).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticsSubFunction {
+    /// 00 (0x00) Return Query Data
+    ReturnQueryData,
+
+    /// 01 (0x01) Restart Communications Option
+    RestartCommunicationsOption,
+
+    /// 02 (0x02) Return Diagnostic Register
+    ReturnDiagnosticRegister,
+
+    /// 03 (0x03) Change ASCII Input Delimiter
+    ChangeAsciiInputDelimiter,
+
+    /// 04 (0x04) Force Listen Only Mode
+    ///
+    /// The server doesn't respond to this request.
+    ForceListenOnlyMode,
+
+    /// 10 (0x0A) Clear Counters and Diagnostic Register
+    ClearCountersAndDiagnosticRegister,
+
+    /// 11 (0x0B) Return Bus Message Count
+    ReturnBusMessageCount,
+
+    /// 12 (0x0C) Return Bus Communication Error Count
+    ReturnBusCommunicationErrorCount,
+
+    /// 13 (0x0D) Return Bus Exception Error Count
+    ReturnBusExceptionErrorCount,
+
+    /// 14 (0x0E) Return Server Message Count
+    ReturnServerMessageCount,
+
+    /// 15 (0x0F) Return Server No Response Count
+    ReturnServerNoResponseCount,
+
+    /// 16 (0x10) Return Server NAK Count
+    ReturnServerNakCount,
+
+    /// 17 (0x11) Return Server Busy Count
+    ReturnServerBusyCount,
+
+    /// 18 (0x12) Return Bus Character Overrun Count
+    ReturnBusCharacterOverrunCount,
+
+    /// 20 (0x14) Clear Overrun Counter and Flag
+    ClearOverrunCounterAndFlag,
+
+    /// Reserved or device specific sub-function.
+    Custom(u16),
+}
+
+impl DiagnosticsSubFunction {
+    /// Create a new [`DiagnosticsSubFunction`] with `value`.
+    #[must_use]
+    pub const fn new(value: u16) -> Self {
+        match value {
+            0x00 => Self::ReturnQueryData,
+            0x01 => Self::RestartCommunicationsOption,
+            0x02 => Self::ReturnDiagnosticRegister,
+            0x03 => Self::ChangeAsciiInputDelimiter,
+            0x04 => Self::ForceListenOnlyMode,
+            0x0A => Self::ClearCountersAndDiagnosticRegister,
+            0x0B => Self::ReturnBusMessageCount,
+            0x0C => Self::ReturnBusCommunicationErrorCount,
+            0x0D => Self::ReturnBusExceptionErrorCount,
+            0x0E => Self::ReturnServerMessageCount,
+            0x0F => Self::ReturnServerNoResponseCount,
+            0x10 => Self::ReturnServerNakCount,
+            0x11 => Self::ReturnServerBusyCount,
+            0x12 => Self::ReturnBusCharacterOverrunCount,
+            0x14 => Self::ClearOverrunCounterAndFlag,
+            code => Self::Custom(code),
+        }
+    }
+
+    /// Gets the [`u16`] value of the current [`DiagnosticsSubFunction`].
+    #[must_use]
+    pub const fn value(self) -> u16 {
+        match self {
+            Self::ReturnQueryData => 0x00,
+            Self::RestartCommunicationsOption => 0x01,
+            Self::ReturnDiagnosticRegister => 0x02,
+            Self::ChangeAsciiInputDelimiter => 0x03,
+            Self::ForceListenOnlyMode => 0x04,
+            Self::ClearCountersAndDiagnosticRegister => 0x0A,
+            Self::ReturnBusMessageCount => 0x0B,
+            Self::ReturnBusCommunicationErrorCount => 0x0C,
+            Self::ReturnBusExceptionErrorCount => 0x0D,
+            Self::ReturnServerMessageCount => 0x0E,
+            Self::ReturnServerNoResponseCount => 0x0F,
+            Self::ReturnServerNakCount => 0x10,
+            Self::ReturnServerBusyCount => 0x11,
+            Self::ReturnBusCharacterOverrunCount => 0x12,
+            Self::ClearOverrunCounterAndFlag => 0x14,
+            Self::Custom(code) => code,
+        }
+    }
+
+    /// Checks if the server echoes the request data in the response.
+    ///
+    /// Returns `false` for sub-functions that return counters or registers
+    /// of the server and for custom sub-functions.
+    #[must_use]
+    pub const fn echoes_data(self) -> bool {
+        // Custom sub-functions might also denote these sub-functions.
+        matches!(
+            Self::new(self.value()),
+            Self::ReturnQueryData
+                | Self::RestartCommunicationsOption
+                | Self::ChangeAsciiInputDelimiter
+                | Self::ForceListenOnlyMode
+                | Self::ClearCountersAndDiagnosticRegister
+                | Self::ClearOverrunCounterAndFlag
+        )
+    }
+}
+
+impl Display for DiagnosticsSubFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value().fmt(f)
+    }
+}
+
 /// A Modbus protocol address is represented by 16 bit from `0` to `65535`.
 ///
 /// This *protocol address* uses 0-based indexing, while the *coil address* or
@@ -224,6 +369,23 @@ pub enum Request<'a> {
     /// The fourth parameter is the vector of values to write to the registers.
     ReadWriteMultipleRegisters(Address, Quantity, Address, Cow<'a, [Word]>),
 
+    /// A request to read the contents of a FIFO queue of holding registers.
+    /// The parameter is the address of the FIFO pointer register.
+    ReadFifoQueue(Address),
+
+    /// A request to read the eight exception status outputs (Serial Line only).
+    ReadExceptionStatus,
+
+    /// A request to read the identification of a device (0x2B / 0x0E).
+    /// The first parameter is the access type.
+    /// The second parameter is the first object to read.
+    ReadDeviceIdentification(ReadDeviceIdCode, DeviceIdObjectId),
+
+    /// A diagnostics request (Serial Line only).
+    /// The first parameter is the sub-function.
+    /// The second parameter is the vector of data words, usually a single word.
+    Diagnostics(DiagnosticsSubFunction, Cow<'a, [Word]>),
+
     /// A raw Modbus request.
     /// The first parameter is the Modbus function code.
     /// The second parameter is the raw bytes of the request.
@@ -256,6 +418,12 @@ impl Request<'_> {
             ReadWriteMultipleRegisters(addr, qty, write_addr, words) => {
                 ReadWriteMultipleRegisters(addr, qty, write_addr, Cow::Owned(words.into_owned()))
             }
+            ReadFifoQueue(addr) => ReadFifoQueue(addr),
+            ReadExceptionStatus => ReadExceptionStatus,
+            ReadDeviceIdentification(code, object_id) => ReadDeviceIdentification(code, object_id),
+            Diagnostics(sub_function, words) => {
+                Diagnostics(sub_function, Cow::Owned(words.into_owned()))
+            }
             Custom(func, bytes) => Custom(func, Cow::Owned(bytes.into_owned())),
         }
     }
@@ -284,9 +452,87 @@ impl Request<'_> {
 
             ReadWriteMultipleRegisters(_, _, _, _) => FunctionCode::ReadWriteMultipleRegisters,
 
+            ReadFifoQueue(_) => FunctionCode::ReadFifoQueue,
+
+            ReadExceptionStatus => FunctionCode::ReadExceptionStatus,
+
+            ReadDeviceIdentification(_, _) => FunctionCode::EncapsulatedInterfaceTransport,
+
+            Diagnostics(_, _) => FunctionCode::Diagnostics,
+
             Custom(code, _) => FunctionCode::Custom(*code),
         }
     }
+
+    /// Checks if the server is supposed to send a response for this request.
+    ///
+    /// Independent of the request, a server never responds to broadcast
+    /// messages on a serial line, see [`Slave::is_broadcast()`](crate::Slave::is_broadcast).
+    #[must_use]
+    pub const fn expects_response(&self) -> bool {
+        use Request::*;
+
+        match self {
+            ReadCoils(_, _)
+            | ReadDiscreteInputs(_, _)
+            | WriteSingleCoil(_, _)
+            | WriteMultipleCoils(_, _)
+            | ReadInputRegisters(_, _)
+            | ReadHoldingRegisters(_, _)
+            | WriteSingleRegister(_, _)
+            | WriteMultipleRegisters(_, _)
+            | ReportServerId
+            | MaskWriteRegister(_, _, _)
+            | ReadWriteMultipleRegisters(_, _, _, _)
+            | ReadFifoQueue(_)
+            | ReadExceptionStatus
+            | ReadDeviceIdentification(_, _)
+            | Custom(_, _) => true,
+            Diagnostics(sub_function, _) => {
+                !matches!(sub_function, DiagnosticsSubFunction::ForceListenOnlyMode)
+            }
+        }
+    }
+
+    /// The response implied by a request that is not answered by the server.
+    ///
+    /// Only requests that write data can be sent without receiving a response,
+    /// because the corresponding response simply echoes the request parameters.
+    /// Returns `None` for all requests that read data and for custom requests,
+    /// whose response is unknown.
+    #[cfg(any(feature = "rtu", feature = "tcp"))]
+    pub(crate) fn implicit_response(&self) -> Option<Response> {
+        use Request::*;
+
+        let response = match self {
+            WriteSingleCoil(addr, coil) => Response::WriteSingleCoil(*addr, *coil),
+            WriteMultipleCoils(addr, coils) => {
+                Response::WriteMultipleCoils(*addr, crate::codec::u16_len(coils.len()))
+            }
+            WriteSingleRegister(addr, word) => Response::WriteSingleRegister(*addr, *word),
+            WriteMultipleRegisters(addr, words) => {
+                Response::WriteMultipleRegisters(*addr, crate::codec::u16_len(words.len()))
+            }
+            MaskWriteRegister(addr, and_mask, or_mask) => {
+                Response::MaskWriteRegister(*addr, *and_mask, *or_mask)
+            }
+            Diagnostics(sub_function, words) if sub_function.echoes_data() => {
+                Response::Diagnostics(*sub_function, words.to_vec())
+            }
+            ReadCoils(_, _)
+            | ReadDiscreteInputs(_, _)
+            | ReadInputRegisters(_, _)
+            | ReadHoldingRegisters(_, _)
+            | ReportServerId
+            | ReadWriteMultipleRegisters(_, _, _, _)
+            | ReadFifoQueue(_)
+            | ReadExceptionStatus
+            | ReadDeviceIdentification(_, _)
+            | Diagnostics(_, _)
+            | Custom(_, _) => return None,
+        };
+        Some(response)
+    }
 }
 
 /// A Modbus request with slave included
@@ -374,6 +620,23 @@ pub enum Response {
     /// The parameter contains the register values that have been read as part of the read instruction
     ReadWriteMultipleRegisters(Vec<Word>),
 
+    /// Response to a `ReadFifoQueue` request
+    /// The parameter contains the queued register values, starting with the oldest value
+    ReadFifoQueue(Vec<Word>),
+
+    /// Response to a `ReadExceptionStatus` request
+    /// The parameter contains the eight exception status outputs, one per bit
+    ReadExceptionStatus(u8),
+
+    /// Response to a `ReadDeviceIdentification` request
+    /// The parameter contains the objects and the segmentation of the response
+    ReadDeviceIdentification(ReadDeviceIdentificationResponse),
+
+    /// Response to a `Diagnostics` request
+    /// The first parameter contains the echoed sub-function
+    /// The second parameter contains the returned data words
+    Diagnostics(DiagnosticsSubFunction, Vec<Word>),
+
     /// Response to a raw Modbus request
     /// The first parameter contains the returned Modbus function code
     /// The second parameter contains the bytes read following the function code
@@ -405,6 +668,14 @@ impl Response {
 
             ReadWriteMultipleRegisters(_) => FunctionCode::ReadWriteMultipleRegisters,
 
+            ReadFifoQueue(_) => FunctionCode::ReadFifoQueue,
+
+            ReadExceptionStatus(_) => FunctionCode::ReadExceptionStatus,
+
+            ReadDeviceIdentification(_) => FunctionCode::EncapsulatedInterfaceTransport,
+
+            Diagnostics(_, _) => FunctionCode::Diagnostics,
+
             Custom(code, _) => FunctionCode::Custom(*code),
         }
     }
@@ -518,8 +789,10 @@ impl<'a> From<RequestPdu<'a>> for Request<'a> {
 }
 
 /// Represents a message from the server (slave) to the client (master).
+///
+/// Clients decode the message into a [`DecodedResponse`].
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub(crate) struct ResponsePdu(pub(crate) Result<Response, ExceptionResponse>);
+pub(crate) struct ResponsePdu<R = Response>(pub(crate) Result<R, ExceptionResponse>);
 
 impl From<Response> for ResponsePdu {
     fn from(from: Response) -> Self {
@@ -527,12 +800,63 @@ impl From<Response> for ResponsePdu {
     }
 }
 
-impl From<ExceptionResponse> for ResponsePdu {
+impl<R> From<ExceptionResponse> for ResponsePdu<R> {
     fn from(from: ExceptionResponse) -> Self {
         ResponsePdu(Err(from))
     }
 }
 
+/// A response as decoded by the built-in clients.
+///
+/// The registers of read responses are only accessed in the received
+/// frame if requested, see
+/// [`Context::read_holding_registers_view()`](crate::client::Context::read_holding_registers_view).
+/// Not exported, i.e. the public API only exposes regular responses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodedResponse {
+    Response(Response),
+
+    /// The registers of a response to a `ReadHoldingRegisters` or
+    /// `ReadInputRegisters` request
+    Registers(FunctionCode, Registers),
+}
+
+impl DecodedResponse {
+    pub(crate) const fn function_code(&self) -> FunctionCode {
+        match self {
+            Self::Response(response) => response.function_code(),
+            Self::Registers(function_code, _) => *function_code,
+        }
+    }
+
+    /// Copies the registers of views into a regular response.
+    pub(crate) fn into_response(self) -> Response {
+        match self {
+            Self::Response(response) => response,
+            Self::Registers(function_code, registers) => {
+                let words = registers.iter().collect();
+                if function_code == FunctionCode::ReadInputRegisters {
+                    Response::ReadInputRegisters(words)
+                } else {
+                    Response::ReadHoldingRegisters(words)
+                }
+            }
+        }
+    }
+}
+
+impl From<Response> for DecodedResponse {
+    fn from(from: Response) -> Self {
+        Self::Response(from)
+    }
+}
+
+impl From<ResponsePdu> for ResponsePdu<DecodedResponse> {
+    fn from(from: ResponsePdu) -> Self {
+        ResponsePdu(from.0.map(Into::into))
+    }
+}
+
 #[cfg(any(
     feature = "rtu-over-tcp-server",
     feature = "rtu-server",
@@ -638,6 +962,17 @@ mod tests {
         assert_eq!(FunctionCode::Custom(70).value(), 70);
     }
 
+    #[test]
+    fn serial_line_only_function_codes() {
+        for code in [0x07, 0x08, 0x0B, 0x0C, 0x11] {
+            assert!(FunctionCode::new(code).is_serial_line_only());
+        }
+        for code in [0x01, 0x03, 0x10, 0x17, 0x2B, 0x64] {
+            assert!(!FunctionCode::new(code).is_serial_line_only());
+        }
+        assert!(FunctionCode::Custom(0x07).is_serial_line_only());
+    }
+
     #[test]
     fn function_code_from_request() {
         use Request::*;
@@ -688,6 +1023,56 @@ mod tests {
         assert_eq!(Custom(88, Cow::Borrowed(&[])).function_code().value(), 88);
     }
 
+    #[test]
+    #[cfg(any(feature = "rtu", feature = "tcp"))]
+    fn implicit_response_of_request() {
+        use Request::*;
+
+        assert_eq!(
+            WriteSingleCoil(1, true).implicit_response(),
+            Some(Response::WriteSingleCoil(1, true))
+        );
+        assert_eq!(
+            WriteMultipleCoils(2, Cow::Borrowed(&[true, false, true])).implicit_response(),
+            Some(Response::WriteMultipleCoils(2, 3))
+        );
+        assert_eq!(
+            WriteMultipleRegisters(3, Cow::Borrowed(&[1, 2])).implicit_response(),
+            Some(Response::WriteMultipleRegisters(3, 2))
+        );
+        assert_eq!(
+            MaskWriteRegister(4, 0xF0, 0x0F).implicit_response(),
+            Some(Response::MaskWriteRegister(4, 0xF0, 0x0F))
+        );
+        assert_eq!(ReadCoils(0, 1).implicit_response(), None);
+        assert_eq!(ReadHoldingRegisters(0, 1).implicit_response(), None);
+        assert_eq!(
+            ReadWriteMultipleRegisters(0, 1, 0, Cow::Borrowed(&[1])).implicit_response(),
+            None
+        );
+        assert_eq!(
+            Custom(0x42, Cow::Borrowed(&[1, 2])).implicit_response(),
+            None
+        );
+    }
+
+    #[test]
+    fn requests_without_response() {
+        use Request::*;
+
+        assert!(ReadCoils(0, 1).expects_response());
+        assert!(Custom(0x42, Cow::Borrowed(&[])).expects_response());
+        assert!(
+            Diagnostics(DiagnosticsSubFunction::ReturnQueryData, Cow::Borrowed(&[1]))
+                .expects_response()
+        );
+        assert!(!Diagnostics(
+            DiagnosticsSubFunction::ForceListenOnlyMode,
+            Cow::Borrowed(&[0])
+        )
+        .expects_response());
+    }
+
     #[test]
     fn function_code_from_response() {
         use Response::*;
@@ -17,9 +17,9 @@ pub struct RequestAdu<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct ResponseAdu {
+pub(crate) struct ResponseAdu<R = Response> {
     pub(crate) hdr: Header,
-    pub(crate) pdu: ResponsePdu,
+    pub(crate) pdu: ResponsePdu<R>,
 }
 
 impl<'a> From<RequestAdu<'a>> for Request<'a> {
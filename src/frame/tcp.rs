@@ -1,17 +1,119 @@
 // SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+// Only reachable from outside of the crate with the `raw-frames` feature.
+#![cfg_attr(not(feature = "raw-frames"), allow(dead_code, unreachable_pub))]
+
+use std::io;
+
 use super::*;
 
-pub(crate) type TransactionId = u16;
-pub(crate) type UnitId = u8;
+/// Transaction identifier of a Modbus TCP frame.
+pub type TransactionId = u16;
+
+/// Unit identifier of a Modbus TCP frame.
+pub type UnitId = u8;
+
+/// Protocol identifier of Modbus TCP frames.
+pub const PROTOCOL_ID: u16 = 0x0000;
 
+/// Length of the MBAP header in bytes.
+pub const HEADER_LEN: usize = 7;
+
+/// The MBAP header of a Modbus TCP frame.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub(crate) struct Header {
+pub struct Header {
     pub(crate) transaction_id: TransactionId,
     pub(crate) unit_id: UnitId,
 }
 
+impl Header {
+    /// Create a new header.
+    #[must_use]
+    pub const fn new(transaction_id: TransactionId, unit_id: UnitId) -> Self {
+        Self {
+            transaction_id,
+            unit_id,
+        }
+    }
+
+    /// The transaction identifier.
+    #[must_use]
+    pub const fn transaction_id(self) -> TransactionId {
+        self.transaction_id
+    }
+
+    /// The unit identifier.
+    #[must_use]
+    pub const fn unit_id(self) -> UnitId {
+        self.unit_id
+    }
+
+    /// Encode the header of a frame with a PDU of `pdu_len` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the PDU is too long to be encoded.
+    pub fn encode(self, pdu_len: usize) -> io::Result<[u8; HEADER_LEN]> {
+        let Some(len) = pdu_len
+            .checked_add(1)
+            .and_then(|len| u16::try_from(len).ok())
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid PDU length: {pdu_len}"),
+            ));
+        };
+        let [transaction_id_hi, transaction_id_lo] = self.transaction_id.to_be_bytes();
+        let [protocol_id_hi, protocol_id_lo] = PROTOCOL_ID.to_be_bytes();
+        let [len_hi, len_lo] = len.to_be_bytes();
+        Ok([
+            transaction_id_hi,
+            transaction_id_lo,
+            protocol_id_hi,
+            protocol_id_lo,
+            len_hi,
+            len_lo,
+            self.unit_id,
+        ])
+    }
+
+    /// Decode the header at the start of a frame.
+    ///
+    /// Returns the header and the length of the subsequent PDU in bytes,
+    /// or `None` if `buf` is too short.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the protocol identifier or the length are invalid.
+    pub fn decode(buf: &[u8]) -> io::Result<Option<(Self, usize)>> {
+        let Some(header_data) = buf.get(..HEADER_LEN) else {
+            return Ok(None);
+        };
+        let len = usize::from(u16::from_be_bytes([header_data[4], header_data[5]]));
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid data length: {len}"),
+            ));
+        }
+        // len = bytes of PDU + one byte (unit ID)
+        let pdu_len = len - 1;
+        let protocol_id = u16::from_be_bytes([header_data[2], header_data[3]]);
+        if protocol_id != PROTOCOL_ID {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Invalid protocol identifier: expected = {PROTOCOL_ID}, actual = {protocol_id}"
+                ),
+            ));
+        }
+        let transaction_id = u16::from_be_bytes([header_data[0], header_data[1]]);
+        let unit_id = header_data[6];
+        Ok(Some((Self::new(transaction_id, unit_id), pdu_len)))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RequestAdu<'a> {
     pub(crate) hdr: Header,
@@ -19,9 +121,9 @@ pub struct RequestAdu<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct ResponseAdu {
+pub(crate) struct ResponseAdu<R = Response> {
     pub(crate) hdr: Header,
-    pub(crate) pdu: ResponsePdu,
+    pub(crate) pdu: ResponsePdu<R>,
 }
 
 impl<'a> From<RequestAdu<'a>> for Request<'a> {
@@ -39,3 +141,23 @@ impl<'a> From<RequestAdu<'a>> for SlaveRequest<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_header() {
+        let hdr = Header::new(0x1001, 0xFE);
+        let data = hdr.encode(5).unwrap();
+        assert_eq!(data, [0x10, 0x01, 0x00, 0x00, 0x00, 0x06, 0xFE]);
+        assert_eq!(Header::decode(&data).unwrap(), Some((hdr, 5)));
+        assert_eq!(Header::decode(&data[..HEADER_LEN - 1]).unwrap(), None);
+    }
+
+    #[test]
+    fn encode_too_long_pdu() {
+        let hdr = Header::new(0, 0);
+        assert!(hdr.encode(usize::from(u16::MAX)).is_err());
+    }
+}
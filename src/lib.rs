@@ -32,10 +32,23 @@
 /// Used by [`Response::Custom`].
 pub use bytes;
 
+// The internal features are only enabled implicitly by the public features.
+#[cfg(all(feature = "sync", not(any(feature = "rtu-sync", feature = "tcp-sync"))))]
+compile_error!("feature \"sync\" is internal, enable \"rtu-sync\" or \"tcp-sync\" instead");
+#[cfg(all(
+    feature = "server",
+    not(any(feature = "rtu-server", feature = "tcp-server"))
+))]
+compile_error!("feature \"server\" is internal, enable \"rtu-server\" or \"tcp-server\" instead");
+
 pub mod prelude;
 
+pub mod features;
+
 pub mod client;
 
+pub mod data;
+
 pub mod slave;
 pub use self::slave::{Slave, SlaveId};
 
@@ -45,10 +58,13 @@ mod error;
 pub use self::error::{Error, ProtocolError};
 
 mod frame;
+#[cfg(feature = "raw-frames")]
+pub mod raw;
 #[cfg(feature = "server")]
 pub use self::frame::SlaveRequest;
 pub use self::frame::{
-    Address, ExceptionCode, ExceptionResponse, FunctionCode, Quantity, Request, Response,
+    Address, DeviceIdObjectId, DiagnosticsSubFunction, ExceptionCode, ExceptionResponse,
+    FunctionCode, Quantity, ReadDeviceIdCode, ReadDeviceIdentificationResponse, Request, Response,
 };
 
 /// Specialized [`std::result::Result`] type for type-checked responses of the _Modbus_ client API.
@@ -63,5 +79,40 @@ pub type Result<T> = std::result::Result<std::result::Result<T, ExceptionCode>,
 
 mod service;
 
+#[cfg(feature = "tracing")]
+mod spans;
+
+mod stats;
+pub use self::stats::ConnectionStats;
+
+mod metrics;
+pub use self::metrics::Metrics;
+
+mod mutex;
+
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+pub mod tap;
+
+#[cfg(feature = "capture")]
+pub mod capture;
+
+#[cfg(feature = "ws")]
+pub mod ws;
+
+#[cfg(feature = "rtu")]
+pub mod timing;
+
+#[cfg(feature = "tcp")]
+pub mod transform;
+
+#[cfg(any(feature = "rtu-sync", feature = "rtu-server"))]
+pub mod serial;
+
 #[cfg(feature = "server")]
 pub mod server;
+
+#[cfg(feature = "server")]
+pub mod simulator;
+
+#[cfg(feature = "tcp-server")]
+pub mod testing;
@@ -0,0 +1,276 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Metrics of clients and servers
+
+use std::{fmt, io};
+
+use crate::{ConnectionStats, Error, ExceptionCode, FunctionCode};
+
+/// Receives the activity of a client or server, e.g. for exporting
+/// counters to a monitoring system.
+///
+/// All methods do nothing by default, i.e. implementations only need
+/// to override the methods of the metrics they are interested in. The
+/// methods are invoked while processing requests and should return
+/// quickly, e.g. by incrementing atomic counters.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// use tokio_modbus::{ExceptionCode, FunctionCode, Metrics};
+///
+/// #[derive(Debug, Default)]
+/// struct Counters {
+///     requests: AtomicU64,
+///     exceptions: AtomicU64,
+/// }
+///
+/// impl Metrics for Counters {
+///     fn request(&self, _: FunctionCode) {
+///         self.requests.fetch_add(1, Ordering::Relaxed);
+///     }
+///
+///     fn exception(&self, _: FunctionCode, _: ExceptionCode) {
+///         self.exceptions.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+/// ```
+pub trait Metrics: fmt::Debug + Send + Sync {
+    /// A request has been sent by a client or received by a server.
+    ///
+    /// Clients report each attempt, i.e. also requests that are repeated.
+    fn request(&self, function: FunctionCode) {
+        let _ = function;
+    }
+
+    /// A response has been received by a client or sent by a server.
+    fn response(&self, function: FunctionCode) {
+        let _ = function;
+    }
+
+    /// An exception response has been received by a client or sent by
+    /// a server.
+    fn exception(&self, function: FunctionCode, exception: ExceptionCode) {
+        let _ = (function, exception);
+    }
+
+    /// A client didn't receive a response in time.
+    fn timeout(&self, function: FunctionCode) {
+        let _ = function;
+    }
+
+    /// Frames with an invalid checksum have been discarded.
+    ///
+    /// Only counted by RTU clients and servers.
+    fn checksum_errors(&self, count: u64) {
+        let _ = count;
+    }
+
+    /// Bytes have been written to the transport.
+    fn bytes_sent(&self, count: u64) {
+        let _ = count;
+    }
+
+    /// Bytes have been read from the transport.
+    fn bytes_received(&self, count: u64) {
+        let _ = count;
+    }
+}
+
+/// The counters of a connection for reporting their increments.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Counters {
+    bytes_sent: u64,
+    bytes_received: u64,
+    checksum_errors: u64,
+}
+
+impl Counters {
+    pub(crate) fn new(stats: Option<ConnectionStats>, checksum_errors: u64) -> Self {
+        let stats = stats.unwrap_or_default();
+        Self {
+            bytes_sent: stats.bytes_sent,
+            bytes_received: stats.bytes_received,
+            checksum_errors,
+        }
+    }
+
+    /// Report the increments since `previous`.
+    ///
+    /// Counters that decreased belong to a new connection and are
+    /// reported completely.
+    pub(crate) fn report_since(self, previous: Self, metrics: &dyn Metrics) {
+        let increment =
+            |current: u64, previous: u64| current.checked_sub(previous).unwrap_or(current);
+        let bytes_sent = increment(self.bytes_sent, previous.bytes_sent);
+        if bytes_sent > 0 {
+            metrics.bytes_sent(bytes_sent);
+        }
+        let bytes_received = increment(self.bytes_received, previous.bytes_received);
+        if bytes_received > 0 {
+            metrics.bytes_received(bytes_received);
+        }
+        let checksum_errors = increment(self.checksum_errors, previous.checksum_errors);
+        if checksum_errors > 0 {
+            metrics.checksum_errors(checksum_errors);
+        }
+    }
+}
+
+/// Reports the requests and the traffic of a server connection.
+#[cfg(feature = "server")]
+#[derive(Debug)]
+pub(crate) struct ServerMetrics<'a> {
+    metrics: &'a dyn Metrics,
+    counters: &'a crate::stats::ConnectionCounters,
+    reported: Counters,
+}
+
+#[cfg(feature = "server")]
+impl<'a> ServerMetrics<'a> {
+    pub(crate) fn new(
+        metrics: &'a dyn Metrics,
+        counters: &'a crate::stats::ConnectionCounters,
+    ) -> Self {
+        Self {
+            metrics,
+            counters,
+            reported: Counters::default(),
+        }
+    }
+
+    /// Report a processed request, the response if any, and the traffic
+    /// since the previous request.
+    pub(crate) fn report(
+        &mut self,
+        function: FunctionCode,
+        response: Option<Result<(), ExceptionCode>>,
+        checksum_errors: u64,
+    ) {
+        self.metrics.request(function);
+        match response {
+            Some(Ok(())) => self.metrics.response(function),
+            Some(Err(exception)) => self.metrics.exception(function, exception),
+            None => {}
+        }
+        let counters = Counters::new(Some(self.counters.stats()), checksum_errors);
+        counters.report_since(self.reported, self.metrics);
+        self.reported = counters;
+    }
+}
+
+/// The outcome of a response that is sent by a server.
+#[cfg(feature = "server")]
+pub(crate) fn outcome(response: &crate::frame::ResponsePdu) -> Result<(), ExceptionCode> {
+    response
+        .0
+        .as_ref()
+        .map(|_| ())
+        .map_err(|exception| exception.exception)
+}
+
+/// Report the result of a client request.
+pub(crate) fn report_result<T>(
+    metrics: &dyn Metrics,
+    function: FunctionCode,
+    result: &crate::Result<T>,
+) {
+    metrics.request(function);
+    match result {
+        Ok(Ok(_)) => metrics.response(function),
+        Ok(Err(exception)) => metrics.exception(function, *exception),
+        Err(Error::Timeout(_)) => metrics.timeout(function),
+        Err(Error::Transport(err)) if err.kind() == io::ErrorKind::TimedOut => {
+            metrics.timeout(function);
+        }
+        Err(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Metrics for Recorder {
+        fn request(&self, function: FunctionCode) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("request {function}"));
+        }
+
+        fn exception(&self, function: FunctionCode, exception: ExceptionCode) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("exception {function} {exception:?}"));
+        }
+
+        fn timeout(&self, function: FunctionCode) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("timeout {function}"));
+        }
+
+        fn bytes_sent(&self, count: u64) {
+            self.events.lock().unwrap().push(format!("sent {count}"));
+        }
+
+        fn bytes_received(&self, count: u64) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("received {count}"));
+        }
+    }
+
+    #[test]
+    fn report_results_and_increments() {
+        let recorder = Recorder::default();
+        let function = FunctionCode::ReadHoldingRegisters;
+        report_result::<()>(
+            &recorder,
+            function,
+            &Ok(Err(ExceptionCode::ServerDeviceBusy)),
+        );
+        report_result::<()>(
+            &recorder,
+            function,
+            &Err(Error::Timeout(std::time::Duration::from_secs(1))),
+        );
+
+        let stats = |bytes_sent, bytes_received| ConnectionStats {
+            bytes_sent,
+            bytes_received,
+            ..Default::default()
+        };
+        let previous = Counters::new(Some(stats(10, 20)), 0);
+        Counters::new(Some(stats(18, 20)), 0).report_since(previous, &recorder);
+        // A new connection.
+        Counters::new(Some(stats(8, 9)), 0).report_since(previous, &recorder);
+
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            [
+                "request 3",
+                "exception 3 ServerDeviceBusy",
+                "request 3",
+                "timeout 3",
+                "sent 8",
+                "sent 8",
+                "received 9",
+            ]
+        );
+    }
+}
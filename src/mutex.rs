@@ -0,0 +1,12 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Lock a mutex, even if another thread panicked while holding it.
+///
+/// All shared state of this crate is updated by operations that
+/// can't leave it inconsistent when interrupted by a panic.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -8,6 +8,12 @@
 ///////////////////////////////////////////////////////////////////
 pub use crate::client;
 
+#[allow(missing_docs)]
+#[cfg(feature = "rtu")]
+pub mod ascii {
+    pub use crate::client::ascii::*;
+}
+
 #[allow(missing_docs)]
 #[cfg(feature = "rtu")]
 pub mod rtu {
@@ -20,6 +26,12 @@ pub mod tcp {
     pub use crate::client::tcp::*;
 }
 
+#[allow(missing_docs)]
+#[cfg(feature = "tcp")]
+pub mod udp {
+    pub use crate::client::udp::*;
+}
+
 #[allow(missing_docs)]
 #[cfg(feature = "sync")]
 pub mod sync {
@@ -0,0 +1,9 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Raw frame types for tests and interoperability tools
+
+/// MBAP header of Modbus TCP frames
+pub mod tcp {
+    pub use crate::frame::tcp::{Header, TransactionId, UnitId, HEADER_LEN, PROTOCOL_ID};
+}
@@ -0,0 +1,219 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Opening serial ports with diagnostics
+//!
+//! Errors of [`tokio_serial`] are classified into the most common
+//! causes why a serial port could not be opened. Each cause comes with
+//! a platform-specific hint for resolving it.
+//!
+//! Functions that return an [`io::Error`] wrap the [`OpenError`], which
+//! could be recovered with [`io::Error::get_ref()`].
+//!
+//! ```no_run
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio_modbus::{prelude::*, serial};
+//!
+//! let builder = tokio_serial::new("/dev/ttyUSB0", 19200);
+//! let port = match serial::open(&builder) {
+//!     Ok(port) => port,
+//!     Err(err) => {
+//!         if let Some(hint) = err.hint() {
+//!             eprintln!("{err} ({hint})");
+//!         }
+//!         return Err(err.into());
+//!     }
+//! };
+//! let ctx = rtu::attach_slave(port, Slave(1));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+
+use thiserror::Error;
+use tokio_serial::{SerialPortBuilder, SerialStream};
+
+use crate::client::Backoff;
+
+/// The cause why a serial port could not be opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenErrorKind {
+    /// The device does not exist.
+    NotFound,
+
+    /// The user is not permitted to access the device.
+    PermissionDenied,
+
+    /// The device is used by another process.
+    Busy,
+
+    /// Any other error, e.g. invalid settings.
+    Other,
+}
+
+impl OpenErrorKind {
+    fn classify(err: &tokio_serial::Error) -> Self {
+        match err.kind() {
+            tokio_serial::ErrorKind::Io(io::ErrorKind::NotFound) => Self::NotFound,
+            tokio_serial::ErrorKind::Io(io::ErrorKind::PermissionDenied) => Self::PermissionDenied,
+            // Reported for EBUSY and for ports that are locked by another
+            // process. On Windows also for missing ports.
+            tokio_serial::ErrorKind::NoDevice => {
+                if cfg!(windows) {
+                    Self::NotFound
+                } else {
+                    Self::Busy
+                }
+            }
+            _ => Self::Other,
+        }
+    }
+
+    /// A hint for resolving the cause on this platform.
+    #[must_use]
+    pub const fn hint(self) -> Option<&'static str> {
+        match self {
+            Self::NotFound => Some(if cfg!(windows) {
+                "check the name of the COM port in the device manager, \
+                 or that it is not used by another application"
+            } else if cfg!(target_os = "macos") {
+                "check the path of the device, e.g. /dev/cu.usbserial-*"
+            } else {
+                "check the path of the device, e.g. /dev/ttyUSB* or /dev/ttyACM*, \
+                 or use a persistent path in /dev/serial/by-id/"
+            }),
+            Self::PermissionDenied => Some(if cfg!(target_os = "linux") {
+                "add the user to the group that owns the device, e.g. \
+                 dialout or uucp, and log in again"
+            } else {
+                "check the permissions of the device"
+            }),
+            Self::Busy => Some(if cfg!(target_os = "linux") {
+                "the device is used by another process, e.g. ModemManager, \
+                 see `fuser` or `lsof`"
+            } else {
+                "the device is used by another process"
+            }),
+            Self::Other => None,
+        }
+    }
+}
+
+impl From<OpenErrorKind> for io::ErrorKind {
+    fn from(kind: OpenErrorKind) -> Self {
+        match kind {
+            OpenErrorKind::NotFound => Self::NotFound,
+            OpenErrorKind::PermissionDenied => Self::PermissionDenied,
+            OpenErrorKind::Busy | OpenErrorKind::Other => Self::Other,
+        }
+    }
+}
+
+/// A serial port could not be opened.
+#[derive(Debug, Error)]
+#[error("failed to open serial port: {source}")]
+pub struct OpenError {
+    kind: OpenErrorKind,
+    source: tokio_serial::Error,
+}
+
+impl OpenError {
+    fn new(source: tokio_serial::Error) -> Self {
+        Self {
+            kind: OpenErrorKind::classify(&source),
+            source,
+        }
+    }
+
+    /// The cause.
+    #[must_use]
+    pub const fn kind(&self) -> OpenErrorKind {
+        self.kind
+    }
+
+    /// A hint for resolving the cause on this platform.
+    #[must_use]
+    pub const fn hint(&self) -> Option<&'static str> {
+        self.kind.hint()
+    }
+}
+
+impl From<OpenError> for io::Error {
+    fn from(err: OpenError) -> Self {
+        io::Error::new(err.kind.into(), err)
+    }
+}
+
+/// Open a serial port.
+///
+/// Requires a Tokio runtime.
+pub fn open(builder: &SerialPortBuilder) -> Result<SerialStream, OpenError> {
+    SerialStream::open(builder).map_err(OpenError::new)
+}
+
+/// Open a serial port and retry while it is busy.
+///
+/// Serial ports are often busy for a short period, e.g. when a previous
+/// instance of the application has not been terminated yet or when a
+/// service probes newly attached devices. All other errors are returned
+/// immediately.
+pub async fn open_retrying_busy(
+    builder: &SerialPortBuilder,
+    backoff: Backoff,
+) -> Result<SerialStream, OpenError> {
+    let mut delays = backoff.delays().peekable();
+    loop {
+        if let Some(delay) = delays.next() {
+            tokio::time::sleep(delay).await;
+        }
+        match open(builder) {
+            Err(err) if err.kind() == OpenErrorKind::Busy && delays.peek().is_some() => {
+                log::debug!("{err}, retrying");
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn classify_errors() {
+        for (kind, expected) in [
+            (
+                tokio_serial::ErrorKind::Io(io::ErrorKind::NotFound),
+                OpenErrorKind::NotFound,
+            ),
+            (
+                tokio_serial::ErrorKind::Io(io::ErrorKind::PermissionDenied),
+                OpenErrorKind::PermissionDenied,
+            ),
+            (tokio_serial::ErrorKind::InvalidInput, OpenErrorKind::Other),
+        ] {
+            let err = tokio_serial::Error::new(kind, "");
+            assert_eq!(OpenErrorKind::classify(&err), expected);
+        }
+        assert!(OpenErrorKind::Busy.hint().is_some());
+        assert!(OpenErrorKind::Other.hint().is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn open_missing_port() {
+        let builder = tokio_serial::new("/dev/tokio-modbus-missing", 19200);
+        let backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(1), 3);
+        let err = open_retrying_busy(&builder, backoff).await.unwrap_err();
+        assert_eq!(err.kind(), OpenErrorKind::NotFound);
+        assert!(err.hint().is_some());
+
+        let err = io::Error::from(err);
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.get_ref().unwrap().is::<OpenError>());
+    }
+}
@@ -0,0 +1,297 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    collections::BTreeMap,
+    num::NonZeroU16,
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::{mutex::lock, Address, FunctionCode, Quantity, Request};
+
+use super::Service;
+
+/// The data tables of a Modbus server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Table {
+    /// Coils (read/write bits)
+    Coils,
+
+    /// Discrete inputs (read-only bits)
+    DiscreteInputs,
+
+    /// Input registers (read-only words)
+    InputRegisters,
+
+    /// Holding registers (read/write words)
+    HoldingRegisters,
+}
+
+/// Access counters of an address range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessCounts {
+    /// Number of requests that read from the address range.
+    pub reads: u64,
+
+    /// Number of requests that wrote to the address range.
+    pub writes: u64,
+
+    /// Time of the most recent access.
+    pub last_access: SystemTime,
+}
+
+/// Access statistics of an address range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressRangeStats {
+    /// The accessed table.
+    pub table: Table,
+
+    /// The addresses in the table.
+    pub addresses: RangeInclusive<Address>,
+
+    /// The access counters.
+    pub counts: AccessCounts,
+}
+
+#[derive(Debug)]
+struct Inner {
+    block_size: NonZeroU16,
+    max_blocks: usize,
+    blocks: BTreeMap<(Table, u16), AccessCounts>,
+    untracked_accesses: u64,
+}
+
+impl Inner {
+    fn record(&mut self, table: Table, addr: Address, cnt: Quantity, write: bool, now: SystemTime) {
+        if cnt == 0 {
+            return;
+        }
+        let block_size = self.block_size.get();
+        let first_block = addr / block_size;
+        let last_addr = addr.saturating_add(cnt - 1);
+        let last_block = last_addr / block_size;
+        for block in first_block..=last_block {
+            let key = (table, block);
+            if !self.blocks.contains_key(&key) && self.blocks.len() >= self.max_blocks {
+                self.untracked_accesses += 1;
+                continue;
+            }
+            let counts = self.blocks.entry(key).or_insert(AccessCounts {
+                reads: 0,
+                writes: 0,
+                last_access: now,
+            });
+            if write {
+                counts.writes += 1;
+            } else {
+                counts.reads += 1;
+            }
+            counts.last_access = now;
+        }
+    }
+}
+
+/// Per address range access statistics of a server.
+///
+/// Reveals which coils and registers are actually accessed by clients,
+/// e.g. for trimming register maps or for heatmap tooling.
+///
+/// Addresses are grouped into fixed-size blocks to bound the memory
+/// usage. Accesses of blocks beyond the configured maximum number of
+/// blocks are not tracked individually, see [`Self::untracked_accesses()`].
+///
+/// The statistics are shared by all clones.
+#[derive(Debug, Clone)]
+pub struct AccessStats {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl AccessStats {
+    /// Track accesses in blocks of `block_size` addresses for up to
+    /// `max_blocks` blocks.
+    #[must_use]
+    pub fn new(block_size: NonZeroU16, max_blocks: usize) -> Self {
+        let inner = Inner {
+            block_size,
+            max_blocks,
+            blocks: BTreeMap::new(),
+            untracked_accesses: 0,
+        };
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Record the accesses of all requests that are processed by the service.
+    pub fn track<S>(&self, service: S) -> TrackAccess<S> {
+        TrackAccess {
+            service,
+            stats: self.clone(),
+        }
+    }
+
+    /// Record the accesses of a request.
+    pub fn record(&self, request: &Request<'_>) {
+        use Request::*;
+
+        let now = SystemTime::now();
+        let mut inner = lock(&self.inner);
+        match request {
+            ReadCoils(addr, cnt) => inner.record(Table::Coils, *addr, *cnt, false, now),
+            ReadDiscreteInputs(addr, cnt) => {
+                inner.record(Table::DiscreteInputs, *addr, *cnt, false, now);
+            }
+            ReadInputRegisters(addr, cnt) => {
+                inner.record(Table::InputRegisters, *addr, *cnt, false, now);
+            }
+            ReadHoldingRegisters(addr, cnt) => {
+                inner.record(Table::HoldingRegisters, *addr, *cnt, false, now);
+            }
+            WriteSingleCoil(addr, _) => inner.record(Table::Coils, *addr, 1, true, now),
+            WriteMultipleCoils(addr, coils) => {
+                let cnt = Quantity::try_from(coils.len()).unwrap_or(Quantity::MAX);
+                inner.record(Table::Coils, *addr, cnt, true, now);
+            }
+            WriteSingleRegister(addr, _) | MaskWriteRegister(addr, _, _) => {
+                inner.record(Table::HoldingRegisters, *addr, 1, true, now);
+            }
+            ReadFifoQueue(addr) => inner.record(Table::HoldingRegisters, *addr, 1, false, now),
+            WriteMultipleRegisters(addr, words) => {
+                let cnt = Quantity::try_from(words.len()).unwrap_or(Quantity::MAX);
+                inner.record(Table::HoldingRegisters, *addr, cnt, true, now);
+            }
+            ReadWriteMultipleRegisters(read_addr, read_cnt, write_addr, words) => {
+                inner.record(Table::HoldingRegisters, *read_addr, *read_cnt, false, now);
+                let write_cnt = Quantity::try_from(words.len()).unwrap_or(Quantity::MAX);
+                inner.record(Table::HoldingRegisters, *write_addr, write_cnt, true, now);
+            }
+            ReportServerId
+            | ReadExceptionStatus
+            | ReadDeviceIdentification(_, _)
+            | Diagnostics(_, _)
+            | Custom(_, _) => (),
+        }
+    }
+
+    /// Statistics of all accessed address ranges, ordered by table and address.
+    #[must_use]
+    pub fn snapshot(&self) -> Vec<AddressRangeStats> {
+        let inner = lock(&self.inner);
+        let block_size = inner.block_size.get();
+        inner
+            .blocks
+            .iter()
+            .map(|(&(table, block), &counts)| {
+                let first_addr = block * block_size;
+                let last_addr = first_addr.saturating_add(block_size - 1);
+                AddressRangeStats {
+                    table,
+                    addresses: first_addr..=last_addr,
+                    counts,
+                }
+            })
+            .collect()
+    }
+
+    /// Number of block accesses that have not been tracked
+    /// because the maximum number of blocks has been exceeded.
+    #[must_use]
+    pub fn untracked_accesses(&self) -> u64 {
+        lock(&self.inner).untracked_accesses
+    }
+
+    /// Discard all statistics.
+    pub fn reset(&self) {
+        let mut inner = lock(&self.inner);
+        inner.blocks.clear();
+        inner.untracked_accesses = 0;
+    }
+}
+
+/// A [`Service`] wrapper created by [`AccessStats::track()`].
+#[derive(Debug)]
+pub struct TrackAccess<S> {
+    service: S,
+    stats: AccessStats,
+}
+
+impl<S> Service for TrackAccess<S>
+where
+    S: Service<Request = Request<'static>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Exception = S::Exception;
+    type Future = S::Future;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        self.stats.record(&req);
+        self.service.call(req)
+    }
+
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        self.service.serve_serial_line_functions_over_tcp()
+    }
+
+    fn supported_functions(&self) -> Option<&[FunctionCode]> {
+        self.service.supported_functions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[test]
+    fn record_accesses_per_block() {
+        let stats = AccessStats::new(NonZeroU16::new(10).unwrap(), 3);
+        stats.record(&Request::ReadHoldingRegisters(5, 10));
+        stats.record(&Request::WriteSingleRegister(12, 0));
+        stats.record(&Request::WriteMultipleCoils(
+            0,
+            Cow::Borrowed(&[true, false]),
+        ));
+        // Exceeds the maximum number of blocks
+        stats.record(&Request::ReadInputRegisters(0, 1));
+        stats.record(&Request::ReportServerId);
+
+        let snapshot = stats.snapshot();
+        let summary: Vec<_> = snapshot
+            .iter()
+            .map(|stats| {
+                (
+                    stats.table,
+                    stats.addresses.clone(),
+                    stats.counts.reads,
+                    stats.counts.writes,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Table::Coils, 0..=9, 0, 1),
+                (Table::HoldingRegisters, 0..=9, 1, 0),
+                (Table::HoldingRegisters, 10..=19, 1, 1),
+            ]
+        );
+        assert_eq!(stats.untracked_accesses(), 1);
+
+        stats.reset();
+        assert!(stats.snapshot().is_empty());
+        assert_eq!(stats.untracked_accesses(), 0);
+    }
+
+    #[test]
+    fn record_accesses_at_end_of_address_space() {
+        let stats = AccessStats::new(NonZeroU16::new(1000).unwrap(), 10);
+        stats.record(&Request::ReadCoils(Address::MAX, 2));
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].addresses, 65000..=65535);
+    }
+}
@@ -0,0 +1,403 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{collections::BTreeMap, error, fmt, ops::RangeInclusive};
+
+use futures_util::future::{self, Either};
+
+use crate::{Address, ExceptionCode, Request};
+
+use super::{Service, Table};
+
+/// Address ranges of the same table overlap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlappingAddresses {
+    /// The table of both address ranges.
+    pub table: Table,
+
+    /// The address range that could not be mounted.
+    pub addresses: RangeInclusive<Address>,
+
+    /// The already mounted address range.
+    pub mounted: RangeInclusive<Address>,
+}
+
+impl fmt::Display for OverlappingAddresses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            table,
+            addresses,
+            mounted,
+        } = self;
+        write!(
+            f,
+            "{table:?} {first}..={last} overlap with mounted {mounted_first}..={mounted_last}",
+            first = addresses.start(),
+            last = addresses.end(),
+            mounted_first = mounted.start(),
+            mounted_last = mounted.end(),
+        )
+    }
+}
+
+impl error::Error for OverlappingAddresses {}
+
+/// Composes a server from services that are mounted on disjoint address ranges.
+///
+/// Requests are dispatched to the service that is mounted on the accessed
+/// table and address range. The addresses of requests are forwarded
+/// unmodified, i.e. they are not relative to the mounted range.
+///
+/// Requests that access unmounted addresses or that span multiple
+/// mounted ranges are answered with [`ExceptionCode::IllegalDataAddress`].
+/// Requests that don't access any table, e.g. [`Request::ReportServerId`],
+/// are dispatched to the [fallback service](Self::set_fallback()) or
+/// answered with [`ExceptionCode::IllegalFunction`] if there is none.
+/// Serial line only functions are
+/// [served over TCP](Service::serve_serial_line_functions_over_tcp())
+/// if the fallback service opts in.
+///
+/// All mounted services must have the same type. Use an `enum` for
+/// dispatching to different kinds of services.
+#[derive(Debug, Clone)]
+pub struct AddressSpace<S> {
+    // The mounted services, keyed by table and the first address.
+    mounts: BTreeMap<(Table, Address), (Address, S)>,
+    fallback: Option<S>,
+}
+
+impl<S> Default for AddressSpace<S> {
+    fn default() -> Self {
+        Self {
+            mounts: BTreeMap::new(),
+            fallback: None,
+        }
+    }
+}
+
+impl<S> AddressSpace<S> {
+    /// An empty address space.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mount a service on an address range of a table.
+    ///
+    /// Fails if the address range overlaps with an already mounted
+    /// address range of the same table.
+    pub fn mount(
+        &mut self,
+        table: Table,
+        addresses: RangeInclusive<Address>,
+        service: S,
+    ) -> Result<(), OverlappingAddresses> {
+        let first = *addresses.start();
+        let last = *addresses.end();
+        debug_assert!(first <= last);
+        let overlapping = self
+            .mounts
+            .range((table, Address::MIN)..=(table, last))
+            .next_back()
+            .filter(|(_, (mounted_last, _))| *mounted_last >= first);
+        if let Some((&(_, mounted_first), &(mounted_last, _))) = overlapping {
+            return Err(OverlappingAddresses {
+                table,
+                addresses,
+                mounted: mounted_first..=mounted_last,
+            });
+        }
+        self.mounts.insert((table, first), (last, service));
+        Ok(())
+    }
+
+    /// Mount a service on an address range of a table.
+    ///
+    /// See also: [`Self::mount()`]
+    pub fn with_mount(
+        mut self,
+        table: Table,
+        addresses: RangeInclusive<Address>,
+        service: S,
+    ) -> Result<Self, OverlappingAddresses> {
+        self.mount(table, addresses, service)?;
+        Ok(self)
+    }
+
+    /// Dispatch requests that don't access any table to a service.
+    ///
+    /// Returns the previous fallback service.
+    pub fn set_fallback(&mut self, service: S) -> Option<S> {
+        self.fallback.replace(service)
+    }
+
+    /// Dispatch requests that don't access any table to a service.
+    ///
+    /// See also: [`Self::set_fallback()`]
+    #[must_use]
+    pub fn with_fallback(mut self, service: S) -> Self {
+        self.set_fallback(service);
+        self
+    }
+
+    /// The mounted address ranges, ordered by table and address.
+    pub fn mounted(&self) -> impl Iterator<Item = (Table, RangeInclusive<Address>)> + '_ {
+        self.mounts
+            .iter()
+            .map(|(&(table, first), &(last, _))| (table, first..=last))
+    }
+
+    /// The service that is mounted on the whole address range.
+    #[must_use]
+    pub fn service(&self, table: Table, addresses: &RangeInclusive<Address>) -> Option<&S> {
+        let (&(mounted_table, _), (mounted_last, service)) = self
+            .mounts
+            .range(..=(table, *addresses.start()))
+            .next_back()?;
+        (mounted_table == table && mounted_last >= addresses.end()).then_some(service)
+    }
+
+    fn route(&self, request: &Request<'_>) -> Result<&S, ExceptionCode> {
+        use Request::*;
+
+        let (table, addr, cnt) = match request {
+            ReadCoils(addr, cnt) => (Table::Coils, *addr, usize::from(*cnt)),
+            ReadDiscreteInputs(addr, cnt) => (Table::DiscreteInputs, *addr, usize::from(*cnt)),
+            ReadInputRegisters(addr, cnt) => (Table::InputRegisters, *addr, usize::from(*cnt)),
+            ReadHoldingRegisters(addr, cnt) => (Table::HoldingRegisters, *addr, usize::from(*cnt)),
+            WriteSingleCoil(addr, _) => (Table::Coils, *addr, 1),
+            WriteMultipleCoils(addr, coils) => (Table::Coils, *addr, coils.len()),
+            WriteSingleRegister(addr, _) | MaskWriteRegister(addr, _, _) | ReadFifoQueue(addr) => {
+                (Table::HoldingRegisters, *addr, 1)
+            }
+            WriteMultipleRegisters(addr, words) => (Table::HoldingRegisters, *addr, words.len()),
+            ReadWriteMultipleRegisters(read_addr, read_cnt, write_addr, words) => {
+                let read_service =
+                    self.lookup(Table::HoldingRegisters, *read_addr, usize::from(*read_cnt))?;
+                let write_service =
+                    self.lookup(Table::HoldingRegisters, *write_addr, words.len())?;
+                if !std::ptr::eq(read_service, write_service) {
+                    return Err(ExceptionCode::IllegalDataAddress);
+                }
+                return Ok(read_service);
+            }
+            ReportServerId
+            | ReadExceptionStatus
+            | ReadDeviceIdentification(_, _)
+            | Diagnostics(_, _)
+            | Custom(_, _) => return self.fallback.as_ref().ok_or(ExceptionCode::IllegalFunction),
+        };
+        self.lookup(table, addr, cnt)
+    }
+
+    fn lookup(&self, table: Table, addr: Address, cnt: usize) -> Result<&S, ExceptionCode> {
+        // The quantity is validated by the mounted service.
+        let last = Address::try_from(usize::from(addr) + cnt.max(1) - 1)
+            .map_err(|_| ExceptionCode::IllegalDataAddress)?;
+        self.service(table, &(addr..=last))
+            .ok_or(ExceptionCode::IllegalDataAddress)
+    }
+}
+
+impl<S> Service for AddressSpace<S>
+where
+    S: Service<Request = Request<'static>, Exception = ExceptionCode>,
+    S::Response: Send,
+{
+    type Request = Request<'static>;
+    type Response = S::Response;
+    type Exception = ExceptionCode;
+    type Future = Either<S::Future, future::Ready<Result<S::Response, ExceptionCode>>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        match self.route(&req) {
+            Ok(service) => Either::Left(service.call(req)),
+            Err(exception) => {
+                log::trace!("Rejecting request {req:?}: {exception}");
+                Either::Right(future::ready(Err(exception)))
+            }
+        }
+    }
+
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        self.fallback
+            .as_ref()
+            .is_some_and(Service::serve_serial_line_functions_over_tcp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use futures_util::FutureExt as _;
+
+    use crate::Response;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Backend(u16);
+
+    impl Service for Backend {
+        type Request = Request<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Response, ExceptionCode>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            future::ready(match req {
+                Request::ReadHoldingRegisters(_, cnt) => {
+                    Ok(Response::ReadHoldingRegisters(vec![self.0; cnt.into()]))
+                }
+                Request::ReadWriteMultipleRegisters(_, cnt, _, _) => {
+                    Ok(Response::ReadWriteMultipleRegisters(vec![
+                        self.0;
+                        cnt.into()
+                    ]))
+                }
+                Request::WriteSingleCoil(addr, coil) => Ok(Response::WriteSingleCoil(addr, coil)),
+                _ => Err(ExceptionCode::IllegalFunction),
+            })
+        }
+    }
+
+    fn call(
+        space: &AddressSpace<Backend>,
+        req: Request<'static>,
+    ) -> Result<Response, ExceptionCode> {
+        space.call(req).now_or_never().unwrap()
+    }
+
+    #[test]
+    fn reject_overlapping_addresses() {
+        let mut space = AddressSpace::new()
+            .with_mount(Table::HoldingRegisters, 10..=19, Backend(1))
+            .unwrap()
+            .with_mount(Table::HoldingRegisters, 30..=39, Backend(2))
+            .unwrap();
+
+        for addresses in [0..=10, 19..=19, 15..=35, 39..=40] {
+            let mounted = if *addresses.end() < 30 {
+                10..=19
+            } else {
+                30..=39
+            };
+            assert_eq!(
+                space.mount(Table::HoldingRegisters, addresses.clone(), Backend(3)),
+                Err(OverlappingAddresses {
+                    table: Table::HoldingRegisters,
+                    addresses,
+                    mounted,
+                })
+            );
+        }
+        space
+            .mount(Table::HoldingRegisters, 20..=29, Backend(3))
+            .unwrap();
+        space
+            .mount(Table::InputRegisters, 10..=19, Backend(4))
+            .unwrap();
+
+        assert_eq!(
+            space.mounted().collect::<Vec<_>>(),
+            [
+                (Table::InputRegisters, 10..=19),
+                (Table::HoldingRegisters, 10..=19),
+                (Table::HoldingRegisters, 20..=29),
+                (Table::HoldingRegisters, 30..=39),
+            ]
+        );
+    }
+
+    #[test]
+    fn dispatch_requests_by_address() {
+        let space = AddressSpace::new()
+            .with_mount(Table::HoldingRegisters, 10..=19, Backend(1))
+            .unwrap()
+            .with_mount(Table::HoldingRegisters, 20..=29, Backend(2))
+            .unwrap()
+            .with_mount(Table::Coils, 0..=0, Backend(3))
+            .unwrap();
+
+        assert_eq!(
+            call(&space, Request::ReadHoldingRegisters(10, 10)),
+            Ok(Response::ReadHoldingRegisters(vec![1; 10]))
+        );
+        assert_eq!(
+            call(&space, Request::ReadHoldingRegisters(25, 2)),
+            Ok(Response::ReadHoldingRegisters(vec![2; 2]))
+        );
+        assert_eq!(
+            call(&space, Request::WriteSingleCoil(0, true)),
+            Ok(Response::WriteSingleCoil(0, true))
+        );
+        assert_eq!(
+            call(
+                &space,
+                Request::ReadWriteMultipleRegisters(20, 1, 29, Cow::Borrowed(&[0]))
+            ),
+            Ok(Response::ReadWriteMultipleRegisters(vec![2]))
+        );
+
+        // Gaps
+        assert_eq!(
+            call(&space, Request::ReadHoldingRegisters(9, 1)),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            call(&space, Request::ReadInputRegisters(10, 1)),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            call(&space, Request::ReadHoldingRegisters(Address::MAX, 2)),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+
+        // Spanning multiple services
+        assert_eq!(
+            call(&space, Request::ReadHoldingRegisters(19, 2)),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            call(
+                &space,
+                Request::ReadWriteMultipleRegisters(10, 1, 20, Cow::Borrowed(&[0]))
+            ),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+
+        assert_eq!(
+            call(&space, Request::ReportServerId),
+            Err(ExceptionCode::IllegalFunction)
+        );
+    }
+
+    #[cfg(feature = "tcp-server")]
+    #[tokio::test]
+    async fn serve_serial_line_functions_over_tcp() {
+        struct ServerIdService;
+
+        impl Service for ServerIdService {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+            type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+            fn call(&self, _: Self::Request) -> Self::Future {
+                future::ready(Ok(Response::ReportServerId(1, true, vec![])))
+            }
+
+            fn serve_serial_line_functions_over_tcp(&self) -> bool {
+                true
+            }
+        }
+
+        let space = AddressSpace::new().with_fallback(ServerIdService);
+        let result = crate::server::tcp::call_over_tcp(space, Request::ReportServerId).await;
+        assert_eq!(
+            result.unwrap(),
+            Ok(Response::ReportServerId(1, true, vec![]))
+        );
+    }
+}
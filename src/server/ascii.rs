@@ -0,0 +1,122 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Modbus ASCII server skeleton
+
+use std::{future::Future, io, path::Path};
+
+use futures_util::FutureExt as _;
+use tokio_serial::{DataBits, Parity, SerialStream, StopBits};
+use tokio_util::codec::Framed;
+
+use crate::{codec::ascii::ServerCodec, frame::rtu::RequestAdu};
+
+use super::{rtu::process, Terminated};
+
+#[derive(Debug)]
+pub struct Server {
+    serial: SerialStream,
+}
+
+impl Server {
+    /// set up a new [`Server`] instance from an interface path and baud rate
+    ///
+    /// The serial line is configured with 7 data bits, even parity, and
+    /// 1 stop bit as required by the specification.
+    pub fn new_from_path<P: AsRef<Path>>(p: P, baud_rate: u32) -> io::Result<Self> {
+        let serial = SerialStream::open(
+            &tokio_serial::new(p.as_ref().to_string_lossy(), baud_rate)
+                .data_bits(DataBits::Seven)
+                .parity(Parity::Even)
+                .stop_bits(StopBits::One),
+        )?;
+        Ok(Server { serial })
+    }
+
+    /// set up a new [`Server`] instance based on a pre-configured [`SerialStream`] instance
+    #[must_use]
+    pub fn new(serial: SerialStream) -> Self {
+        Server { serial }
+    }
+
+    /// Process Modbus ASCII requests.
+    pub async fn serve_forever<S>(self, service: S) -> io::Result<()>
+    where
+        S: super::AsyncService + Send + Sync + 'static,
+        S::Request: From<RequestAdu<'static>> + Send,
+    {
+        let framed = Framed::new(self.serial, ServerCodec::default());
+        process(framed, &service, None).await
+    }
+
+    /// Process Modbus ASCII requests until finished or aborted.
+    ///
+    /// Warning: Request processing is not scoped and could be aborted at any internal await point!
+    /// See also: <https://rust-lang.github.io/wg-async/vision/roadmap/scopes.html#cancellation>
+    pub async fn serve_until<S, X>(self, service: S, abort_signal: X) -> io::Result<Terminated>
+    where
+        S: super::AsyncService + Send + Sync + 'static,
+        S::Request: From<RequestAdu<'static>> + Send,
+        X: Future<Output = ()> + Sync + Send + Unpin + 'static,
+    {
+        let framed = Framed::new(self.serial, ServerCodec::default());
+        let abort_signal = abort_signal.fuse();
+        tokio::select! {
+            res = process(framed, &service, None) => {
+                res.map(|()| Terminated::Finished)
+            },
+            () = abort_signal => {
+                Ok(Terminated::Aborted)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    use crate::{server::Service, ExceptionCode, Request, Response, SlaveRequest};
+
+    use super::*;
+
+    struct Echo;
+
+    impl Service for Echo {
+        type Request = SlaveRequest<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Response, ExceptionCode>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            future::ready(match req.request {
+                Request::WriteSingleRegister(addr, word) => {
+                    Ok(Response::WriteSingleRegister(addr, word))
+                }
+                _ => Err(ExceptionCode::IllegalFunction),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn process_requests() {
+        let (transport, mut client) = tokio::io::duplex(1024);
+        let server = tokio::spawn(async move {
+            process(Framed::new(transport, ServerCodec::default()), &Echo, None).await
+        });
+
+        // The broadcast request is not answered.
+        client
+            .write_all(b":00060001000CED\r\n:010100000001FD\r\n")
+            .await
+            .unwrap();
+        let mut buf = [0; 11];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b":0181017D\r\n");
+
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use futures_util::future::{self, Either, FutureExt as _, Map};
+
+use crate::{ExceptionCode, FunctionCode, Response, Slave, SlaveId, SlaveRequest};
+
+use super::{filter::MapResult, Service};
+
+/// The object identifier of the X.509v3 certificate extension that
+/// contains the role of a client, as defined by the Modbus/TCP Security
+/// specification.
+pub const ROLE_OID: &str = "1.3.6.1.4.1.50316.802.1";
+
+/// The functions and units that a role is permitted to access.
+///
+/// Nothing is permitted unless allowed explicitly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permissions {
+    /// The permitted function codes or `None` for all functions.
+    functions: Option<BTreeSet<u8>>,
+    /// The permitted units or `None` for all units.
+    units: Option<BTreeSet<SlaveId>>,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Permissions {
+    /// No permissions.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            functions: Some(BTreeSet::new()),
+            units: Some(BTreeSet::new()),
+        }
+    }
+
+    /// Permit all functions and units.
+    #[must_use]
+    pub const fn all() -> Self {
+        Self {
+            functions: None,
+            units: None,
+        }
+    }
+
+    /// Permit the given functions in addition.
+    #[must_use]
+    pub fn allow_functions(mut self, functions: impl IntoIterator<Item = FunctionCode>) -> Self {
+        if let Some(permitted) = &mut self.functions {
+            permitted.extend(functions.into_iter().map(FunctionCode::value));
+        }
+        self
+    }
+
+    /// Permit all functions.
+    #[must_use]
+    pub fn allow_all_functions(mut self) -> Self {
+        self.functions = None;
+        self
+    }
+
+    /// Permit the given units in addition.
+    ///
+    /// Broadcast requests are only permitted if [`Slave::broadcast()`]
+    /// is included.
+    #[must_use]
+    pub fn allow_units(mut self, units: impl IntoIterator<Item = Slave>) -> Self {
+        if let Some(permitted) = &mut self.units {
+            permitted.extend(units.into_iter().map(SlaveId::from));
+        }
+        self
+    }
+
+    /// Permit all units.
+    #[must_use]
+    pub fn allow_all_units(mut self) -> Self {
+        self.units = None;
+        self
+    }
+
+    /// Checks if the function is permitted for the unit.
+    #[must_use]
+    pub fn permits(&self, unit: Slave, function: FunctionCode) -> bool {
+        let function_permitted = self
+            .functions
+            .as_ref()
+            .map_or(true, |functions| functions.contains(&function.value()));
+        let unit_permitted = self
+            .units
+            .as_ref()
+            .map_or(true, |units| units.contains(&unit.into()));
+        function_permitted && unit_permitted
+    }
+}
+
+/// Role-based authorization of requests.
+///
+/// Implements the authorization model of the Modbus/TCP Security
+/// specification: The server maps the role of an authenticated client
+/// onto the functions and units that the client is permitted to access.
+/// Clients without a known role are not permitted to access anything.
+///
+/// The role is transmitted in the certificate extension [`ROLE_OID`]
+/// of the client certificate. The TLS server of the `"tls"` feature
+/// extracts it after the handshake, other TLS stacks could extract it
+/// in the `on_connected` callback of the TCP server, see
+/// [`Self::authorize()`].
+///
+/// The rules are shared by all clones.
+#[derive(Debug, Clone, Default)]
+pub struct Authorization {
+    roles: Arc<HashMap<String, Permissions>>,
+}
+
+impl Authorization {
+    /// Authorization without any roles.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Grant permissions to a role.
+    ///
+    /// Replaces the previous permissions of the role.
+    #[must_use]
+    pub fn with_role(mut self, role: impl Into<String>, permissions: Permissions) -> Self {
+        Arc::make_mut(&mut self.roles).insert(role.into(), permissions);
+        self
+    }
+
+    /// The permissions of a role.
+    #[must_use]
+    pub fn permissions(&self, role: &str) -> Option<&Permissions> {
+        self.roles.get(role)
+    }
+
+    /// Enforce the permissions of a client for the service.
+    ///
+    /// Use a separate service for each connection with the role of
+    /// the connected client, if any.
+    #[must_use]
+    pub fn authorize<S>(&self, role: Option<&str>, service: S) -> Authorized<S> {
+        let permissions = role.and_then(|role| self.permissions(role)).cloned();
+        if permissions.is_none() {
+            log::debug!("No permissions for role {role:?}");
+        }
+        Authorized {
+            service,
+            permissions: permissions.unwrap_or_default(),
+        }
+    }
+}
+
+/// A service that only receives requests which the client is permitted
+/// to issue, see [`Authorization`].
+///
+/// Requests that are not permitted are answered with
+/// [`ExceptionCode::IllegalFunction`] as required by the Modbus/TCP
+/// Security specification.
+#[derive(Debug, Clone)]
+pub struct Authorized<S> {
+    service: S,
+    permissions: Permissions,
+}
+
+impl<S> Authorized<S> {
+    /// The permissions of the client.
+    #[must_use]
+    pub const fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    /// The wrapped service.
+    #[must_use]
+    pub const fn service(&self) -> &S {
+        &self.service
+    }
+
+    /// Unwrap the wrapped service.
+    #[must_use]
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+impl<S> Service for Authorized<S>
+where
+    S: Service<Request = SlaveRequest<'static>>,
+{
+    type Request = SlaveRequest<'static>;
+    type Response = Option<Response>;
+    type Exception = ExceptionCode;
+    type Future = Either<
+        Map<S::Future, MapResult<S>>,
+        future::Ready<Result<Option<Response>, ExceptionCode>>,
+    >;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let function = req.request.function_code();
+        if !self.permissions.permits(Slave(req.slave), function) {
+            log::debug!(
+                "Rejecting unauthorized request for slave {slave} (function = {function})",
+                slave = req.slave
+            );
+            return Either::Right(future::ready(Err(ExceptionCode::IllegalFunction)));
+        }
+        let map_result: MapResult<S> = |res| res.map(Into::into).map_err(Into::into);
+        Either::Left(self.service.call(req).map(map_result))
+    }
+
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        self.service.serve_serial_line_functions_over_tcp()
+    }
+
+    fn supported_functions(&self) -> Option<&[FunctionCode]> {
+        self.service.supported_functions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+
+    use crate::Request;
+
+    use super::*;
+
+    struct EchoService;
+
+    impl Service for EchoService {
+        type Request = SlaveRequest<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            future::ready(match req.request {
+                Request::WriteSingleRegister(addr, value) => {
+                    Ok(Response::WriteSingleRegister(addr, value))
+                }
+                _ => Ok(Response::ReadHoldingRegisters(vec![0])),
+            })
+        }
+    }
+
+    fn request(slave: SlaveId, request: Request<'static>) -> SlaveRequest<'static> {
+        SlaveRequest { slave, request }
+    }
+
+    #[tokio::test]
+    async fn enforce_permissions_of_roles() {
+        let authorization = Authorization::new()
+            .with_role(
+                "operator",
+                Permissions::new()
+                    .allow_functions([FunctionCode::WriteSingleRegister])
+                    .allow_units([Slave(1)]),
+            )
+            .with_role("engineer", Permissions::all());
+
+        let operator = authorization.authorize(Some("operator"), EchoService);
+        assert_eq!(
+            operator
+                .call(request(1, Request::WriteSingleRegister(0x10, 1)))
+                .await,
+            Ok(Some(Response::WriteSingleRegister(0x10, 1)))
+        );
+        assert_eq!(
+            operator
+                .call(request(2, Request::WriteSingleRegister(0x10, 1)))
+                .await,
+            Err(ExceptionCode::IllegalFunction)
+        );
+        assert_eq!(
+            operator
+                .call(request(1, Request::ReadHoldingRegisters(0x10, 1)))
+                .await,
+            Err(ExceptionCode::IllegalFunction)
+        );
+
+        let engineer = authorization.authorize(Some("engineer"), EchoService);
+        assert_eq!(
+            engineer
+                .call(request(0, Request::ReadHoldingRegisters(0x10, 1)))
+                .await,
+            Ok(Some(Response::ReadHoldingRegisters(vec![0])))
+        );
+
+        for role in [None, Some("guest")] {
+            let service = authorization.authorize(role, EchoService);
+            assert_eq!(service.permissions(), &Permissions::new());
+            assert_eq!(
+                service
+                    .call(request(1, Request::WriteSingleRegister(0x10, 1)))
+                    .await,
+                Err(ExceptionCode::IllegalFunction)
+            );
+        }
+    }
+}
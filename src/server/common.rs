@@ -0,0 +1,154 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Request processing shared by all servers
+
+use std::fmt;
+
+use crate::{
+    frame::{ExceptionResponse, OptionalResponsePdu, ResponsePdu},
+    ExceptionCode, FunctionCode, Response,
+};
+
+use super::{service::supports_function, AsyncService};
+
+/// Process a request and map the result of the service into the response PDU.
+///
+/// Exceptions of the service are converted into exception responses
+/// of the requested function. Returns `None` if the service decided to
+/// not respond or if the request is not supposed to be answered, e.g.
+/// a broadcast request.
+///
+/// Requests for functions that the service doesn't support are answered
+/// with [`ExceptionCode::IllegalFunction`] without invoking the service.
+pub(super) async fn respond<S>(
+    service: &S,
+    request: S::Request,
+    function: FunctionCode,
+    expects_response: bool,
+    hdr: impl fmt::Debug,
+) -> Option<ResponsePdu>
+where
+    S: AsyncService,
+{
+    #[cfg(feature = "tracing")]
+    let (span, started) = (
+        crate::spans::server_request(function, &hdr),
+        std::time::Instant::now(),
+    );
+    let result: Result<Option<Response>, ExceptionCode> = if supports_function(service, function) {
+        let call = service.handle(request);
+        #[cfg(feature = "tracing")]
+        let call = tracing::Instrument::instrument(call, span.clone());
+        call.await.map(Into::into).map_err(Into::into)
+    } else {
+        log::debug!("Rejecting unsupported function for request {hdr:?} (function = {function})");
+        Err(ExceptionCode::IllegalFunction)
+    };
+    #[cfg(feature = "tracing")]
+    crate::spans::record_request_outcome(&span, &result, started.elapsed());
+    let result = result.map_err(|exception| ExceptionResponse {
+        function,
+        exception,
+    });
+    let OptionalResponsePdu(Some(response_pdu)) = result.into() else {
+        log::trace!("No response for request {hdr:?} (function = {function})");
+        return None;
+    };
+    if !expects_response {
+        log::trace!("Discarding response for request {hdr:?} (function = {function})");
+        return None;
+    }
+    Some(response_pdu)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+
+    use crate::{server::Service, Request};
+
+    use super::*;
+
+    struct DummyService;
+
+    impl Service for DummyService {
+        type Request = Request<'static>;
+        type Response = Option<Response>;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            future::ready(match req {
+                Request::ReadCoils(_, _) => Ok(Some(Response::ReadCoils(vec![true]))),
+                Request::WriteSingleCoil(_, _) => Ok(None),
+                _ => Err(ExceptionCode::IllegalFunction),
+            })
+        }
+    }
+
+    async fn respond_to(request: Request<'static>, expects_response: bool) -> Option<ResponsePdu> {
+        let function = request.function_code();
+        respond(&DummyService, request, function, expects_response, ()).await
+    }
+
+    #[tokio::test]
+    async fn map_service_results() {
+        let response = respond_to(Request::ReadCoils(0, 1), true).await;
+        assert_eq!(response.unwrap().0, Ok(Response::ReadCoils(vec![true])));
+
+        let response = respond_to(Request::ReadHoldingRegisters(0, 1), true).await;
+        assert_eq!(
+            response.unwrap().0,
+            Err(ExceptionResponse {
+                function: FunctionCode::ReadHoldingRegisters,
+                exception: ExceptionCode::IllegalFunction,
+            })
+        );
+
+        assert!(respond_to(Request::WriteSingleCoil(0, true), true)
+            .await
+            .is_none());
+        assert!(respond_to(Request::ReadCoils(0, 1), false).await.is_none());
+    }
+
+    struct CoilReader;
+
+    impl Service for CoilReader {
+        type Request = Request<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            assert!(matches!(req, Request::ReadCoils(_, _)));
+            future::ready(Ok(Response::ReadCoils(vec![true])))
+        }
+
+        fn supported_functions(&self) -> Option<&[FunctionCode]> {
+            Some(&[FunctionCode::ReadCoils])
+        }
+    }
+
+    #[tokio::test]
+    async fn reject_unsupported_functions() {
+        let request = Request::ReadCoils(0, 1);
+        let response = respond(&CoilReader, request, FunctionCode::ReadCoils, true, ()).await;
+        assert_eq!(response.unwrap().0, Ok(Response::ReadCoils(vec![true])));
+
+        let request = Request::WriteSingleCoil(0, true);
+        let function = FunctionCode::WriteSingleCoil;
+        let response = respond(&CoilReader, request.clone(), function, true, ()).await;
+        assert_eq!(
+            response.unwrap().0,
+            Err(ExceptionResponse {
+                function,
+                exception: ExceptionCode::IllegalFunction,
+            })
+        );
+        // Broadcasts are not answered.
+        assert!(respond(&CoilReader, request, function, false, ())
+            .await
+            .is_none());
+    }
+}
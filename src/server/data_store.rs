@@ -0,0 +1,886 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    borrow::Cow,
+    ops::{Range, RangeInclusive},
+    sync::{Arc, Mutex},
+};
+
+use futures_util::future;
+use tokio::sync::broadcast;
+
+use crate::{
+    codec::u16_len, mutex::lock, Address, ExceptionCode, FunctionCode, Quantity, Request, Response,
+};
+
+use super::{Service, Table};
+
+/// The maximum number of coils or discrete inputs that could be read.
+const MAX_READ_BITS: usize = 2000;
+
+/// The maximum number of registers that could be read.
+const MAX_READ_WORDS: usize = 125;
+
+/// The maximum number of coils that could be written.
+const MAX_WRITE_BITS: usize = 1968;
+
+/// The maximum number of registers that could be written.
+const MAX_WRITE_WORDS: usize = 123;
+
+/// The maximum number of registers that could be written
+/// while reading registers.
+const MAX_READ_WRITE_WORDS: usize = 121;
+
+const SUPPORTED_FUNCTIONS: &[FunctionCode] = &[
+    FunctionCode::ReadCoils,
+    FunctionCode::ReadDiscreteInputs,
+    FunctionCode::WriteSingleCoil,
+    FunctionCode::WriteMultipleCoils,
+    FunctionCode::ReadInputRegisters,
+    FunctionCode::ReadHoldingRegisters,
+    FunctionCode::WriteSingleRegister,
+    FunctionCode::WriteMultipleRegisters,
+    FunctionCode::MaskWriteRegister,
+    FunctionCode::ReadWriteMultipleRegisters,
+];
+
+/// The number of changes that are buffered for each subscriber.
+const CHANGES_CAPACITY: usize = 1024;
+
+/// Values of a [`DataStore`] that have been modified by a request.
+///
+/// The variants depend on the [`ChangeGranularity`] of the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataChange {
+    /// A coil has been switched.
+    Coil {
+        /// The address of the coil.
+        addr: Address,
+        /// The previous value.
+        old: bool,
+        /// The current value.
+        new: bool,
+    },
+
+    /// A holding register has been modified.
+    HoldingRegister {
+        /// The address of the register.
+        addr: Address,
+        /// The previous value.
+        old: u16,
+        /// The current value.
+        new: u16,
+    },
+
+    /// Coils have been written by a single request.
+    Coils {
+        /// The address of the first coil.
+        addr: Address,
+        /// The previous values.
+        old: Vec<bool>,
+        /// The current values.
+        new: Vec<bool>,
+    },
+
+    /// Holding registers have been written by a single request.
+    HoldingRegisters {
+        /// The address of the first register.
+        addr: Address,
+        /// The previous values.
+        old: Vec<u16>,
+        /// The current values.
+        new: Vec<u16>,
+    },
+}
+
+/// How the changes of a [`DataStore`] are reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChangeGranularity {
+    /// Report each modified value separately, i.e. as
+    /// [`DataChange::Coil`] or [`DataChange::HoldingRegister`].
+    ///
+    /// This is the default.
+    #[default]
+    Value,
+
+    /// Report all values that have been written by a request at once,
+    /// i.e. as [`DataChange::Coils`] or [`DataChange::HoldingRegisters`].
+    ///
+    /// Covers the whole range of the request, including values that
+    /// have not been modified. Requests that don't modify any value
+    /// are not reported.
+    Request,
+}
+
+/// Restricts the access of requests to the values of a [`DataStore`].
+///
+/// Used for emulating the quirks of devices, see
+/// [`testing::Memory`](crate::testing::Memory).
+pub(crate) trait AccessPolicy {
+    /// Checks if a request is allowed to read or write the values at
+    /// the given addresses.
+    ///
+    /// Returns the addresses that are gap-filled, i.e. read as zero
+    /// and not modified by writing.
+    fn check(
+        &self,
+        table: Table,
+        addresses: Range<usize>,
+        write: bool,
+    ) -> Result<Vec<Range<usize>>, ExceptionCode>;
+}
+
+/// All values are accessible.
+impl AccessPolicy for () {
+    fn check(
+        &self,
+        _: Table,
+        _: Range<usize>,
+        _: bool,
+    ) -> Result<Vec<Range<usize>>, ExceptionCode> {
+        Ok(Vec::new())
+    }
+}
+
+/// A contiguous block of addresses.
+#[derive(Debug, Clone, Default)]
+struct Block<T> {
+    first: Address,
+    values: Vec<T>,
+}
+
+impl<T: Copy + Default> Block<T> {
+    fn new(addresses: RangeInclusive<Address>) -> Self {
+        let (first, last) = addresses.into_inner();
+        let len = (usize::from(last) + 1).saturating_sub(usize::from(first));
+        Self {
+            first,
+            values: vec![T::default(); len],
+        }
+    }
+
+    fn range(&self, addr: Address, cnt: usize) -> Result<Range<usize>, ExceptionCode> {
+        let start = usize::from(addr)
+            .checked_sub(usize::from(self.first))
+            .ok_or(ExceptionCode::IllegalDataAddress)?;
+        let end = start + cnt;
+        if end > self.values.len() {
+            return Err(ExceptionCode::IllegalDataAddress);
+        }
+        Ok(start..end)
+    }
+
+    fn read(&self, addr: Address, cnt: usize) -> Result<Vec<T>, ExceptionCode> {
+        let range = self.range(addr, cnt)?;
+        Ok(self.values[range].to_vec())
+    }
+
+    /// Reads the values that are accessible by a request.
+    fn read_by(
+        &self,
+        table: Table,
+        addr: Address,
+        cnt: usize,
+        policy: &impl AccessPolicy,
+    ) -> Result<Vec<T>, ExceptionCode> {
+        let mut values = self.read(addr, cnt)?;
+        let start = usize::from(addr);
+        for filled in policy.check(table, start..start + cnt, false)? {
+            values[filled.start - start..filled.end - start].fill(T::default());
+        }
+        Ok(values)
+    }
+
+    /// The values that are written by a request.
+    ///
+    /// Gap-filled values are replaced by the current values, i.e.
+    /// they are not modified.
+    fn written_by<'a>(
+        &self,
+        table: Table,
+        addr: Address,
+        values: &'a [T],
+        policy: &impl AccessPolicy,
+    ) -> Result<Cow<'a, [T]>, ExceptionCode> {
+        let range = self.range(addr, values.len())?;
+        let start = usize::from(addr);
+        let filled = policy.check(table, start..start + values.len(), true)?;
+        if filled.is_empty() {
+            return Ok(Cow::Borrowed(values));
+        }
+        let mut values = values.to_vec();
+        for filled in filled {
+            let filled = filled.start - start..filled.end - start;
+            values[filled.clone()].copy_from_slice(
+                &self.values[range.start + filled.start..range.start + filled.end],
+            );
+        }
+        Ok(Cow::Owned(values))
+    }
+
+    fn write(&mut self, addr: Address, values: &[T]) -> Result<(), ExceptionCode> {
+        let range = self.range(addr, values.len())?;
+        self.values[range].copy_from_slice(values);
+        Ok(())
+    }
+
+    /// Writes the values and reports each modified value.
+    fn update(
+        &mut self,
+        addr: Address,
+        values: &[T],
+        mut changed: impl FnMut(Address, T, T),
+    ) -> Result<(), ExceptionCode>
+    where
+        T: PartialEq,
+    {
+        let range = self.range(addr, values.len())?;
+        let mut addr = addr;
+        for (value, &new) in self.values[range].iter_mut().zip(values) {
+            let old = std::mem::replace(value, new);
+            if old != new {
+                changed(addr, old, new);
+            }
+            addr = addr.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    /// Writes the values and returns the previous values if any value
+    /// has been modified.
+    fn replace(&mut self, addr: Address, values: &[T]) -> Result<Option<Vec<T>>, ExceptionCode>
+    where
+        T: PartialEq,
+    {
+        let range = self.range(addr, values.len())?;
+        let current = &mut self.values[range];
+        if current == values {
+            return Ok(None);
+        }
+        let old = current.to_vec();
+        current.copy_from_slice(values);
+        Ok(Some(old))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tables {
+    coils: Block<bool>,
+    discrete_inputs: Block<bool>,
+    input_registers: Block<u16>,
+    holding_registers: Block<u16>,
+    changes: Option<broadcast::Sender<DataChange>>,
+    granularity: ChangeGranularity,
+}
+
+impl Tables {
+    fn write_coils(
+        &mut self,
+        addr: Address,
+        coils: &[bool],
+        policy: &impl AccessPolicy,
+    ) -> Result<(), ExceptionCode> {
+        let coils = &*self.coils.written_by(Table::Coils, addr, coils, policy)?;
+        let changes = self.changes.as_ref();
+        match self.granularity {
+            ChangeGranularity::Value => self.coils.update(addr, coils, |addr, old, new| {
+                notify(changes, DataChange::Coil { addr, old, new });
+            }),
+            ChangeGranularity::Request => {
+                if let Some(old) = self.coils.replace(addr, coils)? {
+                    let new = coils.to_vec();
+                    notify(changes, DataChange::Coils { addr, old, new });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_holding_registers(
+        &mut self,
+        addr: Address,
+        words: &[u16],
+        policy: &impl AccessPolicy,
+    ) -> Result<(), ExceptionCode> {
+        let words =
+            &*self
+                .holding_registers
+                .written_by(Table::HoldingRegisters, addr, words, policy)?;
+        let changes = self.changes.as_ref();
+        match self.granularity {
+            ChangeGranularity::Value => {
+                self.holding_registers
+                    .update(addr, words, |addr, old, new| {
+                        notify(changes, DataChange::HoldingRegister { addr, old, new });
+                    })
+            }
+            ChangeGranularity::Request => {
+                if let Some(old) = self.holding_registers.replace(addr, words)? {
+                    let new = words.to_vec();
+                    notify(changes, DataChange::HoldingRegisters { addr, old, new });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn process(
+        &mut self,
+        request: Request<'_>,
+        policy: &impl AccessPolicy,
+    ) -> Result<Response, ExceptionCode> {
+        use Request::*;
+
+        let response = match request {
+            ReadCoils(addr, cnt) => {
+                let cnt = quantity(cnt, MAX_READ_BITS)?;
+                Response::ReadCoils(self.coils.read_by(Table::Coils, addr, cnt, policy)?)
+            }
+            ReadDiscreteInputs(addr, cnt) => {
+                let cnt = quantity(cnt, MAX_READ_BITS)?;
+                Response::ReadDiscreteInputs(self.discrete_inputs.read_by(
+                    Table::DiscreteInputs,
+                    addr,
+                    cnt,
+                    policy,
+                )?)
+            }
+            ReadInputRegisters(addr, cnt) => {
+                let cnt = quantity(cnt, MAX_READ_WORDS)?;
+                Response::ReadInputRegisters(self.input_registers.read_by(
+                    Table::InputRegisters,
+                    addr,
+                    cnt,
+                    policy,
+                )?)
+            }
+            ReadHoldingRegisters(addr, cnt) => {
+                let cnt = quantity(cnt, MAX_READ_WORDS)?;
+                Response::ReadHoldingRegisters(self.holding_registers.read_by(
+                    Table::HoldingRegisters,
+                    addr,
+                    cnt,
+                    policy,
+                )?)
+            }
+            WriteSingleCoil(addr, coil) => {
+                self.write_coils(addr, &[coil], policy)?;
+                Response::WriteSingleCoil(addr, coil)
+            }
+            WriteMultipleCoils(addr, coils) => {
+                check_len(coils.len(), MAX_WRITE_BITS)?;
+                self.write_coils(addr, &coils, policy)?;
+                Response::WriteMultipleCoils(addr, u16_len(coils.len()))
+            }
+            WriteSingleRegister(addr, word) => {
+                self.write_holding_registers(addr, &[word], policy)?;
+                Response::WriteSingleRegister(addr, word)
+            }
+            WriteMultipleRegisters(addr, words) => {
+                check_len(words.len(), MAX_WRITE_WORDS)?;
+                self.write_holding_registers(addr, &words, policy)?;
+                Response::WriteMultipleRegisters(addr, u16_len(words.len()))
+            }
+            MaskWriteRegister(addr, and_mask, or_mask) => {
+                let word =
+                    self.holding_registers
+                        .read_by(Table::HoldingRegisters, addr, 1, policy)?[0];
+                let word = (word & and_mask) | (or_mask & !and_mask);
+                self.write_holding_registers(addr, &[word], policy)?;
+                Response::MaskWriteRegister(addr, and_mask, or_mask)
+            }
+            ReadWriteMultipleRegisters(read_addr, cnt, write_addr, words) => {
+                let cnt = quantity(cnt, MAX_READ_WORDS)?;
+                check_len(words.len(), MAX_READ_WRITE_WORDS)?;
+                // Both ranges are validated before writing.
+                self.holding_registers
+                    .read_by(Table::HoldingRegisters, read_addr, cnt, policy)?;
+                self.write_holding_registers(write_addr, &words, policy)?;
+                Response::ReadWriteMultipleRegisters(self.holding_registers.read_by(
+                    Table::HoldingRegisters,
+                    read_addr,
+                    cnt,
+                    policy,
+                )?)
+            }
+            _ => return Err(ExceptionCode::IllegalFunction),
+        };
+        Ok(response)
+    }
+}
+
+fn notify(changes: Option<&broadcast::Sender<DataChange>>, change: DataChange) {
+    if let Some(changes) = changes {
+        // Changes are discarded if nobody is subscribed.
+        changes.send(change).ok();
+    }
+}
+
+/// Validates the quantity of a request.
+fn quantity(cnt: Quantity, max: usize) -> Result<usize, ExceptionCode> {
+    let cnt = usize::from(cnt);
+    check_len(cnt, max)?;
+    Ok(cnt)
+}
+
+fn check_len(len: usize, max: usize) -> Result<(), ExceptionCode> {
+    if len == 0 || len > max {
+        return Err(ExceptionCode::IllegalDataValue);
+    }
+    Ok(())
+}
+
+/// An in-memory register bank that serves the standard data access
+/// functions.
+///
+/// Each of the four tables covers a single, configurable address
+/// range. Tables are empty unless configured. All standard read and
+/// write requests are answered, including [`Request::MaskWriteRegister`]
+/// and [`Request::ReadWriteMultipleRegisters`]:
+///
+/// - Quantities that exceed the limits of the specification are
+///   answered with [`ExceptionCode::IllegalDataValue`].
+/// - Requests that access addresses outside of the configured ranges
+///   are answered with [`ExceptionCode::IllegalDataAddress`].
+/// - All other requests are answered with [`ExceptionCode::IllegalFunction`].
+///
+/// The values are shared by all clones, i.e. the application could
+/// update the values of a store that is served concurrently. Changes
+/// of coils and holding registers by clients could be observed with
+/// [`Self::subscribe()`].
+///
+/// Serves requests for any slave. Use [`SlaveFilter`](super::SlaveFilter)
+/// or [`MultiUnitService`](super::MultiUnitService) for selecting slaves.
+///
+/// ```
+/// use tokio_modbus::{prelude::*, server::{DataStore, Service as _}};
+///
+/// let store = DataStore::new()
+///     .with_coils(0..=15)
+///     .with_holding_registers(100..=199);
+/// store.set_holding_registers(100, &[1, 2, 3]).unwrap();
+///
+/// let response = store.call(Request::ReadHoldingRegisters(101, 2)).into_inner();
+/// assert_eq!(response, Ok(Response::ReadHoldingRegisters(vec![2, 3])));
+///
+/// let response = store.call(Request::ReadCoils(15, 2)).into_inner();
+/// assert_eq!(response, Err(ExceptionCode::IllegalDataAddress));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DataStore {
+    shared: Arc<Mutex<Tables>>,
+}
+
+impl DataStore {
+    /// A store without any addresses.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Provide coils within the given addresses.
+    ///
+    /// All coils are initially off.
+    #[must_use]
+    pub fn with_coils(self, addresses: RangeInclusive<Address>) -> Self {
+        lock(&self.shared).coils = Block::new(addresses);
+        self
+    }
+
+    /// Provide discrete inputs within the given addresses.
+    ///
+    /// All discrete inputs are initially off.
+    #[must_use]
+    pub fn with_discrete_inputs(self, addresses: RangeInclusive<Address>) -> Self {
+        lock(&self.shared).discrete_inputs = Block::new(addresses);
+        self
+    }
+
+    /// Provide input registers within the given addresses.
+    ///
+    /// All input registers are initially zero.
+    #[must_use]
+    pub fn with_input_registers(self, addresses: RangeInclusive<Address>) -> Self {
+        lock(&self.shared).input_registers = Block::new(addresses);
+        self
+    }
+
+    /// Provide holding registers within the given addresses.
+    ///
+    /// All holding registers are initially zero.
+    #[must_use]
+    pub fn with_holding_registers(self, addresses: RangeInclusive<Address>) -> Self {
+        lock(&self.shared).holding_registers = Block::new(addresses);
+        self
+    }
+
+    /// Configure how changes are reported to subscribers.
+    ///
+    /// Defaults to [`ChangeGranularity::Value`]. Use
+    /// [`ChangeGranularity::Request`] for receiving a single change
+    /// for each request, e.g. for forwarding block writes of
+    /// [`Request::WriteMultipleRegisters`] at once.
+    #[must_use]
+    pub fn with_change_granularity(self, granularity: ChangeGranularity) -> Self {
+        lock(&self.shared).granularity = granularity;
+        self
+    }
+
+    /// Read coils.
+    ///
+    /// Fails with [`ExceptionCode::IllegalDataAddress`] if any address
+    /// is not provided.
+    pub fn coils(&self, addr: Address, cnt: Quantity) -> Result<Vec<bool>, ExceptionCode> {
+        lock(&self.shared).coils.read(addr, cnt.into())
+    }
+
+    /// Write coils.
+    ///
+    /// Fails with [`ExceptionCode::IllegalDataAddress`] if any address
+    /// is not provided.
+    pub fn set_coils(&self, addr: Address, coils: &[bool]) -> Result<(), ExceptionCode> {
+        lock(&self.shared).coils.write(addr, coils)
+    }
+
+    /// Read discrete inputs.
+    ///
+    /// Fails with [`ExceptionCode::IllegalDataAddress`] if any address
+    /// is not provided.
+    pub fn discrete_inputs(
+        &self,
+        addr: Address,
+        cnt: Quantity,
+    ) -> Result<Vec<bool>, ExceptionCode> {
+        lock(&self.shared).discrete_inputs.read(addr, cnt.into())
+    }
+
+    /// Write discrete inputs.
+    ///
+    /// Fails with [`ExceptionCode::IllegalDataAddress`] if any address
+    /// is not provided.
+    pub fn set_discrete_inputs(&self, addr: Address, inputs: &[bool]) -> Result<(), ExceptionCode> {
+        lock(&self.shared).discrete_inputs.write(addr, inputs)
+    }
+
+    /// Read input registers.
+    ///
+    /// Fails with [`ExceptionCode::IllegalDataAddress`] if any address
+    /// is not provided.
+    pub fn input_registers(&self, addr: Address, cnt: Quantity) -> Result<Vec<u16>, ExceptionCode> {
+        lock(&self.shared).input_registers.read(addr, cnt.into())
+    }
+
+    /// Write input registers.
+    ///
+    /// Fails with [`ExceptionCode::IllegalDataAddress`] if any address
+    /// is not provided.
+    pub fn set_input_registers(&self, addr: Address, words: &[u16]) -> Result<(), ExceptionCode> {
+        lock(&self.shared).input_registers.write(addr, words)
+    }
+
+    /// Read holding registers.
+    ///
+    /// Fails with [`ExceptionCode::IllegalDataAddress`] if any address
+    /// is not provided.
+    pub fn holding_registers(
+        &self,
+        addr: Address,
+        cnt: Quantity,
+    ) -> Result<Vec<u16>, ExceptionCode> {
+        lock(&self.shared).holding_registers.read(addr, cnt.into())
+    }
+
+    /// Write holding registers.
+    ///
+    /// Fails with [`ExceptionCode::IllegalDataAddress`] if any address
+    /// is not provided.
+    pub fn set_holding_registers(&self, addr: Address, words: &[u16]) -> Result<(), ExceptionCode> {
+        lock(&self.shared).holding_registers.write(addr, words)
+    }
+
+    /// Receive the changes of coils and holding registers by requests.
+    ///
+    /// Only values that have actually been modified by a request are
+    /// reported, by default one change per address in ascending order,
+    /// see [`Self::with_change_granularity()`]. Values that are written
+    /// by the application are not reported.
+    ///
+    /// Up to 1024 changes are buffered for each subscriber. Slow
+    /// subscribers miss the oldest changes and are notified with
+    /// [`broadcast::error::RecvError::Lagged`].
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<DataChange> {
+        lock(&self.shared)
+            .changes
+            .get_or_insert_with(|| broadcast::channel(CHANGES_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Process a request with restricted access to the values.
+    pub(crate) fn process_by(
+        &self,
+        request: Request<'_>,
+        policy: &impl AccessPolicy,
+    ) -> Result<Response, ExceptionCode> {
+        lock(&self.shared).process(request, policy)
+    }
+}
+
+impl Service for DataStore {
+    type Request = Request<'static>;
+    type Response = Response;
+    type Exception = ExceptionCode;
+    type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        future::ready(self.process_by(req, &()))
+    }
+
+    fn supported_functions(&self) -> Option<&[FunctionCode]> {
+        Some(SUPPORTED_FUNCTIONS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    fn store() -> DataStore {
+        DataStore::new()
+            .with_coils(10..=19)
+            .with_discrete_inputs(0..=7)
+            .with_input_registers(0..=1)
+            .with_holding_registers(100..=103)
+    }
+
+    fn process(store: &DataStore, request: Request<'_>) -> Result<Response, ExceptionCode> {
+        store.process_by(request, &())
+    }
+
+    #[test]
+    fn read_and_write_tables() {
+        let store = store();
+        store.set_discrete_inputs(6, &[true, true]).unwrap();
+        store.set_input_registers(0, &[1234, 5678]).unwrap();
+
+        assert_eq!(
+            process(
+                &store,
+                Request::WriteMultipleCoils(18, Cow::Borrowed(&[true, false]))
+            ),
+            Ok(Response::WriteMultipleCoils(18, 2))
+        );
+        assert_eq!(
+            process(&store, Request::WriteSingleCoil(10, true)),
+            Ok(Response::WriteSingleCoil(10, true))
+        );
+        assert_eq!(
+            process(&store, Request::ReadCoils(10, 10)),
+            Ok(Response::ReadCoils(vec![
+                true, false, false, false, false, false, false, false, true, false
+            ]))
+        );
+        assert_eq!(
+            process(&store, Request::ReadDiscreteInputs(5, 3)),
+            Ok(Response::ReadDiscreteInputs(vec![false, true, true]))
+        );
+        assert_eq!(
+            process(&store, Request::ReadInputRegisters(0, 2)),
+            Ok(Response::ReadInputRegisters(vec![1234, 5678]))
+        );
+        assert_eq!(
+            process(
+                &store,
+                Request::WriteMultipleRegisters(101, Cow::Borrowed(&[7, 8]))
+            ),
+            Ok(Response::WriteMultipleRegisters(101, 2))
+        );
+        assert_eq!(
+            process(&store, Request::WriteSingleRegister(103, 0x00F2)),
+            Ok(Response::WriteSingleRegister(103, 0x00F2))
+        );
+        assert_eq!(
+            process(&store, Request::MaskWriteRegister(103, 0x00F2, 0x0025)),
+            Ok(Response::MaskWriteRegister(103, 0x00F2, 0x0025))
+        );
+        assert_eq!(
+            process(
+                &store,
+                Request::ReadWriteMultipleRegisters(100, 4, 100, Cow::Borrowed(&[9]))
+            ),
+            Ok(Response::ReadWriteMultipleRegisters(vec![9, 7, 8, 0x00F7]))
+        );
+        assert_eq!(store.holding_registers(102, 2), Ok(vec![8, 0x00F7]));
+        assert_eq!(store.coils(18, 1), Ok(vec![true]));
+    }
+
+    #[test]
+    fn reject_invalid_requests() {
+        let store = store();
+        for (request, exception) in [
+            (Request::ReadCoils(9, 1), ExceptionCode::IllegalDataAddress),
+            (Request::ReadCoils(19, 2), ExceptionCode::IllegalDataAddress),
+            (Request::ReadCoils(10, 0), ExceptionCode::IllegalDataValue),
+            (
+                Request::ReadDiscreteInputs(0, 2001),
+                ExceptionCode::IllegalDataValue,
+            ),
+            (
+                Request::ReadHoldingRegisters(100, 126),
+                ExceptionCode::IllegalDataValue,
+            ),
+            (
+                Request::ReadInputRegisters(0xFFFF, 1),
+                ExceptionCode::IllegalDataAddress,
+            ),
+            (
+                Request::WriteSingleRegister(0, 1),
+                ExceptionCode::IllegalDataAddress,
+            ),
+            (
+                Request::WriteMultipleRegisters(100, Cow::Owned(vec![0; 124])),
+                ExceptionCode::IllegalDataValue,
+            ),
+            (
+                Request::WriteMultipleCoils(10, Cow::Owned(vec![])),
+                ExceptionCode::IllegalDataValue,
+            ),
+            (
+                Request::ReadWriteMultipleRegisters(100, 1, 100, Cow::Owned(vec![0; 122])),
+                ExceptionCode::IllegalDataValue,
+            ),
+            (
+                Request::MaskWriteRegister(104, 0, 0),
+                ExceptionCode::IllegalDataAddress,
+            ),
+            (Request::ReportServerId, ExceptionCode::IllegalFunction),
+        ] {
+            assert_eq!(process(&store, request), Err(exception));
+        }
+
+        // Nothing is written if the read addresses are invalid.
+        assert_eq!(
+            process(
+                &store,
+                Request::ReadWriteMultipleRegisters(103, 2, 100, Cow::Borrowed(&[1]))
+            ),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(store.holding_registers(100, 1), Ok(vec![0]));
+    }
+
+    #[test]
+    fn empty_tables() {
+        let store = DataStore::new();
+        assert_eq!(
+            process(&store, Request::ReadHoldingRegisters(0, 1)),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            store.set_coils(0, &[true]),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+    }
+
+    #[test]
+    fn notify_subscribers_about_changes() {
+        let store = store();
+        let mut changes = store.subscribe();
+        // Not reported.
+        store.set_holding_registers(100, &[0x00F0]).unwrap();
+
+        for request in [
+            Request::WriteMultipleCoils(10, Cow::Borrowed(&[false, true, true])),
+            Request::WriteSingleCoil(11, true),
+            Request::MaskWriteRegister(100, 0x00F0, 0x0005),
+            Request::ReadWriteMultipleRegisters(100, 1, 101, Cow::Borrowed(&[0, 3])),
+        ] {
+            process(&store, request).unwrap();
+        }
+        // Invalid requests don't modify any values.
+        process(&store, Request::WriteSingleRegister(104, 1)).unwrap_err();
+
+        let mut received = Vec::new();
+        while let Ok(change) = changes.try_recv() {
+            received.push(change);
+        }
+        assert_eq!(
+            received,
+            [
+                DataChange::Coil {
+                    addr: 11,
+                    old: false,
+                    new: true
+                },
+                DataChange::Coil {
+                    addr: 12,
+                    old: false,
+                    new: true
+                },
+                DataChange::HoldingRegister {
+                    addr: 100,
+                    old: 0x00F0,
+                    new: 0x00F5
+                },
+                DataChange::HoldingRegister {
+                    addr: 102,
+                    old: 0,
+                    new: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn notify_subscribers_about_changes_per_request() {
+        let store = store().with_change_granularity(ChangeGranularity::Request);
+        let mut changes = store.subscribe();
+        store.set_holding_registers(101, &[2]).unwrap();
+
+        for request in [
+            Request::WriteMultipleRegisters(100, Cow::Borrowed(&[1, 2, 3])),
+            // Not reported, because nothing is modified.
+            Request::WriteMultipleRegisters(101, Cow::Borrowed(&[2, 3])),
+            Request::WriteSingleCoil(10, true),
+            Request::WriteMultipleCoils(10, Cow::Borrowed(&[true, true])),
+        ] {
+            process(&store, request).unwrap();
+        }
+
+        let mut received = Vec::new();
+        while let Ok(change) = changes.try_recv() {
+            received.push(change);
+        }
+        assert_eq!(
+            received,
+            [
+                DataChange::HoldingRegisters {
+                    addr: 100,
+                    old: vec![0, 2, 0],
+                    new: vec![1, 2, 3]
+                },
+                DataChange::Coils {
+                    addr: 10,
+                    old: vec![false],
+                    new: vec![true]
+                },
+                DataChange::Coils {
+                    addr: 10,
+                    old: vec![true, false],
+                    new: vec![true, true]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn share_values_between_clones() {
+        let store = DataStore::new().with_input_registers(0xFFFE..=0xFFFF);
+        let clone = store.clone();
+        store.set_input_registers(0xFFFE, &[1, 2]).unwrap();
+        assert_eq!(clone.input_registers(0xFFFF, 1), Ok(vec![2]));
+    }
+}
@@ -0,0 +1,308 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::BTreeMap;
+
+use crate::{
+    codec::MAX_PDU_SIZE, DeviceIdObjectId, ExceptionCode, ReadDeviceIdCode,
+    ReadDeviceIdentificationResponse,
+};
+
+/// Function code, MEI type, read device id code, conformity level,
+/// more follows, next object id, and number of objects.
+const RESPONSE_HEADER_LEN: usize = 7;
+
+/// Object id and length.
+const OBJECT_HEADER_LEN: usize = 2;
+
+/// A single object must fit into a response.
+const MAX_OBJECT_LEN: usize = MAX_PDU_SIZE - RESPONSE_HEADER_LEN - OBJECT_HEADER_LEN;
+
+/// Conformity level flag for supporting individual access.
+const INDIVIDUAL_ACCESS: u8 = 0x80;
+
+/// The identification objects of a server device.
+///
+/// Answers Read Device Identification requests (0x2B / 0x0E) of both
+/// stream and individual access. Objects that don't fit into a single
+/// response are split into multiple responses that are requested by the
+/// client one after another, see [`ReadDeviceIdentificationResponse`].
+///
+/// # Example
+///
+/// ```
+/// use tokio_modbus::{server::DeviceIdentification, DeviceIdObjectId, ReadDeviceIdCode};
+///
+/// let identification = DeviceIdentification::new("slowtec", "tokio-modbus", "0.16")
+///     .product_name("Modbus server")
+///     .object(DeviceIdObjectId::Custom(0x80), "serial number 42");
+///
+/// let response = identification
+///     .response(ReadDeviceIdCode::Basic, DeviceIdObjectId::VendorName)
+///     .unwrap();
+/// assert_eq!(response.objects.len(), 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeviceIdentification {
+    objects: BTreeMap<DeviceIdObjectId, Vec<u8>>,
+}
+
+impl DeviceIdentification {
+    /// Create the identification with the mandatory basic objects.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value exceeds the maximum length of 244 bytes.
+    #[must_use]
+    pub fn new(
+        vendor_name: impl Into<Vec<u8>>,
+        product_code: impl Into<Vec<u8>>,
+        major_minor_revision: impl Into<Vec<u8>>,
+    ) -> Self {
+        Self {
+            objects: BTreeMap::new(),
+        }
+        .object(DeviceIdObjectId::VendorName, vendor_name)
+        .object(DeviceIdObjectId::ProductCode, product_code)
+        .object(DeviceIdObjectId::MajorMinorRevision, major_minor_revision)
+    }
+
+    /// Set the regular object Vendor Url.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value exceeds the maximum length of 244 bytes.
+    #[must_use]
+    pub fn vendor_url(self, vendor_url: impl Into<Vec<u8>>) -> Self {
+        self.object(DeviceIdObjectId::VendorUrl, vendor_url)
+    }
+
+    /// Set the regular object Product Name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value exceeds the maximum length of 244 bytes.
+    #[must_use]
+    pub fn product_name(self, product_name: impl Into<Vec<u8>>) -> Self {
+        self.object(DeviceIdObjectId::ProductName, product_name)
+    }
+
+    /// Set the regular object Model Name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value exceeds the maximum length of 244 bytes.
+    #[must_use]
+    pub fn model_name(self, model_name: impl Into<Vec<u8>>) -> Self {
+        self.object(DeviceIdObjectId::ModelName, model_name)
+    }
+
+    /// Set the regular object User Application Name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value exceeds the maximum length of 244 bytes.
+    #[must_use]
+    pub fn user_application_name(self, user_application_name: impl Into<Vec<u8>>) -> Self {
+        self.object(DeviceIdObjectId::UserApplicationName, user_application_name)
+    }
+
+    /// Set an arbitrary object, e.g. a device specific extended object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value exceeds the maximum length of 244 bytes.
+    #[must_use]
+    pub fn object(mut self, id: DeviceIdObjectId, value: impl Into<Vec<u8>>) -> Self {
+        let value = value.into();
+        assert!(
+            value.len() <= MAX_OBJECT_LEN,
+            "value of object {id} exceeds {MAX_OBJECT_LEN} bytes"
+        );
+        self.objects
+            .insert(DeviceIdObjectId::new(id.value()), value);
+        self
+    }
+
+    /// The highest category of all objects.
+    fn category(&self) -> ReadDeviceIdCode {
+        self.objects
+            .last_key_value()
+            .map_or(ReadDeviceIdCode::Basic, |(id, _)| id.category())
+    }
+
+    /// The conformity level of the device.
+    ///
+    /// Individual access is always supported.
+    #[must_use]
+    pub fn conformity_level(&self) -> u8 {
+        self.category().value() | INDIVIDUAL_ACCESS
+    }
+
+    /// The response to a Read Device Identification request.
+    ///
+    /// Stream access requests for a category beyond the conformity level
+    /// are answered with the objects of the highest available category.
+    /// Stream access restarts with the first object if the requested
+    /// object doesn't exist. Individual access to a nonexistent object
+    /// fails with [`ExceptionCode::IllegalDataAddress`].
+    pub fn response(
+        &self,
+        read_device_id_code: ReadDeviceIdCode,
+        object_id: DeviceIdObjectId,
+    ) -> Result<ReadDeviceIdentificationResponse, ExceptionCode> {
+        let object_id = DeviceIdObjectId::new(object_id.value());
+        let conformity_level = self.conformity_level();
+        if read_device_id_code == ReadDeviceIdCode::Specific {
+            let Some(value) = self.objects.get(&object_id) else {
+                return Err(ExceptionCode::IllegalDataAddress);
+            };
+            return Ok(ReadDeviceIdentificationResponse {
+                read_device_id_code,
+                conformity_level,
+                more_follows: false,
+                next_object_id: DeviceIdObjectId::VendorName,
+                objects: vec![(object_id, value.clone())],
+            });
+        }
+        let category = read_device_id_code.min(self.category());
+        let first_object_id =
+            if object_id.category() <= category && self.objects.contains_key(&object_id) {
+                object_id
+            } else {
+                DeviceIdObjectId::VendorName
+            };
+        let mut response_len = RESPONSE_HEADER_LEN;
+        let mut objects = Vec::new();
+        let mut next_object_id = None;
+        for (&id, value) in self
+            .objects
+            .range(first_object_id..)
+            .take_while(|(id, _)| id.category() <= category)
+        {
+            let object_len = OBJECT_HEADER_LEN + value.len();
+            if response_len + object_len > MAX_PDU_SIZE {
+                next_object_id = Some(id);
+                break;
+            }
+            response_len += object_len;
+            objects.push((id, value.clone()));
+        }
+        Ok(ReadDeviceIdentificationResponse {
+            read_device_id_code,
+            conformity_level,
+            more_follows: next_object_id.is_some(),
+            next_object_id: next_object_id.unwrap_or(DeviceIdObjectId::VendorName),
+            objects,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object_ids(response: &ReadDeviceIdentificationResponse) -> Vec<u8> {
+        response.objects.iter().map(|(id, _)| id.value()).collect()
+    }
+
+    #[test]
+    fn stream_access_includes_preceding_categories() {
+        let identification = DeviceIdentification::new("vendor", "product", "1.0")
+            .model_name("model")
+            .object(DeviceIdObjectId::Custom(0x80), "extended");
+        assert_eq!(identification.conformity_level(), 0x83);
+
+        let response = identification
+            .response(ReadDeviceIdCode::Basic, DeviceIdObjectId::VendorName)
+            .unwrap();
+        assert_eq!(object_ids(&response), [0x00, 0x01, 0x02]);
+        assert!(!response.more_follows);
+
+        let response = identification
+            .response(ReadDeviceIdCode::Regular, DeviceIdObjectId::VendorName)
+            .unwrap();
+        assert_eq!(object_ids(&response), [0x00, 0x01, 0x02, 0x05]);
+
+        let response = identification
+            .response(ReadDeviceIdCode::Extended, DeviceIdObjectId::ProductCode)
+            .unwrap();
+        assert_eq!(object_ids(&response), [0x01, 0x02, 0x05, 0x80]);
+        assert_eq!(response.read_device_id_code, ReadDeviceIdCode::Extended);
+        assert_eq!(response.conformity_level, 0x83);
+    }
+
+    #[test]
+    fn stream_access_beyond_conformity_level() {
+        let identification = DeviceIdentification::new("vendor", "product", "1.0");
+        assert_eq!(identification.conformity_level(), 0x81);
+        let response = identification
+            .response(ReadDeviceIdCode::Extended, DeviceIdObjectId::VendorName)
+            .unwrap();
+        assert_eq!(object_ids(&response), [0x00, 0x01, 0x02]);
+        assert_eq!(response.read_device_id_code, ReadDeviceIdCode::Extended);
+    }
+
+    #[test]
+    fn stream_access_restarts_at_unknown_objects() {
+        let identification = DeviceIdentification::new("vendor", "product", "1.0")
+            .object(DeviceIdObjectId::Custom(0x80), "extended");
+        for object_id in [DeviceIdObjectId::ModelName, DeviceIdObjectId::Custom(0x80)] {
+            let response = identification
+                .response(ReadDeviceIdCode::Basic, object_id)
+                .unwrap();
+            assert_eq!(object_ids(&response), [0x00, 0x01, 0x02]);
+        }
+    }
+
+    #[test]
+    fn stream_access_continues_in_subsequent_responses() {
+        let mut identification = DeviceIdentification::new("vendor", "product", "1.0");
+        for id in 0x80..0x90 {
+            identification = identification.object(DeviceIdObjectId::new(id), [id; 100]);
+        }
+
+        let mut object_id = DeviceIdObjectId::VendorName;
+        let mut received = Vec::new();
+        loop {
+            let response = identification
+                .response(ReadDeviceIdCode::Extended, object_id)
+                .unwrap();
+            assert!(!response.objects.is_empty());
+            received.extend(object_ids(&response));
+            if !response.more_follows {
+                break;
+            }
+            object_id = response.next_object_id;
+        }
+        let expected: Vec<_> = [0x00, 0x01, 0x02].into_iter().chain(0x80..0x90).collect();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn individual_access() {
+        let identification = DeviceIdentification::new("vendor", "product", "1.0");
+        let response = identification
+            .response(ReadDeviceIdCode::Specific, DeviceIdObjectId::ProductCode)
+            .unwrap();
+        assert_eq!(
+            response.objects,
+            [(DeviceIdObjectId::ProductCode, b"product".to_vec())]
+        );
+        assert!(!response.more_follows);
+        assert_eq!(
+            identification.response(ReadDeviceIdCode::Specific, DeviceIdObjectId::VendorUrl),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+    }
+
+    #[test]
+    #[should_panic = "exceeds"]
+    fn reject_too_long_values() {
+        drop(DeviceIdentification::new(
+            "vendor",
+            "product",
+            [0; MAX_OBJECT_LEN + 1],
+        ));
+    }
+}
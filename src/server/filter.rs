@@ -0,0 +1,185 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use futures_util::future::{self, Either, FutureExt as _, Map};
+
+use crate::{ExceptionCode, Request, Response, Slave, SlaveRequest};
+
+use super::{service::supports_function, Service};
+
+pub(super) type MapResult<S> = fn(
+    Result<<S as Service>::Response, <S as Service>::Exception>,
+) -> Result<Option<Response>, ExceptionCode>;
+
+/// Adapts a [`Service`] for [`Request`]s to be mounted on any server.
+///
+/// The wrapped service only receives requests that are addressed to
+/// one of the given slaves or that are broadcast. Requests addressed
+/// to other slaves are silently ignored, i.e. they are not answered.
+/// Requests for functions that the wrapped service doesn't support,
+/// see [`Service::supported_functions()`], are answered with
+/// [`ExceptionCode::IllegalFunction`] by the filter.
+///
+/// Since both TCP and RTU request ADUs convert into a [`SlaveRequest`]
+/// the same instance could be served over TCP, RTU, and RTU over TCP
+/// with consistent slave filtering and exception mapping.
+#[derive(Debug, Clone)]
+pub struct SlaveFilter<S> {
+    service: S,
+    slaves: Vec<Slave>,
+}
+
+impl<S> SlaveFilter<S> {
+    /// Wrap a service that serves the given slaves.
+    pub fn new(service: S, slaves: impl IntoIterator<Item = Slave>) -> Self {
+        Self {
+            service,
+            slaves: slaves.into_iter().collect(),
+        }
+    }
+
+    /// Check if requests for the slave are forwarded to the wrapped service.
+    #[must_use]
+    pub fn accepts(&self, slave: Slave) -> bool {
+        slave.is_broadcast() || self.slaves.contains(&slave)
+    }
+
+    /// The wrapped service.
+    #[must_use]
+    pub const fn service(&self) -> &S {
+        &self.service
+    }
+
+    /// Unwrap the wrapped service.
+    #[must_use]
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+impl<S> Service for SlaveFilter<S>
+where
+    S: Service<Request = Request<'static>>,
+{
+    type Request = SlaveRequest<'static>;
+    type Response = Option<Response>;
+    type Exception = ExceptionCode;
+    type Future = Either<
+        Map<S::Future, MapResult<S>>,
+        future::Ready<Result<Option<Response>, ExceptionCode>>,
+    >;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let SlaveRequest { slave, request } = req;
+        if !self.accepts(Slave(slave)) {
+            log::trace!("Ignoring request for slave {slave}");
+            return Either::Right(future::ready(Ok(None)));
+        }
+        if !supports_function(&self.service, request.function_code()) {
+            return Either::Right(future::ready(Err(ExceptionCode::IllegalFunction)));
+        }
+        let map_result: MapResult<S> = |res| res.map(Into::into).map_err(Into::into);
+        Either::Left(self.service.call(request).map(map_result))
+    }
+
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        self.service.serve_serial_line_functions_over_tcp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+
+    use crate::FunctionCode;
+
+    use super::*;
+
+    struct EchoService;
+
+    impl Service for EchoService {
+        type Request = Request<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            future::ready(match req {
+                Request::WriteSingleRegister(addr, value) => {
+                    Ok(Response::WriteSingleRegister(addr, value))
+                }
+                _ => unreachable!(),
+            })
+        }
+
+        fn supported_functions(&self) -> Option<&[FunctionCode]> {
+            Some(&[FunctionCode::WriteSingleRegister])
+        }
+    }
+
+    #[tokio::test]
+    async fn filter_requests_by_slave() {
+        let service = SlaveFilter::new(EchoService, [Slave(1), Slave(3)]);
+        let request = |slave| SlaveRequest {
+            slave,
+            request: Request::WriteSingleRegister(0x10, 0x1234),
+        };
+
+        assert_eq!(
+            service.call(request(1)).await,
+            Ok(Some(Response::WriteSingleRegister(0x10, 0x1234)))
+        );
+        assert_eq!(service.call(request(2)).await, Ok(None));
+        assert_eq!(
+            service.call(request(0)).await,
+            Ok(Some(Response::WriteSingleRegister(0x10, 0x1234)))
+        );
+        assert_eq!(
+            service
+                .call(SlaveRequest {
+                    slave: 3,
+                    request: Request::ReadCoils(0, 1),
+                })
+                .await,
+            Err(ExceptionCode::IllegalFunction)
+        );
+        // Unsupported functions of other slaves are ignored.
+        assert_eq!(
+            service
+                .call(SlaveRequest {
+                    slave: 2,
+                    request: Request::ReadCoils(0, 1),
+                })
+                .await,
+            Ok(None)
+        );
+    }
+
+    #[cfg(feature = "tcp-server")]
+    #[tokio::test]
+    async fn serve_serial_line_functions_over_tcp() {
+        struct ServerIdService;
+
+        impl Service for ServerIdService {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+            type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+            fn call(&self, _: Self::Request) -> Self::Future {
+                future::ready(Ok(Response::ReportServerId(1, true, vec![])))
+            }
+
+            fn serve_serial_line_functions_over_tcp(&self) -> bool {
+                true
+            }
+        }
+
+        let service = SlaveFilter::new(ServerIdService, [Slave(1)]);
+        let result = crate::server::tcp::call_over_tcp(service, Request::ReportServerId).await;
+        assert_eq!(
+            result.unwrap(),
+            Ok(Response::ReportServerId(1, true, vec![]))
+        );
+    }
+}
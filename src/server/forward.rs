@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    client::{Client as _, Context},
+    ExceptionCode, Request, Response,
+};
+
+use super::Service;
+
+/// Forwards requests to another server, e.g. for bridging a serial line
+/// and Modbus TCP.
+///
+/// Requests are forwarded unmodified with the client context, i.e. to
+/// the slave that is addressed by the context. Responses and exception
+/// responses of the target are returned as is. Requests that fail, e.g.
+/// because the target could not be reached or did not respond in time,
+/// are answered with [`ExceptionCode::GatewayTargetDevice`]. Configure
+/// a timeout for the context that is shorter than the timeout of the
+/// clients of the server.
+///
+/// Requests are forwarded one after another, clones share the context.
+///
+/// Combine multiple forwarding services with a
+/// [`MultiUnitService`](super::MultiUnitService) for dispatching the
+/// requests of an RTU master to multiple TCP servers by the slave id:
+///
+/// ```no_run
+/// # #[cfg(all(feature = "rtu-server", feature = "tcp"))]
+/// # async fn bridge() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::time::Duration;
+///
+/// use tokio_modbus::{
+///     client::tcp,
+///     server::{rtu::Server, Forward, MultiUnitService},
+///     Slave,
+/// };
+///
+/// let mut meter = tcp::connect("192.168.0.10:502".parse()?).await?;
+/// meter.set_timeout(Duration::from_millis(500));
+/// let mut inverter = tcp::connect_slave("192.168.0.20:502".parse()?, Slave(3)).await?;
+/// inverter.set_timeout(Duration::from_millis(500));
+///
+/// let service = MultiUnitService::new()
+///     .with_unit(Slave(1), Forward::new(meter))
+///     .with_unit(Slave(2), Forward::new(inverter));
+/// Server::new_from_path("/dev/ttyUSB0", 19200)?
+///     .serve_forever(service)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Forward {
+    context: Arc<Mutex<Context>>,
+}
+
+impl Forward {
+    /// Forward requests with the given client context.
+    #[must_use]
+    pub fn new(context: Context) -> Self {
+        Self {
+            context: Arc::new(Mutex::new(context)),
+        }
+    }
+}
+
+impl Service for Forward {
+    type Request = Request<'static>;
+    type Response = Response;
+    type Exception = ExceptionCode;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, ExceptionCode>> + Send>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let context = Arc::clone(&self.context);
+        Box::pin(async move {
+            let function = req.function_code();
+            let mut context = context.lock().await;
+            match context.call(req).await {
+                Ok(result) => result,
+                Err(err) => {
+                    log::warn!("Failed to forward request (function = {function}): {err}");
+                    Err(ExceptionCode::GatewayTargetDevice)
+                }
+            }
+        })
+    }
+
+    /// Serial line only functions are forwarded if requested over TCP.
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use async_trait::async_trait;
+
+    use crate::{
+        client::Client,
+        slave::{Slave, SlaveContext},
+        Result,
+    };
+
+    use super::*;
+
+    /// Answers reads and fails writes.
+    #[derive(Debug)]
+    struct Target;
+
+    #[async_trait]
+    impl Client for Target {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            match request {
+                Request::ReadHoldingRegisters(0, 1) => {
+                    Ok(Ok(Response::ReadHoldingRegisters(vec![7])))
+                }
+                Request::ReadHoldingRegisters(_, _) => Ok(Err(ExceptionCode::IllegalDataAddress)),
+                _ => Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+            }
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for Target {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn forward_requests() {
+        let target: Box<dyn Client> = Box::new(Target);
+        let service = Forward::new(target.into());
+        assert_eq!(
+            service.call(Request::ReadHoldingRegisters(0, 1)).await,
+            Ok(Response::ReadHoldingRegisters(vec![7]))
+        );
+        assert_eq!(
+            service.call(Request::ReadHoldingRegisters(1, 1)).await,
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            service.call(Request::WriteSingleRegister(0, 1)).await,
+            Err(ExceptionCode::GatewayTargetDevice)
+        );
+    }
+}
@@ -0,0 +1,195 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::{sync::watch, time::Instant};
+
+use crate::{frame::Word, Address, FunctionCode, Request};
+
+use super::Service;
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+    value: Option<Word>,
+    changed_at: Instant,
+}
+
+/// Monitors the heartbeat register that is written by a master.
+///
+/// The master is considered as stale if the value of the register
+/// has not changed within the timeout, e.g. because the master has
+/// stopped or lost the connection. Use a separate monitor per master
+/// or connection. The client side counterpart is
+/// [`Heartbeat`](crate::client::Heartbeat).
+///
+/// The state is shared by all clones.
+#[derive(Debug, Clone)]
+pub struct HeartbeatMonitor {
+    addr: Address,
+    timeout: Duration,
+    state: Arc<watch::Sender<State>>,
+}
+
+impl HeartbeatMonitor {
+    /// Monitor writes of the heartbeat register at `addr`.
+    ///
+    /// The master is considered as stale until the first write
+    /// unless the register is written within `timeout`.
+    #[must_use]
+    pub fn new(addr: Address, timeout: Duration) -> Self {
+        let state = State {
+            value: None,
+            changed_at: Instant::now(),
+        };
+        Self {
+            addr,
+            timeout,
+            state: Arc::new(watch::Sender::new(state)),
+        }
+    }
+
+    /// Record the heartbeat writes of all requests that are processed by the service.
+    pub fn track<S>(&self, service: S) -> TrackHeartbeat<S> {
+        TrackHeartbeat {
+            service,
+            monitor: self.clone(),
+        }
+    }
+
+    /// Record a request that might write the heartbeat register.
+    pub fn record(&self, request: &Request<'_>) {
+        use Request::*;
+
+        let value = match request {
+            WriteSingleRegister(addr, word) if *addr == self.addr => Some(*word),
+            WriteMultipleRegisters(addr, words) | ReadWriteMultipleRegisters(_, _, addr, words) => {
+                self.addr
+                    .checked_sub(*addr)
+                    .and_then(|offset| words.get(usize::from(offset)))
+                    .copied()
+            }
+            _ => None,
+        };
+        if let Some(value) = value {
+            self.record_value(value);
+        }
+    }
+
+    fn record_value(&self, value: Word) {
+        self.state.send_if_modified(|state| {
+            if state.value == Some(value) {
+                return false;
+            }
+            state.value = Some(value);
+            state.changed_at = Instant::now();
+            true
+        });
+    }
+
+    /// The most recently written value.
+    #[must_use]
+    pub fn value(&self) -> Option<Word> {
+        self.state.borrow().value
+    }
+
+    /// Check if the value has not changed within the timeout.
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        self.state.borrow().changed_at.elapsed() >= self.timeout
+    }
+
+    /// Wait until the value has not changed within the timeout.
+    ///
+    /// Returns immediately if the master is already stale.
+    pub async fn stale(&self) {
+        let mut state = self.state.subscribe();
+        loop {
+            let changed_at = state.borrow_and_update().changed_at;
+            let remaining = self.timeout.saturating_sub(changed_at.elapsed());
+            if remaining.is_zero() {
+                return;
+            }
+            // The sender is owned by `self` and can't be dropped while waiting.
+            if tokio::time::timeout(remaining, state.changed())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Wait until the value changes.
+    ///
+    /// Only changes after the returned future has been polled for
+    /// the first time are considered.
+    pub async fn alive(&self) {
+        let mut state = self.state.subscribe();
+        drop(state.changed().await);
+    }
+}
+
+/// A [`Service`] wrapper created by [`HeartbeatMonitor::track()`].
+#[derive(Debug)]
+pub struct TrackHeartbeat<S> {
+    service: S,
+    monitor: HeartbeatMonitor,
+}
+
+impl<S> Service for TrackHeartbeat<S>
+where
+    S: Service<Request = Request<'static>>,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Exception = S::Exception;
+    type Future = S::Future;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        self.monitor.record(&req);
+        self.service.call(req)
+    }
+
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        self.service.serve_serial_line_functions_over_tcp()
+    }
+
+    fn supported_functions(&self) -> Option<&[FunctionCode]> {
+        self.service.supported_functions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn detect_stale_master() {
+        let monitor = HeartbeatMonitor::new(10, Duration::from_millis(50));
+        assert!(!monitor.is_stale());
+        assert_eq!(monitor.value(), None);
+
+        monitor.record(&Request::WriteSingleRegister(10, 1));
+        monitor.record(&Request::WriteSingleRegister(11, 2));
+        assert_eq!(monitor.value(), Some(1));
+        monitor.record(&Request::WriteMultipleRegisters(9, Cow::Borrowed(&[0, 2])));
+        assert_eq!(monitor.value(), Some(2));
+
+        let started = Instant::now();
+        monitor.stale().await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(monitor.is_stale());
+
+        // Writing the same value again doesn't revive the master.
+        monitor.record(&Request::WriteSingleRegister(10, 2));
+        assert!(monitor.is_stale());
+
+        tokio::join!(monitor.alive(), async {
+            monitor.record(&Request::WriteSingleRegister(10, 3));
+        });
+        assert!(!monitor.is_stale());
+    }
+}
@@ -0,0 +1,216 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::sync::watch;
+
+use crate::FunctionCode;
+
+use super::Service;
+
+/// Counts the requests that are currently processed by services.
+///
+/// Use a separate child counter for each connection to observe the
+/// connection individually while the parent counter observes all
+/// connections. This allows to quiesce either a single connection
+/// or the whole server, e.g. for a graceful shutdown.
+#[derive(Debug, Clone)]
+pub struct InFlightRequests {
+    count: Arc<watch::Sender<usize>>,
+    parent: Option<Box<InFlightRequests>>,
+}
+
+impl Default for InFlightRequests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InFlightRequests {
+    /// Create a new counter.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            count: Arc::new(watch::Sender::new(0)),
+            parent: None,
+        }
+    }
+
+    /// Create a new counter whose requests are also counted by this counter.
+    #[must_use]
+    pub fn new_child(&self) -> Self {
+        Self {
+            count: Arc::new(watch::Sender::new(0)),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+
+    /// The number of requests that are currently processed.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        *self.count.borrow()
+    }
+
+    /// Wait until no requests are processed.
+    ///
+    /// Returns immediately if no requests are processed. New requests
+    /// might arrive at any time afterwards unless the server has stopped
+    /// accepting them.
+    pub async fn idle(&self) {
+        let mut count = self.count.subscribe();
+        // The sender is owned by `self` and can't be dropped while waiting.
+        drop(count.wait_for(|count| *count == 0).await);
+    }
+
+    /// Count all requests that are processed by the service.
+    pub fn track<S>(&self, service: S) -> TrackInFlight<S> {
+        TrackInFlight {
+            service,
+            in_flight: self.clone(),
+        }
+    }
+
+    fn increment(&self) {
+        self.count.send_modify(|count| *count += 1);
+        if let Some(parent) = &self.parent {
+            parent.increment();
+        }
+    }
+
+    fn decrement(&self) {
+        self.count.send_modify(|count| *count -= 1);
+        if let Some(parent) = &self.parent {
+            parent.decrement();
+        }
+    }
+}
+
+/// A [`Service`] wrapper created by [`InFlightRequests::track()`].
+#[derive(Debug)]
+pub struct TrackInFlight<S> {
+    service: S,
+    in_flight: InFlightRequests,
+}
+
+impl<S> Service for TrackInFlight<S>
+where
+    S: Service,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Exception = S::Exception;
+    type Future = TrackInFlightFuture<S::Future>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        self.in_flight.increment();
+        let guard = InFlightGuard(self.in_flight.clone());
+        TrackInFlightFuture {
+            future: Box::pin(self.service.call(req)),
+            _guard: guard,
+        }
+    }
+
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        self.service.serve_serial_line_functions_over_tcp()
+    }
+
+    fn supported_functions(&self) -> Option<&[FunctionCode]> {
+        self.service.supported_functions()
+    }
+}
+
+#[derive(Debug)]
+struct InFlightGuard(InFlightRequests);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.decrement();
+    }
+}
+
+/// The future returned by [`TrackInFlight`].
+///
+/// The request is counted until the future is either
+/// completed or dropped.
+#[derive(Debug)]
+pub struct TrackInFlightFuture<F> {
+    future: Pin<Box<F>>,
+    _guard: InFlightGuard,
+}
+
+impl<F> Future for TrackInFlightFuture<F>
+where
+    F: Future,
+{
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.future.as_mut().poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+
+    use crate::{ExceptionCode, Response};
+
+    use super::*;
+
+    struct PendingService;
+
+    impl Service for PendingService {
+        type Request = oneshot::Receiver<Response>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = Pin<Box<dyn Future<Output = Result<Response, ExceptionCode>> + Send>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            Box::pin(async move { req.await.map_err(|_| ExceptionCode::ServerDeviceFailure) })
+        }
+    }
+
+    #[tokio::test]
+    async fn count_in_flight_requests() {
+        let server = InFlightRequests::new();
+        let connection1 = server.new_child();
+        let connection2 = server.new_child();
+        let service1 = connection1.track(PendingService);
+        let service2 = connection2.track(PendingService);
+
+        let (tx1, rx1) = oneshot::channel();
+        let (tx2, rx2) = oneshot::channel();
+        let call1 = service1.call(rx1);
+        let call2 = service2.call(rx2);
+        assert_eq!(server.count(), 2);
+        assert_eq!(connection1.count(), 1);
+        assert_eq!(connection2.count(), 1);
+
+        // Dropping the future finishes the request.
+        drop(call2);
+        drop(tx2);
+        assert_eq!(server.count(), 1);
+        connection2.idle().await;
+
+        let response = Response::ReadHoldingRegisters(vec![1]);
+        let idle = async {
+            server.idle().await;
+            assert_eq!(connection1.count(), 0);
+        };
+        let respond = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            tx1.send(response.clone()).unwrap();
+            call1.await
+        };
+        let ((), result) = tokio::join!(idle, respond);
+        assert_eq!(result, Ok(response));
+    }
+}
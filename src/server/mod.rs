@@ -6,17 +6,73 @@
 // TODO: Add missing documentation
 #![allow(missing_docs)]
 
+#[cfg(feature = "rtu-server")]
+pub mod ascii;
+
 #[cfg(feature = "rtu-server")]
 pub mod rtu;
 
 #[cfg(feature = "tcp-server")]
 pub mod tcp;
 
+#[cfg(feature = "tcp-server")]
+pub mod udp;
+
+#[cfg(all(feature = "tls", feature = "tcp-server"))]
+pub mod tls;
+
+#[cfg(all(feature = "ws", feature = "tcp-server"))]
+pub mod ws;
+
 #[cfg(feature = "rtu-over-tcp-server")]
 pub mod rtu_over_tcp;
 
+#[cfg(any(
+    feature = "rtu-over-tcp-server",
+    feature = "rtu-server",
+    feature = "tcp-server"
+))]
+mod common;
+
+mod address_space;
+pub use self::address_space::{AddressSpace, OverlappingAddresses};
+
+mod access_stats;
+pub use self::access_stats::{AccessCounts, AccessStats, AddressRangeStats, Table, TrackAccess};
+
+mod authorization;
+pub use self::authorization::{Authorization, Authorized, Permissions, ROLE_OID};
+
+mod data_store;
+#[cfg(feature = "tcp-server")]
+pub(crate) use self::data_store::AccessPolicy;
+pub use self::data_store::{ChangeGranularity, DataChange, DataStore};
+
+mod device_id;
+pub use self::device_id::DeviceIdentification;
+
+mod filter;
+pub use self::filter::SlaveFilter;
+
+mod forward;
+pub use self::forward::Forward;
+
+mod heartbeat;
+pub use self::heartbeat::{HeartbeatMonitor, TrackHeartbeat};
+
+mod in_flight;
+pub use self::in_flight::{InFlightRequests, TrackInFlight, TrackInFlightFuture};
+
+mod multi_unit;
+pub use self::multi_unit::{BroadcastFuture, MultiUnitService};
+
 mod service;
-pub use self::service::Service;
+pub use self::service::{service_fn, AsyncService, Service, ServiceFn};
+
+#[cfg(feature = "tower")]
+mod tower;
+#[cfg(feature = "tower")]
+pub use self::tower::{BoxError, FromTower, IntoTower};
 
 /// Cause for termination
 #[derive(Debug, Clone)]
@@ -0,0 +1,291 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_util::future::{self, Either, FutureExt as _, Map};
+
+use crate::{ExceptionCode, Request, Response, Slave, SlaveId, SlaveRequest};
+
+use super::{filter::MapResult, service::supports_function, Service};
+
+/// Serves multiple slaves (units) with a separate [`Service`] per slave.
+///
+/// Requests are dispatched by their slave id. Like a real multi-drop
+/// device, requests for unknown slaves are silently ignored by default,
+/// i.e. they are not answered. Gateways should answer them with
+/// [`ExceptionCode::GatewayTargetDevice`] instead, see
+/// [`Self::reject_unknown_units()`].
+///
+/// Broadcast requests are forwarded to all services one after another
+/// and are never answered.
+///
+/// The [supported functions](Service::supported_functions()) of each
+/// unit are checked when dispatching the request. Serial line only
+/// functions are [served over TCP](Service::serve_serial_line_functions_over_tcp())
+/// if any unit opts in.
+///
+/// All services must have the same type. Use an `enum` for dispatching
+/// to different kinds of services.
+#[derive(Debug, Clone)]
+pub struct MultiUnitService<S> {
+    units: BTreeMap<SlaveId, S>,
+    unknown_unit_exception: Option<ExceptionCode>,
+}
+
+impl<S> Default for MultiUnitService<S> {
+    fn default() -> Self {
+        Self {
+            units: BTreeMap::new(),
+            unknown_unit_exception: None,
+        }
+    }
+}
+
+impl<S> MultiUnitService<S> {
+    /// A service without any units.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve requests for a slave with the given service.
+    ///
+    /// Returns the service that has previously served the slave.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slave is the broadcast address.
+    pub fn insert(&mut self, slave: Slave, service: S) -> Option<S> {
+        assert!(!slave.is_broadcast(), "broadcast is not a unit");
+        self.units.insert(slave.into(), service)
+    }
+
+    /// Serve requests for a slave with the given service.
+    ///
+    /// See also: [`Self::insert()`]
+    #[must_use]
+    pub fn with_unit(mut self, slave: Slave, service: S) -> Self {
+        self.insert(slave, service);
+        self
+    }
+
+    /// Answer requests for unknown slaves with an exception instead of
+    /// ignoring them.
+    #[must_use]
+    pub const fn reject_unknown_units(mut self, exception: ExceptionCode) -> Self {
+        self.unknown_unit_exception = Some(exception);
+        self
+    }
+
+    /// The served slaves in ascending order.
+    pub fn units(&self) -> impl Iterator<Item = Slave> + '_ {
+        self.units.keys().copied().map(Slave)
+    }
+
+    /// The service of a slave.
+    #[must_use]
+    pub fn service(&self, slave: Slave) -> Option<&S> {
+        self.units.get(&slave.into())
+    }
+}
+
+impl<S> Service for MultiUnitService<S>
+where
+    S: Service<Request = Request<'static>>,
+{
+    type Request = SlaveRequest<'static>;
+    type Response = Option<Response>;
+    type Exception = ExceptionCode;
+    type Future = Either<
+        Map<S::Future, MapResult<S>>,
+        Either<BroadcastFuture<S::Future>, future::Ready<Result<Option<Response>, ExceptionCode>>>,
+    >;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let SlaveRequest { slave, request } = req;
+        if Slave(slave).is_broadcast() {
+            let pending = self
+                .units
+                .values()
+                .filter(|service| supports_function(*service, request.function_code()))
+                .map(|service| Box::pin(service.call(request.clone())))
+                .collect();
+            return Either::Right(Either::Left(BroadcastFuture { pending }));
+        }
+        let Some(service) = self.units.get(&slave) else {
+            let result = if let Some(exception) = self.unknown_unit_exception {
+                log::debug!("Rejecting request for unknown slave {slave}: {exception}");
+                Err(exception)
+            } else {
+                log::trace!("Ignoring request for unknown slave {slave}");
+                Ok(None)
+            };
+            return Either::Right(Either::Right(future::ready(result)));
+        };
+        if !supports_function(service, request.function_code()) {
+            return Either::Right(Either::Right(future::ready(Err(
+                ExceptionCode::IllegalFunction,
+            ))));
+        }
+        let map_result: MapResult<S> = |res| res.map(Into::into).map_err(Into::into);
+        Either::Left(service.call(request).map(map_result))
+    }
+
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        self.units
+            .values()
+            .any(Service::serve_serial_line_functions_over_tcp)
+    }
+}
+
+/// Processes a broadcast request by all services one after another.
+#[derive(Debug)]
+pub struct BroadcastFuture<F> {
+    /// The remaining services in reverse order.
+    pending: Vec<Pin<Box<F>>>,
+}
+
+impl<F, T, E> Future for BroadcastFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<ExceptionCode>,
+{
+    type Output = Result<Option<Response>, ExceptionCode>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        while let Some(pending) = this.pending.last_mut() {
+            if let Err(exception) = ready!(pending.as_mut().poll(cx)) {
+                let exception = exception.into();
+                log::debug!("Failed to process broadcast request: {exception}");
+            }
+            this.pending.pop();
+        }
+        Poll::Ready(Ok(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future,
+        sync::{
+            atomic::{AtomicU16, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+
+    /// Stores the value of the last written register.
+    #[derive(Debug, Default)]
+    struct Register(Arc<AtomicU16>);
+
+    impl Service for Register {
+        type Request = Request<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            future::ready(match req {
+                Request::WriteSingleRegister(addr, value) => {
+                    self.0.store(value, Ordering::Relaxed);
+                    Ok(Response::WriteSingleRegister(addr, value))
+                }
+                Request::ReadHoldingRegisters(0, 1) => {
+                    Ok(Response::ReadHoldingRegisters(vec![self
+                        .0
+                        .load(Ordering::Relaxed)]))
+                }
+                _ => Err(ExceptionCode::IllegalFunction),
+            })
+        }
+    }
+
+    fn write(slave: SlaveId, value: u16) -> SlaveRequest<'static> {
+        SlaveRequest {
+            slave,
+            request: Request::WriteSingleRegister(0, value),
+        }
+    }
+
+    fn read(slave: SlaveId) -> SlaveRequest<'static> {
+        SlaveRequest {
+            slave,
+            request: Request::ReadHoldingRegisters(0, 1),
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatch_requests_by_slave() {
+        let service = MultiUnitService::new()
+            .with_unit(Slave(1), Register::default())
+            .with_unit(Slave(2), Register::default());
+        assert_eq!(service.units().collect::<Vec<_>>(), [Slave(1), Slave(2)]);
+
+        assert_eq!(
+            service.call(write(1, 0x1234)).await,
+            Ok(Some(Response::WriteSingleRegister(0, 0x1234)))
+        );
+        assert_eq!(
+            service.call(read(1)).await,
+            Ok(Some(Response::ReadHoldingRegisters(vec![0x1234])))
+        );
+        assert_eq!(
+            service.call(read(2)).await,
+            Ok(Some(Response::ReadHoldingRegisters(vec![0])))
+        );
+
+        // Unknown slaves are ignored.
+        assert_eq!(service.call(read(3)).await, Ok(None));
+        let service = service.reject_unknown_units(ExceptionCode::GatewayTargetDevice);
+        assert_eq!(
+            service.call(read(3)).await,
+            Err(ExceptionCode::GatewayTargetDevice)
+        );
+
+        // Broadcasts are processed by all slaves without a response.
+        assert_eq!(service.call(write(0, 0x5678)).await, Ok(None));
+        for slave in [1, 2] {
+            assert_eq!(
+                service.call(read(slave)).await,
+                Ok(Some(Response::ReadHoldingRegisters(vec![0x5678])))
+            );
+        }
+    }
+
+    #[cfg(feature = "tcp-server")]
+    #[tokio::test]
+    async fn serve_serial_line_functions_over_tcp() {
+        struct ServerIdService;
+
+        impl Service for ServerIdService {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+            type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+            fn call(&self, _: Self::Request) -> Self::Future {
+                future::ready(Ok(Response::ReportServerId(1, true, vec![])))
+            }
+
+            fn serve_serial_line_functions_over_tcp(&self) -> bool {
+                true
+            }
+        }
+
+        let service = MultiUnitService::new().with_unit(Slave(1), ServerIdService);
+        let result = crate::server::tcp::call_over_tcp(service, Request::ReportServerId).await;
+        assert_eq!(
+            result.unwrap(),
+            Ok(Response::ReportServerId(1, true, vec![]))
+        );
+    }
+}
@@ -3,49 +3,124 @@
 
 //! Modbus RTU server skeleton
 
-use std::{future::Future, io, path::Path};
+use std::{fmt, future::Future, io, path::Path, sync::Arc};
 
 use futures_util::{FutureExt as _, SinkExt as _, StreamExt as _};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_serial::SerialStream;
-use tokio_util::codec::Framed;
+use tokio_util::codec::{Decoder, Encoder, Framed};
 
 use crate::{
-    codec::rtu::ServerCodec,
+    client::Backoff,
+    codec::{rtu::ServerCodec, ChecksumErrors},
     frame::{
         rtu::{RequestAdu, ResponseAdu},
-        ExceptionResponse, OptionalResponsePdu, RequestPdu,
+        RequestPdu,
     },
+    metrics::{self, ServerMetrics},
+    stats::{ConnectionCounters, CountingIo},
+    tap::{FrameTap, Tap},
+    timing::{FrameTiming, TimedIo},
+    Metrics, Slave,
 };
 
-use super::{Service, Terminated};
+use super::{common::respond, Terminated};
+
+type OpenSerial = dyn Fn() -> io::Result<SerialStream> + Send + Sync;
 
-#[derive(Debug)]
 pub struct Server {
     serial: SerialStream,
+    timing: Option<FrameTiming>,
+    reopen: Option<Reopen>,
+    metrics: Option<Arc<dyn Metrics>>,
+    tap: Option<Tap>,
+}
+
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("serial", &self.serial)
+            .field("timing", &self.timing)
+            .field("reopen", &self.reopen.as_ref().map(|reopen| reopen.backoff))
+            .field("metrics", &self.metrics)
+            .field("tap", &self.tap)
+            .finish()
+    }
 }
 
 impl Server {
     /// set up a new [`Server`] instance from an interface path and baud rate
     pub fn new_from_path<P: AsRef<Path>>(p: P, baud_rate: u32) -> io::Result<Self> {
         let serial =
-            SerialStream::open(&tokio_serial::new(p.as_ref().to_string_lossy(), baud_rate))?;
-        Ok(Server { serial })
+            crate::serial::open(&tokio_serial::new(p.as_ref().to_string_lossy(), baud_rate))?;
+        Ok(Self::new(serial))
     }
 
     /// set up a new [`Server`] instance based on a pre-configured [`SerialStream`] instance
     #[must_use]
     pub fn new(serial: SerialStream) -> Self {
-        Server { serial }
+        Server {
+            serial,
+            timing: None,
+            reopen: None,
+            metrics: None,
+            tap: None,
+        }
+    }
+
+    /// Enforce the timing of the serial line.
+    ///
+    /// Request frames that contain an inter-character gap are discarded
+    /// and responses are only sent after the line has been silent for the
+    /// inter-frame delay, see [`FrameTiming`].
+    #[must_use]
+    pub const fn frame_timing(mut self, timing: FrameTiming) -> Self {
+        self.timing = Some(timing);
+        self
+    }
+
+    /// Reopen the serial port with `open` if it fails or disappears.
+    ///
+    /// USB serial adapters are removed from the system when unplugged or
+    /// reset and might re-appear with a different device path. Instead of
+    /// terminating, the server tries to reopen the port according to the
+    /// `backoff` and continues to process requests.
+    ///
+    /// The server terminates with the last error if all attempts failed.
+    #[must_use]
+    pub fn reopen(
+        mut self,
+        open: impl Fn() -> io::Result<SerialStream> + Send + Sync + 'static,
+        backoff: Backoff,
+    ) -> Self {
+        self.reopen = Some(Reopen {
+            open: Box::new(open),
+            backoff,
+        });
+        self
+    }
+
+    /// Report the requests and the traffic to `metrics`.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Pass a copy of all sent and received frames to `tap`.
+    #[must_use]
+    pub fn with_tap(mut self, tap: impl FrameTap + 'static) -> Self {
+        self.tap = Some(Tap::new(tap));
+        self
     }
 
     /// Process Modbus RTU requests.
     pub async fn serve_forever<S>(self, service: S) -> io::Result<()>
     where
-        S: Service + Send + Sync + 'static,
+        S: super::AsyncService + Send + Sync + 'static,
         S::Request: From<RequestAdu<'static>> + Send,
     {
-        let framed = Framed::new(self.serial, ServerCodec::default());
-        process(framed, service).await
+        self.serve(service).await
     }
 
     /// Process Modbus RTU requests until finished or aborted.
@@ -54,14 +129,13 @@ impl Server {
     /// See also: <https://rust-lang.github.io/wg-async/vision/roadmap/scopes.html#cancellation>
     pub async fn serve_until<S, X>(self, service: S, abort_signal: X) -> io::Result<Terminated>
     where
-        S: Service + Send + Sync + 'static,
+        S: super::AsyncService + Send + Sync + 'static,
         S::Request: From<RequestAdu<'static>> + Send,
         X: Future<Output = ()> + Sync + Send + Unpin + 'static,
     {
-        let framed = Framed::new(self.serial, ServerCodec::default());
         let abort_signal = abort_signal.fuse();
         tokio::select! {
-            res = process(framed, service) => {
+            res = self.serve(service) => {
                 res.map(|()| Terminated::Finished)
             },
             () = abort_signal => {
@@ -69,13 +143,99 @@ impl Server {
             }
         }
     }
+
+    async fn serve<S>(self, service: S) -> io::Result<()>
+    where
+        S: super::AsyncService,
+        S::Request: From<RequestAdu<'static>> + Send,
+    {
+        let Self {
+            mut serial,
+            timing,
+            reopen,
+            metrics,
+            tap,
+        } = self;
+        loop {
+            let counters = Arc::<ConnectionCounters>::default();
+            let metrics = metrics
+                .as_deref()
+                .map(|metrics| ServerMetrics::new(metrics, &counters));
+            let result = if let Some(timing) = timing {
+                let gaps = Arc::default();
+                let framed = Framed::new(
+                    CountingIo::new(
+                        TimedIo::new(serial, timing, Arc::clone(&gaps)),
+                        Arc::clone(&counters),
+                    ),
+                    ServerCodec {
+                        tap: tap.clone(),
+                        ..ServerCodec::with_frame_gaps(gaps)
+                    },
+                );
+                process(framed, &service, metrics).await
+            } else {
+                let framed = Framed::new(
+                    CountingIo::new(serial, Arc::clone(&counters)),
+                    ServerCodec {
+                        tap: tap.clone(),
+                        ..ServerCodec::default()
+                    },
+                );
+                process(framed, &service, metrics).await
+            };
+            let Some(reopen) = &reopen else {
+                return result;
+            };
+            match result {
+                Ok(()) => log::warn!("Serial port has been closed, reopening"),
+                Err(err) => log::warn!("Serial port failed, reopening: {err}"),
+            }
+            serial = reopen.open().await?;
+        }
+    }
+}
+
+struct Reopen {
+    open: Box<OpenSerial>,
+    backoff: Backoff,
+}
+
+impl Reopen {
+    async fn open(&self) -> io::Result<SerialStream> {
+        let mut last_err = None;
+        for delay in self.backoff.delays() {
+            tokio::time::sleep(delay).await;
+            match (self.open)() {
+                Ok(serial) => {
+                    log::info!("Reopened serial port");
+                    return Ok(serial);
+                }
+                Err(err) => {
+                    log::debug!("Failed to reopen serial port: {err}");
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| io::Error::from(io::ErrorKind::NotFound)))
+    }
 }
 
 /// frame wrapper around the underlying service's responses to forwarded requests
-async fn process<S>(mut framed: Framed<SerialStream, ServerCodec>, service: S) -> io::Result<()>
+///
+/// Also used for Modbus ASCII that only differs in the framing.
+pub(super) async fn process<S, T, C>(
+    mut framed: Framed<T, C>,
+    service: &S,
+    mut metrics: Option<ServerMetrics<'_>>,
+) -> io::Result<()>
 where
-    S: Service + Send + Sync + 'static,
+    S: super::AsyncService,
     S::Request: From<RequestAdu<'static>> + Send,
+    T: AsyncRead + AsyncWrite + Unpin,
+    C: Decoder<Item = RequestAdu<'static>, Error = io::Error>
+        + Encoder<ResponseAdu, Error = io::Error>
+        + ChecksumErrors,
 {
     loop {
         let Some(request_adu) = framed.next().await.transpose().inspect_err(|err| {
@@ -92,29 +252,28 @@ where
         } = &request_adu;
         let hdr = *hdr;
         let fc = request.function_code();
-        let OptionalResponsePdu(Some(response_pdu)) = service
-            .call(request_adu.into())
-            .await
-            .map(Into::into)
-            .map_err(|e| ExceptionResponse {
-                function: fc,
-                exception: e.into(),
-            })
-            .into()
-        else {
-            log::trace!("No response for request {hdr:?} (function = {fc})");
-            continue;
-        };
+        // Broadcast requests must be processed but never answered.
+        let expects_response =
+            request.expects_response() && !Slave::from(hdr.slave_id).is_broadcast();
+        let response_pdu = respond(service, request_adu.into(), fc, expects_response, hdr).await;
+        let response = response_pdu.as_ref().map(metrics::outcome);
 
-        framed
-            .send(ResponseAdu {
-                hdr,
-                pdu: response_pdu,
-            })
-            .await
-            .inspect_err(|err| {
-                log::debug!("Failed to send response for request {hdr:?} (function = {fc}): {err}");
-            })?;
+        if let Some(response_pdu) = response_pdu {
+            framed
+                .send(ResponseAdu {
+                    hdr,
+                    pdu: response_pdu,
+                })
+                .await
+                .inspect_err(|err| {
+                    log::debug!(
+                        "Failed to send response for request {hdr:?} (function = {fc}): {err}"
+                    );
+                })?;
+        }
+        if let Some(metrics) = &mut metrics {
+            metrics.report(fc, response, framed.codec().checksum_errors());
+        }
     }
     Ok(())
 }
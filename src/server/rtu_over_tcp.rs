@@ -18,11 +18,12 @@ use crate::{
     codec::rtu::ServerCodec,
     frame::{
         rtu::{RequestAdu, ResponseAdu},
-        ExceptionResponse, OptionalResponsePdu, RequestPdu,
+        RequestPdu,
     },
+    Slave,
 };
 
-use super::{Service, Terminated};
+use super::{common::respond, Terminated};
 
 #[async_trait]
 pub trait BindSocket {
@@ -38,7 +39,7 @@ pub fn accept_tcp_connection<S, NewService>(
     new_service: NewService,
 ) -> io::Result<Option<(S, TcpStream)>>
 where
-    S: Service + Send + Sync + 'static,
+    S: super::AsyncService + Send + Sync + 'static,
     S::Request: From<RequestAdu<'static>> + Send,
     NewService: Fn(SocketAddr) -> io::Result<Option<S>>,
 {
@@ -72,7 +73,7 @@ impl Server {
         on_process_error: OnProcessError,
     ) -> io::Result<()>
     where
-        S: Service + Send + Sync + 'static,
+        S: super::AsyncService + Send + Sync + 'static,
         S::Request: From<RequestAdu<'static>> + Send,
         T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
         OnConnected: Fn(TcpStream, SocketAddr) -> F,
@@ -112,7 +113,7 @@ impl Server {
         abort_signal: X,
     ) -> io::Result<Terminated>
     where
-        S: Service + Send + Sync + 'static,
+        S: super::AsyncService + Send + Sync + 'static,
         S::Request: From<RequestAdu<'static>> + Send,
         T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
         X: Future<Output = ()> + Sync + Send + Unpin + 'static,
@@ -135,7 +136,7 @@ impl Server {
 /// The request-response loop spawned by [`serve_until`] for each client
 async fn process<S, T>(mut framed: Framed<T, ServerCodec>, service: S) -> io::Result<()>
 where
-    S: Service + Send + Sync + 'static,
+    S: super::AsyncService + Send + Sync + 'static,
     S::Request: From<RequestAdu<'static>> + Send,
     T: AsyncRead + AsyncWrite + Unpin,
 {
@@ -154,17 +155,12 @@ where
         } = &request_adu;
         let hdr = *hdr;
         let fc = request.function_code();
-        let OptionalResponsePdu(Some(response_pdu)) = service
-            .call(request_adu.into())
-            .await
-            .map(Into::into)
-            .map_err(|e| ExceptionResponse {
-                function: fc,
-                exception: e.into(),
-            })
-            .into()
+        // Broadcast requests must be processed but never answered.
+        let expects_response =
+            request.expects_response() && !Slave::from(hdr.slave_id).is_broadcast();
+        let Some(response_pdu) =
+            respond(&service, request_adu.into(), fc, expects_response, hdr).await
         else {
-            log::trace!("No response for request {hdr:?} (function = {fc})");
             continue;
         };
 
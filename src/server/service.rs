@@ -1,7 +1,9 @@
 // SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::{future::Future, ops::Deref};
+use std::{fmt, future::Future, marker::PhantomData, ops::Deref};
+
+use crate::FunctionCode;
 
 /// A Modbus server service.
 pub trait Service {
@@ -30,6 +32,30 @@ pub trait Service {
 
     /// Process the request and return the response asynchronously.
     fn call(&self, req: Self::Request) -> Self::Future;
+
+    /// Opt in to serve serial line only functions over TCP.
+    ///
+    /// The TCP server answers requests for functions that are only defined
+    /// for serial lines with [`IllegalFunction`](crate::ExceptionCode::IllegalFunction)
+    /// without invoking the service, unless this returns `true`.
+    /// See also [`FunctionCode::is_serial_line_only()`](crate::FunctionCode::is_serial_line_only).
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        false
+    }
+
+    /// The functions that are supported by the service.
+    ///
+    /// Servers answer requests for other functions with
+    /// [`IllegalFunction`](crate::ExceptionCode::IllegalFunction)
+    /// without invoking the service. The declared functions could also
+    /// be inspected for diagnostics, e.g. for comparing them with the
+    /// documentation of a device.
+    ///
+    /// Returns `None` if all functions are supported, including custom
+    /// functions. This is the default.
+    fn supported_functions(&self) -> Option<&[FunctionCode]> {
+        None
+    }
 }
 
 impl<D> Service for D
@@ -44,6 +70,257 @@ where
 
     /// A forwarding blanket impl to support smart pointers around [`Service`].
     fn call(&self, req: Self::Request) -> Self::Future {
-        self.deref().call(req)
+        Service::call(&**self, req)
+    }
+
+    fn serve_serial_line_functions_over_tcp(&self) -> bool {
+        Service::serve_serial_line_functions_over_tcp(&**self)
+    }
+
+    fn supported_functions(&self) -> Option<&[FunctionCode]> {
+        Service::supported_functions(&**self)
+    }
+}
+
+/// A Modbus server service with an `async fn`.
+///
+/// Same as [`Service`], but without the need to name the type of the
+/// future. Implementors could simply write an `async fn handle()`:
+///
+/// ```
+/// # use tokio_modbus::{prelude::*, server::AsyncService};
+/// struct Echo;
+///
+/// impl AsyncService for Echo {
+///     type Request = Request<'static>;
+///     type Response = Response;
+///     type Exception = ExceptionCode;
+///
+///     async fn handle(&self, req: Self::Request) -> Result<Self::Response, Self::Exception> {
+///         match req {
+///             Request::WriteSingleRegister(addr, word) => {
+///                 Ok(Response::WriteSingleRegister(addr, word))
+///             }
+///             _ => Err(ExceptionCode::IllegalFunction),
+///         }
+///     }
+/// }
+/// ```
+///
+/// All servers accept an `AsyncService`. Every [`Service`] is also an
+/// `AsyncService`, i.e. existing services keep working unchanged.
+/// The methods are named differently than those of [`Service`] and
+/// both traits could be imported at the same time.
+pub trait AsyncService {
+    /// Requests handled by the service.
+    ///
+    /// See also [`Service::Request`].
+    type Request;
+
+    /// Responses sent by the service.
+    ///
+    /// See also [`Service::Response`].
+    type Response: Into<Option<crate::Response>>;
+
+    /// Exceptional responses sent by the service.
+    ///
+    /// See also [`Service::Exception`].
+    type Exception: Into<crate::ExceptionCode>;
+
+    /// Process the request and return the response asynchronously.
+    ///
+    /// See also [`Service::call()`].
+    fn handle(
+        &self,
+        req: Self::Request,
+    ) -> impl Future<Output = Result<Self::Response, Self::Exception>> + Send;
+
+    /// Opt in to handle serial line only functions over TCP.
+    ///
+    /// See also [`Service::serve_serial_line_functions_over_tcp()`].
+    fn handles_serial_line_functions_over_tcp(&self) -> bool {
+        false
+    }
+
+    /// The functions that are handled by the service.
+    ///
+    /// See also [`Service::supported_functions()`].
+    fn handled_functions(&self) -> Option<&[FunctionCode]> {
+        None
+    }
+}
+
+impl<S> AsyncService for S
+where
+    S: Service + ?Sized,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Exception = S::Exception;
+
+    fn handle(
+        &self,
+        req: Self::Request,
+    ) -> impl Future<Output = Result<Self::Response, Self::Exception>> + Send {
+        self.call(req)
+    }
+
+    fn handles_serial_line_functions_over_tcp(&self) -> bool {
+        self.serve_serial_line_functions_over_tcp()
+    }
+
+    fn handled_functions(&self) -> Option<&[FunctionCode]> {
+        self.supported_functions()
+    }
+}
+
+/// Checks if the service supports the function.
+pub(super) fn supports_function<S>(service: &S, function: FunctionCode) -> bool
+where
+    S: AsyncService + ?Sized,
+{
+    service
+        .handled_functions()
+        .map_or(true, |functions| functions.contains(&function))
+}
+
+/// Create a [`Service`] from a closure that returns a future.
+///
+/// Reduces the boilerplate of small servers:
+///
+/// ```
+/// # use tokio_modbus::{prelude::*, server::service_fn};
+/// let service = service_fn(|req: SlaveRequest<'static>| async move {
+///     match req.request {
+///         Request::ReadInputRegisters(_addr, cnt) => {
+///             Ok(Response::ReadInputRegisters(vec![0; cnt.into()]))
+///         }
+///         _ => Err(ExceptionCode::IllegalFunction),
+///     }
+/// });
+/// ```
+///
+/// Requests are selectively ignored by responding with `None`, e.g.
+/// for mimicking an unresponsive device:
+///
+/// ```
+/// # use tokio_modbus::{prelude::*, server::service_fn};
+/// let service = service_fn(|req: Request<'static>| async move {
+///     Ok::<_, ExceptionCode>(match req {
+///         Request::WriteSingleRegister(addr, word) => {
+///             Some(Response::WriteSingleRegister(addr, word))
+///         }
+///         _ => None,
+///     })
+/// });
+/// ```
+pub fn service_fn<Req, F, Fut>(f: F) -> ServiceFn<Req, F>
+where
+    F: Fn(Req) -> Fut,
+{
+    ServiceFn {
+        f,
+        _request: PhantomData,
+    }
+}
+
+/// A [`Service`] created from a closure, see [`service_fn()`].
+pub struct ServiceFn<Req, F> {
+    f: F,
+    _request: PhantomData<fn(Req)>,
+}
+
+impl<Req, F: Clone> Clone for ServiceFn<Req, F> {
+    fn clone(&self) -> Self {
+        Self {
+            f: self.f.clone(),
+            _request: PhantomData,
+        }
+    }
+}
+
+impl<Req, F> fmt::Debug for ServiceFn<Req, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceFn").finish_non_exhaustive()
+    }
+}
+
+impl<Req, F, Fut, Rsp, Exc> Service for ServiceFn<Req, F>
+where
+    F: Fn(Req) -> Fut,
+    Fut: Future<Output = Result<Rsp, Exc>> + Send,
+    Rsp: Into<Option<crate::Response>>,
+    Exc: Into<crate::ExceptionCode>,
+{
+    type Request = Req;
+    type Response = Rsp;
+    type Exception = Exc;
+    type Future = Fut;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        (self.f)(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ExceptionCode, Request, Response};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn call_service_fn() {
+        let service = service_fn(|req: Request<'static>| async move {
+            match req {
+                Request::ReadCoils(_, cnt) => Ok(Some(Response::ReadCoils(vec![true; cnt.into()]))),
+                Request::WriteSingleCoil(..) => Ok(None),
+                _ => Err(ExceptionCode::IllegalFunction),
+            }
+        });
+        assert_eq!(
+            Service::call(&service, Request::ReadCoils(0, 2)).await,
+            Ok(Some(Response::ReadCoils(vec![true, true])))
+        );
+        assert_eq!(
+            Service::call(&service, Request::WriteSingleCoil(0, true)).await,
+            Ok(None)
+        );
+        assert_eq!(
+            Service::call(&service, Request::ReadHoldingRegisters(0, 1)).await,
+            Err(ExceptionCode::IllegalFunction)
+        );
+    }
+
+    #[tokio::test]
+    async fn call_service_as_async_service() {
+        struct Coils;
+
+        impl Service for Coils {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+            type Future = std::future::Ready<Result<Response, ExceptionCode>>;
+
+            fn call(&self, req: Self::Request) -> Self::Future {
+                std::future::ready(match req {
+                    Request::ReadCoils(_, cnt) => Ok(Response::ReadCoils(vec![true; cnt.into()])),
+                    _ => Err(ExceptionCode::IllegalFunction),
+                })
+            }
+
+            fn supported_functions(&self) -> Option<&[FunctionCode]> {
+                Some(&[FunctionCode::ReadCoils])
+            }
+        }
+
+        // Both traits are in scope without ambiguities.
+        let service = Coils;
+        assert_eq!(
+            service.call(Request::ReadCoils(0, 1)).await,
+            service.handle(Request::ReadCoils(0, 1)).await
+        );
+        assert!(!service.handles_serial_line_functions_over_tcp());
+        assert_eq!(service.handled_functions(), service.supported_functions());
+        assert!(!supports_function(&service, FunctionCode::WriteSingleCoil));
     }
 }
@@ -3,7 +3,7 @@
 
 //! Modbus TCP server skeleton
 
-use std::{future::Future, io, net::SocketAddr};
+use std::{fmt, future::Future, io, net::SocketAddr, sync::Arc};
 
 use async_trait::async_trait;
 use futures_util::{FutureExt as _, SinkExt as _, StreamExt as _};
@@ -18,11 +18,16 @@ use crate::{
     codec::tcp::ServerCodec,
     frame::{
         tcp::{RequestAdu, ResponseAdu},
-        ExceptionResponse, OptionalResponsePdu, RequestPdu,
+        ExceptionResponse, RequestPdu, ResponsePdu,
     },
+    metrics::{self, ServerMetrics},
+    stats::{ConnectionCounters, CountingIo},
+    tap::{FrameTap, Tap},
+    transform::FrameTransform,
+    ConnectionStats, ExceptionCode, Metrics,
 };
 
-use super::{Service, Terminated};
+use super::{common::respond, Terminated};
 
 #[async_trait]
 pub trait BindSocket {
@@ -38,24 +43,93 @@ pub fn accept_tcp_connection<S, NewService>(
     new_service: NewService,
 ) -> io::Result<Option<(S, TcpStream)>>
 where
-    S: Service + Send + Sync + 'static,
-    S::Request: From<RequestAdu<'static>> + Send,
+    S: super::AsyncService,
     NewService: Fn(SocketAddr) -> io::Result<Option<S>>,
 {
     let service = new_service(socket_addr)?;
     Ok(service.map(|service| (service, stream)))
 }
 
-#[derive(Debug)]
+type NewFrameTransform = dyn Fn(SocketAddr) -> Box<dyn FrameTransform> + Send + Sync;
+
+type OnDisconnected = dyn Fn(SocketAddr, ConnectionStats) + Send + Sync;
+
 pub struct Server {
     listener: TcpListener,
+    new_frame_transform: Option<Box<NewFrameTransform>>,
+    on_disconnected: Option<Arc<OnDisconnected>>,
+    metrics: Option<Arc<dyn Metrics>>,
+    tap: Option<Tap>,
+}
+
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("listener", &self.listener)
+            .field("frame_transform", &self.new_frame_transform.is_some())
+            .field("on_disconnected", &self.on_disconnected.is_some())
+            .field("metrics", &self.metrics)
+            .field("tap", &self.tap)
+            .finish()
+    }
 }
 
 impl Server {
     /// Attach the Modbus server to a TCP socket server.
     #[must_use]
     pub fn new(listener: TcpListener) -> Self {
-        Self { listener }
+        Self {
+            listener,
+            new_frame_transform: None,
+            on_disconnected: None,
+            metrics: None,
+            tap: None,
+        }
+    }
+
+    /// Pass all frames through a [`FrameTransform`].
+    ///
+    /// A new transform is created for each accepted connection
+    /// from the address of the peer.
+    #[must_use]
+    pub fn with_frame_transform<F, X>(mut self, new_frame_transform: F) -> Self
+    where
+        F: Fn(SocketAddr) -> X + Send + Sync + 'static,
+        X: FrameTransform + 'static,
+    {
+        self.new_frame_transform = Some(Box::new(move |socket_addr| {
+            Box::new(new_frame_transform(socket_addr))
+        }));
+        self
+    }
+
+    /// Invoked with the accounting of each connection after it has
+    /// been closed.
+    ///
+    /// The accounting is also logged, i.e. the callback is only needed
+    /// for further processing.
+    #[must_use]
+    pub fn on_disconnected<F>(mut self, on_disconnected: F) -> Self
+    where
+        F: Fn(SocketAddr, ConnectionStats) + Send + Sync + 'static,
+    {
+        self.on_disconnected = Some(Arc::new(on_disconnected));
+        self
+    }
+
+    /// Report the requests and the traffic of all connections to `metrics`.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Pass a copy of all sent and received frames of all connections
+    /// to `tap`.
+    #[must_use]
+    pub fn with_tap(mut self, tap: impl FrameTap + 'static) -> Self {
+        self.tap = Some(Tap::new(tap));
+        self
     }
 
     /// Listens for incoming connections and starts a Modbus TCP server task for
@@ -72,7 +146,7 @@ impl Server {
         on_process_error: OnProcessError,
     ) -> io::Result<()>
     where
-        S: Service + Send + Sync + 'static,
+        S: super::AsyncService + Send + Sync + 'static,
         S::Request: From<RequestAdu<'static>> + Send,
         T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
         OnConnected: Fn(TcpStream, SocketAddr) -> F,
@@ -80,26 +154,93 @@ impl Server {
         OnProcessError: FnOnce(io::Error) + Clone + Send + 'static,
     {
         loop {
-            let (stream, socket_addr) = self.listener.accept().await?;
-            log::debug!("Accepted connection from {socket_addr}");
-
-            let Some((service, transport)) = on_connected(stream, socket_addr).await? else {
-                log::debug!("No service for connection from {socket_addr}");
+            let Some((framed, service, socket_addr)) = self.accept(on_connected).await? else {
                 continue;
             };
             let on_process_error = on_process_error.clone();
-
-            let framed = Framed::new(transport, ServerCodec::default());
+            let on_disconnected = self.on_disconnected.clone();
+            let metrics = self.metrics.clone();
 
             tokio::spawn(async move {
-                log::debug!("Processing requests from {socket_addr}");
-                if let Err(err) = process(framed, service).await {
+                if let Err(err) =
+                    process_connection(framed, service, socket_addr, on_disconnected, metrics).await
+                {
                     on_process_error(err);
                 }
             });
         }
     }
 
+    /// Listens for incoming connections and starts a local Modbus TCP server
+    /// task for each connection.
+    ///
+    /// Same as [`Self::serve()`], but both the service and the transport
+    /// are not required to be [`Send`] or [`Sync`]. This allows to use
+    /// services that wrap resources which are bound to a single thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if not invoked from within a [`tokio::task::LocalSet`].
+    pub async fn serve_local<S, T, F, OnConnected, OnProcessError>(
+        &self,
+        on_connected: &OnConnected,
+        on_process_error: OnProcessError,
+    ) -> io::Result<()>
+    where
+        S: super::AsyncService + 'static,
+        S::Request: From<RequestAdu<'static>>,
+        T: AsyncRead + AsyncWrite + Unpin + 'static,
+        OnConnected: Fn(TcpStream, SocketAddr) -> F,
+        F: Future<Output = io::Result<Option<(S, T)>>>,
+        OnProcessError: FnOnce(io::Error) + Clone + 'static,
+    {
+        loop {
+            let Some((framed, service, socket_addr)) = self.accept(on_connected).await? else {
+                continue;
+            };
+            let on_process_error = on_process_error.clone();
+            let on_disconnected = self.on_disconnected.clone();
+            let metrics = self.metrics.clone();
+
+            tokio::task::spawn_local(async move {
+                if let Err(err) =
+                    process_connection(framed, service, socket_addr, on_disconnected, metrics).await
+                {
+                    on_process_error(err);
+                }
+            });
+        }
+    }
+
+    async fn accept<S, T, F, OnConnected>(
+        &self,
+        on_connected: &OnConnected,
+    ) -> io::Result<Option<(Framed<CountingIo<T>, ServerCodec>, S, SocketAddr)>>
+    where
+        OnConnected: Fn(TcpStream, SocketAddr) -> F,
+        F: Future<Output = io::Result<Option<(S, T)>>>,
+    {
+        let (stream, socket_addr) = self.listener.accept().await?;
+        log::debug!("Accepted connection from {socket_addr}");
+
+        let Some((service, transport)) = on_connected(stream, socket_addr).await? else {
+            log::debug!("No service for connection from {socket_addr}");
+            return Ok(None);
+        };
+
+        let codec = ServerCodec {
+            transform: self
+                .new_frame_transform
+                .as_ref()
+                .map(|new_frame_transform| new_frame_transform(socket_addr)),
+            tap: self.tap.clone(),
+            ..Default::default()
+        };
+        let counters = Arc::default();
+        let framed = Framed::new(CountingIo::new(transport, counters), codec);
+        Ok(Some((framed, service, socket_addr)))
+    }
+
     /// Start an abortable Modbus TCP server task.
     ///
     /// Warning: Request processing is not scoped and could be aborted at any internal await point!
@@ -111,7 +252,7 @@ impl Server {
         abort_signal: X,
     ) -> io::Result<Terminated>
     where
-        S: Service + Send + Sync + 'static,
+        S: super::AsyncService + Send + Sync + 'static,
         S::Request: From<RequestAdu<'static>> + Send,
         T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
         X: Future<Output = ()> + Sync + Send + Unpin + 'static,
@@ -131,56 +272,160 @@ impl Server {
     }
 }
 
+/// Serve all requests that are received on a single connection.
+///
+/// Returns when the peer has closed the connection. Useful for local
+/// transports that are not accepted by a [`TcpListener`], e.g. named
+/// pipes on Windows (`tokio::net::windows::named_pipe::NamedPipeServer`)
+/// or Unix domain sockets. The peer could use
+/// [`client::tcp::attach()`](crate::client::tcp::attach) on the
+/// other end of the connection.
+pub async fn serve_connection<S, T>(transport: T, service: S) -> io::Result<()>
+where
+    S: super::AsyncService,
+    S::Request: From<RequestAdu<'static>>,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    process(
+        Framed::new(transport, ServerCodec::default()),
+        service,
+        &ConnectionCounters::default(),
+        None,
+    )
+    .await
+}
+
+/// Send a single request from a TCP client to the service.
+#[cfg(test)]
+pub(crate) async fn call_over_tcp<S>(
+    service: S,
+    request: crate::Request<'static>,
+) -> crate::Result<crate::Response>
+where
+    S: super::AsyncService,
+    S::Request: From<RequestAdu<'static>>,
+{
+    use crate::client::Client as _;
+
+    let (client, server) = tokio::io::duplex(1024);
+    let call = async {
+        let mut ctx = crate::client::tcp::attach_slave(client, crate::Slave(1));
+        // Closes the connection afterwards.
+        ctx.call(request).await
+    };
+    let (result, served) = tokio::join!(call, serve_connection(server, service));
+    served.unwrap();
+    result
+}
+
+/// Process all requests of an accepted connection and account for them.
+async fn process_connection<S, T>(
+    framed: Framed<CountingIo<T>, ServerCodec>,
+    service: S,
+    socket_addr: SocketAddr,
+    on_disconnected: Option<Arc<OnDisconnected>>,
+    metrics: Option<Arc<dyn Metrics>>,
+) -> io::Result<()>
+where
+    S: super::AsyncService,
+    S::Request: From<RequestAdu<'static>>,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    log::debug!("Processing requests from {socket_addr}");
+    let counters = Arc::clone(framed.get_ref().counters());
+    let metrics = metrics
+        .as_deref()
+        .map(|metrics| ServerMetrics::new(metrics, &counters));
+    let result = process(framed, service, &counters, metrics).await;
+    let stats = counters.stats();
+    log::info!("Connection from {socket_addr} closed: {stats}");
+    if let Some(on_disconnected) = on_disconnected {
+        on_disconnected(socket_addr, stats);
+    }
+    result
+}
+
 /// The request-response loop spawned by [`serve_until`] for each client
-async fn process<S, T>(mut framed: Framed<T, ServerCodec>, service: S) -> io::Result<()>
+async fn process<S, T>(
+    mut framed: Framed<T, ServerCodec>,
+    service: S,
+    counters: &ConnectionCounters,
+    mut metrics: Option<ServerMetrics<'_>>,
+) -> io::Result<()>
 where
-    S: Service + Send + Sync + 'static,
-    S::Request: From<RequestAdu<'static>> + Send,
+    S: super::AsyncService,
+    S::Request: From<RequestAdu<'static>>,
     T: AsyncRead + AsyncWrite + Unpin,
 {
     loop {
         let Some(request_adu) = framed.next().await.transpose().inspect_err(|err| {
             log::debug!("Failed to receive and decode request ADU: {err}");
+            counters.error();
         })?
         else {
             log::debug!("TCP socket has been closed");
             break;
         };
 
-        let RequestAdu {
-            hdr,
-            pdu: RequestPdu(request),
-        } = &request_adu;
-        let hdr = *hdr;
-        let fc = request.function_code();
-        let OptionalResponsePdu(Some(response_pdu)) = service
-            .call(request_adu.into())
-            .await
-            .map(Into::into)
-            .map_err(|e| ExceptionResponse {
-                function: fc,
-                exception: e.into(),
-            })
-            .into()
-        else {
-            log::trace!("No response for request {hdr:?} (function = {fc})");
-            continue;
-        };
-
-        framed
-            .send(ResponseAdu {
-                hdr,
-                pdu: response_pdu,
-            })
-            .await
-            .inspect_err(|err| {
-                log::debug!("Failed to send response for request {hdr:?} (function = {fc}): {err}");
-            })?;
+        counters.frame_received();
+        let hdr = request_adu.hdr;
+        let fc = request_adu.pdu.0.function_code();
+        let response_pdu = respond_to_adu(&service, request_adu).await;
+        let response = response_pdu.as_ref().map(metrics::outcome);
+
+        if let Some(response_pdu) = response_pdu {
+            framed
+                .send(ResponseAdu {
+                    hdr,
+                    pdu: response_pdu,
+                })
+                .await
+                .inspect_err(|err| {
+                    log::debug!(
+                        "Failed to send response for request {hdr:?} (function = {fc}): {err}"
+                    );
+                    counters.error();
+                })?;
+            counters.frame_sent();
+        }
+        if let Some(metrics) = &mut metrics {
+            metrics.report(fc, response, 0);
+        }
     }
 
     Ok(())
 }
 
+/// Process a request ADU and map the result of the service into the response PDU.
+///
+/// Requests for serial line only functions are rejected unless the
+/// service opted in. Shared with the UDP server.
+pub(super) async fn respond_to_adu<S>(
+    service: &S,
+    request_adu: RequestAdu<'static>,
+) -> Option<ResponsePdu>
+where
+    S: super::AsyncService,
+    S::Request: From<RequestAdu<'static>>,
+{
+    let RequestAdu {
+        hdr,
+        pdu: RequestPdu(request),
+    } = &request_adu;
+    let hdr = *hdr;
+    let fc = request.function_code();
+    let expects_response = request.expects_response();
+    if fc.is_serial_line_only() && !service.handles_serial_line_functions_over_tcp() {
+        log::debug!("Rejecting serial line only function for request {hdr:?} (function = {fc})");
+        let exception = ExceptionResponse {
+            function: fc,
+            exception: ExceptionCode::IllegalFunction,
+        };
+        return Some(exception.into());
+    }
+    respond(service, request_adu.into(), fc, expects_response, hdr).await
+}
+
 /// Start TCP listener - configure and open TCP socket
 #[allow(unused)]
 fn listener(addr: SocketAddr, workers: usize) -> io::Result<TcpListener> {
@@ -280,4 +525,304 @@ mod tests {
 
         assert_eq!(rsp_adu, service.response);
     }
+
+    #[tokio::test]
+    async fn serve_local_service() {
+        use std::{cell::Cell, rc::Rc};
+
+        // Neither `Send` nor `Sync`
+        struct CountingService {
+            count: Rc<Cell<u16>>,
+        }
+
+        impl Service for CountingService {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+            type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+            fn call(&self, _: Self::Request) -> Self::Future {
+                self.count.set(self.count.get() + 1);
+                future::ready(Ok(Response::ReadHoldingRegisters(vec![self.count.get()])))
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let server = Server::new(listener);
+
+        let count = Rc::new(Cell::new(0));
+        let on_connected = |stream, _socket_addr| {
+            let service = CountingService {
+                count: Rc::clone(&count),
+            };
+            async move { Ok(Some((service, stream))) }
+        };
+
+        let local_set = tokio::task::LocalSet::new();
+        local_set
+            .run_until(async {
+                let client = async {
+                    let mut ctx = crate::client::tcp::connect(socket_addr).await.unwrap();
+                    for expected in 1..=2 {
+                        let words = ctx.read_holding_registers(0, 1).await.unwrap().unwrap();
+                        assert_eq!(words, [expected]);
+                    }
+                };
+                tokio::select! {
+                    res = server.serve_local(&on_connected, |err| panic!("{err}")) => {
+                        panic!("server terminated: {res:?}");
+                    }
+                    () = client => {}
+                }
+            })
+            .await;
+        assert_eq!(count.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn account_closed_connections() {
+        struct EchoService;
+
+        impl Service for EchoService {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+            type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+            fn call(&self, req: Self::Request) -> Self::Future {
+                let Request::WriteSingleRegister(addr, word) = req else {
+                    return future::ready(Err(ExceptionCode::IllegalFunction));
+                };
+                future::ready(Ok(Response::WriteSingleRegister(addr, word)))
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let (stats_tx, mut stats_rx) = tokio::sync::mpsc::unbounded_channel();
+        let server = Server::new(listener).on_disconnected(move |_, stats| {
+            stats_tx.send(stats).unwrap();
+        });
+        let on_connected = |stream, _socket_addr| async move { Ok(Some((EchoService, stream))) };
+        let server = tokio::spawn(async move {
+            server
+                .serve(&on_connected, |err| panic!("{err}"))
+                .await
+                .unwrap();
+        });
+
+        let mut ctx = crate::client::tcp::connect(socket_addr).await.unwrap();
+        ctx.write_single_register(1, 2).await.unwrap().unwrap();
+        assert_eq!(
+            ctx.read_coils(0, 1).await.unwrap(),
+            Err(ExceptionCode::IllegalFunction)
+        );
+        let client_stats = ctx.connection_stats().unwrap();
+        ctx.disconnect().await.unwrap();
+
+        let server_stats = stats_rx.recv().await.unwrap();
+        assert_eq!(
+            server_stats,
+            ConnectionStats {
+                bytes_sent: 12 + 9,
+                bytes_received: 12 + 12,
+                frames_sent: 2,
+                frames_received: 2,
+                errors: 0,
+            }
+        );
+        assert_eq!(
+            client_stats,
+            ConnectionStats {
+                bytes_sent: server_stats.bytes_received,
+                bytes_received: server_stats.bytes_sent,
+                ..server_stats
+            }
+        );
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn serve_connection_of_custom_transport() {
+        struct EchoService;
+
+        impl Service for EchoService {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+            type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+            fn call(&self, req: Self::Request) -> Self::Future {
+                let Request::ReadHoldingRegisters(addr, _) = req else {
+                    return future::ready(Err(ExceptionCode::IllegalFunction));
+                };
+                future::ready(Ok(Response::ReadHoldingRegisters(vec![addr])))
+            }
+        }
+
+        let (client, server) = tokio::io::duplex(1024);
+        let server = tokio::spawn(serve_connection(server, EchoService));
+
+        let mut ctx = crate::client::tcp::attach(client);
+        let words = ctx.read_holding_registers(7, 1).await.unwrap().unwrap();
+        assert_eq!(words, [7]);
+
+        ctx.disconnect().await.unwrap();
+        drop(ctx);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn serve_async_service() {
+        use std::sync::atomic::{AtomicU16, Ordering};
+
+        use crate::server::AsyncService;
+
+        #[derive(Default)]
+        struct Register(AtomicU16);
+
+        impl AsyncService for Register {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+
+            async fn handle(&self, req: Self::Request) -> Result<Self::Response, Self::Exception> {
+                tokio::task::yield_now().await;
+                match req {
+                    Request::WriteSingleRegister(0, word) => {
+                        self.0.store(word, Ordering::Relaxed);
+                        Ok(Response::WriteSingleRegister(0, word))
+                    }
+                    Request::ReadHoldingRegisters(0, 1) => {
+                        Ok(Response::ReadHoldingRegisters(vec![self
+                            .0
+                            .load(Ordering::Relaxed)]))
+                    }
+                    _ => Err(ExceptionCode::IllegalDataAddress),
+                }
+            }
+        }
+
+        let (client, server) = tokio::io::duplex(1024);
+        let server = tokio::spawn(serve_connection(server, Register::default()));
+
+        let mut ctx = crate::client::tcp::attach(client);
+        ctx.write_single_register(0, 42).await.unwrap().unwrap();
+        let words = ctx.read_holding_registers(0, 1).await.unwrap().unwrap();
+        assert_eq!(words, [42]);
+        let res = ctx.read_holding_registers(1, 1).await.unwrap();
+        assert_eq!(res, Err(ExceptionCode::IllegalDataAddress));
+
+        ctx.disconnect().await.unwrap();
+        drop(ctx);
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_serial_line_only_functions() {
+        use crate::{
+            codec::tcp::ClientCodec,
+            frame::{tcp::Header, ResponsePdu},
+        };
+
+        struct DummyService {
+            serial_line_functions: bool,
+        }
+
+        impl Service for DummyService {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+            type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+            fn call(&self, _: Self::Request) -> Self::Future {
+                future::ready(Ok(Response::ReadExceptionStatus(0x42)))
+            }
+
+            fn serve_serial_line_functions_over_tcp(&self) -> bool {
+                self.serial_line_functions
+            }
+        }
+
+        for serial_line_functions in [false, true] {
+            let (client, server) = tokio::io::duplex(1024);
+            let service = DummyService {
+                serial_line_functions,
+            };
+            let server = tokio::spawn(serve_connection(server, service));
+
+            let mut client = Framed::new(client, ClientCodec::new());
+            let hdr = Header::new(1, 1);
+            client
+                .send(RequestAdu {
+                    hdr,
+                    pdu: Request::ReadExceptionStatus.into(),
+                })
+                .await
+                .unwrap();
+            let ResponseAdu {
+                pdu: ResponsePdu(result),
+                ..
+            } = client.next().await.unwrap().unwrap();
+            if serial_line_functions {
+                assert_eq!(result, Ok(Response::ReadExceptionStatus(0x42).into()));
+            } else {
+                assert_eq!(
+                    result,
+                    Err(ExceptionResponse {
+                        function: crate::FunctionCode::ReadExceptionStatus,
+                        exception: ExceptionCode::IllegalFunction,
+                    })
+                );
+            }
+
+            drop(client);
+            server.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn no_response_to_force_listen_only_mode() {
+        use std::borrow::Cow;
+
+        use crate::frame::{tcp::Header, DiagnosticsSubFunction, RequestPdu};
+
+        struct DiagnosticsService;
+
+        impl Service for DiagnosticsService {
+            type Request = Request<'static>;
+            type Response = Response;
+            type Exception = ExceptionCode;
+            type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+            fn call(&self, req: Self::Request) -> Self::Future {
+                let Request::Diagnostics(sub_function, data) = req else {
+                    unreachable!();
+                };
+                future::ready(Ok(Response::Diagnostics(sub_function, data.into_owned())))
+            }
+
+            fn serve_serial_line_functions_over_tcp(&self) -> bool {
+                true
+            }
+        }
+
+        let request_adu = |sub_function| RequestAdu {
+            hdr: Header::new(1, 1),
+            pdu: RequestPdu(Request::Diagnostics(sub_function, Cow::Owned(vec![0]))),
+        };
+        assert!(respond_to_adu(
+            &DiagnosticsService,
+            request_adu(DiagnosticsSubFunction::ReturnQueryData)
+        )
+        .await
+        .is_some());
+        assert!(respond_to_adu(
+            &DiagnosticsService,
+            request_adu(DiagnosticsSubFunction::ForceListenOnlyMode)
+        )
+        .await
+        .is_none());
+    }
 }
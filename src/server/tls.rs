@@ -0,0 +1,283 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Modbus/TCP Security server
+//!
+//! Accepts TLS connections and extracts the role of each client from
+//! its certificate, see [`ROLE_OID`](super::ROLE_OID). The role is passed when creating
+//! the service for the connection, e.g. for enforcing permissions with
+//! an [`Authorization`](super::Authorization).
+//!
+//! The [`ServerConfig`] is built with [`rustls`](tokio_rustls::rustls).
+//! Modbus/TCP Security requires the authentication of clients, i.e.
+//! configure a client certificate verifier.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn serve(
+//! #     config: tokio_modbus::server::tls::ServerConfig,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio::net::TcpListener;
+//! use tokio_modbus::{
+//!     server::{tls::Server, Authorization, DataStore, MultiUnitService, Permissions},
+//!     Slave,
+//! };
+//!
+//! let units = MultiUnitService::new()
+//!     .with_unit(Slave(1), DataStore::default().with_holding_registers(0..=99));
+//! let authorization = Authorization::new()
+//!     .with_role("operator", Permissions::all())
+//!     .with_role(
+//!         "observer",
+//!         Permissions::new()
+//!             .allow_functions([tokio_modbus::FunctionCode::ReadHoldingRegisters])
+//!             .allow_all_units(),
+//!     );
+//!
+//! let listener = TcpListener::bind("0.0.0.0:802").await?;
+//! let server = Server::new(listener, config);
+//! let new_service = |_socket_addr, role: Option<&str>| {
+//!     Ok(Some(authorization.authorize(role, units.clone())))
+//! };
+//! server.serve(&new_service, |err| log::error!("{err}")).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fmt, io, net::SocketAddr, sync::Arc, time::Duration};
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+pub use tokio_rustls::rustls::ServerConfig;
+
+use crate::{frame::tcp::RequestAdu, tap::FrameTap, ConnectionStats, Metrics};
+
+use super::{tcp, AsyncService};
+
+/// The maximum duration of the TLS handshake.
+///
+/// Connections are accepted one after another, i.e. clients that stall
+/// the handshake must not block the server.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The DER encoding of [`ROLE_OID`](super::ROLE_OID) without tag and length.
+const ROLE_OID_DER: [u8; 11] = [
+    0x2B, 0x06, 0x01, 0x04, 0x01, 0x83, 0x89, 0x0C, 0x86, 0x22, 0x01,
+];
+
+/// A Modbus/TCP Security server.
+pub struct Server {
+    server: tcp::Server,
+    acceptor: TlsAcceptor,
+}
+
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("server", &self.server)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Server {
+    /// Attach the Modbus server to a TCP socket server.
+    #[must_use]
+    pub fn new(listener: TcpListener, config: impl Into<Arc<ServerConfig>>) -> Self {
+        Self {
+            server: tcp::Server::new(listener),
+            acceptor: TlsAcceptor::from(config.into()),
+        }
+    }
+
+    /// Invoked with the accounting of each connection after it has
+    /// been closed.
+    #[must_use]
+    pub fn on_disconnected<F>(mut self, on_disconnected: F) -> Self
+    where
+        F: Fn(SocketAddr, ConnectionStats) + Send + Sync + 'static,
+    {
+        self.server = self.server.on_disconnected(on_disconnected);
+        self
+    }
+
+    /// Report the requests and the traffic of all connections to `metrics`.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.server = self.server.with_metrics(metrics);
+        self
+    }
+
+    /// Pass a copy of all decrypted frames of all connections to `tap`.
+    #[must_use]
+    pub fn with_tap(mut self, tap: impl FrameTap + 'static) -> Self {
+        self.server = self.server.with_tap(tap);
+        self
+    }
+
+    /// Listens for incoming connections and starts a Modbus server task
+    /// for each connection.
+    ///
+    /// `NewService` is invoked with the address and the role of each
+    /// client after the TLS handshake. If `NewService` returns with `Err`
+    /// then listening stops and [`Self::serve()`] returns with an error.
+    /// If `NewService` returns `Ok(None)` then the connection is rejected.
+    /// Connections with a failed handshake are rejected and logged.
+    pub async fn serve<S, NewService, OnProcessError>(
+        &self,
+        new_service: &NewService,
+        on_process_error: OnProcessError,
+    ) -> io::Result<()>
+    where
+        S: AsyncService + Send + Sync + 'static,
+        S::Request: From<RequestAdu<'static>> + Send,
+        NewService: Fn(SocketAddr, Option<&str>) -> io::Result<Option<S>>,
+        OnProcessError: FnOnce(io::Error) + Clone + Send + 'static,
+    {
+        let on_connected = |stream, socket_addr| async move {
+            let Some((stream, role)) = self.accept(stream, socket_addr).await else {
+                return Ok(None);
+            };
+            let service = new_service(socket_addr, role.as_deref())?;
+            Ok(service.map(|service| (service, stream)))
+        };
+        self.server.serve(&on_connected, on_process_error).await
+    }
+
+    async fn accept(
+        &self,
+        stream: TcpStream,
+        socket_addr: SocketAddr,
+    ) -> Option<(TlsStream<TcpStream>, Option<String>)> {
+        let handshake = self.acceptor.accept(stream);
+        let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(err)) => {
+                log::warn!("TLS handshake with {socket_addr} failed: {err}");
+                return None;
+            }
+            Err(_) => {
+                log::warn!("TLS handshake with {socket_addr} timed out");
+                return None;
+            }
+        };
+        let role = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(<[_]>::first)
+            .and_then(|certificate| role(certificate));
+        log::debug!("Client {socket_addr} authenticated with role {role:?}");
+        Some((stream, role))
+    }
+}
+
+/// Extract the role from a DER encoded X.509v3 certificate.
+///
+/// Returns `None` if the certificate doesn't contain the extension
+/// [`ROLE_OID`](super::ROLE_OID) with a UTF-8 string.
+#[must_use]
+pub fn role(certificate: &[u8]) -> Option<String> {
+    let (_, certificate, _) = read_der(certificate, SEQUENCE)?;
+    let (_, mut tbs_certificate, _) = read_der(certificate, SEQUENCE)?;
+    // Skip the fields up to the optional extensions.
+    let extensions = loop {
+        let (tag, value, rest) = read_any_der(tbs_certificate)?;
+        if tag == EXTENSIONS {
+            break value;
+        }
+        tbs_certificate = rest;
+    };
+    let (_, mut extensions, _) = read_der(extensions, SEQUENCE)?;
+    while !extensions.is_empty() {
+        let (_, extension, rest) = read_der(extensions, SEQUENCE)?;
+        extensions = rest;
+        let (_, oid, extension) = read_der(extension, OBJECT_IDENTIFIER)?;
+        if oid != ROLE_OID_DER {
+            continue;
+        }
+        // Skip the critical flag.
+        let extension = read_der(extension, BOOLEAN).map_or(extension, |(_, _, rest)| rest);
+        let (_, value, _) = read_der(extension, OCTET_STRING)?;
+        let (_, role, _) = read_der(value, UTF8_STRING)?;
+        return std::str::from_utf8(role).ok().map(ToOwned::to_owned);
+    }
+    None
+}
+
+const BOOLEAN: u8 = 0x01;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const UTF8_STRING: u8 = 0x0C;
+const SEQUENCE: u8 = 0x30;
+/// The explicitly tagged extensions of a `TBSCertificate`.
+const EXTENSIONS: u8 = 0xA3;
+
+/// Read a DER value with the expected tag.
+fn read_der(input: &[u8], expected_tag: u8) -> Option<(u8, &[u8], &[u8])> {
+    read_any_der(input).filter(|(tag, _, _)| *tag == expected_tag)
+}
+
+/// Read a DER value with a single byte tag.
+///
+/// Returns the tag, the value, and the remaining input.
+fn read_any_der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&len, mut input) = input.split_first()?;
+    let len = if len < 0x80 {
+        usize::from(len)
+    } else {
+        let num_bytes = usize::from(len & 0x7F);
+        if num_bytes == 0 || num_bytes > std::mem::size_of::<u32>() || input.len() < num_bytes {
+            return None;
+        }
+        let (len, rest) = input.split_at(num_bytes);
+        input = rest;
+        len.iter()
+            .fold(0, |len, &byte| (len << 8) | usize::from(byte))
+    };
+    if input.len() < len {
+        return None;
+    }
+    let (value, rest) = input.split_at(len);
+    Some((tag, value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::ROLE_OID;
+
+    use super::*;
+
+    #[test]
+    fn role_oid_encoding() {
+        let arcs: Vec<u32> = ROLE_OID
+            .split('.')
+            .map(|arc| arc.parse().unwrap())
+            .collect();
+        let mut encoded = vec![u8::try_from(arcs[0] * 40 + arcs[1]).unwrap()];
+        for &arc in &arcs[2..] {
+            let mut bytes = vec![u8::try_from(arc & 0x7F).unwrap()];
+            let mut arc = arc >> 7;
+            while arc > 0 {
+                bytes.push(u8::try_from(arc & 0x7F).unwrap() | 0x80);
+                arc >>= 7;
+            }
+            encoded.extend(bytes.iter().rev());
+        }
+        assert_eq!(encoded, ROLE_OID_DER);
+    }
+
+    #[test]
+    fn role_from_certificate() {
+        let certificate = include_bytes!("../../fixtures/tls-client-operator.der");
+        assert_eq!(role(certificate).as_deref(), Some("operator"));
+
+        let certificate = include_bytes!("../../fixtures/tls-client.der");
+        assert_eq!(role(certificate), None);
+
+        assert_eq!(role(&certificate[..100]), None);
+        assert_eq!(role(&[]), None);
+    }
+}
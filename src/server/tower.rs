@@ -0,0 +1,242 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Conversion between server services and [`tower_service::Service`]
+
+use std::{
+    fmt,
+    future::{poll_fn, Future},
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::sync::Mutex;
+
+use crate::{ExceptionCode, Response};
+
+use super::AsyncService;
+
+/// A type-erased error as used by most _tower_ middleware.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A server service that is used as a [`tower_service::Service`].
+///
+/// The service is always ready and exceptions are returned as errors.
+#[derive(Debug)]
+pub struct IntoTower<S> {
+    service: Arc<S>,
+}
+
+impl<S> IntoTower<S> {
+    /// Wrap a server service.
+    #[must_use]
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Arc::new(service),
+        }
+    }
+
+    /// The wrapped service.
+    #[must_use]
+    pub fn get_ref(&self) -> &S {
+        &self.service
+    }
+}
+
+impl<S> Clone for IntoTower<S> {
+    fn clone(&self) -> Self {
+        Self {
+            service: Arc::clone(&self.service),
+        }
+    }
+}
+
+impl<S> tower_service::Service<S::Request> for IntoTower<S>
+where
+    S: AsyncService + Send + Sync + 'static,
+    S::Request: Send + 'static,
+    S::Response: 'static,
+    S::Exception: 'static,
+{
+    type Response = S::Response;
+    type Error = S::Exception;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Exception>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: S::Request) -> Self::Future {
+        let service = Arc::clone(&self.service);
+        Box::pin(async move { service.handle(req).await })
+    }
+}
+
+/// A [`tower_service::Service`] that is used as a server service.
+///
+/// Allows to put _tower_ middleware like rate limiting, load shedding,
+/// or timeouts in front of a server service, e.g. by wrapping it into
+/// [`IntoTower`] first.
+///
+/// The wrapped service is shared by all requests. It is locked while
+/// waiting until it is ready to accept a request, but not while the
+/// request is processed.
+///
+/// Errors that are an [`ExceptionCode`] are sent as the exceptional
+/// response. All other errors are logged and answered with
+/// [`ExceptionCode::ServerDeviceFailure`]. Use the `map_err` combinator
+/// of _tower_ for sending different exceptions, e.g.
+/// [`ExceptionCode::ServerDeviceBusy`] if the load has been shed.
+pub struct FromTower<S, Req> {
+    service: Arc<Mutex<S>>,
+    _request: PhantomData<fn(Req)>,
+}
+
+impl<S, Req> FromTower<S, Req> {
+    /// Wrap a _tower_ service.
+    #[must_use]
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Arc::new(Mutex::new(service)),
+            _request: PhantomData,
+        }
+    }
+}
+
+impl<S, Req> Clone for FromTower<S, Req> {
+    fn clone(&self) -> Self {
+        Self {
+            service: Arc::clone(&self.service),
+            _request: PhantomData,
+        }
+    }
+}
+
+impl<S, Req> fmt::Debug for FromTower<S, Req> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FromTower").finish_non_exhaustive()
+    }
+}
+
+impl<S, Req> AsyncService for FromTower<S, Req>
+where
+    S: tower_service::Service<Req> + Send,
+    S::Response: Into<Option<Response>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+    Req: Send,
+{
+    type Request = Req;
+    type Response = S::Response;
+    type Exception = ExceptionCode;
+
+    async fn handle(&self, req: Req) -> Result<S::Response, ExceptionCode> {
+        let future = {
+            let mut service = self.service.lock().await;
+            poll_fn(|cx| service.poll_ready(cx))
+                .await
+                .map_err(|err| exception(err.into()))?;
+            service.call(req)
+        };
+        future.await.map_err(|err| exception(err.into()))
+    }
+}
+
+fn exception(err: BoxError) -> ExceptionCode {
+    match err.downcast::<ExceptionCode>() {
+        Ok(exception) => *exception,
+        Err(err) => {
+            log::warn!("Service failed: {err}");
+            ExceptionCode::ServerDeviceFailure
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future, time::Duration};
+
+    use tower::{ServiceBuilder, ServiceExt as _};
+
+    use crate::{server::Service, Request};
+
+    use super::*;
+
+    struct Echo;
+
+    impl Service for Echo {
+        type Request = Request<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Response, ExceptionCode>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            future::ready(match req {
+                Request::WriteSingleRegister(addr, word) => {
+                    Ok(Response::WriteSingleRegister(addr, word))
+                }
+                _ => Err(ExceptionCode::IllegalFunction),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn into_tower() {
+        let service = IntoTower::new(Echo);
+        assert_eq!(
+            service
+                .clone()
+                .oneshot(Request::WriteSingleRegister(1, 2))
+                .await,
+            Ok(Response::WriteSingleRegister(1, 2))
+        );
+        assert_eq!(
+            service.oneshot(Request::ReadCoils(0, 1)).await,
+            Err(ExceptionCode::IllegalFunction)
+        );
+    }
+
+    #[tokio::test]
+    async fn from_tower_with_middleware() {
+        let service = FromTower::new(
+            ServiceBuilder::new()
+                .concurrency_limit(1)
+                .timeout(Duration::from_secs(1))
+                .service(IntoTower::new(Echo)),
+        );
+        assert_eq!(
+            service.handle(Request::WriteSingleRegister(1, 2)).await,
+            Ok(Response::WriteSingleRegister(1, 2))
+        );
+        assert_eq!(
+            service.handle(Request::ReadCoils(0, 1)).await,
+            Err(ExceptionCode::IllegalFunction)
+        );
+    }
+
+    #[tokio::test]
+    async fn from_tower_errors() {
+        let service = FromTower::new(
+            ServiceBuilder::new()
+                .timeout(Duration::from_millis(1))
+                .service_fn(|()| future::pending::<Result<Response, BoxError>>()),
+        );
+        assert_eq!(
+            service.handle(()).await,
+            Err(ExceptionCode::ServerDeviceFailure)
+        );
+
+        let service = FromTower::new(
+            ServiceBuilder::new()
+                .map_err(|_: BoxError| ExceptionCode::ServerDeviceBusy)
+                .timeout(Duration::from_millis(1))
+                .service_fn(|()| future::pending::<Result<Response, BoxError>>()),
+        );
+        assert_eq!(
+            service.handle(()).await,
+            Err(ExceptionCode::ServerDeviceBusy)
+        );
+    }
+}
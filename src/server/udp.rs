@@ -0,0 +1,175 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Modbus TCP over UDP server skeleton
+
+use std::{future::Future, io, net::SocketAddr};
+
+use futures_util::FutureExt as _;
+use tokio::net::UdpSocket;
+use tokio_util::codec::{Decoder as _, Encoder as _};
+
+use crate::{
+    bytes::BytesMut,
+    codec::tcp::{ServerCodec, MAX_ADU_LEN},
+    frame::tcp::{RequestAdu, ResponseAdu},
+};
+
+use super::{tcp::respond_to_adu, Terminated};
+
+/// Serves Modbus TCP frames that are received as UDP datagrams.
+///
+/// Each datagram contains a single request. The response is sent back
+/// to the sender of the request. Requests are processed one after
+/// another in the order of reception.
+#[derive(Debug)]
+pub struct Server {
+    socket: UdpSocket,
+}
+
+impl Server {
+    /// Serve requests that are received on a bound socket.
+    #[must_use]
+    pub const fn new(socket: UdpSocket) -> Self {
+        Self { socket }
+    }
+
+    /// Bind a new socket to the given address.
+    pub async fn bind(socket_addr: SocketAddr) -> io::Result<Self> {
+        UdpSocket::bind(socket_addr).await.map(Self::new)
+    }
+
+    /// The local address of the socket.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Process Modbus requests.
+    pub async fn serve<S>(&self, service: S) -> io::Result<()>
+    where
+        S: super::AsyncService,
+        S::Request: From<RequestAdu<'static>>,
+    {
+        let mut codec = ServerCodec::default();
+        let mut datagram = [0; MAX_ADU_LEN];
+        loop {
+            let (len, peer_addr) = match self.socket.recv_from(&mut datagram).await {
+                Ok(received) => received,
+                // Reported on some platforms if a previous response could not be delivered.
+                Err(err) if err.kind() == io::ErrorKind::ConnectionReset => {
+                    log::debug!("Failed to deliver response: {err}");
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let mut buf = BytesMut::from(&datagram[..len]);
+            let request_adu = match codec.decode(&mut buf) {
+                Ok(Some(request_adu)) if buf.is_empty() => request_adu,
+                Ok(_) => {
+                    log::debug!(
+                        "Discarding datagram from {peer_addr} with an incomplete or oversized ADU"
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    log::debug!("Discarding invalid datagram from {peer_addr}: {err}");
+                    continue;
+                }
+            };
+            let hdr = request_adu.hdr;
+            let fc = request_adu.pdu.0.function_code();
+            let Some(response_pdu) = respond_to_adu(&service, request_adu).await else {
+                continue;
+            };
+            buf.clear();
+            codec.encode(
+                ResponseAdu {
+                    hdr,
+                    pdu: response_pdu,
+                },
+                &mut buf,
+            )?;
+            self.socket
+                .send_to(&buf, peer_addr)
+                .await
+                .inspect_err(|err| {
+                    log::debug!("Failed to send response for request {hdr:?} (function = {fc}) to {peer_addr}: {err}");
+                })?;
+        }
+    }
+
+    /// Process Modbus requests until aborted.
+    ///
+    /// Warning: Request processing is not scoped and could be aborted at any internal await point!
+    /// See also: <https://rust-lang.github.io/wg-async/vision/roadmap/scopes.html#cancellation>
+    pub async fn serve_until<S, X>(&self, service: S, abort_signal: X) -> io::Result<Terminated>
+    where
+        S: super::AsyncService,
+        S::Request: From<RequestAdu<'static>>,
+        X: Future<Output = ()> + Unpin,
+    {
+        let abort_signal = abort_signal.fuse();
+        tokio::select! {
+            res = self.serve(service) => {
+                res.map(|()| Terminated::Finished)
+            },
+            () = abort_signal => {
+                Ok(Terminated::Aborted)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future, net::Ipv4Addr};
+
+    use crate::{
+        client::{udp, Reader as _, Writer as _},
+        server::Service,
+        ExceptionCode, Request, Response, Slave, SlaveRequest,
+    };
+
+    use super::*;
+
+    struct Echo;
+
+    impl Service for Echo {
+        type Request = SlaveRequest<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Response, ExceptionCode>>;
+
+        fn call(&self, req: Self::Request) -> Self::Future {
+            future::ready(match req.request {
+                Request::WriteSingleRegister(addr, word) if req.slave == 1 => {
+                    Ok(Response::WriteSingleRegister(addr, word))
+                }
+                _ => Err(ExceptionCode::IllegalFunction),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn serve_clients() {
+        let server = Server::bind((Ipv4Addr::LOCALHOST, 0).into()).await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let clients = async {
+            let mut first = udp::connect_slave(server_addr, Slave(1)).await.unwrap();
+            let mut second = udp::connect_slave(server_addr, Slave(2)).await.unwrap();
+            assert_eq!(first.write_single_register(1, 2).await.unwrap(), Ok(()));
+            assert_eq!(
+                second.read_holding_registers(0, 1).await.unwrap(),
+                Err(ExceptionCode::IllegalFunction)
+            );
+            // Serial line only functions are rejected.
+            assert_eq!(
+                first.read_exception_status().await.unwrap(),
+                Err(ExceptionCode::IllegalFunction)
+            );
+        };
+        let terminated = server.serve_until(Echo, Box::pin(clients)).await.unwrap();
+        assert!(matches!(terminated, Terminated::Aborted));
+    }
+}
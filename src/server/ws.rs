@@ -0,0 +1,128 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Modbus TCP server for WebSocket connections
+//!
+//! Accepts WebSocket connections, e.g. from browser-based HMIs or
+//! forwarded by a reverse proxy, and serves the Modbus TCP frames that
+//! are carried in their binary messages. See [`crate::ws`] for serving
+//! connections that have been accepted otherwise.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn serve() -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio::net::TcpListener;
+//! use tokio_modbus::server::{ws::Server, DataStore};
+//!
+//! let store = DataStore::default().with_holding_registers(0..=99);
+//! let listener = TcpListener::bind("0.0.0.0:8080").await?;
+//! let server = Server::new(listener);
+//! let new_service = |_socket_addr| Ok(Some(store.clone()));
+//! server.serve(&new_service, |err| log::error!("{err}")).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{
+    frame::tcp::RequestAdu,
+    tap::FrameTap,
+    ws::{self, WebSocketStream},
+    ConnectionStats, Metrics,
+};
+
+use super::{tcp, AsyncService};
+
+/// The maximum duration of the opening handshake.
+///
+/// Connections are accepted one after another, i.e. clients that stall
+/// the handshake must not block the server.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A Modbus TCP server for WebSocket connections.
+#[derive(Debug)]
+pub struct Server {
+    server: tcp::Server,
+}
+
+impl Server {
+    /// Attach the Modbus server to a TCP socket server.
+    #[must_use]
+    pub fn new(listener: TcpListener) -> Self {
+        Self {
+            server: tcp::Server::new(listener),
+        }
+    }
+
+    /// Invoked with the accounting of each connection after it has
+    /// been closed.
+    #[must_use]
+    pub fn on_disconnected<F>(mut self, on_disconnected: F) -> Self
+    where
+        F: Fn(SocketAddr, ConnectionStats) + Send + Sync + 'static,
+    {
+        self.server = self.server.on_disconnected(on_disconnected);
+        self
+    }
+
+    /// Report the requests and the traffic of all connections to `metrics`.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.server = self.server.with_metrics(metrics);
+        self
+    }
+
+    /// Pass a copy of all frames of all connections to `tap`.
+    #[must_use]
+    pub fn with_tap(mut self, tap: impl FrameTap + 'static) -> Self {
+        self.server = self.server.with_tap(tap);
+        self
+    }
+
+    /// Listens for incoming connections and starts a Modbus server task
+    /// for each connection.
+    ///
+    /// `NewService` is invoked with the address of each client after the
+    /// opening handshake. If `NewService` returns with `Err` then listening
+    /// stops and [`Self::serve()`] returns with an error. If `NewService`
+    /// returns `Ok(None)` then the connection is rejected. Connections
+    /// with a failed handshake are rejected and logged.
+    pub async fn serve<S, NewService, OnProcessError>(
+        &self,
+        new_service: &NewService,
+        on_process_error: OnProcessError,
+    ) -> io::Result<()>
+    where
+        S: AsyncService + Send + Sync + 'static,
+        S::Request: From<RequestAdu<'static>> + Send,
+        NewService: Fn(SocketAddr) -> io::Result<Option<S>>,
+        OnProcessError: FnOnce(io::Error) + Clone + Send + 'static,
+    {
+        let on_connected = |stream, socket_addr| async move {
+            let Some(stream) = accept(stream, socket_addr).await else {
+                return Ok(None);
+            };
+            let service = new_service(socket_addr)?;
+            Ok(service.map(|service| (service, stream)))
+        };
+        self.server.serve(&on_connected, on_process_error).await
+    }
+}
+
+async fn accept(stream: TcpStream, socket_addr: SocketAddr) -> Option<WebSocketStream<TcpStream>> {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, ws::accept(stream)).await {
+        Ok(Ok(stream)) => Some(stream),
+        Ok(Err(err)) => {
+            log::warn!("WebSocket handshake with {socket_addr} failed: {err}");
+            None
+        }
+        Err(_) => {
+            log::warn!("WebSocket handshake with {socket_addr} timed out");
+            None
+        }
+    }
+}
@@ -7,8 +7,14 @@ pub(crate) mod rtu;
 #[cfg(feature = "tcp")]
 pub(crate) mod tcp;
 
+#[cfg(feature = "tcp")]
+pub(crate) mod udp;
+
 #[cfg(any(feature = "rtu", feature = "tcp"))]
-async fn disconnect<T, C>(framed: tokio_util::codec::Framed<T, C>) -> std::io::Result<()>
+pub(crate) mod transport;
+
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+pub(crate) async fn disconnect<T, C>(framed: tokio_util::codec::Framed<T, C>) -> std::io::Result<()>
 where
     T: tokio::io::AsyncWrite + Unpin,
 {
@@ -27,17 +33,24 @@ where
         })
 }
 
-/// Check that `req_hdr` is the same `Header` as `rsp_hdr`.
+/// Determine the response of a request that will not be answered.
+///
+/// Returns `None` if a response is expected.
 ///
 /// # Errors
 ///
-/// If the 2 headers are different, an error message with the details will be returned.
+/// Requests that read data could not be sent without receiving a response
+/// and fail with [`Error::NoResponse`](crate::Error::NoResponse).
 #[cfg(any(feature = "rtu", feature = "tcp"))]
-fn verify_response_header<H: Eq + std::fmt::Debug>(req_hdr: &H, rsp_hdr: &H) -> Result<(), String> {
-    if req_hdr != rsp_hdr {
-        return Err(format!(
-            "expected/request = {req_hdr:?}, actual/response = {rsp_hdr:?}"
-        ));
+pub(crate) fn implicit_response(
+    req: &crate::Request<'_>,
+    expects_response: bool,
+) -> Result<Option<crate::Response>, crate::Error> {
+    if expects_response {
+        return Ok(None);
     }
-    Ok(())
+    let Some(response) = req.implicit_response() else {
+        return Err(crate::Error::NoResponse(req.function_code()));
+    };
+    Ok(Some(response))
 }
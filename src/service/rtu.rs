@@ -1,26 +1,36 @@
 // SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::{fmt, io};
+use std::{fmt, io, sync::Arc, time::Duration};
 
-use futures_util::{SinkExt as _, StreamExt as _};
-use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_util::codec::Framed;
+use futures_util::{SinkExt, StreamExt as _};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, Framed};
 
 use crate::{
-    codec,
+    client::verify_decoded_response,
+    codec::{self, ChecksumErrors, RegisterViews},
     frame::{rtu::*, *},
     slave::*,
-    ProtocolError, Result,
+    stats::{ConnectionCounters, CountingIo},
+    ConnectionStats, Result,
 };
 
-use super::{disconnect, verify_response_header};
+use super::{disconnect, implicit_response};
+
+/// Silence on the line that ends the late response to a cancelled request.
+const RESYNC_QUIET_PERIOD: Duration = Duration::from_millis(100);
 
 /// Modbus RTU client
+///
+/// Also used for Modbus ASCII that only differs in the framing.
 #[derive(Debug)]
-pub(crate) struct Client<T> {
-    framed: Option<Framed<T, codec::rtu::ClientCodec>>,
+pub(crate) struct Client<T, C = codec::rtu::ClientCodec> {
+    framed: Option<Framed<CountingIo<T>, C>>,
     slave_id: SlaveId,
+    // Remains set if a call is cancelled before completion.
+    in_flight: bool,
+    counters: Arc<ConnectionCounters>,
 }
 
 impl<T> Client<T>
@@ -28,16 +38,33 @@ where
     T: AsyncRead + AsyncWrite + Unpin,
 {
     pub(crate) fn new(transport: T, slave: Slave) -> Self {
-        let framed = Framed::new(transport, codec::rtu::ClientCodec::default());
+        Self::with_codec(transport, codec::rtu::ClientCodec::default(), slave)
+    }
+}
+
+impl<T, C> Client<T, C>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    C: Decoder<Item = ResponseAdu<DecodedResponse>, Error = io::Error>
+        + for<'a> Encoder<RequestAdu<'a>, Error = io::Error>
+        + RegisterViews,
+{
+    pub(crate) fn with_codec(transport: T, codec: C, slave: Slave) -> Self {
+        let counters = Arc::<ConnectionCounters>::default();
+        let framed = Framed::new(CountingIo::new(transport, Arc::clone(&counters)), codec);
         let slave_id = slave.into();
         Self {
             slave_id,
             framed: Some(framed),
+            in_flight: false,
+            counters,
         }
     }
 
-    fn framed(&mut self) -> io::Result<&mut Framed<T, codec::rtu::ClientCodec>> {
-        let Some(framed) = &mut self.framed else {
+    fn framed(
+        framed: &mut Option<Framed<CountingIo<T>, C>>,
+    ) -> io::Result<&mut Framed<CountingIo<T>, C>> {
+        let Some(framed) = framed else {
             return Err(io::Error::new(io::ErrorKind::NotConnected, "disconnected"));
         };
         Ok(framed)
@@ -53,52 +80,87 @@ where
         RequestAdu { hdr, pdu }
     }
 
-    async fn call(&mut self, req: Request<'_>) -> Result<Response> {
-        log::debug!("Call {:?}", req);
+    async fn call(&mut self, req: Request<'_>, register_views: bool) -> Result<DecodedResponse> {
+        if self.in_flight {
+            self.resynchronize().await?;
+        }
+        self.in_flight = true;
+        let result = self.send_and_receive(req, register_views).await;
+        self.in_flight = false;
+        if result.is_err() {
+            self.counters.error();
+        }
+        result
+    }
+
+    /// Recover from a cancelled or failed call.
+    ///
+    /// Completes the transmission of the request and discards the late
+    /// or remaining response until the line stays quiet for
+    /// [`RESYNC_QUIET_PERIOD`]. Otherwise the late response might be
+    /// mistaken for the response to the next request.
+    async fn resynchronize(&mut self) -> io::Result<()> {
+        let framed = Self::framed(&mut self.framed)?;
+        SinkExt::<RequestAdu<'_>>::flush(framed).await?;
+        let mut discarded = framed.read_buffer().len();
+        framed.read_buffer_mut().clear();
+        let mut buf = [0; 256];
+        while let Ok(read) =
+            tokio::time::timeout(RESYNC_QUIET_PERIOD, framed.get_mut().read(&mut buf)).await
+        {
+            match read? {
+                0 => break,
+                len => discarded += len,
+            }
+        }
+        log::debug!("Discarded {discarded} byte(s) while resynchronizing");
+        self.in_flight = false;
+        Ok(())
+    }
+
+    async fn send_and_receive(
+        &mut self,
+        req: Request<'_>,
+        register_views: bool,
+    ) -> Result<DecodedResponse> {
+        log::debug!("Call {req:?}");
 
         let req_function_code = req.function_code();
+        let expects_response = req.expects_response() && !Slave::from(self.slave_id).is_broadcast();
+        let implicit_response = implicit_response(&req, expects_response)?;
         let req_adu = self.next_request_adu(req);
         let req_hdr = req_adu.hdr;
+        #[cfg(feature = "tracing")]
+        crate::spans::record_request_header(req_hdr.slave_id, None);
 
-        let framed = self.framed()?;
+        let framed = Self::framed(&mut self.framed)?;
 
         framed.read_buffer_mut().clear();
+        framed.codec_mut().set_register_views(register_views);
         framed.send(req_adu).await?;
+        self.counters.frame_sent();
+
+        if let Some(response) = implicit_response {
+            log::debug!(
+                "No response expected for request {req_hdr:?} (function = {req_function_code})"
+            );
+            return Ok(Ok(response.into()));
+        }
 
         let res_adu = framed
             .next()
             .await
             .unwrap_or_else(|| Err(io::Error::from(io::ErrorKind::BrokenPipe)))?;
+        self.counters.frame_received();
         let ResponseAdu {
             hdr: res_hdr,
-            pdu: res_pdu,
+            pdu: ResponsePdu(result),
         } = res_adu;
-        let ResponsePdu(result) = res_pdu;
-
-        // Match headers of request and response.
-        if let Err(message) = verify_response_header(&req_hdr, &res_hdr) {
-            return Err(ProtocolError::HeaderMismatch { message, result }.into());
-        }
-
-        // Match function codes of request and response.
-        let rsp_function_code = match &result {
-            Ok(response) => response.function_code(),
-            Err(ExceptionResponse { function, .. }) => *function,
-        };
-        if req_function_code != rsp_function_code {
-            return Err(ProtocolError::FunctionCodeMismatch {
-                request: req_function_code,
-                result,
-            }
-            .into());
-        }
+        verify_decoded_response(&req_hdr, req_function_code, &res_hdr, result)
+    }
 
-        Ok(result.map_err(
-            |ExceptionResponse {
-                 function: _,
-                 exception,
-             }| exception,
-        ))
+    fn connection_stats(&self) -> ConnectionStats {
+        self.counters.stats()
     }
 
     async fn disconnect(&mut self) -> io::Result<()> {
@@ -106,28 +168,56 @@ where
             // Already disconnected.
             return Ok(());
         };
-        disconnect(framed).await
+        let result = disconnect(framed).await;
+        log::info!("Disconnected: {stats}", stats = self.connection_stats());
+        result
     }
 }
 
-impl<T> SlaveContext for Client<T> {
+impl<T, C> SlaveContext for Client<T, C> {
     fn set_slave(&mut self, slave: Slave) {
         self.slave_id = slave.into();
     }
 }
 
 #[async_trait::async_trait]
-impl<T> crate::client::Client for Client<T>
+impl<T, C> crate::client::Client for Client<T, C>
 where
     T: fmt::Debug + AsyncRead + AsyncWrite + Send + Unpin,
+    C: Decoder<Item = ResponseAdu<DecodedResponse>, Error = io::Error>
+        + for<'a> Encoder<RequestAdu<'a>, Error = io::Error>
+        + ChecksumErrors
+        + RegisterViews
+        + fmt::Debug
+        + Send,
 {
     async fn call(&mut self, req: Request<'_>) -> Result<Response> {
-        self.call(req).await
+        let result = self.call(req, false).await?;
+        Ok(result.map(DecodedResponse::into_response))
+    }
+
+    async fn call_with_register_views(&mut self, req: Request<'_>) -> Result<DecodedResponse> {
+        self.call(req, true).await
     }
 
     async fn disconnect(&mut self) -> io::Result<()> {
         self.disconnect().await
     }
+
+    async fn resynchronize(&mut self) -> io::Result<bool> {
+        self.resynchronize().await?;
+        Ok(true)
+    }
+
+    fn connection_stats(&self) -> Option<ConnectionStats> {
+        Some(self.connection_stats())
+    }
+
+    fn checksum_errors(&self) -> u64 {
+        self.framed
+            .as_ref()
+            .map_or(0, |framed| framed.codec().checksum_errors())
+    }
 }
 
 #[cfg(test)]
@@ -137,12 +227,11 @@ mod tests {
         pin::Pin,
         task::{Context, Poll},
     };
+    use std::time::Duration;
+
     use tokio::io::{AsyncRead, AsyncWrite, ReadBuf, Result};
 
-    use crate::{
-        service::{rtu::Header, verify_response_header},
-        Error,
-    };
+    use crate::{client::VerifiableHeader as _, service::rtu::Header, Error};
 
     #[test]
     fn validate_same_headers() {
@@ -151,7 +240,7 @@ mod tests {
         let rsp_hdr = Header { slave_id: 0 };
 
         // When
-        let result = verify_response_header(&req_hdr, &rsp_hdr);
+        let result = req_hdr.verify_response_header(&rsp_hdr);
 
         // Then
         assert!(result.is_ok());
@@ -164,7 +253,7 @@ mod tests {
         let rsp_hdr = Header { slave_id: 5 };
 
         // When
-        let result = verify_response_header(&req_hdr, &rsp_hdr);
+        let result = req_hdr.verify_response_header(&rsp_hdr);
 
         // Then
         assert!(result.is_err());
@@ -203,9 +292,9 @@ mod tests {
     async fn handle_broken_pipe() {
         let transport = MockTransport;
         let mut client =
-            crate::service::rtu::Client::new(transport, crate::service::rtu::Slave::broadcast());
+            crate::service::rtu::Client::new(transport, crate::service::rtu::Slave::min_device());
         let res = client
-            .call(crate::service::rtu::Request::ReadCoils(0x00, 5))
+            .call(crate::service::rtu::Request::ReadCoils(0x00, 5), false)
             .await;
         assert!(res.is_err());
         let err = res.err().unwrap();
@@ -213,4 +302,86 @@ mod tests {
             matches!(err, Error::Transport(err) if err.kind() == std::io::ErrorKind::BrokenPipe)
         );
     }
+
+    #[tokio::test]
+    async fn broadcast_without_response() {
+        let transport = MockTransport;
+        let mut client =
+            crate::service::rtu::Client::new(transport, crate::service::rtu::Slave::broadcast());
+
+        let res = client
+            .call(
+                crate::service::rtu::Request::WriteSingleRegister(0x01, 0x1234),
+                false,
+            )
+            .await;
+        assert_eq!(
+            res.unwrap(),
+            Ok(crate::service::rtu::Response::WriteSingleRegister(0x01, 0x1234).into())
+        );
+
+        let res = client
+            .call(crate::service::rtu::Request::ReadCoils(0x00, 5), false)
+            .await;
+        assert!(matches!(
+            res,
+            Err(Error::NoResponse(crate::FunctionCode::ReadCoils))
+        ));
+    }
+
+    #[tokio::test]
+    async fn discard_late_response_after_cancelled_call() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        const REQUEST: [u8; 8] = [0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A];
+
+        let (transport, mut device) = tokio::io::duplex(1024);
+        let mut client = crate::service::rtu::Client::new(transport, crate::Slave(1));
+        let request = crate::service::rtu::Request::ReadHoldingRegisters(0x00, 1);
+
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(10),
+            client.call(request.clone(), false),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        let mut buf = [0; REQUEST.len()];
+        device.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, REQUEST);
+        // Late response to the cancelled request
+        device
+            .write_all(&[0x01, 0x03, 0x02, 0x00, 0x01, 0x79, 0x84])
+            .await
+            .unwrap();
+
+        let respond = async {
+            device.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, REQUEST);
+            device
+                .write_all(&[0x01, 0x03, 0x02, 0x00, 0x02, 0x39, 0x85])
+                .await
+                .unwrap();
+        };
+        let (res, ()) = tokio::join!(client.call(request, false), respond);
+        assert_eq!(
+            res.unwrap(),
+            Ok(crate::service::rtu::Response::ReadHoldingRegisters(vec![2]).into())
+        );
+    }
+
+    #[tokio::test]
+    async fn call_after_disconnect() {
+        let (transport, _server) = tokio::io::duplex(1024);
+        let mut client =
+            crate::service::rtu::Client::new(transport, crate::service::rtu::Slave::min_device());
+        client.disconnect().await.unwrap();
+        // Disconnecting again is a no-op.
+        client.disconnect().await.unwrap();
+        let res = client
+            .call(crate::service::rtu::Request::ReadCoils(0x00, 5), false)
+            .await;
+        assert!(
+            matches!(res, Err(Error::Transport(err)) if err.kind() == std::io::ErrorKind::NotConnected)
+        );
+    }
 }
@@ -1,40 +1,43 @@
 // SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::{fmt, io};
+use std::{fmt, io, sync::Arc};
 
-use futures_util::{SinkExt as _, StreamExt as _};
+use futures_util::{FutureExt as _, SinkExt as _, StreamExt as _};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_util::codec::Framed;
 
 use crate::{
-    codec,
+    client, codec,
     frame::{
         tcp::{Header, RequestAdu, ResponseAdu, TransactionId, UnitId},
-        RequestPdu, ResponsePdu,
+        DecodedResponse, RequestPdu, ResponsePdu,
     },
-    service::verify_response_header,
     slave::*,
-    ExceptionResponse, ProtocolError, Request, Response, Result,
+    stats::{ConnectionCounters, CountingIo},
+    ConnectionStats, FunctionCode, Request, Response, Result,
 };
 
-use super::disconnect;
+use super::{disconnect, implicit_response};
 
 const INITIAL_TRANSACTION_ID: TransactionId = 0;
 
+/// Maximum number of requests in the past that a stale response could belong to.
+pub(crate) const DEFAULT_MAX_STALE_TRANSACTION_AGE: TransactionId = 0x8000;
+
 #[derive(Debug)]
-struct TransactionIdGenerator {
+pub(crate) struct TransactionIdGenerator {
     next_transaction_id: TransactionId,
 }
 
 impl TransactionIdGenerator {
-    const fn new() -> Self {
+    pub(crate) const fn new() -> Self {
         Self {
             next_transaction_id: INITIAL_TRANSACTION_ID,
         }
     }
 
-    fn next(&mut self) -> TransactionId {
+    pub(crate) fn next(&mut self) -> TransactionId {
         let next_transaction_id = self.next_transaction_id;
         self.next_transaction_id = next_transaction_id.wrapping_add(1);
         next_transaction_id
@@ -44,9 +47,12 @@ impl TransactionIdGenerator {
 /// Modbus TCP client
 #[derive(Debug)]
 pub(crate) struct Client<T> {
-    framed: Option<Framed<T, codec::tcp::ClientCodec>>,
+    framed: Option<Framed<CountingIo<T>, codec::tcp::ClientCodec>>,
     transaction_id_generator: TransactionIdGenerator,
     unit_id: UnitId,
+    max_stale_transaction_age: TransactionId,
+    stale_responses: u64,
+    counters: Arc<ConnectionCounters>,
 }
 
 impl<T> Client<T>
@@ -54,16 +60,46 @@ where
     T: AsyncRead + AsyncWrite + Unpin,
 {
     pub(crate) fn new(transport: T, slave: Slave) -> Self {
-        let framed = Framed::new(transport, codec::tcp::ClientCodec::new());
+        Self::with_codec(transport, slave, codec::tcp::ClientCodec::new())
+    }
+
+    pub(crate) fn with_codec(transport: T, slave: Slave, codec: codec::tcp::ClientCodec) -> Self {
+        Self::with_buffer_capacity(transport, slave, codec, None)
+    }
+
+    /// Allocate the read and write buffers with an initial capacity.
+    ///
+    /// The buffers retain their capacity across calls and are only
+    /// reallocated if a frame doesn't fit.
+    pub(crate) fn with_buffer_capacity(
+        transport: T,
+        slave: Slave,
+        codec: codec::tcp::ClientCodec,
+        buffer_capacity: Option<usize>,
+    ) -> Self {
+        let counters = Arc::<ConnectionCounters>::default();
+        let transport = CountingIo::new(transport, Arc::clone(&counters));
+        let framed = match buffer_capacity {
+            Some(capacity) => Framed::with_capacity(transport, codec, capacity),
+            None => Framed::new(transport, codec),
+        };
         let transaction_id_generator = TransactionIdGenerator::new();
         let unit_id: UnitId = slave.into();
         Self {
             framed: Some(framed),
             transaction_id_generator,
             unit_id,
+            max_stale_transaction_age: DEFAULT_MAX_STALE_TRANSACTION_AGE,
+            stale_responses: 0,
+            counters,
         }
     }
 
+    /// Discard responses to up to `max_age` previous requests as stale.
+    pub(crate) fn set_max_stale_transaction_age(&mut self, max_age: TransactionId) {
+        self.max_stale_transaction_age = max_age;
+    }
+
     fn next_request_hdr(&mut self, unit_id: UnitId) -> Header {
         let transaction_id = self.transaction_id_generator.next();
         Header {
@@ -82,56 +118,82 @@ where
         }
     }
 
-    fn framed(&mut self) -> io::Result<&mut Framed<T, codec::tcp::ClientCodec>> {
-        let Some(framed) = &mut self.framed else {
+    fn framed(
+        framed: &mut Option<Framed<CountingIo<T>, codec::tcp::ClientCodec>>,
+    ) -> io::Result<&mut Framed<CountingIo<T>, codec::tcp::ClientCodec>> {
+        let Some(framed) = framed else {
             return Err(io::Error::new(io::ErrorKind::NotConnected, "disconnected"));
         };
         Ok(framed)
     }
 
-    pub(crate) async fn call(&mut self, req: Request<'_>) -> Result<Response> {
-        log::debug!("Call {:?}", req);
+    pub(crate) async fn call(
+        &mut self,
+        req: Request<'_>,
+        register_views: bool,
+    ) -> Result<DecodedResponse> {
+        let result = self.send_and_receive(req, register_views).await;
+        if result.is_err() {
+            self.counters.error();
+        }
+        result
+    }
+
+    async fn send_and_receive(
+        &mut self,
+        req: Request<'_>,
+        register_views: bool,
+    ) -> Result<DecodedResponse> {
+        log::debug!("Call {req:?}");
 
         let req_function_code = req.function_code();
+        let implicit_response = implicit_response(&req, req.expects_response())?;
         let req_adu = self.next_request_adu(req);
         let req_hdr = req_adu.hdr;
+        #[cfg(feature = "tracing")]
+        crate::spans::record_request_header(req_hdr.unit_id, Some(req_hdr.transaction_id));
 
-        let framed = self.framed()?;
+        let framed = Self::framed(&mut self.framed)?;
 
+        // Responses that have already been received could only be stale.
+        self.stale_responses += purge_received_responses(framed, &self.counters);
         framed.read_buffer_mut().clear();
+        framed.codec_mut().register_views = register_views;
         framed.send(req_adu).await?;
+        self.counters.frame_sent();
 
-        let res_adu = framed.next().await.ok_or_else(io::Error::last_os_error)??;
-        let ResponseAdu {
-            hdr: res_hdr,
-            pdu: res_pdu,
-        } = res_adu;
-        let ResponsePdu(result) = res_pdu;
-
-        // Match headers of request and response.
-        if let Err(message) = verify_response_header(&req_hdr, &res_hdr) {
-            return Err(ProtocolError::HeaderMismatch { message, result }.into());
+        if let Some(response) = implicit_response {
+            log::debug!(
+                "No response expected for request {req_hdr:?} (function = {req_function_code})"
+            );
+            return Ok(Ok(response.into()));
         }
 
-        // Match function codes of request and response.
-        let rsp_function_code = match &result {
-            Ok(response) => response.function_code(),
-            Err(ExceptionResponse { function, .. }) => *function,
-        };
-        if req_function_code != rsp_function_code {
-            return Err(ProtocolError::FunctionCodeMismatch {
-                request: req_function_code,
-                result,
+        let res_adu = loop {
+            let res_adu = framed
+                .next()
+                .await
+                .unwrap_or_else(|| Err(io::Error::from(io::ErrorKind::BrokenPipe)))?;
+            self.counters.frame_received();
+            if is_stale_response(req_hdr, res_adu.hdr, self.max_stale_transaction_age) {
+                log::debug!(
+                    "Discarding stale response {res_hdr:?} for request {req_hdr:?}",
+                    res_hdr = res_adu.hdr
+                );
+                self.stale_responses += 1;
+                continue;
             }
-            .into());
-        }
+            break res_adu;
+        };
+        verify_response(req_hdr, req_function_code, res_adu)
+    }
 
-        Ok(result.map_err(
-            |ExceptionResponse {
-                 function: _,
-                 exception,
-             }| exception,
-        ))
+    const fn stale_responses(&self) -> u64 {
+        self.stale_responses
+    }
+
+    fn connection_stats(&self) -> ConnectionStats {
+        self.counters.stats()
     }
 
     async fn disconnect(&mut self) -> io::Result<()> {
@@ -139,7 +201,9 @@ where
             // Already disconnected.
             return Ok(());
         };
-        disconnect(framed).await
+        let result = disconnect(framed).await;
+        log::info!("Disconnected: {stats}", stats = self.connection_stats());
+        result
     }
 }
 
@@ -155,16 +219,82 @@ where
     T: fmt::Debug + AsyncRead + AsyncWrite + Send + Unpin,
 {
     async fn call(&mut self, req: Request<'_>) -> Result<Response> {
-        self.call(req).await
+        let result = self.call(req, false).await?;
+        Ok(result.map(DecodedResponse::into_response))
+    }
+
+    async fn call_with_register_views(&mut self, req: Request<'_>) -> Result<DecodedResponse> {
+        self.call(req, true).await
     }
 
     async fn disconnect(&mut self) -> io::Result<()> {
         self.disconnect().await
     }
+
+    fn stale_responses(&self) -> u64 {
+        self.stale_responses()
+    }
+
+    fn connection_stats(&self) -> Option<ConnectionStats> {
+        Some(self.connection_stats())
+    }
+}
+
+/// Discard all responses that have already been received.
+///
+/// Returns the number of discarded responses.
+fn purge_received_responses<T>(
+    framed: &mut Framed<T, codec::tcp::ClientCodec>,
+    counters: &ConnectionCounters,
+) -> u64
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut purged = 0;
+    loop {
+        match framed.next().now_or_never() {
+            Some(Some(Ok(res_adu))) => {
+                log::debug!("Discarding stale response {:?}", res_adu.hdr);
+                counters.frame_received();
+                purged += 1;
+            }
+            Some(Some(Err(err))) => {
+                // The next poll resets the decoder.
+                log::debug!("Discarding invalid data: {err}");
+            }
+            Some(None) | None => break,
+        }
+    }
+    purged
+}
+
+/// Match the response with the request.
+pub(crate) fn verify_response(
+    req_hdr: Header,
+    req_function_code: FunctionCode,
+    res_adu: ResponseAdu<DecodedResponse>,
+) -> Result<DecodedResponse> {
+    let ResponseAdu {
+        hdr: res_hdr,
+        pdu: ResponsePdu(result),
+    } = res_adu;
+    client::verify_decoded_response(&req_hdr, req_function_code, &res_hdr, result)
+}
+
+/// Check if the response belongs to a previous request.
+///
+/// Only responses with a transaction identifier from the
+/// recent past, i.e. up to `max_age` requests ago, are considered
+/// as stale. All other mismatches are reported as errors.
+pub(super) fn is_stale_response(req_hdr: Header, res_hdr: Header, max_age: TransactionId) -> bool {
+    let age = req_hdr.transaction_id.wrapping_sub(res_hdr.transaction_id);
+    age > 0 && age <= max_age
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::client::VerifiableHeader as _;
+
     use super::*;
 
     #[test]
@@ -180,7 +310,7 @@ mod tests {
         };
 
         // When
-        let result = verify_response_header(&req_hdr, &rsp_hdr);
+        let result = req_hdr.verify_response_header(&rsp_hdr);
 
         // Then
         assert!(result.is_ok());
@@ -199,7 +329,7 @@ mod tests {
         };
 
         // When
-        let result = verify_response_header(&req_hdr, &rsp_hdr);
+        let result = req_hdr.verify_response_header(&rsp_hdr);
 
         // Then
         assert!(result.is_err());
@@ -218,9 +348,126 @@ mod tests {
         };
 
         // When
-        let result = verify_response_header(&req_hdr, &rsp_hdr);
+        let result = req_hdr.verify_response_header(&rsp_hdr);
 
         // Then
         assert!(result.is_err());
     }
+
+    #[test]
+    fn stale_responses_belong_to_previous_requests() {
+        let req_hdr = Header {
+            unit_id: 0,
+            transaction_id: 1,
+        };
+        let stale_hdr = |transaction_id| Header {
+            unit_id: 0,
+            transaction_id,
+        };
+        let is_stale = |transaction_id| {
+            is_stale_response(
+                req_hdr,
+                stale_hdr(transaction_id),
+                DEFAULT_MAX_STALE_TRANSACTION_AGE,
+            )
+        };
+        assert!(is_stale(0));
+        assert!(is_stale(0x8001));
+        assert!(!is_stale(1));
+        assert!(!is_stale(2));
+        assert!(!is_stale(0x8000));
+
+        assert!(is_stale_response(req_hdr, stale_hdr(0), 1));
+        assert!(!is_stale_response(req_hdr, stale_hdr(0xFFFF), 1));
+        assert!(!is_stale_response(req_hdr, stale_hdr(0), 0));
+    }
+
+    #[tokio::test]
+    async fn discard_stale_responses() {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        const fn response(transaction_id: u8, value: u8) -> [u8; 11] {
+            [0, transaction_id, 0, 0, 0, 5, 1, 0x03, 2, 0, value]
+        }
+
+        let (transport, mut server) = tokio::io::duplex(1024);
+        let mut client = Client::new(transport, Slave(1));
+
+        // The caller gave up waiting for the response to the first request.
+        client.transaction_id_generator.next();
+        server.write_all(&response(0, 1)).await.unwrap();
+
+        let respond = async {
+            let mut req = [0; 12];
+            server.read_exact(&mut req).await.unwrap();
+            assert_eq!(req[..2], [0, 1]);
+            // Another late response to the first request
+            server.write_all(&response(0, 2)).await.unwrap();
+            server.write_all(&response(1, 3)).await.unwrap();
+        };
+        let (rsp, ()) = tokio::join!(
+            client.call(Request::ReadHoldingRegisters(0, 1), false),
+            respond
+        );
+        assert_eq!(
+            rsp.unwrap(),
+            Ok(Response::ReadHoldingRegisters(vec![3]).into())
+        );
+        assert_eq!(client.stale_responses(), 2);
+    }
+
+    #[tokio::test]
+    async fn complete_request_of_cancelled_call() {
+        use std::time::Duration;
+
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        // Only a part of the first request fits into the transport. The
+        // remainder is sent before the next request, i.e. the stream is not
+        // corrupted, and the late response is discarded as a stale response.
+        let (transport, mut server) = tokio::io::duplex(4);
+        let mut client = Client::new(transport, Slave(1));
+
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(10),
+            client.call(Request::ReadHoldingRegisters(0, 1), false),
+        )
+        .await;
+        assert!(cancelled.is_err());
+
+        let respond = async {
+            let mut req = [0; 24];
+            server.read_exact(&mut req).await.unwrap();
+            assert_eq!(req[..12], [0, 0, 0, 0, 0, 6, 1, 0x03, 0, 0, 0, 1]);
+            assert_eq!(req[12..], [0, 1, 0, 0, 0, 6, 1, 0x03, 0, 0, 0, 1]);
+            server
+                .write_all(&[0, 1, 0, 0, 0, 5, 1, 0x03, 2, 0, 3])
+                .await
+                .unwrap();
+        };
+        let (rsp, ()) = tokio::join!(
+            client.call(Request::ReadHoldingRegisters(0, 1), false),
+            respond
+        );
+        assert_eq!(
+            rsp.unwrap(),
+            Ok(Response::ReadHoldingRegisters(vec![3]).into())
+        );
+    }
+
+    #[tokio::test]
+    async fn call_after_disconnect() {
+        let (transport, _server) = tokio::io::duplex(1024);
+        let mut client = Client::new(transport, Slave(1));
+        client.disconnect().await.unwrap();
+        // Disconnecting again is a no-op.
+        client.disconnect().await.unwrap();
+        let err = crate::client::Client::call(&mut client, Request::ReadHoldingRegisters(0, 1))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, crate::Error::Transport(err) if err.kind() == io::ErrorKind::NotConnected)
+        );
+        assert_eq!(client.connection_stats().errors, 1);
+    }
 }
@@ -0,0 +1,151 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{fmt, io};
+
+use futures_util::{SinkExt as _, StreamExt as _};
+
+use crate::{
+    client::{self, RequestAdu, ResponseAdu, Transport, VerifiableHeader},
+    slave::*,
+    Request, Response, Result,
+};
+
+use super::implicit_response;
+
+/// Maximum number of requests in the past that a stale response could belong to.
+const MAX_STALE_TRANSACTION_AGE: u16 = 0x8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    transaction_id: u16,
+    unit_id: u8,
+}
+
+impl VerifiableHeader for Header {}
+
+/// Client on top of a custom transport
+pub(crate) struct Client<T> {
+    transport: Option<T>,
+    next_transaction_id: u16,
+    unit_id: u8,
+    stale_responses: u64,
+}
+
+impl<T> fmt::Debug for Client<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("connected", &self.transport.is_some())
+            .field("next_transaction_id", &self.next_transaction_id)
+            .field("unit_id", &self.unit_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Client<T>
+where
+    T: Transport,
+{
+    pub(crate) fn new(transport: T, slave: Slave) -> Self {
+        Self {
+            transport: Some(transport),
+            next_transaction_id: 0,
+            unit_id: slave.into(),
+            stale_responses: 0,
+        }
+    }
+
+    fn next_request_hdr(&mut self) -> Header {
+        let transaction_id = self.next_transaction_id;
+        self.next_transaction_id = transaction_id.wrapping_add(1);
+        Header {
+            transaction_id,
+            unit_id: self.unit_id,
+        }
+    }
+
+    pub(crate) async fn call(&mut self, req: Request<'_>) -> Result<Response> {
+        log::debug!("Call {req:?}");
+
+        let req_function_code = req.function_code();
+        let implicit_response = implicit_response(&req, req.expects_response())?;
+        let req_hdr = self.next_request_hdr();
+        let Some(transport) = &mut self.transport else {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "disconnected").into());
+        };
+
+        transport
+            .send(RequestAdu {
+                transaction_id: req_hdr.transaction_id,
+                unit_id: req_hdr.unit_id,
+                request: req.into_owned(),
+            })
+            .await?;
+
+        if let Some(response) = implicit_response {
+            log::debug!(
+                "No response expected for request {req_hdr:?} (function = {req_function_code})"
+            );
+            return Ok(Ok(response));
+        }
+
+        let res_adu = loop {
+            let res_adu = transport
+                .next()
+                .await
+                .unwrap_or_else(|| Err(io::Error::from(io::ErrorKind::BrokenPipe)))?;
+            let age = req_hdr.transaction_id.wrapping_sub(res_adu.transaction_id);
+            if age > 0 && age <= MAX_STALE_TRANSACTION_AGE {
+                log::debug!(
+                    "Discarding stale response (transaction_id = {}) for request {req_hdr:?}",
+                    res_adu.transaction_id
+                );
+                self.stale_responses += 1;
+                continue;
+            }
+            break res_adu;
+        };
+        let ResponseAdu {
+            transaction_id,
+            unit_id,
+            response,
+        } = res_adu;
+        let res_hdr = Header {
+            transaction_id,
+            unit_id,
+        };
+        client::verify_response(&req_hdr, req_function_code, &res_hdr, response)
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        let Some(mut transport) = self.transport.take() else {
+            // Already disconnected.
+            return Ok(());
+        };
+        transport.close().await
+    }
+}
+
+impl<T> SlaveContext for Client<T> {
+    fn set_slave(&mut self, slave: Slave) {
+        self.unit_id = slave.into();
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> crate::client::Client for Client<T>
+where
+    T: Transport,
+{
+    async fn call(&mut self, req: Request<'_>) -> Result<Response> {
+        self.call(req).await
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        self.disconnect().await
+    }
+
+    fn stale_responses(&self) -> u64 {
+        self.stale_responses
+    }
+}
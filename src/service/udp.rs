@@ -0,0 +1,277 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::io;
+
+use tokio::net::UdpSocket;
+use tokio_util::codec::{Decoder as _, Encoder as _};
+
+use crate::{
+    bytes::BytesMut,
+    codec::{self, tcp::MAX_ADU_LEN},
+    frame::{
+        tcp::{Header, RequestAdu, ResponseAdu, UnitId},
+        DecodedResponse,
+    },
+    slave::*,
+    Request, Response, Result,
+};
+
+use super::{
+    implicit_response,
+    tcp::{
+        is_stale_response, verify_response, TransactionIdGenerator,
+        DEFAULT_MAX_STALE_TRANSACTION_AGE,
+    },
+};
+
+/// Modbus TCP client over UDP
+///
+/// Each datagram contains a single ADU. Responses are matched with
+/// requests by their transaction identifier.
+#[derive(Debug)]
+pub(crate) struct Client {
+    socket: Option<UdpSocket>,
+    codec: codec::tcp::ClientCodec,
+    /// Reused for encoding requests and decoding responses.
+    buf: BytesMut,
+    transaction_id_generator: TransactionIdGenerator,
+    unit_id: UnitId,
+    stale_responses: u64,
+}
+
+impl Client {
+    /// The socket is supposed to be connected to the server.
+    pub(crate) fn new(socket: UdpSocket, slave: Slave) -> Self {
+        Self {
+            socket: Some(socket),
+            codec: codec::tcp::ClientCodec::new(),
+            buf: BytesMut::with_capacity(MAX_ADU_LEN),
+            transaction_id_generator: TransactionIdGenerator::new(),
+            unit_id: slave.into(),
+            stale_responses: 0,
+        }
+    }
+
+    pub(crate) async fn call(
+        &mut self,
+        req: Request<'_>,
+        register_views: bool,
+    ) -> Result<DecodedResponse> {
+        log::debug!("Call {req:?}");
+
+        let req_function_code = req.function_code();
+        let implicit_response = implicit_response(&req, req.expects_response())?;
+        let req_hdr = Header {
+            transaction_id: self.transaction_id_generator.next(),
+            unit_id: self.unit_id,
+        };
+        #[cfg(feature = "tracing")]
+        crate::spans::record_request_header(req_hdr.unit_id, Some(req_hdr.transaction_id));
+
+        let Some(socket) = &self.socket else {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "disconnected").into());
+        };
+
+        // Datagrams that have already been received could only be stale.
+        let mut datagram = [0; MAX_ADU_LEN];
+        while let Ok(len) = socket.try_recv(&mut datagram) {
+            if let Some(res_adu) = decode_response(&mut self.codec, &mut self.buf, &datagram[..len])
+            {
+                log::debug!(
+                    "Discarding stale response {res_hdr:?}",
+                    res_hdr = res_adu.hdr
+                );
+                self.stale_responses += 1;
+            }
+        }
+
+        self.buf.clear();
+        self.codec.register_views = register_views;
+        self.codec.encode(
+            RequestAdu {
+                hdr: req_hdr,
+                pdu: req.into(),
+            },
+            &mut self.buf,
+        )?;
+        socket.send(&self.buf).await?;
+
+        if let Some(response) = implicit_response {
+            log::debug!(
+                "No response expected for request {req_hdr:?} (function = {req_function_code})"
+            );
+            return Ok(Ok(response.into()));
+        }
+
+        let res_adu = loop {
+            let len = socket.recv(&mut datagram).await?;
+            let Some(res_adu) = decode_response(&mut self.codec, &mut self.buf, &datagram[..len])
+            else {
+                continue;
+            };
+            if is_stale_response(req_hdr, res_adu.hdr, DEFAULT_MAX_STALE_TRANSACTION_AGE) {
+                log::debug!(
+                    "Discarding stale response {res_hdr:?} for request {req_hdr:?}",
+                    res_hdr = res_adu.hdr
+                );
+                self.stale_responses += 1;
+                continue;
+            }
+            break res_adu;
+        };
+        verify_response(req_hdr, req_function_code, res_adu)
+    }
+
+    const fn stale_responses(&self) -> u64 {
+        self.stale_responses
+    }
+
+    fn disconnect(&mut self) {
+        // Dropping the socket closes it.
+        self.socket = None;
+    }
+}
+
+/// Decodes a datagram that is supposed to contain a single response ADU.
+fn decode_response(
+    codec: &mut codec::tcp::ClientCodec,
+    buf: &mut BytesMut,
+    datagram: &[u8],
+) -> Option<ResponseAdu<DecodedResponse>> {
+    buf.clear();
+    buf.extend_from_slice(datagram);
+    match codec.decode(buf) {
+        Ok(Some(res_adu)) if buf.is_empty() => Some(res_adu),
+        Ok(_) => {
+            log::debug!("Discarding datagram with an incomplete or oversized ADU");
+            None
+        }
+        Err(err) => {
+            log::debug!("Discarding invalid datagram: {err}");
+            None
+        }
+    }
+}
+
+impl SlaveContext for Client {
+    fn set_slave(&mut self, slave: Slave) {
+        self.unit_id = slave.into();
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::client::Client for Client {
+    async fn call(&mut self, req: Request<'_>) -> Result<Response> {
+        let result = self.call(req, false).await?;
+        Ok(result.map(DecodedResponse::into_response))
+    }
+
+    async fn call_with_register_views(&mut self, req: Request<'_>) -> Result<DecodedResponse> {
+        self.call(req, true).await
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        self.disconnect();
+        Ok(())
+    }
+
+    fn stale_responses(&self) -> u64 {
+        self.stale_responses()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use super::*;
+
+    async fn connected_sockets() -> (UdpSocket, UdpSocket) {
+        let localhost = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let client = UdpSocket::bind(localhost).await.unwrap();
+        let server = UdpSocket::bind(localhost).await.unwrap();
+        client.connect(server.local_addr().unwrap()).await.unwrap();
+        server.connect(client.local_addr().unwrap()).await.unwrap();
+        (client, server)
+    }
+
+    const fn response(transaction_id: u8, value: u8) -> [u8; 11] {
+        [0, transaction_id, 0, 0, 0, 5, 1, 0x03, 2, 0, value]
+    }
+
+    #[tokio::test]
+    async fn match_responses_by_transaction_id() {
+        let (socket, server) = connected_sockets().await;
+        let mut client = Client::new(socket, Slave(1));
+
+        // The caller gave up waiting for the response to the first request.
+        client.transaction_id_generator.next();
+
+        let respond = async {
+            let mut req = [0; 32];
+            let len = server.recv(&mut req).await.unwrap();
+            assert_eq!(req[..len], [0, 1, 0, 0, 0, 6, 1, 0x03, 0, 0, 0, 1]);
+            // Late response to the first request
+            server.send(&response(0, 1)).await.unwrap();
+            // Invalid datagrams
+            server.send(&[0, 1, 0, 0]).await.unwrap();
+            server
+                .send(&[response(1, 2), response(1, 2)].concat())
+                .await
+                .unwrap();
+            server.send(&response(1, 3)).await.unwrap();
+        };
+        let (rsp, ()) = tokio::join!(
+            client.call(Request::ReadHoldingRegisters(0, 1), false),
+            respond
+        );
+        assert_eq!(
+            rsp.unwrap(),
+            Ok(Response::ReadHoldingRegisters(vec![3]).into())
+        );
+        assert_eq!(client.stale_responses(), 1);
+    }
+
+    #[tokio::test]
+    async fn only_count_responses_as_stale() {
+        let (socket, server) = connected_sockets().await;
+        let mut client = Client::new(socket, Slave(1));
+
+        // Received before sending the next request
+        server.send(&response(7, 1)).await.unwrap();
+        server.send(&[0, 1, 0, 0]).await.unwrap();
+        server.send(b"garbage").await.unwrap();
+        // Wait until all datagrams have arrived.
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let respond = async {
+            let mut req = [0; 32];
+            server.recv(&mut req).await.unwrap();
+            server.send(&response(0, 2)).await.unwrap();
+        };
+        let (rsp, ()) = tokio::join!(
+            client.call(Request::ReadHoldingRegisters(0, 1), false),
+            respond
+        );
+        assert_eq!(
+            rsp.unwrap(),
+            Ok(Response::ReadHoldingRegisters(vec![2]).into())
+        );
+        assert_eq!(client.stale_responses(), 1);
+    }
+
+    #[tokio::test]
+    async fn call_after_disconnect() {
+        let (socket, _server) = connected_sockets().await;
+        let mut client = Client::new(socket, Slave(1));
+        client.disconnect();
+        let err = client
+            .call(Request::ReadHoldingRegisters(0, 1), false)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, crate::Error::Transport(err) if err.kind() == io::ErrorKind::NotConnected)
+        );
+    }
+}
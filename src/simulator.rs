@@ -0,0 +1,483 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Simulation of Modbus devices
+//!
+//! A [`Simulator`] is a server [`Service`] that answers requests from a
+//! [`DataStore`] with registers that change on their own, see [`Behavior`],
+//! optional response delays, and address regions that fail with
+//! exceptions. Like any other service it is served over TCP with
+//! `server::tcp`, over a serial line with `server::rtu`, or as RTU over
+//! TCP with `server::rtu_over_tcp`.
+//!
+//! # Example
+//!
+//! ```
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! use std::time::Duration;
+//!
+//! use tokio_modbus::{
+//!     prelude::*,
+//!     server::{DataStore, Service as _, Table},
+//!     simulator::{Behavior, Simulator},
+//! };
+//!
+//! let simulator = Simulator::new(DataStore::new().with_input_registers(0..=99))
+//!     .with_input_register(0, Behavior::Counter { start: 10, step: 5 })
+//!     .with_input_register(1, Behavior::Script(vec![1, 2, 3]))
+//!     .with_error_region(Table::InputRegisters, 50..=99, ExceptionCode::ServerDeviceBusy)
+//!     .with_delay(Duration::from_millis(10));
+//!
+//! let response = simulator.call(Request::ReadInputRegisters(0, 2)).await;
+//! assert_eq!(response, Ok(Response::ReadInputRegisters(vec![10, 1])));
+//! let response = simulator.call(Request::ReadInputRegisters(0, 2)).await;
+//! assert_eq!(response, Ok(Response::ReadInputRegisters(vec![15, 2])));
+//!
+//! let response = simulator.call(Request::ReadInputRegisters(40, 20)).await;
+//! assert_eq!(response, Err(ExceptionCode::ServerDeviceBusy));
+//! # }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    ops::RangeInclusive,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::future;
+
+use crate::{
+    mutex::lock,
+    server::{DataStore, Service, Table},
+    Address, ExceptionCode, FunctionCode, Request, Response,
+};
+
+/// The seed of the random walks unless configured otherwise.
+const DEFAULT_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+/// How the value of a register changes.
+///
+/// Values change after each read of the register, i.e. the first read
+/// returns the initial value. Registers are also writable by requests
+/// if the table is writable. Changes continue from the written value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Behavior {
+    /// A value that is only changed by writes.
+    Constant(u16),
+
+    /// A value that increases by `step` and wraps around.
+    Counter {
+        /// The initial value.
+        start: u16,
+        /// The increment after each read.
+        step: u16,
+    },
+
+    /// A value that changes randomly by up to `step` in both directions
+    /// within the bounds.
+    RandomWalk {
+        /// The initial value.
+        start: u16,
+        /// The lower bound.
+        min: u16,
+        /// The upper bound.
+        max: u16,
+        /// The maximum change after each read.
+        step: u16,
+    },
+
+    /// A sequence of values that is repeated.
+    ///
+    /// An empty sequence is a constant zero.
+    Script(Vec<u16>),
+}
+
+impl Behavior {
+    fn start(&self) -> u16 {
+        match self {
+            Self::Constant(value) => *value,
+            Self::Counter { start, .. } | Self::RandomWalk { start, .. } => *start,
+            Self::Script(values) => values.first().copied().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Register {
+    behavior: Behavior,
+    /// The number of reads.
+    reads: usize,
+}
+
+#[derive(Debug)]
+struct ErrorRegion {
+    table: Table,
+    addresses: RangeInclusive<Address>,
+    exception: ExceptionCode,
+}
+
+#[derive(Debug)]
+struct State {
+    registers: BTreeMap<(Table, Address), Register>,
+    error_regions: Vec<ErrorRegion>,
+    /// The state of the xorshift generator for random walks.
+    rng: u64,
+}
+
+impl State {
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+/// A simulated Modbus device.
+///
+/// Serves the standard data access functions from a [`DataStore`], which
+/// defines the available addresses and holds the current values. Requests
+/// are processed as follows:
+///
+/// 1. Requests that access any address of an error region are answered
+///    with the exception of the region.
+/// 2. All other requests are answered by the store.
+/// 3. The registers with a [`Behavior`] that have been read are changed.
+/// 4. The response is delayed if configured.
+///
+/// The state is shared by all clones.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    store: DataStore,
+    shared: Arc<Mutex<State>>,
+    delay: Option<Duration>,
+}
+
+impl Simulator {
+    /// Simulate a device with the addresses and the values of `store`.
+    #[must_use]
+    pub fn new(store: DataStore) -> Self {
+        Self {
+            store,
+            shared: Arc::new(Mutex::new(State {
+                registers: BTreeMap::new(),
+                error_regions: Vec::new(),
+                rng: DEFAULT_SEED,
+            })),
+            delay: None,
+        }
+    }
+
+    /// Change an input register according to `behavior`.
+    ///
+    /// Addresses that are not provided by the store are ignored.
+    #[must_use]
+    pub fn with_input_register(self, addr: Address, behavior: Behavior) -> Self {
+        self.with_register(Table::InputRegisters, addr, behavior)
+    }
+
+    /// Change a holding register according to `behavior`.
+    ///
+    /// Addresses that are not provided by the store are ignored.
+    #[must_use]
+    pub fn with_holding_register(self, addr: Address, behavior: Behavior) -> Self {
+        self.with_register(Table::HoldingRegisters, addr, behavior)
+    }
+
+    /// Answer all requests that access any of the `addresses` of `table`
+    /// with `exception`.
+    #[must_use]
+    pub fn with_error_region(
+        self,
+        table: Table,
+        addresses: RangeInclusive<Address>,
+        exception: ExceptionCode,
+    ) -> Self {
+        lock(&self.shared).error_regions.push(ErrorRegion {
+            table,
+            addresses,
+            exception,
+        });
+        self
+    }
+
+    /// Delay all responses.
+    #[must_use]
+    pub const fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Seed the random walks for reproducible values.
+    ///
+    /// A zero seed is replaced by the default seed.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> Self {
+        lock(&self.shared).rng = if seed == 0 { DEFAULT_SEED } else { seed };
+        self
+    }
+
+    /// The store with the current values.
+    #[must_use]
+    pub const fn store(&self) -> &DataStore {
+        &self.store
+    }
+
+    fn with_register(self, table: Table, addr: Address, behavior: Behavior) -> Self {
+        let start = behavior.start();
+        // Addresses that are not provided are ignored as documented.
+        self.write(table, addr, start).ok();
+        lock(&self.shared)
+            .registers
+            .insert((table, addr), Register { behavior, reads: 0 });
+        self
+    }
+
+    fn write(&self, table: Table, addr: Address, value: u16) -> Result<(), ExceptionCode> {
+        if table == Table::InputRegisters {
+            self.store.set_input_registers(addr, &[value])
+        } else {
+            self.store.set_holding_registers(addr, &[value])
+        }
+    }
+
+    fn read(&self, table: Table, addr: Address) -> Result<u16, ExceptionCode> {
+        let values = if table == Table::InputRegisters {
+            self.store.input_registers(addr, 1)?
+        } else {
+            self.store.holding_registers(addr, 1)?
+        };
+        Ok(values[0])
+    }
+
+    fn process(&self, request: Request<'static>) -> Result<Response, ExceptionCode> {
+        let accesses = accesses(&request);
+        {
+            let state = lock(&self.shared);
+            for (table, addr, cnt, _) in &accesses {
+                let Some(last) = last_address(*addr, *cnt) else {
+                    continue;
+                };
+                if let Some(region) = state.error_regions.iter().find(|region| {
+                    region.table == *table
+                        && *region.addresses.start() <= last
+                        && *addr <= *region.addresses.end()
+                }) {
+                    return Err(region.exception);
+                }
+            }
+        }
+        let response = self.store.call(request).into_inner()?;
+        for (table, addr, cnt, read) in accesses {
+            if read && matches!(table, Table::InputRegisters | Table::HoldingRegisters) {
+                self.advance(table, addr, cnt);
+            }
+        }
+        Ok(response)
+    }
+
+    /// Change all registers with a behavior that have been read.
+    fn advance(&self, table: Table, addr: Address, cnt: usize) {
+        let Some(last) = last_address(addr, cnt) else {
+            return;
+        };
+        let mut state = lock(&self.shared);
+        let addresses: Vec<_> = state
+            .registers
+            .range((table, addr)..=(table, last))
+            .map(|((_, addr), _)| *addr)
+            .collect();
+        for addr in addresses {
+            let Ok(value) = self.read(table, addr) else {
+                continue;
+            };
+            let random = state.next_random();
+            let register = state
+                .registers
+                .get_mut(&(table, addr))
+                .expect("register with behavior");
+            register.reads = register.reads.wrapping_add(1);
+            let value = match &register.behavior {
+                Behavior::Constant(_) => continue,
+                Behavior::Counter { step, .. } => value.wrapping_add(*step),
+                Behavior::RandomWalk { min, max, step, .. } => {
+                    random_walk(value, *min, *max, *step, random)
+                }
+                Behavior::Script(values) => {
+                    if values.is_empty() {
+                        continue;
+                    }
+                    values[register.reads % values.len()]
+                }
+            };
+            self.write(table, addr, value).ok();
+        }
+    }
+}
+
+fn random_walk(value: u16, min: u16, max: u16, step: u16, random: u64) -> u16 {
+    let range = 2 * u64::from(step) + 1;
+    #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+    let delta = (random % range) as i64 - i64::from(step);
+    let value = (i64::from(value) + delta).clamp(i64::from(min), i64::from(max.max(min)));
+    u16::try_from(value).unwrap_or(min)
+}
+
+/// The last address of a non-empty range.
+fn last_address(addr: Address, cnt: usize) -> Option<Address> {
+    let offset = Address::try_from(cnt.checked_sub(1)?).ok()?;
+    addr.checked_add(offset)
+}
+
+/// The tables and address ranges that are accessed by a request and
+/// whether they are read.
+fn accesses(request: &Request<'_>) -> Vec<(Table, Address, usize, bool)> {
+    use Request::*;
+
+    match request {
+        ReadCoils(addr, cnt) => vec![(Table::Coils, *addr, usize::from(*cnt), true)],
+        ReadDiscreteInputs(addr, cnt) => {
+            vec![(Table::DiscreteInputs, *addr, usize::from(*cnt), true)]
+        }
+        ReadInputRegisters(addr, cnt) => {
+            vec![(Table::InputRegisters, *addr, usize::from(*cnt), true)]
+        }
+        ReadHoldingRegisters(addr, cnt) => {
+            vec![(Table::HoldingRegisters, *addr, usize::from(*cnt), true)]
+        }
+        WriteSingleCoil(addr, _) => vec![(Table::Coils, *addr, 1, false)],
+        WriteMultipleCoils(addr, coils) => vec![(Table::Coils, *addr, coils.len(), false)],
+        WriteSingleRegister(addr, _) | MaskWriteRegister(addr, _, _) => {
+            vec![(Table::HoldingRegisters, *addr, 1, false)]
+        }
+        WriteMultipleRegisters(addr, words) => {
+            vec![(Table::HoldingRegisters, *addr, words.len(), false)]
+        }
+        ReadWriteMultipleRegisters(read_addr, read_cnt, write_addr, words) => vec![
+            (Table::HoldingRegisters, *write_addr, words.len(), false),
+            (
+                Table::HoldingRegisters,
+                *read_addr,
+                usize::from(*read_cnt),
+                true,
+            ),
+        ],
+        _ => vec![],
+    }
+}
+
+impl Service for Simulator {
+    type Request = Request<'static>;
+    type Response = Response;
+    type Exception = ExceptionCode;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, ExceptionCode>> + Send>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let result = self.process(req);
+        match self.delay {
+            Some(delay) => Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                result
+            }),
+            None => Box::pin(future::ready(result)),
+        }
+    }
+
+    fn supported_functions(&self) -> Option<&[FunctionCode]> {
+        self.store.supported_functions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_input_registers(simulator: &Simulator, addr: Address, cnt: u16) -> Vec<u16> {
+        match simulator.process(Request::ReadInputRegisters(addr, cnt)) {
+            Ok(Response::ReadInputRegisters(words)) => words,
+            response => panic!("unexpected response: {response:?}"),
+        }
+    }
+
+    #[test]
+    fn simulate_behaviors() {
+        let simulator = Simulator::new(
+            DataStore::new()
+                .with_input_registers(0..=9)
+                .with_holding_registers(0..=9),
+        )
+        .with_input_register(0, Behavior::Constant(7))
+        .with_input_register(
+            1,
+            Behavior::Counter {
+                start: 0xFFFE,
+                step: 1,
+            },
+        )
+        .with_input_register(
+            2,
+            Behavior::RandomWalk {
+                start: 100,
+                min: 90,
+                max: 110,
+                step: 5,
+            },
+        )
+        .with_input_register(3, Behavior::Script(vec![1, 2]))
+        .with_holding_register(0, Behavior::Counter { start: 0, step: 10 })
+        .with_seed(42);
+
+        assert_eq!(read_input_registers(&simulator, 0, 4), [7, 0xFFFE, 100, 1]);
+        let words = read_input_registers(&simulator, 0, 4);
+        assert_eq!(words[..2], [7, 0xFFFF]);
+        assert!((95..=105).contains(&words[2]));
+        assert_eq!(words[3], 2);
+        let words = read_input_registers(&simulator, 0, 4);
+        assert_eq!(words[1], 0);
+        assert!((90..=110).contains(&words[2]));
+        assert_eq!(words[3], 1);
+
+        // Not read, not changed.
+        assert_eq!(
+            simulator.process(Request::WriteSingleRegister(0, 5)),
+            Ok(Response::WriteSingleRegister(0, 5))
+        );
+        assert_eq!(
+            simulator.process(Request::ReadHoldingRegisters(0, 1)),
+            Ok(Response::ReadHoldingRegisters(vec![5]))
+        );
+        assert_eq!(
+            simulator.process(Request::ReadHoldingRegisters(0, 1)),
+            Ok(Response::ReadHoldingRegisters(vec![15]))
+        );
+    }
+
+    #[test]
+    fn answer_error_regions() {
+        let simulator = Simulator::new(DataStore::new().with_coils(0..=99)).with_error_region(
+            Table::Coils,
+            10..=19,
+            ExceptionCode::ServerDeviceFailure,
+        );
+        assert_eq!(
+            simulator.process(Request::ReadCoils(0, 10)),
+            Ok(Response::ReadCoils(vec![false; 10]))
+        );
+        assert_eq!(
+            simulator.process(Request::ReadCoils(5, 10)),
+            Err(ExceptionCode::ServerDeviceFailure)
+        );
+        assert_eq!(
+            simulator.process(Request::WriteSingleCoil(19, true)),
+            Err(ExceptionCode::ServerDeviceFailure)
+        );
+        assert_eq!(
+            simulator.process(Request::ReadCoils(20, 1)),
+            Ok(Response::ReadCoils(vec![false]))
+        );
+    }
+}
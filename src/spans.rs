@@ -0,0 +1,222 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Tracing spans of client calls and server requests
+//!
+//! Fields that are only known after the request has been processed are
+//! declared as empty and recorded when they become available.
+
+use std::time::Duration;
+
+use tracing::{field::Empty, Span};
+
+use crate::{FunctionCode, Response, Result, SlaveId};
+
+/// The span of a client call, including all retries.
+pub(crate) fn client_call(
+    function: FunctionCode,
+    unit: Option<SlaveId>,
+    label: Option<&str>,
+) -> Span {
+    tracing::debug_span!(
+        "modbus_call",
+        function = function.value(),
+        unit,
+        transaction_id = Empty,
+        label,
+        duration_us = Empty,
+        retries = Empty,
+        outcome = Empty,
+        exception = Empty,
+        error = Empty,
+    )
+}
+
+/// Record the header of a request that is sent within the current span.
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+pub(crate) fn record_request_header(unit: SlaveId, transaction_id: Option<u16>) {
+    let span = Span::current();
+    span.record("unit", unit);
+    if let Some(transaction_id) = transaction_id {
+        span.record("transaction_id", transaction_id);
+    }
+}
+
+pub(crate) fn record_call_outcome<T>(
+    span: &Span,
+    result: &Result<T>,
+    duration: Duration,
+    retries: usize,
+) {
+    record_duration(span, duration);
+    span.record("retries", retries);
+    match result {
+        Ok(Ok(_)) => {
+            span.record("outcome", "ok");
+        }
+        Ok(Err(exception)) => {
+            span.record("outcome", "exception");
+            span.record("exception", tracing::field::display(exception));
+        }
+        Err(err) => {
+            span.record("outcome", "error");
+            span.record("error", tracing::field::display(err));
+        }
+    }
+}
+
+/// The span of a request that is processed by a server.
+#[cfg(feature = "server")]
+pub(crate) fn server_request(function: FunctionCode, hdr: &dyn std::fmt::Debug) -> Span {
+    tracing::debug_span!(
+        "modbus_request",
+        function = function.value(),
+        header = ?hdr,
+        duration_us = Empty,
+        outcome = Empty,
+        exception = Empty,
+    )
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn record_request_outcome(
+    span: &Span,
+    result: &std::result::Result<Option<Response>, crate::ExceptionCode>,
+    duration: Duration,
+) {
+    record_duration(span, duration);
+    match result {
+        Ok(Some(_)) => {
+            span.record("outcome", "ok");
+        }
+        Ok(None) => {
+            span.record("outcome", "no_response");
+        }
+        Err(exception) => {
+            span.record("outcome", "exception");
+            span.record("exception", tracing::field::display(exception));
+        }
+    }
+}
+
+fn record_duration(span: &Span, duration: Duration) {
+    span.record(
+        "duration_us",
+        u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fmt, io,
+        sync::{Arc, Mutex},
+    };
+
+    use async_trait::async_trait;
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    use crate::{
+        client::{Client, Context, Reader as _},
+        slave::{Slave, SlaveContext},
+        ExceptionCode, Request,
+    };
+
+    use super::*;
+
+    /// Collects the fields of all spans.
+    #[derive(Debug, Clone, Default)]
+    struct Recorder {
+        fields: Arc<Mutex<HashMap<String, String>>>,
+    }
+
+    impl Visit for &Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.fields
+                .lock()
+                .unwrap()
+                .insert(field.name().to_owned(), format!("{value:?}"));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            span.record(&mut &*self);
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut &*self);
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[derive(Debug)]
+    struct ClientMock;
+
+    #[async_trait]
+    impl Client for ClientMock {
+        async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+            match request {
+                Request::ReadHoldingRegisters(_, 1) => {
+                    Ok(Ok(Response::ReadHoldingRegisters(vec![1])))
+                }
+                _ => Ok(Err(ExceptionCode::IllegalDataAddress)),
+            }
+        }
+
+        async fn disconnect(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SlaveContext for ClientMock {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[tokio::test]
+    async fn record_client_calls() {
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+        let client: Box<dyn Client> = Box::new(ClientMock);
+        let mut ctx = Context::from(client);
+        ctx.set_slave(Slave(7));
+        ctx.set_label("boiler");
+
+        ctx.read_holding_registers(0, 1).await.unwrap().unwrap();
+        {
+            let fields = recorder.fields.lock().unwrap();
+            assert_eq!(fields["function"], "3");
+            assert_eq!(fields["unit"], "7");
+            assert_eq!(fields["label"], "\"boiler\"");
+            assert_eq!(fields["retries"], "0");
+            assert_eq!(fields["outcome"], "\"ok\"");
+            assert!(fields.contains_key("duration_us"));
+        }
+
+        ctx.read_input_registers(0, 1).await.unwrap().unwrap_err();
+        let fields = recorder.fields.lock().unwrap();
+        assert_eq!(fields["function"], "4");
+        assert_eq!(fields["outcome"], "\"exception\"");
+        assert_eq!(
+            fields["exception"],
+            ExceptionCode::IllegalDataAddress.to_string()
+        );
+    }
+}
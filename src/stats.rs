@@ -0,0 +1,199 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Accounting of connections
+
+use std::fmt;
+
+/// The traffic of a single connection.
+///
+/// Bytes are counted on the transport, i.e. including the framing and
+/// any data that could not be decoded. Errors include both transport
+/// and protocol errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// The number of bytes that have been written
+    pub bytes_sent: u64,
+
+    /// The number of bytes that have been read
+    pub bytes_received: u64,
+
+    /// The number of frames that have been sent
+    pub frames_sent: u64,
+
+    /// The number of frames that have been received and decoded
+    pub frames_received: u64,
+
+    /// The number of failed requests or responses
+    pub errors: u64,
+}
+
+impl fmt::Display for ConnectionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            bytes_sent,
+            bytes_received,
+            frames_sent,
+            frames_received,
+            errors,
+        } = self;
+        write!(
+            f,
+            "sent {frames_sent} frame(s) with {bytes_sent} byte(s), \
+             received {frames_received} frame(s) with {bytes_received} byte(s), \
+             {errors} error(s)"
+        )
+    }
+}
+
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+pub(crate) use self::counting::{ConnectionCounters, CountingIo};
+
+#[cfg(any(feature = "rtu", feature = "tcp"))]
+mod counting {
+    use std::{
+        io,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+    };
+
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::ConnectionStats;
+
+    /// Counters of a connection that are updated concurrently.
+    #[derive(Debug, Default)]
+    pub(crate) struct ConnectionCounters {
+        bytes_sent: AtomicU64,
+        bytes_received: AtomicU64,
+        frames_sent: AtomicU64,
+        frames_received: AtomicU64,
+        errors: AtomicU64,
+    }
+
+    impl ConnectionCounters {
+        pub(crate) fn frame_sent(&self) {
+            self.frames_sent.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub(crate) fn frame_received(&self) {
+            self.frames_received.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub(crate) fn error(&self) {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        pub(crate) fn stats(&self) -> ConnectionStats {
+            ConnectionStats {
+                bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+                bytes_received: self.bytes_received.load(Ordering::Relaxed),
+                frames_sent: self.frames_sent.load(Ordering::Relaxed),
+                frames_received: self.frames_received.load(Ordering::Relaxed),
+                errors: self.errors.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// A transport that counts the bytes that are read and written.
+    #[derive(Debug)]
+    pub(crate) struct CountingIo<T> {
+        inner: T,
+        counters: Arc<ConnectionCounters>,
+    }
+
+    impl<T> CountingIo<T> {
+        pub(crate) fn new(inner: T, counters: Arc<ConnectionCounters>) -> Self {
+            Self { inner, counters }
+        }
+
+        #[cfg(feature = "tcp-server")]
+        pub(crate) fn counters(&self) -> &Arc<ConnectionCounters> {
+            &self.counters
+        }
+    }
+
+    impl<T> AsyncRead for CountingIo<T>
+    where
+        T: AsyncRead + Unpin,
+    {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            let filled = buf.filled().len();
+            let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+            let read = buf.filled().len() - filled;
+            this.counters
+                .bytes_received
+                .fetch_add(read as u64, Ordering::Relaxed);
+            poll
+        }
+    }
+
+    impl<T> AsyncWrite for CountingIo<T>
+    where
+        T: AsyncWrite + Unpin,
+    {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+            if let Poll::Ready(Ok(written)) = poll {
+                this.counters
+                    .bytes_sent
+                    .fetch_add(written as u64, Ordering::Relaxed);
+            }
+            poll
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+        use super::*;
+
+        #[tokio::test]
+        async fn count_bytes() {
+            let counters = Arc::new(ConnectionCounters::default());
+            let (transport, mut peer) = tokio::io::duplex(1024);
+            let mut transport = CountingIo::new(transport, Arc::clone(&counters));
+
+            transport.write_all(&[1, 2, 3]).await.unwrap();
+            peer.write_all(&[4, 5]).await.unwrap();
+            let mut buf = [0; 2];
+            transport.read_exact(&mut buf).await.unwrap();
+            counters.frame_sent();
+            counters.frame_received();
+
+            assert_eq!(
+                counters.stats(),
+                ConnectionStats {
+                    bytes_sent: 3,
+                    bytes_received: 2,
+                    frames_sent: 1,
+                    frames_received: 1,
+                    errors: 0,
+                }
+            );
+        }
+    }
+}
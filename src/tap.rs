@@ -0,0 +1,334 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Tapping of raw frames
+//!
+//! A [`FrameTap`] receives a copy of every frame that is sent or
+//! received by a client or server, both the raw bytes on the wire
+//! and the decoded PDU. Useful for building protocol analyzers or for
+//! debugging noisy serial lines.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "tcp")]
+//! # async fn tap() -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio_modbus::{client::tcp, tap::Frame};
+//!
+//! let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+//! let ctx = tcp::Builder::with_host("192.168.0.222", 502)
+//!     .tap(move |frame: &Frame| {
+//!         // The receiver might have been dropped.
+//!         let _ = tx.send(frame.clone());
+//!     })
+//!     .connect()
+//!     .await?;
+//! while let Some(frame) = rx.recv().await {
+//!     println!("{:?} {:02X?}: {:?}", frame.direction, &frame.bytes[..], frame.pdu);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fmt, io, sync::Arc, time::SystemTime};
+
+use crate::{
+    bytes::{Bytes, BytesMut},
+    ExceptionResponse, Request, Response, Slave,
+};
+
+/// The direction of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The frame has been encoded for sending.
+    Sent,
+
+    /// The frame has been received and decoded.
+    Received,
+}
+
+/// The decoded PDU of a frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pdu {
+    /// A request of a client.
+    Request(Request<'static>),
+
+    /// A response or an exception response of a server.
+    Response(Result<Response, ExceptionResponse>),
+}
+
+/// A tapped frame.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Sent or received.
+    pub direction: Direction,
+
+    /// The time when the frame has been encoded or decoded.
+    pub time: SystemTime,
+
+    /// The raw bytes on the wire.
+    ///
+    /// Received bytes might also include bytes that have been discarded
+    /// right before the frame, e.g. noise on a serial line or frames with
+    /// an invalid checksum.
+    pub bytes: Bytes,
+
+    /// The slave or unit ID of the frame.
+    pub slave: Slave,
+
+    /// The transaction ID of a Modbus TCP frame.
+    pub transaction_id: Option<u16>,
+
+    /// The decoded PDU.
+    pub pdu: Pdu,
+}
+
+/// Receives a copy of every frame.
+///
+/// Invoked synchronously while encoding and decoding, i.e. the
+/// implementation should return quickly, e.g. by sending the frame
+/// through a channel.
+pub trait FrameTap: Send + Sync {
+    /// Receive a frame.
+    fn tap(&self, frame: &Frame);
+}
+
+impl<F> FrameTap for F
+where
+    F: Fn(&Frame) + Send + Sync,
+{
+    fn tap(&self, frame: &Frame) {
+        self(frame);
+    }
+}
+
+/// A shared [`FrameTap`] that is used by codecs.
+#[derive(Clone)]
+pub(crate) struct Tap(Arc<dyn FrameTap>);
+
+impl fmt::Debug for Tap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tap").finish_non_exhaustive()
+    }
+}
+
+impl Tap {
+    pub(crate) fn new(tap: impl FrameTap + 'static) -> Self {
+        Self(Arc::new(tap))
+    }
+
+    /// Pass a frame that has not been encoded or decoded by a codec.
+    #[cfg(feature = "tcp-server")]
+    pub(crate) fn tap_frame(&self, frame: &Frame) {
+        self.0.tap(frame);
+    }
+
+    fn tap(&self, direction: Direction, bytes: &[u8], adu: &impl TappedAdu) {
+        let (slave, transaction_id) = adu.header();
+        self.0.tap(&Frame {
+            direction,
+            time: SystemTime::now(),
+            bytes: Bytes::copy_from_slice(bytes),
+            slave,
+            transaction_id,
+            pdu: adu.pdu(),
+        });
+    }
+}
+
+/// ADUs that could be tapped.
+pub(crate) trait TappedAdu {
+    fn header(&self) -> (Slave, Option<u16>);
+
+    fn pdu(&self) -> Pdu;
+}
+
+/// Encode an ADU and pass the encoded bytes to the tap, if any.
+pub(crate) fn encode<A: TappedAdu>(
+    tap: Option<&Tap>,
+    adu: A,
+    buf: &mut BytesMut,
+    encode: impl FnOnce(A, &mut BytesMut) -> io::Result<()>,
+) -> io::Result<()> {
+    let Some(tap) = tap else {
+        return encode(adu, buf);
+    };
+    let start = buf.len();
+    let tapped = (adu.header(), adu.pdu());
+    encode(adu, buf)?;
+    tap.tap(Direction::Sent, &buf[start..], &tapped);
+    Ok(())
+}
+
+/// Decode an ADU and pass the consumed bytes to the tap, if any.
+pub(crate) fn decode<A: TappedAdu>(
+    tap: Option<&Tap>,
+    buf: &mut BytesMut,
+    decode: impl FnOnce(&mut BytesMut) -> io::Result<Option<A>>,
+) -> io::Result<Option<A>> {
+    let Some(tap) = tap else {
+        return decode(buf);
+    };
+    // The consumed bytes are not available after decoding.
+    let received = buf.clone();
+    let adu = decode(buf)?;
+    if let Some(adu) = &adu {
+        let consumed = received.len() - buf.len();
+        tap.tap(Direction::Received, &received[..consumed], adu);
+    }
+    Ok(adu)
+}
+
+impl TappedAdu for ((Slave, Option<u16>), Pdu) {
+    fn header(&self) -> (Slave, Option<u16>) {
+        self.0
+    }
+
+    fn pdu(&self) -> Pdu {
+        self.1.clone()
+    }
+}
+
+#[cfg(feature = "tcp")]
+mod tcp {
+    use crate::frame::{
+        tcp::{RequestAdu, ResponseAdu},
+        DecodedResponse,
+    };
+
+    use super::*;
+
+    impl TappedAdu for RequestAdu<'_> {
+        fn header(&self) -> (Slave, Option<u16>) {
+            (Slave(self.hdr.unit_id), Some(self.hdr.transaction_id))
+        }
+
+        fn pdu(&self) -> Pdu {
+            Pdu::Request(self.pdu.0.clone().into_owned())
+        }
+    }
+
+    impl TappedAdu for ResponseAdu {
+        fn header(&self) -> (Slave, Option<u16>) {
+            (Slave(self.hdr.unit_id), Some(self.hdr.transaction_id))
+        }
+
+        fn pdu(&self) -> Pdu {
+            Pdu::Response(self.pdu.0.clone())
+        }
+    }
+
+    impl TappedAdu for ResponseAdu<DecodedResponse> {
+        fn header(&self) -> (Slave, Option<u16>) {
+            (Slave(self.hdr.unit_id), Some(self.hdr.transaction_id))
+        }
+
+        fn pdu(&self) -> Pdu {
+            Pdu::Response(self.pdu.0.clone().map(DecodedResponse::into_response))
+        }
+    }
+}
+
+#[cfg(feature = "rtu")]
+mod rtu {
+    use crate::frame::{
+        rtu::{RequestAdu, ResponseAdu},
+        DecodedResponse,
+    };
+
+    use super::*;
+
+    impl TappedAdu for RequestAdu<'_> {
+        fn header(&self) -> (Slave, Option<u16>) {
+            (Slave(self.hdr.slave_id), None)
+        }
+
+        fn pdu(&self) -> Pdu {
+            Pdu::Request(self.pdu.0.clone().into_owned())
+        }
+    }
+
+    impl TappedAdu for ResponseAdu {
+        fn header(&self) -> (Slave, Option<u16>) {
+            (Slave(self.hdr.slave_id), None)
+        }
+
+        fn pdu(&self) -> Pdu {
+            Pdu::Response(self.pdu.0.clone())
+        }
+    }
+
+    impl TappedAdu for ResponseAdu<DecodedResponse> {
+        fn header(&self) -> (Slave, Option<u16>) {
+            (Slave(self.hdr.slave_id), None)
+        }
+
+        fn pdu(&self) -> Pdu {
+            Pdu::Response(self.pdu.0.clone().map(DecodedResponse::into_response))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "rtu"))]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio_util::codec::{Decoder as _, Encoder as _};
+
+    use crate::{
+        codec::rtu::ClientCodec,
+        frame::{
+            rtu::{Header, RequestAdu},
+            RequestPdu,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn tap_rtu_client_frames() {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let mut codec = ClientCodec {
+            tap: Some(Tap::new({
+                let frames = Arc::clone(&frames);
+                move |frame: &Frame| frames.lock().unwrap().push(frame.clone())
+            })),
+            ..Default::default()
+        };
+
+        let mut buf = BytesMut::new();
+        let request = Request::ReadHoldingRegisters(0x082B, 2);
+        let adu = RequestAdu {
+            hdr: Header { slave_id: 0x01 },
+            pdu: RequestPdu(request.clone()),
+        };
+        codec.encode(adu, &mut buf).unwrap();
+        assert_eq!(buf[..], [0x01, 0x03, 0x08, 0x2B, 0x00, 0x02, 0xB6, 0x63]);
+
+        // Noise before the response.
+        let response = [0x01, 0x03, 0x04, 0x00, 0x2A, 0x00, 0x2B, 0x9B, 0xE4];
+        let mut buf = BytesMut::from(&[0xFF][..]);
+        buf.extend_from_slice(&response);
+        codec.decode(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+
+        let frames = frames.lock().unwrap();
+        let [sent, received] = &frames[..] else {
+            panic!("unexpected frames: {frames:?}");
+        };
+        assert_eq!(sent.direction, Direction::Sent);
+        assert_eq!(
+            sent.bytes[..],
+            [0x01, 0x03, 0x08, 0x2B, 0x00, 0x02, 0xB6, 0x63]
+        );
+        assert_eq!(sent.slave, Slave(1));
+        assert_eq!(sent.pdu, Pdu::Request(request));
+        assert_eq!(received.direction, Direction::Received);
+        assert_eq!(received.bytes[0], 0xFF);
+        assert_eq!(received.bytes[1..], response);
+        assert_eq!(
+            received.pdu,
+            Pdu::Response(Ok(Response::ReadHoldingRegisters(vec![0x2A, 0x2B])))
+        );
+    }
+}
@@ -0,0 +1,264 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! A client with scripted expectations
+
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    client::{Client, Context},
+    mutex::lock,
+    slave::SlaveContext,
+    Error, ExceptionCode, Request, Response, Result, Slave,
+};
+
+#[derive(Debug)]
+struct Expectation {
+    slave: Option<Slave>,
+    request: Request<'static>,
+    result: Result<Response>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    expectations: VecDeque<Expectation>,
+    calls: Vec<(Slave, Request<'static>)>,
+    unexpected: Vec<String>,
+}
+
+/// A client that answers an expected sequence of requests.
+///
+/// Intended for unit tests of code that uses a [`Context`] without
+/// a server. Each request must match the next expectation and is
+/// answered with its programmed response, exception, or error.
+/// Unexpected requests fail with a transport error of the kind
+/// [`io::ErrorKind::InvalidInput`] and are reported by [`Self::verify()`].
+///
+/// The expectations and the recorded calls are shared by all clones.
+///
+/// # Example
+///
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use tokio_modbus::{prelude::*, testing::MockClient};
+///
+/// let mock = MockClient::new();
+/// mock.expect(
+///     Request::ReadHoldingRegisters(0x1000, 2),
+///     Ok(Response::ReadHoldingRegisters(vec![1, 2])),
+/// );
+/// mock.expect_error(
+///     Request::WriteSingleRegister(0x1000, 3),
+///     std::io::Error::from(std::io::ErrorKind::BrokenPipe),
+/// );
+///
+/// let mut ctx = mock.attach_slave(Slave(1));
+/// assert_eq!(ctx.read_holding_registers(0x1000, 2).await??, [1, 2]);
+/// assert!(ctx.write_single_register(0x1000, 3).await.is_err());
+/// mock.verify();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct MockClient {
+    slave: Slave,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl fmt::Debug for MockClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockClient")
+            .field("slave", &self.slave)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for MockClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClient {
+    /// Create a new client without any expectations.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            slave: Slave::tcp_device(),
+            shared: Arc::default(),
+        }
+    }
+
+    /// Attach a new client context for the given slave.
+    #[must_use]
+    pub fn attach_slave(&self, slave: Slave) -> Context {
+        let mut client = self.clone();
+        client.slave = slave;
+        Context::from(Box::new(client) as Box<dyn Client>)
+    }
+
+    /// Expect a request for any slave and answer it with a response
+    /// or an exception.
+    pub fn expect(
+        &self,
+        request: Request<'_>,
+        response: std::result::Result<Response, ExceptionCode>,
+    ) {
+        self.push(None, request, Ok(response));
+    }
+
+    /// Expect a request for the given slave and answer it with a response
+    /// or an exception.
+    pub fn expect_slave(
+        &self,
+        slave: Slave,
+        request: Request<'_>,
+        response: std::result::Result<Response, ExceptionCode>,
+    ) {
+        self.push(Some(slave), request, Ok(response));
+    }
+
+    /// Expect a request for any slave and fail with an error, e.g. an
+    /// [`io::Error`] or [`Error::Timeout`].
+    pub fn expect_error(&self, request: Request<'_>, error: impl Into<Error>) {
+        self.push(None, request, Err(error.into()));
+    }
+
+    /// All requests that have been received so far, including
+    /// unexpected requests.
+    #[must_use]
+    pub fn calls(&self) -> Vec<(Slave, Request<'static>)> {
+        lock(&self.shared).calls.clone()
+    }
+
+    /// Check that all expectations have been met.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an unexpected request has been received or if any
+    /// expected request is still pending.
+    pub fn verify(&self) {
+        let shared = lock(&self.shared);
+        assert!(
+            shared.unexpected.is_empty(),
+            "Unexpected requests: {:#?}",
+            shared.unexpected
+        );
+        assert!(
+            shared.expectations.is_empty(),
+            "Pending expectations: {:#?}",
+            shared.expectations
+        );
+    }
+
+    fn push(&self, slave: Option<Slave>, request: Request<'_>, result: Result<Response>) {
+        lock(&self.shared).expectations.push_back(Expectation {
+            slave,
+            request: request.into_owned(),
+            result,
+        });
+    }
+}
+
+#[async_trait]
+impl Client for MockClient {
+    async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+        let slave = self.slave;
+        let mut shared = lock(&self.shared);
+        let request = request.into_owned();
+        shared.calls.push((slave, request.clone()));
+        let matches = shared.expectations.front().is_some_and(|expectation| {
+            expectation.slave.map_or(true, |expected| expected == slave)
+                && expectation.request == request
+        });
+        if !matches {
+            let message = match shared.expectations.front() {
+                Some(expectation) => format!(
+                    "{request:?} for slave {slave} instead of {:?}",
+                    expectation.request
+                ),
+                None => format!("{request:?} for slave {slave} without any expectations"),
+            };
+            let err = io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unexpected request {message}"),
+            );
+            shared.unexpected.push(message);
+            return Err(err.into());
+        }
+        let expectation = shared
+            .expectations
+            .pop_front()
+            .expect("matching expectation");
+        expectation.result
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SlaveContext for MockClient {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = slave;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::client::{Reader as _, Writer as _};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn scripted_expectations() {
+        let mock = MockClient::new();
+        mock.expect_slave(
+            Slave(1),
+            Request::ReadCoils(0, 3),
+            Ok(Response::ReadCoils(vec![true, false, true])),
+        );
+        mock.expect(
+            Request::WriteSingleCoil(1, true),
+            Err(ExceptionCode::IllegalDataAddress),
+        );
+        mock.expect_error(
+            Request::ReadInputRegisters(0, 1),
+            Error::Timeout(Duration::from_secs(1)),
+        );
+        let mut ctx = mock.attach_slave(Slave(1));
+
+        assert_eq!(
+            ctx.read_coils(0, 3).await.unwrap(),
+            Ok(vec![true, false, true])
+        );
+        assert_eq!(
+            ctx.write_single_coil(1, true).await.unwrap(),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert!(matches!(
+            ctx.read_input_registers(0, 1).await,
+            Err(Error::Timeout(_))
+        ));
+        mock.verify();
+
+        mock.expect_slave(
+            Slave(2),
+            Request::ReadCoils(0, 1),
+            Ok(Response::ReadCoils(vec![true])),
+        );
+        let err = ctx.read_coils(0, 1).await.unwrap_err();
+        assert!(matches!(err, Error::Transport(err) if err.kind() == io::ErrorKind::InvalidInput));
+        assert_eq!(mock.calls().len(), 4);
+        assert!(std::panic::catch_unwind(|| mock.verify()).is_err());
+    }
+}
@@ -0,0 +1,602 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Test servers for examples, doctests, and integration tests
+//!
+//! Code that uses a client could be tested with a [`MockClient`] that
+//! answers an expected sequence of requests.
+//!
+//! Sessions with real devices could be recorded with a [`RecordingClient`]
+//! and replayed with a [`ReplayClient`] or a [`ReplayServer`] for
+//! regression tests without hardware.
+//!
+//! # Example
+//!
+//! ```
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio_modbus::prelude::*;
+//!
+//! let (socket_addr, server) = tokio_modbus::testing::spawn_memory_server().await?;
+//! server.memory().set_input_registers(0, &[1234, 5678]);
+//!
+//! let mut ctx = tcp::connect(socket_addr).await?;
+//! assert_eq!(ctx.read_input_registers(0, 2).await??, [1234, 5678]);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future, io,
+    net::SocketAddr,
+    ops::{Range, RangeInclusive},
+    sync::{Arc, Mutex},
+};
+
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use crate::{
+    frame::{Coil, Word},
+    mutex::lock,
+    server::{
+        tcp::{accept_tcp_connection, Server},
+        AccessPolicy, DataStore, Service, Table,
+    },
+    Address, ExceptionCode, Quantity, Request, Response,
+};
+
+mod mock;
+pub use self::mock::MockClient;
+
+mod replay;
+pub use self::replay::{Exchange, Journal, RecordingClient, ReplayClient, ReplayServer};
+
+/// The quirks of an emulated device.
+#[derive(Debug)]
+struct Quirks {
+    fifo_queues: HashMap<Address, VecDeque<Word>>,
+    read_only_coils: Vec<Range<usize>>,
+    read_only_holding_registers: Vec<Range<usize>>,
+    read_only_exception: ExceptionCode,
+    address_map: AddressMap,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            fifo_queues: HashMap::new(),
+            read_only_coils: Vec::new(),
+            read_only_holding_registers: Vec::new(),
+            read_only_exception: ExceptionCode::IllegalDataAddress,
+            address_map: AddressMap::default(),
+        }
+    }
+}
+
+impl AccessPolicy for Quirks {
+    fn check(
+        &self,
+        table: Table,
+        addresses: Range<usize>,
+        write: bool,
+    ) -> Result<Vec<Range<usize>>, ExceptionCode> {
+        let read_only = match table {
+            Table::Coils if write => self.read_only_coils.as_slice(),
+            Table::HoldingRegisters if write => self.read_only_holding_registers.as_slice(),
+            _ => &[],
+        };
+        if read_only
+            .iter()
+            .any(|range| range.start < addresses.end && addresses.start < range.end)
+        {
+            return Err(self.read_only_exception);
+        }
+        self.address_map.check(table, &addresses)
+    }
+}
+
+/// How requests that access unmapped addresses are answered.
+///
+/// See [`Memory::unmap()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unmapped {
+    /// Reject the whole request with an exception, usually
+    /// [`ExceptionCode::IllegalDataAddress`].
+    Reject(ExceptionCode),
+
+    /// Read zeros and discard written values, also known as gap-fill.
+    Fill,
+}
+
+/// The unmapped address ranges of all tables.
+#[derive(Debug, Default)]
+struct AddressMap {
+    unmapped: Vec<(Table, Range<usize>, Unmapped)>,
+}
+
+impl AddressMap {
+    /// Unmapped ranges of a table that overlap the addressed values.
+    fn overlapping<'a>(
+        &'a self,
+        table: Table,
+        values: &'a Range<usize>,
+    ) -> impl Iterator<Item = (Range<usize>, Unmapped)> + 'a {
+        self.unmapped
+            .iter()
+            .filter(move |(unmapped_table, _, _)| *unmapped_table == table)
+            .filter_map(|(_, range, unmapped)| {
+                let start = range.start.max(values.start);
+                let end = range.end.min(values.end);
+                (start < end).then_some((start..end, *unmapped))
+            })
+    }
+
+    /// Check that no unmapped values are accessed that must be rejected.
+    ///
+    /// Returns the ranges of unmapped values that are filled.
+    fn check(
+        &self,
+        table: Table,
+        values: &Range<usize>,
+    ) -> Result<Vec<Range<usize>>, ExceptionCode> {
+        let mut filled = Vec::new();
+        for (range, unmapped) in self.overlapping(table, values) {
+            match unmapped {
+                Unmapped::Reject(exception) => return Err(exception),
+                Unmapped::Fill => filled.push(range),
+            }
+        }
+        Ok(filled)
+    }
+}
+
+fn read_only_range(addrs: RangeInclusive<Address>) -> Range<usize> {
+    usize::from(*addrs.start())..usize::from(*addrs.end()) + 1
+}
+
+/// In-memory data tables that cover the whole address space.
+///
+/// All coils, discrete inputs, and registers are initially zero.
+/// Serves all read and write requests as a [`Service`] like a
+/// [`DataStore`] that covers all addresses, see [`Self::data_store()`].
+///
+/// FIFO queues can be populated by the host application for emulating
+/// event queues that are read by [`Request::ReadFifoQueue`]. Reading a
+/// queue doesn't remove its values. Queues with more than 31 values are
+/// answered with [`ExceptionCode::IllegalDataValue`].
+///
+/// Discrete inputs and input registers are read-only, i.e. they could
+/// only be modified by the host application. Coils and holding registers
+/// could be partially write-protected for emulating devices with read-only
+/// values. Requests that write any protected value are rejected with
+/// [`ExceptionCode::IllegalDataAddress`] unless configured otherwise.
+///
+/// Address ranges could be unmapped for emulating devices with gaps
+/// between their values. Requests that access unmapped addresses are
+/// either rejected or gap-filled, see [`Unmapped`].
+///
+/// The tables are shared by all clones.
+#[derive(Debug, Clone)]
+pub struct Memory {
+    data_store: DataStore,
+    quirks: Arc<Mutex<Quirks>>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        let data_store = DataStore::new()
+            .with_coils(0..=Address::MAX)
+            .with_discrete_inputs(0..=Address::MAX)
+            .with_input_registers(0..=Address::MAX)
+            .with_holding_registers(0..=Address::MAX);
+        Self {
+            data_store,
+            quirks: Arc::default(),
+        }
+    }
+}
+
+impl Memory {
+    /// Create new data tables.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The values of the tables.
+    ///
+    /// Changes of the values by requests could be observed with
+    /// [`DataStore::subscribe()`].
+    #[must_use]
+    pub const fn data_store(&self) -> &DataStore {
+        &self.data_store
+    }
+
+    /// Read coils.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the addresses exceed the address space.
+    #[must_use]
+    pub fn coils(&self, addr: Address, cnt: Quantity) -> Vec<Coil> {
+        self.data_store.coils(addr, cnt).expect("valid addresses")
+    }
+
+    /// Write coils.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the addresses exceed the address space.
+    pub fn set_coils(&self, addr: Address, coils: &[Coil]) {
+        self.data_store
+            .set_coils(addr, coils)
+            .expect("valid addresses");
+    }
+
+    /// Read discrete inputs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the addresses exceed the address space.
+    #[must_use]
+    pub fn discrete_inputs(&self, addr: Address, cnt: Quantity) -> Vec<Coil> {
+        self.data_store
+            .discrete_inputs(addr, cnt)
+            .expect("valid addresses")
+    }
+
+    /// Write discrete inputs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the addresses exceed the address space.
+    pub fn set_discrete_inputs(&self, addr: Address, inputs: &[Coil]) {
+        self.data_store
+            .set_discrete_inputs(addr, inputs)
+            .expect("valid addresses");
+    }
+
+    /// Read input registers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the addresses exceed the address space.
+    #[must_use]
+    pub fn input_registers(&self, addr: Address, cnt: Quantity) -> Vec<Word> {
+        self.data_store
+            .input_registers(addr, cnt)
+            .expect("valid addresses")
+    }
+
+    /// Write input registers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the addresses exceed the address space.
+    pub fn set_input_registers(&self, addr: Address, words: &[Word]) {
+        self.data_store
+            .set_input_registers(addr, words)
+            .expect("valid addresses");
+    }
+
+    /// Read holding registers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the addresses exceed the address space.
+    #[must_use]
+    pub fn holding_registers(&self, addr: Address, cnt: Quantity) -> Vec<Word> {
+        self.data_store
+            .holding_registers(addr, cnt)
+            .expect("valid addresses")
+    }
+
+    /// Write holding registers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the addresses exceed the address space.
+    pub fn set_holding_registers(&self, addr: Address, words: &[Word]) {
+        self.data_store
+            .set_holding_registers(addr, words)
+            .expect("valid addresses");
+    }
+
+    /// Reject requests that write any of the given coils.
+    ///
+    /// The host application is still able to modify them.
+    pub fn protect_coils(&self, addrs: RangeInclusive<Address>) {
+        lock(&self.quirks)
+            .read_only_coils
+            .push(read_only_range(addrs));
+    }
+
+    /// Reject requests that write any of the given holding registers.
+    ///
+    /// The host application is still able to modify them.
+    pub fn protect_holding_registers(&self, addrs: RangeInclusive<Address>) {
+        lock(&self.quirks)
+            .read_only_holding_registers
+            .push(read_only_range(addrs));
+    }
+
+    /// The exception for rejecting writes to protected values.
+    ///
+    /// Defaults to [`ExceptionCode::IllegalDataAddress`]. Some devices
+    /// answer with [`ExceptionCode::IllegalFunction`] or
+    /// [`ExceptionCode::ServerDeviceFailure`] instead.
+    pub fn set_write_protection_exception(&self, exception: ExceptionCode) {
+        lock(&self.quirks).read_only_exception = exception;
+    }
+
+    /// Unmap an address range of a table.
+    ///
+    /// Requests that access any of the unmapped addresses are answered as
+    /// configured by `unmapped`. If multiple unmapped ranges overlap the
+    /// rejecting ranges take precedence. The host application is still
+    /// able to access all values.
+    pub fn unmap(&self, table: Table, addrs: RangeInclusive<Address>, unmapped: Unmapped) {
+        lock(&self.quirks)
+            .address_map
+            .unmapped
+            .push((table, read_only_range(addrs), unmapped));
+    }
+
+    /// The values of a FIFO queue, starting with the oldest value.
+    ///
+    /// The queue is addressed by its FIFO pointer address.
+    #[must_use]
+    pub fn fifo_queue(&self, addr: Address) -> Vec<Word> {
+        lock(&self.quirks)
+            .fifo_queues
+            .get(&addr)
+            .map(|queue| queue.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Append a value to a FIFO queue.
+    pub fn push_fifo_queue(&self, addr: Address, word: Word) {
+        lock(&self.quirks)
+            .fifo_queues
+            .entry(addr)
+            .or_default()
+            .push_back(word);
+    }
+
+    /// Remove the oldest value from a FIFO queue.
+    #[must_use]
+    pub fn pop_fifo_queue(&self, addr: Address) -> Option<Word> {
+        lock(&self.quirks).fifo_queues.get_mut(&addr)?.pop_front()
+    }
+
+    fn process(&self, request: Request<'static>) -> Result<Response, ExceptionCode> {
+        let quirks = lock(&self.quirks);
+        let Request::ReadFifoQueue(addr) = request else {
+            return self.data_store.process_by(request, &*quirks);
+        };
+        let words: Vec<_> = quirks
+            .fifo_queues
+            .get(&addr)
+            .map(|queue| queue.iter().copied().collect())
+            .unwrap_or_default();
+        if words.len() > crate::codec::MAX_FIFO_COUNT {
+            return Err(ExceptionCode::IllegalDataValue);
+        }
+        Ok(Response::ReadFifoQueue(words))
+    }
+}
+
+impl Service for Memory {
+    type Request = Request<'static>;
+    type Response = Response;
+    type Exception = ExceptionCode;
+    type Future = future::Ready<Result<Self::Response, Self::Exception>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        future::ready(self.process(req))
+    }
+}
+
+/// A running server spawned by [`spawn_memory_server()`].
+///
+/// The server is aborted when dropped.
+#[derive(Debug)]
+pub struct MemoryServer {
+    memory: Memory,
+    task: JoinHandle<()>,
+}
+
+impl MemoryServer {
+    /// The data tables of the server.
+    #[must_use]
+    pub const fn memory(&self) -> &Memory {
+        &self.memory
+    }
+}
+
+impl Drop for MemoryServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Spawn a Modbus TCP server on a random local port.
+///
+/// All connections share the same [`Memory`]. Returns the socket address
+/// for connecting clients and the server that keeps running until dropped.
+pub async fn spawn_memory_server() -> io::Result<(SocketAddr, MemoryServer)> {
+    let listener = TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).await?;
+    let socket_addr = listener.local_addr()?;
+    let memory = Memory::new();
+    let server = Server::new(listener);
+    let service = memory.clone();
+    let task = tokio::spawn(async move {
+        let new_service = |_socket_addr| Ok(Some(service.clone()));
+        let on_connected = |stream, socket_addr| async move {
+            accept_tcp_connection(stream, socket_addr, new_service)
+        };
+        let on_process_error = |err| log::debug!("Failed to process requests: {err}");
+        if let Err(err) = server.serve(&on_connected, on_process_error).await {
+            log::warn!("Memory server terminated: {err}");
+        }
+    });
+    Ok((socket_addr, MemoryServer { memory, task }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::client::{tcp, Reader as _, Writer as _};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn read_and_write_memory_server() {
+        let (socket_addr, server) = spawn_memory_server().await.unwrap();
+        let mut ctx = tcp::connect(socket_addr).await.unwrap();
+
+        ctx.write_multiple_registers(10, &[1, 2, 3])
+            .await
+            .unwrap()
+            .unwrap();
+        ctx.masked_write_register(11, 0x00FF, 0x1200)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(server.memory().holding_registers(10, 3), [1, 0x1202, 3]);
+
+        server.memory().set_coils(5, &[true, false, true]);
+        assert_eq!(
+            ctx.read_coils(5, 3).await.unwrap().unwrap(),
+            [true, false, true]
+        );
+
+        assert_eq!(
+            ctx.read_input_registers(Address::MAX, 2).await.unwrap(),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+
+        server.memory().push_fifo_queue(0x04DE, 0x01B8);
+        assert_eq!(
+            ctx.read_fifo_queue(0x04DE).await.unwrap().unwrap(),
+            [0x01B8]
+        );
+    }
+
+    #[test]
+    fn write_before_read() {
+        let memory = Memory::new();
+        let response = memory
+            .process(Request::ReadWriteMultipleRegisters(
+                0,
+                2,
+                1,
+                Cow::Borrowed(&[7]),
+            ))
+            .unwrap();
+        assert_eq!(response, Response::ReadWriteMultipleRegisters(vec![0, 7]));
+    }
+
+    #[test]
+    fn reject_writes_to_protected_values() {
+        let memory = Memory::new();
+        memory.protect_coils(10..=11);
+        memory.protect_holding_registers(100..=100);
+        memory.set_holding_registers(100, &[42]);
+
+        assert_eq!(
+            memory.process(Request::WriteMultipleCoils(8, Cow::Borrowed(&[true; 3]))),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            memory.process(Request::WriteSingleCoil(12, true)),
+            Ok(Response::WriteSingleCoil(12, true))
+        );
+        assert_eq!(memory.coils(8, 5), [false, false, false, false, true]);
+
+        memory.set_write_protection_exception(ExceptionCode::IllegalFunction);
+        for request in [
+            Request::WriteSingleRegister(100, 0),
+            Request::WriteMultipleRegisters(99, Cow::Borrowed(&[0, 0])),
+            Request::MaskWriteRegister(100, 0, 0),
+            Request::ReadWriteMultipleRegisters(100, 1, 100, Cow::Borrowed(&[0])),
+        ] {
+            assert_eq!(memory.process(request), Err(ExceptionCode::IllegalFunction));
+        }
+        assert_eq!(memory.holding_registers(100, 1), [42]);
+        assert_eq!(
+            memory.process(Request::WriteSingleRegister(101, 7)),
+            Ok(Response::WriteSingleRegister(101, 7))
+        );
+    }
+
+    #[test]
+    fn unmapped_addresses() {
+        let memory = Memory::new();
+        memory.set_holding_registers(10, &[1, 2, 3, 4]);
+        memory.unmap(Table::HoldingRegisters, 11..=12, Unmapped::Fill);
+        memory.unmap(
+            Table::InputRegisters,
+            0..=9,
+            Unmapped::Reject(ExceptionCode::IllegalDataAddress),
+        );
+
+        assert_eq!(
+            memory.process(Request::ReadHoldingRegisters(10, 4)),
+            Ok(Response::ReadHoldingRegisters(vec![1, 0, 0, 4]))
+        );
+        assert_eq!(
+            memory.process(Request::WriteMultipleRegisters(
+                10,
+                Cow::Borrowed(&[5, 6, 7, 8])
+            )),
+            Ok(Response::WriteMultipleRegisters(10, 4))
+        );
+        assert_eq!(memory.holding_registers(10, 4), [5, 2, 3, 8]);
+
+        assert_eq!(
+            memory.process(Request::ReadInputRegisters(9, 2)),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert_eq!(
+            memory.process(Request::ReadInputRegisters(10, 2)),
+            Ok(Response::ReadInputRegisters(vec![0, 0]))
+        );
+        // Other tables are not affected.
+        assert_eq!(
+            memory.process(Request::ReadCoils(0, 1)),
+            Ok(Response::ReadCoils(vec![false]))
+        );
+    }
+
+    #[test]
+    fn read_fifo_queues() {
+        let memory = Memory::new();
+        assert_eq!(
+            memory.process(Request::ReadFifoQueue(0x04DE)),
+            Ok(Response::ReadFifoQueue(vec![]))
+        );
+
+        memory.push_fifo_queue(0x04DE, 0x01B8);
+        memory.push_fifo_queue(0x04DE, 0x1284);
+        assert_eq!(
+            memory.process(Request::ReadFifoQueue(0x04DE)),
+            Ok(Response::ReadFifoQueue(vec![0x01B8, 0x1284]))
+        );
+        assert_eq!(memory.fifo_queue(0x04DE), [0x01B8, 0x1284]);
+        assert!(memory.fifo_queue(0x04DF).is_empty());
+
+        assert_eq!(memory.pop_fifo_queue(0x04DE), Some(0x01B8));
+        assert_eq!(memory.fifo_queue(0x04DE), [0x1284]);
+
+        for word in 0..31 {
+            memory.push_fifo_queue(0x04DE, word);
+        }
+        assert_eq!(
+            memory.process(Request::ReadFifoQueue(0x04DE)),
+            Err(ExceptionCode::IllegalDataValue)
+        );
+    }
+}
@@ -0,0 +1,639 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Record and replay the exchanges of a client
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    future,
+    io::{self, BufRead, Write},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    bytes::Bytes,
+    client::{Client, Context},
+    frame::ResponsePdu,
+    mutex::lock,
+    server::Service,
+    slave::SlaveContext,
+    tap::{Direction, Frame, FrameTap, Pdu, Tap},
+    ExceptionCode, ExceptionResponse, Request, Response, Result, Slave, SlaveRequest,
+};
+
+/// A request and its response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    /// The addressed slave.
+    pub slave: Slave,
+
+    /// The request.
+    pub request: Request<'static>,
+
+    /// The response or the exception.
+    pub response: std::result::Result<Response, ExceptionCode>,
+}
+
+/// A recorded session, i.e. a sequence of exchanges.
+///
+/// The exchanges are shared by all clones.
+///
+/// Journals are stored as text with one exchange per line: The slave ID,
+/// the request PDU, and the response PDU, both as hex strings. Empty lines
+/// and comments starting with `#` are ignored.
+///
+/// ```text
+/// # Read 2 holding registers at 0x082B
+/// 1 03082B0002 030400200000
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    exchanges: Arc<Mutex<Vec<Exchange>>>,
+}
+
+impl Journal {
+    /// Create an empty journal.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an exchange.
+    pub fn push(&self, exchange: Exchange) {
+        lock(&self.exchanges).push(exchange);
+    }
+
+    /// All exchanges in the order they have been recorded.
+    #[must_use]
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        lock(&self.exchanges).clone()
+    }
+
+    /// Write all exchanges as text.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        for exchange in lock(&self.exchanges).iter() {
+            let Exchange {
+                slave,
+                request,
+                response,
+            } = exchange;
+            let response = response.clone().map_err(|exception| ExceptionResponse {
+                function: request.function_code(),
+                exception,
+            });
+            let request = crate::codec::encode_request(request)?;
+            let response = crate::codec::encode_response_result(&response)?;
+            writeln!(writer, "{} {} {}", slave.0, hex(&request), hex(&response))?;
+        }
+        writer.flush()
+    }
+
+    /// Read exchanges from text.
+    pub fn read_from(reader: impl BufRead) -> io::Result<Self> {
+        let mut exchanges = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let exchange = parse_exchange(line).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid exchange in line {}: {err}", index + 1),
+                )
+            })?;
+            exchanges.push(exchange);
+        }
+        Ok(Self {
+            exchanges: Arc::new(Mutex::new(exchanges)),
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(2 * bytes.len()), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02X}");
+            hex
+        })
+}
+
+fn parse_hex(hex: &str) -> io::Result<Bytes> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid hex: {hex}"));
+    if hex.len() % 2 != 0 {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+fn parse_exchange(line: &str) -> io::Result<Exchange> {
+    let mut fields = line.split_whitespace();
+    let (Some(slave), Some(request), Some(response), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Expected slave, request, and response",
+        ));
+    };
+    let slave = slave
+        .parse()
+        .map(Slave)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let request = Request::try_from(parse_hex(request)?)?;
+    let ResponsePdu(response) = ResponsePdu::try_from(parse_hex(response)?)?;
+    Ok(Exchange {
+        slave,
+        request,
+        response: response.map_err(|exception| exception.exception),
+    })
+}
+
+/// A client that records all exchanges into a [`Journal`].
+///
+/// Only exchanges with a response or an exception are recorded,
+/// transport errors are not.
+///
+/// The recorded exchanges could also be passed to a [`FrameTap`] as
+/// Modbus TCP frames with consecutive transaction IDs, independent of
+/// the actual transport, e.g. for capturing an RTU session with a
+/// [`PcapngWriter`](crate::capture::PcapngWriter) that could be inspected
+/// with Wireshark.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn record() -> Result<(), Box<dyn std::error::Error>> {
+/// use tokio_modbus::{
+///     prelude::*,
+///     testing::{Journal, RecordingClient},
+/// };
+///
+/// let journal = Journal::new();
+/// let ctx = tcp::connect("192.168.0.222:502".parse()?).await?;
+/// let mut ctx = RecordingClient::attach_slave(ctx, Slave(1), journal.clone());
+/// ctx.read_holding_registers(0x082B, 2).await??;
+/// journal.write_to(std::fs::File::create("session.txt")?)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RecordingClient {
+    ctx: Context,
+    slave: Slave,
+    journal: Journal,
+    tap: Option<Tap>,
+    next_transaction_id: u16,
+}
+
+impl RecordingClient {
+    /// Record the exchanges of `ctx` with the given slave.
+    #[must_use]
+    pub fn attach_slave(ctx: Context, slave: Slave, journal: Journal) -> Context {
+        Self::attach(ctx, slave, journal, None)
+    }
+
+    /// Record the exchanges of `ctx` with the given slave and pass them
+    /// to `tap` as Modbus TCP frames.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # #[cfg(feature = "capture")]
+    /// # async fn record(ctx: tokio_modbus::client::Context) -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::{fs::File, io::BufWriter};
+    ///
+    /// use tokio_modbus::{
+    ///     capture::PcapngWriter,
+    ///     prelude::*,
+    ///     testing::{Journal, RecordingClient},
+    /// };
+    ///
+    /// // `ctx` is a client context on any transport, e.g. RTU.
+    /// let file = BufWriter::new(File::create("session.pcapng")?);
+    /// let capture = PcapngWriter::new(file, "10.0.0.1:50200".parse()?, "10.0.0.2:502".parse()?)?;
+    /// let mut ctx = RecordingClient::attach_slave_with_tap(ctx, Slave(1), Journal::new(), capture);
+    /// ctx.read_holding_registers(0x082B, 2).await??;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn attach_slave_with_tap(
+        ctx: Context,
+        slave: Slave,
+        journal: Journal,
+        tap: impl FrameTap + 'static,
+    ) -> Context {
+        Self::attach(ctx, slave, journal, Some(Tap::new(tap)))
+    }
+
+    fn attach(mut ctx: Context, slave: Slave, journal: Journal, tap: Option<Tap>) -> Context {
+        ctx.set_slave(slave);
+        let client: Box<dyn Client> = Box::new(Self {
+            ctx,
+            slave,
+            journal,
+            tap,
+            next_transaction_id: 0,
+        });
+        Context::from(client)
+    }
+
+    /// Pass an exchange to the tap as a request and a response frame.
+    fn tap_exchange(&mut self, exchange: &Exchange, sent: SystemTime, received: SystemTime) {
+        let Some(tap) = &self.tap else {
+            return;
+        };
+        let transaction_id = self.next_transaction_id;
+        self.next_transaction_id = transaction_id.wrapping_add(1);
+        let Exchange {
+            slave,
+            request,
+            response,
+        } = exchange;
+        let response = response.clone().map_err(|exception| ExceptionResponse {
+            function: request.function_code(),
+            exception,
+        });
+        let (request_pdu, response_pdu) = match (
+            crate::codec::encode_request(request),
+            crate::codec::encode_response_result(&response),
+        ) {
+            (Ok(request_pdu), Ok(response_pdu)) => (request_pdu, response_pdu),
+            (Err(err), _) | (_, Err(err)) => {
+                log::warn!("Failed to tap exchange: {err}");
+                return;
+            }
+        };
+        let frame = |direction, time, pdu: &[u8], tapped_pdu| {
+            #[allow(clippy::cast_possible_truncation)]
+            let len = (pdu.len() + 1) as u16;
+            let mut bytes = Vec::with_capacity(7 + pdu.len());
+            bytes.extend_from_slice(&transaction_id.to_be_bytes());
+            // Protocol ID
+            bytes.extend_from_slice(&0u16.to_be_bytes());
+            bytes.extend_from_slice(&len.to_be_bytes());
+            bytes.push(slave.0);
+            bytes.extend_from_slice(pdu);
+            Frame {
+                direction,
+                time,
+                bytes: bytes.into(),
+                slave: *slave,
+                transaction_id: Some(transaction_id),
+                pdu: tapped_pdu,
+            }
+        };
+        tap.tap_frame(&frame(
+            Direction::Sent,
+            sent,
+            &request_pdu,
+            Pdu::Request(request.clone()),
+        ));
+        tap.tap_frame(&frame(
+            Direction::Received,
+            received,
+            &response_pdu,
+            Pdu::Response(response),
+        ));
+    }
+}
+
+#[async_trait]
+impl Client for RecordingClient {
+    async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+        let recorded = request.clone().into_owned();
+        let sent = SystemTime::now();
+        let result = self.ctx.call(request).await;
+        if let Ok(response) = &result {
+            let exchange = Exchange {
+                slave: self.slave,
+                request: recorded,
+                response: response.clone(),
+            };
+            self.tap_exchange(&exchange, sent, SystemTime::now());
+            self.journal.push(exchange);
+        }
+        result
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        self.ctx.disconnect().await
+    }
+}
+
+impl SlaveContext for RecordingClient {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = slave;
+        self.ctx.set_slave(slave);
+    }
+}
+
+/// A client that replays the exchanges of a [`Journal`] in order.
+///
+/// Each request must match the next recorded exchange, otherwise the
+/// call fails with a transport error.
+///
+/// # Example
+///
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use tokio_modbus::{
+///     prelude::*,
+///     testing::{Journal, ReplayClient},
+/// };
+///
+/// let session = "1 03082B0002 0304002A002B\n";
+/// let journal = Journal::read_from(session.as_bytes())?;
+/// let mut ctx = ReplayClient::attach_slave(&journal, Slave(1));
+/// assert_eq!(ctx.read_holding_registers(0x082B, 2).await??, [42, 43]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ReplayClient {
+    slave: Slave,
+    exchanges: VecDeque<Exchange>,
+}
+
+impl ReplayClient {
+    /// Replay the exchanges of `journal`, starting with the given slave.
+    #[must_use]
+    pub fn attach_slave(journal: &Journal, slave: Slave) -> Context {
+        let client: Box<dyn Client> = Box::new(Self {
+            slave,
+            exchanges: journal.exchanges().into(),
+        });
+        let mut ctx = Context::from(client);
+        ctx.set_slave(slave);
+        ctx
+    }
+}
+
+#[async_trait]
+impl Client for ReplayClient {
+    async fn call(&mut self, request: Request<'_>) -> Result<Response> {
+        let Some(exchange) = self.exchanges.front() else {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("No recorded exchange for {request:?}"),
+            )
+            .into());
+        };
+        if exchange.slave != self.slave || exchange.request != request {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unexpected request {request:?} for slave {}, recorded {:?} for slave {}",
+                    self.slave, exchange.request, exchange.slave
+                ),
+            )
+            .into());
+        }
+        let response = exchange.response.clone();
+        self.exchanges.pop_front();
+        Ok(response)
+    }
+
+    async fn disconnect(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SlaveContext for ReplayClient {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = slave;
+    }
+}
+
+/// A server service that answers requests from the exchanges of a [`Journal`].
+///
+/// Requests are answered by the first recorded exchange with the same
+/// slave and request that has not been replayed yet. If all matching
+/// exchanges have been replayed, the replay of these exchanges starts
+/// over, e.g. for answering a polling client with the recorded sequence
+/// of values repeatedly. Requests that have not been recorded are
+/// answered with [`ExceptionCode::ServerDeviceFailure`].
+///
+/// The replay state is shared by all clones.
+#[derive(Debug, Clone)]
+pub struct ReplayServer {
+    shared: Arc<Mutex<Vec<(Exchange, bool)>>>,
+}
+
+impl ReplayServer {
+    /// Answer requests from the exchanges of `journal`.
+    #[must_use]
+    pub fn new(journal: &Journal) -> Self {
+        let exchanges = journal
+            .exchanges()
+            .into_iter()
+            .map(|exchange| (exchange, false))
+            .collect();
+        Self {
+            shared: Arc::new(Mutex::new(exchanges)),
+        }
+    }
+
+    fn replay(
+        &self,
+        slave: Slave,
+        request: &Request<'_>,
+    ) -> Option<std::result::Result<Response, ExceptionCode>> {
+        let mut exchanges = lock(&self.shared);
+        let matches = |exchange: &Exchange| exchange.slave == slave && exchange.request == *request;
+        if !exchanges
+            .iter()
+            .any(|(exchange, replayed)| !replayed && matches(exchange))
+        {
+            for (exchange, replayed) in exchanges.iter_mut() {
+                if matches(exchange) {
+                    *replayed = false;
+                }
+            }
+        }
+        let (exchange, replayed) = exchanges
+            .iter_mut()
+            .find(|(exchange, replayed)| !replayed && matches(exchange))?;
+        *replayed = true;
+        Some(exchange.response.clone())
+    }
+}
+
+impl Service for ReplayServer {
+    type Request = SlaveRequest<'static>;
+    type Response = Response;
+    type Exception = ExceptionCode;
+    type Future = future::Ready<std::result::Result<Self::Response, Self::Exception>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let SlaveRequest { slave, request } = req;
+        let response = self.replay(Slave(slave), &request).unwrap_or_else(|| {
+            log::warn!("No recorded exchange for slave {slave}: {request:?}");
+            Err(ExceptionCode::ServerDeviceFailure)
+        });
+        future::ready(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{DryRunClient, Reader as _, Writer as _};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let journal = Journal::new();
+        let client = DryRunClient::new();
+        client.push_response(Ok(Response::ReadHoldingRegisters(vec![42, 43])));
+        client.push_response(Err(ExceptionCode::IllegalDataAddress));
+        let ctx = client.attach_slave(Slave(1));
+        let mut ctx = RecordingClient::attach_slave(ctx, Slave(1), journal.clone());
+        ctx.read_holding_registers(0x082B, 2)
+            .await
+            .unwrap()
+            .unwrap();
+        ctx.set_slave(Slave(2));
+        ctx.write_single_register(0x1000, 7)
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        let mut text = Vec::new();
+        journal.write_to(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text.clone()).unwrap(),
+            "1 03082B0002 0304002A002B\n2 0610000007 8602\n"
+        );
+        let journal = Journal::read_from(&text[..]).unwrap();
+        assert_eq!(
+            journal.exchanges(),
+            [
+                Exchange {
+                    slave: Slave(1),
+                    request: Request::ReadHoldingRegisters(0x082B, 2),
+                    response: Ok(Response::ReadHoldingRegisters(vec![42, 43])),
+                },
+                Exchange {
+                    slave: Slave(2),
+                    request: Request::WriteSingleRegister(0x1000, 7),
+                    response: Err(ExceptionCode::IllegalDataAddress),
+                },
+            ]
+        );
+
+        let mut ctx = ReplayClient::attach_slave(&journal, Slave(1));
+        // Not the next recorded request.
+        assert!(ctx.read_holding_registers(0x082B, 3).await.is_err());
+        assert_eq!(
+            ctx.read_holding_registers(0x082B, 2).await.unwrap(),
+            Ok(vec![42, 43])
+        );
+        ctx.set_slave(Slave(2));
+        assert_eq!(
+            ctx.write_single_register(0x1000, 7).await.unwrap(),
+            Err(ExceptionCode::IllegalDataAddress)
+        );
+        assert!(ctx.write_single_register(0x1000, 7).await.is_err());
+
+        let server = ReplayServer::new(&journal);
+        let request = |slave, request| SlaveRequest { slave, request };
+        for _ in 0..2 {
+            assert_eq!(
+                server
+                    .call(request(1, Request::ReadHoldingRegisters(0x082B, 2)))
+                    .await,
+                Ok(Response::ReadHoldingRegisters(vec![42, 43]))
+            );
+        }
+        assert_eq!(
+            server
+                .call(request(2, Request::ReadHoldingRegisters(0x082B, 2)))
+                .await,
+            Err(ExceptionCode::ServerDeviceFailure)
+        );
+    }
+
+    #[tokio::test]
+    async fn tap_recorded_exchanges() {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let client = DryRunClient::new();
+        client.push_response(Ok(Response::ReadHoldingRegisters(vec![42, 43])));
+        client.push_response(Err(ExceptionCode::IllegalDataAddress));
+        let ctx = client.attach_slave(Slave(1));
+        let mut ctx = RecordingClient::attach_slave_with_tap(ctx, Slave(1), Journal::new(), {
+            let frames = Arc::clone(&frames);
+            move |frame: &Frame| frames.lock().unwrap().push(frame.clone())
+        });
+        ctx.read_holding_registers(0x082B, 2)
+            .await
+            .unwrap()
+            .unwrap();
+        ctx.set_slave(Slave(2));
+        ctx.write_single_register(0x1000, 7)
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        let frames = frames.lock().unwrap();
+        let tapped: Vec<_> = frames
+            .iter()
+            .map(|frame| (frame.direction, frame.transaction_id, &frame.bytes[..]))
+            .collect();
+        assert_eq!(
+            tapped,
+            [
+                (
+                    Direction::Sent,
+                    Some(0),
+                    &[0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x01, 0x03, 0x08, 0x2B, 0x00, 0x02][..]
+                ),
+                (
+                    Direction::Received,
+                    Some(0),
+                    &[
+                        0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x01, 0x03, 0x04, 0x00, 0x2A, 0x00,
+                        0x2B
+                    ]
+                ),
+                (
+                    Direction::Sent,
+                    Some(1),
+                    &[0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x02, 0x06, 0x10, 0x00, 0x00, 0x07]
+                ),
+                (
+                    Direction::Received,
+                    Some(1),
+                    &[0x00, 0x01, 0x00, 0x00, 0x00, 0x03, 0x02, 0x86, 0x02]
+                ),
+            ]
+        );
+        assert!(frames[0].time <= frames[1].time);
+    }
+
+    #[test]
+    fn reject_invalid_journals() {
+        assert!(Journal::read_from("# comment\n\n".as_bytes())
+            .unwrap()
+            .exchanges()
+            .is_empty());
+        assert!(Journal::read_from("1 03082B0002\n".as_bytes()).is_err());
+        assert!(Journal::read_from("1 03082B000 0304002A002B\n".as_bytes()).is_err());
+        assert!(Journal::read_from("x 03082B0002 0304002A002B\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Timing of RTU frames on serial lines
+//!
+//! The Modbus serial line specification delimits RTU frames by silent
+//! intervals instead of relying on the length of the frames. Without
+//! enforcing the timing, frames that follow each other closely on
+//! noisy links could be merged and fragments of corrupted frames might
+//! be mistaken for the beginning of the next frame.
+
+use std::time::Duration;
+
+/// The number of bits per character: start bit, 8 data bits,
+/// parity or second stop bit, and stop bit.
+const BITS_PER_CHAR: u64 = 11;
+
+/// Baud rates above this threshold use fixed intervals.
+const MAX_VARIABLE_BAUD_RATE: u32 = 19_200;
+
+/// Silent intervals that delimit RTU frames.
+///
+/// The characters of a frame must be transmitted as a continuous
+/// stream. A frame that contains a silent interval of more than
+/// 1.5 character times (t1.5) is incomplete and must be discarded.
+/// Frames are separated by a silent interval of at least 3.5 character
+/// times (t3.5).
+///
+/// The intervals are measured when the received bytes are read from
+/// the transport. Serial drivers and USB adapters buffer the incoming
+/// bytes, e.g. the latency timer of FTDI adapters defaults to 16 ms.
+/// Choose the intervals accordingly or use a low latency setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTiming {
+    inter_char_timeout: Duration,
+    inter_frame_delay: Duration,
+}
+
+impl FrameTiming {
+    /// Custom intervals, e.g. to compensate for the latency of
+    /// serial drivers.
+    #[must_use]
+    pub const fn new(inter_char_timeout: Duration, inter_frame_delay: Duration) -> Self {
+        Self {
+            inter_char_timeout,
+            inter_frame_delay,
+        }
+    }
+
+    /// The intervals for the given baud rate.
+    ///
+    /// Above 19200 baud the fixed intervals of 750 µs (t1.5) and
+    /// 1.75 ms (t3.5) are used as recommended by the specification.
+    #[must_use]
+    pub fn from_baud_rate(baud_rate: u32) -> Self {
+        if baud_rate > MAX_VARIABLE_BAUD_RATE {
+            return Self::new(Duration::from_micros(750), Duration::from_micros(1750));
+        }
+        let char_time_nanos = BITS_PER_CHAR * 1_000_000_000 / u64::from(baud_rate.max(1));
+        Self::new(
+            Duration::from_nanos(char_time_nanos * 3 / 2),
+            Duration::from_nanos(char_time_nanos * 7 / 2),
+        )
+    }
+
+    /// The maximum silent interval between the characters of a frame (t1.5).
+    #[must_use]
+    pub const fn inter_char_timeout(&self) -> Duration {
+        self.inter_char_timeout
+    }
+
+    /// The minimum silent interval between frames (t3.5).
+    #[must_use]
+    pub const fn inter_frame_delay(&self) -> Duration {
+        self.inter_frame_delay
+    }
+}
+
+pub(crate) use self::timed_io::TimedIo;
+
+mod timed_io {
+    use std::{
+        fmt,
+        future::Future as _,
+        io,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{ready, Context, Poll},
+    };
+
+    use tokio::{
+        io::{AsyncRead, AsyncWrite, ReadBuf},
+        time::{Instant, Sleep},
+    };
+
+    use crate::{codec::rtu::FrameGaps, mutex::lock};
+
+    use super::FrameTiming;
+
+    /// A transport that detects the silent intervals between frames.
+    ///
+    /// Writing a frame is delayed until the line has been silent for
+    /// the inter-frame delay. The detected gaps are shared with the
+    /// decoder that discards invalid frames.
+    pub(crate) struct TimedIo<T> {
+        inner: T,
+        timing: FrameTiming,
+        gaps: Arc<Mutex<FrameGaps>>,
+        /// The last time bytes have been received within the current frame.
+        last_received: Option<Instant>,
+        /// The last time bytes have been received or sent.
+        last_activity: Option<Instant>,
+        /// A frame is written but not flushed yet.
+        writing: bool,
+        delay: Option<Pin<Box<Sleep>>>,
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for TimedIo<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TimedIo")
+                .field("inner", &self.inner)
+                .field("timing", &self.timing)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl<T> TimedIo<T> {
+        pub(crate) fn new(inner: T, timing: FrameTiming, gaps: Arc<Mutex<FrameGaps>>) -> Self {
+            Self {
+                inner,
+                timing,
+                gaps,
+                last_received: None,
+                last_activity: None,
+                writing: false,
+                delay: None,
+            }
+        }
+    }
+
+    impl<T> AsyncRead for TimedIo<T>
+    where
+        T: AsyncRead + Unpin,
+    {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            let filled = buf.filled().len();
+            ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+            let read = buf.filled().len() - filled;
+            if read == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            let now = Instant::now();
+            let silence = this
+                .last_received
+                .map(|last_received| now.saturating_duration_since(last_received));
+            let mut gaps = lock(&this.gaps);
+            match silence {
+                Some(silence) if silence < this.timing.inter_frame_delay => {
+                    if silence > this.timing.inter_char_timeout {
+                        gaps.inter_char_gap();
+                    }
+                    gaps.continue_frame(read);
+                }
+                _ => gaps.start_frame(read),
+            }
+            this.last_received = Some(now);
+            this.last_activity = Some(now);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl<T> AsyncWrite for TimedIo<T>
+    where
+        T: AsyncWrite + Unpin,
+    {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            if let Some(last_activity) = this.last_activity.filter(|_| !this.writing) {
+                let deadline = last_activity + this.timing.inter_frame_delay;
+                if deadline > Instant::now() {
+                    let delay = this
+                        .delay
+                        .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+                    delay.as_mut().reset(deadline);
+                    ready!(delay.as_mut().poll(cx));
+                }
+            }
+            let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+            // The next bytes that are received belong to a new frame.
+            this.last_received = None;
+            this.writing = true;
+            Poll::Ready(Ok(written))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            ready!(Pin::new(&mut this.inner).poll_flush(cx))?;
+            if this.writing {
+                this.writing = false;
+                this.last_activity = Some(Instant::now());
+            }
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_from_baud_rate() {
+        let timing = FrameTiming::from_baud_rate(9600);
+        assert_eq!(timing.inter_char_timeout(), Duration::from_nanos(1_718_749));
+        assert_eq!(timing.inter_frame_delay(), Duration::from_nanos(4_010_415));
+        assert_eq!(
+            FrameTiming::from_baud_rate(115_200),
+            FrameTiming::new(Duration::from_micros(750), Duration::from_micros(1750))
+        );
+    }
+}
@@ -0,0 +1,31 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Transformation of raw frames on custom transports
+//!
+//! Some devices wrap standard Modbus frames, e.g. by obfuscating
+//! the bytes or by appending a message authentication code.
+//! A [`FrameTransform`] converts between the wrapped frames on the
+//! wire and the standard ADUs without touching the codecs.
+
+use std::{fmt, io};
+
+use crate::bytes::BytesMut;
+
+/// Transforms the raw bytes of ADUs before sending and after receiving.
+///
+/// Each connection uses a separate instance that could keep state
+/// between frames, e.g. a counter for replay protection.
+pub trait FrameTransform: fmt::Debug + Send {
+    /// Wrap an encoded ADU before it is sent.
+    ///
+    /// The wrapped frame is appended to `buf`.
+    fn encode(&mut self, adu: &[u8], buf: &mut BytesMut) -> io::Result<()>;
+
+    /// Unwrap the next ADU from the received bytes.
+    ///
+    /// Consumes the wrapped frame from the front of `buf` and returns
+    /// the contained ADU. Returns `None` without consuming anything
+    /// if the wrapped frame has not been received completely.
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>>;
+}
@@ -0,0 +1,392 @@
+// SPDX-FileCopyrightText: Copyright (c) 2017-2024 slowtec GmbH <post@slowtec.de>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Modbus TCP frames over WebSocket connections
+//!
+//! Each Modbus TCP frame, including the MBAP header, is carried in a
+//! binary WebSocket message ([RFC 6455](https://www.rfc-editor.org/rfc/rfc6455)).
+//! This allows browser-based HMIs and clients behind reverse proxies to
+//! reach Modbus servers without raw TCP access. The WebSocket protocol
+//! is implemented by [`tokio-tungstenite`](https://docs.rs/tokio-tungstenite).
+//!
+//! A [`WebSocketStream`] is established by [`connect()`] or [`accept()`]
+//! on any connection and is used like a TCP stream, e.g. with
+//! [`client::tcp::attach()`](crate::client::tcp::attach) or
+//! [`server::tcp::serve_connection()`](crate::server::tcp::serve_connection).
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn connect() -> Result<(), Box<dyn std::error::Error>> {
+//! use tokio::net::TcpStream;
+//! use tokio_modbus::{client::tcp, prelude::*, ws};
+//!
+//! // A reverse proxy that forwards the path to a Modbus server.
+//! let stream = TcpStream::connect("10.0.0.1:80").await?;
+//! let stream = ws::connect(stream, "hmi.example.com", "/modbus").await?;
+//! let mut ctx = tcp::attach_slave(stream, Slave(1));
+//! let words = ctx.read_holding_registers(0x1000, 4).await??;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures_core::Stream as _;
+use futures_util::Sink as _;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::{
+    self, error::ProtocolError, protocol::WebSocketConfig, Message,
+};
+
+/// The maximum length of a received message.
+///
+/// Modbus TCP frames are much shorter. Larger messages are rejected
+/// instead of being buffered.
+const MAX_MESSAGE_LEN: usize = 0x1_0000;
+
+/// The size of the read buffer of each connection.
+const READ_BUFFER_SIZE: usize = 4096;
+
+fn config() -> WebSocketConfig {
+    WebSocketConfig::default()
+        .read_buffer_size(READ_BUFFER_SIZE)
+        // Each message is sent when flushed.
+        .write_buffer_size(0)
+        .max_message_size(Some(MAX_MESSAGE_LEN))
+        .max_frame_size(Some(MAX_MESSAGE_LEN))
+}
+
+/// A WebSocket connection that carries bytes in binary messages.
+///
+/// All bytes that are written between two flushes are sent as a single
+/// message, i.e. each Modbus TCP frame is sent in a message of its own.
+/// The received messages are read as a contiguous stream of bytes.
+///
+/// Pings and close frames are answered automatically. The replies are
+/// kept until they have been sent while reading or writing. Text messages
+/// are rejected.
+pub struct WebSocketStream<T> {
+    stream: tokio_tungstenite::WebSocketStream<T>,
+
+    /// The received payload that has not been read yet.
+    payload: Bytes,
+
+    /// The written bytes of the next message.
+    message: BytesMut,
+
+    /// The connection has been closed by the peer.
+    closed: bool,
+}
+
+impl<T> fmt::Debug for WebSocketStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketStream")
+            .field("closed", &self.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> WebSocketStream<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    fn new(stream: tokio_tungstenite::WebSocketStream<T>) -> Self {
+        Self {
+            stream,
+            payload: Bytes::new(),
+            message: BytesMut::new(),
+            closed: false,
+        }
+    }
+
+    /// The underlying connection.
+    #[must_use]
+    pub fn get_ref(&self) -> &T {
+        self.stream.get_ref()
+    }
+
+    /// The underlying connection.
+    ///
+    /// Reading from or writing to the connection corrupts the
+    /// WebSocket connection.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.stream.get_mut()
+    }
+}
+
+impl<T> AsyncRead for WebSocketStream<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if !this.payload.is_empty() {
+                let len = this.payload.len().min(buf.remaining());
+                buf.put_slice(&this.payload.split_to(len));
+                return Poll::Ready(Ok(()));
+            }
+            if this.closed {
+                return Poll::Ready(Ok(()));
+            }
+            // Pending replies to pings and close frames are sent
+            // while waiting for the next message.
+            match ready!(Pin::new(&mut this.stream).poll_next(cx)) {
+                Some(Ok(Message::Binary(payload))) => this.payload = payload,
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Ok(Message::Close(frame))) => {
+                    log::debug!("Received close frame: {frame:?}");
+                    this.closed = true;
+                }
+                Some(Ok(Message::Text(_))) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "unexpected text message",
+                    )));
+                }
+                Some(Err(err)) if is_closed(&err) => this.closed = true,
+                Some(Err(err)) => return Poll::Ready(Err(io_error(err))),
+                None => this.closed = true,
+            }
+        }
+    }
+}
+
+impl<T> AsyncWrite for WebSocketStream<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.message.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if !this.message.is_empty() {
+            ready!(Pin::new(&mut this.stream).poll_ready(cx)).map_err(io_error)?;
+            let payload = this.message.split().freeze();
+            Pin::new(&mut this.stream)
+                .start_send(Message::Binary(payload))
+                .map_err(io_error)?;
+        }
+        Pin::new(&mut this.stream).poll_flush(cx).map_err(io_error)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.stream).poll_close(cx).map_err(io_error)
+    }
+}
+
+/// Perform the opening handshake of a client on the connection `stream`.
+///
+/// `host` and `path` are sent in the HTTP request, e.g. for routing
+/// the connection through a reverse proxy.
+pub async fn connect<T>(stream: T, host: &str, path: &str) -> io::Result<WebSocketStream<T>>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = format!("ws://{host}{path}");
+    let (stream, _response) =
+        tokio_tungstenite::client_async_with_config(request, stream, Some(config()))
+            .await
+            .map_err(handshake_error)?;
+    Ok(WebSocketStream::new(stream))
+}
+
+/// Perform the opening handshake of a server on the connection `stream`.
+///
+/// Requests for any path are accepted.
+pub async fn accept<T>(stream: T) -> io::Result<WebSocketStream<T>>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let stream = tokio_tungstenite::accept_async_with_config(stream, Some(config()))
+        .await
+        .map_err(handshake_error)?;
+    Ok(WebSocketStream::new(stream))
+}
+
+/// The connection has been closed, either with or without a
+/// closing handshake.
+fn is_closed(err: &tungstenite::Error) -> bool {
+    matches!(
+        err,
+        tungstenite::Error::ConnectionClosed
+            | tungstenite::Error::AlreadyClosed
+            | tungstenite::Error::Protocol(ProtocolError::ResetWithoutClosingHandshake)
+    )
+}
+
+fn io_error(err: tungstenite::Error) -> io::Error {
+    match err {
+        tungstenite::Error::Io(err) => err,
+        err if is_closed(&err) => io::ErrorKind::BrokenPipe.into(),
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+fn handshake_error(err: tungstenite::Error) -> io::Error {
+    match err {
+        tungstenite::Error::Io(err) => err,
+        err => io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("WebSocket handshake failed: {err}"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{SinkExt as _, StreamExt as _};
+    use tokio::io::{duplex, AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio_tungstenite::tungstenite::protocol::Role;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn exchange_messages() {
+        let (client, server) = duplex(1024);
+        let (client, server) =
+            tokio::join!(connect(client, "localhost", "/modbus"), accept(server));
+        let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+        client.write_all(&[1, 2, 3]).await.unwrap();
+        client.flush().await.unwrap();
+        let mut buf = [0; 3];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+
+        server.write_all(&[4, 5]).await.unwrap();
+        server.flush().await.unwrap();
+        let mut buf = [0; 2];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [4, 5]);
+
+        client.shutdown().await.unwrap();
+        assert_eq!(server.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn write_messages_of_any_length() {
+        let (client, server) = duplex(1024);
+        let (client, server) = tokio::join!(connect(client, "localhost", "/"), accept(server));
+        let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+        let message = vec![7; 3 * 1024];
+        let write = async {
+            client.write_all(&message).await.unwrap();
+            client.flush().await.unwrap();
+        };
+        let mut buf = vec![0; message.len()];
+        let read = server.read_exact(&mut buf);
+        let ((), read) = tokio::join!(write, read);
+        read.unwrap();
+        assert_eq!(buf, message);
+    }
+
+    #[tokio::test]
+    async fn send_replies_to_pings_after_blocking() {
+        // Too small for all pongs.
+        let (client, server) = duplex(64);
+        let client = tokio_tungstenite::WebSocketStream::from_raw_socket(
+            client,
+            Role::Client,
+            Some(config()),
+        );
+        let server = tokio_tungstenite::WebSocketStream::from_raw_socket(
+            server,
+            Role::Server,
+            Some(config()),
+        );
+        let (mut client, server) = tokio::join!(client, server);
+        let mut server = WebSocketStream::new(server);
+
+        let send = async {
+            for i in 0..10 {
+                client.feed(Message::Ping(vec![i; 8].into())).await.unwrap();
+            }
+            client
+                .send(Message::Binary(vec![1, 2, 3].into()))
+                .await
+                .unwrap();
+        };
+        let mut buf = [0; 3];
+        let ((), read) = tokio::join!(send, server.read_exact(&mut buf));
+        read.unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+
+        // The reply to the last ping is sent once the server continues.
+        let receive = async {
+            loop {
+                match client.next().await.unwrap().unwrap() {
+                    Message::Pong(payload) if payload[..] == [9; 8] => break,
+                    Message::Pong(_) => {}
+                    message => panic!("unexpected message: {message:?}"),
+                }
+            }
+        };
+        let ((), flush) = tokio::join!(receive, server.flush());
+        flush.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_text_messages_and_invalid_handshakes() {
+        let (client, server) = duplex(1024);
+        let (client, server) = tokio::join!(
+            tokio_tungstenite::client_async("ws://localhost/", client),
+            accept(server)
+        );
+        let (mut client, mut server) = (client.unwrap().0, server.unwrap());
+        client.send(Message::text("modbus")).await.unwrap();
+        let err = server.read_u8().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let (mut client, server) = duplex(1024);
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let err = accept(server).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[cfg(feature = "tcp-server")]
+    #[tokio::test]
+    async fn serve_modbus_over_websocket() {
+        use crate::{
+            client::{tcp, Reader as _},
+            server::{tcp::serve_connection, DataStore},
+            Slave,
+        };
+
+        let (client, server) = duplex(1024);
+        let server = async move {
+            let stream = accept(server).await.unwrap();
+            let store = DataStore::default().with_holding_registers(0..=9);
+            drop(serve_connection(stream, store).await);
+        };
+        tokio::spawn(server);
+        let stream = connect(client, "localhost", "/").await.unwrap();
+        let mut ctx = tcp::attach_slave(stream, Slave(1));
+        assert_eq!(
+            ctx.read_holding_registers(0, 2).await.unwrap(),
+            Ok(vec![0, 0])
+        );
+    }
+}